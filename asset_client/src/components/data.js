@@ -21,6 +21,7 @@ const Chart = require('chart.js')
 const GoogleMapsLoader = require('google-maps')
 const modals = require('./modals')
 const api = require('../services/api')
+const payloads = require('../services/payloads')
 
 GoogleMapsLoader.KEY = null
 let google = null
@@ -71,7 +72,7 @@ const LineGraphWidget = {
 
   parseUpdates (updates) {
     return updates.map(d => ({
-      t: d.timestamp * 1000,
+      t: payloads.toMilliseconds(d.timestamp),
       y: d.value,
       reporter: d.reporter.name
     }))
@@ -18,10 +18,11 @@
 
 const _ = require('lodash')
 const moment = require('moment')
-const { FLOAT_PRECISION } = require('./payloads')
+const { FLOAT_PRECISION, toMilliseconds } = require('./payloads')
 
 const STRINGIFIERS = {
   LOCATION: v => `${v.latitude}, ${v.longitude}`,
+  TIMESTAMP: v => formatTimestamp(v),
   weight: v => `${v}kg`,
   temperature: v => `${v} °C`,
   shock: v => `${v}g`,
@@ -59,13 +60,11 @@ const floatifyValue = value => {
 }
 
 /**
- * Parses seconds into a date/time string
+ * Parses a timestamp into a date/time string
  */
-const formatTimestamp = sec => {
-  if (!sec) {
-    sec = Date.now() / 1000
-  }
-  return moment.unix(sec).format('MM/DD/YYYY, h:mm:ss a')
+const formatTimestamp = timestamp => {
+  const millis = timestamp ? toMilliseconds(timestamp) : Date.now()
+  return moment(millis).format('MM/DD/YYYY, h:mm:ss a')
 }
 
 module.exports = {
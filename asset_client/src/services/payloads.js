@@ -31,7 +31,36 @@ const ACTIONS = [
   'UPDATE_PROPERTIES',
   'CREATE_PROPOSAL',
   'ANSWER_PROPOSAL',
-  'REVOKE_REPORTER'
+  'REVOKE_REPORTER',
+  'EXECUTE_TRANSFER',
+  'MESSAGE',
+  'SUBSCRIBE',
+  'UNSUBSCRIBE',
+  'COMPOSITE',
+  'RECEIVE_CONFIRMATION',
+  'ANCHOR_STATE',
+  'CLONE_RECORD',
+  'ANCHOR_FEEDBACK',
+  'MIGRATE_STATE',
+  'DELETE_RECORD_TYPE',
+  'ACKNOWLEDGE_VALUE',
+  'UPDATE_RECORD_TYPE',
+  'DIRECT_TRANSFER',
+  'BULK_UPDATE_PROPERTIES',
+  'DELEGATE_REPORTER',
+  'MERGE_RECORDS',
+  'ARCHIVE_RECORD',
+  'REOPEN_RECORD',
+  'RECALL_RECORD',
+  'SET_AGENT_ROLE',
+  'SET_RECORD_METADATA',
+  'ATTEST_AUDIT',
+  'CANCEL_ALL_PROPOSALS',
+  'AMEND_PROPOSAL',
+  'RELEASE_ESCROW',
+  'DEACTIVATE_AGENT',
+  'CREATE_RECORD_TEMPLATE',
+  'CREATE_RECORD_FROM_TEMPLATE'
 ]
 
 // Create dictionary with key, enum and class names
@@ -75,6 +104,45 @@ _.map(actionMap, action => _.set(action, 'xform', x => x))
 actionMap.createRecord.xform = valueXform
 actionMap.createRecordType.xform = schemaXform
 actionMap.updateProperties.xform = valueXform
+actionMap.bulkUpdateProperties.xform = valueXform
+actionMap.cloneRecord.xform = valueXform
+// Composite actions are made from payloads encoded by the other methods
+actionMap.composite.xform = data => {
+  return _.set(data, 'actions', data.actions.map(a => SCPayload.decode(a)))
+}
+
+// Payload timestamps are in the network's unit, which the server reports,
+// and are read from a clock that can be replaced, for example by one
+// corrected against a time server
+let timestampUnit = 'seconds'
+let clock = () => Date.now()
+
+const setTimestampUnit = unit => {
+  timestampUnit = unit === 'milliseconds' ? unit : 'seconds'
+}
+
+/**
+ * Sets the clock timestamps are read from, a function returning the
+ * current time in milliseconds, like Date.now
+ */
+const setClock = clockFn => {
+  clock = clockFn
+}
+
+/**
+ * Returns the current time as a payload timestamp
+ */
+const makeTimestamp = () => {
+  const now = Math.floor(clock())
+  return timestampUnit === 'milliseconds' ? now : Math.floor(now / 1000)
+}
+
+/**
+ * Converts a timestamp read from the chain into milliseconds
+ */
+const toMilliseconds = timestamp => {
+  return timestampUnit === 'milliseconds' ? timestamp : timestamp * 1000
+}
 
 /**
  * Encodes a new SCPayload with the specified action
@@ -87,11 +155,49 @@ const encode = (actionKey, actionData) => {
 
   return SCPayload.encode({
     action: SCPayload.Action[action.enum],
-    timestamp: Math.floor(Date.now() / 1000),
+    timestamp: makeTimestamp(),
     [actionKey]: action.proto.create(action.xform(actionData))
   }).finish()
 }
 
+/**
+ * Re-encodes a payload with its timestamp moved back to a Unix UTC
+ * timestamp, if it is later than it
+ */
+const limitTimestamp = (payload, latest) => {
+  const decoded = SCPayload.decode(payload)
+  const timestamp = _.isNumber(decoded.timestamp)
+    ? decoded.timestamp
+    : decoded.timestamp.toNumber()
+  if (timestamp <= latest) return payload
+
+  decoded.timestamp = latest
+  return SCPayload.encode(decoded).finish()
+}
+
+/**
+ * Returns the names of the RecordTypes a payload creates or updates,
+ * including those of the actions of a composite payload
+ */
+const recordTypeNames = payload => {
+  const decoded = SCPayload.decode(payload)
+  const actions = decoded.action === SCPayload.Action.COMPOSITE
+    ? decoded.composite.actions
+    : [decoded]
+
+  return actions
+    .map(action => {
+      if (action.action === SCPayload.Action.CREATE_RECORD_TYPE) {
+        return action.createRecordType.name
+      }
+      if (action.action === SCPayload.Action.UPDATE_RECORD_TYPE) {
+        return action.updateRecordType.name
+      }
+      return null
+    })
+    .filter(name => name)
+}
+
 /**
  * Particular encode methods can be called directly with their key name
  * For example: payloads.createAgent({name: 'Susan'})
@@ -104,10 +210,17 @@ const actionMethods = _.reduce(actionMap, (methods, value, key) => {
 actionMethods.createRecord.enum = PropertySchema.DataType
 actionMethods.createRecordType.enum = PropertySchema.DataType
 actionMethods.updateProperties.enum = PropertySchema.DataType
+actionMethods.bulkUpdateProperties.enum = PropertySchema.DataType
 actionMethods.createProposal.enum = Proposal.Role
 actionMethods.answerProposal.enum = actionMap.answerProposal.proto.Response
 
 module.exports = _.assign({
   encode,
+  limitTimestamp,
+  makeTimestamp,
+  recordTypeNames,
+  setClock,
+  setTimestampUnit,
+  toMilliseconds,
   FLOAT_PRECISION: 1000000
 }, actionMethods)
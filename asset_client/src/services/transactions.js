@@ -28,8 +28,15 @@ const {
 } = require('sawtooth-sdk/protobuf')
 const modals = require('../components/modals')
 const api = require('../services/api')
+const payloads = require('../services/payloads')
+const {
+  makeNamespaceInputs,
+  SETTING_ADDRESSES
+} = require('../../../shared/setting_addresses')
 
 const STORAGE_KEY = 'asset_track.encryptedKey'
+const QUEUE_KEY = 'asset_track.queuedTransactions'
+const CONFLICTS_KEY = 'asset_track.queueConflicts'
 const FAMILY_NAME = 'supply_chain'
 const FAMILY_VERSION = '1.1'
 const NAMESPACE = '3400de'
@@ -41,7 +48,10 @@ let batcherPublicKey = null
 
 const setBatcherPubkey = () => {
   return api.get('info')
-    .then(({ pubkey }) => { batcherPublicKey = pubkey })
+    .then(({ pubkey, timestampUnit }) => {
+      batcherPublicKey = pubkey
+      payloads.setTimestampUnit(timestampUnit)
+    })
 }
 setBatcherPubkey()
 
@@ -63,12 +73,18 @@ const requestPassword = () => {
 }
 
 const createTxn = payload => {
+  // A RecordType with a namespaced name is checked against the setting
+  // listing the namespace's members
+  const namespaceInputs = _.flatMap(
+    payloads.recordTypeNames(payload),
+    makeNamespaceInputs)
+
   const header = TransactionHeader.encode({
     signerPublicKey,
     batcherPublicKey,
     familyName: FAMILY_NAME,
     familyVersion: FAMILY_VERSION,
-    inputs: [NAMESPACE],
+    inputs: _.uniq([NAMESPACE].concat(SETTING_ADDRESSES, namespaceInputs)),
     outputs: [NAMESPACE],
     nonce: (Math.random() * 10 ** 18).toString(36),
     payloadSha512: createHash('sha512').update(payload).digest('hex'),
@@ -151,12 +167,8 @@ const changePassword = password => {
     })
 }
 
-/**
- * Wraps a Protobuf payload in a TransactionList and submits it to the API.
- * Prompts user for their password if their private key is not in memory.
- */
-const submit = (payloads, wait = false) => {
-  if (!_.isArray(payloads)) payloads = [payloads]
+// Signs payloads and sends them to the API as a single batch
+const send = (payloadList, wait) => {
   return Promise.resolve()
     .then(() => {
       if (privateKey) return
@@ -172,17 +184,131 @@ const submit = (payloads, wait = false) => {
       return setBatcherPubkey()
     })
     .then(() => {
-      const txns = payloads.map(payload => createTxn(payload))
+      const txns = payloadList.map(payload => createTxn(payload))
       const txnList = encodeTxns(txns)
       return api.postBinary(`transactions${wait ? '?wait' : ''}`, txnList)
     })
+    .then(result => {
+      api.setMinBlock(result.blockNum)
+      return result
+    })
+}
+
+const readList = key => JSON.parse(window.localStorage.getItem(key) || '[]')
+
+const writeList = (key, list) => {
+  window.localStorage.setItem(key, JSON.stringify(list))
+}
+
+/**
+ * Stores payloads made while offline, to be submitted together once there
+ * is a connection again.
+ */
+const enqueue = payloadList => {
+  const queued = readList(QUEUE_KEY).concat({
+    queuedAt: Date.now(),
+    payloads: payloadList.map(p => Buffer.from(p).toString('base64'))
+  })
+  writeList(QUEUE_KEY, queued)
+  return { queued: queued.length }
+}
+
+/**
+ * Returns the payloads waiting to be submitted, and those which were
+ * rejected when they were, each with the error from the API.
+ */
+const getQueued = () => readList(QUEUE_KEY)
+const getConflicts = () => readList(CONFLICTS_KEY)
+const clearConflicts = () => window.localStorage.removeItem(CONFLICTS_KEY)
+
+// Only rejections from the API have an error message, any other failure
+// means the connection was lost again
+const isRejection = err => !!err && err.error !== undefined
+
+/**
+ * Submits queued payloads, oldest first, stopping if the connection is
+ * lost again. Payloads keep the time they were made at, so values are
+ * recorded as of when they were observed, unless the device's clock had
+ * run ahead, as the transaction processor rejects timestamps later than
+ * its own. Rejected payloads are not retried, but moved to the conflicts
+ * for the user to resolve, as the Record they were about may have changed
+ * hands in the meantime.
+ */
+const submitQueued = () => {
+  const submitNext = counts => {
+    const queued = readList(QUEUE_KEY)
+    if (queued.length === 0) return counts
+
+    const now = payloads.makeTimestamp()
+    const entry = queued[0]
+    const entryPayloads = entry.payloads.map(encoded => {
+      return payloads.limitTimestamp(Buffer.from(encoded, 'base64'), now)
+    })
+
+    return send(entryPayloads, true)
+      .then(() => _.assign(counts, { submitted: counts.submitted + 1 }))
+      .catch(err => {
+        if (!isRejection(err)) throw err
+        writeList(CONFLICTS_KEY, getConflicts().concat(_.assign({}, entry, {
+          rejectedAt: Date.now(),
+          error: err.error
+        })))
+        return _.assign(counts, { rejected: counts.rejected + 1 })
+      })
+      .then(updated => {
+        writeList(QUEUE_KEY, readList(QUEUE_KEY).slice(1))
+        return submitNext(updated)
+      })
+  }
+
+  return submitNext({ submitted: 0, rejected: 0 })
 }
 
+/**
+ * Wraps a Protobuf payload in a TransactionList and submits it to the API.
+ * Prompts user for their password if their private key is not in memory.
+ * While the browser is offline, the payloads are queued instead, and
+ * submitted once it is back online.
+ */
+const submit = (payloadList, wait = false) => {
+  if (!_.isArray(payloadList)) payloadList = [payloadList]
+  if (!window.navigator.onLine) {
+    return Promise.resolve(enqueue(payloadList))
+  }
+  return send(payloadList, wait)
+}
+
+window.addEventListener('online', () => {
+  if (getQueued().length === 0) return
+
+  submitQueued()
+    .then(({ rejected }) => {
+      if (rejected === 0) return
+      return modals.show(modals.BasicModal, {
+        title: 'Queued Transactions Rejected',
+        acceptText: 'Dismiss',
+        body: m('.container', getConflicts().map(conflict => {
+          return m('.mb-2', [
+            m('strong', new Date(conflict.queuedAt).toLocaleString()),
+            ': ',
+            conflict.error
+          ])
+        }))
+      })
+        .then(clearConflicts, _.noop)
+    })
+    .catch(err => console.warn('Unable to submit queued transactions:', err))
+})
+
 module.exports = {
   makePrivateKey,
   setPrivateKey,
   clearPrivateKey,
   getPrivateKey,
   changePassword,
-  submit
+  submit,
+  submitQueued,
+  getQueued,
+  getConflicts,
+  clearConflicts
 }
@@ -153,6 +153,17 @@ const _updateReporters = (vnode, reporterIndex) => {
   }
 }
 
+/**
+ * The reporters entered in the form, authorized by the create record
+ * transaction itself rather than by proposals they must accept.
+ */
+const _reporterAuthorizations = state => state.reporters
+  .filter((reporter) => !!reporter.reporterKey)
+  .map((reporter) => ({
+    publicKey: reporter.reporterKey,
+    properties: reporter.properties
+  }))
+
 /**
  * Handle the form submission.
  *
@@ -195,19 +206,11 @@ const _handleSubmit = (signingKey, state) => {
   const recordPayload = payloads.createRecord({
     recordId: state.serialNumber,
     recordType: 'asset',
-    properties
+    properties,
+    reporters: _reporterAuthorizations(state)
   })
 
-  const reporterPayloads = state.reporters
-    .filter((reporter) => !!reporter.reporterKey)
-    .map((reporter) => payloads.createProposal({
-      recordId: state.serialNumber,
-      receivingAgent: reporter.reporterKey,
-      role: payloads.createProposal.enum.REPORTER,
-      properties: reporter.properties
-    }))
-
-  transactions.submit([recordPayload].concat(reporterPayloads), true)
+  transactions.submit([recordPayload], true)
     .then(() => m.route.set(`/assets/${state.serialNumber}`))
 }
 
@@ -527,6 +527,19 @@ const AssetDetail = {
           onsuccess: () => _loadData(vnode.attrs.recordId, vnode.state)
         })),
 
+        (record.owner === publicKey
+         ? (record.unacknowledged || []).map(name => _row(
+             _labelProperty('Awaiting Acknowledgment', _propLink(record, name, name)),
+             m('button.btn.btn-primary', {
+               onclick: (e) => {
+                 e.preventDefault()
+                 _acknowledgeValue(record, name).then(() =>
+                   _loadData(vnode.attrs.recordId, vnode.state))
+               }
+             },
+             'Acknowledge')))
+         : ''),
+
         ((record.owner === publicKey && !record.final)
          ? m('.row.m-2',
              m('.col.text-center',
@@ -571,11 +584,9 @@ const _formatTemp = (temp) => {
   return 'Unknown'
 }
 
-const _formatTimestamp = (sec) => {
-  if (!sec) {
-    sec = Date.now() / 1000
-  }
-  return moment.unix(sec).format('YYYY-MM-DD')
+const _formatTimestamp = (timestamp) => {
+  const millis = timestamp ? payloads.toMilliseconds(timestamp) : Date.now()
+  return moment(millis).format('YYYY-MM-DD')
 }
 
 const _loadData = (recordId, state) => {
@@ -629,6 +640,17 @@ const _updateProperty = (record, value) => {
   })
 }
 
+const _acknowledgeValue = (record, propertyName) => {
+  let acknowledgePayload = payloads.acknowledgeValue({
+    recordId: record.recordId,
+    propertyName
+  })
+
+  return transactions.submit([acknowledgePayload], true).then(() => {
+    console.log('Successfully submitted acknowledgment')
+  })
+}
+
 const _finalizeRecord = (record) => {
   let finalizePayload = payloads.finalizeRecord({
     recordId: record.recordId
@@ -23,6 +23,7 @@ const sjcl = require('sjcl')
 const API_PATH = 'api/'
 const STORAGE_KEY = 'fish_net.authorization'
 let authToken = null
+let minBlock = null
 
 /**
  * Generates a base-64 encoded SHA-256 hash of a plain text password
@@ -56,6 +57,16 @@ const clearAuth = () => {
   return token
 }
 
+/**
+ * Records the block a submitted transaction was committed in, so that
+ * later reads wait for the server to have synced it
+ */
+const setMinBlock = blockNum => {
+  if (_.isInteger(blockNum) && (minBlock === null || blockNum > minBlock)) {
+    minBlock = blockNum
+  }
+}
+
 /**
  * Parses the authToken to return the logged in user's public key
  */
@@ -78,6 +89,11 @@ const baseRequest = opts => {
  * Submits a request to an api endpoint with an auth header if present
  */
 const request = (method, endpoint, data) => {
+  if (method === 'GET' && minBlock !== null) {
+    const separator = endpoint.includes('?') ? '&' : '?'
+    endpoint = `${endpoint}${separator}min_block=${minBlock}`
+  }
+
   return baseRequest({
     method,
     url: endpoint,
@@ -112,6 +128,7 @@ module.exports = {
   setAuth,
   clearAuth,
   getPublicKey,
+  setMinBlock,
   request,
   get,
   post,
@@ -178,6 +178,17 @@ const _updateReporters = (vnode, reporterIndex) => {
   }
 }
 
+/**
+ * The reporters entered in the form, authorized by the create record
+ * transaction itself rather than by proposals they must accept.
+ */
+const _reporterAuthorizations = state => state.reporters
+  .filter((reporter) => !!reporter.reporterKey)
+  .map((reporter) => ({
+    publicKey: reporter.reporterKey,
+    properties: reporter.properties
+  }))
+
 /**
  * Handle the form submission.
  *
@@ -211,19 +222,11 @@ const _handleSubmit = (signingKey, state) => {
         },
         dataType: payloads.createRecord.enum.LOCATION
       }
-    ]
+    ],
+    reporters: _reporterAuthorizations(state)
   })
 
-  const reporterPayloads = state.reporters
-    .filter((reporter) => !!reporter.reporterKey)
-    .map((reporter) => payloads.createProposal({
-      recordId: state.serialNumber,
-      receivingAgent: reporter.reporterKey,
-      role: payloads.createProposal.enum.REPORTER,
-      properties: reporter.properties
-    }))
-
-  transactions.submit([recordPayload].concat(reporterPayloads), true)
+  transactions.submit([recordPayload], true)
     .then(() => m.route.set(`/fish/${state.serialNumber}`))
 }
 
@@ -14,8 +14,10 @@
 # limitations under the License.
 # ------------------------------------------------------------------------------
 
+import hashlib
 import json
 import logging
+import time
 import unittest
 
 from sawtooth_integration.tests.integration_tools import RestClient
@@ -24,13 +26,22 @@ from sawtooth_integration.tests.integration_tools import wait_for_rest_apis
 from sawtooth_sc_test.supply_chain_message_factory import \
     SupplyChainMessageFactory
 from sawtooth_sc_test.supply_chain_message_factory import Enum
+from sawtooth_sc_test.supply_chain_message_factory import Identifier
+from sawtooth_sc_test.supply_chain_message_factory import Measurement
+from sawtooth_sc_test.supply_chain_message_factory import RecordReference
+from sawtooth_sc_test.supply_chain_message_factory import Timestamp
 from sawtooth_signing import create_context
 from sawtooth_signing import CryptoFactory
 
 import sawtooth_sc_test.addressing as addressing
+from sawtooth_sc_test.protobuf.feedback_pb2 import FeedbackEntry
+from sawtooth_sc_test.protobuf.property_pb2 import Location
 from sawtooth_sc_test.protobuf.property_pb2 import PropertySchema
 from sawtooth_sc_test.protobuf.proposal_pb2 import Proposal
+from sawtooth_sc_test.protobuf.payload_pb2 import AgentRole
 from sawtooth_sc_test.protobuf.payload_pb2 import AnswerProposalAction
+from sawtooth_sc_test.protobuf.payload_pb2 import BulkUpdatePropertiesAction
+from sawtooth_sc_test.protobuf.subscription_pb2 import Subscription
 
 
 LOGGER = logging.getLogger(__name__)
@@ -70,36 +81,108 @@ class SupplyChainClient(RestClient):
             self.factory.create_agent(
                 name))
 
-    def create_record_type(self, name, *properties):
+    def create_record_type(self, name, *properties, rules=None,
+                           retention_rules=None, finalization_policy=None):
         return self._post_sc_transaction(
             self.factory.create_record_type(
-                name, *properties))
+                name, *properties, rules=rules,
+                retention_rules=retention_rules,
+                finalization_policy=finalization_policy))
 
-    def create_record(self, record_id, record_type, properties_dict):
+    def set_agent_role(self, agent_id, role):
+        return self._post_sc_transaction(
+            self.factory.set_agent_role(agent_id, role))
+
+    def deactivate_agent(self, agent_id):
+        return self._post_sc_transaction(
+            self.factory.deactivate_agent(agent_id))
+
+    def create_record(self, record_id, record_type, properties_dict,
+                      reporters=None):
         return self._post_sc_transaction(
             self.factory.create_record(
-                record_id, record_type, properties_dict))
+                record_id, record_type, properties_dict, reporters))
+
+    def create_record_template(self, name, record_type, properties_dict):
+        return self._post_sc_transaction(
+            self.factory.create_record_template(
+                name, record_type, properties_dict))
+
+    def create_record_from_template(self, record_id, template,
+                                    properties_dict=None, reporters=None):
+        return self._post_sc_transaction(
+            self.factory.create_record_from_template(
+                record_id, template, properties_dict, reporters))
+
+    def clone_record(self, record_id, source_record_id,
+                     properties_dict=None):
+        return self._post_sc_transaction(
+            self.factory.clone_record(
+                record_id, source_record_id, properties_dict))
+
+    def merge_records(self, record_id, record_type, parent_ids,
+                      properties_dict=None):
+        return self._post_sc_transaction(
+            self.factory.merge_records(
+                record_id, record_type, parent_ids, properties_dict))
 
     def finalize_record(self, record_id):
         return self._post_sc_transaction(
             self.factory.finalize_record(
                 record_id))
 
-    def update_properties(self, record_id, properties_dict):
+    def archive_record(self, record_id, prune_values=False,
+                       delete_pages=False):
+        return self._post_sc_transaction(
+            self.factory.archive_record(
+                record_id, prune_values, delete_pages))
+
+    def reopen_record(self, record_id):
+        return self._post_sc_transaction(
+            self.factory.reopen_record(record_id))
+
+    def set_record_metadata(self, record_id, metadata_dict):
+        return self._post_sc_transaction(
+            self.factory.set_record_metadata(record_id, metadata_dict))
+
+    def attest_audit(self, record_id, reviewed_through, digest, passed):
+        return self._post_sc_transaction(
+            self.factory.attest_audit(
+                record_id, reviewed_through, digest, passed))
+
+    def recall_record(self, record_id, reason, include_descendants=False):
+        return self._post_sc_transaction(
+            self.factory.recall_record(
+                record_id, reason, include_descendants))
+
+    def update_properties(self, record_id, properties_dict,
+                          struct_masks=None):
         return self._post_sc_transaction(
             self.factory.update_properties(
-                record_id, properties_dict))
+                record_id, properties_dict, struct_masks))
+
+    def bulk_update_properties(self, record_ids, properties_dict,
+                               struct_masks=None, policy=None):
+        return self._post_sc_transaction(
+            self.factory.bulk_update_properties(
+                record_ids, properties_dict, struct_masks, policy))
 
     def create_proposal(self, record_id, receiving_agent,
-                        role, properties=None):
+                        role, properties=None, effective_at=0,
+                        expires_at=0, expiration=0, approvers=None,
+                        transfer_terms=None, escrow=False,
+                        release_property=''):
         if properties is None:
             properties = []
 
         return self._post_sc_transaction(
             self.factory.create_proposal(
-                record_id, receiving_agent, role, properties))
+                record_id, receiving_agent, role, properties,
+                effective_at, expires_at, expiration, approvers,
+                transfer_terms, escrow, release_property))
 
-    def answer_proposal(self, record_id, role, response, receiving_agent=None):
+    def answer_proposal(self, record_id, role, response, receiving_agent=None,
+                        terms=''):
         if receiving_agent is None:
             receiving_agent = self.public_key
 
@@ -108,13 +191,100 @@ class SupplyChainClient(RestClient):
                 record_id=record_id,
                 receiving_agent=receiving_agent,
                 role=role,
-                response=response))
+                response=response,
+                terms=terms))
+
+    def amend_proposal(self, record_id, receiving_agent, role,
+                       properties=None, terms='', transfer_terms=None):
+        return self._post_sc_transaction(
+            self.factory.amend_proposal(
+                record_id, receiving_agent, role, properties, terms,
+                transfer_terms))
+
+    def release_escrow(self, record_id, revert=False):
+        return self._post_sc_transaction(
+            self.factory.release_escrow(record_id, revert))
+
+    def cancel_all_proposals(self, record_id, receiving_agents=()):
+        return self._post_sc_transaction(
+            self.factory.cancel_all_proposals(record_id, receiving_agents))
 
     def revoke_reporter(self, record_id, reporter_id, properties):
         return self._post_sc_transaction(
             self.factory.revoke_reporter(
                 record_id, reporter_id, properties))
 
+    def delegate_reporter(self, record_id, reporter_id, properties):
+        return self._post_sc_transaction(
+            self.factory.delegate_reporter(
+                record_id, reporter_id, properties))
+
+    def execute_transfer(self, record_id, receiving_agent, role):
+        return self._post_sc_transaction(
+            self.factory.execute_transfer(
+                record_id, receiving_agent, role))
+
+    def anchor_message(self, record_id, recipient, digest,
+                       proposal_timestamp=0):
+        return self._post_sc_transaction(
+            self.factory.anchor_message(
+                record_id, recipient, digest, proposal_timestamp))
+
+    def subscribe(self, scope, target, events):
+        return self._post_sc_transaction(
+            self.factory.subscribe(scope, target, events))
+
+    def unsubscribe(self, scope, target):
+        return self._post_sc_transaction(
+            self.factory.unsubscribe(scope, target))
+
+    def receive_confirmation(self, record_id, checks, notes=''):
+        return self._post_sc_transaction(
+            self.factory.receive_confirmation(record_id, checks, notes))
+
+    def anchor_state(self, service, block_num, block_id, state_root,
+                     receipt):
+        return self._post_sc_transaction(
+            self.factory.anchor_state(
+                service, block_num, block_id, state_root, receipt))
+
+    def anchor_feedback(self, record_id, kind, digest):
+        return self._post_sc_transaction(
+            self.factory.anchor_feedback(record_id, kind, digest))
+
+    def migrate_state(self, writes):
+        return self._post_sc_transaction(
+            self.factory.migrate_state(writes))
+
+    def delete_record_type(self, name):
+        return self._post_sc_transaction(
+            self.factory.delete_record_type(name))
+
+    def acknowledge_value(self, record_id, property_name):
+        return self._post_sc_transaction(
+            self.factory.acknowledge_value(record_id, property_name))
+
+    def authorize_transfer(self, record_id, role, issuing_agent,
+                           expires_at):
+        return self.factory.authorize_transfer(
+            record_id, role, issuing_agent, expires_at)
+
+    def direct_transfer(self, authorization, signature):
+        return self._post_sc_transaction(
+            self.factory.direct_transfer(authorization, signature))
+
+    def update_record_type(self, name, *properties, rules=None,
+                           retention_rules=None, finalization_policy=None):
+        return self._post_sc_transaction(
+            self.factory.update_record_type(
+                name, *properties, rules=rules,
+                retention_rules=retention_rules,
+                finalization_policy=finalization_policy))
+
+    def composite(self, *transactions):
+        return self._post_sc_transaction(
+            self.factory.composite(*transactions))
+
     def send_empty_payload(self):
         return self._post_sc_transaction(
             self.factory.make_empty_payload(
@@ -136,6 +306,13 @@ class SupplyChainClient(RestClient):
             headers={'Authorization': self.auth_token},
         )[1]
 
+    def get_agent_subscriptions(self, public_key):
+        return self._submit_request(
+            url='{}/agents/{}/subscriptions'.format(
+                API,
+                public_key)
+        )[1]
+
     def get_records(self, fields=None, omit=None):
         return self._submit_request(
             url='{}/records{}'.format(
@@ -153,6 +330,82 @@ class SupplyChainClient(RestClient):
             headers={'Authorization': self.auth_token}
         )[1]
 
+    def get_record_type(self, name):
+        return self._submit_request(
+            url='{}/record-types/{}'.format(API, name)
+        )[1]
+
+    def get_record_template(self, name):
+        return self._submit_request(
+            url='{}/record-templates/{}'.format(API, name)
+        )[1]
+
+    def get_record_timeline(self, record_id, page=None):
+        return self._submit_request(
+            url='{}/records/{}/timeline{}'.format(
+                API,
+                record_id,
+                '' if page is None else '?page={}'.format(page))
+        )[1]
+
+    def get_record_holders(self, record_id, at):
+        return self._submit_request(
+            url='{}/records/{}/holders?at={}'.format(
+                API,
+                record_id,
+                at)
+        )[1]
+
+    def get_record_messages(self, record_id, digest=None):
+        return self._submit_request(
+            url='{}/records/{}/messages{}'.format(
+                API,
+                record_id,
+                '' if digest is None else '?digest={}'.format(digest))
+        )[1]
+
+    def get_location_reports(self, polygon, start=None, end=None):
+        query = 'polygon={}'.format(
+            ';'.join('{},{}'.format(lat, lng) for lat, lng in polygon))
+        if start is not None:
+            query += '&start={}'.format(start)
+        if end is not None:
+            query += '&end={}'.format(end)
+        return self._submit_request(
+            url='{}/location-reports?{}'.format(API, query)
+        )[1]
+
+    def get_record_changes(self, record_ids, since=None, block_id=None):
+        query = 'ids={}'.format(','.join(record_ids))
+        if since is not None:
+            query += '&since={}'.format(since)
+        if block_id is not None:
+            query += '&blockId={}'.format(block_id)
+        return self._submit_request(
+            url='{}/sync?{}'.format(API, query)
+        )[1]
+
+    def get_agent_activity(self, public_key, page=None):
+        return self._submit_request(
+            url='{}/agents/{}/activity{}'.format(
+                API,
+                public_key,
+                '' if page is None else '?page={}'.format(page))
+        )[1]
+
+    def get_record_verification(self, record_id):
+        return self._submit_request(
+            url='{}/verify/{}'.format(API, record_id)
+        )[1]
+
+    def validate_payload(self, **action):
+        return self._submit_request(
+            url=SERVER_URL + '/validate',
+            method='POST',
+            headers={'Content-Type': 'application/json'},
+            data=json.dumps(dict(signer=self.public_key, **action)),
+        )[1]
+
     def get_record_property(self, record_id, property_name,
                             fields=None, omit=None):
         return self._submit_request(
@@ -163,6 +416,34 @@ class SupplyChainClient(RestClient):
                 make_query_string(fields, omit))
         )[1]
 
+    def get_block_activity(self, block_id):
+        return self._submit_request(
+            url='{}/blocks/{}/supply-chain-activity'.format(
+                API,
+                block_id)
+        )[1]
+
+    def get_record_type_stats(self, type_name):
+        return self._submit_request(
+            url='{}/record-types/{}/stats'.format(API, type_name)
+        )[1]
+
+    def get_latest_block_ids(self, count):
+        return [
+            block['header_signature']
+            for block in self._submit_request(
+                url='{}/blocks?limit={}'.format(URL, count)
+            )[1]['data']
+        ]
+
+    def get_record_property_excursions(self, record_id, property_name):
+        return self._submit_request(
+            url='{}/records/{}/property/{}/excursions'.format(
+                API,
+                record_id,
+                property_name)
+        )[1]
+
     def post_user(self, username):
         response = self._submit_request(
             url=SERVER_URL + '/users',
@@ -195,7 +476,9 @@ class TestSupplyChain(unittest.TestCase):
                 'Transaction is unexpectedly invalid -- {}'.format(
                     result[1]['data'][0]['invalid_transactions'][0]['message']))
 
-    def assert_invalid(self, result):
+    def assert_invalid(self, result, message=None):
+        """Checks the transaction was rejected, and if a message is given,
+        that the processor's reason includes it"""
         self.narrate('{}', result)
         try:
             self.assertEqual(
@@ -204,6 +487,10 @@ class TestSupplyChain(unittest.TestCase):
         except (KeyError, IndexError):
             raise AssertionError(
                 'Transaction is unexpectedly valid')
+        if message is not None:
+            self.assertIn(
+                message,
+                result[1]['data'][0]['invalid_transactions'][0]['message'])
 
     def narrate(self, message, *interpolations):
         if NARRATION:
@@ -964,6 +1251,3631 @@ class TestSupplyChain(unittest.TestCase):
             }
         )
 
+    def test_record_type_rules(self):
+        kate = SupplyChainClient()
+        self.assert_valid(
+            kate.create_agent('Kate Austen'))
+
+        self.narrate(
+            '''
+            Kate creates a record type whose rules cap reported
+            temperatures and forbid transfers back to the current owner.
+            ''')
+
+        self.assert_invalid(
+            kate.create_record_type(
+                'crate',
+                ('temperature', PropertySchema.NUMBER, {}),
+                rules=['value <']))
+
+        self.assert_valid(
+            kate.create_record_type(
+                'crate',
+                ('temperature', PropertySchema.NUMBER, {}),
+                rules=[
+                    'action != "update" || value < 40000',
+                    'action != "transfer" || to != owner',
+                ]))
+
+        self.assert_valid(
+            kate.create_record('crate-1', 'crate', {}))
+
+        self.assert_valid(
+            kate.update_properties('crate-1', {'temperature': 39999}))
+
+        self.assert_invalid(
+            kate.update_properties('crate-1', {'temperature': 40000}))
+
+        self.assert_valid(
+            kate.create_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                receiving_agent=kate.public_key,
+            ))
+
+        self.assert_invalid(
+            kate.answer_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        sawyer = SupplyChainClient()
+        self.assert_valid(
+            sawyer.create_agent('James Ford'))
+
+        self.assert_valid(
+            kate.create_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                receiving_agent=sawyer.public_key,
+            ))
+
+        self.assert_valid(
+            sawyer.answer_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+    def test_scheduled_transfer(self):
+        ben = SupplyChainClient()
+        ana = SupplyChainClient()
+        self.assert_valid(ben.create_agent('Ben Linus'))
+        self.assert_valid(ana.create_agent('Ana Lucia'))
+
+        self.assert_valid(
+            ben.create_record_type(
+                'deed',
+                ('parcel', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            ben.create_record('deed-1', 'deed', {}))
+
+        self.narrate(
+            '''
+            Ben agrees to sell their deed to Ana, with title passing at a
+            contractually agreed time shortly from now.
+            ''')
+
+        effective_at = round(time.time()) + 10
+
+        self.assert_valid(
+            ben.create_proposal(
+                record_id='deed-1',
+                role=Proposal.OWNER,
+                receiving_agent=ana.public_key,
+                effective_at=effective_at,
+            ))
+
+        self.assert_valid(
+            ana.answer_proposal(
+                record_id='deed-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assertEqual(ben.get_record('deed-1')['owner'], ben.public_key)
+
+        self.assert_invalid(
+            ana.execute_transfer(
+                record_id='deed-1',
+                receiving_agent=ana.public_key,
+                role=Proposal.OWNER,
+            ))
+
+        time.sleep(max(0, effective_at - time.time()) + 1)
+
+        self.assert_valid(
+            ana.execute_transfer(
+                record_id='deed-1',
+                receiving_agent=ana.public_key,
+                role=Proposal.OWNER,
+            ))
+
+        self.assert_invalid(
+            ana.execute_transfer(
+                record_id='deed-1',
+                receiving_agent=ana.public_key,
+                role=Proposal.OWNER,
+            ))
+
+    def test_reporter_expiry(self):
+        rousseau = SupplyChainClient()
+        miles = SupplyChainClient()
+        self.assert_valid(rousseau.create_agent('Danielle Rousseau'))
+        self.assert_valid(miles.create_agent('Miles Straume'))
+
+        self.assert_valid(
+            rousseau.create_record_type(
+                'beacon',
+                ('signal', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            rousseau.create_record('beacon-1', 'beacon', {}))
+
+        self.narrate(
+            '''
+            Rousseau hires Miles to monitor their beacon for a short
+            contract. Expiry times only apply to reporter authorization,
+            and must be in the future.
+            ''')
+
+        self.assert_invalid(
+            rousseau.create_proposal(
+                record_id='beacon-1',
+                role=Proposal.CUSTODIAN,
+                receiving_agent=miles.public_key,
+                expires_at=round(time.time()) + 60,
+            ))
+
+        self.assert_invalid(
+            rousseau.create_proposal(
+                record_id='beacon-1',
+                role=Proposal.REPORTER,
+                properties=['signal'],
+                receiving_agent=miles.public_key,
+                expires_at=round(time.time()) - 60,
+            ))
+
+        expires_at = round(time.time()) + 10
+
+        self.assert_valid(
+            rousseau.create_proposal(
+                record_id='beacon-1',
+                role=Proposal.REPORTER,
+                properties=['signal'],
+                receiving_agent=miles.public_key,
+                expires_at=expires_at,
+            ))
+
+        self.assert_valid(
+            miles.answer_proposal(
+                record_id='beacon-1',
+                role=Proposal.REPORTER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assert_valid(
+            miles.update_properties('beacon-1', {'signal': 16}))
+
+        self.assertIn(
+            miles.public_key,
+            miles.get_record_property('beacon-1', 'signal')['reporters'])
+
+        self.narrate(
+            '''
+            Once the contract runs out, Miles can no longer report,
+            without Rousseau having to revoke them.
+            ''')
+
+        time.sleep(max(0, expires_at - time.time()) + 1)
+
+        self.assert_invalid(
+            miles.update_properties('beacon-1', {'signal': 23}))
+
+        self.assertNotIn(
+            miles.public_key,
+            miles.get_record_property('beacon-1', 'signal')['reporters'])
+
+    def test_reporter_slot_reuse(self):
+        eloise = SupplyChainClient()
+        daniel = SupplyChainClient()
+        self.assert_valid(eloise.create_agent('Eloise Hawking'))
+        self.assert_valid(daniel.create_agent('Daniel Faraday'))
+
+        self.assert_valid(
+            eloise.create_record_type(
+                'pendulum',
+                ('swing', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            eloise.create_record('pendulum-1', 'pendulum', {}))
+
+        def authorize():
+            self.assert_valid(
+                eloise.create_proposal(
+                    record_id='pendulum-1',
+                    role=Proposal.REPORTER,
+                    properties=['swing'],
+                    receiving_agent=daniel.public_key,
+                ))
+
+            self.assert_valid(
+                daniel.answer_proposal(
+                    record_id='pendulum-1',
+                    role=Proposal.REPORTER,
+                    response=AnswerProposalAction.ACCEPT,
+                ))
+
+        authorize()
+
+        self.assert_valid(
+            daniel.update_properties('pendulum-1', {'swing': 1}))
+
+        self.narrate(
+            '''
+            Eloise revokes Daniel and later takes them back on. Daniel
+            keeps their old reporter slot, so both of their values are
+            still attributed to them.
+            ''')
+
+        self.assert_valid(
+            eloise.revoke_reporter(
+                record_id='pendulum-1',
+                reporter_id=daniel.public_key,
+                properties=['swing']))
+
+        authorize()
+
+        self.assert_valid(
+            daniel.update_properties('pendulum-1', {'swing': 2}))
+
+        swing = eloise.get_record_property('pendulum-1', 'swing')
+
+        log_json(swing)
+
+        self.assertEqual(
+            sorted(swing['reporters']),
+            sorted([eloise.public_key, daniel.public_key]))
+
+        self.assertEqual(
+            [update['reporter']['publicKey'] for update in swing['updates']],
+            [daniel.public_key, daniel.public_key])
+
+    def test_reporter_index_reuse(self):
+        karen = SupplyChainClient()
+        self.assert_valid(karen.create_agent('Karen DeGroot'))
+
+        gerald = SupplyChainClient()
+        self.assert_valid(gerald.create_agent('Gerald DeGroot'))
+
+        harper = SupplyChainClient()
+        self.assert_valid(harper.create_agent('Harper Stanhope'))
+
+        self.assert_valid(
+            karen.create_record_type(
+                'orientation-film',
+                ('plays', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            karen.create_record('film-1', 'orientation-film', {}))
+
+        def authorize(agent):
+            self.assert_valid(
+                karen.create_proposal(
+                    record_id='film-1',
+                    role=Proposal.REPORTER,
+                    properties=['plays'],
+                    receiving_agent=agent.public_key,
+                ))
+
+            self.assert_valid(
+                agent.answer_proposal(
+                    record_id='film-1',
+                    role=Proposal.REPORTER,
+                    response=AnswerProposalAction.ACCEPT,
+                ))
+
+        self.narrate(
+            '''
+            Karen authorizes Gerald to count the film's plays, but
+            revokes them before they report any. Harper, authorized
+            next, takes over Gerald's reporter slot rather than adding
+            another.
+            ''')
+
+        authorize(gerald)
+
+        self.assert_valid(
+            karen.revoke_reporter(
+                record_id='film-1',
+                reporter_id=gerald.public_key,
+                properties=['plays']))
+
+        authorize(harper)
+
+        self.assert_valid(
+            harper.update_properties('film-1', {'plays': 1}))
+
+        plays = karen.get_record_property('film-1', 'plays')
+
+        self.assertEqual(
+            sorted(plays['reporters']),
+            sorted([karen.public_key, harper.public_key]))
+
+        self.assertEqual(
+            [update['reporter']['publicKey'] for update in plays['updates']],
+            [harper.public_key])
+
+    def test_location_reports(self):
+        ilana = SupplyChainClient()
+        self.assert_valid(ilana.create_agent('Ilana Verdansky'))
+
+        self.assert_valid(
+            ilana.create_record_type(
+                'crate',
+                ('position', PropertySchema.LOCATION, {})))
+
+        def position(latitude, longitude):
+            return Location(
+                latitude=int(latitude * 1000000),
+                longitude=int(longitude * 1000000))
+
+        self.assert_valid(
+            ilana.create_record(
+                'crate-1', 'crate', {'position': position(44.05, -79.1)}))
+
+        self.assert_valid(
+            ilana.create_record(
+                'crate-2', 'crate', {'position': position(45.5, -73.6)}))
+
+        self.narrate(
+            '''
+            A warehouse floods, and Ilana needs to know which crates
+            were reported inside it, and when.
+            ''')
+
+        warehouse = [
+            (44.0, -79.2),
+            (44.1, -79.2),
+            (44.1, -79.0),
+            (44.0, -79.0),
+        ]
+
+        # Keep the crates' reports in different seconds
+        time.sleep(1)
+
+        self.assert_valid(
+            ilana.update_properties(
+                'crate-2', {'position': position(44.06, -79.05)}))
+
+        self.assert_valid(
+            ilana.update_properties(
+                'crate-1', {'position': position(45.0, -75.0)}))
+
+        reports = ilana.get_location_reports(warehouse)
+
+        log_json(reports)
+
+        self.assertEqual(
+            [record['recordId'] for record in reports],
+            ['crate-1', 'crate-2'])
+
+        self.assertEqual(len(reports[0]['reports']), 1)
+        self.assertEqual(reports[0]['reports'][0]['propertyName'], 'position')
+        self.assertEqual(
+            reports[1]['reports'][0]['location'],
+            {'latitude': 44060000, 'longitude': -79050000})
+
+        packed = reports[0]['reports'][0]['timestamp']
+
+        self.assertEqual(
+            [record['recordId'] for record in
+             ilana.get_location_reports(warehouse, end=packed)],
+            ['crate-1'])
+
+        self.assertEqual(
+            ilana.get_location_reports(
+                [(0, 0), (0, 1), (1, 1)], start=0),
+            [])
+
+    def test_public_verification(self):
+        hugo = SupplyChainClient()
+        libby = SupplyChainClient()
+        self.assert_valid(hugo.create_agent('Hugo Reyes'))
+        self.assert_valid(libby.create_agent('Libby Smith'))
+
+        self.assert_valid(
+            hugo.create_record_type(
+                'lottery-ticket',
+                ('origin', PropertySchema.STRING, {'required': True}),
+                ('serial', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            hugo.create_record(
+                'ticket-1', 'lottery-ticket',
+                {'origin': 'Mr. Cluck\'s', 'serial': '4815162342'}))
+
+        self.assert_valid(
+            hugo.create_proposal(
+                record_id='ticket-1',
+                role=Proposal.OWNER,
+                receiving_agent=libby.public_key,
+            ))
+
+        self.assert_valid(
+            libby.answer_proposal(
+                record_id='ticket-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.narrate(
+            '''
+            Anyone who scans the ticket can see where it came from and
+            who has owned it, without signing in, along with the state
+            addresses needed to check that against the blockchain.
+            ''')
+
+        provenance = SupplyChainClient().get_record_verification('ticket-1')
+
+        log_json(provenance)
+
+        self.assertEqual(provenance['recordType'], 'lottery-ticket')
+        self.assertEqual(provenance['origin']['name'], 'Hugo Reyes')
+        self.assertEqual(
+            [owner['publicKey'] for owner in provenance['ownership']],
+            [hugo.public_key, libby.public_key])
+
+        # Only public properties are included
+        self.assertEqual(list(provenance['properties']), ['origin'])
+        self.assertEqual(
+            provenance['properties']['origin']['value'], 'Mr. Cluck\'s')
+
+        proof = provenance['proof']
+        self.assertEqual(
+            proof['addresses']['record'],
+            addressing.make_record_address('ticket-1'))
+        self.assertEqual(len(proof['addresses']['timeline']), 1)
+        self.assertEqual(len(proof['stateRootHash']), 64)
+
+        self.assertEqual(
+            hugo.get_record_verification('ticket-2'),
+            'Not Found')
+
+    def test_payload_validation(self):
+        ben = SupplyChainClient()
+        juliet = SupplyChainClient()
+        self.assert_valid(ben.create_agent('Ben Linus'))
+        self.assert_valid(juliet.create_agent('Juliet Burke'))
+
+        self.assert_valid(
+            ben.create_record_type(
+                'sample',
+                ('donor', PropertySchema.STRING, {'required': True}),
+                ('volume', PropertySchema.NUMBER, {})))
+
+        self.narrate(
+            '''
+            Before submitting a payload, Ben can check it against the
+            current RecordType, and is told every problem with it.
+            ''')
+
+        result = ben.validate_payload(createRecord={
+            'recordId': 'sample-1',
+            'recordType': 'sample',
+            'properties': [
+                {'name': 'volume', 'dataType': 'STRING',
+                 'stringValue': '12'},
+                {'name': 'color', 'dataType': 'STRING',
+                 'stringValue': 'red'},
+            ],
+        })
+
+        log_json(result)
+
+        self.assertFalse(result['valid'])
+        self.assertEqual(
+            sorted(error['message'] for error in result['errors']),
+            ['Provided property color is not in schemata',
+             'Required property donor not provided',
+             'Value provided for volume is the wrong type'])
+
+        self.assertEqual(
+            ben.validate_payload(createRecord={
+                'recordId': 'sample-1',
+                'recordType': 'sample',
+                'properties': [
+                    {'name': 'donor', 'dataType': 'STRING',
+                     'stringValue': 'Ethan Rom'},
+                ],
+            }),
+            {'valid': True, 'errors': []})
+
+        self.assert_valid(
+            ben.create_record('sample-1', 'sample', {'donor': 'Ethan Rom'}))
+
+        self.narrate(
+            '''
+            Juliet is not a reporter, so an update from them would be
+            rejected, with the same message the processor would give.
+            ''')
+
+        result = juliet.validate_payload(updateProperties={
+            'recordId': 'sample-1',
+            'properties': [
+                {'name': 'volume', 'dataType': 'NUMBER',
+                 'numberValue': 12},
+            ],
+        })
+
+        self.assertFalse(result['valid'])
+        self.assertEqual(
+            result['errors'],
+            [{'property': 'volume',
+              'message': 'Reporter is not authorized: {}'.format(
+                  juliet.public_key)}])
+
+    def test_composite(self):
+        desmond = SupplyChainClient()
+        penny = SupplyChainClient()
+        self.assert_valid(desmond.create_agent('Desmond Hume'))
+        self.assert_valid(penny.create_agent('Penny Widmore'))
+
+        self.narrate(
+            '''
+            Desmond defines a RecordType, creates a Record of it, and asks
+            Penny to report on it, all in a single transaction.
+            ''')
+
+        self.assert_valid(
+            desmond.composite(
+                desmond.factory.create_record_type(
+                    'letter',
+                    ('sender', PropertySchema.STRING, {'required': True}),
+                    ('location', PropertySchema.LOCATION, {})),
+                desmond.factory.create_record(
+                    'letter-1', 'letter', {'sender': 'Desmond Hume'}),
+                desmond.factory.create_proposal(
+                    'letter-1', penny.public_key, Proposal.REPORTER,
+                    ['location'])))
+
+        self.assertEqual(
+            desmond.get_record_type('letter')['name'], 'letter')
+        self.assert_valid(
+            penny.answer_proposal(
+                record_id='letter-1',
+                role=Proposal.REPORTER,
+                response=AnswerProposalAction.ACCEPT))
+
+        self.narrate(
+            '''
+            If any action is invalid, none of them are applied.
+            ''')
+
+        self.assert_invalid(
+            desmond.composite(
+                desmond.factory.create_record(
+                    'letter-2', 'letter', {'sender': 'Desmond Hume'}),
+                desmond.factory.create_record(
+                    'letter-3', 'postcard', {'sender': 'Desmond Hume'})))
+
+        self.assertEqual(desmond.get_record_verification('letter-2'),
+                         'Not Found')
+
+        self.assert_invalid(
+            desmond.composite(
+                desmond.factory.composite(
+                    desmond.factory.finalize_record('letter-1'))))
+
+    def test_agent_activity(self):
+        sayid = SupplyChainClient()
+        self.assert_valid(sayid.create_agent('Sayid Jarrah'))
+
+        self.assert_valid(
+            sayid.create_record_type(
+                'radio',
+                ('frequency', PropertySchema.NUMBER, {'required': True})))
+
+        self.assert_valid(
+            sayid.create_record('radio-1', 'radio', {'frequency': 8}))
+
+        self.assert_valid(
+            sayid.update_properties('radio-1', {'frequency': 15}))
+
+        self.narrate(
+            '''
+            Every transaction Sayid signs is added to their activity, which
+            can be read a page at a time.
+            ''')
+
+        activity = sayid.get_agent_activity(sayid.public_key)
+
+        log_json(activity)
+
+        self.assertEqual(activity['currentPage'], 1)
+        self.assertEqual(activity['pageNum'], 1)
+        self.assertEqual(
+            [(entry['action'], entry['recordId'])
+             for entry in activity['entries']],
+            [('CREATE_AGENT', ''),
+             ('CREATE_RECORD_TYPE', ''),
+             ('CREATE_RECORD', 'radio-1'),
+             ('UPDATE_PROPERTIES', 'radio-1')])
+
+        self.assertEqual(
+            sayid.get_agent_activity(sayid.public_key, page=2)['entries'],
+            [])
+
+    def test_record_changes(self):
+        rose = SupplyChainClient()
+        self.assert_valid(rose.create_agent('Rose Nadler'))
+
+        self.assert_valid(
+            rose.create_record_type(
+                'crate',
+                ('weight', PropertySchema.NUMBER, {'required': True})))
+
+        self.assert_valid(
+            rose.create_record('crate-1', 'crate', {'weight': 40}))
+
+        self.assert_valid(
+            rose.create_record('crate-2', 'crate', {'weight': 25}))
+
+        self.narrate(
+            '''
+            Rose's warehouse app keeps a copy of the crates it handles,
+            starting with everything about them.
+            ''')
+
+        first = rose.get_record_changes(['crate-1', 'crate-2'])
+
+        log_json(first)
+
+        self.assertTrue(first['reset'])
+        self.assertEqual(
+            sorted(first['changes']), ['crate-1', 'crate-2'])
+        self.assertIn(
+            ('records', []),
+            [(change['table'], change['key'])
+             for change in first['changes']['crate-1']])
+
+        self.assert_valid(
+            rose.update_properties('crate-1', {'weight': 38}))
+
+        self.narrate(
+            '''
+            Once back online, it asks only for what changed since the
+            block it last saw.
+            ''')
+
+        second = rose.get_record_changes(
+            ['crate-1', 'crate-2'],
+            since=first['blockNum'],
+            block_id=first['blockId'])
+
+        log_json(second)
+
+        self.assertFalse(second['reset'])
+        self.assertEqual(list(second['changes']), ['crate-1'])
+        self.assertEqual(
+            [(change['table'], change['key'])
+             for change in second['changes']['crate-1']],
+            [('propertyPages', ['weight', 1])])
+
+        self.narrate(
+            '''
+            If the block it last saw was forked out, it is told to start
+            its copy over.
+            ''')
+
+        forked = rose.get_record_changes(
+            ['crate-1'], since=first['blockNum'], block_id='0' * 128)
+
+        self.assertTrue(forked['reset'])
+        self.assertIn('crate-1', forked['changes'])
+
+    def test_record_timeline(self):
+        kate = SupplyChainClient()
+        jack = SupplyChainClient()
+        self.assert_valid(kate.create_agent('Kate Austen'))
+        self.assert_valid(jack.create_agent('Jack Shephard'))
+
+        self.assert_valid(
+            kate.create_record_type(
+                'case',
+                ('contents', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            kate.create_record('case-1', 'case', {}))
+
+        created_at = round(time.time())
+        time.sleep(2)
+
+        self.narrate(
+            '''
+            Kate sells their case to Jack. Ownership changes are kept on
+            the Record's timeline, so it can be asked who held the case
+            at any point.
+            ''')
+
+        self.assert_valid(
+            kate.create_proposal(
+                record_id='case-1',
+                role=Proposal.OWNER,
+                receiving_agent=jack.public_key,
+            ))
+
+        self.assert_valid(
+            jack.answer_proposal(
+                record_id='case-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        timeline = jack.get_record_timeline('case-1')
+
+        log_json(timeline)
+
+        self.assertEqual(timeline['pageNum'], 1)
+        self.assertEqual(
+            [(entry['role'], entry['agentId'])
+             for entry in timeline['entries']],
+            [('OWNER', kate.public_key),
+             ('CUSTODIAN', kate.public_key),
+             ('OWNER', jack.public_key)])
+
+        before = jack.get_record_holders('case-1', created_at)
+        self.assertEqual(before['owner'], kate.public_key)
+        self.assertEqual(before['custodian'], kate.public_key)
+
+        after = jack.get_record_holders('case-1', round(time.time()))
+        self.assertEqual(after['owner'], jack.public_key)
+        self.assertEqual(after['custodian'], kate.public_key)
+
+        self.assertEqual(
+            len(jack.get_record('case-1')['updates']['owners']), 2)
+
+    def test_receipt_confirmation(self):
+        hurley = SupplyChainClient()
+        charlie = SupplyChainClient()
+        self.assert_valid(hurley.create_agent('Hugo Reyes'))
+        self.assert_valid(charlie.create_agent('Charlie Pace'))
+
+        self.assert_valid(
+            hurley.create_record_type(
+                'guitar',
+                ('maker', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            hurley.create_record('guitar-1', 'guitar', {}))
+
+        self.assert_valid(
+            hurley.create_proposal(
+                record_id='guitar-1',
+                role=Proposal.CUSTODIAN,
+                receiving_agent=charlie.public_key,
+            ))
+
+        self.narrate(
+            '''
+            Charlie cannot confirm receipt of the guitar before they
+            have custody of it.
+            ''')
+
+        self.assert_invalid(
+            charlie.receive_confirmation(
+                'guitar-1', [('case sealed', True)]))
+
+        self.assert_valid(
+            charlie.answer_proposal(
+                record_id='guitar-1',
+                role=Proposal.CUSTODIAN,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.narrate(
+            '''
+            Once they do, Charlie records its condition on arrival: the
+            case was sealed, but a string was broken.
+            ''')
+
+        self.assert_invalid(
+            charlie.receive_confirmation('guitar-1', []))
+
+        self.assert_valid(
+            charlie.receive_confirmation(
+                'guitar-1',
+                [('case sealed', True),
+                 ('strings intact', False, 'high E snapped')],
+                notes='Arrived by boat'))
+
+        timeline = charlie.get_record_timeline('guitar-1')
+
+        log_json(timeline)
+
+        receipt = timeline['entries'][-1]['receipt']
+        self.assertEqual(timeline['entries'][-1]['agentId'],
+                         charlie.public_key)
+        self.assertEqual(receipt['notes'], 'Arrived by boat')
+        self.assertEqual(
+            [(check['item'], check['passed'], check['note'])
+             for check in receipt['checks']],
+            [('case sealed', True, ''),
+             ('strings intact', False, 'high E snapped')])
+
+        self.assertEqual(
+            charlie.get_record('guitar-1')['updates']['custodians'][0][
+                'receipt']['notes'],
+            'Arrived by boat')
+
+        self.narrate(
+            '''
+            A custodianship is confirmed only once, and only by the
+            custodian.
+            ''')
+
+        self.assert_invalid(
+            charlie.receive_confirmation(
+                'guitar-1', [('case sealed', True)]))
+
+        self.assert_invalid(
+            hurley.receive_confirmation(
+                'guitar-1', [('case sealed', True)]))
+
+    def test_message_anchors(self):
+        ben = SupplyChainClient()
+        juliet = SupplyChainClient()
+        self.assert_valid(ben.create_agent('Ben Linus'))
+        self.assert_valid(juliet.create_agent('Juliet Burke'))
+
+        self.assert_valid(
+            ben.create_record_type(
+                'vaccine',
+                ('doses', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            ben.create_record('vaccine-1', 'vaccine', {}))
+
+        self.narrate(
+            '''
+            Ben and Juliet negotiate over the vaccine off-chain. Ben
+            anchors a digest of their offer, so either of them can later
+            prove what was said without the offer itself being stored.
+            ''')
+
+        offer = b'100 doses for the hatch access code'
+        digest = hashlib.sha512(offer).hexdigest()
+
+        self.assert_invalid(
+            ben.anchor_message('vaccine-1', juliet.public_key, 'not-a-hash'))
+
+        self.assert_valid(
+            ben.anchor_message('vaccine-1', juliet.public_key, digest))
+
+        self.assert_invalid(
+            juliet.anchor_message('vaccine-1', ben.public_key, digest))
+
+        messages = juliet.get_record_messages('vaccine-1', digest)
+
+        log_json(messages)
+
+        self.assertEqual(len(messages), 1)
+        self.assertEqual(messages[0]['sender'], ben.public_key)
+        self.assertEqual(messages[0]['recipient'], juliet.public_key)
+        self.assertEqual(messages[0]['proposalTimestamp'], 0)
+
+    def test_subscriptions(self):
+        sayid = SupplyChainClient()
+        self.assert_valid(sayid.create_agent('Sayid Jarrah'))
+
+        self.assert_valid(
+            sayid.create_record_type(
+                'radio',
+                ('frequency', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            sayid.create_record('radio-1', 'radio', {}))
+
+        self.narrate(
+            '''
+            Sayid registers what they want to hear about: changes to
+            their radio, and transfers of any Record they hold. A subscription
+            to a Record that does not exist is rejected.
+            ''')
+
+        self.assert_invalid(
+            sayid.subscribe(
+                Subscription.RECORD, 'radio-2',
+                [Subscription.PROPERTY_UPDATED]))
+
+        self.assert_valid(
+            sayid.subscribe(
+                Subscription.RECORD, 'radio-1',
+                [Subscription.PROPERTY_UPDATED, Subscription.FINALIZED]))
+
+        self.assert_valid(
+            sayid.subscribe(
+                Subscription.HELD_RECORDS, '',
+                [Subscription.TRANSFERRED]))
+
+        subscriptions = sayid.get_agent_subscriptions(sayid.public_key)
+
+        log_json(subscriptions)
+
+        self.assertEqual(
+            [(sub['scope'], sub['target'], sub['events'])
+             for sub in subscriptions],
+            [('RECORD', 'radio-1', ['PROPERTY_UPDATED', 'FINALIZED']),
+             ('HELD_RECORDS', '', ['TRANSFERRED'])])
+
+        self.assert_valid(
+            sayid.unsubscribe(Subscription.RECORD, 'radio-1'))
+
+        self.assert_invalid(
+            sayid.unsubscribe(Subscription.RECORD, 'radio-1'))
+
+        self.assertEqual(
+            len(sayid.get_agent_subscriptions(sayid.public_key)), 1)
+
+    def test_excursions(self):
+        juliet = SupplyChainClient()
+        self.assert_valid(juliet.create_agent('Juliet Burke'))
+
+        self.narrate(
+            '''
+            Juliet keeps vaccines that must stay between 2 and 8
+            degrees. A threshold only makes sense for numbers.
+            ''')
+
+        self.assert_invalid(
+            juliet.create_record_type(
+                'label',
+                ('text', PropertySchema.STRING,
+                 {'threshold': {'min': 2, 'max': 8}})))
+
+        self.assert_valid(
+            juliet.create_record_type(
+                'vaccine',
+                ('temperature', PropertySchema.NUMBER,
+                 {'threshold': {'min': 2, 'max': 8}})))
+
+        self.assert_valid(
+            juliet.create_record(
+                'vaccine-1', 'vaccine', {'temperature': 5}))
+
+        self.narrate(
+            '''
+            The freezer fails. Out of range readings are still
+            accepted, but they are logged as an excursion which ends
+            with the first reading back in range.
+            ''')
+
+        for temperature in (9, 12, 10, 6):
+            self.assert_valid(
+                juliet.update_properties(
+                    'vaccine-1', {'temperature': temperature}))
+
+        excursions = juliet.get_record_property_excursions(
+            'vaccine-1', 'temperature')
+
+        log_json(excursions)
+
+        self.assertEqual(len(excursions), 1)
+        self.assertEqual(excursions[0]['peakValue'], 12)
+        self.assertEqual(excursions[0]['valueCount'], 3)
+        self.assertGreaterEqual(
+            excursions[0]['end'], excursions[0]['start'])
+        self.assertNotEqual(excursions[0]['end'], 0)
+
+        self.assert_valid(
+            juliet.update_properties('vaccine-1', {'temperature': 1}))
+
+        excursions = juliet.get_record_property_excursions(
+            'vaccine-1', 'temperature')
+
+        self.assertEqual(len(excursions), 2)
+        self.assertEqual(excursions[1]['end'], 0)
+
+    def test_retention_rules(self):
+        rose = SupplyChainClient()
+        self.assert_valid(rose.create_agent('Rose Nadler'))
+
+        self.narrate(
+            '''
+            Rose must keep shipment summaries forever, but raw
+            telemetry only for thirty days after a shipment ends.
+            A retention rule must name one of the type's Properties.
+            ''')
+
+        self.assert_invalid(
+            rose.create_record_type(
+                'crate',
+                ('temperature', PropertySchema.NUMBER, {}),
+                retention_rules={'humidity': 30 * 86400}))
+
+        self.assert_valid(
+            rose.create_record_type(
+                'crate',
+                ('temperature', PropertySchema.NUMBER, {}),
+                ('summary', PropertySchema.STRING, {}),
+                retention_rules={'temperature': 30 * 86400}))
+
+        record_type = rose.get_record_type('crate')
+
+        log_json(record_type)
+
+        self.assertEqual(
+            record_type['retentionRules'],
+            [{'propertyName': 'temperature', 'prunableAfter': 30 * 86400}])
+
+        self.assert_valid(
+            rose.create_record('crate-1', 'crate', {}))
+
+        self.assertEqual(rose.get_record('crate-1')['finalizedAt'], 0)
+
+        self.assert_valid(rose.finalize_record('crate-1'))
+
+        self.assertNotEqual(rose.get_record('crate-1')['finalizedAt'], 0)
+
+    def test_default_values(self):
+        hurley = SupplyChainClient()
+        self.assert_valid(hurley.create_agent('Hugo Reyes'))
+
+        self.narrate(
+            '''
+            Hurley's lottery tickets all start out unclaimed, so the
+            type gives the claimed flag a default. A default must match
+            its Property's type, and cannot be given to a required
+            Property.
+            ''')
+
+        self.assert_invalid(
+            hurley.create_record_type(
+                'ticket',
+                ('claimed', PropertySchema.BOOLEAN,
+                 {'default_value': 'no'})))
+
+        self.assert_invalid(
+            hurley.create_record_type(
+                'ticket',
+                ('claimed', PropertySchema.BOOLEAN,
+                 {'required': True, 'default_value': False})))
+
+        self.assert_valid(
+            hurley.create_record_type(
+                'ticket',
+                ('numbers', PropertySchema.STRING, {'required': True}),
+                ('claimed', PropertySchema.BOOLEAN,
+                 {'default_value': False})))
+
+        self.assert_valid(
+            hurley.create_record(
+                'ticket-1', 'ticket', {'numbers': '4 8 15 16 23 42'}))
+
+        claimed = hurley.get_record_property('ticket-1', 'claimed')
+
+        log_json(claimed)
+
+        self.assertIs(claimed['value']['value'], False)
+        self.assertEqual(claimed['value']['reporter']['publicKey'],
+                         hurley.public_key)
+
+    def test_immutable_properties(self):
+        desmond = SupplyChainClient()
+        self.assert_valid(desmond.create_agent('Desmond Hume'))
+
+        self.assert_valid(
+            desmond.create_record_type(
+                'hatch',
+                ('serial', PropertySchema.STRING, {'immutable': True}),
+                ('origin', PropertySchema.STRING, {'immutable': True}),
+                ('code', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            desmond.create_record(
+                'hatch-1', 'hatch', {'serial': 'swan-3'}))
+
+        self.narrate(
+            '''
+            Desmond may set the origin once, since it was not given at
+            creation, but neither it nor the serial number can change
+            after that. Other Properties update as usual.
+            ''')
+
+        self.assert_invalid(
+            desmond.update_properties('hatch-1', {'serial': 'swan-4'}))
+
+        self.assert_valid(
+            desmond.update_properties('hatch-1', {'origin': 'Dharma'}))
+
+        self.assert_invalid(
+            desmond.update_properties('hatch-1', {'origin': 'Hanso'}))
+
+        self.assert_valid(
+            desmond.update_properties('hatch-1', {'code': 4815162342}))
+
+        record = desmond.get_record('hatch-1')
+
+        log_json(record)
+
+        properties = {prop['name']: prop for prop in record['properties']}
+        self.assertEqual(properties['origin']['value'], 'Dharma')
+        self.assertTrue(properties['serial']['immutable'])
+        self.assertNotIn('immutable', properties['code'])
+
+    def test_property_flag_combinations(self):
+        charlotte = SupplyChainClient()
+        self.assert_valid(charlotte.create_agent('Charlotte Lewis'))
+
+        self.narrate(
+            '''
+            Charlotte's dig site samples need flags that work together.
+            A Property cannot be both required and delayed, nor fixed and
+            delayed, since neither could ever be set. An immutable
+            Property cannot have a default, which would use up its only
+            value.
+            ''')
+
+        self.assert_invalid(
+            charlotte.create_record_type(
+                'sample',
+                ('site', PropertySchema.STRING,
+                 {'required': True, 'delayed': True})))
+
+        self.assert_invalid(
+            charlotte.create_record_type(
+                'sample',
+                ('site', PropertySchema.STRING,
+                 {'fixed': True, 'delayed': True})))
+
+        self.assert_invalid(
+            charlotte.create_record_type(
+                'sample',
+                ('site', PropertySchema.STRING,
+                 {'immutable': True, 'default_value': 'unknown'})))
+
+        self.assert_valid(
+            charlotte.create_record_type(
+                'sample',
+                ('site', PropertySchema.STRING,
+                 {'required': True, 'immutable': True}),
+                ('age', PropertySchema.NUMBER,
+                 {'delayed': True, 'immutable': True}),
+                ('depth', PropertySchema.NUMBER,
+                 {'fixed': True, 'immutable': True})))
+
+        self.narrate(
+            '''
+            The site must be given at creation, and the age cannot be.
+            ''')
+
+        self.assert_invalid(
+            charlotte.create_record(
+                'sample-1', 'sample', {'depth': 3}))
+
+        self.assert_invalid(
+            charlotte.create_record(
+                'sample-1', 'sample', {'site': 'Tunisia', 'age': 2000}))
+
+        self.assert_valid(
+            charlotte.create_record(
+                'sample-1', 'sample', {'site': 'Tunisia', 'depth': 3}))
+
+        self.narrate(
+            '''
+            After creation, only the age can be set, and only once.
+            ''')
+
+        self.assert_invalid(
+            charlotte.update_properties('sample-1', {'site': 'Oxford'}))
+
+        self.assert_invalid(
+            charlotte.update_properties('sample-1', {'depth': 4}))
+
+        self.assert_valid(
+            charlotte.update_properties('sample-1', {'age': 2000}))
+
+        self.assert_invalid(
+            charlotte.update_properties('sample-1', {'age': 2001}))
+
+        age = charlotte.get_record_property('sample-1', 'age')
+
+        log_json(age)
+
+        self.assertEqual(age['value']['value'], 2000)
+
+    def test_struct_partial_updates(self):
+        miles = SupplyChainClient()
+        self.assert_valid(miles.create_agent('Miles Straume'))
+
+        self.assert_valid(
+            miles.create_record_type(
+                'crate',
+                ('manifest', PropertySchema.STRUCT,
+                 {'struct_properties': [
+                     ('contents', PropertySchema.STRING, {}),
+                     ('seal', PropertySchema.STRUCT,
+                      {'struct_properties': [
+                          ('number', PropertySchema.NUMBER, {}),
+                          ('intact', PropertySchema.BOOLEAN, {})
+                      ]})
+                 ]}),
+                ('weight', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(miles.create_record('crate-1', 'crate', {}))
+
+        self.narrate(
+            '''
+            A partial update needs an earlier value to fill in the
+            fields it leaves out.
+            ''')
+
+        self.assert_invalid(
+            miles.update_properties(
+                'crate-1',
+                {'manifest': {'seal': {'intact': False}}},
+                {'manifest': ['seal.intact']}))
+
+        self.assert_valid(
+            miles.update_properties(
+                'crate-1',
+                {'manifest': {
+                    'contents': 'rations',
+                    'seal': {'number': 23, 'intact': True}}}))
+
+        self.narrate(
+            '''
+            When the seal is broken, Miles only sends that one field.
+            Fields outside the mask, and masks for anything but a
+            STRUCT, are rejected.
+            ''')
+
+        self.assert_invalid(
+            miles.update_properties(
+                'crate-1',
+                {'manifest': {'contents': 'guns', 'seal': {'intact': False}}},
+                {'manifest': ['seal.intact']}))
+
+        self.assert_invalid(
+            miles.update_properties(
+                'crate-1',
+                {'weight': 40},
+                {'weight': ['value']}))
+
+        self.assert_valid(
+            miles.update_properties(
+                'crate-1',
+                {'manifest': {'seal': {'intact': False}}},
+                {'manifest': ['seal.intact']}))
+
+        manifest = miles.get_record_property('crate-1', 'manifest')
+
+        log_json(manifest)
+
+        self.assertEqual(
+            manifest['value']['value'],
+            {'contents': 'rations',
+             'seal': {'number': 23, 'intact': False}})
+
+    def test_state_anchors(self):
+        ilana = SupplyChainClient()
+        self.assert_valid(ilana.create_agent('Ilana Verdansky'))
+
+        block_id = 'b' * 128
+        state_root = 'c' * 64
+
+        self.narrate(
+            '''
+            Ilana tries to record a receipt for publishing a state root,
+            but the anchor must name its service, carry a receipt, and
+            have a well-formed block id and root.
+            ''')
+
+        self.assert_invalid(
+            ilana.anchor_state('', 1, block_id, state_root, b'receipt'))
+
+        self.assert_invalid(
+            ilana.anchor_state('opentimestamps', 1, block_id, state_root,
+                               b''))
+
+        self.assert_invalid(
+            ilana.anchor_state('opentimestamps', 1, block_id, 'not-a-root',
+                               b'receipt'))
+
+        self.narrate(
+            '''
+            Even a well-formed anchor is rejected, since only the keys in
+            the supply_chain.anchor.signers setting may record anchors,
+            and this network lists none. Sent with the inputs the anchor
+            script lists, the setting is read, so that is the reason.
+            ''')
+
+        self.assert_invalid(
+            ilana.anchor_state('opentimestamps', 1, block_id, state_root,
+                               b'receipt'),
+            'Signer is not permitted to record anchors')
+
+    def test_record_type_namespaces(self):
+        widmore = SupplyChainClient()
+        self.assert_valid(widmore.create_agent('Charles Widmore'))
+
+        self.narrate(
+            '''
+            Widmore wants their types kept apart from the Dharma
+            Initiative's, but a namespaced name can only be used once the
+            network registers the namespace and its members, which this
+            network has not.
+            ''')
+
+        self.assert_invalid(
+            widmore.create_record_type(
+                'widmore.freighter',
+                ('tonnage', PropertySchema.NUMBER, {})))
+
+        self.assert_invalid(
+            widmore.create_record_type(
+                '.freighter',
+                ('tonnage', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            widmore.create_record_type(
+                'freighter',
+                ('tonnage', PropertySchema.NUMBER, {})))
+
+    def test_proposal_guards(self):
+        chang = SupplyChainClient()
+        horace = SupplyChainClient()
+        radzinsky = SupplyChainClient()
+
+        self.assert_valid(chang.create_agent('Pierre Chang'))
+        self.assert_valid(horace.create_agent('Horace Goodspeed'))
+        self.assert_valid(radzinsky.create_agent('Stuart Radzinsky'))
+
+        self.assert_valid(
+            chang.create_record_type(
+                'orientation-film',
+                ('reel', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            chang.create_record('film-1', 'orientation-film', {}))
+
+        self.narrate(
+            '''
+            Chang offers the film to Horace, then changes their mind
+            and tries to offer it to Radzinsky instead. Only one
+            ownership transfer can be open for a Record at a time, so
+            the first offer has to be canceled.
+            ''')
+
+        self.assert_valid(
+            chang.create_proposal(
+                record_id='film-1',
+                role=Proposal.OWNER,
+                receiving_agent=horace.public_key,
+            ))
+
+        self.assert_invalid(
+            chang.create_proposal(
+                record_id='film-1',
+                role=Proposal.OWNER,
+                receiving_agent=radzinsky.public_key,
+            ))
+
+        self.assert_valid(
+            chang.answer_proposal(
+                record_id='film-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.CANCEL,
+                receiving_agent=horace.public_key,
+            ))
+
+        self.assert_valid(
+            chang.create_proposal(
+                record_id='film-1',
+                role=Proposal.OWNER,
+                receiving_agent=radzinsky.public_key,
+            ))
+
+        self.narrate(
+            '''
+            Offers to different Agents to report on the film are fine,
+            up to the network's limit.
+            ''')
+
+        self.assert_valid(
+            chang.create_proposal(
+                record_id='film-1',
+                role=Proposal.REPORTER,
+                properties=['reel'],
+                receiving_agent=horace.public_key,
+            ))
+
+        self.assert_valid(
+            chang.create_proposal(
+                record_id='film-1',
+                role=Proposal.REPORTER,
+                properties=['reel'],
+                receiving_agent=radzinsky.public_key,
+            ))
+
+    def test_block_activity(self):
+        inman = SupplyChainClient()
+        desmond = SupplyChainClient()
+
+        self.assert_valid(inman.create_agent('Kelvin Inman'))
+        self.assert_valid(desmond.create_agent('Desmond Hume'))
+
+        self.assert_valid(
+            inman.create_record_type(
+                'hatch-log',
+                ('entries', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            inman.create_record('hatch-log-1', 'hatch-log', {}))
+
+        self.narrate(
+            '''
+            Inman offers the hatch log to Desmond. A block explorer can
+            see which Records and Agents the block touched from the
+            transaction receipts, without decoding the payload.
+            ''')
+
+        self.assert_valid(
+            inman.create_proposal(
+                record_id='hatch-log-1',
+                role=Proposal.OWNER,
+                receiving_agent=desmond.public_key,
+            ))
+
+        activities = [
+            inman.get_block_activity(block_id)
+            for block_id in inman.get_latest_block_ids(3)
+        ]
+
+        activity = next(
+            activity for activity in activities
+            if 'hatch-log-1' in activity['recordIds'])
+
+        self.assertIn(inman.public_key, activity['agents'])
+        self.assertIn(desmond.public_key, activity['agents'])
+
+    def test_schema_caps(self):
+        bernard = SupplyChainClient()
+        self.assert_valid(bernard.create_agent('Bernard Nadler'))
+
+        self.narrate(
+            '''
+            Bernard grades the island's crops. A type's schemas are
+            capped, so an enum cannot have more than 256 options, and a
+            struct cannot have more than 64 fields at any level.
+            ''')
+
+        self.assert_invalid(
+            bernard.create_record_type(
+                'crop',
+                ('grade', PropertySchema.ENUM,
+                 {'enum_options': [str(n) for n in range(257)]})))
+
+        self.assert_invalid(
+            bernard.create_record_type(
+                'crop',
+                ('yield', PropertySchema.STRUCT,
+                 {'struct_properties': [
+                     (str(n), PropertySchema.NUMBER, {})
+                     for n in range(65)]})))
+
+        self.assert_valid(
+            bernard.create_record_type(
+                'crop',
+                ('grade', PropertySchema.ENUM,
+                 {'enum_options': ['poor', 'fair', 'good']})))
+
+        self.assert_valid(
+            bernard.create_record(
+                'crop-1', 'crop', {'grade': Enum('fair')}))
+
+        self.narrate(
+            '''
+            The options are kept only on the type, so updates are
+            checked against it, and the grade is still shown by name.
+            ''')
+
+        self.assert_invalid(
+            bernard.update_properties('crop-1', {'grade': Enum('great')}))
+
+        self.assert_valid(
+            bernard.update_properties('crop-1', {'grade': Enum('good')}))
+
+        grade = bernard.get_record_property('crop-1', 'grade')
+
+        log_json(grade)
+
+        self.assertEqual(grade['value']['value'], 'good')
+
+    def test_clone_record(self):
+        walt = SupplyChainClient()
+        michael = SupplyChainClient()
+        self.assert_valid(walt.create_agent('Walt Lloyd'))
+        self.assert_valid(michael.create_agent('Michael Dawson'))
+
+        self.assert_valid(
+            walt.create_record_type(
+                'raft',
+                ('hull', PropertySchema.STRING, {}),
+                ('length', PropertySchema.NUMBER, {}),
+                ('serial', PropertySchema.NUMBER, {'immutable': True})))
+
+        self.assert_valid(
+            walt.create_record(
+                'raft-1', 'raft',
+                {'hull': 'bamboo', 'length': 12, 'serial': 1}))
+
+        self.narrate(
+            '''
+            Walt builds rafts to the same plan, so each new one is cloned
+            from the first. Its latest values are copied, but not its
+            serial, which is immutable and is given for the new raft.
+            ''')
+
+        self.assert_valid(
+            walt.clone_record('raft-2', 'raft-1', {'serial': 2}))
+
+        hull = walt.get_record_property('raft-2', 'hull')
+        serial = walt.get_record_property('raft-2', 'serial')
+
+        log_json(hull)
+
+        self.assertEqual(hull['value']['value'], 'bamboo')
+        self.assertEqual(serial['value']['value'], 2)
+
+        self.narrate(
+            '''
+            Only the owner of a Record can clone it, so Michael cannot.
+            ''')
+
+        self.assert_invalid(michael.clone_record('raft-3', 'raft-1'))
+
+    def test_record_type_stats(self):
+        frank = SupplyChainClient()
+        self.assert_valid(frank.create_agent('Frank Lapidus'))
+
+        self.assert_valid(
+            frank.create_record_type(
+                'flight',
+                ('passengers', PropertySchema.NUMBER, {})))
+
+        self.narrate(
+            '''
+            Frank logs each of the day's flights, and closes the log
+            once a flight lands. A dashboard reads the day's totals for
+            the type, without going through every flight.
+            ''')
+
+        self.assert_valid(frank.create_record('flight-1', 'flight', {}))
+        self.assert_valid(frank.create_record('flight-2', 'flight', {}))
+        self.assert_valid(frank.finalize_record('flight-1'))
+
+        stats = frank.get_record_type_stats('flight')
+
+        log_json(stats)
+
+        self.assertEqual(sum(day['created'] for day in stats), 2)
+        self.assertEqual(sum(day['finalized'] for day in stats), 1)
+        for day in stats:
+            if day['finalized'] > 0:
+                self.assertGreaterEqual(day['averageSecondsToFinalize'], 0)
+
+    def test_feedback_anchoring(self):
+        alvar = SupplyChainClient()
+        self.assert_valid(alvar.create_agent('Alvar Hanso'))
+
+        self.assert_valid(
+            alvar.create_record_type(
+                'crate',
+                ('contents', PropertySchema.STRING, {})))
+
+        self.assert_valid(alvar.create_record('crate-1', 'crate', {}))
+
+        digest = hashlib.sha512(b'scanned at the dock').hexdigest()
+
+        self.narrate(
+            '''
+            Alvar sets up a kiosk where visitors scan crates, keeping only
+            a digest of each scan on-chain. The digest must be a SHA-512
+            hash.
+            ''')
+
+        self.assert_invalid(
+            alvar.anchor_feedback('crate-1', FeedbackEntry.SCAN, 'scanned'))
+
+        self.narrate(
+            '''
+            Even a well-formed scan is rejected, since only the keys in
+            the supply_chain.feedback.kiosks setting may anchor them,
+            and this network lists none. Sent with the inputs the clients
+            list, the setting is read, so that is the reason.
+            ''')
+
+        self.assert_invalid(
+            alvar.anchor_feedback('crate-1', FeedbackEntry.SCAN, digest),
+            'Signer is not a feedback kiosk')
+
+    def test_state_migration(self):
+        mittelwerk = SupplyChainClient()
+        self.assert_valid(mittelwerk.create_agent('Thomas Mittelwerk'))
+
+        old_address = addressing.make_record_address('crate-1')
+        new_address = addressing.make_record_address('acme/crate-1')
+
+        self.narrate(
+            '''
+            Thomas wants to move state to a new addressing scheme, but a
+            migration must write at least one address, each in the
+            supply_chain namespace and written only once.
+            ''')
+
+        self.assert_invalid(mittelwerk.migrate_state([]))
+
+        self.assert_invalid(
+            mittelwerk.migrate_state([
+                (addressing.make_setting_address('some.setting'), b'data'),
+            ]))
+
+        self.assert_invalid(
+            mittelwerk.migrate_state([
+                (old_address, b''),
+                (old_address, b''),
+            ]))
+
+        self.narrate(
+            '''
+            Even a well-formed migration is rejected, since only the keys
+            in the supply_chain.migration.signers setting may rewrite
+            state, and this network lists none. Sent with the inputs the
+            migration script lists, the setting is read, so that is the
+            reason.
+            ''')
+
+        self.assert_invalid(
+            mittelwerk.migrate_state([
+                (new_address, b'data'),
+                (old_address, b''),
+            ]),
+            'Signer is not permitted to migrate state')
+
+    def test_delete_record_type(self):
+        richard = SupplyChainClient()
+        self.assert_valid(richard.create_agent('Richard Alpert'))
+
+        ethan = SupplyChainClient()
+        self.assert_valid(ethan.create_agent('Ethan Rom'))
+
+        for name in ('supply-drop', 'survey'):
+            self.assert_valid(
+                richard.create_record_type(
+                    name,
+                    ('weight', PropertySchema.NUMBER, {})))
+
+        self.narrate(
+            '''
+            Richard set up two record types while planning, but only
+            ever records surveys. No supply drop has been recorded, so
+            Richard, and only Richard, can delete that type.
+            ''')
+
+        self.assert_valid(richard.create_record('survey-1', 'survey', {}))
+
+        self.assert_invalid(ethan.delete_record_type('supply-drop'))
+
+        self.assert_valid(richard.delete_record_type('supply-drop'))
+
+        self.assert_invalid(
+            richard.create_record('drop-1', 'supply-drop', {}))
+
+        self.narrate(
+            '''
+            A type with Records is kept, so Richard cannot delete the
+            survey type, nor delete a type that no longer exists.
+            ''')
+
+        self.assert_invalid(richard.delete_record_type('survey'))
+
+        self.assert_invalid(richard.delete_record_type('supply-drop'))
+
+        self.narrate(
+            '''
+            The name is free again, so Ethan can create a type of their
+            own with it.
+            ''')
+
+        self.assert_valid(
+            ethan.create_record_type(
+                'supply-drop',
+                ('crates', PropertySchema.NUMBER, {})))
+
+    def test_acknowledge_value(self):
+        shannon = SupplyChainClient()
+        self.assert_valid(shannon.create_agent('Shannon Rutherford'))
+
+        boone = SupplyChainClient()
+        self.assert_valid(boone.create_agent('Boone Carlyle'))
+
+        self.assert_valid(
+            shannon.create_record_type(
+                'beechcraft',
+                ('damage', PropertySchema.STRING,
+                 {'requires_acknowledgment': True}),
+                ('fuel', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            shannon.create_record('beechcraft-1', 'beechcraft', {}))
+
+        self.assert_valid(
+            shannon.create_proposal(
+                record_id='beechcraft-1',
+                receiving_agent=boone.public_key,
+                role=Proposal.REPORTER,
+                properties=['damage', 'fuel']))
+
+        self.assert_valid(
+            boone.answer_proposal(
+                record_id='beechcraft-1',
+                role=Proposal.REPORTER,
+                response=AnswerProposalAction.ACCEPT))
+
+        self.narrate(
+            '''
+            Boone climbs up to the plane and reports its fuel, which
+            Shannon need not review, and the damage to its fuselage,
+            which Shannon must acknowledge before handing the plane
+            over or closing its record.
+            ''')
+
+        self.assert_valid(
+            boone.update_properties('beechcraft-1', {'fuel': 20}))
+
+        self.assert_valid(
+            boone.update_properties(
+                'beechcraft-1', {'damage': 'fuselage cracked'}))
+
+        self.assert_invalid(shannon.finalize_record('beechcraft-1'))
+
+        self.assert_valid(
+            shannon.create_proposal(
+                record_id='beechcraft-1',
+                receiving_agent=boone.public_key,
+                role=Proposal.OWNER))
+
+        self.assert_invalid(
+            boone.answer_proposal(
+                record_id='beechcraft-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT))
+
+        self.narrate(
+            '''
+            Only Shannon, as owner, can acknowledge the report, and
+            only once. Then Boone can take the plane.
+            ''')
+
+        self.assert_invalid(
+            boone.acknowledge_value('beechcraft-1', 'damage'))
+
+        self.assert_invalid(
+            shannon.acknowledge_value('beechcraft-1', 'fuel'))
+
+        self.assert_valid(
+            shannon.acknowledge_value('beechcraft-1', 'damage'))
+
+        self.assert_invalid(
+            shannon.acknowledge_value('beechcraft-1', 'damage'))
+
+        self.assert_valid(
+            boone.answer_proposal(
+                record_id='beechcraft-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT))
+
+    def test_update_record_type(self):
+        nikki = SupplyChainClient()
+        self.assert_valid(nikki.create_agent('Nikki Fernandez'))
+
+        paulo = SupplyChainClient()
+        self.assert_valid(paulo.create_agent('Paulo'))
+
+        self.assert_valid(
+            nikki.create_record_type(
+                'diamond',
+                ('carats', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            nikki.create_record('diamond-1', 'diamond', {'carats': 3}))
+
+        self.narrate(
+            '''
+            Nikki decides the diamonds should be graded rather than
+            weighed. Only Nikki, who created the type, can change it.
+            ''')
+
+        self.assert_invalid(
+            paulo.update_record_type(
+                'diamond',
+                ('carats', PropertySchema.STRING, {})))
+
+        self.assert_invalid(
+            nikki.update_record_type(
+                'emerald',
+                ('carats', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            nikki.update_record_type(
+                'diamond',
+                ('carats', PropertySchema.STRING, {}),
+                ('cut', PropertySchema.STRING, {})))
+
+        record_type = nikki.get_record_type('diamond')
+
+        log_json(record_type)
+
+        self.assertEqual(
+            [prop['name'] for prop in record_type['properties']],
+            ['carats', 'cut'])
+
+        self.assert_valid(
+            nikki.create_record(
+                'diamond-2', 'diamond', {'carats': 'VS1', 'cut': 'round'}))
+
+        self.narrate(
+            '''
+            The first diamond keeps the definition it was made with, so
+            its weight is still a number, while the second is graded.
+            ''')
+
+        self.assert_valid(
+            nikki.update_properties('diamond-1', {'carats': 4}))
+
+        self.assert_invalid(
+            nikki.update_properties('diamond-1', {'cut': 'round'}))
+
+        self.assert_invalid(
+            nikki.update_properties('diamond-2', {'carats': 4}))
+
+        self.assert_valid(
+            nikki.update_properties('diamond-2', {'carats': 'VVS2'}))
+
+        self.assertEqual(
+            [prop['name']
+             for prop in nikki.get_record('diamond-1')['properties']],
+            ['carats'])
+
+        self.narrate(
+            '''
+            Paulo takes the first diamond, which Nikki can still hand
+            over under its old definition.
+            ''')
+
+        self.assert_valid(
+            nikki.create_proposal(
+                record_id='diamond-1',
+                receiving_agent=paulo.public_key,
+                role=Proposal.OWNER))
+
+        self.assert_valid(
+            paulo.answer_proposal(
+                record_id='diamond-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT))
+
+    def test_create_record_with_reporters(self):
+        tom = SupplyChainClient()
+        self.assert_valid(tom.create_agent('Tom Friendly'))
+
+        eko = SupplyChainClient()
+        self.assert_valid(eko.create_agent('Mr. Eko'))
+
+        self.assert_valid(
+            tom.create_record_type(
+                'hatch',
+                ('countdown', PropertySchema.NUMBER, {}),
+                ('code', PropertySchema.STRING, {})))
+
+        self.narrate(
+            '''
+            Tom sets up the hatch and lets Eko report its countdown
+            from the start, without a proposal for Eko to accept.
+            Eko cannot be given a Property the hatch does not have.
+            ''')
+
+        self.assert_invalid(
+            tom.create_record(
+                'hatch-1', 'hatch', {},
+                reporters={eko.public_key: ['timer']}))
+
+        self.assert_valid(
+            tom.create_record(
+                'hatch-1', 'hatch', {},
+                reporters={eko.public_key: ['countdown']}))
+
+        self.assert_valid(
+            eko.update_properties('hatch-1', {'countdown': 108}))
+
+        self.assert_invalid(
+            eko.update_properties('hatch-1', {'code': '4 8 15 16 23 42'}))
+
+        self.assertIn(
+            eko.public_key,
+            tom.get_record_property('hatch-1', 'countdown')['reporters'])
+
+    def test_direct_transfer(self):
+        claire = SupplyChainClient()
+        self.assert_valid(claire.create_agent('Claire Littleton'))
+
+        mikhail = SupplyChainClient()
+        self.assert_valid(mikhail.create_agent('Mikhail Bakunin'))
+
+        self.assert_valid(
+            claire.create_record_type(
+                'crib',
+                ('height', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            claire.create_record('crib-1', 'crib', {'height': 90}))
+
+        self.narrate(
+            '''
+            Mikhail agrees to take the crib over from Claire for the
+            next hour, and signs the agreement. Claire sends it with
+            the transfer, so the crib changes hands in one transaction.
+            ''')
+
+        expires_at = round(time.time()) + 3600
+
+        authorization, signature = mikhail.authorize_transfer(
+            'crib-1', Proposal.OWNER, claire.public_key, expires_at)
+
+        self.assert_invalid(
+            mikhail.direct_transfer(authorization, signature))
+
+        self.assert_invalid(
+            claire.direct_transfer(authorization, signature[::-1]))
+
+        self.assert_valid(
+            claire.direct_transfer(authorization, signature))
+
+        self.assertEqual(
+            claire.get_record('crib-1')['owner'], mikhail.public_key)
+
+        self.assert_valid(
+            mikhail.update_properties('crib-1', {'height': 95}))
+
+        self.narrate(
+            '''
+            Claire cannot send the agreement again, as the crib is no
+            longer Claire's to hand over.
+            ''')
+
+        self.assert_invalid(
+            claire.direct_transfer(authorization, signature))
+
+    def test_finalization_policy(self):
+        vincent = SupplyChainClient()
+        self.assert_valid(vincent.create_agent('Vincent'))
+
+        self.assert_invalid(
+            vincent.create_record_type(
+                'ration',
+                ('calories', PropertySchema.NUMBER, {}),
+                finalization_policy={'reported_properties': ['brand']}))
+
+        self.assert_valid(
+            vincent.create_record_type(
+                'ration',
+                ('calories', PropertySchema.NUMBER, {}),
+                ('inspector', PropertySchema.STRING, {}),
+                finalization_policy={
+                    'reported_properties': ['inspector'],
+                    'bounds': {'calories': (100, 500)},
+                }))
+
+        self.assert_valid(
+            vincent.create_record('ration-1', 'ration', {'calories': 900}))
+
+        self.narrate(
+            '''
+            Vincent cannot close the ration's record until it has been
+            inspected and its calories are within the bounds its type
+            sets, so a final ration record is a complete one.
+            ''')
+
+        self.assert_invalid(vincent.finalize_record('ration-1'))
+
+        self.assert_valid(
+            vincent.update_properties('ration-1', {'inspector': 'Dharma'}))
+
+        self.assert_invalid(vincent.finalize_record('ration-1'))
+
+        self.assert_valid(
+            vincent.update_properties('ration-1', {'calories': 300}))
+
+        self.assert_valid(vincent.finalize_record('ration-1'))
+
+        self.assertTrue(vincent.get_record('ration-1')['final'])
+
+    def test_bulk_update_properties(self):
+        naomi = SupplyChainClient()
+        self.assert_valid(naomi.create_agent('Naomi Dorrit'))
+
+        karl = SupplyChainClient()
+        self.assert_valid(karl.create_agent('Karl'))
+
+        self.assert_valid(
+            naomi.create_record_type(
+                'crate',
+                ('temperature', PropertySchema.NUMBER, {})))
+
+        for record_id in ('crate-1', 'crate-2'):
+            self.assert_valid(
+                naomi.create_record(record_id, 'crate', {'temperature': 4}))
+
+        self.assert_valid(
+            karl.create_record('crate-3', 'crate', {'temperature': 4}))
+
+        self.narrate(
+            '''
+            The sensor on Naomi's pallet reports the temperature of
+            every crate on it in one transaction. Naomi cannot report
+            on Karl's crate, so none of the crates are updated when it
+            is included.
+            ''')
+
+        self.assert_invalid(
+            naomi.bulk_update_properties(
+                ['crate-1', 'crate-2', 'crate-3'], {'temperature': 6}))
+
+        temperature = naomi.get_record_property('crate-1', 'temperature')
+        self.assertEqual(temperature['value']['value'], 4)
+
+        self.assert_invalid(
+            naomi.bulk_update_properties(
+                ['crate-1', 'crate-1'], {'temperature': 6}))
+
+        self.narrate(
+            '''
+            With the BEST_EFFORT policy, the crates Naomi may report on
+            are updated, and Karl's crate is skipped.
+            ''')
+
+        self.assert_valid(
+            naomi.bulk_update_properties(
+                ['crate-1', 'crate-3'], {'temperature': 5},
+                policy=BulkUpdatePropertiesAction.BEST_EFFORT))
+
+        temperature = naomi.get_record_property('crate-1', 'temperature')
+        self.assertEqual(temperature['value']['value'], 5)
+        temperature = karl.get_record_property('crate-3', 'temperature')
+        self.assertEqual(temperature['value']['value'], 4)
+
+        self.assert_valid(
+            naomi.bulk_update_properties(
+                ['crate-1', 'crate-2'], {'temperature': 6}))
+
+        for record_id in ('crate-1', 'crate-2'):
+            temperature = naomi.get_record_property(record_id, 'temperature')
+            self.assertEqual(temperature['value']['value'], 6)
+
+    def test_proposal_expiration(self):
+        dogen = SupplyChainClient()
+        self.assert_valid(dogen.create_agent('Dogen'))
+
+        lennon = SupplyChainClient()
+        self.assert_valid(lennon.create_agent('Lennon'))
+
+        self.assert_valid(
+            dogen.create_record_type(
+                'scroll',
+                ('pages', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            dogen.create_record('scroll-1', 'scroll', {'pages': 12}))
+
+        self.assert_invalid(
+            dogen.create_proposal(
+                record_id='scroll-1',
+                role=Proposal.OWNER,
+                receiving_agent=lennon.public_key,
+                expiration=round(time.time()) - 60,
+            ))
+
+        self.narrate(
+            '''
+            Dogen offers the scroll to Lennon, but only for a few
+            seconds. Lennon answers too late, so the offer lapses and
+            the scroll stays with Dogen.
+            ''')
+
+        expiration = round(time.time()) + 10
+
+        self.assert_valid(
+            dogen.create_proposal(
+                record_id='scroll-1',
+                role=Proposal.OWNER,
+                receiving_agent=lennon.public_key,
+                expiration=expiration,
+            ))
+
+        self.assertEqual(len(dogen.get_record('scroll-1')['proposals']), 1)
+
+        time.sleep(max(0, expiration - time.time()) + 1)
+
+        self.assertEqual(len(dogen.get_record('scroll-1')['proposals']), 0)
+
+        self.assert_valid(
+            lennon.answer_proposal(
+                record_id='scroll-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assertEqual(
+            dogen.get_record('scroll-1')['owner'], dogen.public_key)
+
+        self.assert_invalid(
+            lennon.answer_proposal(
+                record_id='scroll-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assert_valid(
+            dogen.create_proposal(
+                record_id='scroll-1',
+                role=Proposal.OWNER,
+                receiving_agent=lennon.public_key,
+            ))
+
+    def test_counter_proposal(self):
+        sawyer = SupplyChainClient()
+        self.assert_valid(sawyer.create_agent('Sawyer'))
+
+        locke = SupplyChainClient()
+        self.assert_valid(locke.create_agent('John Locke'))
+
+        self.assert_valid(
+            sawyer.create_record_type(
+                'stash',
+                ('bottles', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            sawyer.create_record('stash-1', 'stash', {'bottles': 40}))
+
+        self.narrate(
+            '''
+            Sawyer offers Locke their stash for a price. Locke counters
+            with a lower one, Sawyer counters back, and Locke accepts.
+            ''')
+
+        self.assert_valid(
+            sawyer.create_proposal(
+                record_id='stash-1',
+                role=Proposal.OWNER,
+                receiving_agent=locke.public_key,
+            ))
+
+        self.assert_invalid(
+            sawyer.answer_proposal(
+                record_id='stash-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.COUNTER,
+                receiving_agent=locke.public_key,
+                terms='Two guns',
+            ))
+
+        self.assert_valid(
+            locke.answer_proposal(
+                record_id='stash-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.COUNTER,
+                terms='One gun',
+            ))
+
+        proposals = sawyer.get_record('stash-1')['proposals']
+        self.assertEqual(len(proposals), 1)
+        self.assertEqual(proposals[0]['issuingAgent'], locke.public_key)
+        self.assertEqual(proposals[0]['receivingAgent'], sawyer.public_key)
+        self.assertEqual(proposals[0]['terms'], 'One gun')
+        self.assertTrue(proposals[0]['toHolder'])
+        self.assertEqual(
+            proposals[0]['previousProposal']['receivingAgent'],
+            locke.public_key)
+
+        self.assert_valid(
+            sawyer.answer_proposal(
+                record_id='stash-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.COUNTER,
+                terms='One gun and the ammunition',
+            ))
+
+        self.assert_valid(
+            locke.answer_proposal(
+                record_id='stash-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assertEqual(
+            sawyer.get_record('stash-1')['owner'], locke.public_key)
+
+    def test_multi_signature_proposal(self):
+        rousseau = SupplyChainClient()
+        self.assert_valid(rousseau.create_agent('Alex Rousseau'))
+
+        aaron = SupplyChainClient()
+        self.assert_valid(aaron.create_agent('Aaron'))
+
+        jacob = SupplyChainClient()
+        self.assert_valid(jacob.create_agent('Jacob'))
+
+        self.assert_valid(
+            rousseau.create_record_type(
+                'transmitter',
+                ('frequency', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            rousseau.create_record(
+                'transmitter-1', 'transmitter', {'frequency': 16}))
+
+        self.narrate(
+            '''
+            Rousseau offers their transmitter to Aaron, but nothing leaves
+            the island without Jacob's approval. Aaron accepts first, and
+            the transmitter only changes hands once Jacob approves too.
+            ''')
+
+        self.assert_invalid(
+            rousseau.create_proposal(
+                record_id='transmitter-1',
+                role=Proposal.OWNER,
+                receiving_agent=aaron.public_key,
+                approvers=[jacob.public_key, jacob.public_key],
+            ))
+
+        self.assert_valid(
+            rousseau.create_proposal(
+                record_id='transmitter-1',
+                role=Proposal.OWNER,
+                receiving_agent=aaron.public_key,
+                approvers=[jacob.public_key],
+            ))
+
+        self.assert_valid(
+            aaron.answer_proposal(
+                record_id='transmitter-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        record = rousseau.get_record('transmitter-1')
+        self.assertEqual(record['owner'], rousseau.public_key)
+        self.assertEqual(
+            record['proposals'][0]['approvals'], [aaron.public_key])
+
+        self.assert_invalid(
+            aaron.answer_proposal(
+                record_id='transmitter-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assert_valid(
+            jacob.answer_proposal(
+                record_id='transmitter-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT,
+                receiving_agent=aaron.public_key,
+            ))
+
+        self.assertEqual(
+            rousseau.get_record('transmitter-1')['owner'], aaron.public_key)
+
+    def test_delegate_reporter(self):
+        christian = SupplyChainClient()
+        self.assert_valid(christian.create_agent('Christian Shephard'))
+
+        cindy = SupplyChainClient()
+        self.assert_valid(cindy.create_agent('Cindy Chandler'))
+
+        zach = SupplyChainClient()
+        self.assert_valid(zach.create_agent('Zach'))
+
+        self.assert_valid(
+            christian.create_record_type(
+                'coffin',
+                ('flight', PropertySchema.STRING, {}),
+                ('temperature', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            christian.create_record(
+                'coffin-1', 'coffin', {
+                    'flight': 'Oceanic 815',
+                    'temperature': 4,
+                }))
+
+        self.narrate(
+            '''
+            Christian hires Cindy's airline to carry their coffin. Cindy
+            delegates reporting its flight to Zach, who loads it, but
+            cannot delegate its temperature, which they never reported on.
+            ''')
+
+        self.assert_invalid(
+            cindy.delegate_reporter(
+                record_id='coffin-1',
+                reporter_id=zach.public_key,
+                properties=['flight']))
+
+        self.assert_valid(
+            christian.create_proposal(
+                record_id='coffin-1',
+                receiving_agent=cindy.public_key,
+                role=Proposal.REPORTER,
+                properties=['flight'],
+            ))
+
+        self.assert_valid(
+            cindy.answer_proposal(
+                record_id='coffin-1',
+                role=Proposal.REPORTER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assert_invalid(
+            cindy.delegate_reporter(
+                record_id='coffin-1',
+                reporter_id=zach.public_key,
+                properties=['temperature']))
+
+        self.assert_valid(
+            cindy.delegate_reporter(
+                record_id='coffin-1',
+                reporter_id=zach.public_key,
+                properties=['flight']))
+
+        self.assert_valid(
+            zach.update_properties(
+                'coffin-1', {'flight': 'Ajira 316'}))
+
+        self.narrate(
+            '''
+            Christian revokes Cindy, and Zach loses the authorization
+            they were delegated along with them.
+            ''')
+
+        self.assert_valid(
+            christian.revoke_reporter(
+                record_id='coffin-1',
+                reporter_id=cindy.public_key,
+                properties=['flight']))
+
+        self.assert_invalid(
+            zach.update_properties(
+                'coffin-1', {'flight': 'Oceanic 316'}))
+
+    def test_merge_records(self):
+        roger = SupplyChainClient()
+        self.assert_valid(roger.create_agent('Roger Linus'))
+
+        self.assert_valid(
+            roger.create_record_type(
+                'batch',
+                ('code', PropertySchema.STRING, {})))
+
+        for batch_id in ('batch-4', 'batch-8', 'batch-15'):
+            self.assert_valid(
+                roger.create_record(batch_id, 'batch', {'code': batch_id}))
+
+        self.narrate(
+            '''
+            Roger pours three batches into one. The merged batch lists
+            the three as its parents, and each of them is final and
+            names the batch it went into.
+            ''')
+
+        self.assert_invalid(
+            roger.merge_records('batch-16', 'batch', ['batch-4']))
+
+        self.assert_valid(
+            roger.merge_records(
+                'batch-16', 'batch', ['batch-4', 'batch-8', 'batch-15'],
+                {'code': '4 8 15'}))
+
+        merged = roger.get_record('batch-16')
+        self.assertEqual(
+            merged['parents'], ['batch-4', 'batch-8', 'batch-15'])
+
+        consumed = roger.get_record('batch-4')
+        self.assertTrue(consumed['final'])
+        self.assertEqual(consumed['consumedBy'], 'batch-16')
+
+        self.assertEqual(
+            roger.get_record_verification('batch-8')['mergedInto'],
+            'batch-16')
+
+        self.assert_invalid(
+            roger.update_properties('batch-15', {'code': 'lost'}))
+
+    def test_archive_record(self):
+        leslie = SupplyChainClient()
+        self.assert_valid(leslie.create_agent('Leslie Arzt'))
+
+        self.assert_valid(
+            leslie.create_record_type(
+                'crate',
+                ('label', PropertySchema.STRING, {}),
+                ('handling', PropertySchema.STRING, {}),
+                retention_rules={'handling': 0}))
+
+        self.assert_valid(
+            leslie.create_record(
+                'crate-1', 'crate',
+                {'label': 'dynamite', 'handling': 'gently'}))
+
+        self.narrate(
+            '''
+            Leslie archives a crate once it is finalized. Its handling
+            notes are past their retention period, so only their digest
+            is kept.
+            ''')
+
+        self.assert_invalid(leslie.archive_record('crate-1', True))
+
+        self.assert_valid(leslie.finalize_record('crate-1'))
+        self.assert_valid(leslie.archive_record('crate-1', True))
+
+        archived = leslie.get_record('crate-1')
+        self.assertGreater(archived['archivedAt'], 0)
+        self.assertEqual(len(archived['archivedDigest']), 128)
+        self.assertEqual(
+            [pruned['name'] for pruned in archived['prunedProperties']],
+            ['handling'])
+
+        self.assert_invalid(leslie.archive_record('crate-1'))
+
+        self.narrate(
+            '''
+            Leslie archives a second crate without waiting for any
+            retention period, deleting the pages of all its Properties.
+            ''')
+
+        self.assert_valid(
+            leslie.create_record(
+                'crate-2', 'crate',
+                {'label': 'nitro', 'handling': 'carefully'}))
+        self.assert_valid(leslie.finalize_record('crate-2'))
+        self.assert_valid(
+            leslie.archive_record('crate-2', delete_pages=True))
+
+        archived = leslie.get_record('crate-2')
+        self.assertEqual(
+            sorted(pruned['name']
+                   for pruned in archived['prunedProperties']),
+            ['handling', 'label'])
+
+    def test_reopen_record(self):
+        bram = SupplyChainClient()
+        self.assert_valid(bram.create_agent('Bram'))
+
+        self.assert_valid(
+            bram.create_record_type(
+                'case',
+                ('contents', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            bram.create_record('case-1', 'case', {'contents': 'ash'}))
+
+        self.narrate(
+            '''
+            Bram finalizes a case before it is packed. Holding it as both
+            owner and custodian, Bram can reopen it alone, and the
+            reopening is kept on the case.
+            ''')
+
+        self.assert_invalid(bram.reopen_record('case-1'))
+
+        self.assert_valid(bram.finalize_record('case-1'))
+        self.assert_valid(bram.reopen_record('case-1'))
+
+        reopened = bram.get_record('case-1')
+        self.assertFalse(reopened['final'])
+        self.assertEqual(len(reopened['reopenings']), 1)
+        self.assertEqual(
+            reopened['reopenings'][0]['approvedBy'], bram.public_key)
+
+        self.assert_valid(
+            bram.update_properties('case-1', {'contents': 'ash, sealed'}))
+
+    def test_recall_record(self):
+        neil = SupplyChainClient()
+        self.assert_valid(neil.create_agent('Neil Frogurt'))
+
+        danny = SupplyChainClient()
+        self.assert_valid(danny.create_agent('Danny Pickett'))
+
+        self.assert_valid(
+            neil.create_record_type(
+                'yogurt',
+                ('flavor', PropertySchema.STRING, {})))
+
+        for batch_id in ('yogurt-1', 'yogurt-2'):
+            self.assert_valid(
+                neil.create_record(batch_id, 'yogurt', {'flavor': 'plain'}))
+
+        self.assert_valid(
+            neil.merge_records(
+                'yogurt-3', 'yogurt', ['yogurt-1', 'yogurt-2'],
+                {'flavor': 'plain'}))
+
+        self.narrate(
+            '''
+            Neil recalls a batch of yogurt that went bad, along with the
+            batch it was poured into. Neither can be sold on.
+            ''')
+
+        self.assert_invalid(neil.recall_record('yogurt-1', ''))
+
+        self.assert_valid(
+            neil.recall_record('yogurt-1', 'spoiled', True))
+
+        recalled = neil.get_record('yogurt-3')
+        self.assertEqual(recalled['recall']['reason'], 'spoiled')
+        self.assertEqual(recalled['recall']['origin'], 'yogurt-1')
+
+        self.assertIsNone(neil.get_record('yogurt-2')['recall'])
+
+        self.assertEqual(
+            neil.get_record_verification('yogurt-3')['recall']['reason'],
+            'spoiled')
+
+        self.assert_invalid(
+            neil.create_proposal(
+                record_id='yogurt-3',
+                receiving_agent=danny.public_key,
+                role=Proposal.OWNER))
+
+    def test_agent_roles(self):
+        amy = SupplyChainClient()
+        self.assert_valid(amy.create_agent('Amy'))
+
+        phil = SupplyChainClient()
+        self.assert_valid(phil.create_agent('Phil'))
+
+        self.narrate(
+            '''
+            Amy wants Phil to be the one who defines the Initiative's
+            record types, but only the keys in the
+            supply_chain.agent.admins setting, or Agents they have made
+            admins, may set roles, and this network lists none.
+            ''')
+
+        self.assert_invalid(
+            amy.set_agent_role(phil.public_key, AgentRole.ADMIN))
+
+        self.narrate(
+            '''
+            Since the network names no admins, roles are not enforced,
+            and Amy can still create record types themselves.
+            ''')
+
+        self.assert_valid(
+            amy.create_record_type(
+                'sonar-fence',
+                ('voltage', PropertySchema.NUMBER, {})))
+
+        self.assertEqual(phil.get_agent(phil.public_key)['role'], 'OPERATOR')
+
+    def test_record_metadata(self):
+        bea = SupplyChainClient()
+        self.assert_valid(bea.create_agent('Bea Klugh'))
+
+        greta = SupplyChainClient()
+        self.assert_valid(greta.create_agent('Greta'))
+
+        self.assert_valid(
+            bea.create_record_type(
+                'hatch-supply',
+                ('contents', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            bea.create_record(
+                'supply-108', 'hatch-supply', {'contents': 'batteries'}))
+
+        self.narrate(
+            '''
+            Bea notes the purchase order and waybill of a supply
+            drop on its record. Only the owner may set them, and setting
+            a key to an empty value removes it.
+            ''')
+
+        self.assert_invalid(
+            greta.set_record_metadata('supply-108', {'po': '4815'}))
+
+        self.assert_valid(
+            bea.set_record_metadata(
+                'supply-108', {'po': '4815', 'waybill': '162342'}))
+
+        self.assert_valid(
+            bea.set_record_metadata('supply-108', {'waybill': ''}))
+
+        self.assertEqual(
+            bea.get_record('supply-108')['metadata'],
+            [{'key': 'po', 'value': '4815'}])
+
+        self.assert_invalid(
+            bea.set_record_metadata('supply-108', {'po': 'x' * 257}))
+
+    def test_audit_attestations(self):
+        abaddon = SupplyChainClient()
+        self.assert_valid(abaddon.create_agent('Matthew Abaddon'))
+
+        self.assert_valid(
+            abaddon.create_record_type(
+                'freighter-cargo',
+                ('contents', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            abaddon.create_record(
+                'cargo-42', 'freighter-cargo', {'contents': 'c4'}))
+
+        digest = hashlib.sha512(b'cargo-42 history').hexdigest()
+        reviewed_through = int(time.time())
+
+        self.narrate(
+            '''
+            Abaddon tries to attest that they audited the cargo's history,
+            but the attestation must carry a well-formed digest and the
+            time the audit reviewed through.
+            ''')
+
+        self.assert_invalid(
+            abaddon.attest_audit(
+                'cargo-42', reviewed_through, 'not-a-hash', True))
+
+        self.assert_invalid(
+            abaddon.attest_audit('cargo-42', 0, digest, True))
+
+        self.narrate(
+            '''
+            Even a well-formed attestation is rejected, since only agents
+            given the AUDITOR role may attest audits, and this network
+            names no admins to give it.
+            ''')
+
+        self.assert_invalid(
+            abaddon.attest_audit('cargo-42', reviewed_through, digest, True))
+
+        self.assertEqual(abaddon.get_record('cargo-42')['audits'], [])
+
+    def test_cancel_all_proposals(self):
+        cooper = SupplyChainClient()
+        self.assert_valid(cooper.create_agent('Anthony Cooper'))
+
+        helen = SupplyChainClient()
+        self.assert_valid(helen.create_agent('Helen Norwood'))
+
+        nadia = SupplyChainClient()
+        self.assert_valid(nadia.create_agent('Nadia Jazeem'))
+
+        self.assert_valid(
+            cooper.create_record_type(
+                'con',
+                ('take', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            cooper.create_record('con-1', 'con', {'take': 300000}))
+
+        self.narrate(
+            '''
+            Cooper offers ownership and custody of the con to Helen, and
+            asks Nadia to report its take. When the deal falls through,
+            one transaction withdraws every offer.
+            ''')
+
+        self.assert_valid(
+            cooper.create_proposal(
+                record_id='con-1',
+                role=Proposal.OWNER,
+                receiving_agent=helen.public_key))
+
+        self.assert_valid(
+            cooper.create_proposal(
+                record_id='con-1',
+                role=Proposal.CUSTODIAN,
+                receiving_agent=helen.public_key))
+
+        self.assert_valid(
+            cooper.create_proposal(
+                record_id='con-1',
+                role=Proposal.REPORTER,
+                receiving_agent=nadia.public_key,
+                properties=['take']))
+
+        self.assertEqual(len(cooper.get_record('con-1')['proposals']), 3)
+
+        self.narrate(
+            '''
+            Helen cannot withdraw offers they did not make.
+            ''')
+
+        self.assert_invalid(helen.cancel_all_proposals('con-1'))
+
+        self.assert_valid(cooper.cancel_all_proposals('con-1'))
+
+        self.assertEqual(len(cooper.get_record('con-1')['proposals']), 0)
+
+        self.assert_invalid(
+            helen.answer_proposal(
+                record_id='con-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT))
+
+        self.assert_invalid(cooper.cancel_all_proposals('con-1'))
+
+    def test_amend_proposal(self):
+        keamy = SupplyChainClient()
+        self.assert_valid(keamy.create_agent('Martin Keamy'))
+
+        omar = SupplyChainClient()
+        self.assert_valid(omar.create_agent('Omar'))
+
+        self.assert_valid(
+            keamy.create_record_type(
+                'mercenary-gear',
+                ('ammo', PropertySchema.NUMBER, {}),
+                ('location', PropertySchema.LOCATION, {})))
+
+        self.assert_valid(
+            keamy.create_record(
+                'gear-1', 'mercenary-gear',
+                {'ammo': 500,
+                 'location': {'latitude': 0, 'longitude': 0}}))
+
+        self.narrate(
+            '''
+            Keamy asks Omar to report the gear's ammo, then decides Omar
+            should report its location too. Rather than canceling and
+            proposing again, Keamy amends the open proposal.
+            ''')
+
+        self.assert_valid(
+            keamy.create_proposal(
+                record_id='gear-1',
+                role=Proposal.REPORTER,
+                receiving_agent=omar.public_key,
+                properties=['ammo']))
+
+        self.assert_invalid(
+            omar.amend_proposal(
+                'gear-1', omar.public_key, Proposal.REPORTER,
+                properties=['ammo', 'location']))
+
+        self.assert_invalid(
+            keamy.amend_proposal(
+                'gear-1', omar.public_key, Proposal.REPORTER))
+
+        self.assert_valid(
+            keamy.amend_proposal(
+                'gear-1', omar.public_key, Proposal.REPORTER,
+                properties=['ammo', 'location'],
+                terms='Report hourly'))
+
+        proposal, = keamy.get_record('gear-1')['proposals']
+        self.assertEqual(proposal['properties'], ['ammo', 'location'])
+        self.assertEqual(proposal['terms'], 'Report hourly')
+        self.assertEqual(len(proposal['amendedAt']), 1)
+
+        self.assert_valid(
+            omar.answer_proposal(
+                record_id='gear-1',
+                role=Proposal.REPORTER,
+                response=AnswerProposalAction.ACCEPT))
+
+        self.assert_valid(
+            omar.update_properties(
+                'gear-1',
+                {'location': {'latitude': 1, 'longitude': 1}}))
+
+    def test_transfer_terms(self):
+        seth = SupplyChainClient()
+        self.assert_valid(seth.create_agent('Seth Norris'))
+
+        caesar = SupplyChainClient()
+        self.assert_valid(caesar.create_agent('Caesar'))
+
+        self.assert_valid(
+            seth.create_record_type(
+                'aircraft-part',
+                ('serial', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            seth.create_record(
+                'rotor-1', 'aircraft-part', {'serial': 'AJIRA-316'}))
+
+        deadline = round(time.time()) + 86400
+
+        self.narrate(
+            '''
+            Seth sells a rotor to Caesar for $12,500, free on board, to
+            be delivered within a day. Terms must name their currency and
+            a real Incoterms rule, and reporter proposals cannot have any.
+            ''')
+
+        self.assert_invalid(
+            seth.create_proposal(
+                record_id='rotor-1',
+                role=Proposal.OWNER,
+                receiving_agent=caesar.public_key,
+                transfer_terms={'price': 1250000}))
+
+        self.assert_invalid(
+            seth.create_proposal(
+                record_id='rotor-1',
+                role=Proposal.OWNER,
+                receiving_agent=caesar.public_key,
+                transfer_terms={'incoterm': 'SHIP'}))
+
+        self.assert_invalid(
+            seth.create_proposal(
+                record_id='rotor-1',
+                role=Proposal.REPORTER,
+                receiving_agent=caesar.public_key,
+                properties=['serial'],
+                transfer_terms={'incoterm': 'FOB'}))
+
+        terms = {
+            'price': 1250000,
+            'currency': 'USD',
+            'incoterm': 'FOB',
+            'deliveryDeadline': deadline,
+        }
+
+        self.assert_valid(
+            seth.create_proposal(
+                record_id='rotor-1',
+                role=Proposal.OWNER,
+                receiving_agent=caesar.public_key,
+                transfer_terms={
+                    'price': terms['price'],
+                    'currency': terms['currency'],
+                    'incoterm': terms['incoterm'],
+                    'delivery_deadline': deadline,
+                }))
+
+        proposal, = caesar.get_record('rotor-1')['proposals']
+        self.assertEqual(proposal['transferTerms'], terms)
+
+        self.narrate(
+            '''
+            Once Caesar accepts, the terms are kept on the rotor's
+            timeline with the change of ownership.
+            ''')
+
+        self.assert_valid(
+            caesar.answer_proposal(
+                record_id='rotor-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT))
+
+        timeline = caesar.get_record_timeline('rotor-1')
+
+        self.assertEqual(timeline['entries'][-1]['agentId'],
+                         caesar.public_key)
+        self.assertEqual(timeline['entries'][-1]['terms'], terms)
+
+    def test_escrowed_transfer(self):
+        jill = SupplyChainClient()
+        self.assert_valid(jill.create_agent('Jill'))
+
+        pryce = SupplyChainClient()
+        self.assert_valid(pryce.create_agent('Ryan Pryce'))
+
+        self.assert_valid(
+            jill.create_record_type(
+                'barracks-crate',
+                ('inspected', PropertySchema.BOOLEAN, {})))
+
+        self.assert_valid(
+            jill.create_record(
+                'crate-1', 'barracks-crate', {'inspected': False}))
+
+        self.narrate(
+            '''
+            Jill sells a crate to Pryce, but only once it has been
+            inspected. Accepting the escrowed proposal holds the crate in
+            escrow, so Jill can neither offer it to anyone else nor hand
+            it over directly.
+            ''')
+
+        self.assert_invalid(
+            jill.create_proposal(
+                record_id='crate-1',
+                role=Proposal.CUSTODIAN,
+                receiving_agent=pryce.public_key,
+                escrow=True))
+
+        self.assert_valid(
+            jill.create_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                receiving_agent=pryce.public_key,
+                escrow=True,
+                release_property='inspected'))
+
+        self.assert_valid(
+            pryce.answer_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT))
+
+        crate = jill.get_record('crate-1')
+        self.assertEqual(crate['owner'], jill.public_key)
+        self.assertEqual(crate['escrow']['receivingAgent'], pryce.public_key)
+
+        self.assert_invalid(pryce.release_escrow('crate-1'))
+
+        self.narrate(
+            '''
+            Reporting the inspection releases the escrow, and the crate
+            passes to Pryce.
+            ''')
+
+        self.assert_valid(
+            jill.update_properties('crate-1', {'inspected': True}))
+
+        crate = pryce.get_record('crate-1')
+        self.assertEqual(crate['owner'], pryce.public_key)
+        self.assertIsNone(crate['escrow'])
+
+        self.narrate(
+            '''
+            Pryce offers the crate back to Jill in escrow, then changes
+            their mind and reverts the transfer.
+            ''')
+
+        self.assert_valid(
+            pryce.create_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                receiving_agent=jill.public_key,
+                escrow=True))
+
+        self.assert_valid(
+            jill.answer_proposal(
+                record_id='crate-1',
+                role=Proposal.OWNER,
+                response=AnswerProposalAction.ACCEPT))
+
+        self.assert_valid(pryce.release_escrow('crate-1', revert=True))
+
+        self.assertEqual(
+            pryce.get_record('crate-1')['owner'], pryce.public_key)
+
+    def test_deactivate_agent(self):
+        zoe = SupplyChainClient()
+        self.assert_valid(zoe.create_agent('Zoe'))
+
+        bonnie = SupplyChainClient()
+        self.assert_valid(bonnie.create_agent('Bonnie'))
+
+        self.assert_valid(
+            zoe.create_record_type(
+                'jamming-beacon',
+                ('frequency', PropertySchema.NUMBER, {})))
+
+        self.assert_valid(
+            zoe.create_record(
+                'beacon-1', 'jamming-beacon', {'frequency': 50}))
+
+        self.narrate(
+            '''
+            Bonnie wants Zoe's key retired, but only Zoe or an admin may
+            deactivate it, and this network lists no admins.
+            ''')
+
+        self.assert_invalid(bonnie.deactivate_agent(zoe.public_key))
+
+        self.narrate(
+            '''
+            Zoe suspects their key has been copied and deactivates it.
+            Nothing signed with it is accepted afterwards, not even
+            registering it again.
+            ''')
+
+        self.assert_valid(zoe.deactivate_agent(zoe.public_key))
+
+        self.assert_invalid(
+            zoe.update_properties('beacon-1', {'frequency': 60}))
+
+        self.assert_invalid(zoe.create_agent('Zoe'))
+
+        self.narrate(
+            '''
+            The beacon still shows Zoe as its owner.
+            ''')
+
+        self.assertEqual(
+            bonnie.get_record('beacon-1')['owner'], zoe.public_key)
+
+        self.assertNotEqual(
+            zoe.get_agent(zoe.public_key)['deactivatedAt'], 0)
+
+    def test_record_templates(self):
+        magnus = SupplyChainClient()
+        self.assert_valid(magnus.create_agent('Magnus Hanso'))
+
+        diane = SupplyChainClient()
+        self.assert_valid(diane.create_agent('Diane Janssen'))
+
+        self.assert_valid(
+            magnus.create_record_type(
+                'cargo-chest',
+                ('vessel', PropertySchema.STRING, {'required': True}),
+                ('port', PropertySchema.STRING, {}),
+                ('weight', PropertySchema.NUMBER, {})))
+
+        self.narrate(
+            '''
+            Every chest Magnus ships goes aboard the Black Rock from
+            Portsmouth, so they define a template with those values
+            rather than sending them with every chest.
+            ''')
+
+        self.assert_invalid(
+            magnus.create_record_template(
+                'black-rock', 'cargo-chest', {'hold': 'aft'}))
+
+        self.assert_valid(
+            magnus.create_record_template(
+                'black-rock', 'cargo-chest',
+                {'vessel': 'Black Rock', 'port': 'Portsmouth'}))
+
+        self.assert_invalid(
+            diane.create_record_template(
+                'black-rock', 'cargo-chest', {'vessel': 'Elizabeth'}))
+
+        template = diane.get_record_template('black-rock')
+
+        self.assertEqual(template['recordType'], 'cargo-chest')
+        self.assertEqual(template['creator'], magnus.public_key)
+
+        self.narrate(
+            '''
+            Diane creates a chest from Magnus's template, giving only its
+            weight and a different port.
+            ''')
+
+        self.assert_valid(
+            diane.create_record_from_template(
+                'chest-1', 'black-rock',
+                {'port': 'Lagos', 'weight': 40}))
+
+        self.assert_invalid(
+            diane.create_record_from_template('chest-2', 'narcissus'))
+
+        record = diane.get_record('chest-1')
+
+        self.assertEqual(record['owner'], diane.public_key)
+
+        properties = {prop['name']: prop for prop in record['properties']}
+        self.assertEqual(properties['vessel']['value'], 'Black Rock')
+        self.assertEqual(properties['port']['value'], 'Lagos')
+        self.assertEqual(properties['weight']['value'], 40)
+
+    def test_timestamp_values(self):
+        edgar = SupplyChainClient()
+        self.assert_valid(edgar.create_agent('Edgar Halliwax'))
+
+        self.assert_valid(
+            edgar.create_record_type(
+                'sample-jar',
+                ('collected', PropertySchema.TIMESTAMP, {'required': True}),
+                ('expires', PropertySchema.TIMESTAMP, {})))
+
+        self.narrate(
+            '''
+            Edgar labels each jar with when it was collected and when it
+            expires. A jar left unlabelled, or dated a thousand years from
+            now, is turned away.
+            ''')
+
+        collected = int(time.time()) - 86400
+        expires = collected + 30 * 86400
+
+        self.assert_invalid(
+            edgar.create_record(
+                'jar-0', 'sample-jar', {'collected': Timestamp(0)}))
+
+        self.assert_invalid(
+            edgar.create_record(
+                'jar-0', 'sample-jar',
+                {'collected': Timestamp(collected + 1000 * 365 * 86400)}))
+
+        self.assert_invalid(
+            edgar.create_record(
+                'jar-0', 'sample-jar', {'collected': collected}))
+
+        self.assert_valid(
+            edgar.create_record(
+                'jar-1', 'sample-jar',
+                {'collected': Timestamp(collected),
+                 'expires': Timestamp(expires)}))
+
+        self.assert_valid(
+            edgar.update_properties(
+                'jar-1', {'expires': Timestamp((expires + 86400) * 1000)}))
+
+        jar = edgar.get_record_property('jar-1', 'collected')
+
+        log_json(jar)
+
+        self.assertEqual(jar['dataType'], 'TIMESTAMP')
+        self.assertEqual(jar['value']['value'], collected)
+
+    def test_record_references(self):
+        regina = SupplyChainClient()
+        self.assert_valid(regina.create_agent('Regina'))
+
+        self.assert_valid(
+            regina.create_record_type(
+                'haul',
+                ('species', PropertySchema.STRING, {'required': True})))
+
+        self.assert_valid(
+            regina.create_record_type(
+                'fillet',
+                ('processed_from', PropertySchema.RECORD_REFERENCE,
+                 {'required': True})))
+
+        self.assert_valid(
+            regina.create_record('haul-1', 'haul', {'species': 'tuna'}))
+
+        self.narrate(
+            '''
+            Regina fillets the tuna from haul-1 aboard the freighter. Each
+            fillet records the haul it was processed from, which must be
+            a Record that exists.
+            ''')
+
+        self.assert_invalid(
+            regina.create_record(
+                'fillet-1', 'fillet',
+                {'processed_from': RecordReference('haul-9')}))
+
+        self.assert_valid(
+            regina.create_record(
+                'fillet-2', 'fillet',
+                {'processed_from': RecordReference('haul-1')}))
+
+        self.assert_invalid(
+            regina.update_properties(
+                'fillet-2', {'processed_from': RecordReference('fillet-2')}))
+
+        fillet = regina.get_record_property('fillet-2', 'processed_from')
+
+        log_json(fillet)
+
+        self.assertEqual(fillet['dataType'], 'RECORD_REFERENCE')
+        self.assertEqual(fillet['value']['value'], 'haul-1')
+
+    def test_linked_record_rules(self):
+        regina = SupplyChainClient()
+        self.assert_valid(regina.create_agent('Regina'))
+
+        self.narrate(
+            '''
+            Regina's lots record the lot they were split from, and a
+            lot must come from the same country as its parent.
+            ''')
+
+        self.assert_valid(
+            regina.create_record_type(
+                'lot',
+                ('origin_country', PropertySchema.STRING, {}),
+                ('parent', PropertySchema.RECORD_REFERENCE, {}),
+                rules=[
+                    'action == "transfer" || parent == ""'
+                    ' || property != "origin_country"'
+                    ' || value == parent.origin_country',
+                ]))
+
+        self.assert_valid(
+            regina.create_record('lot-1', 'lot', {'origin_country': 'PE'}))
+
+        self.assert_invalid(
+            regina.create_record(
+                'lot-2', 'lot',
+                {'origin_country': 'CL',
+                 'parent': RecordReference('lot-1')}))
+
+        self.assert_valid(
+            regina.create_record(
+                'lot-2', 'lot',
+                {'origin_country': 'PE',
+                 'parent': RecordReference('lot-1')}))
+
+        self.assert_invalid(
+            regina.update_properties('lot-2', {'origin_country': 'CL'}))
+
+    def test_array_values(self):
+        candle = SupplyChainClient()
+        self.assert_valid(candle.create_agent('Marvin Candle'))
+
+        self.assert_valid(
+            candle.create_record_type(
+                'station-log',
+                ('readings', PropertySchema.ARRAY, {
+                    'element_schema': (PropertySchema.NUMBER, {}),
+                    'max_length': 3,
+                }),
+                ('certifications', PropertySchema.ARRAY, {
+                    'element_schema': (PropertySchema.STRUCT, {
+                        'struct_properties': [
+                            ('issuer', PropertySchema.STRING, {}),
+                            ('level', PropertySchema.NUMBER, {}),
+                        ],
+                    }),
+                })))
+
+        self.narrate(
+            '''
+            Dr. Candle logs a batch of readings from the station at once,
+            along with the certifications the station holds. A batch may
+            hold at most three readings.
+            ''')
+
+        self.assert_invalid(
+            candle.create_record(
+                'log-0', 'station-log', {'readings': [4, 8, 15, 16]}))
+
+        self.assert_invalid(
+            candle.create_record(
+                'log-0', 'station-log', {'readings': [4, 'eight']}))
+
+        self.assert_valid(
+            candle.create_record(
+                'log-1', 'station-log', {
+                    'readings': [4, 8, 15],
+                    'certifications': [
+                        {'issuer': 'Dharma', 'level': 2},
+                        {'issuer': 'Hanso', 'level': 1},
+                    ],
+                }))
+
+        self.assert_invalid(
+            candle.update_properties(
+                'log-1', {'certifications': [{'issuer': 'Widmore'}]}))
+
+        self.assert_valid(
+            candle.update_properties('log-1', {'readings': [16, 23, 42]}))
+
+        readings = candle.get_record_property('log-1', 'readings')
+
+        log_json(readings)
+
+        self.assertEqual(readings['dataType'], 'ARRAY')
+        self.assertEqual(readings['value']['value'], [16, 23, 42])
+
+        certifications = candle.get_record_property(
+            'log-1', 'certifications')
+
+        self.assertEqual(
+            certifications['value']['value'],
+            [{'issuer': 'Dharma', 'level': 2},
+             {'issuer': 'Hanso', 'level': 1}])
+
+    def test_number_bounds(self):
+        aldo = SupplyChainClient()
+        self.assert_valid(aldo.create_agent('Aldo'))
+
+        self.assert_invalid(
+            aldo.create_record_type(
+                'cage',
+                ('voltage', PropertySchema.NUMBER,
+                 {'number_exponent': 19})))
+
+        self.assert_invalid(
+            aldo.create_record_type(
+                'cage',
+                ('voltage', PropertySchema.NUMBER,
+                 {'bounds': {'min': 10, 'max': 0}})))
+
+        self.assert_valid(
+            aldo.create_record_type(
+                'cage',
+                ('voltage', PropertySchema.NUMBER,
+                 {'number_exponent': 3, 'bounds': {'min': 0, 'max': 5000}}),
+                ('charge', PropertySchema.NUMBER,
+                 {'number_exponent': 18})))
+
+        self.narrate(
+            '''
+            Aldo guards the cages, whose fences never carry more than
+            5,000 kilovolts. A reading above that, or one too large to
+            hold once its exponent is applied, is a mistake.
+            ''')
+
+        self.assert_invalid(
+            aldo.create_record(
+                'cage-1', 'cage', {'voltage': 5001}))
+
+        self.assert_invalid(
+            aldo.create_record(
+                'cage-1', 'cage', {'voltage': 100, 'charge': 10}))
+
+        self.assert_valid(
+            aldo.create_record(
+                'cage-1', 'cage', {'voltage': 5000, 'charge': 9}))
+
+        self.assert_invalid(
+            aldo.update_properties('cage-1', {'voltage': -1}))
+
+        self.assert_valid(
+            aldo.update_properties('cage-1', {'voltage': 0}))
+
+    def test_identifier_values(self):
+        porter = SupplyChainClient()
+        self.assert_valid(porter.create_agent('Brian Porter'))
+
+        self.assert_valid(
+            porter.create_record_type(
+                'ration-pallet',
+                ('trace_code', PropertySchema.IDENTIFIER,
+                 {'identifier_format': PropertySchema.UUID_V4}),
+                ('gtin', PropertySchema.IDENTIFIER,
+                 {'identifier_format': PropertySchema.GS1}),
+                ('lot', PropertySchema.IDENTIFIER, {})))
+
+        self.narrate(
+            '''
+            Brian tags each pallet of rations dropped on the island with a
+            trace code and the GTIN of its contents. A mistyped GTIN has
+            the wrong check digit, and is caught before it is stored.
+            ''')
+
+        self.assert_invalid(
+            porter.create_record(
+                'pallet-1', 'ration-pallet',
+                {'gtin': Identifier('4006381333932')}))
+
+        self.assert_invalid(
+            porter.create_record(
+                'pallet-1', 'ration-pallet',
+                {'trace_code': Identifier('not-a-uuid')}))
+
+        self.assert_invalid(
+            porter.create_record(
+                'pallet-1', 'ration-pallet',
+                {'lot': Identifier('lot 4')}))
+
+        self.assert_valid(
+            porter.create_record(
+                'pallet-1', 'ration-pallet', {
+                    'trace_code': Identifier(
+                        '1b4e28ba-2fa1-41d2-883f-0016d3cca427'),
+                    'gtin': Identifier('4006381333931'),
+                    'lot': Identifier('DHARMA-4815'),
+                }))
+
+        gtin = porter.get_record_property('pallet-1', 'gtin')
+
+        log_json(gtin)
+
+        self.assertEqual(gtin['dataType'], 'IDENTIFIER')
+        self.assertEqual(gtin['value']['value'], '4006381333931')
+
+    def test_string_constraints(self):
+        jerome = SupplyChainClient()
+        self.assert_valid(jerome.create_agent('Jerome'))
+
+        self.assert_invalid(
+            jerome.create_record_type(
+                'seed-bag',
+                ('lot', PropertySchema.STRING,
+                 {'string_pattern': '(DHARMA|HANSO)-\\d+'})))
+
+        self.assert_valid(
+            jerome.create_record_type(
+                'seed-bag',
+                ('lot', PropertySchema.STRING,
+                 {'string_pattern': '[A-Z]{3}-\\d+', 'max_length': 8}),
+                ('note', PropertySchema.STRING, {'max_length': 20})))
+
+        self.narrate(
+            '''
+            Jerome bags seed for the garden, each bag with a lot code of
+            three letters and a number. An empty or lowercase lot code is
+            turned away, as is a note too long for the label.
+            ''')
+
+        self.assert_invalid(
+            jerome.create_record('bag-1', 'seed-bag', {'lot': ''}))
+
+        self.assert_invalid(
+            jerome.create_record('bag-1', 'seed-bag', {'lot': 'mcx-4'}))
+
+        self.assert_invalid(
+            jerome.create_record(
+                'bag-1', 'seed-bag', {'lot': 'MCX-40000'}))
+
+        self.assert_invalid(
+            jerome.create_record(
+                'bag-1', 'seed-bag',
+                {'lot': 'MCX-4', 'note': 'Plant before the rains come'}))
+
+        self.assert_valid(
+            jerome.create_record(
+                'bag-1', 'seed-bag', {'lot': 'MCX-4', 'note': 'Tomatoes'}))
+
+        self.assert_invalid(
+            jerome.update_properties('bag-1', {'lot': 'MCX4'}))
+
+        self.assert_valid(
+            jerome.update_properties('bag-1', {'lot': 'MCX-8'}))
+
+    def test_unit_conversion(self):
+        emily = SupplyChainClient()
+        self.assert_valid(emily.create_agent('Emily Linus'))
+
+        self.assert_valid(
+            emily.create_record_type(
+                'supply-drop',
+                ('weight', PropertySchema.NUMBER,
+                 {'unit': 'kg', 'number_exponent': -3}),
+                ('temperature', PropertySchema.NUMBER, {'unit': 'C'}),
+                ('crates', PropertySchema.NUMBER, {})))
+
+        self.narrate(
+            '''
+            Emily Linus logs the supply drops, whose scales read in pounds
+            and whose thermometers read in Fahrenheit. Readings are
+            converted to kilograms and Celsius before they are stored, and
+            a unit that cannot be converted is turned away.
+            ''')
+
+        self.assert_invalid(
+            emily.create_record(
+                'drop-1', 'supply-drop', {'weight': Measurement(1000, 'C')}))
+
+        self.assert_invalid(
+            emily.create_record(
+                'drop-1', 'supply-drop', {'crates': Measurement(4, 'kg')}))
+
+        self.assert_invalid(
+            emily.create_record(
+                'drop-1', 'supply-drop',
+                {'temperature': Measurement(70, 'furlongs')}))
+
+        self.assert_valid(
+            emily.create_record(
+                'drop-1', 'supply-drop', {'weight': Measurement(1000, 'lb')}))
+
+        self.assert_valid(
+            emily.update_properties(
+                'drop-1', {'temperature': Measurement(212, 'F')}))
+
+        weight = emily.get_record_property('drop-1', 'weight')
+        temperature = emily.get_record_property('drop-1', 'temperature')
+
+        log_json(weight)
+        log_json(temperature)
+
+        self.assertEqual(weight['value']['value'], 454)
+        self.assertEqual(temperature['value']['value'], 100)
+
+    def test_location_fences(self):
+        colleen = SupplyChainClient()
+        self.assert_valid(colleen.create_agent('Colleen'))
+
+        self.assert_invalid(
+            colleen.create_record_type(
+                'supply-boat',
+                ('position', PropertySchema.LOCATION,
+                 {'fence': [(0, 0), (0, 1000000)]})))
+
+        self.assert_valid(
+            colleen.create_record_type(
+                'supply-boat',
+                ('position', PropertySchema.LOCATION, {
+                    'fence': [
+                        (-4000000, 90000000),
+                        (-4000000, 92000000),
+                        (-2000000, 92000000),
+                        (-2000000, 90000000),
+                    ]}),
+                ('last_port', PropertySchema.LOCATION, {})))
+
+        self.narrate(
+            '''
+            Colleen sails the supply boat between the islands, and must
+            stay within the corridor around them. A position outside it,
+            or one which is not on the globe at all, is turned away.
+            ''')
+
+        self.assert_invalid(
+            colleen.create_record(
+                'boat-1', 'supply-boat',
+                {'position': Location(latitude=-5000000, longitude=91000000)}))
+
+        self.assert_invalid(
+            colleen.create_record(
+                'boat-1', 'supply-boat',
+                {'last_port': Location(latitude=91000000, longitude=0)}))
+
+        self.assert_valid(
+            colleen.create_record(
+                'boat-1', 'supply-boat',
+                {'position': Location(latitude=-3000000, longitude=91000000)}))
+
+        self.assert_valid(
+            colleen.update_properties(
+                'boat-1',
+                {'position': Location(latitude=-2000000, longitude=92000000)}))
+
+        self.assert_invalid(
+            colleen.update_properties(
+                'boat-1',
+                {'position': Location(latitude=-3000000, longitude=93000000)}))
+
+    def test_allowed_reporters(self):
+        amelia = SupplyChainClient()
+        isabel = SupplyChainClient()
+        self.assert_valid(amelia.create_agent('Amelia'))
+        self.assert_valid(isabel.create_agent('Isabel'))
+
+        self.assert_invalid(
+            amelia.create_record_type(
+                'water-sample',
+                ('lab_result', PropertySchema.STRING, {
+                    'allowed_reporters': [isabel.public_key, ''],
+                })))
+
+        self.assert_valid(
+            amelia.create_record_type(
+                'water-sample',
+                ('lab_result', PropertySchema.STRING, {
+                    'allowed_reporters': [isabel.public_key],
+                }),
+                ('source', PropertySchema.STRING, {})))
+
+        self.assert_valid(
+            amelia.create_record('sample-1', 'water-sample', {}))
+
+        self.narrate(
+            '''
+            Amelia draws water samples at the Barracks, but only Isabel's
+            certified lab may report their results. Amelia owns the
+            samples and reports where they came from, but cannot report
+            a result themselves.
+            ''')
+
+        self.assert_invalid(
+            amelia.update_properties(
+                'sample-1', {'lab_result': 'clean'}))
+
+        self.assert_valid(
+            amelia.update_properties(
+                'sample-1', {'source': 'the Barracks'}))
+
+        self.narrate(
+            '''
+            Isabel's lab must still be made a reporter for the sample by
+            its owner.
+            ''')
+
+        self.assert_invalid(
+            isabel.update_properties(
+                'sample-1', {'lab_result': 'clean'}))
+
+        self.assert_valid(
+            amelia.create_proposal(
+                record_id='sample-1',
+                receiving_agent=isabel.public_key,
+                role=Proposal.REPORTER,
+                properties=['lab_result'],
+            ))
+
+        self.assert_valid(
+            isabel.answer_proposal(
+                record_id='sample-1',
+                role=Proposal.REPORTER,
+                response=AnswerProposalAction.ACCEPT,
+            ))
+
+        self.assert_valid(
+            isabel.update_properties(
+                'sample-1', {'lab_result': 'clean'}))
+
     def assert_record_attributes(self, record):
         for attr in ('custodian',
                      'owner',
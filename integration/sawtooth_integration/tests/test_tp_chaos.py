@@ -0,0 +1,331 @@
+# Copyright 2018 Intel Corporation
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+# ------------------------------------------------------------------------------
+
+"""Fault-injection tests for the transaction processor.
+
+These run the processor against a mock validator instead of a network, so
+they can do what a real validator would only do under failure: drop the
+connection partway through a transaction, send the same transaction more
+than once, and interleave transactions from competing forks.
+"""
+
+import itertools
+import logging
+import os
+import time
+import unittest
+
+from sawtooth_processor_test.mock_validator import MockValidator
+from sawtooth_sdk.protobuf.processor_pb2 import TpProcessRequest
+from sawtooth_sdk.protobuf.processor_pb2 import TpProcessResponse
+from sawtooth_sdk.protobuf.state_context_pb2 import TpEventAddResponse
+from sawtooth_sdk.protobuf.state_context_pb2 import TpReceiptAddDataResponse
+from sawtooth_sdk.protobuf.state_context_pb2 import TpStateDeleteRequest
+from sawtooth_sdk.protobuf.state_context_pb2 import TpStateDeleteResponse
+from sawtooth_sdk.protobuf.state_context_pb2 import TpStateEntry
+from sawtooth_sdk.protobuf.state_context_pb2 import TpStateGetRequest
+from sawtooth_sdk.protobuf.state_context_pb2 import TpStateGetResponse
+from sawtooth_sdk.protobuf.state_context_pb2 import TpStateSetRequest
+from sawtooth_sdk.protobuf.state_context_pb2 import TpStateSetResponse
+from sawtooth_sdk.protobuf.transaction_pb2 import TransactionHeader
+from sawtooth_sdk.protobuf.validator_pb2 import Message
+from sawtooth_signing import create_context
+from sawtooth_signing import CryptoFactory
+
+from sawtooth_sc_test.supply_chain_message_factory import \
+    SupplyChainMessageFactory
+import sawtooth_sc_test.addressing as addressing
+from sawtooth_sc_test.protobuf.property_pb2 import PropertySchema
+
+
+LOGGER = logging.getLogger(__name__)
+LOGGER.setLevel(logging.DEBUG)
+
+BIND = os.getenv('TEST_BIND', 'tcp://eth0:4004')
+
+# Time for a closed socket to release its port before rebinding it
+RESTART_DELAY = 2
+
+OK = TpProcessResponse.OK
+INVALID = TpProcessResponse.INVALID_TRANSACTION
+
+CONTEXT_IDS = ('context-{}'.format(i) for i in itertools.count())
+
+
+class Context:
+    """One validator context: a view of state that a transaction is applied
+    to, and the changes the transaction asked to make to it"""
+
+    def __init__(self, state):
+        self.context_id = next(CONTEXT_IDS)
+        self.state = dict(state)
+        self.writes = {}
+        self.deletes = []
+
+    def read(self, address):
+        if address in self.writes:
+            return self.writes[address]
+        if address in self.deletes:
+            return None
+        return self.state.get(address)
+
+    def committed(self):
+        """The state this context would leave behind if committed"""
+        state = dict(self.state)
+        for address in self.deletes:
+            state.pop(address, None)
+        state.update(self.writes)
+        return state
+
+
+def make_agent():
+    context = create_context('secp256k1')
+    signer = CryptoFactory(context).new_signer(
+        context.new_random_private_key())
+    return SupplyChainMessageFactory(signer=signer)
+
+
+def make_request(transaction, context):
+    return TpProcessRequest(
+        header=TransactionHeader.FromString(transaction.header),
+        payload=transaction.payload,
+        signature=transaction.header_signature,
+        context_id=context.context_id)
+
+
+class TestProcessorFaults(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.validator = cls.start_validator()
+
+    @classmethod
+    def tearDownClass(cls):
+        cls.validator.close()
+
+    @staticmethod
+    def start_validator():
+        validator = MockValidator()
+        validator.listen(BIND)
+        if not validator.register_processor():
+            raise Exception('Failed to register processor')
+        return validator
+
+    def process(self, *pairs):
+        """Sends each (transaction, context) pair to the processor, then
+        answers its requests from the matching context until every
+        transaction has a response. Requests from different contexts may
+        arrive in any order. Returns the response statuses, in order."""
+        contexts = {}
+        for transaction, context in pairs:
+            contexts[context.context_id] = context
+            self.validator.send(
+                make_request(transaction, context),
+                correlation_id=context.context_id)
+
+        statuses = {}
+        while len(statuses) < len(pairs):
+            message = self.validator.receive()
+
+            if message.message_type == Message.TP_PROCESS_RESPONSE:
+                self.assertIn(message.correlation_id, contexts)
+                self.assertNotIn(
+                    message.correlation_id, statuses,
+                    'Processor answered the same request twice')
+                statuses[message.correlation_id] = \
+                    TpProcessResponse.FromString(message.content).status
+            else:
+                self.answer(message, contexts)
+
+        return [statuses[context.context_id] for _, context in pairs]
+
+    def answer(self, message, contexts):
+        message_type = message.message_type
+
+        if message_type == Message.TP_STATE_GET_REQUEST:
+            request = TpStateGetRequest.FromString(message.content)
+            context = contexts[request.context_id]
+            entries = [
+                TpStateEntry(address=address, data=context.read(address))
+                for address in request.addresses
+                if context.read(address) is not None
+            ]
+            self.validator.respond(
+                TpStateGetResponse(
+                    entries=entries, status=TpStateGetResponse.OK),
+                message)
+
+        elif message_type == Message.TP_STATE_SET_REQUEST:
+            request = TpStateSetRequest.FromString(message.content)
+            context = contexts[request.context_id]
+            for entry in request.entries:
+                context.writes[entry.address] = entry.data
+            self.validator.respond(
+                TpStateSetResponse(
+                    addresses=[entry.address for entry in request.entries],
+                    status=TpStateSetResponse.OK),
+                message)
+
+        elif message_type == Message.TP_STATE_DELETE_REQUEST:
+            request = TpStateDeleteRequest.FromString(message.content)
+            context = contexts[request.context_id]
+            for address in request.addresses:
+                context.writes.pop(address, None)
+                context.deletes.append(address)
+            self.validator.respond(
+                TpStateDeleteResponse(
+                    addresses=request.addresses,
+                    status=TpStateDeleteResponse.OK),
+                message)
+
+        elif message_type == Message.TP_EVENT_ADD_REQUEST:
+            self.validator.respond(
+                TpEventAddResponse(status=TpEventAddResponse.OK),
+                message)
+
+        elif message_type == Message.TP_RECEIPT_ADD_DATA_REQUEST:
+            self.validator.respond(
+                TpReceiptAddDataResponse(status=TpReceiptAddDataResponse.OK),
+                message)
+
+        else:
+            self.fail('Unexpected message from processor: {}'.format(
+                message_type))
+
+    def assert_healthy(self):
+        """The processor is still connected and applying transactions"""
+        agent = make_agent()
+        context = Context({})
+        self.assertEqual(
+            self.process((agent.create_agent('Healthcheck'), context)),
+            [OK])
+
+    def test_duplicate_requests(self):
+        """A transaction delivered more than once is applied to each
+        context independently, and never on top of itself"""
+        jin = make_agent()
+        create = jin.create_agent('Jin-Soo Kwon')
+
+        first = Context({})
+        self.assertEqual(self.process((create, first)), [OK])
+        self.assertEqual(
+            sorted(first.writes),
+            [addressing.make_agent_activity_address(jin.public_key, 1),
+             addressing.make_agent_address(jin.public_key)])
+
+        # Redelivered to the same state, e.g. after a validator retry, the
+        # transaction applies again with exactly the same result
+        second = Context({})
+        self.assertEqual(self.process((create, second)), [OK])
+        self.assertEqual(second.writes, first.writes)
+
+        # Delivered twice at once, both copies see only their own context
+        third, fourth = Context({}), Context({})
+        self.assertEqual(
+            self.process((create, third), (create, fourth)),
+            [OK, OK])
+        self.assertEqual(third.writes, first.writes)
+        self.assertEqual(fourth.writes, first.writes)
+
+        # Redelivered on top of its own result, it must not apply twice
+        replay = Context(first.committed())
+        self.assertEqual(self.process((create, replay)), [INVALID])
+        self.assertEqual(replay.writes, {})
+
+        self.assert_healthy()
+
+    def test_interleaved_forks(self):
+        """Transactions for competing forks, interleaved, each see only
+        their own fork's state"""
+        sun = make_agent()
+
+        genesis = Context({})
+        self.assertEqual(
+            self.process((sun.create_agent('Sun-Hwa Kwon'), genesis)),
+            [OK])
+        base = genesis.committed()
+
+        fork_a = Context(base)
+        self.assertEqual(
+            self.process((
+                sun.create_record_type(
+                    'orchid', ('color', PropertySchema.STRING, {})),
+                fork_a)),
+            [OK])
+
+        # Fork B never created the record type, so the same record can
+        # only be created on fork A
+        create = sun.create_record('orchid-1', 'orchid', {})
+        on_a = Context(fork_a.committed())
+        on_b = Context(base)
+        self.assertEqual(
+            self.process((create, on_a), (create, on_b)),
+            [OK, INVALID])
+
+        record_address = addressing.make_record_address('orchid-1')
+        self.assertIn(record_address, on_a.writes)
+        self.assertEqual(on_b.writes, {})
+
+        # Alternate many transactions between the forks
+        contexts = []
+        for i in range(10):
+            record_id = 'orchid-{}'.format(i + 2)
+            state = fork_a.committed() if i % 2 == 0 else base
+            contexts.append(
+                (sun.create_record(record_id, 'orchid', {}), Context(state)))
+
+        self.assertEqual(
+            self.process(*contexts),
+            [OK if i % 2 == 0 else INVALID for i in range(10)])
+
+        for i, (_, context) in enumerate(contexts):
+            if i % 2 == 0:
+                self.assertIn(
+                    addressing.make_record_address(
+                        'orchid-{}'.format(i + 2)),
+                    context.writes)
+            else:
+                self.assertEqual(context.writes, {})
+
+        self.assert_healthy()
+
+    def test_validator_restart(self):
+        """The processor survives losing its validator partway through a
+        transaction, and reconnects to the next one"""
+        hurley = make_agent()
+        create = hurley.create_agent('Hugo Reyes')
+
+        abandoned = Context({})
+        self.validator.send(
+            make_request(create, abandoned),
+            correlation_id=abandoned.context_id)
+
+        # Wait until the processor is reading state, then disappear
+        message = self.validator.receive()
+        self.assertEqual(message.message_type, Message.TP_STATE_GET_REQUEST)
+        self.validator.close()
+
+        time.sleep(RESTART_DELAY)
+        type(self).validator = self.start_validator()
+
+        # The new validator knows nothing of the abandoned context, and
+        # retries the transaction from scratch
+        retry = Context({})
+        self.assertEqual(self.process((create, retry)), [OK])
+        self.assertEqual(
+            sorted(retry.writes),
+            [addressing.make_agent_activity_address(hurley.public_key, 1),
+             addressing.make_agent_address(hurley.public_key)])
+
+        self.assert_healthy()
@@ -21,7 +21,14 @@ const db = require('./')
 
 const stateTables = [
   'agents',
+  'agentActivityPages',
+  'subscriptions',
+  'excursionLogs',
+  'messageAnchors',
+  'feedbackLogs',
+  'feedbackPages',
   'records',
+  'recordTimelinePages',
   'recordTypes',
   'properties',
   'propertyPages',
@@ -68,6 +75,17 @@ const insert = block => {
     })
 }
 
+// Fetch the ids of the most recent blocks, newest first
+const listLatestIds = count => {
+  return db.queryTable('blocks', blocks => {
+    return blocks
+      .orderBy(r.desc('blockNum'))
+      .limit(count)
+      .map(block => block('blockId'))
+  })
+}
+
 module.exports = {
-  insert
+  insert,
+  listLatestIds
 }
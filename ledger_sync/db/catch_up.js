@@ -0,0 +1,42 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const db = require('./')
+
+// Progress is stored as a single document, so an interrupted catch-up
+// can resume from the last page it finished
+const PROGRESS_ID = 'catchUp'
+
+const fetchProgress = () => {
+  return db.queryTable('syncProgress', progress => {
+    return progress.get(PROGRESS_ID)
+  }, false)
+}
+
+const saveProgress = progress => {
+  return db.modifyTable('syncProgress', table => {
+    return table.insert(Object.assign({ id: PROGRESS_ID }, progress), {
+      conflict: 'replace'
+    })
+  })
+}
+
+module.exports = {
+  fetchProgress,
+  saveProgress
+}
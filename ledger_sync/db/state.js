@@ -27,15 +27,31 @@ const valueNames = {
   NUMBER: 'numberValue',
   STRING: 'stringValue',
   ENUM: 'enumValue',
-  LOCATION: 'locationValue'
+  LOCATION: 'locationValue',
+  TIMESTAMP: 'timestampValue',
+  RECORD_REFERENCE: 'recordReferenceValue',
+  IDENTIFIER: 'identifierValue'
+}
+
+// Locations are stored in millionths of a degree, and values outside the
+// valid range cannot be made into geo points
+const isValidLocation = ({ latitude, longitude }) => {
+  return Math.abs(latitude) <= 90e6 && Math.abs(longitude) <= 180e6
+}
+
+const toPoint = ({ latitude, longitude }) => {
+  return r.point(longitude / 1e6, latitude / 1e6)
+}
+
+const xformValue = property => {
+  if (property.dataType === 'STRUCT') return xformStruct(property.structValues)
+  if (property.dataType === 'ARRAY') return property.arrayValues.map(xformValue)
+  return property[ valueNames[property.dataType] ]
 }
 
 const xformStruct = properties => {
   return _.fromPairs(properties.map(property => {
-    const value = property.dataType === 'STRUCT'
-      ? xformStruct(property.structValues)
-      : property[ valueNames[property.dataType] ]
-    return [property.name, value]
+    return [property.name, xformValue(property)]
   }))
 }
 
@@ -77,22 +93,108 @@ const addAgent = (agent, blockNum) => {
                        agent, blockNum)
 }
 
+const addAgentActivityPage = (page, blockNum) => {
+  return addBlockState('agentActivityPages', 'attributes',
+                       ['agentId', 'pageNum'].map(k => page[k]),
+                       page, blockNum)
+}
+
+const addSubscriptionList = (list, blockNum) => {
+  return addBlockState('subscriptions', 'agentId', list.agentId,
+                       list, blockNum)
+}
+
+const addExcursionLog = (log, blockNum) => {
+  return addBlockState('excursionLogs', 'attributes',
+                       ['name', 'recordId'].map(k => log[k]),
+                       log, blockNum)
+}
+
+const addMessageAnchor = (anchor, blockNum) => {
+  return addBlockState('messageAnchors', 'attributes',
+                       ['recordId', 'digest'].map(k => anchor[k]),
+                       anchor, blockNum)
+}
+
+const addFeedbackLog = (log, blockNum) => {
+  return addBlockState('feedbackLogs', 'recordId', log.recordId,
+                       log, blockNum)
+}
+
+const addFeedbackPage = (page, blockNum) => {
+  return addBlockState('feedbackPages', 'attributes',
+                       ['recordId', 'pageNum'].map(k => page[k]),
+                       page, blockNum)
+}
+
 const addRecord = (record, blockNum) => {
   return addBlockState('records', 'recordId', record.recordId,
                        record, blockNum)
 }
 
+const addRecordTimelinePage = (page, blockNum) => {
+  return addBlockState('recordTimelinePages', 'attributes',
+                       ['recordId', 'pageNum'].map(k => page[k]),
+                       page, blockNum)
+}
+
 const addRecordType = (type, blockNum) => {
   return addBlockState('recordTypes', 'name', type.name,
                        type, blockNum)
 }
 
+// Ends the current versions of the RecordTypes whose names a predicate
+// picks out, once they have been deleted from state
+const removeRecordTypes = (isRemoved, blockNum) => {
+  return db.queryTable('recordTypes', types => {
+    return types
+      .filter({ endBlockNum: Number.MAX_SAFE_INTEGER })
+      .pluck('id', 'name')
+  })
+    .then(types => types.filter(type => isRemoved(type.name)))
+    .then(removed => {
+      if (removed.length === 0) return null
+      return db.modifyTable('recordTypes', table => {
+        return table
+          .getAll(r.args(removed.map(type => type.id)))
+          .update({ endBlockNum: blockNum })
+      })
+    })
+}
+
 const addProperty = (property, blockNum) => {
   return addBlockState('properties', 'attributes',
                        ['name', 'recordId'].map(k => property[k]),
                        property, blockNum)
 }
 
+// Newer Properties leave their enum options on the schema in their Record's
+// type, rather than keeping a copy of them
+const fetchEnumOptions = property => {
+  if (!_.isEmpty(property.enumOptions)) {
+    return Promise.resolve(property.enumOptions)
+  }
+
+  return db.queryTable('records', records => {
+    return records
+      .getAll(property.recordId, { index: 'recordId' })
+      .filter({ endBlockNum: Number.MAX_SAFE_INTEGER })
+  })
+    .then(records => {
+      if (records.length === 0) return []
+      return db.queryTable('recordTypes', types => {
+        return types
+          .getAll(records[0].recordType, { index: 'name' })
+          .filter({ endBlockNum: Number.MAX_SAFE_INTEGER })
+      })
+    })
+    .then(types => {
+      if (types.length === 0) return []
+      const schema = _.find(types[0].properties, { name: property.name })
+      return schema ? schema.enumOptions : []
+    })
+}
+
 const addPropertyPage = (page, blockNum) => {
   return db.queryTable('properties', properties => {
     return properties
@@ -108,16 +210,24 @@ const addPropertyPage = (page, blockNum) => {
       const property = properties[0]
 
       // Convert enum indexes into names, or empty strings if not an enum
-      if (property.dataType === 'ENUM') {
-        page.reportedValues.forEach(reported => {
-          reported.enumValue = property.enumOptions[reported.enumValue]
-        })
-      } else {
+      if (property.dataType !== 'ENUM') {
         page.reportedValues.forEach(reported => {
           reported.enumValue = ''
         })
+        return property
       }
 
+      return fetchEnumOptions(property)
+        .then(enumOptions => {
+          page.reportedValues.forEach(reported => {
+            reported.enumValue = enumOptions[reported.enumValue]
+          })
+          return property
+        })
+    })
+    .then(property => {
+      if (!property) return
+
       // Convert `structValues` array into `structValue` object
       if (property.dataType === 'STRUCT') {
         page.reportedValues.forEach(reported => {
@@ -131,6 +241,23 @@ const addPropertyPage = (page, blockNum) => {
         })
       }
 
+      // Likewise convert `arrayValues` into a plain `arrayValue` list
+      page.reportedValues.forEach(reported => {
+        reported.arrayValue = property.dataType === 'ARRAY'
+          ? reported.arrayValues.map(xformValue)
+          : []
+        delete reported.arrayValues
+      })
+
+      // Index reported positions as geo points, so pages can be found
+      // by the area their values were reported in
+      if (property.dataType === 'LOCATION') {
+        page.locations = page.reportedValues
+          .map(reported => reported.locationValue)
+          .filter(isValidLocation)
+          .map(toPoint)
+      }
+
     })
     .then(() => {
       return addBlockState('propertyPages', 'attributes',
@@ -146,11 +273,28 @@ const addProposal = (proposal, blockNum) => {
     proposal, blockNum)
 }
 
+// Closed proposals are moved to history pages, so each one is stored
+// as a new version of the proposal it replaces
+const addProposalHistoryPage = (page, blockNum) => {
+  return Promise.all(page.proposals.map(proposal => {
+    return addProposal(proposal, blockNum)
+  }))
+}
+
 module.exports = {
   addAgent,
+  addAgentActivityPage,
+  addSubscriptionList,
+  addExcursionLog,
+  addMessageAnchor,
+  addFeedbackLog,
+  addFeedbackPage,
   addRecord,
+  addRecordTimelinePage,
   addRecordType,
+  removeRecordTypes,
   addProperty,
   addPropertyPage,
-  addProposal
+  addProposal,
+  addProposalHistoryPage
 }
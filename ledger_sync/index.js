@@ -18,11 +18,16 @@
 
 const db = require('./db')
 const subscriber = require('./subscriber')
+const catchUp = require('./subscriber/catch_up')
 const protos = require('./subscriber/protos')
+const config = require('./system/config')
+
+const CATCH_UP = config.CATCH_UP === true || config.CATCH_UP === 'true'
 
 Promise.all([
   db.connect(),
   protos.compile()
 ])
+  .then(() => CATCH_UP ? catchUp.run() : [])
   .then(subscriber.start)
   .catch(err => console.error(err.message))
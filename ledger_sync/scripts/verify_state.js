@@ -0,0 +1,510 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Scans the supply_chain namespace at a block (the chain head, or the block
+// id passed as the first argument) and reports state that breaks the
+// invariants the transaction processor maintains. Exits non-zero if any
+// anomalies are found.
+
+const _ = require('lodash')
+const { createHash } = require('crypto')
+
+const protos = require('../subscriber/protos')
+const { getProtoName } = require('../subscriber/deltas')
+const {
+  fetchHead,
+  fetchBlock,
+  fetchStatePage
+} = require('../system/rest_api')
+
+const PREFIX = '3400de'
+const MAX_PAGE = 0xffff
+
+const OPEN_STATUSES = ['OPEN', 'SCHEDULED', 'ESCROWED']
+const CLOSED_STATUSES = [
+  'ACCEPTED', 'REJECTED', 'CANCELED', 'EXPIRED', 'COUNTERED'
+]
+
+const hash = (str, length) => {
+  return createHash('sha512').update(str).digest('hex').slice(0, length)
+}
+
+const pageNumber = address => parseInt(address.slice(-4), 16)
+const toPage = num => _.padStart(num.toString(16), 4, '0')
+
+const recordRange = (infix, recordId, length) => {
+  return PREFIX + infix + hash(recordId, length)
+}
+
+// For each kind of container: the key entries are sorted by, and the
+// address an entry belongs at, given the page number of its address
+const LAYOUTS = {
+  ExcursionLog: {
+    key: e => [e.recordId, e.name],
+    address: e => recordRange('ac', e.recordId, 36) + hash(e.name, 26)
+  },
+  MessageAnchor: {
+    key: e => [e.recordId, e.digest],
+    address: e => recordRange('ad', e.recordId, 36) + hash(e.digest, 26)
+  },
+  Agent: {
+    key: e => [e.publicKey],
+    address: e => PREFIX + 'ae' + hash(e.publicKey, 62)
+  },
+  AgentActivityPage: {
+    key: e => [e.agentId],
+    address: (e, page) => PREFIX + 'a0' + hash(e.agentId, 58) + toPage(page)
+  },
+  SubscriptionList: {
+    key: e => [e.agentId],
+    address: e => PREFIX + 'af' + hash(e.agentId, 62)
+  },
+  Record: {
+    key: e => [e.recordId],
+    address: e => PREFIX + 'ec' + hash(e.recordId, 62)
+  },
+  RecordTimelinePage: {
+    key: e => [e.recordId],
+    address: (e, page) => recordRange('ed', e.recordId, 58) + toPage(page)
+  },
+  RecordType: {
+    key: e => [e.name],
+    address: e => PREFIX + 'ee' + hash(e.name, 62)
+  },
+  Property: {
+    key: e => [e.name],
+    address: e => recordRange('ea', e.recordId, 36) + hash(e.name, 22) +
+      toPage(0)
+  },
+  PropertyPage: {
+    key: e => [e.name],
+    address: (e, page) => recordRange('ea', e.recordId, 36) +
+      hash(e.name, 22) + toPage(page)
+  },
+  Proposal: {
+    key: e => [e.recordId, e.receivingAgent, e.timestamp],
+    address: e => recordRange('aa', e.recordId, 36) +
+      hash(e.receivingAgent, 26)
+  },
+  ProposalHistoryPage: {
+    key: e => [e.recordId, e.receivingAgent],
+    address: (e, page) => recordRange('ab', e.recordId, 36) +
+      hash(e.receivingAgent, 22) + toPage(page)
+  }
+}
+
+const compareKeys = (a, b) => {
+  for (let i = 0; i < a.length; i++) {
+    if (a[i] < b[i]) return -1
+    if (a[i] > b[i]) return 1
+  }
+  return 0
+}
+
+const createReport = () => {
+  const anomalies = []
+  return {
+    anomalies,
+    add (check, address, message) {
+      anomalies.push({ check, address, message })
+      console.log(`[${check}] ${address}: ${message}`)
+    }
+  }
+}
+
+// What is kept of the namespace between the scan and the cross-reference
+// checks; reported values and proposal terms are dropped as entries are read
+const createIndex = () => ({
+  agents: new Set(),
+  agentActivity: {},
+  activityPages: {},
+  recordTypes: new Set(),
+  records: {},
+  properties: {},
+  propertyPages: {},
+  timelinePages: {},
+  proposals: [],
+  messages: [],
+  subscriptions: [],
+  excursionLogs: [],
+  addresses: 0
+})
+
+const decode = (address, value, report) => {
+  let name = null
+  try {
+    name = getProtoName(address)
+  } catch (err) {
+    report.add('decode', address, 'address has an unknown type infix')
+    return null
+  }
+
+  try {
+    const container = protos[`${name}Container`].decode(value)
+    const { entries } = protos[`${name}Container`].toObject(container, {
+      enums: String,
+      longs: Number,
+      defaults: true
+    })
+    return { name, entries }
+  } catch (err) {
+    report.add('decode', address, `cannot decode ${name} container`)
+    return null
+  }
+}
+
+// Checks that only need the container at hand: ordering, duplicates and
+// that each entry hashes to the address it is stored at
+const checkContainer = (address, name, entries, report) => {
+  const layout = LAYOUTS[name]
+  const page = pageNumber(address)
+
+  // Proposal containers are left empty once their last Proposal closes
+  if (entries.length === 0 && name !== 'Proposal') {
+    report.add('empty', address, `${name} container has no entries`)
+  }
+
+  entries.forEach((entry, i) => {
+    if (name === 'ProposalHistoryPage') {
+      entry.proposals
+        .filter(p => p.recordId !== entry.recordId ||
+                     p.receivingAgent !== entry.receivingAgent)
+        .forEach(() => {
+          report.add('proposal', address,
+            'history page holds a Proposal for another Record or Agent')
+        })
+    }
+
+    if (name === 'ExcursionLog') {
+      const ongoing = entry.excursions.filter(e => e.end === 0).length
+      const last = _.last(entry.excursions)
+      if (ongoing > 1 || (ongoing === 1 && last.end !== 0)) {
+        report.add('excursion', address,
+          `only the latest excursion of ${entry.name} may be ongoing`)
+      }
+    }
+
+    if (layout.address(entry, page) !== address) {
+      report.add('address', address,
+        `${name} ${layout.key(entry).join('/')} belongs at ` +
+        layout.address(entry, page))
+    }
+
+    if (i === 0) return
+    const order = compareKeys(layout.key(entries[i - 1]), layout.key(entry))
+    if (order > 0) {
+      report.add('sorted', address,
+        `${name} ${layout.key(entry).join('/')} is out of order`)
+    } else if (order === 0) {
+      report.add('sorted', address,
+        `${name} ${layout.key(entry).join('/')} appears more than once`)
+    }
+  })
+}
+
+const propertyKey = (recordId, name) => `${recordId}\u0000${name}`
+
+const indexEntries = (address, name, entries, index) => {
+  const page = pageNumber(address)
+  entries.forEach(entry => {
+    if (name === 'Agent') {
+      index.agents.add(entry.publicKey)
+      index.agentActivity[entry.publicKey] = {
+        address,
+        activityPage: entry.activityPage
+      }
+    } else if (name === 'AgentActivityPage') {
+      const pages = index.activityPages[entry.agentId] || []
+      pages.push(page)
+      index.activityPages[entry.agentId] = pages
+    } else if (name === 'SubscriptionList') {
+      index.subscriptions.push({
+        address,
+        agentId: entry.agentId,
+        subscriptions: entry.subscriptions.map(s => _.pick(s, [
+          'scope',
+          'target'
+        ]))
+      })
+    } else if (name === 'ExcursionLog') {
+      index.excursionLogs.push({
+        address,
+        recordId: entry.recordId,
+        name: entry.name
+      })
+    } else if (name === 'MessageAnchor') {
+      index.messages.push({
+        address,
+        recordId: entry.recordId,
+        sender: entry.sender,
+        recipient: entry.recipient
+      })
+    } else if (name === 'RecordType') {
+      index.recordTypes.add(entry.name)
+    } else if (name === 'Record') {
+      index.records[entry.recordId] = {
+        address,
+        recordType: entry.recordType,
+        timelinePage: entry.timelinePage
+      }
+    } else if (name === 'RecordTimelinePage') {
+      const pages = index.timelinePages[entry.recordId] || []
+      pages.push(page)
+      index.timelinePages[entry.recordId] = pages
+    } else if (name === 'Property') {
+      index.properties[propertyKey(entry.recordId, entry.name)] = {
+        address,
+        recordId: entry.recordId,
+        name: entry.name,
+        currentPage: entry.currentPage,
+        wrapped: entry.wrapped
+      }
+    } else if (name === 'PropertyPage') {
+      const key = propertyKey(entry.recordId, entry.name)
+      const pages = index.propertyPages[key] || []
+      pages.push({ address, page })
+      index.propertyPages[key] = pages
+    } else if (name === 'Proposal') {
+      index.proposals.push({
+        address,
+        container: true,
+        recordId: entry.recordId,
+        receivingAgent: entry.receivingAgent,
+        issuingAgent: entry.issuingAgent,
+        role: entry.role,
+        status: entry.status,
+        effectiveAt: entry.effectiveAt
+      })
+    } else if (name === 'ProposalHistoryPage') {
+      entry.proposals.forEach(proposal => {
+        index.proposals.push({
+          address,
+          container: false,
+          recordId: proposal.recordId,
+          status: proposal.status
+        })
+      })
+    }
+  })
+}
+
+const checkReferences = (index, report) => {
+  _.forEach(index.records, (record, recordId) => {
+    if (!index.recordTypes.has(record.recordType)) {
+      report.add('reference', record.address,
+        `Record ${recordId} has unknown type ${record.recordType}`)
+    }
+  })
+
+  _.forEach(index.properties, property => {
+    if (!index.records[property.recordId]) {
+      report.add('reference', property.address,
+        `Property ${property.name} belongs to missing Record ` +
+        property.recordId)
+    }
+  })
+
+  _.forEach(index.propertyPages, (pages, key) => {
+    if (index.properties[key]) return
+    pages.forEach(({ address }) => {
+      report.add('reference', address, 'PropertyPage has no Property')
+    })
+  })
+
+  index.excursionLogs.forEach(log => {
+    if (index.properties[propertyKey(log.recordId, log.name)]) return
+    report.add('reference', log.address,
+      `excursion log belongs to missing Property ${log.name}`)
+  })
+
+  index.subscriptions.forEach(list => {
+    if (!index.agents.has(list.agentId)) {
+      report.add('reference', list.address,
+        `subscriptions belong to missing Agent ${list.agentId}`)
+    }
+    // Subscriptions to a RecordType outlive it if it is deleted, so only
+    // those to Records must refer to existing state
+    list.subscriptions.forEach(({ scope, target }) => {
+      if (scope === 'RECORD' && !index.records[target]) {
+        report.add('reference', list.address,
+          `${scope} subscription targets missing ${target}`)
+      }
+    })
+  })
+
+  index.messages.forEach(message => {
+    if (!index.records[message.recordId]) {
+      report.add('reference', message.address,
+        `message anchor references missing Record ${message.recordId}`)
+    }
+    if (!index.agents.has(message.sender) ||
+        !index.agents.has(message.recipient)) {
+      report.add('reference', message.address,
+        'message anchor references missing Agent')
+    }
+  })
+
+  _.forEach(index.timelinePages, (pages, recordId) => {
+    if (index.records[recordId]) return
+    report.add('reference', recordRange('ed', recordId, 58),
+      `timeline pages belong to missing Record ${recordId}`)
+  })
+
+  _.forEach(index.activityPages, (pages, agentId) => {
+    if (index.agents.has(agentId)) return
+    report.add('reference', PREFIX + 'a0' + hash(agentId, 58),
+      `activity pages belong to missing Agent ${agentId}`)
+  })
+}
+
+const checkPages = (index, report) => {
+  _.forEach(index.properties, (property, key) => {
+    const pages = _.map(index.propertyPages[key], 'page')
+    const current = property.currentPage
+
+    if (current < 1 || current > MAX_PAGE) {
+      report.add('page', property.address,
+        `Property ${property.name} has invalid current_page ${current}`)
+    } else if (!pages.includes(current)) {
+      report.add('page', property.address,
+        `Property ${property.name} current_page ${current} does not exist`)
+    }
+
+    if (!property.wrapped) {
+      pages.filter(page => page > current).forEach(page => {
+        report.add('page', property.address,
+          `Property ${property.name} has page ${page} past current_page ` +
+          `${current} but has not wrapped`)
+      })
+    }
+  })
+
+  _.forEach(index.records, (record, recordId) => {
+    if (record.timelinePage === 0) return
+    const pages = index.timelinePages[recordId] || []
+    if (!pages.includes(record.timelinePage)) {
+      report.add('page', record.address,
+        `Record ${recordId} timeline_page ${record.timelinePage} ` +
+        'does not exist')
+    }
+  })
+
+  _.forEach(index.agentActivity, (agent, agentId) => {
+    if (agent.activityPage === 0) return
+    const pages = index.activityPages[agentId] || []
+    if (!pages.includes(agent.activityPage)) {
+      report.add('page', agent.address,
+        `Agent ${agentId} activity_page ${agent.activityPage} ` +
+        'does not exist')
+    }
+  })
+}
+
+const checkProposals = (index, report) => {
+  const openByRole = _.groupBy(
+    index.proposals.filter(p => p.container &&
+                                OPEN_STATUSES.includes(p.status)),
+    p => [p.recordId, p.receivingAgent, p.role].join('\u0000'))
+
+  index.proposals.forEach(proposal => {
+    const { address } = proposal
+
+    if (!index.records[proposal.recordId]) {
+      report.add('reference', address,
+        `Proposal references missing Record ${proposal.recordId}`)
+    }
+
+    if (!proposal.container) {
+      if (!CLOSED_STATUSES.includes(proposal.status)) {
+        report.add('proposal', address,
+          `history page holds a ${proposal.status} Proposal`)
+      }
+      return
+    }
+
+    if (!index.agents.has(proposal.issuingAgent) ||
+        !index.agents.has(proposal.receivingAgent)) {
+      report.add('reference', address, 'Proposal references missing Agent')
+    }
+
+    if (!OPEN_STATUSES.includes(proposal.status)) {
+      report.add('proposal', address,
+        `${proposal.status} Proposal was not moved to its history`)
+    }
+
+    if (proposal.status === 'SCHEDULED' &&
+        (proposal.role === 'REPORTER' || !proposal.effectiveAt)) {
+      report.add('proposal', address,
+        `SCHEDULED ${proposal.role} Proposal has no effective time`)
+    }
+
+    if (proposal.role !== 'REPORTER' && proposal.expiresAt) {
+      report.add('proposal', address,
+        `${proposal.role} Proposal has a reporter expiry time`)
+    }
+  })
+
+  _.forEach(openByRole, open => {
+    if (open.length > 1) {
+      report.add('proposal', open[0].address,
+        `${open.length} open ${open[0].role} Proposals for ` +
+        open[0].receivingAgent)
+    }
+  })
+}
+
+const scan = (block, index, report, start = null) => {
+  return fetchStatePage(block, PREFIX, start)
+    .then(({ data, paging }) => {
+      data.forEach(({ address, data: value }) => {
+        index.addresses++
+        const decoded = decode(address, Buffer.from(value, 'base64'), report)
+        if (!decoded) return
+        checkContainer(address, decoded.name, decoded.entries, report)
+        indexEntries(address, decoded.name, decoded.entries, index)
+      })
+
+      const next = _.get(paging, 'next_position', null)
+      if (next) return scan(block, index, report, next)
+    })
+}
+
+const blockId = process.argv[2]
+
+protos.compile()
+  .then(() => blockId ? fetchBlock(blockId) : fetchHead())
+  .then(block => {
+    console.log(`Verifying supply_chain state at block ${block.blockNum}`)
+    const index = createIndex()
+    const report = createReport()
+
+    return scan(block, index, report)
+      .then(() => {
+        checkReferences(index, report)
+        checkPages(index, report)
+        checkProposals(index, report)
+
+        console.log(`Checked ${index.addresses} addresses, ` +
+                    `found ${report.anomalies.length} anomalies`)
+        if (report.anomalies.length > 0) process.exitCode = 1
+      })
+  })
+  .catch(err => {
+    console.error(err.message)
+    process.exitCode = 2
+  })
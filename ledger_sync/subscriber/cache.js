@@ -0,0 +1,70 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// A least-recently-used cache of decoded state containers, keyed by the
+// address and the state root hash of the block the change was seen in.
+// Each entry also remembers its block, so that a fork can evict anything
+// decoded from blocks that are no longer on the chain.
+const createCache = maxSize => ({
+  _entries: new Map(),
+  _blockIds: new Map(),
+
+  _key (address, stateRootHash) {
+    return `${stateRootHash}:${address}`
+  },
+
+  get (address, stateRootHash) {
+    const key = this._key(address, stateRootHash)
+    const entry = this._entries.get(key)
+    if (!entry) return null
+
+    // Re-insert to mark as most recently used
+    this._entries.delete(key)
+    this._entries.set(key, entry)
+    return entry.value
+  },
+
+  set (address, block, value) {
+    const key = this._key(address, block.stateRootHash)
+    this._entries.delete(key)
+    this._entries.set(key, { blockNum: block.blockNum, value })
+
+    while (this._entries.size > maxSize) {
+      this._entries.delete(this._entries.keys().next().value)
+    }
+  },
+
+  // Compare the block against the last one seen at its height, evicting
+  // everything from that height on if the ids differ
+  checkFork (block) {
+    const knownId = this._blockIds.get(block.blockNum)
+    if (knownId !== undefined && knownId !== block.blockId) {
+      for (const [ key, entry ] of this._entries) {
+        if (entry.blockNum >= block.blockNum) this._entries.delete(key)
+      }
+      for (const blockNum of this._blockIds.keys()) {
+        if (blockNum > block.blockNum) this._blockIds.delete(blockNum)
+      }
+    }
+    this._blockIds.set(block.blockNum, block.blockId)
+  }
+})
+
+module.exports = {
+  createCache
+}
@@ -0,0 +1,134 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+
+const blocks = require('../db/blocks')
+const progress = require('../db/catch_up')
+const deltas = require('./deltas')
+const { fetchHead, fetchStatePage } = require('../system/rest_api')
+
+const PREFIX = '3400de'
+
+// Property pages need their Property in the database before they can be
+// added, so they are walked in a second pass over the "ea" prefix
+const PASSES = [
+  {
+    prefixes: ['ae', 'ee', 'ec', 'ed', 'ea', 'aa', 'ab', 'ad', 'af', 'ac'],
+    pages: false
+  },
+  { prefixes: ['ea'], pages: true }
+]
+
+const isPage = address => deltas.getProtoName(address) === 'PropertyPage'
+
+// Walk every address under a prefix, adding its entries at the head
+// block and saving progress after each page
+const walkPrefix = (state, pass) => {
+  const prefix = pass.prefixes[state.prefixIndex]
+  return fetchStatePage(state.block, PREFIX + prefix, state.start)
+    .then(({ data, paging }) => {
+      const changes = data
+        .filter(entry => isPage(entry.address) === pass.pages)
+        .map(entry => ({
+          address: entry.address,
+          value: Buffer.from(entry.data, 'base64')
+        }))
+
+      return deltas.addChanges(state.block, changes)
+        .then(() => {
+          const next = _.get(paging, 'next_position', null)
+          const update = next
+            ? { start: next }
+            : { prefixIndex: state.prefixIndex + 1, start: null }
+          return _.assign({}, state, update, {
+            addresses: state.addresses + changes.length
+          })
+        })
+    })
+    .then(next => progress.saveProgress(next).then(() => next))
+    .then(next => {
+      console.log(`Catch-up: ${next.addresses} addresses synced ` +
+                  `(pass ${next.passIndex + 1}/${PASSES.length}, ` +
+                  `prefix ${prefix})`)
+      return next
+    })
+}
+
+const walk = state => {
+  if (state.passIndex >= PASSES.length) return Promise.resolve(state)
+
+  const pass = PASSES[state.passIndex]
+  if (state.prefixIndex >= pass.prefixes.length) {
+    return walk(_.assign({}, state, {
+      passIndex: state.passIndex + 1,
+      prefixIndex: 0,
+      start: null
+    }))
+  }
+
+  return walkPrefix(state, pass).then(walk)
+}
+
+const startFresh = () => {
+  return fetchHead()
+    .then(block => {
+      console.log(`Catch-up: syncing state at block ${block.blockNum}`)
+      return {
+        block,
+        passIndex: 0,
+        prefixIndex: 0,
+        start: null,
+        addresses: 0,
+        complete: false
+      }
+    })
+}
+
+// Bootstrap the database from the current chain head rather than by
+// replaying every block from genesis. Resolves with the ids of the blocks
+// the event subscription should resume from.
+const run = () => {
+  return progress.fetchProgress()
+    .then(saved => {
+      if (saved && saved.complete) {
+        console.log('Catch-up already complete, resuming from last block')
+        return blocks.listLatestIds(1)
+      }
+      if (saved) {
+        console.log(`Catch-up: resuming at block ${saved.block.blockNum}`)
+      }
+
+      return Promise.resolve(saved || startFresh())
+        .then(walk)
+        .then(state => {
+          return blocks.insert(state.block)
+            .then(() => progress.saveProgress(
+              _.assign({}, state, { complete: true })))
+            .then(() => {
+              console.log(`Catch-up complete: ${state.addresses} addresses ` +
+                          `synced at block ${state.block.blockNum}`)
+              return [state.block.blockId]
+            })
+        })
+    })
+}
+
+module.exports = {
+  run
+}
@@ -17,9 +17,15 @@
 'use strict'
 
 const _ = require('lodash')
+const { createHash } = require('crypto')
 const blocks = require('../db/blocks')
 const state = require('../db/state')
 const protos = require('./protos')
+const { createCache } = require('./cache')
+const config = require('../system/config')
+
+const containerCache = createCache(Number(config.CONTAINER_CACHE_SIZE))
+const WRITE_BATCH_SIZE = Number(config.WRITE_BATCH_SIZE)
 
 const deltaQueue = {
   _queue: [],
@@ -46,17 +52,39 @@ const deltaQueue = {
   }
 }
 
+// Kinds of state with no table in the database: StateAnchors, which are
+// only read by auditors from the chain itself, OpenProposalIndexes,
+// which only the processor reads, RecordTypeStats and RecordTemplates,
+// which the server reads from state, and earlier versions of
+// RecordTypes, which only the processor reads to check the records made
+// with them
+const UNSYNCED_PREFIXES = ['a1', 'a2', 'a3', 'a5', 'a6']
+
+const isSynced = ({ address }) => {
+  return !UNSYNCED_PREFIXES.includes(address.slice(6, 8))
+}
+
 const getProtoName = address => {
   const typePrefix = address.slice(6, 8)
   if (typePrefix === 'ea') {
     if (address.slice(-4) === '0000') return 'Property'
     else return 'PropertyPage'
   }
+  if (typePrefix === 'a4') {
+    if (address.slice(-4) === '0000') return 'FeedbackLog'
+    else return 'FeedbackPage'
+  }
 
   const names = {
+    a0: 'AgentActivityPage',
+    ac: 'ExcursionLog',
+    ad: 'MessageAnchor',
     ae: 'Agent',
+    af: 'SubscriptionList',
     aa: 'Proposal',
+    ab: 'ProposalHistoryPage',
     ec: 'Record',
+    ed: 'RecordTimelinePage',
     ee: 'RecordType'
   }
   if (names[typePrefix]) return names[typePrefix]
@@ -72,7 +100,8 @@ const getObjectifier = address => {
       longs: Number,  // convert int64 to Number, limiting precision to 2^53
       defaults: true  // use default for falsey values
     })
-    if (name === 'PropertyPage') {
+    if (name === 'PropertyPage' || name === 'RecordTimelinePage' ||
+        name === 'AgentActivityPage' || name === 'FeedbackPage') {
       obj.pageNum = parseInt(address.slice(-4), 16)
     }
     return obj
@@ -83,40 +112,89 @@ const stateAdder = address => {
   const addState = state[`add${getProtoName(address)}`]
   const toObject = getObjectifier(address)
   return (stateInstance, blockNum) => {
-    addState(toObject(stateInstance), blockNum)
+    return addState(toObject(stateInstance), blockNum)
   }
 }
 
-const getEntries = ({ address, value }) => {
-  return protos[`${getProtoName(address)}Container`]
+const getEntries = ({ address, value }, block) => {
+  const cached = containerCache.get(address, block.stateRootHash)
+  if (cached) return cached
+
+  const entries = protos[`${getProtoName(address)}Container`]
     .decode(value)
     .entries
+  containerCache.set(address, block, entries)
+  return entries
+}
+
+// Runs fn over items a batch at a time, starting each batch only once
+// the one before it has finished
+const inBatches = (items, fn) => {
+  return _.chunk(items, WRITE_BATCH_SIZE).reduce((done, batch) => {
+    return done.then(() => Promise.all(batch.map(fn)))
+  }, Promise.resolve())
+}
+
+// Adds the entries of every change with at most WRITE_BATCH_SIZE writes
+// in flight, so that a block or state page of large containers does not
+// send them all to the database at once. Containers are only decoded as
+// their batch is reached, which also keeps the sync from reading ahead
+// of the database.
+const addChanges = (block, changes) => {
+  return _.chunk(changes, WRITE_BATCH_SIZE).reduce((done, changeBatch) => {
+    return done.then(() => {
+      const writes = _.flatMap(changeBatch, change => {
+        const addState = stateAdder(change.address)
+        return getEntries(change, block).map(entry => [addState, entry])
+      })
+      return inBatches(writes, ([addState, entry]) => {
+        return addState(entry, block.blockNum)
+      })
+    })
+  }, Promise.resolve())
 }
 
-const entryAdder = block => change => {
-  const addState = stateAdder(change.address)
-  return Promise.all(getEntries(change).map(entry => {
-    return addState(entry, block.blockNum)
-  }))
+const makeRecordTypeAddress = name => {
+  return '3400deee' +
+    createHash('sha512').update(name).digest('hex').slice(0, 62)
+}
+
+// RecordTypes can be deleted, so any type stored at the address of a
+// changed container which is no longer in it is ended
+const recordTypeRemover = block => change => {
+  const kept = getEntries(change, block).map(type => type.name)
+  return state.removeRecordTypes(name => {
+    return !kept.includes(name) && makeRecordTypeAddress(name) === change.address
+  }, block.blockNum)
 }
 
 const handle = (block, changes) => {
   deltaQueue.add(() => {
-    const [ pageChanges, otherChanges ] = _.partition(changes, change => {
+    containerCache.checkFork(block)
+
+    const synced = changes.filter(isSynced)
+    const [ pageChanges, otherChanges ] = _.partition(synced, change => {
       return getProtoName(change.address) === 'PropertyPage'
     })
 
-    return Promise.all(otherChanges.map(entryAdder(block)))
+    const typeChanges = otherChanges.filter(change => {
+      return getProtoName(change.address) === 'RecordType'
+    })
+
+    return addChanges(block, otherChanges)
+      .then(() => Promise.all(typeChanges.map(recordTypeRemover(block))))
       .then(() => {
         // If there are page changes, give other changes a chance to propagate
         const wait = pageChanges.length === 0 ? 0 : 100
         return new Promise(resolve => setTimeout(resolve, wait))
       })
-      .then(() =>  Promise.all(pageChanges.map(entryAdder(block))))
+      .then(() => addChanges(block, pageChanges))
       .then(() => blocks.insert(block))
   })
 }
 
 module.exports = {
+  getProtoName,
+  addChanges,
   handle
 }
@@ -73,7 +73,7 @@ const handleEvent = msg => {
 }
 
 // Send delta event subscription request to validator
-const subscribe = () => {
+const subscribe = lastKnownBlockIds => {
   const blockSub = EventSubscription.create({
     eventType: 'sawtooth/block-commit'
   })
@@ -89,7 +89,7 @@ const subscribe = () => {
   return stream.send(
     Message.MessageType.CLIENT_EVENTS_SUBSCRIBE_REQUEST,
     ClientEventsSubscribeRequest.encode({
-      lastKnownBlockIds: [NULL_BLOCK_ID],
+      lastKnownBlockIds,
       subscriptions: [blockSub, deltaSub]
     }).finish()
   )
@@ -103,12 +103,17 @@ const subscribe = () => {
     })
 }
 
-// Start stream and send delta event subscription request
-const start = () => {
+// Start stream and send delta event subscription request, replaying
+// from genesis unless the ids of already synced blocks are provided
+const start = (lastKnownBlockIds = []) => {
+  const knownIds = lastKnownBlockIds.length > 0
+    ? lastKnownBlockIds
+    : [NULL_BLOCK_ID]
+
   return new Promise(resolve => {
     stream.connect(() => {
       stream.onReceive(handleEvent)
-      subscribe().then(resolve)
+      subscribe(knownIds).then(resolve)
     })
   })
 }
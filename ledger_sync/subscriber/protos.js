@@ -36,7 +36,9 @@ const compile = () => {
   return Promise.all([
     loadProtos('agent.proto', [
       'Agent',
-      'AgentContainer'
+      'AgentContainer',
+      'AgentActivityPage',
+      'AgentActivityPageContainer'
     ]),
     loadProtos('property.proto', [
       'Property',
@@ -45,15 +47,21 @@ const compile = () => {
       'PropertyPageContainer',
       'PropertySchema',
       'PropertyValue',
-      'Location'
+      'Location',
+      'ExcursionLog',
+      'ExcursionLogContainer'
     ]),
     loadProtos('proposal.proto', [
       'Proposal',
-      'ProposalContainer'
+      'ProposalContainer',
+      'ProposalHistoryPage',
+      'ProposalHistoryPageContainer'
     ]),
     loadProtos('record.proto', [
       'Record',
       'RecordContainer',
+      'RecordTimelinePage',
+      'RecordTimelinePageContainer',
       'RecordType',
       'RecordTypeContainer'
     ]),
@@ -66,7 +74,52 @@ const compile = () => {
       'UpdatePropertiesAction',
       'CreateProposalAction',
       'AnswerProposalAction',
-      'RevokeReporterAction'
+      'RevokeReporterAction',
+      'ExecuteTransferAction',
+      'MessageAction',
+      'SubscribeAction',
+      'UnsubscribeAction',
+      'CompositeAction',
+      'ReceiveConfirmationAction',
+      'AnchorStateAction',
+      'CloneRecordAction',
+      'AnchorFeedbackAction',
+      'MigrateStateAction',
+      'DeleteRecordTypeAction',
+      'AcknowledgeValueAction',
+      'UpdateRecordTypeAction',
+      'DirectTransferAction',
+      'TransferAuthorization',
+      'BulkUpdatePropertiesAction',
+      'DelegateReporterAction',
+      'MergeRecordsAction',
+      'ArchiveRecordAction',
+      'ReopenRecordAction',
+      'RecallRecordAction',
+      'SetAgentRoleAction',
+      'SetRecordMetadataAction',
+      'AttestAuditAction',
+      'CancelAllProposalsAction',
+      'AmendProposalAction',
+      'ReleaseEscrowAction',
+      'DeactivateAgentAction',
+      'CreateRecordTemplateAction',
+      'CreateRecordFromTemplateAction'
+    ]),
+    loadProtos('feedback.proto', [
+      'FeedbackLog',
+      'FeedbackLogContainer',
+      'FeedbackPage',
+      'FeedbackPageContainer'
+    ]),
+    loadProtos('message.proto', [
+      'MessageAnchor',
+      'MessageAnchorContainer'
+    ]),
+    loadProtos('subscription.proto', [
+      'Subscription',
+      'SubscriptionList',
+      'SubscriptionListContainer'
     ])
   ])
 }
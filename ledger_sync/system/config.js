@@ -39,5 +39,10 @@ initConfigValue('VALIDATOR_URL', 'tcp://localhost:4004')
 initConfigValue('DB_HOST', 'localhost')
 initConfigValue('DB_PORT', 28015)
 initConfigValue('DB_NAME', 'supply_chain')
+initConfigValue('CONTAINER_CACHE_SIZE', 1000)
+// The most state entries written to the database at once
+initConfigValue('WRITE_BATCH_SIZE', 100)
+initConfigValue('REST_API_URL', 'http://localhost:8008')
+initConfigValue('CATCH_UP', false)
 
 module.exports = config
@@ -0,0 +1,79 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const http = require('http')
+const { URL } = require('url')
+
+const config = require('./config')
+
+const PAGE_LIMIT = 100
+
+const getJson = path => {
+  return new Promise((resolve, reject) => {
+    const url = new URL(path, config.REST_API_URL)
+    http.get(url, res => {
+      let body = ''
+      res.on('data', chunk => { body += chunk })
+      res.on('end', () => {
+        if (res.statusCode !== 200) {
+          return reject(new Error(
+            `REST API responded to ${url.pathname} with ${res.statusCode}`))
+        }
+        try {
+          resolve(JSON.parse(body))
+        } catch (err) {
+          reject(err)
+        }
+      })
+    }).on('error', reject)
+  })
+}
+
+const formatBlock = block => ({
+  blockNum: parseInt(block.header.block_num),
+  blockId: block.header_signature,
+  stateRootHash: block.header.state_root_hash
+})
+
+const fetchHead = () => {
+  return getJson('/blocks?limit=1')
+    .then(({ data }) => formatBlock(data[0]))
+}
+
+const fetchBlock = blockId => {
+  return getJson(`/blocks/${blockId}`)
+    .then(({ data }) => formatBlock(data))
+}
+
+// Fetch one page of the state entries under an address prefix at a block,
+// starting from a paging position returned by a previous call
+const fetchStatePage = (block, address, start) => {
+  const query = [
+    `address=${address}`,
+    `head=${block.blockId}`,
+    `limit=${PAGE_LIMIT}`
+  ]
+  if (start) query.push(`start=${start}`)
+  return getJson(`/state?${query.join('&')}`)
+}
+
+module.exports = {
+  fetchHead,
+  fetchBlock,
+  fetchStatePage
+}
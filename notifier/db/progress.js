@@ -0,0 +1,45 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const r = require('rethinkdb')
+const db = require('./')
+
+const KNOWN_BLOCK_COUNT = 15
+
+// Records that every event in a block has been handed off for delivery
+const insert = ({ blockNum, blockId }) => {
+  return db.modifyTable('notifierProgress', progress => {
+    return progress.insert({ blockNum, blockId }, { conflict: 'replace' })
+  })
+}
+
+// The ids of the most recent processed blocks, newest first, suitable for
+// resuming an event subscription after a restart
+const lastKnownBlockIds = () => {
+  return db.queryTable('notifierProgress', progress => {
+    return progress
+      .orderBy({ index: r.desc('blockNum') })
+      .limit(KNOWN_BLOCK_COUNT)
+      .map(block => block('blockId'))
+  })
+}
+
+module.exports = {
+  insert,
+  lastKnownBlockIds
+}
@@ -0,0 +1,105 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const r = require('rethinkdb')
+const db = require('./')
+const config = require('../system/config')
+
+const RETRY_WAIT = config.RETRY_WAIT
+const SYNC_ATTEMPTS = parseInt(config.SYNC_ATTEMPTS)
+
+const wait = ms => new Promise(resolve => setTimeout(resolve, ms))
+
+const isLiveAt = blockNum => doc => {
+  return doc.startBlockNum <= blockNum && doc.endBlockNum > blockNum
+}
+
+// Property values are read from the tables kept by the ledger sync, which
+// may not have reached a block by the time its events arrive. Resolves
+// true once the block has been synced, or false if it never is.
+const awaitBlock = ({ blockNum, blockId }, attempt = 1) => {
+  return db.queryTable('blocks', blocks => blocks.get(blockNum), false)
+    .then(block => {
+      if (block && block.blockId === blockId) return true
+      if (attempt >= SYNC_ATTEMPTS) return false
+      return wait(RETRY_WAIT).then(() => awaitBlock({ blockNum, blockId },
+                                                    attempt + 1))
+    })
+}
+
+// The values a block reported for a NUMBER Property, oldest first, each
+// paired with the value before it. Values have the Property's exponent
+// applied. Resolves null if the Property is not a NUMBER.
+const fetchNumberUpdates = (recordId, name, blockNum) => {
+  const live = doc => doc('startBlockNum').le(blockNum)
+    .and(doc('endBlockNum').ge(blockNum))
+
+  const fetchProperty = db.queryTable('properties', properties => {
+    return properties
+      .getAll([name, recordId], { index: 'attributes' })
+      .filter(property => property('startBlockNum').le(blockNum)
+        .and(property('endBlockNum').gt(blockNum)))
+      .pluck('dataType', 'numberExponent')
+  })
+
+  // Pages as they were both before and after the block
+  const fetchPages = db.queryTable('propertyPages', pages => {
+    return pages
+      .between([name, recordId, r.minval], [name, recordId, r.maxval],
+               { index: 'attributes' })
+      .filter(live)
+      .pluck('startBlockNum', 'endBlockNum', 'reportedValues')
+  })
+
+  return Promise.all([fetchProperty, fetchPages])
+    .then(([properties, pages]) => {
+      const property = properties[0]
+      if (!property || property.dataType !== 'NUMBER') return null
+
+      const scale = Math.pow(10, property.numberExponent || 0)
+      const valuesAt = block => _.chain(pages)
+        .filter(isLiveAt(block))
+        .flatMap('reportedValues')
+        .sortBy('timestamp')
+        .value()
+      const key = reported => {
+        return [reported.timestamp, reported.reporterIndex,
+                reported.numberValue].join(':')
+      }
+
+      const before = valuesAt(blockNum - 1)
+      const seen = new Set(before.map(key))
+      const added = valuesAt(blockNum).filter(reported => !seen.has(key(reported)))
+
+      let previous = before.length > 0 ? _.last(before).numberValue : null
+      return added.map(reported => {
+        const update = {
+          previous: previous === null ? null : previous * scale,
+          value: reported.numberValue * scale
+        }
+        previous = reported.numberValue
+        return update
+      })
+    })
+}
+
+module.exports = {
+  awaitBlock,
+  fetchNumberUpdates
+}
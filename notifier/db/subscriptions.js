@@ -0,0 +1,54 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const r = require('rethinkdb')
+const db = require('./')
+
+// Finds every webhook that should receive an event, based on the current
+// on-chain subscriptions of each Agent. Each webhook is returned with the
+// subscriptions that matched, as the Agent may have filters for them.
+const findWebhooks = ({ name, recordId, recordType, owner, custodian, from }) => {
+  const event = name.toUpperCase()
+  const holders = [owner, custodian, from].filter(key => !!key)
+
+  return db.queryTable('subscriptions', subscriptions => {
+    return subscriptions
+      .filter({ endBlockNum: Number.MAX_SAFE_INTEGER })
+      .concatMap(list => list('subscriptions').merge({
+        agentId: list('agentId')
+      }))
+      .filter(sub => sub('events').contains(event))
+      .filter(sub => r.branch(
+        sub('scope').eq('RECORD'), sub('target').eq(recordId),
+        sub('scope').eq('RECORD_TYPE'), sub('target').eq(recordType),
+        sub('scope').eq('HELD_RECORDS'), r.expr(holders).contains(sub('agentId')),
+        false
+      ))
+      .eqJoin('agentId', r.table('webhooks'))
+      .group(pair => pair('right')('publicKey'))
+      .ungroup()
+      .map(group => group('reduction')(0)('right').merge({
+        matches: group('reduction').map(pair => pair('left').pluck(
+          'scope', 'target'))
+      }))
+  })
+}
+
+module.exports = {
+  findWebhooks
+}
@@ -0,0 +1,111 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const properties = require('../db/properties')
+
+// Events with a role attribute, which a filter's roles apply to
+const ROLE_EVENTS = ['transferred', 'proposal_created', 'proposal_answered']
+
+// Attributes which may repeat are strings when they only appear once
+const toArray = value => value === undefined ? [] : [].concat(value)
+
+// Whether a value moved past a bound, or is past it with nothing before it
+const crosses = ({ above, below }, { previous, value }) => {
+  if (_.isNumber(above) && value > above) {
+    return previous === null || previous <= above
+  }
+  if (_.isNumber(below) && value < below) {
+    return previous === null || previous >= below
+  }
+  return false
+}
+
+// Loads the updates to each NUMBER Property in an event at most once.
+// Resolves null if the values cannot be read, so that a delay in the
+// ledger sync never causes a notification to be dropped.
+const makeUpdateLoader = event => {
+  const synced = _.once(() => properties.awaitBlock(event))
+
+  return _.memoize(name => synced()
+    .then(isSynced => {
+      if (isSynced) {
+        return properties.fetchNumberUpdates(event.recordId, name,
+                                             event.blockNum)
+      }
+      console.warn(`Block ${event.blockNum} not synced, delivering`,
+                   `"${event.deliveryId}" without checking thresholds`)
+      return null
+    }))
+}
+
+const passesThresholds = (thresholds, event, loadUpdates) => {
+  const updated = toArray(event.property)
+  const relevant = thresholds.filter(t => updated.includes(t.property))
+
+  return Promise.all(relevant.map(threshold => {
+    return loadUpdates(threshold.property)
+      .then(updates => {
+        return updates === null ||
+          updates.some(update => crosses(threshold, update))
+      })
+  }))
+    .then(results => results.some(passed => passed))
+}
+
+// Checks an event against one of an Agent's filters. A filter only
+// narrows the events its subscription already matches:
+//   - recordTypes, if set, limits every event to Records of those types
+//   - roles, if set, limits events with a role to those roles
+//   - thresholds, if set, limits property_updated events to those where
+//     a listed NUMBER Property crossed one of its bounds
+const passes = (filter, event, loadUpdates) => {
+  if (filter.recordTypes && !filter.recordTypes.includes(event.recordType)) {
+    return Promise.resolve(false)
+  }
+
+  if (filter.roles && ROLE_EVENTS.includes(event.name) &&
+      !filter.roles.includes(event.role)) {
+    return Promise.resolve(false)
+  }
+
+  if (filter.thresholds && event.name === 'property_updated') {
+    return passesThresholds(filter.thresholds, event, loadUpdates)
+  }
+
+  return Promise.resolve(true)
+}
+
+// Narrows the webhooks whose subscriptions matched an event to those
+// with at least one matching subscription whose filter, if any, passes
+const select = (webhooks, event) => {
+  const loadUpdates = makeUpdateLoader(event)
+
+  return Promise.all(webhooks.map(webhook => {
+    return Promise.all(webhook.matches.map(match => {
+      const filter = _.find(webhook.filters, _.pick(match, 'scope', 'target'))
+      return filter ? passes(filter, event, loadUpdates) : true
+    }))
+      .then(results => results.some(passed => passed))
+  }))
+    .then(selected => webhooks.filter((webhook, i) => selected[i]))
+}
+
+module.exports = {
+  select
+}
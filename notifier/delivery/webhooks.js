@@ -0,0 +1,105 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const crypto = require('crypto')
+const http = require('http')
+const https = require('https')
+const { URL } = require('url')
+const config = require('../system/config')
+
+const ATTEMPTS = parseInt(config.DELIVERY_ATTEMPTS)
+const BACKOFF = parseInt(config.DELIVERY_BACKOFF)
+const TIMEOUT = parseInt(config.DELIVERY_TIMEOUT)
+
+const wait = ms => new Promise(resolve => setTimeout(resolve, ms))
+
+// Receivers verify deliveries by computing the same HMAC over the raw body
+const sign = (secret, body) => {
+  const hmac = crypto.createHmac('sha256', secret).update(body).digest('hex')
+  return `sha256=${hmac}`
+}
+
+// Server errors and rate limiting are worth retrying, other responses
+// indicate the receiver will never accept the delivery
+const isRetryable = status => status >= 500 || status === 429
+
+const post = (url, headers, body) => {
+  const target = new URL(url)
+  const transport = target.protocol === 'https:' ? https : http
+
+  return new Promise((resolve, reject) => {
+    const req = transport.request({
+      protocol: target.protocol,
+      hostname: target.hostname,
+      port: target.port,
+      path: target.pathname + target.search,
+      method: 'POST',
+      headers: Object.assign({
+        'Content-Type': 'application/json',
+        'Content-Length': Buffer.byteLength(body)
+      }, headers),
+      timeout: TIMEOUT
+    }, res => {
+      res.resume()
+      res.on('end', () => resolve(res.statusCode))
+    })
+
+    req.on('timeout', () => req.abort())
+    req.on('error', reject)
+    req.end(body)
+  })
+}
+
+// Delivers an event to a single webhook, retrying with an exponential
+// backoff. Never rejects, failed deliveries are logged and dropped.
+const deliver = (webhook, event) => {
+  const body = JSON.stringify(event)
+  const headers = {
+    'X-Supply-Chain-Event': event.name,
+    'X-Supply-Chain-Delivery': event.deliveryId,
+    'X-Supply-Chain-Signature': sign(webhook.secret, body)
+  }
+
+  const attempt = count => {
+    return post(webhook.url, headers, body)
+      .then(status => {
+        if (status < 300) return
+        if (!isRetryable(status)) {
+          throw Object.assign(new Error(`Rejected with status ${status}`),
+                              { final: true })
+        }
+        throw new Error(`Failed with status ${status}`)
+      })
+      .catch(err => {
+        if (err.final || count >= ATTEMPTS) {
+          console.warn(`Unable to deliver "${event.deliveryId}" to `,
+                       `"${webhook.url}": ${err.message}`)
+          return
+        }
+        return wait(BACKOFF * Math.pow(2, count - 1))
+          .then(() => attempt(count + 1))
+      })
+  }
+
+  return attempt(1)
+}
+
+module.exports = {
+  sign,
+  deliver
+}
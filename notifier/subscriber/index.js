@@ -0,0 +1,152 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const { Stream } = require('sawtooth-sdk/messaging/stream')
+const {
+  Message,
+  EventList,
+  EventSubscription,
+  ClientEventsSubscribeRequest,
+  ClientEventsSubscribeResponse
+} = require('sawtooth-sdk/protobuf')
+
+const progress = require('../db/progress')
+const subscriptions = require('../db/subscriptions')
+const filters = require('../delivery/filters')
+const webhooks = require('../delivery/webhooks')
+const config = require('../system/config')
+
+const EVENT_PREFIX = 'supply_chain/'
+const EVENT_NAMES = [
+  'property_updated',
+  'proposal_created',
+  'proposal_answered',
+  'proposal_amended',
+  'transferred',
+  'finalized',
+  'merged',
+  'archived',
+  'reopen_requested',
+  'reopened',
+  'recalled',
+  'escrow_released',
+  'audit_attested',
+  'excursion',
+  'receipt_confirmed',
+  'value_acknowledged'
+]
+const VALIDATOR_URL = config.VALIDATOR_URL
+const stream = new Stream(VALIDATOR_URL)
+
+// Collects event attributes into an object with camelCase keys,
+// attributes which repeat (like updated properties) become arrays
+const getAttributes = event => {
+  return _.chain(event.attributes)
+    .groupBy('key')
+    .mapKeys((attrs, key) => _.camelCase(key))
+    .mapValues(attrs => attrs.length > 1 ? _.map(attrs, 'value') : attrs[0].value)
+    .value()
+}
+
+const getBlock = events => {
+  const block = getAttributes(
+    _.find(events, e => e.eventType === 'sawtooth/block-commit'))
+
+  return {
+    blockNum: parseInt(block.blockNum),
+    blockId: block.blockId
+  }
+}
+
+// Events are identified by their block and position within it, so a
+// receiver can recognize redelivered events
+const getSupplyChainEvents = (events, block) => {
+  return events
+    .filter(e => e.eventType.startsWith(EVENT_PREFIX))
+    .map((e, index) => _.assign({
+      name: e.eventType.slice(EVENT_PREFIX.length),
+      deliveryId: `${block.blockId}-${index}`
+    }, block, getAttributes(e)))
+}
+
+const notify = event => {
+  return subscriptions.findWebhooks(event)
+    .then(hooks => filters.select(hooks, event))
+    .then(hooks => Promise.all(hooks.map(hook => {
+      return webhooks.deliver(hook, event)
+    })))
+}
+
+// Handle event message received by stream
+const handleEvent = msg => {
+  if (msg.messageType === Message.MessageType.CLIENT_EVENTS) {
+    const events = EventList.decode(msg.content).events
+    const block = getBlock(events)
+
+    Promise.all(getSupplyChainEvents(events, block).map(notify))
+      .then(() => progress.insert(block))
+      .catch(err => {
+        console.error(`Unable to notify for block ${block.blockNum}:`,
+                      err.message)
+      })
+  } else {
+    console.warn('Received message of unknown type:', msg.messageType)
+  }
+}
+
+const subscribe = lastKnownBlockIds => {
+  const blockSub = EventSubscription.create({
+    eventType: 'sawtooth/block-commit'
+  })
+  const supplyChainSubs = EVENT_NAMES.map(name => {
+    return EventSubscription.create({ eventType: EVENT_PREFIX + name })
+  })
+
+  return stream.send(
+    Message.MessageType.CLIENT_EVENTS_SUBSCRIBE_REQUEST,
+    ClientEventsSubscribeRequest.encode({
+      lastKnownBlockIds,
+      subscriptions: [blockSub].concat(supplyChainSubs)
+    }).finish()
+  )
+    .then(response => ClientEventsSubscribeResponse.decode(response))
+    .then(decoded => {
+      const status = _.findKey(ClientEventsSubscribeResponse.Status,
+                               val => val === decoded.status)
+      if (status !== 'OK') {
+        throw new Error(`Validator responded with status "${status}"`)
+      }
+    })
+}
+
+// Start stream and subscribe to events, resuming after the last processed
+// block if there is one, otherwise from the current chain head
+const start = () => {
+  return progress.lastKnownBlockIds()
+    .then(knownIds => new Promise(resolve => {
+      stream.connect(() => {
+        stream.onReceive(handleEvent)
+        subscribe(knownIds).then(resolve)
+      })
+    }))
+}
+
+module.exports = {
+  start
+}
@@ -0,0 +1,47 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const loadConfig = (defaultValue = {}) => {
+  try {
+    return require('../config.json')
+  } catch (err) {
+    // Throw error on bad JSON, otherwise ignore
+    if (err instanceof SyntaxError) throw err
+    return {}
+  }
+}
+
+const config = loadConfig()
+
+const initConfigValue = (key, defaultValue = null) => {
+  config[key] = process.env[key] || config[key] || defaultValue
+}
+
+// Setup non-sensitive config variable with sensible defaults,
+// if not set in environment variables or config.json
+initConfigValue('RETRY_WAIT', 5000)
+initConfigValue('VALIDATOR_URL', 'tcp://localhost:4004')
+initConfigValue('DB_HOST', 'localhost')
+initConfigValue('DB_PORT', 28015)
+initConfigValue('DB_NAME', 'supply_chain')
+initConfigValue('DELIVERY_ATTEMPTS', 5)
+initConfigValue('DELIVERY_BACKOFF', 1000)
+initConfigValue('DELIVERY_TIMEOUT', 10000)
+initConfigValue('SYNC_ATTEMPTS', 12)
+
+module.exports = config
@@ -13,14 +13,29 @@
 // limitations under the License.
 
 use crypto::digest::Digest;
-use crypto::sha2::Sha512;
+use crypto::sha2::{Sha256, Sha512};
 
 const FAMILY_NAME: &str = "supply_chain";
 const AGENT: &str = "ae";
+const AGENT_ACTIVITY: &str = "a0";
+const EXCURSION_LOG: &str = "ac";
+const FEEDBACK: &str = "a4";
+const MESSAGE: &str = "ad";
 const PROPERTY: &str = "ea";
 const PROPOSAL: &str = "aa";
+const PROPOSAL_HISTORY: &str = "ab";
+const PROPOSAL_INDEX: &str = "a2";
 const RECORD: &str = "ec";
+const RECORD_TEMPLATE: &str = "a6";
+const RECORD_TIMELINE: &str = "ed";
 const RECORD_TYPE: &str = "ee";
+const RECORD_TYPE_STATS: &str = "a3";
+const RECORD_TYPE_VERSION: &str = "a5";
+const STATE_ANCHOR: &str = "a1";
+const SUBSCRIPTION: &str = "af";
+
+const SETTINGS_PREFIX: &str = "000000";
+const SETTINGS_KEY_PARTS: usize = 4;
 
 pub fn get_supply_chain_prefix() -> String {
     let mut sha = Sha512::new();
@@ -39,18 +54,97 @@ pub fn hash(to_hash: &str, num: usize) -> String {
     hash.to_string()
 }
 
+fn hash_settings_part(part: &str) -> String {
+    let mut sha = Sha256::new();
+    sha.input_str(part);
+    sha.result_str()[..16].to_string()
+}
+
+/// Computes the address of an on-chain setting in the sawtooth_settings
+/// namespace, splitting the key on dots into at most four parts
+pub fn make_setting_address(key: &str) -> String {
+    let mut parts: Vec<&str> = key.splitn(SETTINGS_KEY_PARTS, '.').collect();
+    while parts.len() < SETTINGS_KEY_PARTS {
+        parts.push("");
+    }
+    parts.iter().fold(String::from(SETTINGS_PREFIX), |address, part| {
+        address + &hash_settings_part(part)
+    })
+}
+
 pub fn make_agent_address(identifier: &str) -> String {
     get_supply_chain_prefix() + &AGENT + &hash(identifier, 62)
 }
 
+pub fn make_agent_activity_address(agent_id: &str, page: u32) -> String {
+    make_agent_activity_address_range(agent_id) + &num_to_page_number(page)
+}
+
+pub fn make_agent_activity_address_range(agent_id: &str) -> String {
+    get_supply_chain_prefix() + AGENT_ACTIVITY + &hash(agent_id, 58)
+}
+
+pub fn make_subscription_address(agent_id: &str) -> String {
+    get_supply_chain_prefix() + SUBSCRIPTION + &hash(agent_id, 62)
+}
+
+pub fn make_message_address(record_id: &str, digest: &str) -> String {
+    make_message_address_range(record_id) + &hash(digest, 26)
+}
+
+pub fn make_message_address_range(record_id: &str) -> String {
+    get_supply_chain_prefix() + MESSAGE + &hash(record_id, 36)
+}
+
+pub fn make_feedback_log_address(record_id: &str) -> String {
+    make_feedback_address(record_id, 0)
+}
+
+pub fn make_feedback_address(record_id: &str, page: u32) -> String {
+    make_feedback_address_range(record_id) + &num_to_page_number(page)
+}
+
+pub fn make_feedback_address_range(record_id: &str) -> String {
+    get_supply_chain_prefix() + FEEDBACK + &hash(record_id, 58)
+}
+
+pub fn make_state_anchor_address(service: &str, block_num: u64) -> String {
+    get_supply_chain_prefix() + STATE_ANCHOR + &hash(service, 46) + &format!("{:016x}", block_num)
+}
+
 pub fn make_record_address(record_id: &str) -> String {
     get_supply_chain_prefix() + &RECORD + &hash(record_id, 62)
 }
 
+pub fn make_record_timeline_address(record_id: &str, page: u32) -> String {
+    make_record_timeline_address_range(record_id) + &num_to_page_number(page)
+}
+
+pub fn make_record_timeline_address_range(record_id: &str) -> String {
+    get_supply_chain_prefix() + RECORD_TIMELINE + &hash(record_id, 58)
+}
+
+pub fn make_record_template_address(name: &str) -> String {
+    get_supply_chain_prefix() + RECORD_TEMPLATE + &hash(name, 62)
+}
+
 pub fn make_record_type_address(type_name: &str) -> String {
     get_supply_chain_prefix() + &RECORD_TYPE + &hash(type_name, 62)
 }
 
+pub fn make_record_type_version_address(type_name: &str, version: u32) -> String {
+    get_supply_chain_prefix() + RECORD_TYPE_VERSION + &hash(type_name, 54)
+        + &format!("{:08x}", version)
+}
+
+pub fn make_record_type_stats_address(type_name: &str, day: u64) -> String {
+    make_record_type_stats_address_range(type_name) + &format!("{:08x}", day)
+}
+
+pub fn make_record_type_stats_address_range(type_name: &str) -> String {
+    get_supply_chain_prefix() + RECORD_TYPE_STATS + &hash(type_name, 54)
+}
+
 pub fn make_property_address(record_id: &str, property_name: &str, page: u32) -> String {
     make_property_address_range(record_id) + &hash(property_name, 22) + &num_to_page_number(page)
 }
@@ -59,6 +153,10 @@ pub fn make_property_address_range(record_id: &str) -> String {
     get_supply_chain_prefix() + &PROPERTY + &hash(record_id, 36)
 }
 
+pub fn make_excursion_log_address(record_id: &str, property_name: &str) -> String {
+    get_supply_chain_prefix() + EXCURSION_LOG + &hash(record_id, 36) + &hash(property_name, 26)
+}
+
 pub fn num_to_page_number(page: u32) -> String {
     format!("{:01$x}", page, 4)
 }
@@ -66,3 +164,12 @@ pub fn num_to_page_number(page: u32) -> String {
 pub fn make_proposal_address(record_id: &str, agent_id: &str) -> String {
     get_supply_chain_prefix() + PROPOSAL + &hash(record_id, 36) + &hash(agent_id, 26)
 }
+
+pub fn make_proposal_history_address(record_id: &str, agent_id: &str, page: u32) -> String {
+    get_supply_chain_prefix() + PROPOSAL_HISTORY + &hash(record_id, 36) + &hash(agent_id, 22)
+        + &num_to_page_number(page)
+}
+
+pub fn make_proposal_index_address(record_id: &str) -> String {
+    get_supply_chain_prefix() + PROPOSAL_INDEX + &hash(record_id, 62)
+}
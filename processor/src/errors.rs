@@ -0,0 +1,171 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Codes for invalid transactions.
+//!
+//! `ApplyError` only carries a string, so the code of an invalid
+//! transaction travels at the front of its message, as `[CODE] message`.
+//! The handler moves it into the context appended to every rejection, so
+//! the message a client receives reads `message (code: CODE, ...)`. The
+//! English message is what is logged; clients showing errors in another
+//! language look the code up in the API server's message catalog instead.
+//!
+//! Codes are part of the API: once released, one must not be renamed or
+//! given a different meaning.
+
+use sawtooth_sdk::processor::handler::ApplyError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    MalformedPayload,
+    InvalidTimestamp,
+    ActionDisabled,
+    InvalidComposite,
+    MissingField,
+    InvalidFormat,
+    AgentExists,
+    AgentNotFound,
+    AgentDeactivated,
+    RecordTypeExists,
+    RecordTypeNotFound,
+    RecordTypeInUse,
+    RecordTemplateExists,
+    RecordTemplateNotFound,
+    RecordExists,
+    RecordNotFound,
+    RecordFinal,
+    RecordNotFinal,
+    RecordArchived,
+    RecordRecalled,
+    RecordInEscrow,
+    PropertyNotFound,
+    NamespaceNotRegistered,
+    NotNamespaceMember,
+    InvalidSchema,
+    InvalidValue,
+    PropertyImmutable,
+    UnacknowledgedValues,
+    NothingToAcknowledge,
+    IncompleteRecord,
+    RuleFailed,
+    PluginRejected,
+    NotAuthorized,
+    LimitReached,
+    ProposalExists,
+    ProposalNotFound,
+    InvalidProposal,
+    InvalidRevocation,
+    Duplicate,
+    SubscriptionNotFound,
+    InvalidSubscription,
+    InvalidRecipient,
+    InconsistentState,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Code::MalformedPayload => "MALFORMED_PAYLOAD",
+            Code::InvalidTimestamp => "INVALID_TIMESTAMP",
+            Code::ActionDisabled => "ACTION_DISABLED",
+            Code::InvalidComposite => "INVALID_COMPOSITE",
+            Code::MissingField => "MISSING_FIELD",
+            Code::InvalidFormat => "INVALID_FORMAT",
+            Code::AgentExists => "AGENT_EXISTS",
+            Code::AgentNotFound => "AGENT_NOT_FOUND",
+            Code::AgentDeactivated => "AGENT_DEACTIVATED",
+            Code::RecordTypeExists => "RECORD_TYPE_EXISTS",
+            Code::RecordTypeNotFound => "RECORD_TYPE_NOT_FOUND",
+            Code::RecordTypeInUse => "RECORD_TYPE_IN_USE",
+            Code::RecordTemplateExists => "RECORD_TEMPLATE_EXISTS",
+            Code::RecordTemplateNotFound => "RECORD_TEMPLATE_NOT_FOUND",
+            Code::RecordExists => "RECORD_EXISTS",
+            Code::RecordNotFound => "RECORD_NOT_FOUND",
+            Code::RecordFinal => "RECORD_FINAL",
+            Code::RecordNotFinal => "RECORD_NOT_FINAL",
+            Code::RecordArchived => "RECORD_ARCHIVED",
+            Code::RecordRecalled => "RECORD_RECALLED",
+            Code::RecordInEscrow => "RECORD_IN_ESCROW",
+            Code::PropertyNotFound => "PROPERTY_NOT_FOUND",
+            Code::NamespaceNotRegistered => "NAMESPACE_NOT_REGISTERED",
+            Code::NotNamespaceMember => "NOT_NAMESPACE_MEMBER",
+            Code::InvalidSchema => "INVALID_SCHEMA",
+            Code::InvalidValue => "INVALID_VALUE",
+            Code::PropertyImmutable => "PROPERTY_IMMUTABLE",
+            Code::UnacknowledgedValues => "UNACKNOWLEDGED_VALUES",
+            Code::NothingToAcknowledge => "NOTHING_TO_ACKNOWLEDGE",
+            Code::IncompleteRecord => "INCOMPLETE_RECORD",
+            Code::RuleFailed => "RULE_FAILED",
+            Code::PluginRejected => "PLUGIN_REJECTED",
+            Code::NotAuthorized => "NOT_AUTHORIZED",
+            Code::LimitReached => "LIMIT_REACHED",
+            Code::ProposalExists => "PROPOSAL_EXISTS",
+            Code::ProposalNotFound => "PROPOSAL_NOT_FOUND",
+            Code::InvalidProposal => "INVALID_PROPOSAL",
+            Code::InvalidRevocation => "INVALID_REVOCATION",
+            Code::Duplicate => "DUPLICATE",
+            Code::SubscriptionNotFound => "SUBSCRIPTION_NOT_FOUND",
+            Code::InvalidSubscription => "INVALID_SUBSCRIPTION",
+            Code::InvalidRecipient => "INVALID_RECIPIENT",
+            Code::InconsistentState => "INCONSISTENT_STATE",
+        }
+    }
+}
+
+/// An invalid transaction error with a code
+pub fn invalid<S: Into<String>>(code: Code, message: S) -> ApplyError {
+    ApplyError::InvalidTransaction(format!("[{}] {}", code.as_str(), message.into()))
+}
+
+/// Splits the code, if there is one, from the front of an invalid
+/// transaction's message
+pub fn split_code(message: &str) -> (Option<&str>, &str) {
+    if message.starts_with('[') {
+        if let Some(end) = message.find("] ") {
+            let code = &message[1..end];
+            if !code.is_empty()
+                && code.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+            {
+                return (Some(code), &message[end + 2..]);
+            }
+        }
+    }
+    (None, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_round_trips_through_message() {
+        match invalid(Code::RecordNotFound, "Record does not exist: fish") {
+            ApplyError::InvalidTransaction(msg) => {
+                assert_eq!(msg, "[RECORD_NOT_FOUND] Record does not exist: fish");
+                assert_eq!(
+                    split_code(&msg),
+                    (Some("RECORD_NOT_FOUND"), "Record does not exist: fish")
+                );
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn messages_without_codes_are_unchanged() {
+        assert_eq!(split_code("Rejected"), (None, "Rejected"));
+        assert_eq!(split_code("[not a code] x"), (None, "[not a code] x"));
+        assert_eq!(split_code("[RECORD_FINAL]x"), (None, "[RECORD_FINAL]x"));
+    }
+}
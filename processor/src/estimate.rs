@@ -0,0 +1,294 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dry-run cost estimates of payloads.
+//!
+//! The `estimate` subcommand applies a payload to a snapshot of state,
+//! counting the state reads and writes it makes and the bytes they
+//! carry, without a validator. Integrators can use it to catch a payload
+//! which would be slow or large on the network, such as a record of a
+//! type with hundreds of properties, before submitting it.
+//!
+//! Snapshots are the validator REST API's `/state` responses, so one can
+//! be taken with `curl <rest api>/state?address=3400de`. Addresses the
+//! snapshot does not hold read as empty.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+use rustc_serialize::base64::FromBase64;
+use rustc_serialize::json::Json;
+
+use protobuf::RepeatedField;
+
+use sawtooth_sdk::messages::processor::TpProcessRequest;
+use sawtooth_sdk::messages::transaction::TransactionHeader;
+use sawtooth_sdk::processor::handler::{ApplyError, ContextError};
+
+use addressing::get_supply_chain_prefix;
+use handler::{setting_addresses, SupplyChainTransactionHandler};
+use replay::StateContext;
+
+/// What applying a payload cost
+#[derive(Debug, Default, PartialEq)]
+pub struct Estimate {
+    /// Reads sent to the validator, including repeated reads of an address
+    pub reads: usize,
+    /// Distinct addresses read
+    pub addresses_read: usize,
+    pub bytes_read: usize,
+    /// Distinct addresses written
+    pub writes: usize,
+    pub bytes_written: usize,
+    pub events: usize,
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "reads:  {} ({} addresses, {} bytes)",
+            self.reads, self.addresses_read, self.bytes_read
+        )?;
+        writeln!(
+            f,
+            "writes: {} addresses, {} bytes",
+            self.writes, self.bytes_written
+        )?;
+        write!(f, "events: {}", self.events)
+    }
+}
+
+/// Serves reads from a snapshot, seeing the payload's own writes, and
+/// counts what is read and written
+struct EstimatingContext {
+    state: HashMap<String, Vec<u8>>,
+    read: BTreeSet<String>,
+    written: HashMap<String, usize>,
+    estimate: Estimate,
+}
+
+impl StateContext for EstimatingContext {
+    fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+        let data = addresses
+            .first()
+            .and_then(|address| self.state.get(address))
+            .filter(|data| !data.is_empty())
+            .cloned();
+
+        self.estimate.reads += 1;
+        self.estimate.bytes_read += data.as_ref().map_or(0, |data| data.len());
+        self.read.extend(addresses.into_iter().take(1));
+        Ok(data)
+    }
+
+    fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+        // Only the last write to an address reaches the block
+        for (address, data) in entries {
+            self.written.insert(address.clone(), data.len());
+            self.state.insert(address, data);
+        }
+        Ok(())
+    }
+
+    fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+        // A deletion reaches the block as a change to the address
+        for address in addresses {
+            self.written.insert(address.clone(), 0);
+            self.state.remove(&address);
+        }
+        Ok(())
+    }
+
+    fn add_event(
+        &mut self,
+        _event_type: String,
+        _attributes: Vec<(String, String)>,
+        _data: &[u8],
+    ) -> Result<(), ContextError> {
+        self.estimate.events += 1;
+        Ok(())
+    }
+
+    fn add_receipt_data(&mut self, _data: &[u8]) -> Result<(), ContextError> {
+        Ok(())
+    }
+}
+
+/// Applies a request to a snapshot of state. If the transaction is
+/// invalid, the estimate only covers what was done before the handler
+/// rejected it.
+pub fn estimate(
+    handler: &SupplyChainTransactionHandler,
+    request: &TpProcessRequest,
+    state: HashMap<String, Vec<u8>>,
+) -> (Estimate, Result<(), ApplyError>) {
+    let mut context = EstimatingContext {
+        state: state,
+        read: BTreeSet::new(),
+        written: HashMap::new(),
+        estimate: Estimate::default(),
+    };
+    let result = handler.process(request, &mut context);
+
+    let mut estimate = context.estimate;
+    estimate.addresses_read = context.read.len();
+    estimate.writes = context.written.len();
+    estimate.bytes_written = context.written.values().sum();
+    (estimate, result)
+}
+
+/// Builds a header for estimating a payload, declaring the supply_chain
+/// namespace and the settings the handler reads, as a client would
+pub fn make_header(signer: &str) -> TransactionHeader {
+    let mut inputs = vec![get_supply_chain_prefix()];
+    inputs.extend(setting_addresses());
+    let mut header = TransactionHeader::new();
+    header.set_signer_public_key(signer.to_string());
+    header.set_inputs(RepeatedField::from_vec(inputs));
+    header.set_outputs(RepeatedField::from_vec(vec![get_supply_chain_prefix()]));
+    header
+}
+
+/// Reads a snapshot of state from a REST API `/state` response
+pub fn load_snapshot(path: &str) -> Result<HashMap<String, Vec<u8>>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|err| format!("Cannot read {}: {}", path, err))?;
+    let json = Json::from_str(&text).map_err(|err| format!("Cannot parse {}: {}", path, err))?;
+
+    let entries = json
+        .find("data")
+        .and_then(|data| data.as_array())
+        .ok_or_else(|| format!("{} is not a /state response", path))?;
+    entries
+        .iter()
+        .map(|entry| {
+            let field = |name| entry.find(name).and_then(|value| value.as_string());
+            match (field("address"), field("data")) {
+                (Some(address), Some(data)) => data
+                    .from_base64()
+                    .map(|data| (address.to_string(), data))
+                    .map_err(|err| format!("Bad data for {} in {}: {}", address, path, err)),
+                _ => Err(format!("{} has an entry without an address and data", path)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use addressing::{make_agent_address, make_setting_address};
+    use log;
+    use messages::payload;
+    use protobuf::{Message, RepeatedField};
+    use sawtooth_sdk::messages::transaction::TransactionHeader;
+
+    fn make_request(signer: &str, payload: &payload::SCPayload) -> TpProcessRequest {
+        let mut header = TransactionHeader::new();
+        header.set_signer_public_key(signer.to_string());
+        header.set_inputs(RepeatedField::from_vec(vec![String::from("in")]));
+        header.set_outputs(RepeatedField::from_vec(vec![String::from("out")]));
+
+        let mut request = TpProcessRequest::new();
+        request.set_header(header);
+        request.set_payload(payload.write_to_bytes().unwrap());
+        request.set_signature(String::from("signature"));
+        request
+    }
+
+    fn make_agent_payload(name: &str) -> payload::SCPayload {
+        let mut action = payload::CreateAgentAction::new();
+        action.set_name(name.to_string());
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::CREATE_AGENT);
+        payload.set_create_agent(action);
+        payload.set_timestamp(1000);
+        payload
+    }
+
+    #[test]
+    fn counts_reads_and_writes() {
+        let handler = SupplyChainTransactionHandler::new();
+        let request = make_request("alice", &make_agent_payload("Alice"));
+
+        let (estimate, result) = estimate(&handler, &request, HashMap::new());
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(estimate.reads >= estimate.addresses_read);
+        assert!(estimate.writes > 0);
+        assert!(estimate.bytes_written > 0);
+    }
+
+    #[test]
+    fn reads_come_from_the_snapshot() {
+        let handler = SupplyChainTransactionHandler::new();
+        let request = make_request("alice", &make_agent_payload("Alice"));
+
+        // Creating the agent again fails, having read the existing one
+        let mut context = EstimatingContext {
+            state: HashMap::new(),
+            read: BTreeSet::new(),
+            written: HashMap::new(),
+            estimate: Estimate::default(),
+        };
+        handler.process(&request, &mut context).unwrap();
+        let address = make_agent_address("alice");
+        let snapshot = context.state;
+        let stored = snapshot[&address].len();
+
+        let (estimate, result) = estimate(&handler, &request, snapshot);
+        assert!(result.is_err());
+        assert!(estimate.bytes_read >= stored);
+        assert_eq!(estimate.writes, 0);
+    }
+
+    struct DiscardLogger;
+
+    impl log::Log for DiscardLogger {
+        fn enabled(&self, _metadata: &log::LogMetadata) -> bool {
+            true
+        }
+
+        fn log(&self, _record: &log::LogRecord) {}
+    }
+
+    #[test]
+    fn estimates_with_any_header() {
+        // Logging as under -v, so that the handler's logged details of
+        // the request are worked out
+        let _ = log::set_logger(|max_level| {
+            max_level.set(log::LogLevelFilter::Info);
+            Box::new(DiscardLogger)
+        });
+        let handler = SupplyChainTransactionHandler::new();
+        let payload = make_agent_payload("Alice");
+
+        let mut request = TpProcessRequest::new();
+        request.set_header(make_header("alice"));
+        request.set_payload(payload.write_to_bytes().unwrap());
+        let header = request.get_header();
+        assert_eq!(header.get_outputs(), &[get_supply_chain_prefix()]);
+        assert!(header.get_inputs().contains(&make_setting_address("supply_chain.agent.admins")));
+        let (_, result) = estimate(&handler, &request, HashMap::new());
+        assert!(result.is_ok(), "{:?}", result);
+
+        request.set_header(TransactionHeader::new());
+        let (_, result) = estimate(&handler, &request, HashMap::new());
+        assert!(result.is_ok(), "{:?}", result);
+    }
+}
@@ -13,20 +13,177 @@
 // limitations under the License.
 
 use protobuf;
+use protobuf::error::{ProtobufError, ProtobufResult, WireError};
+use protobuf::wire_format::WireType;
+use protobuf::CodedInputStream;
 use protobuf::Message;
+use protobuf::ProtobufEnum;
 use protobuf::RepeatedField;
 
-use std::collections::HashMap;
+use crypto::digest::Digest;
+use crypto::sha2::Sha512;
+
+use std::any::Any;
+use std::cmp;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
 
 use sawtooth_sdk::processor::handler::ApplyError;
 use sawtooth_sdk::processor::handler::TransactionContext;
 use sawtooth_sdk::processor::handler::TransactionHandler;
 use sawtooth_sdk::messages::processor::TpProcessRequest;
+use sawtooth_sdk::messages::setting::Setting;
+use sawtooth_sdk::signing::Context;
+use sawtooth_sdk::signing::secp256k1::{Secp256k1Context, Secp256k1PublicKey};
 
 use messages::*;
 use addressing::*;
+use errors::{invalid, split_code, Code};
+use identifiers;
+use locations;
+use patterns::Pattern;
+use plugins::ValidationPlugin;
+use replay::{RecordingContext, ReplayLog, StateContext};
+use rules;
+use staging::StagedContext;
+use units;
+use values;
 
 const PROPERTY_PAGE_MAX_LENGTH: usize = 256;
+const PROPOSAL_HISTORY_PAGE_MAX_LENGTH: usize = 256;
+const RECORD_TIMELINE_PAGE_MAX_LENGTH: usize = 256;
+const EXCURSION_LOG_MAX_LENGTH: usize = 256;
+const AGENT_ACTIVITY_PAGE_MAX_LENGTH: usize = 256;
+const FEEDBACK_PAGE_MAX_LENGTH: usize = 256;
+// Feedback pages form a smaller ring than other pages, so a Record keeps
+// at most 16,384 entries however often it is scanned
+const FEEDBACK_MAX_PAGES: u32 = 64;
+
+const MAX_PROPERTIES_SETTING: &str = "supply_chain.record_type.max_properties";
+const DEFAULT_MAX_PROPERTIES: usize = 100;
+
+// Followed by a namespace, a comma-separated list of the public keys of
+// the Agents allowed to create record types in it
+const RECORD_TYPE_NAMESPACE_SETTING: &str = "supply_chain.record_type.namespace.";
+
+// Limits on a PropertySchema's enum options, struct properties, and array
+// elements, which apply at each level of nested structs and arrays
+const MAX_ENUM_OPTIONS: usize = 256;
+const MAX_STRUCT_PROPERTIES: usize = 64;
+const MAX_ARRAY_LENGTH: usize = 256;
+
+// The largest number_exponent either way. 10^18 is the largest power of
+// ten an i64 can hold.
+const MAX_NUMBER_EXPONENT: i32 = 18;
+
+const MAX_REPORTERS_SETTING: &str = "supply_chain.property.max_reporters";
+const DEFAULT_MAX_REPORTERS: usize = 64;
+
+// The most Agents a PropertySchema may allow to report its values
+const MAX_ALLOWED_REPORTERS: usize = 256;
+
+// The most open reporter Proposals an issuer may have for one Record at
+// a time, across every receiving Agent
+const MAX_OPEN_PROPOSALS_SETTING: &str = "supply_chain.proposal.max_open";
+const DEFAULT_MAX_OPEN_PROPOSALS: usize = 16;
+
+// The most Agents a Proposal may need the approval of besides its
+// receiving Agent
+const MAX_PROPOSAL_APPROVERS: usize = 8;
+
+// The most receiving Agents a CancelAllProposals may name besides those
+// in the open Proposal index
+const MAX_CANCELED_RECEIVING_AGENTS: usize = 64;
+
+// The rules of Incoterms 2020 that transfer terms may name
+const INCOTERMS: &[&str] = &[
+    "EXW", "FCA", "CPT", "CIP", "DAP", "DPU", "DDP", "FAS", "FOB", "CFR", "CIF",
+];
+
+const MAX_SUBSCRIPTIONS: usize = 256;
+
+// A comma-separated list of payload actions, such as "COMPOSITE", which
+// the network does not accept
+const DISABLED_ACTIONS_SETTING: &str = "supply_chain.actions.disabled";
+
+const MAX_COMPOSITE_ACTIONS: usize = 32;
+
+const MAX_BULK_UPDATE_RECORDS: usize = 256;
+
+// The most Records one MergeRecords may combine
+const MAX_MERGE_RECORDS: usize = 64;
+
+// The most times a reporter authorization may be delegated onward
+const MAX_DELEGATION_DEPTH: usize = 8;
+
+// How long a request to reopen a final Record waits for the other of its
+// owner and custodian to agree, in seconds
+const REOPEN_REQUEST_SECONDS: u64 = SECONDS_PER_DAY;
+
+// The most Records one RecallRecord may recall down a line of merges
+const MAX_RECALL_DESCENDANTS: usize = 64;
+
+// Limits on the metadata kept with a Record, with lengths in bytes
+const MAX_METADATA_ENTRIES: usize = 32;
+const MAX_METADATA_KEY_LENGTH: usize = 64;
+const MAX_METADATA_VALUE_LENGTH: usize = 256;
+
+// The most auditors whose attestations one Record keeps
+const MAX_RECORD_AUDITORS: usize = 16;
+
+// A comma-separated list of the public keys allowed to record state anchors
+const ANCHOR_SIGNERS_SETTING: &str = "supply_chain.anchor.signers";
+
+// A comma-separated list of the public keys of the kiosk Agents allowed
+// to anchor consumer scans and feedback
+const FEEDBACK_KIOSKS_SETTING: &str = "supply_chain.feedback.kiosks";
+
+// A comma-separated list of the public keys allowed to rewrite state when
+// migrating it to a new addressing scheme
+const MIGRATION_SIGNERS_SETTING: &str = "supply_chain.migration.signers";
+
+// A comma-separated list of the public keys allowed to set Agents' roles.
+// Agents' roles are only enforced once this is set.
+const AGENT_ADMINS_SETTING: &str = "supply_chain.agent.admins";
+
+const MAX_MIGRATION_WRITES: usize = 64;
+
+// The unit of payload timestamps, "seconds" (the default) or "milliseconds"
+const TIMESTAMP_UNIT_SETTING: &str = "supply_chain.timestamp.unit";
+
+// Timestamps from here on are too late to be in seconds (the year 2286),
+// and earlier ones too early to be in milliseconds (April 1970)
+const TIMESTAMP_UNIT_BOUNDARY: u64 = 10_000_000_000;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// TIMESTAMP values more than about a century after the transaction are
+// taken to be mistakes, such as a date in the wrong unit
+const MAX_TIMESTAMP_VALUE_AHEAD: u64 = 100 * 365 * SECONDS_PER_DAY;
+
+// The settings read by a fixed key. shared/setting_addresses.js lists
+// the same keys for the server and clients.
+const FIXED_SETTINGS: &[&str] = &[
+    MAX_PROPERTIES_SETTING,
+    MAX_REPORTERS_SETTING,
+    MAX_OPEN_PROPOSALS_SETTING,
+    DISABLED_ACTIONS_SETTING,
+    ANCHOR_SIGNERS_SETTING,
+    FEEDBACK_KIOSKS_SETTING,
+    MIGRATION_SIGNERS_SETTING,
+    AGENT_ADMINS_SETTING,
+    TIMESTAMP_UNIT_SETTING,
+];
+
+/// The addresses of the settings read by a fixed key, for tools which
+/// build their own transaction headers. The namespaced RecordType
+/// settings depend on the RecordType's name, and are not included.
+pub fn setting_addresses() -> Vec<String> {
+    FIXED_SETTINGS
+        .iter()
+        .map(|key| make_setting_address(key))
+        .collect()
+}
 
 #[derive(Debug, Clone)]
 enum Action {
@@ -38,1100 +195,6856 @@ enum Action {
     CreateProposal(payload::CreateProposalAction),
     AnswerProposal(payload::AnswerProposalAction),
     RevokeReporter(payload::RevokeReporterAction),
+    ExecuteTransfer(payload::ExecuteTransferAction),
+    Message(payload::MessageAction),
+    Subscribe(payload::SubscribeAction),
+    Unsubscribe(payload::UnsubscribeAction),
+    Composite(Vec<Action>),
+    ReceiveConfirmation(payload::ReceiveConfirmationAction),
+    AnchorState(payload::AnchorStateAction),
+    CloneRecord(payload::CloneRecordAction),
+    AnchorFeedback(payload::AnchorFeedbackAction),
+    MigrateState(payload::MigrateStateAction),
+    DeleteRecordType(payload::DeleteRecordTypeAction),
+    AcknowledgeValue(payload::AcknowledgeValueAction),
+    UpdateRecordType(payload::UpdateRecordTypeAction),
+    DirectTransfer(payload::TransferAuthorization),
+    // The bulk update's policy, and one UpdateProperties for each Record
+    BulkUpdateProperties(
+        payload::BulkUpdatePropertiesAction_Policy,
+        Vec<payload::UpdatePropertiesAction>,
+    ),
+    DelegateReporter(payload::DelegateReporterAction),
+    MergeRecords(payload::MergeRecordsAction),
+    ArchiveRecord(payload::ArchiveRecordAction),
+    ReopenRecord(payload::ReopenRecordAction),
+    RecallRecord(payload::RecallRecordAction),
+    SetAgentRole(payload::SetAgentRoleAction),
+    SetRecordMetadata(payload::SetRecordMetadataAction),
+    AttestAudit(payload::AttestAuditAction),
+    CancelAllProposals(payload::CancelAllProposalsAction),
+    AmendProposal(payload::AmendProposalAction),
+    ReleaseEscrow(payload::ReleaseEscrowAction),
+    DeactivateAgent(payload::DeactivateAgentAction),
+    CreateRecordTemplate(payload::CreateRecordTemplateAction),
+    CreateRecordFromTemplate(payload::CreateRecordFromTemplateAction),
 }
 
-struct SupplyChainPayload {
-    action: Action,
-    timestamp: u64,
-}
+impl Action {
+    fn name(&self) -> &'static str {
+        match *self {
+            Action::CreateAgent(_) => "CreateAgent",
+            Action::CreateRecord(_) => "CreateRecord",
+            Action::FinalizeRecord(_) => "FinalizeRecord",
+            Action::CreateRecordType(_) => "CreateRecordType",
+            Action::UpdateProperties(_) => "UpdateProperties",
+            Action::CreateProposal(_) => "CreateProposal",
+            Action::AnswerProposal(_) => "AnswerProposal",
+            Action::RevokeReporter(_) => "RevokeReporter",
+            Action::ExecuteTransfer(_) => "ExecuteTransfer",
+            Action::Message(_) => "Message",
+            Action::Subscribe(_) => "Subscribe",
+            Action::Unsubscribe(_) => "Unsubscribe",
+            Action::Composite(_) => "Composite",
+            Action::ReceiveConfirmation(_) => "ReceiveConfirmation",
+            Action::AnchorState(_) => "AnchorState",
+            Action::CloneRecord(_) => "CloneRecord",
+            Action::AnchorFeedback(_) => "AnchorFeedback",
+            Action::MigrateState(_) => "MigrateState",
+            Action::DeleteRecordType(_) => "DeleteRecordType",
+            Action::AcknowledgeValue(_) => "AcknowledgeValue",
+            Action::UpdateRecordType(_) => "UpdateRecordType",
+            Action::DirectTransfer(_) => "DirectTransfer",
+            Action::BulkUpdateProperties(..) => "BulkUpdateProperties",
+            Action::DelegateReporter(_) => "DelegateReporter",
+            Action::MergeRecords(_) => "MergeRecords",
+            Action::ArchiveRecord(_) => "ArchiveRecord",
+            Action::ReopenRecord(_) => "ReopenRecord",
+            Action::RecallRecord(_) => "RecallRecord",
+            Action::SetAgentRole(_) => "SetAgentRole",
+            Action::SetRecordMetadata(_) => "SetRecordMetadata",
+            Action::AttestAudit(_) => "AttestAudit",
+            Action::CancelAllProposals(_) => "CancelAllProposals",
+            Action::AmendProposal(_) => "AmendProposal",
+            Action::ReleaseEscrow(_) => "ReleaseEscrow",
+            Action::DeactivateAgent(_) => "DeactivateAgent",
+            Action::CreateRecordTemplate(_) => "CreateRecordTemplate",
+            Action::CreateRecordFromTemplate(_) => "CreateRecordFromTemplate",
+        }
+    }
 
-impl SupplyChainPayload {
-    pub fn new(payload: &[u8]) -> Result<Option<SupplyChainPayload>, ApplyError> {
-        let payload: payload::SCPayload = match protobuf::parse_from_bytes(payload) {
-            Ok(payload) => payload,
-            Err(_) => {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Cannot deserialize payload",
-                )))
+    /// The payload action this was sent as
+    fn kind(&self) -> payload::SCPayload_Action {
+        match *self {
+            Action::CreateAgent(_) => payload::SCPayload_Action::CREATE_AGENT,
+            Action::CreateRecord(_) => payload::SCPayload_Action::CREATE_RECORD,
+            Action::FinalizeRecord(_) => payload::SCPayload_Action::FINALIZE_RECORD,
+            Action::CreateRecordType(_) => payload::SCPayload_Action::CREATE_RECORD_TYPE,
+            Action::UpdateProperties(_) => payload::SCPayload_Action::UPDATE_PROPERTIES,
+            Action::CreateProposal(_) => payload::SCPayload_Action::CREATE_PROPOSAL,
+            Action::AnswerProposal(_) => payload::SCPayload_Action::ANSWER_PROPOSAL,
+            Action::RevokeReporter(_) => payload::SCPayload_Action::REVOKE_REPORTER,
+            Action::ExecuteTransfer(_) => payload::SCPayload_Action::EXECUTE_TRANSFER,
+            Action::Message(_) => payload::SCPayload_Action::MESSAGE,
+            Action::Subscribe(_) => payload::SCPayload_Action::SUBSCRIBE,
+            Action::Unsubscribe(_) => payload::SCPayload_Action::UNSUBSCRIBE,
+            Action::Composite(_) => payload::SCPayload_Action::COMPOSITE,
+            Action::ReceiveConfirmation(_) => payload::SCPayload_Action::RECEIVE_CONFIRMATION,
+            Action::AnchorState(_) => payload::SCPayload_Action::ANCHOR_STATE,
+            Action::CloneRecord(_) => payload::SCPayload_Action::CLONE_RECORD,
+            Action::AnchorFeedback(_) => payload::SCPayload_Action::ANCHOR_FEEDBACK,
+            Action::MigrateState(_) => payload::SCPayload_Action::MIGRATE_STATE,
+            Action::DeleteRecordType(_) => payload::SCPayload_Action::DELETE_RECORD_TYPE,
+            Action::AcknowledgeValue(_) => payload::SCPayload_Action::ACKNOWLEDGE_VALUE,
+            Action::UpdateRecordType(_) => payload::SCPayload_Action::UPDATE_RECORD_TYPE,
+            Action::DirectTransfer(_) => payload::SCPayload_Action::DIRECT_TRANSFER,
+            Action::BulkUpdateProperties(..) => payload::SCPayload_Action::BULK_UPDATE_PROPERTIES,
+            Action::DelegateReporter(_) => payload::SCPayload_Action::DELEGATE_REPORTER,
+            Action::MergeRecords(_) => payload::SCPayload_Action::MERGE_RECORDS,
+            Action::ArchiveRecord(_) => payload::SCPayload_Action::ARCHIVE_RECORD,
+            Action::ReopenRecord(_) => payload::SCPayload_Action::REOPEN_RECORD,
+            Action::RecallRecord(_) => payload::SCPayload_Action::RECALL_RECORD,
+            Action::SetAgentRole(_) => payload::SCPayload_Action::SET_AGENT_ROLE,
+            Action::SetRecordMetadata(_) => payload::SCPayload_Action::SET_RECORD_METADATA,
+            Action::AttestAudit(_) => payload::SCPayload_Action::ATTEST_AUDIT,
+            Action::CancelAllProposals(_) => payload::SCPayload_Action::CANCEL_ALL_PROPOSALS,
+            Action::AmendProposal(_) => payload::SCPayload_Action::AMEND_PROPOSAL,
+            Action::ReleaseEscrow(_) => payload::SCPayload_Action::RELEASE_ESCROW,
+            Action::DeactivateAgent(_) => payload::SCPayload_Action::DEACTIVATE_AGENT,
+            Action::CreateRecordTemplate(_) => payload::SCPayload_Action::CREATE_RECORD_TEMPLATE,
+            Action::CreateRecordFromTemplate(_) => {
+                payload::SCPayload_Action::CREATE_RECORD_FROM_TEMPLATE
             }
-        };
+        }
+    }
 
-        let supply_chain_action = payload.get_action();
-        let action = match supply_chain_action {
-            payload::SCPayload_Action::CREATE_AGENT => {
-                let create_agent = payload.get_create_agent();
-                if create_agent.get_name() == "" {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Agent name cannot be an empty string",
-                    )));
-                }
-                Action::CreateAgent(create_agent.clone())
-            }
-            payload::SCPayload_Action::CREATE_RECORD => {
-                let create_record = payload.get_create_record();
-                if create_record.get_record_id() == "" {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Record id cannot be empty string",
-                    )));
-                }
-                Action::CreateRecord(create_record.clone())
-            }
-            payload::SCPayload_Action::FINALIZE_RECORD => {
-                Action::FinalizeRecord(payload.get_finalize_record().clone())
-            }
-            payload::SCPayload_Action::CREATE_RECORD_TYPE => {
-                let create_record_type = payload.get_create_record_type();
-                if create_record_type.get_name() == "" {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Record Type name cannot be an empty string",
-                    )));
-                };
-                let properties = create_record_type.get_properties();
-                if properties.len() == 0 {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Record type must have at least one property",
-                    )));
-                }
-                for prop in properties {
-                    if prop.name == "" {
-                        return Err(ApplyError::InvalidTransaction(String::from(
-                            "Property name cannot be an empty string",
-                        )));
-                    }
-                }
+    fn record_id(&self) -> Option<&str> {
+        match *self {
+            Action::CreateAgent(_)
+            | Action::CreateRecordType(_)
+            | Action::Subscribe(_)
+            | Action::Unsubscribe(_)
+            | Action::Composite(_)
+            | Action::AnchorState(_)
+            | Action::MigrateState(_)
+            | Action::DeleteRecordType(_)
+            | Action::UpdateRecordType(_)
+            | Action::BulkUpdateProperties(..)
+            | Action::SetAgentRole(_)
+            | Action::DeactivateAgent(_)
+            | Action::CreateRecordTemplate(_) => None,
+            Action::CreateRecord(ref a) => Some(a.get_record_id()),
+            Action::CreateRecordFromTemplate(ref a) => Some(a.get_record_id()),
+            Action::FinalizeRecord(ref a) => Some(a.get_record_id()),
+            Action::UpdateProperties(ref a) => Some(a.get_record_id()),
+            Action::CreateProposal(ref a) => Some(a.get_record_id()),
+            Action::AnswerProposal(ref a) => Some(a.get_record_id()),
+            Action::RevokeReporter(ref a) => Some(a.get_record_id()),
+            Action::ExecuteTransfer(ref a) => Some(a.get_record_id()),
+            Action::Message(ref a) => Some(a.get_record_id()),
+            Action::ReceiveConfirmation(ref a) => Some(a.get_record_id()),
+            Action::CloneRecord(ref a) => Some(a.get_record_id()),
+            Action::AnchorFeedback(ref a) => Some(a.get_record_id()),
+            Action::AcknowledgeValue(ref a) => Some(a.get_record_id()),
+            Action::DirectTransfer(ref a) => Some(a.get_record_id()),
+            Action::DelegateReporter(ref a) => Some(a.get_record_id()),
+            Action::MergeRecords(ref a) => Some(a.get_record_id()),
+            Action::ArchiveRecord(ref a) => Some(a.get_record_id()),
+            Action::ReopenRecord(ref a) => Some(a.get_record_id()),
+            Action::RecallRecord(ref a) => Some(a.get_record_id()),
+            Action::SetRecordMetadata(ref a) => Some(a.get_record_id()),
+            Action::AttestAudit(ref a) => Some(a.get_record_id()),
+            Action::CancelAllProposals(ref a) => Some(a.get_record_id()),
+            Action::AmendProposal(ref a) => Some(a.get_record_id()),
+            Action::ReleaseEscrow(ref a) => Some(a.get_record_id()),
+        }
+    }
 
-                Action::CreateRecordType(create_record_type.clone())
-            }
-            payload::SCPayload_Action::UPDATE_PROPERTIES => {
-                Action::UpdateProperties(payload.get_update_properties().clone())
-            }
-            payload::SCPayload_Action::CREATE_PROPOSAL => {
-                Action::CreateProposal(payload.get_create_proposal().clone())
-            }
-            payload::SCPayload_Action::ANSWER_PROPOSAL => {
-                Action::AnswerProposal(payload.get_answer_proposal().clone())
-            }
-            payload::SCPayload_Action::REVOKE_REPORTER => {
-                Action::RevokeReporter(payload.get_revoke_reporter().clone())
-            }
-        };
-        let timestamp = match payload.get_timestamp() {
-            0 => {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Timestamp is not set",
-                )))
+    /// The Agents other than the signer that the action names
+    fn agent_ids(&self) -> Vec<&str> {
+        match *self {
+            Action::CreateRecord(ref a) => a
+                .get_reporters()
+                .iter()
+                .map(|reporter| reporter.get_public_key())
+                .collect(),
+            Action::CreateRecordFromTemplate(ref a) => a
+                .get_reporters()
+                .iter()
+                .map(|reporter| reporter.get_public_key())
+                .collect(),
+            Action::CreateProposal(ref a) => {
+                let mut agents = vec![a.get_receiving_agent()];
+                agents.extend(a.get_approvers().iter().map(|approver| approver.as_str()));
+                agents
             }
-            x => x,
-        };
-
-        Ok(Some(SupplyChainPayload {
-            action: action,
-            timestamp: timestamp,
-        }))
+            Action::AnswerProposal(ref a) => vec![a.get_receiving_agent()],
+            Action::AmendProposal(ref a) => vec![a.get_receiving_agent()],
+            Action::RevokeReporter(ref a) => vec![a.get_reporter_id()],
+            Action::DelegateReporter(ref a) => vec![a.get_reporter_id()],
+            Action::ExecuteTransfer(ref a) => vec![a.get_receiving_agent()],
+            Action::DirectTransfer(ref a) => vec![a.get_receiving_agent()],
+            Action::Message(ref a) => vec![a.get_recipient()],
+            Action::SetAgentRole(ref a) => vec![a.get_agent_id()],
+            Action::DeactivateAgent(ref a) => vec![a.get_agent_id()],
+            _ => vec![],
+        }
     }
+}
 
-    pub fn get_action(&self) -> Action {
-        self.action.clone()
+/// Lists the Records and Agents a transaction touched, including those of
+/// each action of a composite and each Record of a bulk update
+fn make_activity_receipt(action: &Action, signer: &str) -> receipt::ActivityReceipt {
+    let mut record_ids = BTreeSet::new();
+    let mut agents = BTreeSet::new();
+    agents.insert(signer.to_string());
+
+    let actions = match *action {
+        Action::Composite(ref actions) => actions.iter().collect(),
+        ref action => vec![action],
+    };
+    for action in actions {
+        if let Some(record_id) = action.record_id() {
+            record_ids.insert(record_id.to_string());
+        }
+        if let Action::BulkUpdateProperties(_, ref updates) = *action {
+            record_ids.extend(updates.iter().map(|u| u.get_record_id().to_string()));
+        }
+        if let Action::MergeRecords(ref merge) = *action {
+            record_ids.extend(merge.get_parent_ids().iter().cloned());
+        }
+        agents.extend(action.agent_ids().into_iter().map(String::from));
     }
 
-    pub fn get_timestamp(&self) -> u64 {
-        self.timestamp
+    let mut activity = receipt::ActivityReceipt::new();
+    activity.set_record_ids(RepeatedField::from_vec(record_ids.into_iter().collect()));
+    activity.set_agents(RepeatedField::from_vec(agents.into_iter().collect()));
+    activity
+}
+
+/// Converts a payload timestamp to seconds. Timestamps are checked
+/// against the unit setting, so the boundary between the units tells
+/// them apart even if the setting has since changed.
+fn timestamp_seconds(timestamp: u64) -> u64 {
+    if timestamp >= TIMESTAMP_UNIT_BOUNDARY {
+        timestamp / 1000
+    } else {
+        timestamp
     }
 }
 
-pub struct SupplyChainState<'a> {
-    context: &'a mut TransactionContext,
+/// Rejects a property schema, or any struct or array nested in it, with
+/// more enum options, struct properties, or array elements than are
+/// allowed, or with a pattern or fence which cannot be used
+fn check_schema_size(schema: &property::PropertySchema) -> Result<(), ApplyError> {
+    if schema.get_enum_options().len() > MAX_ENUM_OPTIONS {
+        return Err(invalid(Code::InvalidSchema, format!(
+            "Property {} has {} enum options, more than the maximum of {}",
+            schema.get_name(),
+            schema.get_enum_options().len(),
+            MAX_ENUM_OPTIONS
+        )));
+    }
+    if schema.get_struct_properties().len() > MAX_STRUCT_PROPERTIES {
+        return Err(invalid(Code::InvalidSchema, format!(
+            "Property {} has {} struct properties, more than the maximum of {}",
+            schema.get_name(),
+            schema.get_struct_properties().len(),
+            MAX_STRUCT_PROPERTIES
+        )));
+    }
+    for field in schema.get_struct_properties() {
+        check_schema_size(field)?;
+    }
+    let pattern = schema.get_string_pattern();
+    if !pattern.is_empty() {
+        if schema.get_data_type() != property::PropertySchema_DataType::STRING {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Only STRING properties may have a pattern: {}",
+                schema.get_name()
+            )));
+        }
+        if let Err(err) = Pattern::parse(pattern) {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Invalid pattern for {}: {}",
+                schema.get_name(),
+                err
+            )));
+        }
+    }
+    let fence = schema.get_fence();
+    if !fence.is_empty() {
+        if schema.get_data_type() != property::PropertySchema_DataType::LOCATION {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Only LOCATION properties may have a fence: {}",
+                schema.get_name()
+            )));
+        }
+        if let Err(err) = locations::check_fence(fence) {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Invalid fence for {}: {}",
+                schema.get_name(),
+                err
+            )));
+        }
+    }
+    if schema.get_data_type() == property::PropertySchema_DataType::ARRAY {
+        let element = schema.get_element_schema();
+        if element.get_data_type() == property::PropertySchema_DataType::TYPE_UNSET {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Array property {} must have an element schema",
+                schema.get_name()
+            )));
+        }
+        if schema.get_max_length() as usize > MAX_ARRAY_LENGTH {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Property {} allows {} array elements, more than the maximum of {}",
+                schema.get_name(),
+                schema.get_max_length(),
+                MAX_ARRAY_LENGTH
+            )));
+        }
+        check_schema_size(element)?;
+    }
+    Ok(())
 }
 
-impl<'a> SupplyChainState<'a> {
-    pub fn new(context: &'a mut TransactionContext) -> SupplyChainState {
-        SupplyChainState { context: context }
+/// Rejects a NUMBER schema, or one nested in a struct or array, whose
+/// exponent or bounds would let values overflow once the exponent is
+/// applied
+fn check_number_schema(schema: &property::PropertySchema) -> Result<(), ApplyError> {
+    let exponent = schema.get_number_exponent();
+    if exponent.abs() > MAX_NUMBER_EXPONENT {
+        return Err(invalid(Code::InvalidSchema, format!(
+            "Property {} has exponent {}, outside of -{} to {}",
+            schema.get_name(),
+            exponent,
+            MAX_NUMBER_EXPONENT,
+            MAX_NUMBER_EXPONENT
+        )));
+    }
+    if schema.has_bounds() {
+        if schema.get_data_type() != property::PropertySchema_DataType::NUMBER {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Only NUMBER properties may have bounds: {}",
+                schema.get_name()
+            )));
+        }
+        let bounds = schema.get_bounds();
+        if bounds.get_min() > bounds.get_max() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Bounds minimum is greater than its maximum: {}",
+                schema.get_name()
+            )));
+        }
     }
+    for field in schema.get_struct_properties() {
+        check_number_schema(field)?;
+    }
+    if schema.has_element_schema() {
+        check_number_schema(schema.get_element_schema())?;
+    }
+    Ok(())
+}
 
-    pub fn get_record(&mut self, record_id: &str) -> Result<Option<record::Record>, ApplyError> {
-        let address = make_record_address(record_id);
-        let d = self.context.get_state(vec![address])?;
-        match d {
-            Some(packed) => {
-                let records: record::RecordContainer =
-                    match protobuf::parse_from_bytes(packed.as_slice()) {
-                        Ok(records) => records,
-                        Err(_) => {
-                            return Err(ApplyError::InternalError(String::from(
-                                "Cannot deserialize record container",
-                            )))
-                        }
-                    };
+/// Rejects a property schema whose allowed reporters are too many, or
+/// include an empty or repeated public key
+fn check_allowed_reporters(schema: &property::PropertySchema) -> Result<(), ApplyError> {
+    let allowed = schema.get_allowed_reporters();
+    if allowed.len() > MAX_ALLOWED_REPORTERS {
+        return Err(invalid(Code::InvalidSchema, format!(
+            "Property {} allows {} reporters, more than the maximum of {}",
+            schema.get_name(),
+            allowed.len(),
+            MAX_ALLOWED_REPORTERS
+        )));
+    }
+    let mut seen = BTreeSet::new();
+    for public_key in allowed {
+        if public_key.is_empty() || !seen.insert(public_key.as_str()) {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Property {} allows an empty or repeated reporter: {}",
+                schema.get_name(),
+                public_key
+            )));
+        }
+    }
+    Ok(())
+}
 
-                for record in records.get_entries() {
-                    if record.record_id == record_id {
-                        return Ok(Some(record.clone()));
-                    }
-                }
-                Ok(None)
-            }
-            None => Ok(None),
+/// Rejects a NUMBER value which is outside its schema's bounds, or which
+/// would overflow an i64 once the schema's exponent is applied
+fn check_number_value(
+    name: &str,
+    value: i64,
+    schema: &property::PropertySchema,
+) -> Result<(), ApplyError> {
+    let exponent = schema.get_number_exponent();
+    let fits = exponent <= 0
+        || 10i64
+            .checked_pow(exponent as u32)
+            .and_then(|scale| value.checked_mul(scale))
+            .is_some();
+    if !fits {
+        return Err(invalid(Code::InvalidValue, format!(
+            "Value of {} overflows with exponent {}: {}",
+            name, exponent, value
+        )));
+    }
+    if schema.has_bounds() {
+        let bounds = schema.get_bounds();
+        if value < bounds.get_min() || value > bounds.get_max() {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Value of {} is outside of {} to {}: {}",
+                name,
+                bounds.get_min(),
+                bounds.get_max(),
+                value
+            )));
         }
     }
+    Ok(())
+}
 
-    pub fn set_record(
-        &mut self,
-        record_id: &str,
-        record: record::Record,
-    ) -> Result<(), ApplyError> {
-        let address = make_record_address(record_id);
-        let d = self.context.get_state(vec![address.clone()])?;
-        let mut record_container = match d {
-            Some(packed) => match protobuf::parse_from_bytes(packed.as_slice()) {
-                Ok(records) => records,
-                Err(_) => {
-                    return Err(ApplyError::InternalError(String::from(
-                        "Cannot deserialize record container",
-                    )))
-                }
-            },
-            None => record::RecordContainer::new(),
-        };
-        // remove old record if it exists and sort the records by record id
-        let records = record_container.get_entries().to_vec();
-        let mut index = None;
-        let mut count = 0;
-        for record in records.clone() {
-            if record.record_id == record_id {
-                index = Some(count);
-                break;
-            }
-            count = count + 1;
+/// Rejects an IDENTIFIER value which does not have its schema's format
+fn check_identifier_value(
+    name: &str,
+    value: &str,
+    schema: &property::PropertySchema,
+) -> Result<(), ApplyError> {
+    identifiers::check(schema.get_identifier_format(), value)
+        .map_err(|err| invalid(Code::InvalidValue, format!("{}: {}", err, name)))
+}
+
+/// Rejects a STRING value which is longer than its schema allows, or does
+/// not match its schema's pattern
+fn check_string_value(
+    name: &str,
+    value: &str,
+    schema: &property::PropertySchema,
+) -> Result<(), ApplyError> {
+    let max_length = schema.get_max_length() as usize;
+    if max_length > 0 && value.chars().count() > max_length {
+        return Err(invalid(Code::InvalidValue, format!(
+            "Value of {} is longer than {} characters",
+            name, max_length
+        )));
+    }
+    let pattern = schema.get_string_pattern();
+    if !pattern.is_empty() {
+        // Patterns are checked when the schema is created
+        let matches = Pattern::parse(pattern).map(|p| p.matches(value)).unwrap_or(false);
+        if !matches {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Value of {} does not match the pattern {}: {}",
+                name, pattern, value
+            )));
         }
+    }
+    Ok(())
+}
 
-        match index {
-            Some(x) => {
-                record_container.entries.remove(x);
-            }
-            None => (),
-        };
-        record_container.entries.push(record);
-        record_container
-            .entries
-            .sort_by_key(|r| r.clone().record_id);
-        let serialized = match record_container.write_to_bytes() {
-            Ok(serialized) => serialized,
-            Err(_) => {
-                return Err(ApplyError::InternalError(String::from(
-                    "Cannot serialize record container",
-                )))
-            }
-        };
-        let mut sets = HashMap::new();
-        sets.insert(address, serialized);
-        self.context
-            .set_state(sets)
-            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
-        Ok(())
+/// Rejects a LOCATION value which is not on the globe, or is outside its
+/// schema's fence
+fn check_location_value(
+    name: &str,
+    value: &property::Location,
+    schema: &property::PropertySchema,
+) -> Result<(), ApplyError> {
+    locations::check_coordinates(value)
+        .map_err(|err| invalid(Code::InvalidValue, format!("{}: {}", err, name)))?;
+    let fence = schema.get_fence();
+    if !fence.is_empty() && !locations::is_inside(value, fence) {
+        return Err(invalid(Code::InvalidValue, format!(
+            "Location of {} is outside its fence: {}, {}",
+            name,
+            value.get_latitude(),
+            value.get_longitude()
+        )));
     }
+    Ok(())
+}
 
-    pub fn get_record_type(
-        &mut self,
-        type_name: &str,
-    ) -> Result<Option<record::RecordType>, ApplyError> {
-        let address = make_record_type_address(type_name);
-        let d = self.context.get_state(vec![address])?;
-        match d {
-            Some(packed) => {
-                let record_types: record::RecordTypeContainer =
-                    match protobuf::parse_from_bytes(packed.as_slice()) {
-                        Ok(record_types) => record_types,
-                        Err(_) => {
-                            return Err(ApplyError::InternalError(String::from(
-                                "Cannot deserialize record type container",
-                            )))
-                        }
-                    };
+/// A NUMBER value in its schema's unit, converting it from the unit it
+/// was reported in if that is different
+fn number_in_schema_unit(
+    name: &str,
+    value: &property::PropertyValue,
+    schema: &property::PropertySchema,
+) -> Result<i64, ApplyError> {
+    let unit = value.get_unit();
+    if unit.is_empty() {
+        return Ok(value.get_number_value());
+    }
+    if value.get_data_type() != property::PropertySchema_DataType::NUMBER {
+        return Err(invalid(Code::InvalidValue, format!(
+            "Only NUMBER values may have a unit: {}",
+            name
+        )));
+    }
+    if unit == schema.get_unit() {
+        return Ok(value.get_number_value());
+    }
+    if schema.get_unit().is_empty() {
+        return Err(invalid(Code::InvalidValue, format!(
+            "Property {} has no unit, so its values may not have one",
+            name
+        )));
+    }
+    units::convert(
+        value.get_number_value(),
+        schema.get_number_exponent(),
+        unit,
+        schema.get_unit(),
+    ).map_err(|err| invalid(Code::InvalidValue, format!("{}: {}", err, name)))
+}
 
-                for record_type in record_types.get_entries() {
-                    if record_type.name == type_name {
-                        return Ok(Some(record_type.clone()));
-                    }
-                }
-                Ok(None)
-            }
-            None => Ok(None),
+/// Applies the checks of NUMBER, STRING, IDENTIFIER, and LOCATION schemas
+/// to a value, which is assumed to have the schema's type
+fn check_scalar_value(
+    name: &str,
+    value: &property::PropertyValue,
+    schema: &property::PropertySchema,
+) -> Result<(), ApplyError> {
+    match schema.get_data_type() {
+        property::PropertySchema_DataType::NUMBER => {
+            check_number_value(name, number_in_schema_unit(name, value, schema)?, schema)
+        }
+        property::PropertySchema_DataType::STRING => {
+            check_string_value(name, value.get_string_value(), schema)
+        }
+        property::PropertySchema_DataType::IDENTIFIER => {
+            check_identifier_value(name, value.get_identifier_value(), schema)
         }
+        property::PropertySchema_DataType::LOCATION => {
+            check_location_value(name, value.get_location_value(), schema)
+        }
+        _ => Ok(()),
     }
+}
 
-    pub fn set_record_type(
-        &mut self,
-        type_name: &str,
-        record_type: record::RecordType,
-    ) -> Result<(), ApplyError> {
-        let address = make_record_type_address(type_name);
-        let d = self.context.get_state(vec![address.clone()])?;
-        let mut record_types = match d {
-            Some(packed) => match protobuf::parse_from_bytes(packed.as_slice()) {
-                Ok(record_types) => record_types,
-                Err(_) => {
-                    return Err(ApplyError::InternalError(String::from(
-                        "Cannot deserialize record container",
-                    )))
-                }
-            },
-            None => record::RecordTypeContainer::new(),
-        };
+/// The most elements an ARRAY property's values may have
+fn array_max_length(schema: &property::PropertySchema) -> usize {
+    match schema.get_max_length() {
+        0 => MAX_ARRAY_LENGTH,
+        max_length => max_length as usize,
+    }
+}
 
-        record_types.entries.push(record_type);
-        record_types.entries.sort_by_key(|rt| rt.clone().name);
-        let serialized = match record_types.write_to_bytes() {
-            Ok(serialized) => serialized,
-            Err(_) => {
-                return Err(ApplyError::InternalError(String::from(
-                    "Cannot serialize record type container",
-                )))
-            }
-        };
-        let mut sets = HashMap::new();
-        sets.insert(address, serialized);
-        self.context
-            .set_state(sets)
-            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
-        Ok(())
+/// Whether values of a schema hold record_ids of other Records, either
+/// directly or as elements of an array or fields of a struct
+fn holds_record_references(schema: &property::PropertySchema) -> bool {
+    match schema.get_data_type() {
+        property::PropertySchema_DataType::RECORD_REFERENCE => true,
+        property::PropertySchema_DataType::ARRAY => {
+            holds_record_references(schema.get_element_schema())
+        }
+        property::PropertySchema_DataType::STRUCT => {
+            schema.get_struct_properties().iter().any(holds_record_references)
+        }
+        _ => false,
     }
+}
 
-    pub fn get_agent(&mut self, agent_id: &str) -> Result<Option<agent::Agent>, ApplyError> {
-        let address = make_agent_address(agent_id);
-        let d = self.context.get_state(vec![address])?;
-        match d {
-            Some(packed) => {
-                let agents: agent::AgentContainer =
-                    match protobuf::parse_from_bytes(packed.as_slice()) {
-                        Ok(agents) => agents,
-                        Err(_) => {
-                            return Err(ApplyError::InternalError(String::from(
-                                "Cannot deserialize agent container",
-                            )))
-                        }
-                    };
+/// The attributes identifying a proposal in the events about it
+fn proposal_event_details(proposal: &proposal::Proposal) -> Vec<(&'static str, String)> {
+    vec![
+        ("issuing_agent", proposal.get_issuing_agent().to_string()),
+        ("receiving_agent", proposal.get_receiving_agent().to_string()),
+        ("role", format!("{:?}", proposal.get_role())),
+        ("status", format!("{:?}", proposal.get_status())),
+    ]
+}
 
-                for agent in agents.get_entries() {
-                    if agent.public_key == agent_id {
-                        return Ok(Some(agent.clone()));
-                    }
-                }
-                Ok(None)
-            }
-            None => Ok(None),
+/// The binding rules see for a value. Rules have no floats, so numbers
+/// are bound as their stored integer; other types cannot be bound.
+fn rule_value(value: &values::Value) -> Option<rules::Value> {
+    match *value {
+        values::Value::Number(ref number) => Some(rules::Value::Int(number.value)),
+        values::Value::Boolean(boolean) => Some(rules::Value::Bool(boolean)),
+        values::Value::String(ref string)
+        | values::Value::Enum(ref string)
+        | values::Value::RecordReference(ref string)
+        | values::Value::Identifier(ref string) => {
+            Some(rules::Value::Str(string.to_string()))
         }
+        _ => None,
     }
+}
 
-    pub fn set_agent(&mut self, agent_id: &str, agent: agent::Agent) -> Result<(), ApplyError> {
-        let address = make_agent_address(agent_id);
-        let d = self.context.get_state(vec![address.clone()])?;
-        let mut agents = match d {
-            Some(packed) => match protobuf::parse_from_bytes(packed.as_slice()) {
-                Ok(agents) => agents,
-                Err(_) => {
-                    return Err(ApplyError::InternalError(String::from(
-                        "Cannot deserialize agent container",
-                    )))
-                }
-            },
-            None => agent::AgentContainer::new(),
-        };
+/// Whether every agent a proposal needs the approval of has given it,
+/// the receiving agent included
+fn proposal_fully_approved(proposal: &proposal::Proposal) -> bool {
+    let approvals = proposal.get_approvals();
+    let approved = |agent: &str| approvals.iter().any(|a| a == agent);
+    approved(proposal.get_receiving_agent())
+        && proposal.get_approvers().iter().all(|agent| approved(agent.as_str()))
+}
 
-        agents.entries.push(agent);
-        agents.entries.sort_by_key(|a| a.clone().public_key);
-        let serialized = match agents.write_to_bytes() {
-            Ok(serialized) => serialized,
-            Err(_) => {
-                return Err(ApplyError::InternalError(String::from(
-                    "Cannot serialize agent container",
-                )))
-            }
-        };
-        let mut sets = HashMap::new();
-        sets.insert(address, serialized);
-        self.context
-            .set_state(sets)
-            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
-        Ok(())
+/// Checks the commercial terms of a proposal, which only ownership and
+/// custodianship transfers may have
+fn check_transfer_terms(
+    terms: &record::TransferTerms,
+    role: proposal::Proposal_Role,
+    timestamp: u64,
+) -> Result<(), ApplyError> {
+    if role == proposal::Proposal_Role::REPORTER {
+        return Err(invalid(Code::InvalidProposal, String::from(
+            "Reporter proposals cannot have transfer terms",
+        )));
+    }
+    let currency = terms.get_currency();
+    if terms.get_price() != 0 && currency.is_empty() {
+        return Err(invalid(Code::MissingField, String::from(
+            "A price must be given with its currency",
+        )));
+    }
+    if !currency.is_empty()
+        && (currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()))
+    {
+        return Err(invalid(Code::InvalidFormat, format!(
+            "Currency must be an ISO 4217 code: {}",
+            currency
+        )));
+    }
+    let incoterm = terms.get_incoterm();
+    if !incoterm.is_empty() && !INCOTERMS.contains(&incoterm) {
+        return Err(invalid(Code::InvalidFormat, format!(
+            "Unknown Incoterms rule: {}",
+            incoterm
+        )));
+    }
+    let deadline = terms.get_delivery_deadline();
+    if deadline != 0 && deadline <= timestamp {
+        return Err(invalid(Code::InvalidProposal, format!(
+            "Delivery deadline has already passed: {}",
+            deadline
+        )));
     }
+    Ok(())
+}
 
-    pub fn get_property(
-        &mut self,
-        record_id: &str,
-        property_name: &str,
-    ) -> Result<Option<property::Property>, ApplyError> {
-        let address = make_property_address(record_id, property_name, 0);
-        let d = self.context.get_state(vec![address])?;
-        match d {
-            Some(packed) => {
-                let properties: property::PropertyContainer =
-                    match protobuf::parse_from_bytes(packed.as_slice()) {
-                        Ok(properties) => properties,
-                        Err(_) => {
-                            return Err(ApplyError::InternalError(String::from(
-                                "Cannot deserialize property container",
-                            )))
-                        }
-                    };
+/// A timeline entry for a role taken over by accepting a proposal,
+/// carrying the proposal's transfer terms if it had any
+fn transfer_timeline_entry(
+    role: record::RecordTimelineEntry_Role,
+    agent: &record::Record_AssociatedAgent,
+    transfer: &proposal::Proposal,
+) -> record::RecordTimelineEntry {
+    let mut entry = timeline_entry(role, agent);
+    if transfer.has_transfer_terms() {
+        entry.set_terms(transfer.get_transfer_terms().clone());
+    }
+    entry
+}
 
-                for property in properties.get_entries() {
-                    if property.name == property_name {
-                        return Ok(Some(property.clone()));
-                    }
-                }
-                Ok(None)
-            }
-            None => Ok(None),
-        }
+/// Whether an open proposal has lapsed without being answered
+fn proposal_has_expired(proposal: &proposal::Proposal, timestamp: u64) -> bool {
+    let expiration = proposal.get_expiration();
+    expiration != 0 && expiration <= timestamp
+}
+
+/// The transfer a proposal makes if accepted: from its issuing agent to
+/// its receiving agent, or the other way around for a counter-proposal
+/// sent back to the holder of the role
+fn proposal_transfer(proposal: &proposal::Proposal) -> proposal::Proposal {
+    let mut transfer = proposal.clone();
+    if proposal.get_to_holder() {
+        transfer.set_issuing_agent(proposal.get_receiving_agent().to_string());
+        transfer.set_receiving_agent(proposal.get_issuing_agent().to_string());
     }
+    transfer
+}
 
-    pub fn set_property(
-        &mut self,
-        record_id: &str,
-        property_name: &str,
-        property: property::Property,
-    ) -> Result<(), ApplyError> {
-        let address = make_property_address(record_id, property_name, 0);
-        let d = self.context.get_state(vec![address.clone()])?;
-        let mut property_container = match d {
-            Some(packed) => match protobuf::parse_from_bytes(packed.as_slice()) {
-                Ok(properties) => properties,
-                Err(_) => {
-                    return Err(ApplyError::InternalError(String::from(
-                        "Cannot deserialize property container",
-                    )))
-                }
-            },
-            None => property::PropertyContainer::new(),
-        };
-        // remove old property if it exists and sort the properties by name
-        let properties = property_container.get_entries().to_vec();
-        let mut index = None;
-        let mut count = 0;
-        for prop in properties.clone() {
-            if prop.name == property_name {
-                index = Some(count);
-                break;
-            }
-            count = count + 1;
-        }
+/// Whether a reporter may report at the given time; authorizations with
+/// an expiry lapse without needing a RevokeReporter
+fn reporter_is_authorized(reporter: &property::Property_Reporter, timestamp: u64) -> bool {
+    let expires_at = reporter.get_expires_at();
+    reporter.get_authorized() && (expires_at == 0 || timestamp < expires_at)
+}
 
-        match index {
-            Some(x) => {
-                property_container.entries.remove(x);
-            }
-            None => (),
-        };
-        property_container.entries.push(property);
-        property_container.entries.sort_by_key(|p| p.clone().name);
-        let serialized = match property_container.write_to_bytes() {
-            Ok(serialized) => serialized,
-            Err(_) => {
-                return Err(ApplyError::InternalError(String::from(
-                    "Cannot serialize property container",
-                )))
-            }
-        };
-        let mut sets = HashMap::new();
-        sets.insert(address, serialized);
-        self.context
-            .set_state(sets)
-            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
-        Ok(())
+/// Finds an agent's position in a property's reporter list. Reporters are
+/// kept sorted by public key; lists written before that are scanned.
+fn find_reporter(reporters: &[property::Property_Reporter], public_key: &str) -> Option<usize> {
+    match reporters.binary_search_by(|r| r.get_public_key().cmp(public_key)) {
+        Ok(position) => Some(position),
+        Err(_) => reporters.iter().position(|r| r.get_public_key() == public_key),
     }
+}
 
-    pub fn get_property_page(
-        &mut self,
-        record_id: &str,
-        property_name: &str,
-        page: u32,
-    ) -> Result<Option<property::PropertyPage>, ApplyError> {
-        let address = make_property_address(record_id, property_name, page);
-        let d = self.context.get_state(vec![address])?;
-        match d {
-            Some(packed) => {
-                let property_pages: property::PropertyPageContainer =
-                    match protobuf::parse_from_bytes(packed.as_slice()) {
-                        Ok(property_pages) => property_pages,
-                        Err(_) => {
-                            return Err(ApplyError::InternalError(String::from(
-                                "Cannot deserialize property page container",
-                            )))
-                        }
-                    };
+/// The cursor a Record keeps for a property whose next value can be
+/// appended without it: it has one authorized reporter, and no check on
+/// the value needs the property itself
+fn reporting_cursor(prop: &property::Property) -> Option<record::Record_ReportingCursor> {
+    if prop.fixed || prop.get_immutable() || prop.has_threshold() {
+        return None;
+    }
+    let mut authorized = prop.get_reporters().iter().filter(|r| r.get_authorized());
+    let reporter = match (authorized.next(), authorized.next()) {
+        (Some(reporter), None) => reporter,
+        _ => return None,
+    };
+    let mut cursor = record::Record_ReportingCursor::new();
+    cursor.set_property_name(prop.get_name().to_string());
+    cursor.set_data_type(prop.get_data_type());
+    cursor.set_reporter(reporter.get_public_key().to_string());
+    cursor.set_reporter_index(reporter.get_index());
+    cursor.set_expires_at(reporter.get_expires_at());
+    cursor.set_current_page(prop.get_current_page());
+    Some(cursor)
+}
 
-                for property_page in property_pages.get_entries() {
-                    if property_page.name == property_name {
-                        return Ok(Some(property_page.clone()));
-                    }
-                }
-                Ok(None)
-            }
-            None => Ok(None),
-        }
+/// Finds the cursor of a property which the signer may report on its own
+fn find_reporting_cursor<'a>(
+    record: &'a record::Record,
+    name: &str,
+    signer: &str,
+    timestamp: u64,
+) -> Option<&'a record::Record_ReportingCursor> {
+    record.get_reporting_cursors().iter().find(|cursor| {
+        let expires_at = cursor.get_expires_at();
+        cursor.get_property_name() == name
+            && cursor.get_reporter() == signer
+            && (expires_at == 0 || timestamp < expires_at)
+    })
+}
+
+/// Finds the slot of a revoked reporter that no stored value was reported
+/// under, which a new reporter may take over along with its index
+fn free_reporter_slot(prop: &property::Property) -> Option<usize> {
+    if !prop.get_counts_pages() {
+        return None;
     }
+    prop.get_reporters()
+        .iter()
+        .position(|r| !r.get_authorized() && r.get_page_count() == 0)
+}
 
-    pub fn set_property_page(
-        &mut self,
-        record_id: &str,
-        property_name: &str,
-        page_num: u32,
-        property_page: property::PropertyPage,
-    ) -> Result<(), ApplyError> {
-        let address = make_property_address(record_id, property_name, page_num);
-        let d = self.context.get_state(vec![address.clone()])?;
-        let mut property_pages = match d {
-            Some(packed) => match protobuf::parse_from_bytes(packed.as_slice()) {
-                Ok(property_pages) => property_pages,
-                Err(_) => {
-                    return Err(ApplyError::InternalError(String::from(
-                        "Cannot deserialize property page container",
-                    )))
-                }
-            },
-            None => property::PropertyPageContainer::new(),
-        };
-        // remove old property page if it exists and sort the property pages by name
-        let pages = property_pages.get_entries().to_vec();
-        let mut index = None;
-        let mut count = 0;
-        for page in pages.clone() {
-            if page.name == property_name {
-                index = Some(count);
-                break;
-            }
-            count = count + 1;
-        }
+/// Whether a property has no room for another reporter
+fn reporters_full(prop: &property::Property, max_reporters: usize) -> bool {
+    prop.get_reporters().len() >= max_reporters && free_reporter_slot(prop).is_none()
+}
 
-        match index {
-            Some(x) => {
-                property_pages.entries.remove(x);
-            }
-            None => (),
-        };
-        property_pages.entries.push(property_page);
-        property_pages.entries.sort_by_key(|pp| pp.clone().name);
-        let serialized = match property_pages.write_to_bytes() {
-            Ok(serialized) => serialized,
-            Err(_) => {
-                return Err(ApplyError::InternalError(String::from(
-                    "Cannot serialize property page container",
-                )))
-            }
-        };
-        let mut sets = HashMap::new();
-        sets.insert(address, serialized);
-        self.context
-            .set_state(sets)
-            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
-        Ok(())
+/// Counts a page of the property which has come to hold, or no longer
+/// holds, values reported under a reporter's index. Counting pages rather
+/// than values means an update only changes the property with the first
+/// of a reporter's values on each page.
+fn count_reporter_page(prop: &mut property::Property, index: u32, holds_values: bool) {
+    if !prop.get_counts_pages() {
+        return;
     }
+    if let Some(reporter) = prop.mut_reporters().iter_mut().find(|r| r.get_index() == index) {
+        let count = reporter.get_page_count();
+        reporter.set_page_count(if holds_values { count + 1 } else { count.saturating_sub(1) });
+    }
+}
 
-    pub fn get_proposal_container(
-        &mut self,
-        record_id: &str,
-        agent_id: &str,
-    ) -> Result<Option<proposal::ProposalContainer>, ApplyError> {
-        let address = make_proposal_address(record_id, agent_id);
-        let d = self.context.get_state(vec![address])?;
-        match d {
-            Some(packed) => {
-                let proposals: proposal::ProposalContainer =
-                    match protobuf::parse_from_bytes(packed.as_slice()) {
-                        Ok(property_pages) => property_pages,
-                        Err(_) => {
-                            return Err(ApplyError::InternalError(String::from(
-                                "Cannot deserialize proposal container",
-                            )))
-                        }
-                    };
+/// Authorizes an agent to report on a property, reusing the agent's slot
+/// if it has reported before so that its index keeps identifying its
+/// earlier values. A new agent takes over a free slot if there is one.
+fn authorize_reporter(prop: &mut property::Property, public_key: &str, expires_at: u64) {
+    if let Some(position) = find_reporter(prop.get_reporters(), public_key) {
+        let reporter = &mut prop.mut_reporters()[position];
+        reporter.set_authorized(true);
+        reporter.set_expires_at(expires_at);
+        reporter.clear_delegated_by();
+        return;
+    }
 
-                Ok(Some(proposals))
-            }
-            None => Ok(None),
+    let mut reporter = property::Property_Reporter::new();
+    reporter.set_public_key(public_key.to_string());
+    reporter.set_authorized(true);
+    reporter.set_expires_at(expires_at);
+    match free_reporter_slot(prop) {
+        Some(position) => {
+            reporter.set_index(prop.get_reporters()[position].get_index());
+            prop.mut_reporters()[position] = reporter;
+        }
+        None => {
+            reporter.set_index(prop.get_reporters().len() as u32);
+            prop.mut_reporters().push(reporter);
         }
     }
+    prop.mut_reporters()
+        .sort_by(|a, b| a.get_public_key().cmp(b.get_public_key()));
+}
 
-    pub fn set_proposal_container(
-        &mut self,
-        record_id: &str,
-        agent_id: &str,
-        proposals: proposal::ProposalContainer,
-    ) -> Result<(), ApplyError> {
-        let address = make_proposal_address(record_id, agent_id);
-        let serialized = match proposals.write_to_bytes() {
-            Ok(serialized) => serialized,
-            Err(_) => {
-                return Err(ApplyError::InternalError(String::from(
-                    "Cannot serialize proposal container",
-                )))
-            }
-        };
-        let mut sets = HashMap::new();
-        sets.insert(address, serialized);
-        self.context
-            .set_state(sets)
-            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
-        Ok(())
+fn timeline_entry(
+    role: record::RecordTimelineEntry_Role,
+    agent: &record::Record_AssociatedAgent,
+) -> record::RecordTimelineEntry {
+    let mut entry = record::RecordTimelineEntry::new();
+    entry.set_role(role);
+    entry.set_agent_id(agent.get_agent_id().to_string());
+    entry.set_timestamp(agent.get_timestamp());
+    entry
+}
+
+/// Extends a property page's digest chain from `digest` over `values`.
+/// Each link is the SHA-512 of the hex digest before it followed by the
+/// encoded ReportedValue, so a page's chain starts from the digest of the
+/// page before it.
+fn chain_digest(
+    digest: &str,
+    values: &[property::PropertyPage_ReportedValue],
+) -> Result<String, ApplyError> {
+    let mut digest = digest.to_string();
+    for value in values {
+        let serialized = value.write_to_bytes().map_err(|_| {
+            ApplyError::InternalError(String::from("Cannot serialize reported value"))
+        })?;
+        let mut sha = Sha512::new();
+        sha.input(digest.as_bytes());
+        sha.input(&serialized);
+        digest = sha.result_str();
     }
+    Ok(digest)
 }
 
-pub struct SupplyChainTransactionHandler {
-    family_name: String,
-    family_versions: Vec<String>,
-    namespaces: Vec<String>,
+/// Appends the transaction's context to an error message so that a
+/// rejected transaction reported by a user can be matched with the
+/// processor logs. Invalid transactions lead their context with the error
+/// code, and internal errors also carry the transaction signature.
+fn with_context(
+    err: ApplyError,
+    action: Option<&Action>,
+    signer: &str,
+    signature: &str,
+) -> ApplyError {
+    let mut context = match action {
+        Some(action) => format!("action: {}", action.name()),
+        None => String::from("action: unknown"),
+    };
+    if let Some(record_id) = action.and_then(|a| a.record_id()) {
+        context.push_str(&format!(", record: {}", record_id));
+    }
+    context.push_str(&format!(", signer: {}", signer));
+
+    match err {
+        ApplyError::InvalidTransaction(msg) => match split_code(&msg) {
+            (Some(code), msg) => ApplyError::InvalidTransaction(format!(
+                "{} (code: {}, {})",
+                msg, code, context
+            )),
+            (None, msg) => ApplyError::InvalidTransaction(format!("{} ({})", msg, context)),
+        },
+        ApplyError::InternalError(msg) => ApplyError::InternalError(format!(
+            "{} ({}, signature: {})",
+            msg, context, signature
+        )),
+    }
 }
 
-impl SupplyChainTransactionHandler {
-    pub fn new() -> SupplyChainTransactionHandler {
-        SupplyChainTransactionHandler {
-            family_name: "supply_chain".to_string(),
-            family_versions: vec!["1.1".to_string()],
-            namespaces: vec![get_supply_chain_prefix().to_string()],
+/// Rejects an action the signer's role does not permit. RecordTypes are
+/// managed by admins, and auditors may not take actions which would make
+/// them the owner of a Record. Roles are set by SetAgentRole, which checks
+/// the signer itself, as the network's admins may not be Agents.
+fn check_role(action: &Action, role: payload::AgentRole) -> Result<(), ApplyError> {
+    let permitted = match *action {
+        Action::CreateRecordType(_)
+        | Action::UpdateRecordType(_)
+        | Action::DeleteRecordType(_) => role == payload::AgentRole::ADMIN,
+        Action::CreateRecord(_)
+        | Action::CreateRecordFromTemplate(_)
+        | Action::CloneRecord(_)
+        | Action::MergeRecords(_) => {
+            role != payload::AgentRole::AUDITOR
         }
+        _ => true,
+    };
+    if permitted {
+        Ok(())
+    } else {
+        Err(invalid(Code::NotAuthorized, format!(
+            "Agents with the {:?} role cannot {}",
+            role,
+            action.name()
+        )))
     }
+}
 
-    fn _create_agent(
-        &self,
-        payload: payload::CreateAgentAction,
-        mut state: SupplyChainState,
-        signer: &str,
-        timestamp: u64,
-    ) -> Result<(), ApplyError> {
-        let name = payload.get_name();
-        match state.get_agent(signer) {
-            Ok(Some(_)) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Agent already exists: {}",
-                    name
-                )))
-            }
-            Ok(None) => (),
-            Err(err) => return Err(err),
-        }
+/// Identifies which action of a composite an error came from
+fn in_composite(index: usize, action: Option<&Action>, err: ApplyError) -> ApplyError {
+    let prefix = match action {
+        Some(action) => format!("Action {} of composite ({})", index, action.name()),
+        None => format!("Action {} of composite", index),
+    };
+    with_prefix(&prefix, err)
+}
 
-        let mut new_agent = agent::Agent::new();
-        new_agent.set_public_key(signer.to_string());
-        new_agent.set_name(name.to_string());
-        new_agent.set_timestamp(timestamp);
+/// Identifies which Record of a bulk update an error came from
+fn in_bulk_update(record_id: &str, err: ApplyError) -> ApplyError {
+    with_prefix(&format!("Record {} of bulk update", record_id), err)
+}
 
-        state.set_agent(signer, new_agent)?;
-        Ok(())
+/// Puts a prefix in front of an error's message, after its code
+fn with_prefix(prefix: &str, err: ApplyError) -> ApplyError {
+    match err {
+        ApplyError::InvalidTransaction(msg) => match split_code(&msg) {
+            (Some(code), msg) => ApplyError::InvalidTransaction(format!(
+                "[{}] {}: {}",
+                code, prefix, msg
+            )),
+            (None, msg) => ApplyError::InvalidTransaction(format!("{}: {}", prefix, msg)),
+        },
+        ApplyError::InternalError(msg) => {
+            ApplyError::InternalError(format!("{}: {}", prefix, msg))
+        }
     }
+}
 
-    fn _create_record(
-        &self,
-        payload: payload::CreateRecordAction,
-        mut state: SupplyChainState,
-        signer: &str,
-        timestamp: u64,
-    ) -> Result<(), ApplyError> {
-        match state.get_agent(signer) {
-            Ok(Some(_)) => (),
-            Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Agent is not register: {}",
-                    signer
+struct SupplyChainPayload {
+    action: Action,
+    timestamp: u64,
+}
+
+/// Reads a serialized SCPayload's action without decoding the rest of it,
+/// or None if the payload is malformed or its action unknown
+pub fn payload_action(bytes: &[u8]) -> Option<payload::SCPayload_Action> {
+    scan_payload(bytes)
+        .ok()
+        .and_then(|(action_number, _, _)| payload::SCPayload_Action::from_i32(action_number))
+}
+
+/// Splits a serialized SCPayload into its action, its timestamp, and the
+/// still-encoded bytes of each sub-message field. The sub-message slices
+/// borrow from the request payload, so that only the one selected by the
+/// action needs to be copied out and decoded.
+fn scan_payload(bytes: &[u8]) -> ProtobufResult<(i32, u64, Vec<(u32, &[u8])>)> {
+    let mut input = CodedInputStream::from_bytes(bytes);
+    let mut action = 0;
+    let mut timestamp = 0;
+    let mut fields = Vec::new();
+
+    while !input.eof()? {
+        let (field_number, wire_type) = input.read_tag_unpack()?;
+        match (field_number, wire_type) {
+            (1, WireType::WireTypeVarint) => action = input.read_int32()?,
+            (2, WireType::WireTypeVarint) => timestamp = input.read_uint64()?,
+            (1, _) | (2, _) => {
+                return Err(ProtobufError::WireError(WireError::UnexpectedWireType(
+                    wire_type,
                 )))
             }
-            Err(err) => return Err(err),
+            (_, WireType::WireTypeLengthDelimited) => {
+                let len = input.read_raw_varint32()?;
+                let start = input.pos() as usize;
+                input.skip_raw_bytes(len)?;
+                fields.push((field_number, &bytes[start..start + len as usize]));
+            }
+            (_, _) => input.skip_field(wire_type)?,
         }
-        let record_id = payload.get_record_id();
-        match state.get_record(record_id) {
-            Ok(Some(_)) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Record already exists: {}",
-                    record_id
+    }
+
+    Ok((action, timestamp, fields))
+}
+
+/// Splits a serialized CompositeAction into the still-encoded bytes of
+/// each SCPayload in it, in order
+fn scan_composite(bytes: &[u8]) -> ProtobufResult<Vec<&[u8]>> {
+    let mut input = CodedInputStream::from_bytes(bytes);
+    let mut payloads = Vec::new();
+
+    while !input.eof()? {
+        let (field_number, wire_type) = input.read_tag_unpack()?;
+        match (field_number, wire_type) {
+            (1, WireType::WireTypeLengthDelimited) => {
+                let len = input.read_raw_varint32()?;
+                let start = input.pos() as usize;
+                input.skip_raw_bytes(len)?;
+                payloads.push(&bytes[start..start + len as usize]);
+            }
+            (1, _) => {
+                return Err(ProtobufError::WireError(WireError::UnexpectedWireType(
+                    wire_type,
                 )))
             }
-            Ok(None) => (),
-            Err(err) => return Err(err),
+            (_, _) => input.skip_field(wire_type)?,
         }
+    }
 
-        let type_name = payload.get_record_type();
-        let record_type = match state.get_record_type(type_name) {
-            Ok(Some(record_type)) => record_type,
-            Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Record Type does not exist {}",
-                    type_name
+    Ok(payloads)
+}
+
+/// Decodes every occurrence of one sub-message field, merging them as a
+/// full protobuf parse would.
+fn decode_field<M: Message>(fields: &[(u32, &[u8])], field_number: u32) -> Result<M, ApplyError> {
+    let mut message = M::new();
+    for &(number, bytes) in fields {
+        if number == field_number && message.merge_from_bytes(bytes).is_err() {
+            return Err(invalid(Code::MalformedPayload, String::from(
+                "Cannot deserialize payload",
+            )));
+        }
+    }
+    Ok(message)
+}
+
+/// Decodes the authorization of a direct transfer, checking that it is
+/// signed by the receiving agent. The signature depends on nothing in
+/// state, so it is checked with the rest of the payload.
+fn verify_transfer_authorization(
+    direct_transfer: &payload::DirectTransferAction,
+) -> Result<payload::TransferAuthorization, ApplyError> {
+    let authorization: payload::TransferAuthorization =
+        match protobuf::parse_from_bytes(direct_transfer.get_authorization()) {
+            Ok(authorization) => authorization,
+            Err(_) => {
+                return Err(invalid(Code::MalformedPayload, String::from(
+                    "Cannot deserialize transfer authorization",
                 )))
             }
-            Err(err) => return Err(err),
         };
+    if authorization.get_record_id().is_empty() {
+        return Err(invalid(Code::MissingField, String::from(
+            "Record id cannot be empty string",
+        )));
+    }
+    if authorization.get_role() == proposal::Proposal_Role::REPORTER {
+        return Err(invalid(Code::InvalidProposal, String::from(
+            "Only ownership and custodianship can be transferred directly",
+        )));
+    }
+    if authorization.get_expires_at() == 0 {
+        return Err(invalid(Code::MissingField, String::from(
+            "Transfer authorization must have an expiry",
+        )));
+    }
 
-        let mut type_schemata: HashMap<&str, property::PropertySchema> = HashMap::new();
-        let mut required_properties: HashMap<&str, property::PropertySchema> = HashMap::new();
-        let mut provided_properties: HashMap<&str, property::PropertyValue> = HashMap::new();
-        for property in record_type.get_properties() {
-            type_schemata.insert(property.get_name(), property.clone());
-            if property.get_required() {
-                required_properties.insert(property.get_name(), property.clone());
-            }
+    let receiving_agent = authorization.get_receiving_agent();
+    let public_key = match Secp256k1PublicKey::from_hex(receiving_agent) {
+        Ok(public_key) => public_key,
+        Err(_) => {
+            return Err(invalid(Code::InvalidFormat, format!(
+                "Receiving agent is not a public key: {}",
+                receiving_agent
+            )))
         }
+    };
+    let signed = Secp256k1Context::new()
+        .verify(
+            direct_transfer.get_signature(),
+            direct_transfer.get_authorization(),
+            &public_key,
+        )
+        .unwrap_or(false);
+    if !signed {
+        return Err(invalid(Code::NotAuthorized, format!(
+            "Transfer authorization is not signed by the receiving agent: {}",
+            receiving_agent
+        )));
+    }
+    Ok(authorization)
+}
 
-        for property in payload.get_properties() {
-            provided_properties.insert(property.get_name(), property.clone());
+/// Checks that each struct update mask is for one of the properties
+/// being updated, and that no property has more than one
+fn check_struct_masks(
+    properties: &[property::PropertyValue],
+    masks: &[payload::StructUpdateMask],
+) -> Result<(), ApplyError> {
+    let mut masked: Vec<&str> = Vec::new();
+    for mask in masks {
+        let name = mask.get_property();
+        if !properties.iter().any(|p| p.get_name() == name) {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Update mask is for a property not being updated: {}",
+                name
+            )));
+        }
+        if masked.contains(&name) {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Property has more than one update mask: {}",
+                name
+            )));
+        }
+        if mask.get_fields().is_empty() {
+            return Err(invalid(Code::MissingField, format!(
+                "Update mask must name at least one field: {}",
+                name
+            )));
         }
+        masked.push(name);
+    }
+    Ok(())
+}
 
-        for name in required_properties.keys() {
-            if !provided_properties.contains_key(name) {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Required property {} not provided",
-                    name
+/// Checks the parts of a record type's definition which do not depend on
+/// state, for both new types and new versions of existing ones
+fn check_record_type_definition(
+    properties: &[property::PropertySchema],
+    type_rules: &[String],
+    retention_rules: &[record::RetentionRule],
+    policy: &record::FinalizationPolicy,
+) -> Result<(), ApplyError> {
+    if properties.is_empty() {
+        return Err(invalid(Code::MissingField, String::from(
+            "Record type must have at least one property",
+        )));
+    }
+    for prop in properties {
+        if prop.name.is_empty() {
+            return Err(invalid(Code::MissingField, String::from(
+                "Property name cannot be an empty string",
+            )));
+        }
+        if prop.has_threshold() {
+            if prop.data_type != property::PropertySchema_DataType::NUMBER {
+                return Err(invalid(Code::InvalidSchema, format!(
+                    "Only NUMBER properties may have a threshold: {}",
+                    prop.name
+                )));
+            }
+            let threshold = prop.get_threshold();
+            if threshold.get_min() > threshold.get_max() {
+                return Err(invalid(Code::InvalidSchema, format!(
+                    "Threshold minimum is greater than its maximum: {}",
+                    prop.name
                 )));
             }
         }
+        check_number_schema(prop)?;
+        check_allowed_reporters(prop)?;
+    }
+    for rule in type_rules {
+        if let Err(err) = rules::parse(rule) {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Invalid rule {}: {}",
+                rule, err
+            )));
+        }
+    }
+    for (i, retention) in retention_rules.iter().enumerate() {
+        let name = retention.get_property_name();
+        if !properties.iter().any(|prop| prop.get_name() == name) {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Retention rule names an unknown property: {}",
+                name
+            )));
+        }
+        if retention_rules[..i]
+            .iter()
+            .any(|other| other.get_property_name() == name)
+        {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Property has more than one retention rule: {}",
+                name
+            )));
+        }
+    }
+    check_finalization_policy(properties, policy)
+}
 
-        for (provided_name, provided_properties) in provided_properties.clone() {
-            let required_type = match type_schemata.get(provided_name) {
-                Some(required_type) => required_type.data_type,
-                None => {
-                    return Err(ApplyError::InvalidTransaction(format!(
-                        "Provided property {} is not in schemata",
-                        provided_name
+fn check_finalization_policy(
+    properties: &[property::PropertySchema],
+    policy: &record::FinalizationPolicy,
+) -> Result<(), ApplyError> {
+    let find_schema = |name: &str| properties.iter().find(|prop| prop.get_name() == name);
+    for name in policy.get_reported_properties() {
+        if find_schema(name).is_none() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Finalization policy names an unknown property: {}",
+                name
+            )));
+        }
+    }
+
+    let bounds = policy.get_bounds();
+    for (i, bound) in bounds.iter().enumerate() {
+        let name = bound.get_property_name();
+        match find_schema(name) {
+            Some(schema) if schema.data_type == property::PropertySchema_DataType::NUMBER => (),
+            Some(_) => {
+                return Err(invalid(Code::InvalidSchema, format!(
+                    "Only NUMBER properties may have a finalization bound: {}",
+                    name
+                )))
+            }
+            None => {
+                return Err(invalid(Code::InvalidSchema, format!(
+                    "Finalization policy names an unknown property: {}",
+                    name
+                )))
+            }
+        }
+        if bound.get_min() > bound.get_max() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Finalization bound minimum is greater than its maximum: {}",
+                name
+            )));
+        }
+        if bounds[..i]
+            .iter()
+            .any(|other| other.get_property_name() == name)
+        {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Property has more than one finalization bound: {}",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes and checks the action selected by an action number, from the
+/// fields scanned out of the SCPayload it was sent in
+fn parse_action(action_number: i32, fields: &[(u32, &[u8])]) -> Result<Action, ApplyError> {
+    let supply_chain_action = match payload::SCPayload_Action::from_i32(action_number) {
+        Some(action) => action,
+        None => {
+            return Err(invalid(Code::MalformedPayload, format!(
+                "Unknown payload action: {}",
+                action_number
+            )))
+        }
+    };
+
+    // Field numbers are those of the action messages in SCPayload
+    let action = match supply_chain_action {
+        payload::SCPayload_Action::CREATE_AGENT => {
+            let create_agent: payload::CreateAgentAction = decode_field(fields, 3)?;
+            if create_agent.get_name() == "" {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Agent name cannot be an empty string",
+                )));
+            }
+            Action::CreateAgent(create_agent)
+        }
+        payload::SCPayload_Action::CREATE_RECORD => {
+            let create_record: payload::CreateRecordAction = decode_field(fields, 4)?;
+            if create_record.get_record_id() == "" {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            Action::CreateRecord(create_record)
+        }
+        payload::SCPayload_Action::FINALIZE_RECORD => {
+            Action::FinalizeRecord(decode_field(fields, 5)?)
+        }
+        payload::SCPayload_Action::CREATE_RECORD_TYPE => {
+            let create_record_type: payload::CreateRecordTypeAction =
+                decode_field(fields, 6)?;
+            if create_record_type.get_name() == "" {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record Type name cannot be an empty string",
+                )));
+            };
+            let name = create_record_type.get_name();
+            if name.starts_with('.') || name.ends_with('.') {
+                return Err(invalid(Code::InvalidFormat, format!(
+                    "Record Type name cannot start or end with a period: {}",
+                    name
+                )));
+            }
+            check_record_type_definition(
+                create_record_type.get_properties(),
+                create_record_type.get_rules(),
+                create_record_type.get_retention_rules(),
+                create_record_type.get_finalization_policy(),
+            )?;
+
+            Action::CreateRecordType(create_record_type)
+        }
+        payload::SCPayload_Action::UPDATE_PROPERTIES => {
+            let update: payload::UpdatePropertiesAction = decode_field(fields, 7)?;
+            check_struct_masks(update.get_properties(), update.get_struct_masks())?;
+            Action::UpdateProperties(update)
+        }
+        payload::SCPayload_Action::CREATE_PROPOSAL => {
+            Action::CreateProposal(decode_field(fields, 8)?)
+        }
+        payload::SCPayload_Action::ANSWER_PROPOSAL => {
+            Action::AnswerProposal(decode_field(fields, 9)?)
+        }
+        payload::SCPayload_Action::REVOKE_REPORTER => {
+            Action::RevokeReporter(decode_field(fields, 10)?)
+        }
+        payload::SCPayload_Action::EXECUTE_TRANSFER => {
+            let execute_transfer: payload::ExecuteTransferAction =
+                decode_field(fields, 11)?;
+            if execute_transfer.get_role() == proposal::Proposal_Role::REPORTER {
+                return Err(invalid(Code::InvalidProposal, String::from(
+                    "Only ownership and custodianship transfers can be executed",
+                )));
+            }
+            Action::ExecuteTransfer(execute_transfer)
+        }
+        payload::SCPayload_Action::MESSAGE => {
+            let message: payload::MessageAction = decode_field(fields, 12)?;
+            if !is_hex_digest(message.get_digest(), 128) {
+                return Err(invalid(Code::InvalidFormat, String::from(
+                    "Message digest must be a lowercase hex-encoded SHA-512 hash",
+                )));
+            }
+            Action::Message(message)
+        }
+        payload::SCPayload_Action::SUBSCRIBE => {
+            let subscribe: payload::SubscribeAction = decode_field(fields, 13)?;
+            if subscribe.get_events().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Subscription must include at least one event",
+                )));
+            }
+            let held_records =
+                subscribe.get_scope() == subscription::Subscription_Scope::HELD_RECORDS;
+            if held_records != subscribe.get_target().is_empty() {
+                return Err(invalid(Code::InvalidSubscription, String::from(
+                    "Only HELD_RECORDS subscriptions have an empty target",
+                )));
+            }
+            Action::Subscribe(subscribe)
+        }
+        payload::SCPayload_Action::UNSUBSCRIBE => {
+            Action::Unsubscribe(decode_field(fields, 14)?)
+        }
+        payload::SCPayload_Action::COMPOSITE => {
+            let mut composite = Vec::new();
+            for &(number, bytes) in fields {
+                if number == 15 {
+                    composite.extend_from_slice(bytes);
+                }
+            }
+            let payloads = match scan_composite(&composite) {
+                Ok(payloads) => payloads,
+                Err(_) => {
+                    return Err(invalid(Code::MalformedPayload, String::from(
+                        "Cannot deserialize payload",
                     )))
                 }
             };
-            let provided_type = provided_properties.data_type;
-            if provided_type != required_type {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Value provided for {} is the wrong type",
-                    provided_name
+            if payloads.is_empty() {
+                return Err(invalid(Code::InvalidComposite, String::from(
+                    "Composite action must include at least one action",
                 )));
-            };
+            }
+            if payloads.len() > MAX_COMPOSITE_ACTIONS {
+                return Err(invalid(Code::InvalidComposite, format!(
+                    "Composite action has more than {} actions",
+                    MAX_COMPOSITE_ACTIONS
+                )));
+            }
 
-            let is_delayed = match type_schemata.get(provided_name) {
-                Some(property_schema) => property_schema.delayed,
-                None => false,
-            };
-            if is_delayed {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Property is 'delayed', and cannot be set at record creation: {}",
-                    provided_name
+            let mut actions = Vec::with_capacity(payloads.len());
+            for (i, bytes) in payloads.into_iter().enumerate() {
+                let (action_number, _, fields) = match scan_payload(bytes) {
+                    Ok(scanned) => scanned,
+                    Err(_) => {
+                        return Err(invalid(Code::MalformedPayload, format!(
+                            "Cannot deserialize action {} of composite",
+                            i
+                        )))
+                    }
+                };
+                if action_number == payload::SCPayload_Action::COMPOSITE.value() {
+                    return Err(invalid(Code::InvalidComposite, String::from(
+                        "Composite actions cannot be nested",
+                    )));
+                }
+                match parse_action(action_number, &fields) {
+                    Ok(action) => actions.push(action),
+                    Err(err) => return Err(in_composite(i, None, err)),
+                }
+            }
+            Action::Composite(actions)
+        }
+        payload::SCPayload_Action::RECEIVE_CONFIRMATION => {
+            let confirmation: payload::ReceiveConfirmationAction = decode_field(fields, 16)?;
+            if confirmation.get_checks().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Receipt confirmation must include at least one condition check",
                 )));
-            };
+            }
+            let mut items: Vec<&str> = Vec::new();
+            for check in confirmation.get_checks() {
+                if check.get_item() == "" {
+                    return Err(invalid(Code::MissingField, String::from(
+                        "Condition check item cannot be an empty string",
+                    )));
+                }
+                if items.contains(&check.get_item()) {
+                    return Err(invalid(Code::Duplicate, format!(
+                        "Condition check item is repeated: {}",
+                        check.get_item()
+                    )));
+                }
+                items.push(check.get_item());
+            }
+            Action::ReceiveConfirmation(confirmation)
         }
-        let mut new_record = record::Record::new();
-        new_record.set_record_id(record_id.to_string());
-        new_record.set_record_type(type_name.to_string());
-        new_record.set_field_final(false);
+        payload::SCPayload_Action::ANCHOR_STATE => {
+            let anchor: payload::AnchorStateAction = decode_field(fields, 17)?;
+            if anchor.get_service() == "" {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Anchor service cannot be an empty string",
+                )));
+            }
+            if !is_hex_digest(anchor.get_block_id(), 128) {
+                return Err(invalid(Code::InvalidFormat, String::from(
+                    "Anchor block id must be a lowercase hex-encoded block signature",
+                )));
+            }
+            if !is_hex_digest(anchor.get_state_root(), 64) {
+                return Err(invalid(Code::InvalidFormat, String::from(
+                    "Anchor state root must be a lowercase hex-encoded merkle root",
+                )));
+            }
+            if anchor.get_receipt().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Anchor must include the service's receipt",
+                )));
+            }
+            Action::AnchorState(anchor)
+        }
+        payload::SCPayload_Action::CLONE_RECORD => {
+            Action::CloneRecord(decode_field(fields, 18)?)
+        }
+        payload::SCPayload_Action::ANCHOR_FEEDBACK => {
+            let feedback: payload::AnchorFeedbackAction = decode_field(fields, 19)?;
+            if !is_hex_digest(feedback.get_digest(), 128) {
+                return Err(invalid(Code::InvalidFormat, String::from(
+                    "Feedback digest must be a lowercase hex-encoded SHA-512 hash",
+                )));
+            }
+            Action::AnchorFeedback(feedback)
+        }
+        payload::SCPayload_Action::MIGRATE_STATE => {
+            let migration: payload::MigrateStateAction = decode_field(fields, 20)?;
+            let writes = migration.get_writes();
+            if writes.is_empty() || writes.len() > MAX_MIGRATION_WRITES {
+                return Err(invalid(Code::LimitReached, format!(
+                    "Migration must have between 1 and {} writes",
+                    MAX_MIGRATION_WRITES
+                )));
+            }
+            let prefix = get_supply_chain_prefix();
+            let mut addresses = Vec::with_capacity(writes.len());
+            for write in writes {
+                let address = write.get_address();
+                if !address.starts_with(&prefix) || !is_hex_digest(address, 70) {
+                    return Err(invalid(Code::InvalidFormat, format!(
+                        "Migration address is not in the supply_chain namespace: {}",
+                        address
+                    )));
+                }
+                if addresses.contains(&address) {
+                    return Err(invalid(Code::Duplicate, format!(
+                        "Migration writes address more than once: {}",
+                        address
+                    )));
+                }
+                addresses.push(address);
+            }
+            Action::MigrateState(migration)
+        }
+        payload::SCPayload_Action::DELETE_RECORD_TYPE => {
+            let delete_record_type: payload::DeleteRecordTypeAction =
+                decode_field(fields, 21)?;
+            if delete_record_type.get_name() == "" {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record Type name cannot be an empty string",
+                )));
+            }
+            Action::DeleteRecordType(delete_record_type)
+        }
+        payload::SCPayload_Action::UPDATE_RECORD_TYPE => {
+            let update_record_type: payload::UpdateRecordTypeAction = decode_field(fields, 23)?;
+            if update_record_type.get_name() == "" {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record Type name cannot be an empty string",
+                )));
+            }
+            check_record_type_definition(
+                update_record_type.get_properties(),
+                update_record_type.get_rules(),
+                update_record_type.get_retention_rules(),
+                update_record_type.get_finalization_policy(),
+            )?;
+            Action::UpdateRecordType(update_record_type)
+        }
+        payload::SCPayload_Action::DIRECT_TRANSFER => {
+            let direct_transfer: payload::DirectTransferAction = decode_field(fields, 24)?;
+            Action::DirectTransfer(verify_transfer_authorization(&direct_transfer)?)
+        }
+        payload::SCPayload_Action::BULK_UPDATE_PROPERTIES => {
+            let bulk: payload::BulkUpdatePropertiesAction = decode_field(fields, 25)?;
+            let record_ids = bulk.get_record_ids();
+            if record_ids.is_empty() || record_ids.len() > MAX_BULK_UPDATE_RECORDS {
+                return Err(invalid(Code::LimitReached, format!(
+                    "Bulk update must have between 1 and {} records",
+                    MAX_BULK_UPDATE_RECORDS
+                )));
+            }
+            check_struct_masks(bulk.get_properties(), bulk.get_struct_masks())?;
 
-        let mut owner = record::Record_AssociatedAgent::new();
-        owner.set_agent_id(signer.to_string());
-        owner.set_timestamp(timestamp);
-        new_record.owners.push(owner.clone());
-        new_record.custodians.push(owner.clone());
+            let mut seen = BTreeSet::new();
+            let mut updates = Vec::with_capacity(record_ids.len());
+            for record_id in record_ids {
+                if record_id.is_empty() {
+                    return Err(invalid(Code::MissingField, String::from(
+                        "Record id cannot be empty string",
+                    )));
+                }
+                if !seen.insert(record_id) {
+                    return Err(invalid(Code::Duplicate, format!(
+                        "Bulk update names record more than once: {}",
+                        record_id
+                    )));
+                }
+                let mut update = payload::UpdatePropertiesAction::new();
+                update.set_record_id(record_id.clone());
+                update.set_properties(bulk.get_properties().iter().cloned().collect());
+                update.set_struct_masks(bulk.get_struct_masks().iter().cloned().collect());
+                updates.push(update);
+            }
+            Action::BulkUpdateProperties(bulk.get_policy(), updates)
+        }
+        payload::SCPayload_Action::DELEGATE_REPORTER => {
+            let delegation: payload::DelegateReporterAction = decode_field(fields, 26)?;
+            if delegation.get_properties().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Property list cannot be empty for a delegation",
+                )));
+            }
+            Action::DelegateReporter(delegation)
+        }
+        payload::SCPayload_Action::MERGE_RECORDS => {
+            let merge: payload::MergeRecordsAction = decode_field(fields, 27)?;
+            let record_id = merge.get_record_id();
+            if record_id.is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            let parent_ids = merge.get_parent_ids();
+            if parent_ids.len() < 2 || parent_ids.len() > MAX_MERGE_RECORDS {
+                return Err(invalid(Code::LimitReached, format!(
+                    "A merge must combine between 2 and {} records",
+                    MAX_MERGE_RECORDS
+                )));
+            }
+            let mut seen = BTreeSet::new();
+            for parent_id in parent_ids {
+                if !seen.insert(parent_id) {
+                    return Err(invalid(Code::Duplicate, format!(
+                        "Merge names record more than once: {}",
+                        parent_id
+                    )));
+                }
+            }
+            Action::MergeRecords(merge)
+        }
+        payload::SCPayload_Action::ARCHIVE_RECORD => {
+            let archive: payload::ArchiveRecordAction = decode_field(fields, 28)?;
+            if archive.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            Action::ArchiveRecord(archive)
+        }
+        payload::SCPayload_Action::REOPEN_RECORD => {
+            let reopen: payload::ReopenRecordAction = decode_field(fields, 29)?;
+            if reopen.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            Action::ReopenRecord(reopen)
+        }
+        payload::SCPayload_Action::RECALL_RECORD => {
+            let recall: payload::RecallRecordAction = decode_field(fields, 30)?;
+            if recall.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            if recall.get_reason().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "A recall must give a reason",
+                )));
+            }
+            Action::RecallRecord(recall)
+        }
+        payload::SCPayload_Action::SET_AGENT_ROLE => {
+            let set_role: payload::SetAgentRoleAction = decode_field(fields, 31)?;
+            if set_role.get_agent_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Agent id cannot be empty string",
+                )));
+            }
+            Action::SetAgentRole(set_role)
+        }
+        payload::SCPayload_Action::DEACTIVATE_AGENT => {
+            let deactivate: payload::DeactivateAgentAction = decode_field(fields, 37)?;
+            if deactivate.get_agent_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Agent id cannot be empty string",
+                )));
+            }
+            Action::DeactivateAgent(deactivate)
+        }
+        payload::SCPayload_Action::CREATE_RECORD_TEMPLATE => {
+            let template: payload::CreateRecordTemplateAction = decode_field(fields, 38)?;
+            if template.get_name().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record Template name cannot be an empty string",
+                )));
+            }
+            if template.get_record_type().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record Type name cannot be an empty string",
+                )));
+            }
+            Action::CreateRecordTemplate(template)
+        }
+        payload::SCPayload_Action::CREATE_RECORD_FROM_TEMPLATE => {
+            let create_record: payload::CreateRecordFromTemplateAction =
+                decode_field(fields, 39)?;
+            if create_record.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            if create_record.get_template().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record Template name cannot be an empty string",
+                )));
+            }
+            Action::CreateRecordFromTemplate(create_record)
+        }
+        payload::SCPayload_Action::SET_RECORD_METADATA => {
+            let metadata: payload::SetRecordMetadataAction = decode_field(fields, 32)?;
+            if metadata.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            if metadata.get_entries().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Metadata entries cannot be empty",
+                )));
+            }
+            let mut seen = BTreeSet::new();
+            for entry in metadata.get_entries() {
+                let key = entry.get_key();
+                if key.is_empty() {
+                    return Err(invalid(Code::MissingField, String::from(
+                        "Metadata key cannot be empty string",
+                    )));
+                }
+                if key.len() > MAX_METADATA_KEY_LENGTH
+                    || entry.get_value().len() > MAX_METADATA_VALUE_LENGTH
+                {
+                    return Err(invalid(Code::LimitReached, format!(
+                        "Metadata keys are limited to {} bytes and values to {}: {}",
+                        MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH, key
+                    )));
+                }
+                if !seen.insert(key) {
+                    return Err(invalid(Code::Duplicate, format!(
+                        "Metadata key is set more than once: {}",
+                        key
+                    )));
+                }
+            }
+            Action::SetRecordMetadata(metadata)
+        }
+        payload::SCPayload_Action::ATTEST_AUDIT => {
+            let attestation: payload::AttestAuditAction = decode_field(fields, 33)?;
+            if attestation.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            if attestation.get_reviewed_through() == 0 {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Audit must give the time it reviewed through",
+                )));
+            }
+            if !is_hex_digest(attestation.get_digest(), 128) {
+                return Err(invalid(Code::InvalidFormat, String::from(
+                    "Audit digest must be a lowercase hex-encoded SHA-512 hash",
+                )));
+            }
+            Action::AttestAudit(attestation)
+        }
+        payload::SCPayload_Action::CANCEL_ALL_PROPOSALS => {
+            let cancellation: payload::CancelAllProposalsAction = decode_field(fields, 34)?;
+            if cancellation.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            let receiving_agents = cancellation.get_receiving_agents();
+            if receiving_agents.len() > MAX_CANCELED_RECEIVING_AGENTS {
+                return Err(invalid(Code::LimitReached, format!(
+                    "Cancellation cannot name more than {} receiving agents",
+                    MAX_CANCELED_RECEIVING_AGENTS
+                )));
+            }
+            if receiving_agents.iter().any(|agent| agent.is_empty()) {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Receiving agent cannot be empty string",
+                )));
+            }
+            Action::CancelAllProposals(cancellation)
+        }
+        payload::SCPayload_Action::AMEND_PROPOSAL => {
+            Action::AmendProposal(decode_field(fields, 35)?)
+        }
+        payload::SCPayload_Action::RELEASE_ESCROW => {
+            let release: payload::ReleaseEscrowAction = decode_field(fields, 36)?;
+            if release.get_record_id().is_empty() {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Record id cannot be empty string",
+                )));
+            }
+            Action::ReleaseEscrow(release)
+        }
+        payload::SCPayload_Action::ACKNOWLEDGE_VALUE => {
+            let acknowledgment: payload::AcknowledgeValueAction = decode_field(fields, 22)?;
+            if acknowledgment.get_property_name() == "" {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Property name cannot be an empty string",
+                )));
+            }
+            Action::AcknowledgeValue(acknowledgment)
+        }
+    };
+    Ok(action)
+}
 
-        state.set_record(record_id, new_record)?;
+/// The version number of a RecordType, or of the RecordType a Record was
+/// created with. Those from before versions were kept have 0, which is
+/// the first version.
+fn type_version(version: u32) -> u32 {
+    cmp::max(version, 1)
+}
 
-        let mut reporter = property::Property_Reporter::new();
-        reporter.set_public_key(signer.to_string());
-        reporter.set_authorized(true);
-        reporter.set_index(0);
+/// The namespace of a record type name, which is the part before its
+/// first period, as in "acme.fish_lot"
+fn record_type_namespace(name: &str) -> Option<&str> {
+    name.find('.').map(|period| &name[..period])
+}
 
-        for (property_name, property) in type_schemata {
-            let mut new_property = property::Property::new();
-            new_property.set_name(property_name.to_string());
-            new_property.set_record_id(record_id.to_string());
-            new_property.set_data_type(property.get_data_type());
-            new_property.reporters.push(reporter.clone());
-            new_property.set_current_page(1);
-            new_property.set_wrapped(false);
-            new_property.set_fixed(property.get_fixed());
-            new_property.set_number_exponent(property.get_number_exponent());
-            new_property.set_enum_options(
-                RepeatedField::from_vec(property.get_enum_options().to_vec()));
-            new_property.set_struct_properties(
-                RepeatedField::from_vec(property.get_struct_properties().to_vec()));
-            new_property.set_unit(property.get_unit().to_string());
+fn is_hex_digest(value: &str, length: usize) -> bool {
+    value.len() == length && value.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
 
-            state.set_property(record_id, property_name, new_property.clone())?;
+impl SupplyChainPayload {
+    pub fn new(payload: &[u8]) -> Result<Option<SupplyChainPayload>, ApplyError> {
+        let (action_number, timestamp, fields) = match scan_payload(payload) {
+            Ok(scanned) => scanned,
+            Err(_) => {
+                return Err(invalid(Code::MalformedPayload, String::from(
+                    "Cannot deserialize payload",
+                )))
+            }
+        };
 
-            let mut new_property_page = property::PropertyPage::new();
-            new_property_page.set_name(property_name.to_string());
-            new_property_page.set_record_id(record_id.to_string());
+        let action = parse_action(action_number, &fields)?;
+        let timestamp = match timestamp {
+            0 => {
+                return Err(invalid(Code::MalformedPayload, String::from(
+                    "Timestamp is not set",
+                )))
+            }
+            x => x,
+        };
 
-            if provided_properties.contains_key(property_name) {
-                let provided_property = &provided_properties[property_name];
-                let reported_value = match self._make_new_reported_value(
-                    0,
-                    timestamp,
-                    provided_property,
-                    &new_property,
-                ) {
-                    Ok(reported_value) => reported_value,
-                    Err(err) => return Err(err),
-                };
+        Ok(Some(SupplyChainPayload {
+            action: action,
+            timestamp: timestamp,
+        }))
+    }
 
-                new_property_page.reported_values.push(reported_value);
+    pub fn get_action(&self) -> &Action {
+        &self.action
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+pub struct SupplyChainState<'a> {
+    context: &'a mut dyn StateContext,
+    // Containers decoded during this transaction, keyed by address. Each
+    // value is an Option of the container type, with None recording that
+    // nothing is stored at the address. Setters update the cached copy
+    // as they write, so a get followed by a set reads and decodes the
+    // address only once.
+    containers: HashMap<String, Box<dyn Any>>,
+    // Property schemas of the record types looked up during this
+    // transaction, keyed by type name and version and then property name.
+    // A version's schemas never change once created, so its name and
+    // number identify them, and they are indexed once however many are
+    // read.
+    schemas: HashMap<(String, u32), HashMap<String, property::PropertySchema>>,
+}
+
+impl<'a> SupplyChainState<'a> {
+    pub fn new(context: &'a mut dyn StateContext) -> SupplyChainState {
+        SupplyChainState {
+            context: context,
+            containers: HashMap::new(),
+            schemas: HashMap::new(),
+        }
+    }
+
+    fn load_container<M: Message>(
+        &mut self,
+        address: &str,
+        name: &str,
+    ) -> Result<&mut Option<M>, ApplyError> {
+        if !self.containers.contains_key(address) {
+            let container: Option<M> = match self.context.get_state(vec![address.to_string()])? {
+                Some(packed) => match protobuf::parse_from_bytes(packed.as_slice()) {
+                    Ok(container) => Some(container),
+                    Err(_) => {
+                        return Err(ApplyError::InternalError(format!(
+                            "Cannot deserialize {} container",
+                            name
+                        )))
+                    }
+                },
+                None => None,
+            };
+            self.containers
+                .insert(address.to_string(), Box::new(container));
+        }
+
+        match self
+            .containers
+            .get_mut(address)
+            .and_then(|container| container.downcast_mut::<Option<M>>())
+        {
+            Some(container) => Ok(container),
+            None => Err(ApplyError::InternalError(format!(
+                "Cannot deserialize {} container",
+                name
+            ))),
+        }
+    }
+
+    fn take_container<M: Message>(&mut self, address: &str, name: &str) -> Result<M, ApplyError> {
+        Ok(self
+            .load_container(address, name)?
+            .take()
+            .unwrap_or_else(M::new))
+    }
+
+    fn store_container<M: Message>(
+        &mut self,
+        address: &str,
+        name: &str,
+        container: M,
+    ) -> Result<(), ApplyError> {
+        let serialized = match container.write_to_bytes() {
+            Ok(serialized) => serialized,
+            Err(_) => {
+                return Err(ApplyError::InternalError(format!(
+                    "Cannot serialize {} container",
+                    name
+                )))
+            }
+        };
+        let mut sets = HashMap::new();
+        sets.insert(address.to_string(), serialized);
+        self.context
+            .set_state(sets)
+            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
+        self.containers
+            .insert(address.to_string(), Box::new(Some(container)));
+        Ok(())
+    }
+
+    /// Deletes whatever is stored at an address, recording that nothing is
+    /// there now
+    fn delete_container<M: Message>(&mut self, address: &str) -> Result<(), ApplyError> {
+        self.context
+            .delete_state(vec![address.to_string()])
+            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
+        self.containers
+            .insert(address.to_string(), Box::new(None::<M>));
+        Ok(())
+    }
+
+    /// Writes bytes to an address without decoding them, dropping any
+    /// container cached for it
+    pub fn set_raw(&mut self, address: &str, data: Vec<u8>) -> Result<(), ApplyError> {
+        let mut sets = HashMap::new();
+        sets.insert(address.to_string(), data);
+        self.context
+            .set_state(sets)
+            .map_err(|err| ApplyError::InternalError(format!("{}", err)))?;
+        self.containers.remove(address);
+        Ok(())
+    }
+
+    /// Emits a supply_chain event about a record, identifying the record
+    /// and its current holders so that notification services can match
+    /// it against subscriptions without reading state
+    pub fn add_record_event(
+        &mut self,
+        name: &str,
+        record: &record::Record,
+        details: Vec<(&str, String)>,
+    ) -> Result<(), ApplyError> {
+        let holder = |agents: &[record::Record_AssociatedAgent]| {
+            agents
+                .last()
+                .map(|agent| agent.get_agent_id().to_string())
+                .unwrap_or_default()
+        };
+        let mut attributes = vec![
+            (String::from("record_id"), record.get_record_id().to_string()),
+            (String::from("record_type"), record.get_record_type().to_string()),
+            (String::from("owner"), holder(record.get_owners())),
+            (String::from("custodian"), holder(record.get_custodians())),
+        ];
+        attributes.extend(details.into_iter().map(|(key, value)| (key.to_string(), value)));
+        self.context
+            .add_event(format!("supply_chain/{}", name), attributes, &[])
+            .map_err(|err| ApplyError::InternalError(format!("{}", err)))
+    }
+
+    pub fn get_setting(&mut self, key: &str) -> Result<Option<String>, ApplyError> {
+        let address = make_setting_address(key);
+        match *self.load_container::<Setting>(&address, "setting")? {
+            Some(ref setting) => Ok(setting
+                .get_entries()
+                .iter()
+                .find(|entry| entry.get_key() == key)
+                .map(|entry| entry.get_value().to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a numeric limit from settings, falling back to its default
+    pub fn get_limit_setting(&mut self, key: &str, default: usize) -> Result<usize, ApplyError> {
+        match self.get_setting(key)? {
+            Some(value) => match value.parse::<usize>() {
+                Ok(limit) => Ok(limit),
+                Err(_) => Err(ApplyError::InternalError(format!(
+                    "Setting {} is not a number: {}",
+                    key, value
+                ))),
+            },
+            None => Ok(default),
+        }
+    }
+
+    pub fn get_record(&mut self, record_id: &str) -> Result<Option<record::Record>, ApplyError> {
+        let address = make_record_address(record_id);
+        match *self.load_container::<record::RecordContainer>(&address, "record")? {
+            Some(ref records) => Ok(records
+                .get_entries()
+                .iter()
+                .find(|record| record.record_id == record_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_record(
+        &mut self,
+        record_id: &str,
+        mut record: record::Record,
+    ) -> Result<(), ApplyError> {
+        let address = make_record_address(record_id);
+        let mut record_container: record::RecordContainer =
+            self.take_container(&address, "record")?;
+        // Reporting cursors are only kept by set_property, so a Record read
+        // before one of its Properties was written still has the latest
+        if let Some(stored) = record_container
+            .entries
+            .iter_mut()
+            .find(|r| r.record_id == record_id)
+        {
+            record.set_reporting_cursors(stored.take_reporting_cursors());
+        }
+        // remove old record if it exists and sort the records by record id
+        record_container.entries.retain(|r| r.record_id != record_id);
+        record_container.entries.push(record);
+        record_container
+            .entries
+            .sort_by(|a, b| a.record_id.cmp(&b.record_id));
+        self.store_container(&address, "record", record_container)
+    }
+
+    pub fn get_record_timeline_page(
+        &mut self,
+        record_id: &str,
+        page: u32,
+    ) -> Result<Option<record::RecordTimelinePage>, ApplyError> {
+        let address = make_record_timeline_address(record_id, page);
+        match *self.load_container::<record::RecordTimelinePageContainer>(
+            &address,
+            "record timeline page",
+        )? {
+            Some(ref timeline_pages) => Ok(timeline_pages
+                .get_entries()
+                .iter()
+                .find(|timeline_page| timeline_page.record_id == record_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_record_timeline_page(
+        &mut self,
+        record_id: &str,
+        page_num: u32,
+        timeline_page: record::RecordTimelinePage,
+    ) -> Result<(), ApplyError> {
+        let address = make_record_timeline_address(record_id, page_num);
+        let mut timeline_pages: record::RecordTimelinePageContainer =
+            self.take_container(&address, "record timeline page")?;
+        // remove old timeline page if it exists and sort the pages by record id
+        timeline_pages
+            .entries
+            .retain(|tp| tp.record_id != record_id);
+        timeline_pages.entries.push(timeline_page);
+        timeline_pages
+            .entries
+            .sort_by(|a, b| a.record_id.cmp(&b.record_id));
+        self.store_container(&address, "record timeline page", timeline_pages)
+    }
+
+    pub fn get_feedback_log(
+        &mut self,
+        record_id: &str,
+    ) -> Result<Option<feedback::FeedbackLog>, ApplyError> {
+        let address = make_feedback_log_address(record_id);
+        match *self.load_container::<feedback::FeedbackLogContainer>(&address, "feedback log")? {
+            Some(ref logs) => Ok(logs
+                .get_entries()
+                .iter()
+                .find(|log| log.record_id == record_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_feedback_log(
+        &mut self,
+        record_id: &str,
+        log: feedback::FeedbackLog,
+    ) -> Result<(), ApplyError> {
+        let address = make_feedback_log_address(record_id);
+        let mut logs: feedback::FeedbackLogContainer =
+            self.take_container(&address, "feedback log")?;
+        // remove old log if it exists and sort the logs by record id
+        logs.entries.retain(|l| l.record_id != record_id);
+        logs.entries.push(log);
+        logs.entries.sort_by(|a, b| a.record_id.cmp(&b.record_id));
+        self.store_container(&address, "feedback log", logs)
+    }
+
+    pub fn get_feedback_page(
+        &mut self,
+        record_id: &str,
+        page: u32,
+    ) -> Result<Option<feedback::FeedbackPage>, ApplyError> {
+        let address = make_feedback_address(record_id, page);
+        match *self.load_container::<feedback::FeedbackPageContainer>(&address, "feedback page")? {
+            Some(ref pages) => Ok(pages
+                .get_entries()
+                .iter()
+                .find(|page| page.record_id == record_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_feedback_page(
+        &mut self,
+        record_id: &str,
+        page_num: u32,
+        page: feedback::FeedbackPage,
+    ) -> Result<(), ApplyError> {
+        let address = make_feedback_address(record_id, page_num);
+        let mut pages: feedback::FeedbackPageContainer =
+            self.take_container(&address, "feedback page")?;
+        // remove old page if it exists and sort the pages by record id
+        pages.entries.retain(|p| p.record_id != record_id);
+        pages.entries.push(page);
+        pages.entries.sort_by(|a, b| a.record_id.cmp(&b.record_id));
+        self.store_container(&address, "feedback page", pages)
+    }
+
+    pub fn get_record_type(
+        &mut self,
+        type_name: &str,
+    ) -> Result<Option<record::RecordType>, ApplyError> {
+        let address = make_record_type_address(type_name);
+        match *self.load_container::<record::RecordTypeContainer>(&address, "record type")? {
+            Some(ref record_types) => Ok(record_types
+                .get_entries()
+                .iter()
+                .find(|record_type| record_type.name == type_name)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds a version of a record type, which is either its current
+    /// version or one kept at an address of its own since
+    pub fn get_record_type_version(
+        &mut self,
+        type_name: &str,
+        version: u32,
+    ) -> Result<Option<record::RecordType>, ApplyError> {
+        let version = type_version(version);
+        if let Some(current) = self.get_record_type(type_name)? {
+            if type_version(current.get_version()) == version {
+                return Ok(Some(current));
+            }
+        }
+
+        let address = make_record_type_version_address(type_name, version);
+        match *self.load_container::<record::RecordTypeContainer>(
+            &address,
+            "record type version",
+        )? {
+            Some(ref record_types) => Ok(record_types
+                .get_entries()
+                .iter()
+                .find(|record_type| record_type.name == type_name)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    /// Keeps a version of a record type which is being replaced, for the
+    /// Records created with it
+    pub fn set_record_type_version(
+        &mut self,
+        record_type: record::RecordType,
+    ) -> Result<(), ApplyError> {
+        let address = make_record_type_version_address(
+            record_type.get_name(),
+            type_version(record_type.get_version()),
+        );
+        let mut record_types: record::RecordTypeContainer =
+            self.take_container(&address, "record type version")?;
+
+        record_types.entries.retain(|t| t.name != record_type.name);
+        record_types.entries.push(record_type);
+        record_types.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self.store_container(&address, "record type version", record_types)
+    }
+
+    /// Finds the schema of a Record's property in the version of its type
+    /// it was created with, for values which are checked against the type
+    /// rather than a copy on the Property. Returns None if either the
+    /// version or the property does not exist.
+    pub fn get_property_schema(
+        &mut self,
+        record: &record::Record,
+        property_name: &str,
+    ) -> Result<Option<property::PropertySchema>, ApplyError> {
+        let key = (
+            record.get_record_type().to_string(),
+            type_version(record.get_record_type_version()),
+        );
+        if !self.schemas.contains_key(&key) {
+            let record_type = match self.get_record_type_version(&key.0, key.1)? {
+                Some(record_type) => record_type,
+                None => return Ok(None),
+            };
+            let schemas = record_type
+                .properties
+                .into_iter()
+                .map(|schema| (schema.name.clone(), schema))
+                .collect();
+            self.schemas.insert(key.clone(), schemas);
+        }
+
+        Ok(self.schemas
+            .get(&key)
+            .and_then(|schemas| schemas.get(property_name))
+            .cloned())
+    }
+
+    pub fn get_record_template(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<record::RecordTemplate>, ApplyError> {
+        let address = make_record_template_address(name);
+        match *self
+            .load_container::<record::RecordTemplateContainer>(&address, "record template")?
+        {
+            Some(ref templates) => Ok(templates
+                .get_entries()
+                .iter()
+                .find(|template| template.name == name)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_record_template(
+        &mut self,
+        name: &str,
+        template: record::RecordTemplate,
+    ) -> Result<(), ApplyError> {
+        let address = make_record_template_address(name);
+        let mut templates: record::RecordTemplateContainer =
+            self.take_container(&address, "record template")?;
+
+        templates.entries.retain(|t| t.name != name);
+        templates.entries.push(template);
+        templates.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self.store_container(&address, "record template", templates)
+    }
+
+    pub fn set_record_type(
+        &mut self,
+        type_name: &str,
+        record_type: record::RecordType,
+    ) -> Result<(), ApplyError> {
+        let address = make_record_type_address(type_name);
+        let mut record_types: record::RecordTypeContainer =
+            self.take_container(&address, "record type")?;
+
+        record_types.entries.retain(|t| t.name != type_name);
+        record_types.entries.push(record_type);
+        record_types.entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self.store_container(&address, "record type", record_types)
+    }
+
+    /// Removes a record type, storing what is left of its container, or
+    /// deleting the container if nothing is
+    pub fn delete_record_type(&mut self, type_name: &str) -> Result<(), ApplyError> {
+        let address = make_record_type_address(type_name);
+        let mut record_types: record::RecordTypeContainer =
+            self.take_container(&address, "record type")?;
+
+        record_types.entries.retain(|t| t.name != type_name);
+        self.schemas.retain(|key, _| key.0 != type_name);
+        if record_types.entries.is_empty() {
+            self.delete_container::<record::RecordTypeContainer>(&address)
+        } else {
+            self.store_container(&address, "record type", record_types)
+        }
+    }
+
+    pub fn get_record_type_stats(
+        &mut self,
+        type_name: &str,
+        day: u64,
+    ) -> Result<Option<stats::RecordTypeStats>, ApplyError> {
+        let address = make_record_type_stats_address(type_name, day);
+        match *self.load_container::<stats::RecordTypeStatsContainer>(&address, "record type stats")? {
+            Some(ref all_stats) => Ok(all_stats
+                .get_entries()
+                .iter()
+                .find(|stats| stats.record_type == type_name && stats.day == day)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_record_type_stats(
+        &mut self,
+        type_name: &str,
+        day: u64,
+        stats: stats::RecordTypeStats,
+    ) -> Result<(), ApplyError> {
+        let address = make_record_type_stats_address(type_name, day);
+        let mut all_stats: stats::RecordTypeStatsContainer =
+            self.take_container(&address, "record type stats")?;
+        // remove old stats if they exist and sort the stats by type and day
+        all_stats
+            .entries
+            .retain(|s| !(s.record_type == type_name && s.day == day));
+        all_stats.entries.push(stats);
+        all_stats
+            .entries
+            .sort_by(|a, b| (&a.record_type, a.day).cmp(&(&b.record_type, b.day)));
+        self.store_container(&address, "record type stats", all_stats)
+    }
+
+    pub fn get_agent(&mut self, agent_id: &str) -> Result<Option<agent::Agent>, ApplyError> {
+        let address = make_agent_address(agent_id);
+        match *self.load_container::<agent::AgentContainer>(&address, "agent")? {
+            Some(ref agents) => Ok(agents
+                .get_entries()
+                .iter()
+                .find(|agent| agent.public_key == agent_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    /// The role of an Agent, keys which are not Agents being operators
+    pub fn get_agent_role(&mut self, agent_id: &str) -> Result<payload::AgentRole, ApplyError> {
+        Ok(self
+            .get_agent(agent_id)?
+            .map_or(payload::AgentRole::OPERATOR, |agent| agent.get_role()))
+    }
+
+    /// Whether a key is one of the network's admins, or an Agent with the
+    /// admin role
+    pub fn is_agent_admin(&mut self, agent_id: &str) -> Result<bool, ApplyError> {
+        let admins = self.get_setting(AGENT_ADMINS_SETTING)?.unwrap_or_default();
+        Ok(admins.split(',').any(|key| key.trim() == agent_id)
+            || self.get_agent_role(agent_id)? == payload::AgentRole::ADMIN)
+    }
+
+    pub fn set_agent(&mut self, agent_id: &str, agent: agent::Agent) -> Result<(), ApplyError> {
+        let address = make_agent_address(agent_id);
+        let mut agents: agent::AgentContainer = self.take_container(&address, "agent")?;
+
+        agents.entries.retain(|a| a.public_key != agent_id);
+        agents.entries.push(agent);
+        agents.entries.sort_by(|a, b| a.public_key.cmp(&b.public_key));
+        self.store_container(&address, "agent", agents)
+    }
+
+    pub fn get_agent_activity_page(
+        &mut self,
+        agent_id: &str,
+        page: u32,
+    ) -> Result<Option<agent::AgentActivityPage>, ApplyError> {
+        let address = make_agent_activity_address(agent_id, page);
+        match *self.load_container::<agent::AgentActivityPageContainer>(
+            &address,
+            "agent activity page",
+        )? {
+            Some(ref activity_pages) => Ok(activity_pages
+                .get_entries()
+                .iter()
+                .find(|activity_page| activity_page.agent_id == agent_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_agent_activity_page(
+        &mut self,
+        agent_id: &str,
+        page_num: u32,
+        activity_page: agent::AgentActivityPage,
+    ) -> Result<(), ApplyError> {
+        let address = make_agent_activity_address(agent_id, page_num);
+        let mut activity_pages: agent::AgentActivityPageContainer =
+            self.take_container(&address, "agent activity page")?;
+        activity_pages
+            .entries
+            .retain(|ap| ap.agent_id != agent_id);
+        activity_pages.entries.push(activity_page);
+        activity_pages
+            .entries
+            .sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+        self.store_container(&address, "agent activity page", activity_pages)
+    }
+
+    pub fn get_property(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+    ) -> Result<Option<property::Property>, ApplyError> {
+        let address = make_property_address(record_id, property_name, 0);
+        match *self.load_container::<property::PropertyContainer>(&address, "property")? {
+            Some(ref properties) => Ok(properties
+                .get_entries()
+                .iter()
+                .find(|property| {
+                    property.record_id == record_id && property.name == property_name
+                })
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_property(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+        property: property::Property,
+    ) -> Result<(), ApplyError> {
+        let address = make_property_address(record_id, property_name, 0);
+        let mut property_container: property::PropertyContainer =
+            self.take_container(&address, "property")?;
+        // remove old property if it exists and sort the properties by name.
+        // Another Record's property of the same name may share the address.
+        property_container
+            .entries
+            .retain(|p| !(p.record_id == record_id && p.name == property_name));
+        let cursor = reporting_cursor(&property);
+        property_container.entries.push(property);
+        property_container
+            .entries
+            .sort_by(|a, b| (&a.name, &a.record_id).cmp(&(&b.name, &b.record_id)));
+        self.store_container(&address, "property", property_container)?;
+        self.set_reporting_cursor(record_id, property_name, cursor)
+    }
+
+    /// Drops the cursors a Record keeps for its Properties, which are only
+    /// used while it can still take new values
+    pub fn clear_reporting_cursors(&mut self, record_id: &str) -> Result<(), ApplyError> {
+        let address = make_record_address(record_id);
+        let mut record_container: record::RecordContainer =
+            self.take_container(&address, "record")?;
+        if let Some(stored) = record_container
+            .entries
+            .iter_mut()
+            .find(|r| r.record_id == record_id)
+        {
+            stored.clear_reporting_cursors();
+        }
+        self.store_container(&address, "record", record_container)
+    }
+
+    /// Replaces the cursor a Record keeps for one of its Properties,
+    /// writing the Record only if the cursor changed
+    fn set_reporting_cursor(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+        cursor: Option<record::Record_ReportingCursor>,
+    ) -> Result<(), ApplyError> {
+        let record = match self.get_record(record_id)? {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        let cursors = record.get_reporting_cursors();
+        let position = cursors
+            .binary_search_by(|c| c.get_property_name().cmp(property_name));
+        let unchanged = match (position, cursor.as_ref()) {
+            (Ok(index), Some(cursor)) => cursors[index] == *cursor,
+            (Err(_), None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return Ok(());
+        }
+
+        let address = make_record_address(record_id);
+        let mut record_container: record::RecordContainer =
+            self.take_container(&address, "record")?;
+        if let Some(stored) = record_container
+            .entries
+            .iter_mut()
+            .find(|r| r.record_id == record_id)
+        {
+            let cursors = stored.mut_reporting_cursors();
+            match (position, cursor) {
+                (Ok(index), Some(cursor)) => cursors[index] = cursor,
+                (Ok(index), None) => {
+                    cursors.remove(index);
+                }
+                (Err(index), Some(cursor)) => cursors.insert(index, cursor),
+                (Err(_), None) => (),
+            }
+        }
+        self.store_container(&address, "record", record_container)
+    }
+
+    pub fn get_property_page(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+        page: u32,
+    ) -> Result<Option<property::PropertyPage>, ApplyError> {
+        let address = make_property_address(record_id, property_name, page);
+        match *self.load_container::<property::PropertyPageContainer>(&address, "property page")? {
+            Some(ref property_pages) => Ok(property_pages
+                .get_entries()
+                .iter()
+                .find(|property_page| {
+                    property_page.record_id == record_id && property_page.name == property_name
+                })
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_property_page(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+        page_num: u32,
+        property_page: property::PropertyPage,
+    ) -> Result<(), ApplyError> {
+        let address = make_property_address(record_id, property_name, page_num);
+        let mut property_pages: property::PropertyPageContainer =
+            self.take_container(&address, "property page")?;
+        // remove old property page if it exists and sort the property pages by name
+        property_pages
+            .entries
+            .retain(|p| !(p.record_id == record_id && p.name == property_name));
+        property_pages.entries.push(property_page);
+        property_pages
+            .entries
+            .sort_by(|a, b| (&a.name, &a.record_id).cmp(&(&b.name, &b.record_id)));
+        self.store_container(&address, "property page", property_pages)
+    }
+
+    /// Removes a property page, storing what is left of its container, or
+    /// deleting the container if nothing is
+    pub fn delete_property_page(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+        page_num: u32,
+    ) -> Result<(), ApplyError> {
+        let address = make_property_address(record_id, property_name, page_num);
+        let mut property_pages: property::PropertyPageContainer =
+            self.take_container(&address, "property page")?;
+        property_pages
+            .entries
+            .retain(|p| !(p.record_id == record_id && p.name == property_name));
+        if property_pages.entries.is_empty() {
+            self.delete_container::<property::PropertyPageContainer>(&address)
+        } else {
+            self.store_container(&address, "property page", property_pages)
+        }
+    }
+
+    pub fn get_proposal_container(
+        &mut self,
+        record_id: &str,
+        agent_id: &str,
+    ) -> Result<Option<proposal::ProposalContainer>, ApplyError> {
+        let address = make_proposal_address(record_id, agent_id);
+        Ok(self
+            .load_container::<proposal::ProposalContainer>(&address, "proposal")?
+            .clone())
+    }
+
+    pub fn set_proposal_container(
+        &mut self,
+        record_id: &str,
+        agent_id: &str,
+        proposals: proposal::ProposalContainer,
+    ) -> Result<(), ApplyError> {
+        let address = make_proposal_address(record_id, agent_id);
+        self.store_container(&address, "proposal", proposals)
+    }
+
+    pub fn get_proposal_history_page(
+        &mut self,
+        record_id: &str,
+        agent_id: &str,
+        page: u32,
+    ) -> Result<Option<proposal::ProposalHistoryPage>, ApplyError> {
+        let address = make_proposal_history_address(record_id, agent_id, page);
+        match *self.load_container::<proposal::ProposalHistoryPageContainer>(
+            &address,
+            "proposal history page",
+        )? {
+            Some(ref history_pages) => Ok(history_pages
+                .get_entries()
+                .iter()
+                .find(|history_page| {
+                    history_page.record_id == record_id
+                        && history_page.receiving_agent == agent_id
+                })
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_proposal_history_page(
+        &mut self,
+        record_id: &str,
+        agent_id: &str,
+        page_num: u32,
+        history_page: proposal::ProposalHistoryPage,
+    ) -> Result<(), ApplyError> {
+        let address = make_proposal_history_address(record_id, agent_id, page_num);
+        let mut history_pages: proposal::ProposalHistoryPageContainer =
+            self.take_container(&address, "proposal history page")?;
+        // remove old history page if it exists and sort the pages by record and agent
+        history_pages
+            .entries
+            .retain(|hp| !(hp.record_id == record_id && hp.receiving_agent == agent_id));
+        history_pages.entries.push(history_page);
+        history_pages.entries.sort_by(|a, b| {
+            (&a.record_id, &a.receiving_agent).cmp(&(&b.record_id, &b.receiving_agent))
+        });
+        self.store_container(&address, "proposal history page", history_pages)
+    }
+
+    pub fn get_open_proposal_index(
+        &mut self,
+        record_id: &str,
+    ) -> Result<Option<proposal::OpenProposalIndex>, ApplyError> {
+        let address = make_proposal_index_address(record_id);
+        match *self.load_container::<proposal::OpenProposalIndexContainer>(
+            &address,
+            "open proposal index",
+        )? {
+            Some(ref indexes) => Ok(indexes
+                .get_entries()
+                .iter()
+                .find(|index| index.record_id == record_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_open_proposal_index(
+        &mut self,
+        record_id: &str,
+        index: proposal::OpenProposalIndex,
+    ) -> Result<(), ApplyError> {
+        let address = make_proposal_index_address(record_id);
+        let mut indexes: proposal::OpenProposalIndexContainer =
+            self.take_container(&address, "open proposal index")?;
+        // remove old index if it exists and sort the indexes by record
+        indexes.entries.retain(|i| i.record_id != record_id);
+        indexes.entries.push(index);
+        indexes.entries.sort_by(|a, b| a.record_id.cmp(&b.record_id));
+        self.store_container(&address, "open proposal index", indexes)
+    }
+
+    /// Removes a record's open proposal index, storing what is left of its
+    /// container, or deleting the container if nothing is
+    pub fn delete_open_proposal_index(&mut self, record_id: &str) -> Result<(), ApplyError> {
+        let address = make_proposal_index_address(record_id);
+        let mut indexes: proposal::OpenProposalIndexContainer =
+            self.take_container(&address, "open proposal index")?;
+        indexes.entries.retain(|i| i.record_id != record_id);
+        if indexes.entries.is_empty() {
+            self.delete_container::<proposal::OpenProposalIndexContainer>(&address)
+        } else {
+            self.store_container(&address, "open proposal index", indexes)
+        }
+    }
+
+    pub fn get_subscription_list(
+        &mut self,
+        agent_id: &str,
+    ) -> Result<Option<subscription::SubscriptionList>, ApplyError> {
+        let address = make_subscription_address(agent_id);
+        match *self.load_container::<subscription::SubscriptionListContainer>(
+            &address,
+            "subscription list",
+        )? {
+            Some(ref lists) => Ok(lists
+                .get_entries()
+                .iter()
+                .find(|list| list.agent_id == agent_id)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_subscription_list(
+        &mut self,
+        agent_id: &str,
+        list: subscription::SubscriptionList,
+    ) -> Result<(), ApplyError> {
+        let address = make_subscription_address(agent_id);
+        let mut lists: subscription::SubscriptionListContainer =
+            self.take_container(&address, "subscription list")?;
+        // remove old list if it exists and sort the lists by agent
+        lists.entries.retain(|l| l.agent_id != agent_id);
+        lists.entries.push(list);
+        lists.entries.sort_by(|a, b| a.agent_id.cmp(&b.agent_id));
+        self.store_container(&address, "subscription list", lists)
+    }
+
+    pub fn get_excursion_log(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+    ) -> Result<Option<property::ExcursionLog>, ApplyError> {
+        let address = make_excursion_log_address(record_id, property_name);
+        match *self.load_container::<property::ExcursionLogContainer>(&address, "excursion log")? {
+            Some(ref logs) => Ok(logs
+                .get_entries()
+                .iter()
+                .find(|log| log.record_id == record_id && log.name == property_name)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_excursion_log(
+        &mut self,
+        record_id: &str,
+        property_name: &str,
+        log: property::ExcursionLog,
+    ) -> Result<(), ApplyError> {
+        let address = make_excursion_log_address(record_id, property_name);
+        let mut logs: property::ExcursionLogContainer =
+            self.take_container(&address, "excursion log")?;
+        // remove old log if it exists and sort the logs by record and name
+        logs.entries
+            .retain(|l| !(l.record_id == record_id && l.name == property_name));
+        logs.entries.push(log);
+        logs.entries
+            .sort_by(|a, b| (&a.record_id, &a.name).cmp(&(&b.record_id, &b.name)));
+        self.store_container(&address, "excursion log", logs)
+    }
+
+    pub fn get_message_anchor(
+        &mut self,
+        record_id: &str,
+        digest: &str,
+    ) -> Result<Option<message::MessageAnchor>, ApplyError> {
+        let address = make_message_address(record_id, digest);
+        match *self.load_container::<message::MessageAnchorContainer>(&address, "message")? {
+            Some(ref anchors) => Ok(anchors
+                .get_entries()
+                .iter()
+                .find(|anchor| anchor.record_id == record_id && anchor.digest == digest)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_message_anchor(
+        &mut self,
+        record_id: &str,
+        digest: &str,
+        anchor: message::MessageAnchor,
+    ) -> Result<(), ApplyError> {
+        let address = make_message_address(record_id, digest);
+        let mut anchors: message::MessageAnchorContainer =
+            self.take_container(&address, "message")?;
+        // remove old anchor if it exists and sort the anchors by record and digest
+        anchors
+            .entries
+            .retain(|a| !(a.record_id == record_id && a.digest == digest));
+        anchors.entries.push(anchor);
+        anchors
+            .entries
+            .sort_by(|a, b| (&a.record_id, &a.digest).cmp(&(&b.record_id, &b.digest)));
+        self.store_container(&address, "message", anchors)
+    }
+
+    pub fn get_state_anchor(
+        &mut self,
+        service: &str,
+        block_num: u64,
+    ) -> Result<Option<anchor::StateAnchor>, ApplyError> {
+        let address = make_state_anchor_address(service, block_num);
+        match *self.load_container::<anchor::StateAnchorContainer>(&address, "state anchor")? {
+            Some(ref anchors) => Ok(anchors
+                .get_entries()
+                .iter()
+                .find(|anchor| anchor.service == service && anchor.block_num == block_num)
+                .cloned()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_state_anchor(
+        &mut self,
+        service: &str,
+        block_num: u64,
+        anchor: anchor::StateAnchor,
+    ) -> Result<(), ApplyError> {
+        let address = make_state_anchor_address(service, block_num);
+        let mut anchors: anchor::StateAnchorContainer =
+            self.take_container(&address, "state anchor")?;
+        // remove old anchor if it exists and sort the anchors by service and block
+        anchors
+            .entries
+            .retain(|a| !(a.service == service && a.block_num == block_num));
+        anchors.entries.push(anchor);
+        anchors
+            .entries
+            .sort_by(|a, b| (&a.service, a.block_num).cmp(&(&b.service, b.block_num)));
+        self.store_container(&address, "state anchor", anchors)
+    }
+}
+
+pub struct SupplyChainTransactionHandler {
+    family_name: String,
+    family_versions: Vec<String>,
+    namespaces: Vec<String>,
+    plugins: Vec<Box<dyn ValidationPlugin>>,
+    replay_log: Option<Mutex<ReplayLog>>,
+}
+
+impl SupplyChainTransactionHandler {
+    pub fn new() -> SupplyChainTransactionHandler {
+        SupplyChainTransactionHandler {
+            family_name: "supply_chain".to_string(),
+            family_versions: vec!["1.1".to_string()],
+            namespaces: vec![get_supply_chain_prefix().to_string()],
+            plugins: Vec::new(),
+            replay_log: None,
+        }
+    }
+
+    /// Registers a validation plugin; plugins run in registration order
+    #[allow(dead_code)]
+    pub fn add_plugin(&mut self, plugin: Box<dyn ValidationPlugin>) {
+        self.plugins.push(plugin);
+    }
+
+    /// Records every transaction applied from now on to `log`
+    pub fn set_replay_log(&mut self, log: ReplayLog) {
+        self.replay_log = Some(Mutex::new(log));
+    }
+
+    fn _run_plugins<F>(&self, hook: F) -> Result<(), ApplyError>
+    where
+        F: Fn(&dyn ValidationPlugin) -> Result<(), ApplyError>,
+    {
+        for plugin in &self.plugins {
+            match hook(plugin.as_ref()) {
+                Ok(_) => (),
+                Err(ApplyError::InvalidTransaction(msg)) => {
+                    return Err(invalid(Code::PluginRejected, format!(
+                        "Rejected by plugin {}: {}",
+                        plugin.name(),
+                        msg
+                    )))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects an action, or a composite including one, which has been
+    /// switched off for the network
+    fn _check_enabled(
+        &self,
+        action: &Action,
+        context: &mut dyn StateContext,
+    ) -> Result<(), ApplyError> {
+        let setting = match SupplyChainState::new(context).get_setting(DISABLED_ACTIONS_SETTING)? {
+            Some(setting) => setting,
+            None => return Ok(()),
+        };
+        let disabled: Vec<&str> = setting.split(',').map(|name| name.trim()).collect();
+        let check = |action: &Action| {
+            let name = action.kind().descriptor().name();
+            if disabled.contains(&name) {
+                Err(invalid(Code::ActionDisabled, format!(
+                    "Action is disabled: {}",
+                    name
+                )))
+            } else {
+                Ok(())
+            }
+        };
+
+        check(action)?;
+        if let Action::Composite(ref actions) = *action {
+            for (i, action) in actions.iter().enumerate() {
+                if let Err(err) = check(action) {
+                    return Err(in_composite(i, Some(action), err));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects an action, or a composite including one, which the signer's
+    /// role does not permit, once the network has named its admins
+    fn _check_permissions(
+        &self,
+        action: &Action,
+        context: &mut dyn StateContext,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let mut state = SupplyChainState::new(context);
+        if state.get_setting(AGENT_ADMINS_SETTING)?.is_none() {
+            return Ok(());
+        }
+        let signer_role = state.get_agent_role(signer)?;
+        let mut check = |action: &Action| {
+            check_role(action, signer_role)?;
+            let receiving_agent = match *action {
+                Action::CreateProposal(ref a)
+                    if a.get_role() != proposal::Proposal_Role::REPORTER =>
+                {
+                    Some(a.get_receiving_agent())
+                }
+                Action::DirectTransfer(ref a) => Some(a.get_receiving_agent()),
+                _ => None,
+            };
+            if let Some(receiving_agent) = receiving_agent {
+                if state.get_agent_role(receiving_agent)? == payload::AgentRole::AUDITOR {
+                    return Err(invalid(Code::NotAuthorized, format!(
+                        "Auditors cannot own or hold records: {}",
+                        receiving_agent
+                    )));
+                }
+            }
+            Ok(())
+        };
+
+        check(action)?;
+        if let Action::Composite(ref actions) = *action {
+            for (i, action) in actions.iter().enumerate() {
+                if let Err(err) = check(action) {
+                    return Err(in_composite(i, Some(action), err));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects any transaction signed by a deactivated Agent's key
+    fn _check_active(
+        &self,
+        context: &mut dyn StateContext,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        match SupplyChainState::new(context).get_agent(signer)? {
+            Some(ref agent) if agent.get_deactivated_at() != 0 => {
+                Err(invalid(Code::AgentDeactivated, format!(
+                    "Agent was deactivated and may not sign transactions: {}",
+                    signer
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Rejects a payload timestamp whose magnitude shows it is not in the
+    /// network's timestamp unit
+    fn _check_timestamp(
+        &self,
+        timestamp: u64,
+        context: &mut dyn StateContext,
+    ) -> Result<(), ApplyError> {
+        let unit = SupplyChainState::new(context).get_setting(TIMESTAMP_UNIT_SETTING)?;
+        match unit.as_deref() {
+            None | Some("seconds") => {
+                if timestamp >= TIMESTAMP_UNIT_BOUNDARY {
+                    return Err(invalid(Code::InvalidTimestamp, format!(
+                        "Timestamp is too large to be in seconds: {}",
+                        timestamp
+                    )));
+                }
+            }
+            Some("milliseconds") => {
+                if timestamp < TIMESTAMP_UNIT_BOUNDARY {
+                    return Err(invalid(Code::InvalidTimestamp, format!(
+                        "Timestamp is too small to be in milliseconds: {}",
+                        timestamp
+                    )));
+                }
+            }
+            Some(other) => {
+                return Err(ApplyError::InternalError(format!(
+                    "Setting {} is not seconds or milliseconds: {}",
+                    TIMESTAMP_UNIT_SETTING, other
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn _apply_action(
+        &self,
+        action: &Action,
+        timestamp: u64,
+        context: &mut dyn StateContext,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        if let Action::Composite(ref actions) = *action {
+            return self._apply_composite(actions, timestamp, context, signer);
+        }
+        if let Action::BulkUpdateProperties(policy, ref updates) = *action {
+            self._bulk_update_properties(policy, updates, timestamp, context, signer)?;
+            return self._log_activity(action, timestamp, SupplyChainState::new(context), signer);
+        }
+        if let Action::MergeRecords(ref merge) = *action {
+            self._merge_records(merge, timestamp, context, signer)?;
+            return self._log_activity(action, timestamp, SupplyChainState::new(context), signer);
+        }
+
+        let state = SupplyChainState::new(context);
+
+        match *action {
+            Action::CreateAgent(ref agent_payload) => {
+                self._create_agent(agent_payload, state, signer, timestamp)?
+            }
+            Action::CreateRecord(ref record_payload) => {
+                self._create_record(record_payload, state, signer, timestamp)?
+            }
+            Action::CreateRecordTemplate(ref template_payload) => {
+                self._create_record_template(template_payload, state, signer, timestamp)?
+            }
+            Action::CreateRecordFromTemplate(ref record_payload) => {
+                self._create_record_from_template(record_payload, state, signer, timestamp)?
+            }
+            Action::FinalizeRecord(ref finalize_payload) => {
+                self._finalize_record(finalize_payload, state, signer, timestamp)?
+            }
+            Action::CreateRecordType(ref record_type_payload) => {
+                self._create_record_type(record_type_payload, state, signer)?
+            }
+            Action::UpdateProperties(ref update_properties_payload) => self._update_properties(
+                update_properties_payload,
+                state,
+                signer,
+                timestamp,
+            )?,
+            Action::CreateProposal(ref proposal_payload) => {
+                self._create_proposal(proposal_payload, state, signer, timestamp)?
+            }
+            Action::AnswerProposal(ref answer_proposal_payload) => self._answer_proposal(
+                answer_proposal_payload,
+                state,
+                signer,
+                timestamp,
+            )?,
+            Action::CancelAllProposals(ref cancel_payload) => {
+                self._cancel_all_proposals(cancel_payload, state, signer, timestamp)?
+            }
+            Action::AmendProposal(ref amend_payload) => {
+                self._amend_proposal(amend_payload, state, signer, timestamp)?
+            }
+            Action::ReleaseEscrow(ref release_payload) => {
+                self._release_escrow(release_payload, state, signer, timestamp)?
+            }
+            Action::RevokeReporter(ref revoke_reporter_payload) => {
+                self._revoke_reporter(revoke_reporter_payload, state, signer)?
+            }
+            Action::ExecuteTransfer(ref execute_transfer_payload) => self._execute_transfer(
+                execute_transfer_payload,
+                state,
+                signer,
+                timestamp,
+            )?,
+            Action::Message(ref message_payload) => {
+                self._anchor_message(message_payload, state, signer, timestamp)?
+            }
+            Action::Subscribe(ref subscribe_payload) => {
+                self._subscribe(subscribe_payload, state, signer, timestamp)?
+            }
+            Action::Unsubscribe(ref unsubscribe_payload) => {
+                self._unsubscribe(unsubscribe_payload, state, signer)?
+            }
+            Action::ReceiveConfirmation(ref confirmation_payload) => {
+                self._receive_confirmation(confirmation_payload, state, signer, timestamp)?
+            }
+            Action::AnchorState(ref anchor_payload) => {
+                self._anchor_state(anchor_payload, state, signer, timestamp)?
+            }
+            Action::CloneRecord(ref clone_payload) => {
+                self._clone_record(clone_payload, state, signer, timestamp)?
+            }
+            Action::AnchorFeedback(ref feedback_payload) => {
+                self._anchor_feedback(feedback_payload, state, signer, timestamp)?
+            }
+            Action::MigrateState(ref migration_payload) => {
+                self._migrate_state(migration_payload, state, signer)?
+            }
+            Action::DeleteRecordType(ref delete_payload) => {
+                self._delete_record_type(delete_payload, state, signer)?
+            }
+            Action::AcknowledgeValue(ref acknowledge_payload) => {
+                self._acknowledge_value(acknowledge_payload, state, signer)?
+            }
+            Action::UpdateRecordType(ref update_type_payload) => {
+                self._update_record_type(update_type_payload, state, signer)?
+            }
+            Action::DirectTransfer(ref authorization) => {
+                self._direct_transfer(authorization, state, signer, timestamp)?
+            }
+            Action::DelegateReporter(ref delegate_payload) => {
+                self._delegate_reporter(delegate_payload, state, signer, timestamp)?
+            }
+            Action::ArchiveRecord(ref archive_payload) => {
+                self._archive_record(archive_payload, state, signer, timestamp)?
+            }
+            Action::ReopenRecord(ref reopen_payload) => {
+                self._reopen_record(reopen_payload, state, signer, timestamp)?
+            }
+            Action::RecallRecord(ref recall_payload) => {
+                self._recall_record(recall_payload, state, signer, timestamp)?
+            }
+            Action::SetAgentRole(ref role_payload) => {
+                self._set_agent_role(role_payload, state, signer)?
+            }
+            Action::DeactivateAgent(ref deactivate_payload) => {
+                self._deactivate_agent(deactivate_payload, state, signer, timestamp)?
+            }
+            Action::SetRecordMetadata(ref metadata_payload) => {
+                self._set_record_metadata(metadata_payload, state, signer)?
+            }
+            Action::AttestAudit(ref audit_payload) => {
+                self._attest_audit(audit_payload, state, signer, timestamp)?
+            }
+            Action::Composite(_) | Action::BulkUpdateProperties(..) | Action::MergeRecords(_) => {
+                unreachable!()
+            }
+        }
+
+        self._log_activity(action, timestamp, SupplyChainState::new(context), signer)
+    }
+
+    /// Adds an action to the signer's activity pages. Actions signed by a
+    /// key that is not an Agent, which are only ever rejected, are not kept.
+    fn _log_activity(
+        &self,
+        action: &Action,
+        timestamp: u64,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let mut signer_agent = match state.get_agent(signer)? {
+            Some(agent) => agent,
+            None => return Ok(()),
+        };
+
+        let mut page_number = match signer_agent.get_activity_page() {
+            0 => 1,
+            page => page,
+        };
+        let mut page = match state.get_agent_activity_page(signer, page_number)? {
+            Some(page) => page,
+            None => {
+                let mut page = agent::AgentActivityPage::new();
+                page.set_agent_id(signer.to_string());
+                page
+            }
+        };
+        if page.entries.len() >= AGENT_ACTIVITY_PAGE_MAX_LENGTH {
+            // Pages form a ring buffer, like property pages
+            page_number = if page_number == 0xffff {
+                1
+            } else {
+                page_number + 1
+            };
+            page.set_entries(RepeatedField::from_vec(Vec::new()));
+        }
+
+        let mut entry = agent::AgentActivityEntry::new();
+        entry.set_action(action.kind());
+        entry.set_record_id(action.record_id().unwrap_or("").to_string());
+        entry.set_timestamp(timestamp);
+        page.entries.push(entry);
+        state.set_agent_activity_page(signer, page_number, page)?;
+
+        if signer_agent.get_activity_page() != page_number {
+            signer_agent.set_activity_page(page_number);
+            state.set_agent(signer, signer_agent)?;
+        }
+        Ok(())
+    }
+
+    fn _add_activity_receipt(
+        &self,
+        action: &Action,
+        context: &mut dyn StateContext,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let data = make_activity_receipt(action, signer)
+            .write_to_bytes()
+            .map_err(|_| ApplyError::InternalError(String::from("Cannot serialize receipt")))?;
+        context
+            .add_receipt_data(&data)
+            .map_err(|err| ApplyError::InternalError(format!("{}", err)))
+    }
+
+    /// Applies each action in turn against staged state, so that their
+    /// changes reach the validator only once all of them have succeeded
+    fn _apply_composite(
+        &self,
+        actions: &[Action],
+        timestamp: u64,
+        context: &mut dyn StateContext,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let mut staged = StagedContext::new(context);
+        for (i, action) in actions.iter().enumerate() {
+            if let Err(err) = self._apply_action(action, timestamp, &mut staged, signer) {
+                return Err(in_composite(i, Some(action), err));
+            }
+        }
+        staged.commit()?;
+        Ok(())
+    }
+
+    /// Applies a bulk update's UpdateProperties to each Record in turn
+    /// against staged state. Unless the policy is BEST_EFFORT, no Record
+    /// is updated unless all of them can be; either way the outcome for
+    /// each Record is added to the transaction's receipt.
+    fn _bulk_update_properties(
+        &self,
+        policy: payload::BulkUpdatePropertiesAction_Policy,
+        updates: &[payload::UpdatePropertiesAction],
+        timestamp: u64,
+        context: &mut dyn StateContext,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let best_effort = policy == payload::BulkUpdatePropertiesAction_Policy::BEST_EFFORT;
+        let mut staged = StagedContext::new(context);
+        let mut bulk_receipt = receipt::BulkUpdateReceipt::new();
+        for update in updates {
+            let mut entry = receipt::BulkUpdateReceipt_Entry::new();
+            entry.set_record_id(update.get_record_id().to_string());
+            // Each Record is staged on its own, so that one which rejects
+            // the update leaves nothing behind
+            let mut record_staged = StagedContext::new(&mut staged);
+            let state = SupplyChainState::new(&mut record_staged);
+            match self._update_properties(update, state, signer, timestamp) {
+                Ok(()) => {
+                    record_staged.commit()?;
+                    entry.set_applied(true);
+                }
+                Err(ApplyError::InvalidTransaction(msg)) if best_effort => entry.set_error(msg),
+                Err(err) => return Err(in_bulk_update(update.get_record_id(), err)),
+            }
+            bulk_receipt.mut_entries().push(entry);
+        }
+
+        let data = bulk_receipt
+            .write_to_bytes()
+            .map_err(|_| ApplyError::InternalError(String::from("Cannot serialize receipt")))?;
+        staged.add_receipt_data(&data)?;
+        staged.commit()?;
+        Ok(())
+    }
+
+    /// Combines lots into a new record. The new record is created as by
+    /// CreateRecord, then each lot merged into it is finalized as its
+    /// owner could finalize it and marked as consumed by the new record,
+    /// which lists them as its parents.
+    fn _merge_records(
+        &self,
+        merge: &payload::MergeRecordsAction,
+        timestamp: u64,
+        context: &mut dyn StateContext,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let record_id = merge.get_record_id();
+        let parent_ids = merge.get_parent_ids();
+        let mut staged = StagedContext::new(context);
+
+        let mut create = payload::CreateRecordAction::new();
+        create.set_record_id(record_id.to_string());
+        create.set_record_type(merge.get_record_type().to_string());
+        create.set_properties(merge.get_properties().iter().cloned().collect());
+        self._create_record(&create, SupplyChainState::new(&mut staged), signer, timestamp)?;
+
+        for parent_id in parent_ids {
+            let mut finalize = payload::FinalizeRecordAction::new();
+            finalize.set_record_id(parent_id.to_string());
+            let state = SupplyChainState::new(&mut staged);
+            self._finalize_record(&finalize, state, signer, timestamp)?;
+        }
+
+        {
+            let mut state = SupplyChainState::new(&mut staged);
+            for parent_id in parent_ids {
+                let mut parent = match state.get_record(parent_id)? {
+                    Some(parent) => parent,
+                    None => {
+                        return Err(ApplyError::InternalError(format!(
+                            "Merged record does not exist: {}",
+                            parent_id
+                        )))
+                    }
+                };
+                parent.set_consumed_by(record_id.to_string());
+                state.set_record(parent_id, parent)?;
+            }
+
+            let mut merged = match state.get_record(record_id)? {
+                Some(merged) => merged,
+                None => {
+                    return Err(ApplyError::InternalError(format!(
+                        "Merged record was not created: {}",
+                        record_id
+                    )))
+                }
+            };
+            merged.set_parents(RepeatedField::from_vec(parent_ids.to_vec()));
+            state.set_record(record_id, merged.clone())?;
+            let details = parent_ids.iter().map(|id| ("parent", id.to_string())).collect();
+            state.add_record_event("merged", &merged, details)?;
+        }
+
+        staged.commit()?;
+        Ok(())
+    }
+
+    fn _create_agent(
+        &self,
+        payload: &payload::CreateAgentAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let name = payload.get_name();
+        match state.get_agent(signer) {
+            Ok(Some(_)) => {
+                return Err(invalid(Code::AgentExists, format!(
+                    "Agent already exists: {}",
+                    name
+                )))
+            }
+            Ok(None) => (),
+            Err(err) => return Err(err),
+        }
+
+        let mut new_agent = agent::Agent::new();
+        new_agent.set_public_key(signer.to_string());
+        new_agent.set_name(name.to_string());
+        new_agent.set_timestamp(timestamp);
+
+        state.set_agent(signer, new_agent)?;
+        Ok(())
+    }
+
+    /// Sets an Agent's role. The keys listed in the admins setting may set
+    /// roles, so that the first admins can be appointed, as may Agents
+    /// with the ADMIN role.
+    fn _set_agent_role(
+        &self,
+        payload: &payload::SetAgentRoleAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        if !state.is_agent_admin(signer)? {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Signer is not permitted to set roles: {}",
+                signer
+            )));
+        }
+
+        let agent_id = payload.get_agent_id();
+        let mut role_agent = match state.get_agent(agent_id)? {
+            Some(agent) => agent,
+            None => {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Agent does not exist: {}",
+                    agent_id
+                )))
+            }
+        };
+        role_agent.set_role(payload.get_role());
+        state.set_agent(agent_id, role_agent)
+    }
+
+    fn _deactivate_agent(
+        &self,
+        payload: &payload::DeactivateAgentAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let agent_id = payload.get_agent_id();
+        let mut agent = match state.get_agent(agent_id)? {
+            Some(agent) => agent,
+            None => {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Agent does not exist: {}",
+                    agent_id
+                )))
+            }
+        };
+        if agent_id != signer && !state.is_agent_admin(signer)? {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Signer is not permitted to deactivate other Agents: {}",
+                signer
+            )));
+        }
+        if agent.get_deactivated_at() != 0 {
+            return Err(invalid(Code::AgentDeactivated, format!(
+                "Agent is already deactivated: {}",
+                agent_id
+            )));
+        }
+
+        // The Agent itself is kept, so the Records, Proposals and history
+        // which name it still resolve
+        agent.set_deactivated_at(timestamp);
+        agent.set_deactivated_by(signer.to_string());
+        state.set_agent(agent_id, agent)
+    }
+
+    fn _create_record(
+        &self,
+        payload: &payload::CreateRecordAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        match state.get_agent(signer) {
+            Ok(Some(_)) => (),
+            Ok(None) => {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Agent is not register: {}",
+                    signer
+                )))
+            }
+            Err(err) => return Err(err),
+        }
+        let record_id = payload.get_record_id();
+        match state.get_record(record_id) {
+            Ok(Some(_)) => {
+                return Err(invalid(Code::RecordExists, format!(
+                    "Record already exists: {}",
+                    record_id
+                )))
+            }
+            Ok(None) => (),
+            Err(err) => return Err(err),
+        }
+
+        let type_name = payload.get_record_type();
+        let record_type = match state.get_record_type(type_name) {
+            Ok(Some(record_type)) => record_type,
+            Ok(None) => {
+                return Err(invalid(Code::RecordTypeNotFound, format!(
+                    "Record Type does not exist {}",
+                    type_name
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+        if record_type.get_unused() {
+            let mut used_type = record_type.clone();
+            used_type.set_unused(false);
+            state.set_record_type(type_name, used_type)?;
+        }
+
+        let mut type_schemata: HashMap<&str, property::PropertySchema> = HashMap::new();
+        let mut required_properties: HashMap<&str, property::PropertySchema> = HashMap::new();
+        let mut provided_properties: HashMap<&str, property::PropertyValue> = HashMap::new();
+        for property in record_type.get_properties() {
+            type_schemata.insert(property.get_name(), property.clone());
+            if property.get_required() {
+                required_properties.insert(property.get_name(), property.clone());
+            }
+        }
+
+        for property in payload.get_properties() {
+            provided_properties.insert(property.get_name(), property.clone());
+        }
+
+        for name in required_properties.keys() {
+            if !provided_properties.contains_key(name) {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Required property {} not provided",
+                    name
+                )));
+            }
+        }
+
+        // Defaults are validated when the type is created, so they are
+        // added after the checks on values the signer provided
+        let mut defaults = Vec::new();
+        for (name, schema) in &type_schemata {
+            if schema.has_default_value() && !provided_properties.contains_key(name) {
+                let mut default_value = schema.get_default_value().clone();
+                default_value.set_name(name.to_string());
+                defaults.push((*name, default_value));
+            }
+        }
+
+        for (provided_name, provided_properties) in provided_properties.clone() {
+            let required_type = match type_schemata.get(provided_name) {
+                Some(required_type) => required_type.data_type,
+                None => {
+                    return Err(invalid(Code::PropertyNotFound, format!(
+                        "Provided property {} is not in schemata",
+                        provided_name
+                    )))
+                }
+            };
+            let provided_type = provided_properties.data_type;
+            if provided_type != required_type {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Value provided for {} is the wrong type",
+                    provided_name
+                )));
+            };
+
+            let is_delayed = match type_schemata.get(provided_name) {
+                Some(property_schema) => property_schema.delayed,
+                None => false,
+            };
+            if is_delayed {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Property is 'delayed', and cannot be set at record creation: {}",
+                    provided_name
+                )));
+            };
+        }
+        provided_properties.extend(defaults);
+
+        if !payload.get_reporters().is_empty() {
+            self._check_initial_reporters(
+                &mut state,
+                payload.get_reporters(),
+                &type_schemata,
+                signer,
+            )?;
+        }
+
+        let mut new_record = record::Record::new();
+        new_record.set_record_id(record_id.to_string());
+        new_record.set_record_type(type_name.to_string());
+        new_record.set_field_final(false);
+        new_record.set_rules(RepeatedField::from_vec(record_type.get_rules().to_vec()));
+        new_record.set_record_type_version(type_version(record_type.get_version()));
+
+        let mut owner = record::Record_AssociatedAgent::new();
+        owner.set_agent_id(signer.to_string());
+        owner.set_timestamp(timestamp);
+        new_record.owners.push(owner.clone());
+        new_record.custodians.push(owner.clone());
+
+        self._run_plugins(|plugin| {
+            plugin.pre_create_record(&new_record, payload.get_properties(), signer)
+        })?;
+
+        // Rules see each value the Record is created with as they would
+        // see it reported by an update
+        let mut initial_values: Vec<property::PropertyValue> =
+            provided_properties.values().cloned().collect();
+        initial_values.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+        let mut bindings = self._make_rule_bindings(&new_record, "create", signer, timestamp);
+        self._bind_linked_values(&mut state, &new_record, &initial_values, &mut bindings)?;
+        if initial_values.is_empty() {
+            self._check_rules(&new_record, &bindings)?;
+        }
+        for value in &initial_values {
+            let name = value.get_name();
+            let mut value_bindings = bindings.clone();
+            value_bindings.insert(String::from("property"), rules::Value::Str(name.to_string()));
+            if let Some(bound) = type_schemata
+                .get(name)
+                .and_then(|schema| values::from_property_value(schema, value).ok())
+                .and_then(|bound| rule_value(&bound))
+            {
+                value_bindings.insert(String::from("value"), bound);
+            }
+            self._check_rules(&new_record, &value_bindings)?;
+        }
+
+        new_record.set_timeline_page(1);
+        self._append_timeline(
+            &mut state,
+            &mut new_record,
+            record::RecordTimelineEntry_Role::OWNER,
+            &owner,
+        )?;
+        self._append_timeline(
+            &mut state,
+            &mut new_record,
+            record::RecordTimelineEntry_Role::CUSTODIAN,
+            &owner,
+        )?;
+        state.set_record(record_id, new_record.clone())?;
+        self._count_record_stats(&mut state, type_name, timestamp, |stats| {
+            stats.created += 1;
+        })?;
+
+        let mut reporter = property::Property_Reporter::new();
+        reporter.set_public_key(signer.to_string());
+        reporter.set_authorized(true);
+        reporter.set_index(0);
+
+        for (property_name, property) in type_schemata {
+            let mut new_property = property::Property::new();
+            new_property.set_name(property_name.to_string());
+            new_property.set_record_id(record_id.to_string());
+            new_property.set_data_type(property.get_data_type());
+            new_property.set_counts_pages(true);
+            new_property.reporters.push(reporter.clone());
+            for authorization in payload.get_reporters() {
+                if authorization
+                    .get_properties()
+                    .iter()
+                    .any(|name| name.as_str() == property_name)
+                {
+                    authorize_reporter(&mut new_property, authorization.get_public_key(), 0);
+                }
+            }
+            if provided_properties.contains_key(property_name) {
+                count_reporter_page(&mut new_property, 0, true);
+            }
+            new_property.set_current_page(1);
+            new_property.set_wrapped(false);
+            new_property.set_fixed(property.get_fixed());
+            new_property.set_immutable(property.get_immutable());
+            // Enum options and struct properties are left on the record
+            // type, which cannot change, rather than copied onto each record
+            new_property.set_number_exponent(property.get_number_exponent());
+            new_property.set_unit(property.get_unit().to_string());
+            if property.has_threshold() {
+                new_property.set_threshold(property.get_threshold().clone());
+            }
+
+            state.set_property(record_id, property_name, new_property.clone())?;
+
+            let mut new_property_page = property::PropertyPage::new();
+            new_property_page.set_name(property_name.to_string());
+            new_property_page.set_record_id(record_id.to_string());
+
+            if provided_properties.contains_key(property_name) {
+                let provided_property = &provided_properties[property_name];
+                let reported_value = match self._make_new_reported_value(
+                    0,
+                    timestamp,
+                    provided_property,
+                    &property,
+                ) {
+                    Ok(reported_value) => reported_value,
+                    Err(err) => return Err(err),
+                };
+                self._check_record_reference(&mut state, record_id, provided_property)?;
+                let number_value = reported_value.get_number_value();
+
+                new_property_page.reported_values.push(reported_value);
+                let digest = chain_digest("", &new_property_page.reported_values)?;
+                new_property_page.set_digest(digest);
+                if new_property.has_threshold() {
+                    self._track_excursion(
+                        &mut state,
+                        &new_record,
+                        &new_property,
+                        number_value,
+                        timestamp,
+                    )?;
+                }
+            }
+            state.set_property_page(record_id, property_name, 1, new_property_page)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the reporters a Record is created with as a reporter Proposal
+    /// to each would be checked when it was accepted
+    fn _check_initial_reporters(
+        &self,
+        state: &mut SupplyChainState,
+        reporters: &[payload::ReporterAuthorization],
+        type_schemata: &HashMap<&str, property::PropertySchema>,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let max_reporters =
+            state.get_limit_setting(MAX_REPORTERS_SETTING, DEFAULT_MAX_REPORTERS)?;
+
+        // The signer is every Property's first reporter
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (i, authorization) in reporters.iter().enumerate() {
+            let public_key = authorization.get_public_key();
+            if reporters[..i]
+                .iter()
+                .any(|other| other.get_public_key() == public_key)
+            {
+                return Err(invalid(Code::Duplicate, format!(
+                    "Reporter is authorized more than once: {}",
+                    public_key
+                )));
+            }
+            if authorization.get_properties().is_empty() {
+                return Err(invalid(Code::MissingField, format!(
+                    "No properties given for reporter: {}",
+                    public_key
+                )));
+            }
+            if state.get_agent(public_key)?.is_none() {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Reporter is not registered as an agent: {}",
+                    public_key
+                )));
+            }
+
+            let properties = authorization.get_properties();
+            for (j, name) in properties.iter().enumerate() {
+                if !type_schemata.contains_key(name.as_str()) {
+                    return Err(invalid(Code::PropertyNotFound, format!(
+                        "Reporter {} cannot be authorized for a property the record type \
+                         does not have: {}",
+                        public_key, name
+                    )));
+                }
+                if public_key == signer || properties[..j].contains(name) {
+                    continue;
+                }
+                let count = counts.entry(name.as_str()).or_insert(1);
+                *count += 1;
+                if *count > max_reporters {
+                    return Err(invalid(Code::LimitReached, format!(
+                        "Property {} already has the maximum of {} reporters",
+                        name, max_reporters
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a Record like CreateRecord, with the values it is not given
+    /// copied from the latest values of a Record the signer owns
+    fn _clone_record(
+        &self,
+        payload: &payload::CloneRecordAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let source_id = payload.get_source_record_id();
+        let source = match state.get_record(source_id)? {
+            Some(source) => source,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Source record does not exist: {}",
+                    source_id
+                )))
+            }
+        };
+        match source.owners.last() {
+            Some(owner) if owner.agent_id == signer => (),
+            _ => {
+                return Err(invalid(Code::NotAuthorized, format!(
+                    "Must be owner to clone record: {}",
+                    source_id
+                )))
+            }
+        }
+
+        let type_name = source.get_record_type();
+        let record_type = match state.get_record_type(type_name)? {
+            Some(record_type) => record_type,
+            None => {
+                return Err(ApplyError::InternalError(format!(
+                    "Record type of record {} does not exist: {}",
+                    source_id, type_name
+                )))
+            }
+        };
+
+        let mut properties = payload.get_properties().to_vec();
+        for schema in record_type.get_properties() {
+            let name = schema.get_name();
+            // Immutable values, such as serial numbers, belong to one
+            // Record, and delayed ones cannot be set at creation
+            if schema.get_immutable()
+                || schema.get_delayed()
+                || properties.iter().any(|value| value.get_name() == name)
+            {
+                continue;
+            }
+
+            let prop = match state.get_property(source_id, name)? {
+                Some(prop) => prop,
+                None => continue,
+            };
+            // The source may have been created with an earlier version of
+            // its type, so its values are read with that version's schema
+            // and only copied where the Property's type is unchanged
+            let source_schema = match state.get_property_schema(&source, name)? {
+                Some(source_schema) => source_schema,
+                None => continue,
+            };
+            if source_schema.get_data_type() != schema.get_data_type() {
+                continue;
+            }
+            if let Some(latest) = self._latest_reported_value(&mut state, source_id, &prop)? {
+                properties.push(
+                    values::to_property_value(&source_schema, &latest)
+                        .map_err(ApplyError::InternalError)?,
+                );
+            }
+        }
+
+        let mut create = payload::CreateRecordAction::new();
+        create.set_record_id(payload.get_record_id().to_string());
+        create.set_record_type(type_name.to_string());
+        create.set_properties(RepeatedField::from_vec(properties));
+        self._create_record(&create, state, signer, timestamp)
+    }
+
+    fn _finalize_record(
+        &self,
+        payload: &payload::FinalizeRecordAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let final_record = match state.get_record(record_id) {
+            Ok(Some(final_record)) => final_record,
+            Ok(None) => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+        let owner = match final_record.owners.last() {
+            Some(x) => x,
+            None => {
+                return Err(invalid(Code::InconsistentState, String::from(
+                    "Owner was not found",
+                )))
+            }
+        };
+        let custodian = match final_record.custodians.last() {
+            Some(x) => x,
+            None => {
+                return Err(invalid(Code::InconsistentState, String::from(
+                    "Custodian was not found",
+                )))
+            }
+        };
+
+        if owner.agent_id != signer || custodian.agent_id != signer {
+            return Err(invalid(
+                Code::NotAuthorized,
+                String::from("Must be owner and custodian to finalize record"),
+            ));
+        }
+        if final_record.has_escrow() {
+            return Err(invalid(Code::RecordInEscrow, format!(
+                "Record is held in escrow: {}",
+                record_id
+            )));
+        }
+        if final_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is already final: {}",
+                record_id
+            )));
+        }
+        if !final_record.get_unacknowledged().is_empty() {
+            return Err(invalid(Code::UnacknowledgedValues, format!(
+                "Record has values awaiting the owner's acknowledgment: {}",
+                final_record.get_unacknowledged().join(", ")
+            )));
+        }
+
+        let record_type = match state.get_record_type_version(
+            final_record.get_record_type(),
+            final_record.get_record_type_version(),
+        )? {
+            Some(record_type) => record_type,
+            None => {
+                return Err(invalid(Code::RecordTypeNotFound, format!(
+                    "RecordType does not exist: {}",
+                    final_record.get_record_type()
+                )))
+            }
+        };
+        if record_type.has_finalization_policy() {
+            self._check_finalization_policy(&mut state, &final_record, &record_type)?;
+        }
+
+        let mut record_clone = final_record.clone();
+        record_clone.set_field_final(true);
+        record_clone.set_finalized_at(timestamp);
+        state.set_record(record_id, record_clone.clone())?;
+        state.add_record_event("finalized", &record_clone, vec![])?;
+
+        let created_at = final_record.owners.first().map_or(timestamp, |owner| owner.timestamp);
+        let open_for = timestamp_seconds(timestamp).saturating_sub(timestamp_seconds(created_at));
+        self._count_record_stats(&mut state, final_record.get_record_type(), timestamp, |stats| {
+            stats.finalized += 1;
+            stats.finalized_seconds += open_for;
+        })?;
+
+        Ok(())
+    }
+
+    /// Archives a final record, compacting it to a stub that drops what is
+    /// only used to check changes it can no longer have and keeps the
+    /// digest of its full form. If asked to, the reported values of each
+    /// property whose retention period has passed, or of every property,
+    /// are removed, keeping the digest they can later be verified against.
+    fn _archive_record(
+        &self,
+        payload: &payload::ArchiveRecordAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let mut archived = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        match archived.owners.last() {
+            Some(owner) if owner.agent_id == signer => (),
+            _ => {
+                return Err(invalid(Code::NotAuthorized, String::from(
+                    "Must be owner to archive record",
+                )))
+            }
+        }
+        if !archived.get_field_final() {
+            return Err(invalid(Code::RecordNotFinal, format!(
+                "Record must be final to be archived: {}",
+                record_id
+            )));
+        }
+        if archived.get_archived_at() != 0 {
+            return Err(invalid(Code::RecordArchived, format!(
+                "Record is already archived: {}",
+                record_id
+            )));
+        }
+
+        let serialized = archived.write_to_bytes().map_err(|_| {
+            ApplyError::InternalError(String::from("Cannot serialize record"))
+        })?;
+        let mut sha = Sha512::new();
+        sha.input(&serialized);
+        let digest = sha.result_str();
+
+        if payload.get_prune_values() || payload.get_delete_pages() {
+            let record_type = match state.get_record_type_version(
+                archived.get_record_type(),
+                archived.get_record_type_version(),
+            )? {
+                Some(record_type) => record_type,
+                None => {
+                    return Err(invalid(Code::RecordTypeNotFound, format!(
+                        "RecordType does not exist: {}",
+                        archived.get_record_type()
+                    )))
+                }
+            };
+            let names: Vec<&str> = if payload.get_delete_pages() {
+                record_type
+                    .get_properties()
+                    .iter()
+                    .map(|schema| schema.get_name())
+                    .collect()
+            } else {
+                // Records finalized before the time was kept cannot be
+                // shown to be past any retention period
+                let finalized_at = archived.get_finalized_at();
+                let elapsed = timestamp_seconds(timestamp)
+                    .saturating_sub(timestamp_seconds(finalized_at));
+                record_type
+                    .get_retention_rules()
+                    .iter()
+                    .filter(|retention| {
+                        finalized_at != 0 && elapsed >= retention.get_prunable_after()
+                    })
+                    .map(|retention| retention.get_property_name())
+                    .collect()
+            };
+            for name in names {
+                if let Some(pruned) = self._prune_property(&mut state, record_id, name)? {
+                    archived.pruned_properties.push(pruned);
+                }
+            }
+        }
+
+        archived.clear_rules();
+        archived.clear_reporting_cursors();
+        archived.clear_reopen_request();
+        archived.clear_unacknowledged();
+        archived.set_archived_digest(digest);
+        archived.set_archived_at(timestamp);
+        state.set_record(record_id, archived.clone())?;
+        state.clear_reporting_cursors(record_id)?;
+        let details = archived
+            .get_pruned_properties()
+            .iter()
+            .map(|pruned| ("property", pruned.get_name().to_string()))
+            .collect();
+        state.add_record_event("archived", &archived, details)
+    }
+
+    /// Reopens a final record once both its owner and custodian have asked
+    /// to, within a day of each other. The first to ask leaves an open
+    /// request on the record, and each reopening is kept on the record.
+    fn _reopen_record(
+        &self,
+        payload: &payload::ReopenRecordAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let mut record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        let holder = |agents: &[record::Record_AssociatedAgent]| {
+            agents.last().map(|agent| agent.get_agent_id().to_string())
+        };
+        let (owner, custodian) = match (holder(&record.owners), holder(&record.custodians)) {
+            (Some(owner), Some(custodian)) => (owner, custodian),
+            _ => {
+                return Err(invalid(Code::InconsistentState, String::from(
+                    "Owner or custodian was not found",
+                )))
+            }
+        };
+        if owner != signer && custodian != signer {
+            return Err(invalid(Code::NotAuthorized, String::from(
+                "Must be owner or custodian to reopen record",
+            )));
+        }
+        if !record.get_field_final() {
+            return Err(invalid(Code::RecordNotFinal, format!(
+                "Record is not final: {}",
+                record_id
+            )));
+        }
+        if record.get_archived_at() != 0 {
+            return Err(invalid(Code::RecordArchived, format!(
+                "Archived records cannot be reopened: {}",
+                record_id
+            )));
+        }
+        if !record.get_consumed_by().is_empty() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record was merged into {} and cannot be reopened",
+                record.get_consumed_by()
+            )));
+        }
+
+        // A request lapses if it is not agreed to in time, or if the
+        // Agent who made it no longer holds either role
+        let open_request = if record.has_reopen_request() {
+            let request = record.get_reopen_request();
+            let requested_by = request.get_requested_by();
+            let elapsed = timestamp_seconds(timestamp)
+                .saturating_sub(timestamp_seconds(request.get_requested_at()));
+            if elapsed < REOPEN_REQUEST_SECONDS
+                && (requested_by == owner || requested_by == custodian)
+            {
+                Some(request.clone())
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let mut reopening = match open_request {
+            Some(ref request) if request.get_requested_by() == signer => {
+                return Err(invalid(Code::Duplicate, format!(
+                    "Reopening has already been requested: {}",
+                    record_id
+                )))
+            }
+            Some(request) => request,
+            None => {
+                let mut request = record::Record_Reopening::new();
+                request.set_requested_by(signer.to_string());
+                request.set_requested_at(timestamp);
+                request.set_finalized_at(record.get_finalized_at());
+                if owner == custodian {
+                    request
+                } else {
+                    record.set_reopen_request(request);
+                    state.set_record(record_id, record.clone())?;
+                    return state.add_record_event(
+                        "reopen_requested",
+                        &record,
+                        vec![("requested_by", signer.to_string())],
+                    );
+                }
+            }
+        };
+
+        reopening.set_approved_by(signer.to_string());
+        reopening.set_reopened_at(timestamp);
+        record.clear_reopen_request();
+        record.reopenings.push(reopening.clone());
+        record.set_field_final(false);
+        record.set_finalized_at(0);
+        state.set_record(record_id, record.clone())?;
+        state.add_record_event(
+            "reopened",
+            &record,
+            vec![
+                ("requested_by", reopening.get_requested_by().to_string()),
+                ("approved_by", signer.to_string()),
+            ],
+        )
+    }
+
+    /// Records an auditor's attestation that it reviewed a record's
+    /// history, replacing the auditor's earlier attestation if it has one
+    fn _attest_audit(
+        &self,
+        payload: &payload::AttestAuditAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        if state.get_agent_role(signer)? != payload::AgentRole::AUDITOR {
+            return Err(invalid(Code::NotAuthorized, String::from(
+                "Only auditors can attest audits",
+            )));
+        }
+        let record_id = payload.get_record_id();
+        let mut audited = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        if payload.get_reviewed_through() > timestamp {
+            return Err(invalid(Code::InvalidFormat, format!(
+                "Audit cannot review past the time it is attested: {}",
+                payload.get_reviewed_through()
+            )));
+        }
+
+        let mut attestation = record::Record_AuditAttestation::new();
+        attestation.set_auditor(signer.to_string());
+        attestation.set_reviewed_through(payload.get_reviewed_through());
+        attestation.set_timestamp(timestamp);
+        attestation.set_digest(payload.get_digest().to_string());
+        attestation.set_passed(payload.get_passed());
+
+        audited.audits.retain(|audit| audit.auditor != signer);
+        if audited.audits.len() >= MAX_RECORD_AUDITORS {
+            return Err(invalid(Code::LimitReached, format!(
+                "Records keep the attestations of at most {} auditors",
+                MAX_RECORD_AUDITORS
+            )));
+        }
+        audited.audits.push(attestation);
+        audited.audits.sort_by(|a, b| a.auditor.cmp(&b.auditor));
+        state.set_record(record_id, audited.clone())?;
+        state.add_record_event(
+            "audit_attested",
+            &audited,
+            vec![
+                ("auditor", signer.to_string()),
+                ("passed", payload.get_passed().to_string()),
+            ],
+        )
+    }
+
+    /// Sets or removes metadata entries of a record, keeping them sorted
+    /// by key
+    fn _set_record_metadata(
+        &self,
+        payload: &payload::SetRecordMetadataAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let mut metadata_record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        match metadata_record.owners.last() {
+            Some(owner) if owner.agent_id == signer => (),
+            _ => {
+                return Err(invalid(Code::NotAuthorized, String::from(
+                    "Must be owner to set record metadata",
+                )))
+            }
+        }
+        if metadata_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is final: {}",
+                record_id
+            )));
+        }
+
+        for entry in payload.get_entries() {
+            metadata_record.metadata.retain(|existing| existing.key != entry.key);
+            if !entry.get_value().is_empty() {
+                metadata_record.metadata.push(entry.clone());
+            }
+        }
+        if metadata_record.metadata.len() > MAX_METADATA_ENTRIES {
+            return Err(invalid(Code::LimitReached, format!(
+                "Records may have at most {} metadata entries",
+                MAX_METADATA_ENTRIES
+            )));
+        }
+        metadata_record.metadata.sort_by(|a, b| a.key.cmp(&b.key));
+        state.set_record(record_id, metadata_record)
+    }
+
+    /// Recalls a record, and if asked to, each record down the line of
+    /// merges it went into. Descendants already recalled are left as
+    /// they are.
+    fn _recall_record(
+        &self,
+        payload: &payload::RecallRecordAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let recalled = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        match recalled.owners.last() {
+            Some(owner) if owner.agent_id == signer => (),
+            _ => {
+                return Err(invalid(Code::NotAuthorized, String::from(
+                    "Must be owner to recall record",
+                )))
+            }
+        }
+        if recalled.has_recall() {
+            return Err(invalid(Code::RecordRecalled, format!(
+                "Record is already recalled: {}",
+                record_id
+            )));
+        }
+
+        let mut recall = record::Record_Recall::new();
+        recall.set_reason(payload.get_reason().to_string());
+        recall.set_recalled_by(signer.to_string());
+        recall.set_recalled_at(timestamp);
+
+        let mut next_id = recalled.get_consumed_by().to_string();
+        self._set_recall(&mut state, recalled, recall.clone())?;
+        if !payload.get_include_descendants() {
+            return Ok(());
+        }
+
+        recall.set_origin(record_id.to_string());
+        let mut count = 0;
+        while !next_id.is_empty() {
+            count += 1;
+            if count > MAX_RECALL_DESCENDANTS {
+                return Err(invalid(Code::LimitReached, format!(
+                    "A recall may include at most {} merged records",
+                    MAX_RECALL_DESCENDANTS
+                )));
+            }
+            let descendant = match state.get_record(&next_id)? {
+                Some(record) => record,
+                None => {
+                    return Err(invalid(Code::InconsistentState, format!(
+                        "Record merged into was not found: {}",
+                        next_id
+                    )))
+                }
+            };
+            next_id = descendant.get_consumed_by().to_string();
+            if !descendant.has_recall() {
+                self._set_recall(&mut state, descendant, recall.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks a record as recalled, with an event naming the reason
+    fn _set_recall(
+        &self,
+        state: &mut SupplyChainState,
+        mut recalled: record::Record,
+        recall: record::Record_Recall,
+    ) -> Result<(), ApplyError> {
+        let mut details = vec![("reason", recall.get_reason().to_string())];
+        if !recall.get_origin().is_empty() {
+            details.push(("origin", recall.get_origin().to_string()));
+        }
+        let record_id = recalled.get_record_id().to_string();
+        recalled.set_recall(recall);
+        state.set_record(&record_id, recalled.clone())?;
+        state.add_record_event("recalled", &recalled, details)
+    }
+
+    /// Removes every page of a property's reported values, returning the
+    /// digest of the last, or None if it has no values
+    fn _prune_property(
+        &self,
+        state: &mut SupplyChainState,
+        record_id: &str,
+        name: &str,
+    ) -> Result<Option<record::PrunedProperty>, ApplyError> {
+        let mut prop = match state.get_property(record_id, name)? {
+            Some(prop) => prop,
+            None => return Ok(None),
+        };
+        let current_page = prop.get_current_page();
+        let digest = match state.get_property_page(record_id, name, current_page)? {
+            Some(page) => page.get_digest().to_string(),
+            None => return Ok(None),
+        };
+        let last_page = if prop.get_wrapped() {
+            PROPERTY_PAGE_MAX_LENGTH as u32
+        } else {
+            current_page
+        };
+        for page_num in 1..=last_page {
+            state.delete_property_page(record_id, name, page_num)?;
+        }
+        if prop.get_counts_pages() {
+            for reporter in prop.mut_reporters().iter_mut() {
+                reporter.set_page_count(0);
+            }
+            state.set_property(record_id, name, prop)?;
+        }
+
+        let mut pruned = record::PrunedProperty::new();
+        pruned.set_name(name.to_string());
+        pruned.set_digest(digest);
+        Ok(Some(pruned))
+    }
+
+    /// Checks that a record meets its type's finalization policy
+    fn _check_finalization_policy(
+        &self,
+        state: &mut SupplyChainState,
+        final_record: &record::Record,
+        record_type: &record::RecordType,
+    ) -> Result<(), ApplyError> {
+        let record_id = final_record.get_record_id();
+        let policy = record_type.get_finalization_policy();
+
+        let latest_value = |state: &mut SupplyChainState, name: &str| {
+            let prop = match state.get_property(record_id, name)? {
+                Some(prop) => prop,
+                None => {
+                    return Err(invalid(Code::PropertyNotFound, format!(
+                        "Property does not exist: {}",
+                        name
+                    )))
+                }
+            };
+            match self._latest_reported_value(state, record_id, &prop)? {
+                Some(value) => Ok(value),
+                None => Err(invalid(Code::IncompleteRecord, format!(
+                    "Property has no reported value: {}",
+                    name
+                ))),
+            }
+        };
+
+        for name in policy.get_reported_properties() {
+            latest_value(state, name)?;
+        }
+        for bound in policy.get_bounds() {
+            let name = bound.get_property_name();
+            let value = latest_value(state, name)?.get_number_value();
+            if value < bound.get_min() || value > bound.get_max() {
+                return Err(invalid(Code::IncompleteRecord, format!(
+                    "Latest value of {} is {}, outside {} to {}",
+                    name,
+                    value,
+                    bound.get_min(),
+                    bound.get_max()
+                )));
+            }
+        }
+
+        if policy.get_no_open_excursions() {
+            for schema in record_type.get_properties() {
+                if !schema.has_threshold() {
+                    continue;
+                }
+                let name = schema.get_name();
+                if let Some(log) = state.get_excursion_log(record_id, name)? {
+                    if log.get_excursions().iter().any(|excursion| excursion.get_end() == 0) {
+                        return Err(invalid(Code::IncompleteRecord, format!(
+                            "Property has an excursion which has not ended: {}",
+                            name
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the day's totals for a record type, starting them if this
+    /// is the first Record counted on the day
+    fn _count_record_stats<F: FnOnce(&mut stats::RecordTypeStats)>(
+        &self,
+        state: &mut SupplyChainState,
+        type_name: &str,
+        timestamp: u64,
+        count: F,
+    ) -> Result<(), ApplyError> {
+        let day = timestamp_seconds(timestamp) / SECONDS_PER_DAY;
+        let mut stats = match state.get_record_type_stats(type_name, day)? {
+            Some(stats) => stats,
+            None => {
+                let mut stats = stats::RecordTypeStats::new();
+                stats.set_record_type(type_name.to_string());
+                stats.set_day(day);
+                stats
+            }
+        };
+        count(&mut stats);
+        state.set_record_type_stats(type_name, day, stats)
+    }
+
+    fn _create_record_type(
+        &self,
+        payload: &payload::CreateRecordTypeAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        match state.get_agent(signer) {
+            Ok(Some(_)) => (),
+            Ok(None) => {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Agent is not register: {}",
+                    signer
+                )))
+            }
+            Err(err) => return Err(err),
+        }
+        let name = payload.get_name();
+
+        self._check_namespace_member(&mut state, name, signer)?;
+        self._check_property_schemas(&mut state, payload.get_properties())?;
+
+        match state.get_record_type(name) {
+            Ok(Some(_)) => {
+                return Err(invalid(Code::RecordTypeExists, format!(
+                    "Record type already exists: {}",
+                    signer
+                )))
+            }
+            Ok(None) => (),
+            Err(err) => return Err(err),
+        }
+        let mut record_type = record::RecordType::new();
+        record_type.set_name(name.to_string());
+        record_type.set_properties(RepeatedField::from_vec(payload.get_properties().to_vec()));
+        record_type.set_rules(RepeatedField::from_vec(payload.get_rules().to_vec()));
+        record_type.set_retention_rules(RepeatedField::from_vec(
+            payload.get_retention_rules().to_vec(),
+        ));
+        record_type.set_creator(signer.to_string());
+        record_type.set_unused(true);
+        record_type.set_version(1);
+        if payload.has_finalization_policy() {
+            record_type.set_finalization_policy(payload.get_finalization_policy().clone());
+        }
+
+        state.set_record_type(name, record_type)?;
+
+        Ok(())
+    }
+
+    /// Stores initial values for Records of a type. Each value is checked
+    /// as it would be on a Record created from the template now, though a
+    /// Record is only created if its values pass the checks then.
+    fn _create_record_template(
+        &self,
+        payload: &payload::CreateRecordTemplateAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        if state.get_agent(signer)?.is_none() {
+            return Err(invalid(Code::AgentNotFound, format!(
+                "Agent is not register: {}",
+                signer
+            )));
+        }
+        let name = payload.get_name();
+        if state.get_record_template(name)?.is_some() {
+            return Err(invalid(Code::RecordTemplateExists, format!(
+                "Record template already exists: {}",
+                name
+            )));
+        }
+        let type_name = payload.get_record_type();
+        let record_type = match state.get_record_type(type_name)? {
+            Some(record_type) => record_type,
+            None => {
+                return Err(invalid(Code::RecordTypeNotFound, format!(
+                    "Record Type does not exist {}",
+                    type_name
+                )))
+            }
+        };
+
+        let properties = payload.get_properties();
+        for (i, value) in properties.iter().enumerate() {
+            let value_name = value.get_name();
+            if properties[..i].iter().any(|other| other.get_name() == value_name) {
+                return Err(invalid(Code::Duplicate, format!(
+                    "Property is given more than one value: {}",
+                    value_name
+                )));
+            }
+            let schema = match record_type
+                .get_properties()
+                .iter()
+                .find(|schema| schema.get_name() == value_name)
+            {
+                Some(schema) => schema,
+                None => {
+                    return Err(invalid(Code::PropertyNotFound, format!(
+                        "Provided property {} is not in schemata",
+                        value_name
+                    )))
+                }
+            };
+            if value.get_data_type() != schema.get_data_type() {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Value provided for {} is the wrong type",
+                    value_name
+                )));
+            }
+            if schema.get_delayed() {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Property is 'delayed', and cannot be set at record creation: {}",
+                    value_name
+                )));
+            }
+            self._make_new_reported_value(0, timestamp, value, schema)?;
+        }
+
+        let mut template = record::RecordTemplate::new();
+        template.set_name(name.to_string());
+        template.set_record_type(type_name.to_string());
+        template.set_properties(RepeatedField::from_vec(properties.to_vec()));
+        template.set_creator(signer.to_string());
+        template.set_timestamp(timestamp);
+        state.set_record_template(name, template)
+    }
+
+    /// Creates a Record from a template's values, with the values the
+    /// signer provides taking the place of the template's
+    fn _create_record_from_template(
+        &self,
+        payload: &payload::CreateRecordFromTemplateAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let template_name = payload.get_template();
+        let template = match state.get_record_template(template_name)? {
+            Some(template) => template,
+            None => {
+                return Err(invalid(Code::RecordTemplateNotFound, format!(
+                    "Record template does not exist: {}",
+                    template_name
+                )))
+            }
+        };
+
+        let provided = payload.get_properties();
+        let mut properties: Vec<property::PropertyValue> = template
+            .get_properties()
+            .iter()
+            .filter(|value| {
+                !provided.iter().any(|other| other.get_name() == value.get_name())
+            })
+            .cloned()
+            .collect();
+        properties.extend(provided.iter().cloned());
+
+        let mut create_record = payload::CreateRecordAction::new();
+        create_record.set_record_id(payload.get_record_id().to_string());
+        create_record.set_record_type(template.get_record_type().to_string());
+        create_record.set_properties(RepeatedField::from_vec(properties));
+        create_record.set_reporters(RepeatedField::from_vec(payload.get_reporters().to_vec()));
+        self._create_record(&create_record, state, signer, timestamp)
+    }
+
+    /// Checks that the signer is a member of the namespace of a record
+    /// type's name, if it has one
+    fn _check_namespace_member(
+        &self,
+        state: &mut SupplyChainState,
+        name: &str,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        if let Some(namespace) = record_type_namespace(name) {
+            let key = format!("{}{}", RECORD_TYPE_NAMESPACE_SETTING, namespace);
+            let members = match state.get_setting(&key)? {
+                Some(members) => members,
+                None => {
+                    return Err(invalid(Code::NamespaceNotRegistered, format!(
+                        "Record type namespace is not registered: {}",
+                        namespace
+                    )))
+                }
+            };
+            if !members.split(',').any(|key| key.trim() == signer) {
+                return Err(invalid(Code::NotNamespaceMember, format!(
+                    "Agent is not a member of record type namespace {}: {}",
+                    namespace, signer
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the property schemas of a new record type, or a new version
+    /// of one, against the limits and flags which depend on settings
+    fn _check_property_schemas(
+        &self,
+        state: &mut SupplyChainState,
+        properties: &[property::PropertySchema],
+    ) -> Result<(), ApplyError> {
+        let max_properties =
+            state.get_limit_setting(MAX_PROPERTIES_SETTING, DEFAULT_MAX_PROPERTIES)?;
+        if properties.len() > max_properties {
+            return Err(invalid(Code::LimitReached, format!(
+                "Record type has {} properties, more than the maximum of {}; \
+                 group related properties into struct properties or split \
+                 them across linked records",
+                properties.len(),
+                max_properties
+            )));
+        }
+
+        for property in properties {
+            check_schema_size(property)?;
+            self._validate_property_flags(property)?;
+            if property.has_default_value() {
+                self._validate_default_value(property)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the definition of a record type with a new version. The
+    /// version being replaced is kept for the Records created with it,
+    /// unless there are none.
+    fn _update_record_type(
+        &self,
+        payload: &payload::UpdateRecordTypeAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let name = payload.get_name();
+        let current = match state.get_record_type(name)? {
+            Some(record_type) => record_type,
+            None => {
+                return Err(invalid(Code::RecordTypeNotFound, format!(
+                    "Record type does not exist: {}",
+                    name
+                )))
+            }
+        };
+
+        // The members of a namespace share its types, and any other type
+        // belongs to its creator
+        if record_type_namespace(name).is_some() {
+            self._check_namespace_member(&mut state, name, signer)?;
+        } else if current.get_creator() != signer {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Only the creator of a record type can update it: {}",
+                name
+            )));
+        }
+        self._check_property_schemas(&mut state, payload.get_properties())?;
+
+        let mut updated = current.clone();
+        updated.set_properties(RepeatedField::from_vec(payload.get_properties().to_vec()));
+        updated.set_rules(RepeatedField::from_vec(payload.get_rules().to_vec()));
+        updated.set_retention_rules(RepeatedField::from_vec(
+            payload.get_retention_rules().to_vec(),
+        ));
+        if payload.has_finalization_policy() {
+            updated.set_finalization_policy(payload.get_finalization_policy().clone());
+        } else {
+            updated.clear_finalization_policy();
+        }
+        updated.set_version(type_version(current.get_version()) + 1);
+
+        if !current.get_unused() {
+            state.set_record_type_version(current)?;
+        }
+        state.set_record_type(name, updated)
+    }
+
+    fn _delete_record_type(
+        &self,
+        payload: &payload::DeleteRecordTypeAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let name = payload.get_name();
+        let record_type = match state.get_record_type(name)? {
+            Some(record_type) => record_type,
+            None => {
+                return Err(invalid(Code::RecordTypeNotFound, format!(
+                    "Record type does not exist: {}",
+                    name
+                )))
+            }
+        };
+
+        // Records cannot be listed by type, so the type itself keeps
+        // whether one has ever been created
+        if !record_type.get_unused() {
+            return Err(invalid(Code::RecordTypeInUse, format!(
+                "Record type has been used, or predates tracking its use: {}",
+                name
+            )));
+        }
+        if record_type.get_creator() != signer {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Only the creator of a record type can delete it: {}",
+                name
+            )));
+        }
+
+        state.delete_record_type(name)
+    }
+
+    fn _update_properties(
+        &self,
+        payload: &payload::UpdatePropertiesAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let update_record = match state.get_record(record_id) {
+            Ok(Some(update_record)) => update_record,
+            Ok(None) => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+
+        if update_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is final: {}",
+                record_id
+            )));
+        }
+
+        let type_name = update_record.get_record_type();
+
+        let updates = payload.get_properties();
+        let mut awaiting_acknowledgment = Vec::new();
+        // Writing a Property can move its cursor, such as onto a new page,
+        // so the cursors are read again from the stored Record after one
+        let mut cursor_record: Option<record::Record> = None;
+
+        for update in updates {
+            let name = update.get_name();
+            let data_type = update.get_data_type();
+            let mask = payload
+                .get_struct_masks()
+                .iter()
+                .find(|mask| mask.get_property() == name);
+
+            // A lone reporter's value nearly always goes on the page that
+            // the Record's cursor names, so the Property is only loaded if
+            // it is needed to count that page or to start the next one
+            let cursor = match mask {
+                None if self.plugins.is_empty() => {
+                    let record = cursor_record.as_ref().unwrap_or(&update_record);
+                    find_reporting_cursor(record, name, signer, timestamp).cloned()
+                }
+                _ => None,
+            };
+            let (prop, reporter_index, page_number) = match cursor {
+                Some(cursor) => {
+                    if data_type != cursor.get_data_type() {
+                        return Err(invalid(Code::InvalidValue, format!(
+                            "Update has wrong type: {:?} != {:?}",
+                            data_type,
+                            cursor.get_data_type()
+                        )));
+                    }
+                    (None, cursor.get_reporter_index(), cursor.get_current_page())
+                }
+                None => {
+                    let prop = match state.get_property(record_id, name) {
+                        Ok(Some(prop)) => prop,
+                        Ok(None) => {
+                            return Err(invalid(Code::PropertyNotFound, format!(
+                                "Record does not have provided poperty: {}",
+                                name
+                            )))
+                        }
+                        Err(err) => return Err(err),
+                    };
+
+                    let reporters = prop.get_reporters();
+                    let authorized_reporter = match find_reporter(reporters, signer) {
+                        Some(position)
+                            if reporter_is_authorized(&reporters[position], timestamp) =>
+                        {
+                            Some(&reporters[position])
+                        }
+                        // Lists written before slots were reused may name an
+                        // agent twice
+                        _ => reporters.iter().find(|r| {
+                            r.get_public_key() == signer && reporter_is_authorized(r, timestamp)
+                        }),
+                    };
+                    let reporter_index = match authorized_reporter {
+                        Some(reporter) => reporter.get_index(),
+                        None => {
+                            return Err(invalid(Code::NotAuthorized, format!(
+                                "Reporter is not authorized: {}",
+                                signer
+                            )))
+                        }
+                    };
+
+                    if prop.fixed {
+                        return Err(invalid(Code::PropertyImmutable, format!(
+                            "Property is fixed and cannot be updated: {}",
+                            prop.name
+                        )));
+                    }
+
+                    if data_type != prop.data_type {
+                        return Err(invalid(Code::InvalidValue, format!(
+                            "Update has wrong type: {:?} != {:?}",
+                            data_type, prop.data_type
+                        )));
+                    }
+
+                    let page_number = prop.get_current_page();
+                    (Some(prop), reporter_index, page_number)
+                }
+            };
+
+            let schema = match state.get_property_schema(&update_record, name)? {
+                Some(schema) => schema,
+                None => {
+                    return Err(ApplyError::InternalError(format!(
+                        "Record type {} does not have property: {}",
+                        type_name, name
+                    )))
+                }
+            };
+
+            // The record type may narrow who reports a property on any
+            // of its Records, whoever the Record's owner authorizes
+            let allowed = schema.get_allowed_reporters();
+            if !allowed.is_empty() && !allowed.iter().any(|key| key == signer) {
+                return Err(invalid(Code::NotAuthorized, format!(
+                    "Record type {} does not allow {} to report {}",
+                    type_name, signer, name
+                )));
+            }
+
+            // A partial struct is filled in from the latest value, and
+            // everything after sees the merged struct
+            let merged;
+            let update = match (mask, prop.as_ref()) {
+                (Some(mask), Some(prop)) => {
+                    merged = self._merge_struct_update(&mut state, record_id, prop, update, mask)?;
+                    &merged
+                }
+                _ => update,
+            };
+
+            if let Some(ref prop) = prop {
+                self._run_plugins(|plugin| {
+                    plugin.pre_update_property(&update_record, prop, update, signer)
+                })?;
+            }
+
+            let mut bindings =
+                self._make_rule_bindings(&update_record, "update", signer, timestamp);
+            bindings.insert(String::from("property"), rules::Value::Str(name.to_string()));
+            if let Some(value) = values::from_property_value(&schema, update)
+                .ok()
+                .and_then(|value| rule_value(&value))
+            {
+                bindings.insert(String::from("value"), value);
+            }
+            self._bind_linked_values(&mut state, &update_record, updates, &mut bindings)?;
+            self._check_rules(&update_record, &bindings)?;
+
+            if schema.get_requires_acknowledgment() {
+                awaiting_acknowledgment.push(name.to_string());
+            }
+
+            let mut page = match state.get_property_page(record_id, name, page_number) {
+                Ok(Some(page)) => page,
+                Ok(None) => {
+                    return Err(invalid(Code::PropertyNotFound, String::from(
+                        "Property page does not exist",
+                    )))
+                }
+                Err(err) => return Err(err),
+            };
+
+            // An immutable property's single value is always on its first page
+            let already_set = match prop {
+                Some(ref prop) if prop.get_immutable() => {
+                    page_number != 1 || prop.get_wrapped() || !page.reported_values.is_empty()
+                }
+                _ => false,
+            };
+            if already_set {
+                return Err(invalid(Code::PropertyImmutable, format!(
+                    "Property is immutable and already has a value: {}",
+                    name
+                )));
+            }
+
+            let reported_value = match self._make_new_reported_value(
+                reporter_index,
+                timestamp,
+                update,
+                &schema,
+            ) {
+                Ok(reported_value) => reported_value,
+                Err(err) => return Err(err),
+            };
+            self._check_record_reference(&mut state, record_id, update)?;
+            let number_value = reported_value.get_number_value();
+            // The last value on a page is nearly always the reporter's own
+            let first_on_page = !page
+                .reported_values
+                .iter()
+                .rev()
+                .any(|rv| rv.reporter_index == reporter_index);
+            // Updates almost always arrive in timestamp order, so append
+            // and only search for the insertion point when one is late
+            let key = (reported_value.timestamp, reported_value.reporter_index);
+            let in_order = match page.reported_values.last() {
+                Some(last) => (last.timestamp, last.reporter_index) <= key,
+                None => true,
+            };
+            if in_order {
+                page.reported_values.push(reported_value);
+            } else {
+                let index = page
+                    .reported_values
+                    .iter()
+                    .position(|rv| (rv.timestamp, rv.reporter_index) > key)
+                    .unwrap_or(page.reported_values.len());
+                page.reported_values.insert(index, reported_value);
+            }
+            // An appended value extends the page's chain. A late one, or
+            // a page written before digests were kept, is chained again
+            // from the start of the page.
+            let legacy_page = page.get_digest().is_empty() && page.reported_values.len() > 1;
+            let digest = if in_order && !legacy_page {
+                let appended = page.reported_values.len() - 1;
+                chain_digest(page.get_digest(), &page.get_reported_values()[appended..])?
+            } else {
+                chain_digest(page.get_previous_digest(), &page.reported_values)?
+            };
+            page.set_digest(digest);
+            state.set_property_page(record_id, name, page_number, page.clone())?;
+            let page_full = page.reported_values.len() >= PROPERTY_PAGE_MAX_LENGTH;
+            let mut prop = match prop {
+                Some(prop) => prop,
+                None if first_on_page || page_full => match state.get_property(record_id, name)? {
+                    Some(prop) => prop,
+                    None => {
+                        return Err(ApplyError::InternalError(format!(
+                            "Record {} has a reporting cursor for a missing property: {}",
+                            record_id, name
+                        )))
+                    }
+                },
+                None => continue,
+            };
+            let counted = first_on_page && prop.get_counts_pages();
+            if counted {
+                count_reporter_page(&mut prop, reporter_index, true);
+            }
+            // Excursions are tracked in timestamp order, so late values
+            // are stored but cannot open or close one
+            if in_order && prop.has_threshold() {
+                self._track_excursion(
+                    &mut state,
+                    &update_record,
+                    &prop,
+                    number_value,
+                    timestamp,
+                )?;
+            }
+            if page_full {
+                let mut new_page_number = page_number + 1;
+                if page_number + 1 <= PROPERTY_PAGE_MAX_LENGTH as u32 {
+                    new_page_number = 1;
+                }
+
+                let mut new_page = match state.get_property_page(record_id, name, new_page_number) {
+                    Ok(Some(mut new_page)) => {
+                        // The values the page held are overwritten
+                        let dropped: BTreeSet<u32> = new_page
+                            .get_reported_values()
+                            .iter()
+                            .map(|rv| rv.get_reporter_index())
+                            .collect();
+                        for index in dropped {
+                            count_reporter_page(&mut prop, index, false);
+                        }
+                        new_page.set_reported_values(RepeatedField::from_vec(Vec::new()));
+                        new_page
+                    }
+                    Ok(None) => {
+                        let mut new_page = property::PropertyPage::new();
+                        new_page.set_name(name.to_string());
+                        new_page.set_record_id(record_id.to_string());
+                        new_page
+                    }
+                    Err(err) => return Err(err),
+                };
+                new_page.set_previous_digest(page.get_digest().to_string());
+                new_page.set_digest(page.get_digest().to_string());
+                state.set_property_page(record_id, name, new_page_number, new_page)?;
+
+                prop.set_current_page(new_page_number);
+                if new_page_number == 1 && !prop.get_wrapped() {
+                    prop.set_wrapped(true);
+                }
+                state.set_property(record_id, name, prop)?;
+                cursor_record = state.get_record(record_id)?;
+            } else if counted {
+                state.set_property(record_id, name, prop)?;
+                cursor_record = state.get_record(record_id)?;
+            }
+        }
+
+        // Values reported by the owner need no acknowledgment from them
+        let by_owner = match update_record.owners.last() {
+            Some(owner) => owner.get_agent_id() == signer,
+            None => false,
+        };
+        if !awaiting_acknowledgment.is_empty() && !by_owner {
+            let mut pending_record = match state.get_record(record_id)? {
+                Some(record) => record,
+                None => update_record.clone(),
+            };
+            for name in awaiting_acknowledgment {
+                if let Err(index) = pending_record.get_unacknowledged().binary_search(&name) {
+                    pending_record.mut_unacknowledged().insert(index, name);
+                }
+            }
+            state.set_record(record_id, pending_record)?;
+        }
+
+        let details = updates
+            .iter()
+            .map(|update| ("property", update.get_name().to_string()))
+            .collect();
+        state.add_record_event("property_updated", &update_record, details)?;
+
+        // Reporting an escrowed transfer's release property completes it
+        if update_record.has_escrow() {
+            let release_property = update_record.get_escrow().get_release_property();
+            if !release_property.is_empty()
+                && updates.iter().any(|update| update.get_name() == release_property)
+            {
+                self._settle_escrow(&mut state, record_id, false, timestamp)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Acknowledges, as the owner of a Record, the values reported for
+    /// one of its Properties since they were last acknowledged
+    fn _acknowledge_value(
+        &self,
+        payload: &payload::AcknowledgeValueAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let name = payload.get_property_name();
+        let mut acknowledged_record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+
+        match acknowledged_record.owners.last() {
+            Some(owner) if owner.get_agent_id() == signer => (),
+            _ => {
+                return Err(invalid(Code::NotAuthorized, String::from(
+                    "Only the owner of a record can acknowledge its values",
+                )))
+            }
+        }
+
+        let index = match acknowledged_record
+            .get_unacknowledged()
+            .iter()
+            .position(|pending| pending == name)
+        {
+            Some(index) => index,
+            None => {
+                return Err(invalid(Code::NothingToAcknowledge, format!(
+                    "Property has no values awaiting acknowledgment: {}",
+                    name
+                )))
+            }
+        };
+        acknowledged_record.mut_unacknowledged().remove(index);
+        state.set_record(record_id, acknowledged_record.clone())?;
+        state.add_record_event(
+            "value_acknowledged",
+            &acknowledged_record,
+            vec![("property", name.to_string())],
+        )
+    }
+
+    fn _merge_struct_update(
+        &self,
+        state: &mut SupplyChainState,
+        record_id: &str,
+        prop: &property::Property,
+        update: &property::PropertyValue,
+        mask: &payload::StructUpdateMask,
+    ) -> Result<property::PropertyValue, ApplyError> {
+        if prop.data_type != property::PropertySchema_DataType::STRUCT {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Only STRUCT properties can be partially updated: {}",
+                prop.name
+            )));
+        }
+
+        let latest = match self._latest_reported_value(state, record_id, prop)? {
+            Some(latest) => latest,
+            None => {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Property has no value to partially update: {}",
+                    prop.name
+                )))
+            }
+        };
+
+        let struct_values = values::merge_struct(
+            latest.get_struct_values(),
+            update.get_struct_values(),
+            mask.get_fields(),
+        )
+        .map_err(|err| invalid(Code::InvalidValue, err))?;
+
+        let mut merged = update.clone();
+        merged.set_struct_values(RepeatedField::from_vec(struct_values));
+        Ok(merged)
+    }
+
+    /// Finds the latest value reported for a property: the last on its
+    /// current page, or on the page before if the current one was just
+    /// started.
+    fn _latest_reported_value(
+        &self,
+        state: &mut SupplyChainState,
+        record_id: &str,
+        prop: &property::Property,
+    ) -> Result<Option<property::PropertyPage_ReportedValue>, ApplyError> {
+        let name = prop.get_name();
+        let current_page = prop.get_current_page();
+        if let Some(page) = state.get_property_page(record_id, name, current_page)? {
+            if let Some(value) = page.get_reported_values().last() {
+                return Ok(Some(value.clone()));
+            }
+        }
+
+        let previous_page = if current_page > 1 {
+            current_page - 1
+        } else if prop.get_wrapped() {
+            PROPERTY_PAGE_MAX_LENGTH as u32
+        } else {
+            return Ok(None);
+        };
+        Ok(state
+            .get_property_page(record_id, name, previous_page)?
+            .and_then(|page| page.get_reported_values().last().cloned()))
+    }
+
+    fn _create_proposal(
+        &self,
+        payload: &payload::CreateProposalAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let receiving_agent = payload.get_receiving_agent();
+        let role = payload.get_role();
+        let properties = payload.get_properties();
+        let effective_at = payload.get_effective_at();
+        let expires_at = payload.get_expires_at();
+        let expiration = payload.get_expiration();
+        let approvers = payload.get_approvers();
+
+        match state.get_agent(signer) {
+            Ok(Some(agent)) => agent,
+            Ok(None) => {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Issuing agent does not exist: {}",
+                    signer
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+
+        match state.get_agent(&receiving_agent) {
+            Ok(Some(agent)) => agent,
+            Ok(None) => {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Receiving agent does not exist: {}",
+                    receiving_agent
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+
+        if approvers.len() > MAX_PROPOSAL_APPROVERS {
+            return Err(invalid(Code::LimitReached, format!(
+                "A proposal can have at most {} approvers",
+                MAX_PROPOSAL_APPROVERS
+            )));
+        }
+        for (i, approver) in approvers.iter().enumerate() {
+            if approver == signer || approver == receiving_agent {
+                return Err(invalid(Code::InvalidProposal, String::from(
+                    "The issuing and receiving agents cannot be approvers",
+                )));
+            }
+            if approvers[..i].contains(approver) {
+                return Err(invalid(Code::Duplicate, format!(
+                    "Approver listed more than once: {}",
+                    approver
+                )));
+            }
+            if state.get_agent(approver)?.is_none() {
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Approving agent does not exist: {}",
+                    approver
+                )));
+            }
+        }
+
+        if expiration != 0 && expiration <= timestamp {
+            return Err(invalid(Code::InvalidProposal, format!(
+                "Proposal would already be expired: {}",
+                expiration
+            )));
+        }
+
+        self._expire_proposals(&mut state, record_id, role, receiving_agent, timestamp)?;
+
+        let mut proposals = match state.get_proposal_container(&record_id, &receiving_agent) {
+            Ok(Some(proposals)) => proposals,
+            Ok(None) => proposal::ProposalContainer::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut open_proposals = Vec::<proposal::Proposal>::new();
+        for prop in proposals.get_entries() {
+            if prop.status == proposal::Proposal_Status::OPEN
+                || prop.status == proposal::Proposal_Status::SCHEDULED
+                || prop.status == proposal::Proposal_Status::ESCROWED
+            {
+                open_proposals.push(prop.clone());
+            }
+        }
+
+        for prop in open_proposals {
+            if prop.get_receiving_agent() == receiving_agent && prop.get_role() == role
+                && prop.get_record_id() == record_id
+            {
+                return Err(invalid(Code::ProposalExists, String::from(
+                    "Proposal already exists",
+                )));
+            }
+        }
+
+        let proposal_record = match state.get_record(&record_id) {
+            Ok(Some(record)) => record,
+            Ok(None) => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+
+        if proposal_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is final: {}",
+                record_id
+            )));
+        }
+        if proposal_record.has_recall() && role != proposal::Proposal_Role::REPORTER {
+            return Err(invalid(Code::RecordRecalled, format!(
+                "Recalled records cannot be transferred: {}",
+                record_id
+            )));
+        }
+        if proposal_record.has_escrow() && role == proposal::Proposal_Role::OWNER {
+            return Err(invalid(Code::RecordInEscrow, format!(
+                "Record is held in escrow: {}",
+                record_id
+            )));
+        }
+
+        if role == proposal::Proposal_Role::OWNER || role == proposal::Proposal_Role::REPORTER {
+            let owner = match proposal_record.owners.last() {
+                Some(owner) => owner,
+                None => {
+                    return Err(invalid(Code::InconsistentState, String::from(
+                        "Owner not found",
+                    )))
+                }
+            };
+            if owner.get_agent_id() != signer {
+                return Err(invalid(Code::NotAuthorized, String::from(
+                    "Only the owner can create a proposal to change ownership",
+                )));
+            }
+            if role == proposal::Proposal_Role::REPORTER && properties.len() == 0 {
+                return Err(invalid(Code::MissingField, String::from(
+                    "Property list cannot be empty for Reporter role",
+                )))
+            }
+        }
+
+        if role == proposal::Proposal_Role::REPORTER && effective_at != 0 {
+            return Err(invalid(Code::InvalidProposal, String::from(
+                "Reporter proposals cannot have an effective time",
+            )));
+        }
+
+        if expires_at != 0 {
+            if role != proposal::Proposal_Role::REPORTER {
+                return Err(invalid(Code::InvalidProposal, String::from(
+                    "Only reporter proposals can have an expiry time",
+                )));
+            }
+            if expires_at <= timestamp {
+                return Err(invalid(Code::InvalidProposal, format!(
+                    "Reporter authorization would already be expired: {}",
+                    expires_at
+                )));
+            }
+        }
+
+        if payload.has_transfer_terms() {
+            check_transfer_terms(payload.get_transfer_terms(), role, timestamp)?;
+        }
+
+        let release_property = payload.get_release_property();
+        if payload.get_escrow() {
+            if role != proposal::Proposal_Role::OWNER {
+                return Err(invalid(Code::InvalidProposal, String::from(
+                    "Only ownership transfers can be held in escrow",
+                )));
+            }
+            if effective_at != 0 {
+                return Err(invalid(Code::InvalidProposal, String::from(
+                    "Escrowed transfers cannot have an effective time",
+                )));
+            }
+            if !release_property.is_empty()
+                && state.get_property(record_id, release_property)?.is_none()
+            {
+                return Err(invalid(Code::PropertyNotFound, format!(
+                    "Release property does not exist: {}",
+                    release_property
+                )));
+            }
+        } else if !release_property.is_empty() {
+            return Err(invalid(Code::InvalidProposal, String::from(
+                "Only escrowed transfers can have a release property",
+            )));
+        }
+
+        if role == proposal::Proposal_Role::CUSTODIAN {
+            let custodian = match proposal_record.custodians.last() {
+                Some(custodian) => custodian,
+                None => {
+                    return Err(invalid(Code::InconsistentState, String::from(
+                        "Custodian not found",
+                    )))
+                }
+            };
+
+            if custodian.get_agent_id() != signer {
+                return Err(invalid(Code::NotAuthorized, String::from(
+                    "Only the custodian can create a proposal to change custodianship",
+                )));
+            }
+        }
+
+        let mut open_index = match state.get_open_proposal_index(record_id)? {
+            Some(index) => index,
+            None => {
+                let mut index = proposal::OpenProposalIndex::new();
+                index.set_record_id(record_id.to_string());
+                index
+            }
+        };
+
+        // Entries from an earlier holder of the role are left to be
+        // canceled when answered, and do not count against the signer
+        let outstanding = open_index
+            .get_entries()
+            .iter()
+            .filter(|entry| entry.get_role() == role && entry.get_issuing_agent() == signer)
+            .count();
+        if role == proposal::Proposal_Role::REPORTER {
+            let max_open =
+                state.get_limit_setting(MAX_OPEN_PROPOSALS_SETTING, DEFAULT_MAX_OPEN_PROPOSALS)?;
+            if outstanding >= max_open {
+                return Err(invalid(Code::LimitReached, format!(
+                    "Issuer already has the maximum of {} open reporter proposals for record {}",
+                    max_open, record_id
+                )));
+            }
+        } else if outstanding > 0 {
+            return Err(invalid(Code::ProposalExists, format!(
+                "A {:?} transfer proposal is already open for record {}",
+                role, record_id
+            )));
+        }
+
+        let mut entry = proposal::OpenProposalIndex_Entry::new();
+        entry.set_role(role);
+        entry.set_issuing_agent(signer.to_string());
+        entry.set_receiving_agent(receiving_agent.to_string());
+        open_index.entries.push(entry);
+        state.set_open_proposal_index(record_id, open_index)?;
+
+        let mut new_proposal = proposal::Proposal::new();
+        new_proposal.set_record_id(record_id.to_string());
+        new_proposal.set_timestamp(timestamp);
+        new_proposal.set_issuing_agent(signer.to_string());
+        new_proposal.set_receiving_agent(receiving_agent.to_string());
+        new_proposal.set_role(role);
+        new_proposal.set_properties(RepeatedField::from_vec(properties.to_vec()));
+        new_proposal.set_status(proposal::Proposal_Status::OPEN);
+        new_proposal.set_effective_at(effective_at);
+        new_proposal.set_expires_at(expires_at);
+        new_proposal.set_expiration(expiration);
+        new_proposal.set_approvers(RepeatedField::from_vec(approvers.to_vec()));
+        if payload.has_transfer_terms() {
+            new_proposal.set_transfer_terms(payload.get_transfer_terms().clone());
+        }
+        new_proposal.set_escrow(payload.get_escrow());
+        new_proposal.set_release_property(release_property.to_string());
+
+        state.add_record_event(
+            "proposal_created",
+            &proposal_record,
+            proposal_event_details(&new_proposal),
+        )?;
+
+        proposals.entries.push(new_proposal);
+        proposals.entries.sort_by_key(|p| {
+            (
+                p.clone().record_id,
+                p.clone().receiving_agent,
+                p.clone().timestamp,
+            )
+        });
+        state.set_proposal_container(&record_id, &receiving_agent, proposals)?;
+
+        Ok(())
+    }
+
+    fn _answer_proposal(
+        &self,
+        payload: &payload::AnswerProposalAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let receiving_agent = payload.get_receiving_agent();
+        let role = payload.get_role();
+        let response = payload.get_response();
+
+        let proposals = match state.get_proposal_container(record_id, receiving_agent) {
+            Ok(Some(proposals)) => proposals,
+            Ok(None) => {
+                return Err(invalid(Code::ProposalNotFound, String::from(
+                    "Proposal does not exist",
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut exists = false;
+        let mut current_proposal = match proposals.clone().entries.last() {
+            Some(current_proposal) => current_proposal.clone(),
+            None => {
+                return Err(invalid(Code::ProposalNotFound, format!(
+                    "No open proposals found for record {} for {}",
+                    record_id, receiving_agent
+                )))
+            }
+        };
+
+        let mut proposal_index = 0;
+        let mut count = 0;
+
+        for prop in proposals.get_entries() {
+            if prop.get_receiving_agent() == receiving_agent && prop.get_role() == role
+                && prop.get_record_id() == record_id
+                && prop.status == proposal::Proposal_Status::OPEN
+            {
+                current_proposal = prop.clone();
+                exists = true;
+                proposal_index = count;
+                break;
+            }
+            count = count + 1;
+        }
+
+        if !exists {
+            return Err(invalid(Code::ProposalNotFound, format!(
+                "No open proposals found for record {} for {}",
+                record_id, receiving_agent
+            )));
+        }
+
+        if proposal_has_expired(&current_proposal, timestamp) {
+            if current_proposal.get_issuing_agent() != signer
+                && current_proposal.get_receiving_agent() != signer
+            {
+                return Err(invalid(Code::NotAuthorized, String::from(
+                    "Only the issuing or receiving agent can answer a proposal",
+                )));
+            }
+            current_proposal.status = proposal::Proposal_Status::EXPIRED;
+            info!("Proposal expired before it was answered");
+            return self._close_proposal(&mut state, proposals, proposal_index, current_proposal);
+        }
+
+        let is_approver = current_proposal.get_approvers().iter().any(|a| a == signer);
+
+        match response {
+            payload::AnswerProposalAction_Response::CANCEL => {
+                if current_proposal.get_issuing_agent() != signer {
+                    return Err(invalid(Code::NotAuthorized, String::from(
+                        "Only the issuing agent can cancel a proposal",
+                    )));
+                }
+                current_proposal.status = proposal::Proposal_Status::CANCELED;
+            }
+            payload::AnswerProposalAction_Response::REJECT => {
+                if current_proposal.get_receiving_agent() != signer && !is_approver {
+                    return Err(invalid(Code::NotAuthorized, String::from(
+                        "Only the receiving agent can reject a proposal",
+                    )));
+                }
+                current_proposal.status = proposal::Proposal_Status::REJECTED;
+            }
+            payload::AnswerProposalAction_Response::COUNTER => {
+                if current_proposal.get_receiving_agent() != signer {
+                    return Err(invalid(Code::NotAuthorized, String::from(
+                        "Only the receiving agent can counter a proposal",
+                    )));
+                }
+                return self._counter_proposal(
+                    &mut state,
+                    proposals,
+                    proposal_index,
+                    current_proposal,
+                    payload.get_terms(),
+                    timestamp,
+                );
+            }
+            payload::AnswerProposalAction_Response::ACCEPT => {
+                if current_proposal.get_receiving_agent() != signer && !is_approver {
+                    return Err(invalid(Code::NotAuthorized, String::from(
+                        "Only the receiving agent can Accept a proposal",
+                    )));
+                };
+                if !current_proposal.get_approvers().is_empty() {
+                    if current_proposal.get_approvals().iter().any(|a| a == signer) {
+                        return Err(invalid(Code::Duplicate, format!(
+                            "Proposal already approved by {}",
+                            signer
+                        )));
+                    }
+                    current_proposal.approvals.push(signer.to_string());
+                    if !proposal_fully_approved(&current_proposal) {
+                        // Left open, with the approval recorded, until
+                        // the rest of the approvers have given theirs
+                        let mut proposals = proposals;
+                        proposals.entries[proposal_index] = current_proposal;
+                        return state.set_proposal_container(
+                            record_id,
+                            receiving_agent,
+                            proposals,
+                        );
+                    }
+                }
+                let transfer = proposal_transfer(&current_proposal);
+
+                let proposal_record = match state.get_record(record_id) {
+                    Ok(Some(record)) => record,
+                    Ok(None) => {
+                        return Err(invalid(Code::RecordNotFound, format!(
+                            "Record in proposal does not exist: {}",
+                            record_id
+                        )))
+                    }
+                    Err(err) => return Err(err),
+                };
+
+                let owner = match proposal_record.clone().owners.last() {
+                    Some(owner) => owner.clone(),
+                    None => {
+                        return Err(invalid(Code::InconsistentState, String::from(
+                            "Owner not found",
+                        )))
+                    }
+                };
+
+                let custodian = match proposal_record.clone().custodians.last() {
+                    Some(custodian) => custodian.clone(),
+                    None => {
+                        return Err(invalid(Code::InconsistentState, String::from(
+                            "Custodian not found",
+                        )))
+                    }
+                };
+
+                match role {
+                    proposal::Proposal_Role::OWNER | proposal::Proposal_Role::CUSTODIAN => {
+                        let (holder, holder_role) = match role {
+                            proposal::Proposal_Role::OWNER => (owner, "owner"),
+                            _ => (custodian, "custodian"),
+                        };
+                        if holder.get_agent_id() != transfer.get_issuing_agent() {
+                            current_proposal.status = proposal::Proposal_Status::CANCELED;
+                            info!(
+                                "Record {} does not match the issuing agent of the proposal",
+                                holder_role
+                            );
+                            return self._close_proposal(
+                                &mut state,
+                                proposals,
+                                proposal_index,
+                                current_proposal,
+                            );
+                        }
+
+                        if current_proposal.get_escrow() {
+                            // Neither party can pass the record on until the
+                            // escrow is released one way or the other
+                            let mut escrowed_record = proposal_record;
+                            if escrowed_record.has_escrow() {
+                                return Err(invalid(Code::RecordInEscrow, format!(
+                                    "Record is held in escrow: {}",
+                                    record_id
+                                )));
+                            }
+                            let mut escrow = record::Record_Escrow::new();
+                            escrow.set_issuing_agent(transfer.get_issuing_agent().to_string());
+                            escrow.set_receiving_agent(transfer.get_receiving_agent().to_string());
+                            escrow.set_proposal_timestamp(current_proposal.get_timestamp());
+                            escrow.set_release_property(
+                                current_proposal.get_release_property().to_string(),
+                            );
+                            escrow.set_escrowed_at(timestamp);
+                            escrowed_record.set_escrow(escrow);
+                            state.set_record(record_id, escrowed_record.clone())?;
+
+                            current_proposal.status = proposal::Proposal_Status::ESCROWED;
+                            state.add_record_event(
+                                "proposal_answered",
+                                &escrowed_record,
+                                proposal_event_details(&current_proposal),
+                            )?;
+                            let mut proposals = proposals;
+                            proposals.entries[proposal_index] = current_proposal;
+                            return state.set_proposal_container(
+                                record_id,
+                                receiving_agent,
+                                proposals,
+                            );
+                        }
+
+                        if current_proposal.get_effective_at() > timestamp {
+                            // The agreement is recorded now, but the record only
+                            // changes hands once an ExecuteTransfer lands
+                            current_proposal.status = proposal::Proposal_Status::SCHEDULED;
+                            state.add_record_event(
+                                "proposal_answered",
+                                &proposal_record,
+                                proposal_event_details(&current_proposal),
+                            )?;
+                            let mut proposals = proposals;
+                            proposals.entries[proposal_index] = current_proposal;
+                            return state.set_proposal_container(
+                                record_id,
+                                receiving_agent,
+                                proposals,
+                            );
+                        }
+
+                        // The receiving agent is the one accepting, even when
+                        // it is an approver whose answer completes the approvals
+                        self._transfer_record(
+                            &mut state,
+                            proposal_record,
+                            &transfer,
+                            receiving_agent,
+                            timestamp,
+                        )?;
+                        current_proposal.status = proposal::Proposal_Status::ACCEPTED;
+                    }
+                    proposal::Proposal_Role::REPORTER => {
+                        if owner.get_agent_id() != transfer.get_issuing_agent() {
+                            current_proposal.status = proposal::Proposal_Status::CANCELED;
+                            info!("Record owner does not match the issuing agent of the proposal");
+                            return self._close_proposal(
+                                &mut state,
+                                proposals,
+                                proposal_index,
+                                current_proposal,
+                            );
+                        }
+
+                        let expires_at = current_proposal.get_expires_at();
+                        if expires_at != 0 && expires_at <= timestamp {
+                            current_proposal.status = proposal::Proposal_Status::CANCELED;
+                            info!("Reporter proposal expired before it was accepted");
+                            return self._close_proposal(
+                                &mut state,
+                                proposals,
+                                proposal_index,
+                                current_proposal,
+                            );
+                        }
+
+                        let max_reporters = state
+                            .get_limit_setting(MAX_REPORTERS_SETTING, DEFAULT_MAX_REPORTERS)?;
+
+                        for prop_name in current_proposal.get_properties() {
+                            let mut prop = match state.get_property(record_id, prop_name) {
+                                Ok(Some(prop)) => prop,
+                                Ok(None) => {
+                                    return Err(invalid(Code::PropertyNotFound, String::from(
+                                        "Property does not exist",
+                                    )))
+                                }
+                                Err(err) => return Err(err),
+                            };
+                            let reporter = transfer.get_receiving_agent();
+                            if find_reporter(prop.get_reporters(), reporter).is_none()
+                                && reporters_full(&prop, max_reporters)
+                            {
+                                return Err(invalid(Code::LimitReached, format!(
+                                    "Property {} already has the maximum of {} reporters",
+                                    prop_name, max_reporters
+                                )));
+                            }
+                            authorize_reporter(&mut prop, reporter, expires_at);
+                            state.set_property(record_id, prop_name, prop)?;
+                        }
+                        current_proposal.status = proposal::Proposal_Status::ACCEPTED;
+                    }
+                }
+            }
+        }
+        self._close_proposal(&mut state, proposals, proposal_index, current_proposal)
+    }
+
+    fn _execute_transfer(
+        &self,
+        payload: &payload::ExecuteTransferAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let receiving_agent = payload.get_receiving_agent();
+        let role = payload.get_role();
+
+        let proposals = match state.get_proposal_container(record_id, receiving_agent) {
+            Ok(Some(proposals)) => proposals,
+            Ok(None) => {
+                return Err(invalid(Code::ProposalNotFound, String::from(
+                    "Proposal does not exist",
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+
+        let proposal_index = match proposals.get_entries().iter().position(|p| {
+            p.get_receiving_agent() == receiving_agent && p.get_role() == role
+                && p.get_record_id() == record_id
+                && p.status == proposal::Proposal_Status::SCHEDULED
+        }) {
+            Some(index) => index,
+            None => {
+                return Err(invalid(Code::ProposalNotFound, format!(
+                    "No scheduled transfer found for record {} for {}",
+                    record_id, receiving_agent
+                )))
+            }
+        };
+        let mut current_proposal = proposals.get_entries()[proposal_index].clone();
+
+        if current_proposal.get_issuing_agent() != signer
+            && current_proposal.get_receiving_agent() != signer
+        {
+            return Err(invalid(Code::NotAuthorized, String::from(
+                "Only the issuing or receiving agent can execute a transfer",
+            )));
+        }
+
+        if timestamp < current_proposal.get_effective_at() {
+            return Err(invalid(Code::InvalidProposal, format!(
+                "Transfer is not effective until {}",
+                current_proposal.get_effective_at()
+            )));
+        }
+
+        let transfer_record = match state.get_record(record_id) {
+            Ok(Some(record)) => record,
+            Ok(None) => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record in proposal does not exist: {}",
+                    record_id
+                )))
             }
-            state.set_property_page(record_id, property_name, 1, new_property_page)?;
+            Err(err) => return Err(err),
+        };
+
+        if transfer_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is final: {}",
+                record_id
+            )));
         }
 
-        Ok(())
+        let holder = match role {
+            proposal::Proposal_Role::OWNER => transfer_record.owners.last(),
+            _ => transfer_record.custodians.last(),
+        };
+        let transfer = proposal_transfer(&current_proposal);
+        let still_holder = match holder {
+            Some(holder) => holder.get_agent_id() == transfer.get_issuing_agent(),
+            None => false,
+        };
+        if !still_holder {
+            current_proposal.status = proposal::Proposal_Status::CANCELED;
+            info!("Record holder does not match the issuing agent of the scheduled transfer");
+            return self._close_proposal(&mut state, proposals, proposal_index, current_proposal);
+        }
+
+        self._transfer_record(&mut state, transfer_record, &transfer, signer, timestamp)?;
+        current_proposal.status = proposal::Proposal_Status::ACCEPTED;
+        self._close_proposal(&mut state, proposals, proposal_index, current_proposal)
     }
 
-    fn _finalize_record(
+    /// Hands a record over to an agent which has authorized the transfer,
+    /// without a proposal. The transfer is made as if the agent had
+    /// accepted a proposal from the signer.
+    fn _direct_transfer(
         &self,
-        payload: payload::FinalizeRecordAction,
+        authorization: &payload::TransferAuthorization,
         mut state: SupplyChainState,
         signer: &str,
+        timestamp: u64,
     ) -> Result<(), ApplyError> {
-        let record_id = payload.get_record_id();
-        let final_record = match state.get_record(record_id) {
-            Ok(Some(final_record)) => final_record,
-            Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
+        let record_id = authorization.get_record_id();
+        let receiving_agent = authorization.get_receiving_agent();
+        let role = authorization.get_role();
+
+        if authorization.get_issuing_agent() != signer {
+            return Err(invalid(Code::NotAuthorized, String::from(
+                "Transfer authorization was not issued to the signer",
+            )));
+        }
+        if receiving_agent == signer {
+            return Err(invalid(Code::InvalidProposal, String::from(
+                "A record cannot be transferred to its holder",
+            )));
+        }
+        if authorization.get_expires_at() <= timestamp {
+            return Err(invalid(Code::InvalidProposal, format!(
+                "Transfer authorization expired at {}",
+                authorization.get_expires_at()
+            )));
+        }
+        if state.get_agent(receiving_agent)?.is_none() {
+            return Err(invalid(Code::AgentNotFound, format!(
+                "Receiving agent is not registered: {}",
+                receiving_agent
+            )));
+        }
+
+        let transfer_record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
                     "Record does not exist: {}",
                     record_id
                 )))
             }
-            Err(err) => return Err(err),
         };
-        let owner = match final_record.owners.last() {
-            Some(x) => x,
-            None => {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Owner was not found",
-                )))
-            }
+        if transfer_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is final: {}",
+                record_id
+            )));
+        }
+
+        let holder = match role {
+            proposal::Proposal_Role::OWNER => transfer_record.owners.last(),
+            _ => transfer_record.custodians.last(),
         };
-        let custodian = match final_record.custodians.last() {
-            Some(x) => x,
-            None => {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Custodian was not found",
+        let held_since = match holder {
+            Some(holder) if holder.get_agent_id() == signer => holder.get_timestamp(),
+            _ => {
+                return Err(invalid(Code::NotAuthorized, format!(
+                    "Only the {:?} of a record can transfer it: {}",
+                    role, record_id
                 )))
             }
         };
+        // An authorization signed before the signer last took the role was
+        // for an earlier transfer, which has since been undone
+        if authorization.get_timestamp() < held_since {
+            return Err(invalid(Code::InvalidProposal, String::from(
+                "Transfer authorization was signed before the signer held the record",
+            )));
+        }
 
-        if owner.agent_id != signer || custodian.agent_id != signer {
-            return Err(ApplyError::InvalidTransaction(format!(
-                "Must be owner and custodian to finalize record"
+        let mut transfer = proposal::Proposal::new();
+        transfer.set_record_id(record_id.to_string());
+        transfer.set_role(role);
+        transfer.set_issuing_agent(signer.to_string());
+        transfer.set_receiving_agent(receiving_agent.to_string());
+        transfer.set_timestamp(timestamp);
+        self._transfer_record(&mut state, transfer_record, &transfer, signer, timestamp)
+    }
+
+    /// Moves ownership or custodianship of a record to the receiving agent
+    /// of an accepted proposal, or of a direct transfer. A new owner also
+    /// takes over the previous owner's reporter authorizations.
+    fn _transfer_record(
+        &self,
+        state: &mut SupplyChainState,
+        mut transfer_record: record::Record,
+        transfer: &proposal::Proposal,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = transfer.get_record_id();
+        let receiving_agent = transfer.get_receiving_agent();
+        let role = transfer.get_role();
+
+        if transfer_record.has_recall() {
+            return Err(invalid(Code::RecordRecalled, format!(
+                "Recalled records cannot be transferred: {}",
+                record_id
             )));
         }
-        if final_record.get_field_final() {
-            return Err(ApplyError::InvalidTransaction(format!(
-                "Record is already final: {}",
+        if transfer_record.has_escrow() && role == proposal::Proposal_Role::OWNER {
+            return Err(invalid(Code::RecordInEscrow, format!(
+                "Record is held in escrow: {}",
                 record_id
             )));
         }
+        if !transfer_record.get_unacknowledged().is_empty() {
+            return Err(invalid(Code::UnacknowledgedValues, format!(
+                "Record has values awaiting the owner's acknowledgment: {}",
+                transfer_record.get_unacknowledged().join(", ")
+            )));
+        }
 
-        let mut record_clone = final_record.clone();
-        record_clone.set_field_final(true);
-        state.set_record(record_id, record_clone)?;
+        self._run_plugins(|plugin| plugin.pre_transfer(&transfer_record, transfer, signer))?;
 
-        Ok(())
+        let mut bindings = self._make_rule_bindings(&transfer_record, "transfer", signer, timestamp);
+        bindings.insert(
+            String::from("role"),
+            rules::Value::Str(format!("{:?}", role)),
+        );
+        bindings.insert(
+            String::from("from"),
+            rules::Value::Str(transfer.get_issuing_agent().to_string()),
+        );
+        bindings.insert(
+            String::from("to"),
+            rules::Value::Str(receiving_agent.to_string()),
+        );
+        self._bind_linked_values(state, &transfer_record, &[], &mut bindings)?;
+        self._check_rules(&transfer_record, &bindings)?;
+
+        let mut new_agent = record::Record_AssociatedAgent::new();
+        new_agent.set_agent_id(receiving_agent.to_string());
+        new_agent.set_timestamp(timestamp);
+
+        match role {
+            proposal::Proposal_Role::OWNER => {
+                let owner = match transfer_record.owners.last() {
+                    Some(owner) => owner.clone(),
+                    None => {
+                        return Err(invalid(Code::InconsistentState, String::from(
+                            "Owner not found",
+                        )))
+                    }
+                };
+                self._extend_timeline(
+                    state,
+                    &mut transfer_record,
+                    vec![transfer_timeline_entry(
+                        record::RecordTimelineEntry_Role::OWNER,
+                        &new_agent,
+                        transfer,
+                    )],
+                )?;
+                transfer_record.set_owners(RepeatedField::from_vec(vec![new_agent]));
+                state.set_record(record_id, transfer_record.clone())?;
+
+                let record_type = match state.get_record_type_version(
+                    transfer_record.get_record_type(),
+                    transfer_record.get_record_type_version(),
+                ) {
+                    Ok(Some(record_type)) => record_type,
+                    Ok(None) => {
+                        return Err(invalid(Code::RecordTypeNotFound, format!(
+                            "RecordType does not exist: {}",
+                            transfer_record.get_record_type()
+                        )))
+                    }
+                    Err(err) => return Err(err),
+                };
+
+                for prop_schema in record_type.get_properties() {
+                    let mut prop = match state.get_property(record_id, prop_schema.get_name()) {
+                        Ok(Some(prop)) => prop,
+                        Ok(None) => {
+                            return Err(invalid(Code::PropertyNotFound, String::from(
+                                "Property does not exist",
+                            )))
+                        }
+                        Err(err) => return Err(err),
+                    };
+
+                    for reporter in prop.mut_reporters().iter_mut() {
+                        if reporter.get_public_key() == owner.get_agent_id() {
+                            reporter.set_authorized(false);
+                        }
+                    }
+                    authorize_reporter(&mut prop, receiving_agent, 0);
+                    state.set_property(record_id, prop.get_name(), prop.clone())?;
+                }
+            }
+            proposal::Proposal_Role::CUSTODIAN => {
+                self._extend_timeline(
+                    state,
+                    &mut transfer_record,
+                    vec![transfer_timeline_entry(
+                        record::RecordTimelineEntry_Role::CUSTODIAN,
+                        &new_agent,
+                        transfer,
+                    )],
+                )?;
+                transfer_record.set_custodians(RepeatedField::from_vec(vec![new_agent]));
+                state.set_record(record_id, transfer_record.clone())?;
+            }
+            proposal::Proposal_Role::REPORTER => {
+                return Err(invalid(Code::InvalidProposal, String::from(
+                    "Reporter proposals do not transfer a record",
+                )))
+            }
+        }
+
+        state.add_record_event(
+            "transferred",
+            &transfer_record,
+            vec![
+                ("role", format!("{:?}", role)),
+                ("from", transfer.get_issuing_agent().to_string()),
+                ("to", receiving_agent.to_string()),
+            ],
+        )
     }
 
-    fn _create_record_type(
+    /// Appends a change of owner or custodian to the record's timeline and
+    /// points the record at the page written. The caller is responsible
+    /// for saving the record.
+    fn _append_timeline(
         &self,
-        payload: payload::CreateRecordTypeAction,
-        mut state: SupplyChainState,
-        signer: &str,
+        state: &mut SupplyChainState,
+        timeline_record: &mut record::Record,
+        role: record::RecordTimelineEntry_Role,
+        agent: &record::Record_AssociatedAgent,
     ) -> Result<(), ApplyError> {
-        match state.get_agent(signer) {
-            Ok(Some(_)) => (),
-            Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Agent is not register: {}",
-                    signer
-                )))
+        self._extend_timeline(state, timeline_record, vec![timeline_entry(role, agent)])
+    }
+
+    /// Writes entries to the end of the record's timeline and points the
+    /// record at the last page written. Records created before timelines
+    /// existed have their earlier holders copied in first.
+    fn _extend_timeline(
+        &self,
+        state: &mut SupplyChainState,
+        timeline_record: &mut record::Record,
+        new_entries: Vec<record::RecordTimelineEntry>,
+    ) -> Result<(), ApplyError> {
+        let record_id = timeline_record.get_record_id().to_string();
+
+        let mut entries = Vec::new();
+        let mut page_number = timeline_record.get_timeline_page();
+        if page_number == 0 {
+            page_number = 1;
+            for owner in timeline_record.get_owners() {
+                entries.push(timeline_entry(record::RecordTimelineEntry_Role::OWNER, owner));
             }
-            Err(err) => return Err(err),
-        }
-        let name = payload.get_name();
-        let mut provided_properties: HashMap<&str, property::PropertySchema> = HashMap::new();
-        for property in payload.get_properties() {
-            provided_properties.insert(property.get_name(), property.clone());
+            for custodian in timeline_record.get_custodians() {
+                entries.push(timeline_entry(
+                    record::RecordTimelineEntry_Role::CUSTODIAN,
+                    custodian,
+                ));
+            }
+            entries.sort_by_key(|e| e.get_timestamp());
         }
-        match state.get_record_type(name) {
-            Ok(Some(_)) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Record type already exists: {}",
-                    signer
-                )))
+        entries.extend(new_entries);
+
+        let mut page = match state.get_record_timeline_page(&record_id, page_number) {
+            Ok(Some(page)) => page,
+            Ok(None) => {
+                let mut page = record::RecordTimelinePage::new();
+                page.set_record_id(record_id.clone());
+                page
             }
-            Ok(None) => (),
             Err(err) => return Err(err),
-        }
-        let mut record_type = record::RecordType::new();
-        record_type.set_name(name.to_string());
-        record_type.set_properties(RepeatedField::from_vec(payload.get_properties().to_vec()));
+        };
 
-        state.set_record_type(name, record_type)?;
+        for entry in entries {
+            if page.entries.len() >= RECORD_TIMELINE_PAGE_MAX_LENGTH {
+                state.set_record_timeline_page(&record_id, page_number, page.clone())?;
+                // Pages form a ring buffer, like property pages
+                page_number = if page_number == 0xffff {
+                    1
+                } else {
+                    page_number + 1
+                };
+                page.set_entries(RepeatedField::from_vec(Vec::new()));
+            }
+            page.entries.push(entry);
+        }
+        state.set_record_timeline_page(&record_id, page_number, page)?;
 
+        timeline_record.set_timeline_page(page_number);
         Ok(())
     }
 
-    fn _update_properties(
+    /// Records the custodian's attestation of a record's condition on
+    /// arrival against the timeline entry for their custodianship. Each
+    /// custodianship can be confirmed once.
+    fn _receive_confirmation(
         &self,
-        payload: payload::UpdatePropertiesAction,
+        payload: &payload::ReceiveConfirmationAction,
         mut state: SupplyChainState,
         signer: &str,
         timestamp: u64,
     ) -> Result<(), ApplyError> {
         let record_id = payload.get_record_id();
-        let update_record = match state.get_record(record_id) {
-            Ok(Some(update_record)) => update_record,
-            Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
+        let mut confirmed_record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
                     "Record does not exist: {}",
                     record_id
                 )))
             }
-            Err(err) => return Err(err),
         };
-
-        if update_record.get_field_final() {
-            return Err(ApplyError::InvalidTransaction(format!(
+        if confirmed_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
                 "Record is final: {}",
                 record_id
             )));
         }
+        let custodian = match confirmed_record.custodians.last() {
+            Some(custodian) => custodian.clone(),
+            None => {
+                return Err(invalid(Code::InconsistentState, String::from(
+                    "Custodian was not found",
+                )))
+            }
+        };
+        if custodian.get_agent_id() != signer {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Only the custodian can confirm receipt: {}",
+                signer
+            )));
+        }
 
-        let updates = payload.get_properties();
-
-        for update in updates {
-            let name = update.get_name();
-            let data_type = update.get_data_type();
+        if confirmed_record.get_timeline_page() == 0 {
+            self._extend_timeline(&mut state, &mut confirmed_record, vec![])?;
+            state.set_record(record_id, confirmed_record.clone())?;
+        }
 
-            let mut prop = match state.get_property(record_id, name) {
-                Ok(Some(prop)) => prop,
-                Ok(None) => {
-                    return Err(ApplyError::InvalidTransaction(format!(
-                        "Record does not have provided poperty: {}",
-                        name
+        // The custodianship is the last custodian entry, so is usually on
+        // the current page, but a run of ownership transfers can push it
+        // onto earlier ones
+        let is_custodianship = |entry: &record::RecordTimelineEntry| {
+            entry.get_role() == record::RecordTimelineEntry_Role::CUSTODIAN
+                && entry.get_agent_id() == signer
+                && entry.get_timestamp() == custodian.get_timestamp()
+        };
+        let first_page = confirmed_record.get_timeline_page();
+        let mut page_number = first_page;
+        let (mut page, position) = loop {
+            let page = match state.get_record_timeline_page(record_id, page_number)? {
+                Some(page) => page,
+                None => {
+                    return Err(invalid(Code::InconsistentState, format!(
+                        "Custodianship not found in timeline of record: {}",
+                        record_id
                     )))
                 }
-                Err(err) => return Err(err),
             };
-
-            let mut allowed = false;
-            let mut reporter_index = 0;
-            for reporter in prop.get_reporters() {
-                if reporter.get_public_key() == signer && reporter.get_authorized() {
-                    allowed = true;
-                    reporter_index = reporter.get_index();
-                    break;
-                }
+            if let Some(position) = page.get_entries().iter().rposition(&is_custodianship) {
+                break (page, position);
             }
-            if !allowed {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Reporter is not authorized: {}",
-                    signer
+            page_number = if page_number == 1 {
+                0xffff
+            } else {
+                page_number - 1
+            };
+            if page_number == first_page {
+                return Err(invalid(Code::InconsistentState, format!(
+                    "Custodianship not found in timeline of record: {}",
+                    record_id
                 )));
             }
+        };
 
-            if prop.fixed {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Property is fixed and cannot be updated: {}",
-                    prop.name
-                )));
-            }
+        if page.get_entries()[position].has_receipt() {
+            return Err(invalid(Code::Duplicate, format!(
+                "Receipt already confirmed for record: {}",
+                record_id
+            )));
+        }
+        let mut receipt = record::ReceiptConfirmation::new();
+        receipt.set_checks(payload.get_checks().to_vec().into());
+        receipt.set_notes(payload.get_notes().to_string());
+        receipt.set_timestamp(timestamp);
+        page.mut_entries()[position].set_receipt(receipt);
+        state.set_record_timeline_page(record_id, page_number, page)?;
+
+        let failed = payload.get_checks().iter().filter(|check| !check.get_passed());
+        let mut details = vec![("passed", failed.clone().next().is_none().to_string())];
+        details.extend(failed.map(|check| ("failed_item", check.get_item().to_string())));
+        state.add_record_event("receipt_confirmed", &confirmed_record, details)
+    }
 
-            if data_type != prop.data_type {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Update has wrong type: {:?} != {:?}",
-                    data_type, prop.data_type
-                )));
-            }
+    fn _anchor_state(
+        &self,
+        payload: &payload::AnchorStateAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let service = payload.get_service();
+        let block_num = payload.get_block_num();
+
+        let signers = state.get_setting(ANCHOR_SIGNERS_SETTING)?.unwrap_or_default();
+        if !signers.split(',').any(|key| key.trim() == signer) {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Signer is not permitted to record anchors: {}",
+                signer
+            )));
+        }
 
-            let page_number = prop.get_current_page();
-            let mut page = match state.get_property_page(record_id, name, page_number) {
-                Ok(Some(page)) => page,
-                Ok(None) => {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Property page does not exist",
-                    )))
-                }
-                Err(err) => return Err(err),
-            };
+        if state.get_state_anchor(service, block_num)?.is_some() {
+            return Err(invalid(Code::Duplicate, format!(
+                "Anchor already recorded for block {} with service: {}",
+                block_num, service
+            )));
+        }
 
-            let reported_value = match self._make_new_reported_value(
-                reporter_index,
-                timestamp,
-                update,
-                &prop,
-            ) {
-                Ok(reported_value) => reported_value,
-                Err(err) => return Err(err),
+        let mut anchor = anchor::StateAnchor::new();
+        anchor.set_service(service.to_string());
+        anchor.set_block_num(block_num);
+        anchor.set_block_id(payload.get_block_id().to_string());
+        anchor.set_state_root(payload.get_state_root().to_string());
+        anchor.set_receipt(payload.get_receipt().to_vec());
+        anchor.set_signer(signer.to_string());
+        anchor.set_timestamp(timestamp);
+
+        state.set_state_anchor(service, block_num, anchor)
+    }
+
+    /// Stores containers as they are, moving state to the addresses of a
+    /// new addressing scheme. The migration tool derives the writes, so
+    /// only the signers the network trusts to run it may send them.
+    fn _migrate_state(
+        &self,
+        payload: &payload::MigrateStateAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let signers = state.get_setting(MIGRATION_SIGNERS_SETTING)?.unwrap_or_default();
+        if !signers.split(',').any(|key| key.trim() == signer) {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Signer is not permitted to migrate state: {}",
+                signer
+            )));
+        }
+
+        for write in payload.get_writes() {
+            state.set_raw(write.get_address(), write.get_data().to_vec())?;
+        }
+        Ok(())
+    }
+
+    /// Appends a consumer scan or feedback digest to a Record's feedback
+    /// pages. Only kiosks listed in the setting can, and the Record may
+    /// be final, since finished goods are what consumers scan.
+    fn _anchor_feedback(
+        &self,
+        payload: &payload::AnchorFeedbackAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+
+        let kiosks = state.get_setting(FEEDBACK_KIOSKS_SETTING)?.unwrap_or_default();
+        if !kiosks.split(',').any(|key| key.trim() == signer) {
+            return Err(invalid(Code::NotAuthorized, format!(
+                "Signer is not a feedback kiosk: {}",
+                signer
+            )));
+        }
+        if state.get_agent(signer)?.is_none() {
+            return Err(invalid(Code::AgentNotFound, format!(
+                "Kiosk is not an agent: {}",
+                signer
+            )));
+        }
+        if state.get_record(record_id)?.is_none() {
+            return Err(invalid(Code::RecordNotFound, format!(
+                "Record does not exist: {}",
+                record_id
+            )));
+        }
+
+        let mut log = match state.get_feedback_log(record_id)? {
+            Some(log) => log,
+            None => {
+                let mut log = feedback::FeedbackLog::new();
+                log.set_record_id(record_id.to_string());
+                log.set_current_page(1);
+                log
+            }
+        };
+        let mut page_number = log.get_current_page();
+        let mut page = match state.get_feedback_page(record_id, page_number)? {
+            Some(page) => page,
+            None => {
+                let mut page = feedback::FeedbackPage::new();
+                page.set_record_id(record_id.to_string());
+                page
+            }
+        };
+        if page.entries.len() >= FEEDBACK_PAGE_MAX_LENGTH {
+            page_number = if page_number == FEEDBACK_MAX_PAGES {
+                log.set_wrapped(true);
+                1
+            } else {
+                page_number + 1
             };
-            page.reported_values.push(reported_value);
-            page.reported_values
-                .sort_by_key(|rv| (rv.clone().timestamp, rv.clone().reporter_index));
-            state.set_property_page(record_id, name, page_number, page.clone())?;
-            if page.reported_values.len() >= PROPERTY_PAGE_MAX_LENGTH {
-                let mut new_page_number = page_number + 1;
-                if page_number + 1 <= PROPERTY_PAGE_MAX_LENGTH as u32 {
-                    new_page_number = 1;
-                }
+            page.set_entries(RepeatedField::from_vec(Vec::new()));
+        }
 
-                let new_page = match state.get_property_page(record_id, name, new_page_number) {
-                    Ok(Some(mut new_page)) => {
-                        new_page.set_reported_values(RepeatedField::from_vec(Vec::new()));
-                        new_page
-                    }
-                    Ok(None) => {
-                        let mut new_page = property::PropertyPage::new();
-                        new_page.set_name(name.to_string());
-                        new_page.set_record_id(record_id.to_string());
-                        new_page
-                    }
-                    Err(err) => return Err(err),
-                };
-                state.set_property_page(record_id, name, new_page_number, new_page)?;
+        let mut entry = feedback::FeedbackEntry::new();
+        entry.set_kind(payload.get_kind());
+        entry.set_digest(payload.get_digest().to_string());
+        entry.set_kiosk(signer.to_string());
+        entry.set_timestamp(timestamp);
+        page.entries.push(entry);
+        state.set_feedback_page(record_id, page_number, page)?;
+
+        log.set_current_page(page_number);
+        log.set_total(log.get_total() + 1);
+        state.set_feedback_log(record_id, log)
+    }
 
-                prop.set_current_page(new_page_number);
-                if new_page_number == 1 && !prop.get_wrapped() {
-                    prop.set_wrapped(true);
+    /// Opens, extends, or closes the property's current excursion based on
+    /// a newly reported value, emitting an excursion event when one starts
+    /// or ends. Excursions end with the first value back within the
+    /// threshold, so their duration covers every value reported outside it.
+    fn _track_excursion(
+        &self,
+        state: &mut SupplyChainState,
+        record: &record::Record,
+        prop: &property::Property,
+        value: i64,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = record.get_record_id();
+        let name = prop.get_name();
+        let threshold = prop.get_threshold();
+        let distance = |value: i64| {
+            if value > threshold.get_max() {
+                value.saturating_sub(threshold.get_max())
+            } else {
+                threshold.get_min().saturating_sub(value)
+            }
+        };
+        let outside = distance(value) > 0;
+
+        let mut log = match state.get_excursion_log(record_id, name)? {
+            Some(log) => log,
+            None if outside => {
+                let mut log = property::ExcursionLog::new();
+                log.set_name(name.to_string());
+                log.set_record_id(record_id.to_string());
+                log
+            }
+            None => return Ok(()),
+        };
+        let ongoing = match log.excursions.last() {
+            Some(excursion) => excursion.get_end() == 0,
+            None => false,
+        };
+
+        let details = match (ongoing, outside) {
+            (false, false) => return Ok(()),
+            (false, true) => {
+                let mut excursion = property::ExcursionLog_Excursion::new();
+                excursion.set_start(timestamp);
+                excursion.set_peak_value(value);
+                excursion.set_value_count(1);
+                log.excursions.push(excursion);
+                if log.excursions.len() > EXCURSION_LOG_MAX_LENGTH {
+                    log.excursions.remove(0);
                 }
-                state.set_property(record_id, name, prop)?;
+                Some(vec![
+                    ("property", name.to_string()),
+                    ("status", String::from("started")),
+                    ("start", timestamp.to_string()),
+                    ("peak_value", value.to_string()),
+                ])
             }
-        }
+            (true, true) => {
+                if let Some(excursion) = log.excursions.last_mut() {
+                    if distance(value) > distance(excursion.get_peak_value()) {
+                        excursion.set_peak_value(value);
+                    }
+                    let value_count = excursion.get_value_count();
+                    excursion.set_value_count(value_count + 1);
+                }
+                None
+            }
+            (true, false) => match log.excursions.last_mut() {
+                Some(excursion) => {
+                    excursion.set_end(timestamp);
+                    Some(vec![
+                        ("property", name.to_string()),
+                        ("status", String::from("ended")),
+                        ("start", excursion.get_start().to_string()),
+                        ("end", timestamp.to_string()),
+                        (
+                            "duration",
+                            timestamp.saturating_sub(excursion.get_start()).to_string(),
+                        ),
+                        ("peak_value", excursion.get_peak_value().to_string()),
+                        ("value_count", excursion.get_value_count().to_string()),
+                    ])
+                }
+                None => None,
+            },
+        };
 
+        state.set_excursion_log(record_id, name, log)?;
+        if let Some(details) = details {
+            state.add_record_event("excursion", record, details)?;
+        }
         Ok(())
     }
 
-    fn _create_proposal(
+    fn _anchor_message(
         &self,
-        payload: payload::CreateProposalAction,
+        payload: &payload::MessageAction,
         mut state: SupplyChainState,
         signer: &str,
         timestamp: u64,
     ) -> Result<(), ApplyError> {
-        let record_id = payload.record_id;
-        let receiving_agent = payload.receiving_agent;
-        let role = payload.role;
-        let properties = payload.properties;
+        let record_id = payload.get_record_id();
+        let recipient = payload.get_recipient();
+        let digest = payload.get_digest();
+        let proposal_timestamp = payload.get_proposal_timestamp();
 
         match state.get_agent(signer) {
-            Ok(Some(agent)) => agent,
+            Ok(Some(_)) => (),
             Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Issuing agent does not exist: {}",
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Sender is not an agent: {}",
                     signer
                 )))
             }
             Err(err) => return Err(err),
-        };
+        }
 
-        match state.get_agent(&receiving_agent) {
-            Ok(Some(agent)) => agent,
+        if recipient == signer {
+            return Err(invalid(Code::InvalidRecipient, String::from(
+                "Agents cannot anchor messages to themselves",
+            )));
+        }
+
+        match state.get_agent(recipient) {
+            Ok(Some(_)) => (),
             Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Receiving agent does not exist: {}",
-                    receiving_agent
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Recipient is not an agent: {}",
+                    recipient
                 )))
             }
             Err(err) => return Err(err),
-        };
+        }
 
-        let mut proposals = match state.get_proposal_container(&record_id, &receiving_agent) {
-            Ok(Some(proposals)) => proposals,
-            Ok(None) => proposal::ProposalContainer::new(),
+        let record = match state.get_record(record_id) {
+            Ok(Some(record)) => record,
+            Ok(None) => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
             Err(err) => return Err(err),
         };
 
-        let mut open_proposals = Vec::<proposal::Proposal>::new();
-        for prop in proposals.get_entries() {
-            if prop.status == proposal::Proposal_Status::OPEN {
-                open_proposals.push(prop.clone());
+        if proposal_timestamp == 0 {
+            // Messages about the Record itself must involve one of its holders
+            let is_holder = |agent_id: &str| {
+                record.owners.last().map(|o| o.get_agent_id()) == Some(agent_id)
+                    || record.custodians.last().map(|c| c.get_agent_id()) == Some(agent_id)
+            };
+            if !is_holder(signer) && !is_holder(recipient) {
+                return Err(invalid(Code::NotAuthorized, format!(
+                    "Neither agent is an owner or custodian of record {}",
+                    record_id
+                )));
             }
-        }
-
-        for prop in open_proposals {
-            if prop.get_receiving_agent() == receiving_agent && prop.get_role() == role
-                && prop.get_record_id() == record_id
+        } else {
+            let mut found = false;
+            for &(issuing_agent, receiving_agent) in
+                &[(signer, recipient), (recipient, signer)]
             {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Proposal already exists",
+                if let Some(proposals) = state.get_proposal_container(record_id, receiving_agent)? {
+                    found = proposals.get_entries().iter().any(|p| {
+                        p.get_timestamp() == proposal_timestamp
+                            && (p.status == proposal::Proposal_Status::OPEN
+                                || p.status == proposal::Proposal_Status::SCHEDULED
+                                || p.status == proposal::Proposal_Status::ESCROWED)
+                            && p.get_issuing_agent() == issuing_agent
+                            && p.get_receiving_agent() == receiving_agent
+                            && p.get_record_id() == record_id
+                    });
+                }
+                if found {
+                    break;
+                }
+            }
+            if !found {
+                return Err(invalid(Code::ProposalNotFound, format!(
+                    "No open proposal between the agents for record {} at {}",
+                    record_id, proposal_timestamp
                 )));
             }
         }
 
-        let proposal_record = match state.get_record(&record_id) {
-            Ok(Some(record)) => record,
+        match state.get_message_anchor(record_id, digest) {
+            Ok(Some(_)) => {
+                return Err(invalid(Code::Duplicate, format!(
+                    "Message already anchored: {}",
+                    digest
+                )))
+            }
+            Ok(None) => (),
+            Err(err) => return Err(err),
+        }
+
+        let mut anchor = message::MessageAnchor::new();
+        anchor.set_digest(digest.to_string());
+        anchor.set_sender(signer.to_string());
+        anchor.set_recipient(recipient.to_string());
+        anchor.set_record_id(record_id.to_string());
+        anchor.set_proposal_timestamp(proposal_timestamp);
+        anchor.set_timestamp(timestamp);
+
+        state.set_message_anchor(record_id, digest, anchor)
+    }
+
+    fn _subscribe(
+        &self,
+        payload: &payload::SubscribeAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let scope = payload.get_scope();
+        let target = payload.get_target();
+
+        match state.get_agent(signer) {
+            Ok(Some(_)) => (),
             Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Record does not exist: {}",
-                    record_id
+                return Err(invalid(Code::AgentNotFound, format!(
+                    "Signer is not an agent: {}",
+                    signer
                 )))
             }
             Err(err) => return Err(err),
+        }
+
+        match scope {
+            subscription::Subscription_Scope::RECORD => {
+                if state.get_record(target)?.is_none() {
+                    return Err(invalid(Code::RecordNotFound, format!(
+                        "Record does not exist: {}",
+                        target
+                    )));
+                }
+            }
+            subscription::Subscription_Scope::RECORD_TYPE => {
+                if state.get_record_type(target)?.is_none() {
+                    return Err(invalid(Code::RecordTypeNotFound, format!(
+                        "Record type does not exist: {}",
+                        target
+                    )));
+                }
+            }
+            subscription::Subscription_Scope::HELD_RECORDS => (),
+        }
+
+        let mut events = payload.get_events().to_vec();
+        events.sort_by_key(|event| event.value());
+        events.dedup();
+
+        let mut new_subscription = subscription::Subscription::new();
+        new_subscription.set_scope(scope);
+        new_subscription.set_target(target.to_string());
+        new_subscription.set_events(events);
+        new_subscription.set_timestamp(timestamp);
+
+        let mut list = match state.get_subscription_list(signer)? {
+            Some(list) => list,
+            None => {
+                let mut list = subscription::SubscriptionList::new();
+                list.set_agent_id(signer.to_string());
+                list
+            }
         };
 
-        if proposal_record.get_field_final() {
-            return Err(ApplyError::InvalidTransaction(format!(
-                "Record is final: {}",
-                record_id
+        list.subscriptions
+            .retain(|s| !(s.scope == scope && s.target == target));
+        if list.subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Err(invalid(Code::LimitReached, format!(
+                "Agent cannot have more than {} subscriptions",
+                MAX_SUBSCRIPTIONS
             )));
         }
+        list.subscriptions.push(new_subscription);
+        list.subscriptions
+            .sort_by(|a, b| (a.scope.value(), &a.target).cmp(&(b.scope.value(), &b.target)));
 
-        if role == proposal::Proposal_Role::OWNER || role == proposal::Proposal_Role::REPORTER {
-            let owner = match proposal_record.owners.last() {
-                Some(owner) => owner,
-                None => {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Owner not found",
-                    )))
-                }
-            };
-            if owner.get_agent_id() != signer {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Only the owner can create a proposal to change ownership",
-                )));
-            }
-            if role == proposal::Proposal_Role::REPORTER && properties.len() == 0 {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Property list cannot be empty for Reporter role",
+        state.set_subscription_list(signer, list)
+    }
+
+    fn _unsubscribe(
+        &self,
+        payload: &payload::UnsubscribeAction,
+        mut state: SupplyChainState,
+        signer: &str,
+    ) -> Result<(), ApplyError> {
+        let scope = payload.get_scope();
+        let target = payload.get_target();
+
+        let mut list = match state.get_subscription_list(signer)? {
+            Some(list) => list,
+            None => {
+                return Err(invalid(Code::SubscriptionNotFound, format!(
+                    "Agent has no subscriptions: {}",
+                    signer
                 )))
             }
+        };
+
+        let count = list.subscriptions.len();
+        list.subscriptions
+            .retain(|s| !(s.scope == scope && s.target == target));
+        if list.subscriptions.len() == count {
+            return Err(invalid(Code::SubscriptionNotFound, format!(
+                "Agent is not subscribed to {:?} {}",
+                scope, target
+            )));
         }
 
-        if role == proposal::Proposal_Role::CUSTODIAN {
-            let custodian = match proposal_record.custodians.last() {
-                Some(custodian) => custodian,
-                None => {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Custodian not found",
-                    )))
-                }
-            };
+        state.set_subscription_list(signer, list)
+    }
 
-            if custodian.get_agent_id() != signer {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Only the custodian can create a proposal to change custodianship",
-                )));
+    /// Closes a proposal as COUNTERED, and opens a counter-proposal with the
+    /// signer's terms back to its issuing agent, for the same transfer.
+    /// Proposals can be countered back and forth until one is accepted,
+    /// each linking to the one it answered.
+    fn _counter_proposal(
+        &self,
+        state: &mut SupplyChainState,
+        proposals: proposal::ProposalContainer,
+        proposal_index: usize,
+        mut countered: proposal::Proposal,
+        terms: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = countered.get_record_id().to_string();
+        let role = countered.get_role();
+
+        let proposal_record = match state.get_record(&record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record in proposal does not exist: {}",
+                    record_id
+                )))
             }
+        };
+        if proposal_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is final: {}",
+                record_id
+            )));
+        }
+        if proposal_record.has_recall() && role != proposal::Proposal_Role::REPORTER {
+            return Err(invalid(Code::RecordRecalled, format!(
+                "Recalled records cannot be transferred: {}",
+                record_id
+            )));
+        }
+        if proposal_record.has_escrow() && role == proposal::Proposal_Role::OWNER {
+            return Err(invalid(Code::RecordInEscrow, format!(
+                "Record is held in escrow: {}",
+                record_id
+            )));
         }
 
-        let mut new_proposal = proposal::Proposal::new();
-        new_proposal.set_record_id(record_id.to_string());
-        new_proposal.set_timestamp(timestamp);
-        new_proposal.set_issuing_agent(signer.to_string());
-        new_proposal.set_receiving_agent(receiving_agent.to_string());
-        new_proposal.set_role(role);
-        new_proposal.set_properties(properties);
-        new_proposal.set_status(proposal::Proposal_Status::OPEN);
+        // As when accepting, a proposal from an earlier holder of the role
+        // is canceled rather than countered
+        let holder = match role {
+            proposal::Proposal_Role::CUSTODIAN => proposal_record.custodians.last(),
+            _ => proposal_record.owners.last(),
+        };
+        let transfer = proposal_transfer(&countered);
+        let still_holder = match holder {
+            Some(holder) => holder.get_agent_id() == transfer.get_issuing_agent(),
+            None => false,
+        };
+        if !still_holder {
+            countered.status = proposal::Proposal_Status::CANCELED;
+            info!("Record holder does not match the issuing agent of the countered proposal");
+            return self._close_proposal(state, proposals, proposal_index, countered);
+        }
 
-        proposals.entries.push(new_proposal);
-        proposals.entries.sort_by_key(|p| {
+        let mut previous = proposal::Proposal_Reference::new();
+        previous.set_receiving_agent(countered.get_receiving_agent().to_string());
+        previous.set_timestamp(countered.get_timestamp());
+
+        let mut counter = countered.clone();
+        counter.set_timestamp(timestamp);
+        counter.set_issuing_agent(countered.get_receiving_agent().to_string());
+        counter.set_receiving_agent(countered.get_issuing_agent().to_string());
+        counter.set_terms(terms.to_string());
+        counter.set_previous_proposal(previous);
+        counter.set_to_holder(!countered.get_to_holder());
+        // The counter needs the same approvers, who approve it afresh
+        counter.clear_approvals();
+
+        countered.status = proposal::Proposal_Status::COUNTERED;
+        self._close_proposal(state, proposals, proposal_index, countered)?;
+
+        let receiving_agent = counter.get_receiving_agent().to_string();
+        self._expire_proposals(state, &record_id, role, &receiving_agent, timestamp)?;
+
+        let mut counters = match state.get_proposal_container(&record_id, &receiving_agent)? {
+            Some(proposals) => proposals,
+            None => proposal::ProposalContainer::new(),
+        };
+        let exists = counters.get_entries().iter().any(|p| {
+            p.get_receiving_agent() == receiving_agent && p.get_role() == role
+                && (p.status == proposal::Proposal_Status::OPEN
+                    || p.status == proposal::Proposal_Status::SCHEDULED
+                    || p.status == proposal::Proposal_Status::ESCROWED)
+        });
+        if exists {
+            return Err(invalid(Code::ProposalExists, format!(
+                "A {:?} proposal to {} is already open for record {}",
+                role, receiving_agent, record_id
+            )));
+        }
+
+        let mut open_index = match state.get_open_proposal_index(&record_id)? {
+            Some(index) => index,
+            None => {
+                let mut index = proposal::OpenProposalIndex::new();
+                index.set_record_id(record_id.clone());
+                index
+            }
+        };
+        let mut entry = proposal::OpenProposalIndex_Entry::new();
+        entry.set_role(role);
+        entry.set_issuing_agent(counter.get_issuing_agent().to_string());
+        entry.set_receiving_agent(receiving_agent.clone());
+        open_index.entries.push(entry);
+        state.set_open_proposal_index(&record_id, open_index)?;
+
+        state.add_record_event(
+            "proposal_created",
+            &proposal_record,
+            proposal_event_details(&counter),
+        )?;
+
+        counters.entries.push(counter);
+        counters.entries.sort_by_key(|p| {
             (
                 p.clone().record_id,
                 p.clone().receiving_agent,
                 p.clone().timestamp,
             )
         });
-        state.set_proposal_container(&record_id, &receiving_agent, proposals)?;
-
-        Ok(())
+        state.set_proposal_container(&record_id, &receiving_agent, counters)
     }
 
-    fn _answer_proposal(
+    /// Replaces the properties and terms of an open proposal issued by the
+    /// signer. Approvals given to the old terms are cleared, so the
+    /// receiving agent and approvers must approve the amended proposal.
+    fn _amend_proposal(
         &self,
-        payload: payload::AnswerProposalAction,
+        payload: &payload::AmendProposalAction,
         mut state: SupplyChainState,
         signer: &str,
         timestamp: u64,
@@ -1139,277 +7052,394 @@ impl SupplyChainTransactionHandler {
         let record_id = payload.get_record_id();
         let receiving_agent = payload.get_receiving_agent();
         let role = payload.get_role();
-        let response = payload.get_response();
+        let properties = payload.get_properties();
 
-        let mut proposals = match state.get_proposal_container(record_id, receiving_agent) {
-            Ok(Some(proposals)) => proposals,
-            Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(String::from(
+        let mut proposals = match state.get_proposal_container(record_id, receiving_agent)? {
+            Some(proposals) => proposals,
+            None => {
+                return Err(invalid(Code::ProposalNotFound, String::from(
                     "Proposal does not exist",
                 )))
             }
-            Err(err) => return Err(err),
         };
-
-        let mut exists = false;
-        let mut current_proposal = match proposals.clone().entries.last() {
-            Some(current_proposal) => current_proposal.clone(),
+        let position = match proposals.get_entries().iter().position(|p| {
+            p.get_record_id() == record_id && p.get_receiving_agent() == receiving_agent
+                && p.get_role() == role
+                && p.status == proposal::Proposal_Status::OPEN
+        }) {
+            Some(position) => position,
             None => {
-                return Err(ApplyError::InvalidTransaction(format!(
+                return Err(invalid(Code::ProposalNotFound, format!(
                     "No open proposals found for record {} for {}",
                     record_id, receiving_agent
                 )))
             }
         };
+        let mut amended = proposals.get_entries()[position].clone();
 
-        let mut proposal_index = 0;
-        let mut count = 0;
+        if amended.get_issuing_agent() != signer {
+            return Err(invalid(Code::NotAuthorized, String::from(
+                "Only the issuing agent can amend a proposal",
+            )));
+        }
+        if proposal_has_expired(&amended, timestamp) {
+            return Err(invalid(Code::InvalidProposal, String::from(
+                "Expired proposals cannot be amended",
+            )));
+        }
+        if role == proposal::Proposal_Role::REPORTER && properties.is_empty() {
+            return Err(invalid(Code::MissingField, String::from(
+                "Property list cannot be empty for Reporter role",
+            )));
+        }
+        if role != proposal::Proposal_Role::REPORTER && !properties.is_empty() {
+            return Err(invalid(Code::InvalidProposal, String::from(
+                "Only reporter proposals can list properties",
+            )));
+        }
+        if payload.has_transfer_terms() {
+            check_transfer_terms(payload.get_transfer_terms(), role, timestamp)?;
+        }
+        if amended.get_properties() == properties && amended.get_terms() == payload.get_terms()
+            && amended.get_transfer_terms() == payload.get_transfer_terms()
+        {
+            return Err(invalid(Code::InvalidProposal, String::from(
+                "Amendment does not change the proposal",
+            )));
+        }
 
-        for prop in proposals.get_entries() {
-            if prop.get_receiving_agent() == receiving_agent && prop.get_role() == role
-                && prop.get_record_id() == record_id
-                && prop.status == proposal::Proposal_Status::OPEN
-            {
-                current_proposal = prop.clone();
-                exists = true;
-                proposal_index = count;
-                break;
-            }
-            count = count + 1;
+        amended.set_properties(RepeatedField::from_vec(properties.to_vec()));
+        amended.set_terms(payload.get_terms().to_string());
+        if payload.has_transfer_terms() {
+            amended.set_transfer_terms(payload.get_transfer_terms().clone());
+        } else {
+            amended.clear_transfer_terms();
+        }
+        amended.clear_approvals();
+        amended.amended_at.push(timestamp);
+
+        if let Some(record) = state.get_record(record_id)? {
+            state.add_record_event(
+                "proposal_amended",
+                &record,
+                proposal_event_details(&amended),
+            )?;
         }
 
-        if !exists {
-            return Err(ApplyError::InvalidTransaction(format!(
-                "No open proposals found for record {} for {}",
-                record_id, receiving_agent
+        proposals.entries[position] = amended;
+        state.set_proposal_container(record_id, receiving_agent, proposals)
+    }
+
+    /// Completes or reverts, as the issuing agent, the escrowed ownership
+    /// transfer of a record
+    fn _release_escrow(
+        &self,
+        payload: &payload::ReleaseEscrowAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let escrowed_record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        if !escrowed_record.has_escrow() {
+            return Err(invalid(Code::ProposalNotFound, format!(
+                "No escrowed transfer found for record {}",
+                record_id
+            )));
+        }
+        if escrowed_record.get_escrow().get_issuing_agent() != signer {
+            return Err(invalid(Code::NotAuthorized, String::from(
+                "Only the issuing agent can release an escrow",
             )));
         }
+        self._settle_escrow(&mut state, record_id, payload.get_revert(), timestamp)
+    }
+
+    /// Clears a record's escrow and closes its escrowed proposal, either
+    /// handing the record to the receiving agent or leaving it with the
+    /// issuing agent
+    fn _settle_escrow(
+        &self,
+        state: &mut SupplyChainState,
+        record_id: &str,
+        revert: bool,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let mut escrowed_record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        let escrow = escrowed_record.take_escrow();
+
+        // The proposal is stored with those to the agent holding the role,
+        // not the one taking it, when it was a counter-proposal
+        let mut found = None;
+        for agent in &[escrow.get_receiving_agent(), escrow.get_issuing_agent()] {
+            if let Some(proposals) = state.get_proposal_container(record_id, agent)? {
+                let position = proposals.get_entries().iter().position(|p| {
+                    p.get_record_id() == record_id
+                        && p.get_timestamp() == escrow.get_proposal_timestamp()
+                        && p.status == proposal::Proposal_Status::ESCROWED
+                });
+                if let Some(position) = position {
+                    found = Some((proposals, position));
+                    break;
+                }
+            }
+        }
+        let (proposals, position) = match found {
+            Some(found) => found,
+            None => {
+                return Err(invalid(Code::InconsistentState, format!(
+                    "Escrowed proposal not found for record {}",
+                    record_id
+                )))
+            }
+        };
+        let mut escrowed = proposals.get_entries()[position].clone();
+
+        state.set_record(record_id, escrowed_record.clone())?;
+        state.add_record_event(
+            "escrow_released",
+            &escrowed_record,
+            vec![
+                ("issuing_agent", escrow.get_issuing_agent().to_string()),
+                ("receiving_agent", escrow.get_receiving_agent().to_string()),
+                ("reverted", revert.to_string()),
+            ],
+        )?;
+
+        if revert {
+            escrowed.status = proposal::Proposal_Status::CANCELED;
+        } else {
+            let transfer = proposal_transfer(&escrowed);
+            self._transfer_record(
+                state,
+                escrowed_record,
+                &transfer,
+                escrow.get_receiving_agent(),
+                timestamp,
+            )?;
+            escrowed.status = proposal::Proposal_Status::ACCEPTED;
+        }
+        self._close_proposal(state, proposals, position, escrowed)
+    }
 
-        match response {
-            payload::AnswerProposalAction_Response::CANCEL => {
-                if current_proposal.get_issuing_agent() != signer {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Only the issuing agent can cancel a proposal",
-                    )));
-                }
-                current_proposal.status = proposal::Proposal_Status::CANCELED;
+    /// Cancels every open proposal for a record issued by the signer, as
+    /// listed in the record's open index. Those which have already expired
+    /// are closed as EXPIRED instead.
+    fn _cancel_all_proposals(
+        &self,
+        payload: &payload::CancelAllProposalsAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        // Proposals opened before the index existed are only found if the
+        // submitter names their receiving Agents
+        let mut receiving_agents: Vec<String> = Vec::new();
+        for agent in payload.get_receiving_agents() {
+            if !receiving_agents.contains(agent) {
+                receiving_agents.push(agent.to_string());
             }
-            payload::AnswerProposalAction_Response::REJECT => {
-                if current_proposal.get_receiving_agent() != signer {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Only the receiving agent can reject a proposal",
-                    )));
+        }
+        if let Some(open_index) = state.get_open_proposal_index(record_id)? {
+            for entry in open_index.get_entries() {
+                let agent = entry.get_receiving_agent().to_string();
+                if entry.get_issuing_agent() == signer && !receiving_agents.contains(&agent) {
+                    receiving_agents.push(agent);
                 }
-                current_proposal.status = proposal::Proposal_Status::REJECTED;
             }
-            payload::AnswerProposalAction_Response::ACCEPT => {
-                if current_proposal.get_receiving_agent() != signer {
-                    return Err(ApplyError::InvalidTransaction(String::from(
-                        "Only the receiving agent can Accept a proposal",
-                    )));
-                };
-
-                let mut proposal_record = match state.get_record(record_id) {
-                    Ok(Some(record)) => record,
-                    Ok(None) => {
-                        return Err(ApplyError::InvalidTransaction(format!(
-                            "Record in proposal does not exist: {}",
-                            record_id
-                        )))
-                    }
-                    Err(err) => return Err(err),
-                };
+        }
 
-                let owner = match proposal_record.clone().owners.last() {
-                    Some(owner) => owner.clone(),
-                    None => {
-                        return Err(ApplyError::InvalidTransaction(String::from(
-                            "Owner not found",
-                        )))
-                    }
+        let mut canceled = 0;
+        for agent in receiving_agents {
+            // Each close rewrites the container, so it is read again
+            // until none of the signer's proposals to the agent are open
+            while let Some(proposals) = state.get_proposal_container(record_id, &agent)? {
+                let position = proposals.get_entries().iter().position(|p| {
+                    p.get_record_id() == record_id && p.get_receiving_agent() == agent
+                        && p.get_issuing_agent() == signer
+                        && p.status == proposal::Proposal_Status::OPEN
+                });
+                let position = match position {
+                    Some(position) => position,
+                    None => break,
                 };
-
-                let custodian = match proposal_record.clone().custodians.last() {
-                    Some(custodian) => custodian.clone(),
-                    None => {
-                        return Err(ApplyError::InvalidTransaction(String::from(
-                            "Custodian not found",
-                        )))
-                    }
+                let mut closed = proposals.get_entries()[position].clone();
+                closed.status = if proposal_has_expired(&closed, timestamp) {
+                    proposal::Proposal_Status::EXPIRED
+                } else {
+                    proposal::Proposal_Status::CANCELED
                 };
+                self._close_proposal(&mut state, proposals, position, closed)?;
+                canceled += 1;
+            }
+        }
 
-                match role {
-                    proposal::Proposal_Role::OWNER => {
-                        if owner.get_agent_id() != current_proposal.get_issuing_agent() {
-                            current_proposal.status = proposal::Proposal_Status::CANCELED;
-                            info!("Record owner does not match the issuing agent of the proposal");
-                            // remove old proposal and replace with new one
-                            proposals.entries.remove(proposal_index);
-                            proposals.entries.push(current_proposal);
-                            proposals.entries.sort_by_key(|p| {
-                                (
-                                    p.clone().record_id,
-                                    p.clone().receiving_agent,
-                                    p.clone().timestamp,
-                                )
-                            });
-                            state.set_proposal_container(&record_id, &receiving_agent, proposals)?;
-                            return Ok(());
-                        }
-
-                        let mut new_owner = record::Record_AssociatedAgent::new();
-                        new_owner.set_agent_id(receiving_agent.to_string());
-                        new_owner.set_timestamp(timestamp);
-                        proposal_record.owners.push(new_owner);
-                        state.set_record(record_id, proposal_record.clone())?;
-
-                        let record_type =
-                            match state.get_record_type(proposal_record.get_record_type()) {
-                                Ok(Some(record_type)) => record_type,
-                                Ok(None) => {
-                                    return Err(ApplyError::InvalidTransaction(format!(
-                                        "RecordType does not exist: {}",
-                                        proposal_record.get_record_type()
-                                    )))
-                                }
-                                Err(err) => return Err(err),
-                            };
-
-                        for prop_schema in record_type.get_properties() {
-                            let mut prop =
-                                match state.get_property(record_id, prop_schema.get_name()) {
-                                    Ok(Some(prop)) => prop,
-                                    Ok(None) => {
-                                        return Err(ApplyError::InvalidTransaction(String::from(
-                                            "Property does not exist",
-                                        )))
-                                    }
-                                    Err(err) => return Err(err),
-                                };
-
-                            let mut authorized = false;
-                            let mut new_reporters: Vec<
-                                property::Property_Reporter,
-                            > = Vec::new();
-                            let temp_prob = prop.clone();
-                            let reporters = temp_prob.get_reporters();
-                            for reporter in reporters {
-                                if reporter.get_public_key() == owner.get_agent_id() {
-                                    let mut new_reporter = reporter.clone();
-                                    new_reporter.set_authorized(false);
-                                    new_reporters.push(new_reporter);
-                                } else if reporter.get_public_key() == receiving_agent {
-                                    let mut new_reporter = reporter.clone();
-                                    new_reporter.set_authorized(true);
-                                    authorized = true;
-                                    new_reporters.push(new_reporter);
-                                } else {
-                                    new_reporters.push(reporter.clone());
-                                }
-                            }
-
-                            if !authorized {
-                                let mut reporter = property::Property_Reporter::new();
-                                reporter.set_public_key(receiving_agent.to_string());
-                                reporter.set_authorized(true);
-                                reporter.set_index(prop.reporters.len() as u32);
-                                new_reporters.push(reporter);
-                            }
+        if canceled == 0 {
+            return Err(invalid(Code::ProposalNotFound, format!(
+                "No open proposals issued by {} found for record {}",
+                signer, record_id
+            )));
+        }
+        Ok(())
+    }
 
-                            prop.set_reporters(RepeatedField::from_vec(new_reporters));
-                            state.set_property(record_id, prop.get_name(), prop.clone())?;
-                        }
-                        current_proposal.status = proposal::Proposal_Status::ACCEPTED;
-                    }
-                    proposal::Proposal_Role::CUSTODIAN => {
-                        if custodian.get_agent_id() != current_proposal.get_issuing_agent() {
-                            current_proposal.status = proposal::Proposal_Status::CANCELED;
-                            info!(
-                                "Record custodian does not match the issuing agent of the proposal"
-                            );
-                            // remove old proposal and replace with new one
-                            proposals.entries.remove(proposal_index);
-                            proposals.entries.push(current_proposal.clone());
-                            proposals.entries.sort_by_key(|p| {
-                                (
-                                    p.clone().record_id,
-                                    p.clone().receiving_agent,
-                                    p.clone().timestamp,
-                                )
-                            });
-                            state.set_proposal_container(
-                                &record_id,
-                                &receiving_agent,
-                                proposals.clone(),
-                            )?;
-                        }
+    /// Closes, as EXPIRED, the open proposals for a role on a record whose
+    /// expiration has passed, so that they no longer block new ones. The
+    /// receiving agent's are checked as well as those in the open index,
+    /// which proposals opened before it existed are missing from.
+    fn _expire_proposals(
+        &self,
+        state: &mut SupplyChainState,
+        record_id: &str,
+        role: proposal::Proposal_Role,
+        receiving_agent: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let mut receiving_agents = vec![receiving_agent.to_string()];
+        if let Some(open_index) = state.get_open_proposal_index(record_id)? {
+            for entry in open_index.get_entries() {
+                let agent = entry.get_receiving_agent().to_string();
+                if entry.get_role() == role && !receiving_agents.contains(&agent) {
+                    receiving_agents.push(agent);
+                }
+            }
+        }
 
-                        let mut new_custodian = record::Record_AssociatedAgent::new();
-                        new_custodian.set_agent_id(receiving_agent.to_string());
-                        new_custodian.set_timestamp(timestamp);
-                        proposal_record.custodians.push(new_custodian.clone());
-                        state.set_record(record_id, proposal_record)?;
-                        current_proposal.status = proposal::Proposal_Status::ACCEPTED;
-                    }
-                    proposal::Proposal_Role::REPORTER => {
-                        if owner.get_agent_id() != current_proposal.get_issuing_agent() {
-                            current_proposal.status = proposal::Proposal_Status::CANCELED;
-                            info!("Record owner does not match the issuing agent of the proposal");
-                            // remove old proposal and replace with new one
-                            proposals.entries.remove(proposal_index);
-                            proposals.entries.push(current_proposal);
-                            proposals.entries.sort_by_key(|p| {
-                                (
-                                    p.clone().record_id,
-                                    p.clone().receiving_agent,
-                                    p.clone().timestamp,
-                                )
-                            });
-                            state.set_proposal_container(&record_id, &receiving_agent, proposals)?;
-                            return Ok(());
-                        }
+        for agent in receiving_agents {
+            let proposals = match state.get_proposal_container(record_id, &agent)? {
+                Some(proposals) => proposals,
+                None => continue,
+            };
+            let position = proposals.get_entries().iter().position(|p| {
+                p.get_record_id() == record_id && p.get_receiving_agent() == agent
+                    && p.get_role() == role
+                    && p.status == proposal::Proposal_Status::OPEN
+                    && proposal_has_expired(p, timestamp)
+            });
+            if let Some(position) = position {
+                let mut expired = proposals.get_entries()[position].clone();
+                expired.status = proposal::Proposal_Status::EXPIRED;
+                info!("Proposal expired before it was answered");
+                self._close_proposal(state, proposals, position, expired)?;
+            }
+        }
+        Ok(())
+    }
 
-                        let mut reporter = property::Property_Reporter::new();
-                        reporter.set_public_key(receiving_agent.to_string());
-                        reporter.set_authorized(true);
+    /// Removes a proposal that is no longer open from its container and
+    /// appends it to the pair's proposal history, so that the container
+    /// only holds open proposals. Closed proposals left in the container
+    /// by earlier versions of this handler are moved along with it.
+    fn _close_proposal(
+        &self,
+        state: &mut SupplyChainState,
+        mut proposals: proposal::ProposalContainer,
+        proposal_index: usize,
+        closed_proposal: proposal::Proposal,
+    ) -> Result<(), ApplyError> {
+        let record_id = closed_proposal.get_record_id().to_string();
+        let receiving_agent = closed_proposal.get_receiving_agent().to_string();
+
+        if let Some(record) = state.get_record(&record_id)? {
+            state.add_record_event(
+                "proposal_answered",
+                &record,
+                proposal_event_details(&closed_proposal),
+            )?;
+        }
 
-                        for prop_name in current_proposal.get_properties() {
-                            let mut prop = match state.get_property(record_id, prop_name) {
-                                Ok(Some(prop)) => prop,
-                                Ok(None) => {
-                                    return Err(ApplyError::InvalidTransaction(String::from(
-                                        "Property does not exist",
-                                    )))
-                                }
-                                Err(err) => return Err(err),
-                            };
-                            reporter.set_index(prop.reporters.len() as u32);
-                            prop.reporters.push(reporter.clone());
-                            state.set_property(record_id, prop_name, prop)?;
-                        }
-                        current_proposal.status = proposal::Proposal_Status::ACCEPTED;
-                    }
+        if let Some(mut open_index) = state.get_open_proposal_index(&record_id)? {
+            // Proposals opened before the index existed have no entry
+            let position = open_index.get_entries().iter().position(|entry| {
+                entry.get_role() == closed_proposal.get_role()
+                    && entry.get_issuing_agent() == closed_proposal.get_issuing_agent()
+                    && entry.get_receiving_agent() == receiving_agent
+            });
+            if let Some(position) = position {
+                open_index.entries.remove(position);
+                if open_index.entries.is_empty() {
+                    state.delete_open_proposal_index(&record_id)?;
+                } else {
+                    state.set_open_proposal_index(&record_id, open_index)?;
                 }
             }
         }
-        // remove old proposal and replace with new one
+
         proposals.entries.remove(proposal_index);
-        proposals.entries.push(current_proposal.clone());
-        proposals.entries.sort_by_key(|p| {
-            (
-                p.clone().record_id,
-                p.clone().receiving_agent,
-                p.clone().timestamp,
-            )
-        });
-        state.set_proposal_container(&record_id, &receiving_agent, proposals)?;
+        let (mut closed, open): (Vec<proposal::Proposal>, Vec<proposal::Proposal>) =
+            proposals.take_entries().into_iter().partition(|p| {
+                p.status != proposal::Proposal_Status::OPEN
+                    && p.status != proposal::Proposal_Status::SCHEDULED
+                    && p.status != proposal::Proposal_Status::ESCROWED
+                    && p.record_id == record_id
+                    && p.receiving_agent == receiving_agent
+            });
+        closed.push(closed_proposal);
+        proposals.set_entries(RepeatedField::from_vec(open));
+
+        let mut page_number = match proposals.get_history_page() {
+            0 => 1,
+            x => x,
+        };
+        let mut page = match state.get_proposal_history_page(
+            &record_id,
+            &receiving_agent,
+            page_number,
+        ) {
+            Ok(Some(page)) => page,
+            Ok(None) => {
+                let mut page = proposal::ProposalHistoryPage::new();
+                page.set_record_id(record_id.clone());
+                page.set_receiving_agent(receiving_agent.clone());
+                page
+            }
+            Err(err) => return Err(err),
+        };
 
-        Ok(())
+        for closed_proposal in closed {
+            if page.proposals.len() >= PROPOSAL_HISTORY_PAGE_MAX_LENGTH {
+                state.set_proposal_history_page(
+                    &record_id,
+                    &receiving_agent,
+                    page_number,
+                    page.clone(),
+                )?;
+                // Pages form a ring buffer, like property pages
+                page_number = if page_number == 0xffff {
+                    1
+                } else {
+                    page_number + 1
+                };
+                page.set_proposals(RepeatedField::from_vec(Vec::new()));
+            }
+            page.proposals.push(closed_proposal);
+        }
+        state.set_proposal_history_page(&record_id, &receiving_agent, page_number, page)?;
+
+        proposals.set_history_page(page_number);
+        state.set_proposal_container(&record_id, &receiving_agent, proposals)
     }
 
     fn _revoke_reporter(
         &self,
-        payload: payload::RevokeReporterAction,
+        payload: &payload::RevokeReporterAction,
         mut state: SupplyChainState,
         signer: &str,
     ) -> Result<(), ApplyError> {
@@ -1420,7 +7450,7 @@ impl SupplyChainTransactionHandler {
         let revoke_record = match state.get_record(record_id) {
             Ok(Some(record)) => record,
             Ok(None) => {
-                return Err(ApplyError::InvalidTransaction(format!(
+                return Err(invalid(Code::RecordNotFound, format!(
                     "Record does not exists: {}",
                     record_id
                 )))
@@ -1431,64 +7461,323 @@ impl SupplyChainTransactionHandler {
         let owner = match revoke_record.owners.last() {
             Some(x) => x,
             None => {
-                return Err(ApplyError::InvalidTransaction(String::from(
+                return Err(invalid(Code::InconsistentState, String::from(
                     "Owner was not found",
                 )))
             }
         };
 
         if owner.get_agent_id() != signer {
-            return Err(ApplyError::InvalidTransaction(format!(
-                "Must be owner to revoke reporters"
-            )));
+            return Err(invalid(
+                Code::NotAuthorized,
+                String::from("Must be owner to revoke reporters"),
+            ));
         }
 
         if revoke_record.get_field_final() {
-            return Err(ApplyError::InvalidTransaction(format!(
+            return Err(invalid(Code::RecordFinal, format!(
+                "Record is final: {}",
+                record_id
+            )));
+        }
+
+        for prop_name in properties {
+            let mut prop = match state.get_property(record_id, prop_name) {
+                Ok(Some(prop)) => prop,
+                Ok(None) => {
+                    return Err(invalid(
+                        Code::PropertyNotFound,
+                        String::from("Property does not exists"),
+                    ))
+                }
+                Err(err) => return Err(err),
+            };
+
+            let mut new_reporters: Vec<property::Property_Reporter> = Vec::new();
+            let mut revoked = false;
+            for reporter in prop.get_reporters() {
+                if reporter.get_public_key() == reporter_id {
+                    if !reporter.get_authorized() {
+                        return Err(invalid(
+                            Code::InvalidRevocation,
+                            String::from("Reporter is already unauthorized."),
+                        ));
+                    }
+                    let mut unauthorized_reporter = reporter.clone();
+                    unauthorized_reporter.set_authorized(false);
+                    revoked = true;
+                    new_reporters.push(unauthorized_reporter);
+                } else if reporter.get_delegated_by().iter().any(|d| d == reporter_id) {
+                    // Delegated through the revoked reporter, so revoked with it
+                    let mut delegate = reporter.clone();
+                    delegate.set_authorized(false);
+                    new_reporters.push(delegate);
+                } else {
+                    new_reporters.push(reporter.clone());
+                }
+            }
+            if !revoked {
+                return Err(invalid(Code::InvalidRevocation, format!(
+                    "Reporter cannot be revoked: {}",
+                    reporter_id
+                )));
+            }
+            prop.set_reporters(RepeatedField::from_vec(new_reporters));
+
+            state.set_property(record_id, prop_name, prop)?;
+        }
+
+        Ok(())
+    }
+
+    /// Authorizes an agent to report on properties the signer is itself
+    /// authorized to report on. The delegate keeps the chain of reporters
+    /// its authorization came through, and lapses with the signer's.
+    fn _delegate_reporter(
+        &self,
+        payload: &payload::DelegateReporterAction,
+        mut state: SupplyChainState,
+        signer: &str,
+        timestamp: u64,
+    ) -> Result<(), ApplyError> {
+        let record_id = payload.get_record_id();
+        let reporter_id = payload.get_reporter_id();
+
+        let delegate_record = match state.get_record(record_id)? {
+            Some(record) => record,
+            None => {
+                return Err(invalid(Code::RecordNotFound, format!(
+                    "Record does not exist: {}",
+                    record_id
+                )))
+            }
+        };
+        if delegate_record.get_field_final() {
+            return Err(invalid(Code::RecordFinal, format!(
                 "Record is final: {}",
                 record_id
             )));
         }
+        if state.get_agent(reporter_id)?.is_none() {
+            return Err(invalid(Code::AgentNotFound, format!(
+                "Delegate is not registered: {}",
+                reporter_id
+            )));
+        }
+
+        let max_reporters =
+            state.get_limit_setting(MAX_REPORTERS_SETTING, DEFAULT_MAX_REPORTERS)?;
+
+        for prop_name in payload.get_properties() {
+            let mut prop = match state.get_property(record_id, prop_name)? {
+                Some(prop) => prop,
+                None => {
+                    return Err(invalid(Code::PropertyNotFound, format!(
+                        "Property does not exist: {}",
+                        prop_name
+                    )))
+                }
+            };
+
+            let delegator = find_reporter(prop.get_reporters(), signer)
+                .map(|position| prop.get_reporters()[position].clone());
+            let delegator = match delegator {
+                Some(reporter) if reporter_is_authorized(&reporter, timestamp) => reporter,
+                _ => {
+                    return Err(invalid(Code::NotAuthorized, format!(
+                        "Signer is not authorized to report on {}",
+                        prop_name
+                    )))
+                }
+            };
+            if delegator.get_delegated_by().len() >= MAX_DELEGATION_DEPTH {
+                return Err(invalid(Code::LimitReached, format!(
+                    "Reporting on {} cannot be delegated more than {} times",
+                    prop_name, MAX_DELEGATION_DEPTH
+                )));
+            }
+
+            let existing = find_reporter(prop.get_reporters(), reporter_id)
+                .map(|position| &prop.get_reporters()[position]);
+            match existing {
+                Some(reporter) if reporter_is_authorized(reporter, timestamp) => {
+                    return Err(invalid(Code::Duplicate, format!(
+                        "{} is already authorized to report on {}",
+                        reporter_id, prop_name
+                    )))
+                }
+                None if reporters_full(&prop, max_reporters) => {
+                    return Err(invalid(Code::LimitReached, format!(
+                        "Property {} already has the maximum of {} reporters",
+                        prop_name, max_reporters
+                    )))
+                }
+                _ => (),
+            }
+
+            let mut delegated_by = delegator.get_delegated_by().to_vec();
+            delegated_by.push(signer.to_string());
+            authorize_reporter(&mut prop, reporter_id, delegator.get_expires_at());
+            if let Some(position) = find_reporter(prop.get_reporters(), reporter_id) {
+                prop.mut_reporters()[position]
+                    .set_delegated_by(RepeatedField::from_vec(delegated_by));
+            }
+            state.set_property(record_id, prop_name, prop)?;
+        }
+
+        Ok(())
+    }
+
+    fn _make_rule_bindings(
+        &self,
+        record: &record::Record,
+        action: &str,
+        signer: &str,
+        timestamp: u64,
+    ) -> rules::Bindings {
+        let mut bindings = rules::Bindings::new();
+        bindings.insert(String::from("action"), rules::Value::Str(action.to_string()));
+        bindings.insert(String::from("signer"), rules::Value::Str(signer.to_string()));
+        bindings.insert(
+            String::from("timestamp"),
+            rules::Value::Int(timestamp as i64),
+        );
+        bindings.insert(
+            String::from("record_type"),
+            rules::Value::Str(record.get_record_type().to_string()),
+        );
+        if let Some(owner) = record.owners.last() {
+            bindings.insert(
+                String::from("owner"),
+                rules::Value::Str(owner.get_agent_id().to_string()),
+            );
+        }
+        if let Some(custodian) = record.custodians.last() {
+            bindings.insert(
+                String::from("custodian"),
+                rules::Value::Str(custodian.get_agent_id().to_string()),
+            );
+        }
+        bindings
+    }
+
+    /// Binds each variable of the Record's rules named `link.name` to the
+    /// latest value of `name` on the Record which the Record's
+    /// RECORD_REFERENCE property `link` refers to, and `link` itself to
+    /// that Record's id, or to "" if it is not set. A variable is left
+    /// unbound if the value is not set or is of a type rules cannot see.
+    fn _bind_linked_values(
+        &self,
+        state: &mut SupplyChainState,
+        record: &record::Record,
+        pending: &[property::PropertyValue],
+        bindings: &mut rules::Bindings,
+    ) -> Result<(), ApplyError> {
+        let mut variables = BTreeSet::new();
+        for rule in record.get_rules() {
+            // A rule which cannot be parsed fails when it is evaluated
+            if let Ok(names) = rules::variables(rule) {
+                variables.extend(names);
+            }
+        }
+
+        for variable in variables {
+            if bindings.contains_key(&variable) {
+                continue;
+            }
+            let (link, name) = match variable.find('.') {
+                Some(dot) => (&variable[..dot], Some(&variable[dot + 1..])),
+                None => (variable.as_str(), None),
+            };
+            let linked_id = match self._linked_record_id(state, record, link, pending)? {
+                Some(linked_id) => linked_id,
+                None => continue,
+            };
+            let name = match name {
+                Some(name) if !linked_id.is_empty() => name,
+                Some(_) => continue,
+                None => {
+                    bindings.insert(variable.clone(), rules::Value::Str(linked_id));
+                    continue;
+                }
+            };
+            let linked = match state.get_record(&linked_id)? {
+                Some(linked) => linked,
+                None => continue,
+            };
+            let schema = match state.get_property_schema(&linked, name)? {
+                Some(schema) => schema,
+                None => continue,
+            };
+            let latest = match state.get_property(&linked_id, name)? {
+                Some(prop) => self._latest_reported_value(state, &linked_id, &prop)?,
+                None => None,
+            };
+            if let Some(value) = latest
+                .and_then(|latest| values::from_reported_value(&schema, &latest).ok())
+                .and_then(|value| rule_value(&value))
+            {
+                bindings.insert(variable.clone(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// The Record named by one of a Record's RECORD_REFERENCE properties,
+    /// taking a value the transaction sets ahead of the stored one, or ""
+    /// if the property has no value
+    fn _linked_record_id(
+        &self,
+        state: &mut SupplyChainState,
+        record: &record::Record,
+        link: &str,
+        pending: &[property::PropertyValue],
+    ) -> Result<Option<String>, ApplyError> {
+        let is_link = match state.get_property_schema(record, link)? {
+            Some(schema) => {
+                schema.get_data_type() == property::PropertySchema_DataType::RECORD_REFERENCE
+            }
+            None => false,
+        };
+        if !is_link {
+            return Ok(None);
+        }
+        if let Some(value) = pending.iter().find(|value| value.get_name() == link) {
+            return Ok(Some(value.get_record_reference_value().to_string()));
+        }
 
-        for prop_name in properties {
-            let mut prop = match state.get_property(record_id, prop_name) {
-                Ok(Some(prop)) => prop,
-                Ok(None) => {
-                    return Err(ApplyError::InvalidTransaction(format!(
-                        "Property does not exists"
+        let record_id = record.get_record_id();
+        let latest = match state.get_property(record_id, link)? {
+            Some(prop) => self._latest_reported_value(state, record_id, &prop)?,
+            None => None,
+        };
+        Ok(Some(latest
+            .map(|latest| latest.get_record_reference_value().to_string())
+            .unwrap_or_default()))
+    }
+
+    fn _check_rules(
+        &self,
+        record: &record::Record,
+        bindings: &rules::Bindings,
+    ) -> Result<(), ApplyError> {
+        for rule in record.get_rules() {
+            match rules::evaluate(rule, bindings) {
+                Ok(true) => (),
+                Ok(false) => {
+                    return Err(invalid(Code::RuleFailed, format!(
+                        "Record rule failed: {}",
+                        rule
                     )))
                 }
-                Err(err) => return Err(err),
-            };
-
-            let mut new_reporters: Vec<property::Property_Reporter> = Vec::new();
-            let mut revoked = false;
-            for reporter in prop.get_reporters() {
-                if reporter.get_public_key() == reporter_id {
-                    if !reporter.get_authorized() {
-                        return Err(ApplyError::InvalidTransaction(format!(
-                            "Reporter is already unauthorized."
-                        )));
-                    }
-                    let mut unauthorized_reporter = reporter.clone();
-                    unauthorized_reporter.set_authorized(false);
-                    revoked = true;
-                    new_reporters.push(unauthorized_reporter);
-                } else {
-                    new_reporters.push(reporter.clone());
+                Err(err) => {
+                    return Err(invalid(Code::RuleFailed, format!(
+                        "Record rule {} could not be evaluated: {}",
+                        rule, err
+                    )))
                 }
             }
-            if !revoked {
-                return Err(ApplyError::InvalidTransaction(format!(
-                    "Reporter cannot be revoked: {}",
-                    reporter_id
-                )));
-            }
-            prop.set_reporters(RepeatedField::from_vec(new_reporters));
-
-            state.set_property(record_id, prop_name, prop)?;
         }
-
         Ok(())
     }
 
@@ -1497,15 +7786,17 @@ impl SupplyChainTransactionHandler {
         reporter_index: u32,
         timestamp: u64,
         value: &property::PropertyValue,
-        property: &property::Property,
+        schema: &property::PropertySchema,
     ) -> Result<property::PropertyPage_ReportedValue, ApplyError> {
         let mut reported_value = property::PropertyPage_ReportedValue::new();
         reported_value.set_reporter_index(reporter_index);
         reported_value.set_timestamp(timestamp);
+        // Also rejects units on values other than numbers
+        let number_value = number_in_schema_unit(value.get_name(), value, schema)?;
 
         match value.get_data_type() {
             property::PropertySchema_DataType::TYPE_UNSET => {
-                return Err(ApplyError::InvalidTransaction(String::from(
+                return Err(invalid(Code::MalformedPayload, String::from(
                     "DataType is not set",
                 )))
             }
@@ -1516,18 +7807,20 @@ impl SupplyChainTransactionHandler {
                 reported_value.set_boolean_value(value.get_boolean_value())
             }
             property::PropertySchema_DataType::NUMBER => {
-                reported_value.set_number_value(value.get_number_value())
+                check_number_value(value.get_name(), number_value, schema)?;
+                reported_value.set_number_value(number_value)
             }
             property::PropertySchema_DataType::STRING => {
+                check_string_value(value.get_name(), value.get_string_value(), schema)?;
                 reported_value.set_string_value(value.get_string_value().to_string())
             }
             property::PropertySchema_DataType::ENUM => {
                 let enum_name = value.get_enum_value().to_string();
-                let enum_index = match property.enum_options.iter()
+                let enum_index = match schema.enum_options.iter()
                     .position(|name| name == &enum_name) {
                         Some(index) => index,
                         None => {
-                            return Err(ApplyError::InvalidTransaction(format!(
+                            return Err(invalid(Code::InvalidValue, format!(
                                 "Provided enum name is not a valid option: {}",
                                 enum_name,
                             )))
@@ -1538,7 +7831,7 @@ impl SupplyChainTransactionHandler {
             property::PropertySchema_DataType::STRUCT => {
                 match self._validate_struct_values(
                     &value.struct_values,
-                    &property.struct_properties
+                    &schema.struct_properties
                 ) {
                     Ok(_) => (),
                     Err(e) => return Err(e),
@@ -1548,19 +7841,174 @@ impl SupplyChainTransactionHandler {
                 reported_value.set_struct_values(struct_values)
             }
             property::PropertySchema_DataType::LOCATION => {
+                check_location_value(value.get_name(), value.get_location_value(), schema)?;
                 reported_value.set_location_value(value.get_location_value().clone())
             }
+            property::PropertySchema_DataType::TIMESTAMP => {
+                let timestamp_value = value.get_timestamp_value();
+                if timestamp_value == 0 {
+                    return Err(invalid(Code::InvalidValue, format!(
+                        "Timestamp value must be set: {}",
+                        value.get_name()
+                    )));
+                }
+                let latest = timestamp_seconds(timestamp) + MAX_TIMESTAMP_VALUE_AHEAD;
+                if timestamp_seconds(timestamp_value) > latest {
+                    return Err(invalid(Code::InvalidValue, format!(
+                        "Timestamp value is too far in the future: {}: {}",
+                        value.get_name(),
+                        timestamp_value
+                    )));
+                }
+                reported_value.set_timestamp_value(timestamp_value)
+            }
+            property::PropertySchema_DataType::RECORD_REFERENCE => {
+                reported_value.set_record_reference_value(
+                    value.get_record_reference_value().to_string(),
+                )
+            }
+            property::PropertySchema_DataType::ARRAY => {
+                self._validate_array_values(value.get_array_values(), schema)?;
+                let array_values = RepeatedField::from_vec(value.get_array_values().to_vec());
+                reported_value.set_array_values(array_values)
+            }
+            property::PropertySchema_DataType::IDENTIFIER => {
+                check_identifier_value(value.get_name(), value.get_identifier_value(), schema)?;
+                reported_value.set_identifier_value(value.get_identifier_value().to_string())
+            }
         };
         Ok(reported_value)
     }
 
+    /// Checks that a RECORD_REFERENCE value, or each one in an array or
+    /// struct, names another Record which exists. Other values are left
+    /// to `_make_new_reported_value`.
+    fn _check_record_reference(
+        &self,
+        state: &mut SupplyChainState,
+        record_id: &str,
+        value: &property::PropertyValue,
+    ) -> Result<(), ApplyError> {
+        match value.get_data_type() {
+            property::PropertySchema_DataType::RECORD_REFERENCE => (),
+            property::PropertySchema_DataType::ARRAY => {
+                for element in value.get_array_values() {
+                    self._check_record_reference(state, record_id, element)?;
+                }
+                return Ok(());
+            }
+            property::PropertySchema_DataType::STRUCT => {
+                for field in value.get_struct_values() {
+                    self._check_record_reference(state, record_id, field)?;
+                }
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+        let referenced = value.get_record_reference_value();
+        if referenced.is_empty() {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Record reference must be set: {}",
+                value.get_name()
+            )));
+        }
+        if referenced == record_id {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Record cannot reference itself: {}",
+                value.get_name()
+            )));
+        }
+        match state.get_record(referenced)? {
+            Some(_) => Ok(()),
+            None => Err(invalid(Code::RecordNotFound, format!(
+                "Referenced record does not exist: {}",
+                referenced
+            ))),
+        }
+    }
+
+    /// Rejects combinations of the required, delayed, fixed, and immutable
+    /// flags which would leave a property that can never be validly set.
+    fn _validate_property_flags(&self, schema: &property::PropertySchema) -> Result<(), ApplyError> {
+        let name = schema.get_name();
+        if schema.get_delayed() && schema.get_required() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Property cannot be both required and delayed: {}",
+                name
+            )));
+        }
+        if schema.get_delayed() && schema.get_fixed() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Property cannot be both fixed and delayed: {}",
+                name
+            )));
+        }
+        // A default would use up the only value an immutable property gets
+        if schema.get_immutable() && schema.has_default_value() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Immutable properties cannot have a default value: {}",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    fn _validate_default_value(&self, schema: &property::PropertySchema) -> Result<(), ApplyError> {
+        let name = schema.get_name();
+        if schema.get_required() || schema.get_delayed() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Required and delayed properties cannot have a default value: {}",
+                name
+            )));
+        }
+
+        // Whether a referenced Record exists can only be checked when a
+        // value is reported, not once for every Record of the type
+        if holds_record_references(schema) {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Record reference properties cannot have a default value: {}",
+                name
+            )));
+        }
+
+        let default_value = schema.get_default_value();
+        if default_value.get_data_type() != schema.get_data_type() {
+            return Err(invalid(Code::InvalidSchema, format!(
+                "Default value for {} must have data type: {:?}",
+                name,
+                schema.get_data_type()
+            )));
+        }
+
+        match schema.get_data_type() {
+            property::PropertySchema_DataType::ENUM => {
+                let option = default_value.get_enum_value();
+                if !schema.get_enum_options().iter().any(|o| o == option) {
+                    return Err(invalid(Code::InvalidSchema, format!(
+                        "Default value for {} is not a valid option: {}",
+                        name, option
+                    )));
+                }
+            }
+            property::PropertySchema_DataType::STRUCT => self._validate_struct_values(
+                &default_value.struct_values,
+                &schema.struct_properties,
+            )?,
+            property::PropertySchema_DataType::ARRAY => {
+                self._validate_array_values(default_value.get_array_values(), schema)?
+            }
+            _ => check_scalar_value(name, default_value, schema)?,
+        }
+        Ok(())
+    }
+
     fn _validate_struct_values(
         &self,
         struct_values: &RepeatedField<property::PropertyValue>,
         schema_values: &RepeatedField<property::PropertySchema>
     ) -> Result<(), ApplyError> {
         if struct_values.len() != schema_values.len() {
-            return Err(ApplyError::InvalidTransaction(format!(
+            return Err(invalid(Code::InvalidValue, format!(
                 "Provided struct does not match schema length: {:?} != {:?}",
                 struct_values.len(),
                 schema_values.len(),
@@ -1570,19 +8018,26 @@ impl SupplyChainTransactionHandler {
         for schema in schema_values.iter() {
             let value = match struct_values.iter().find(|val| val.name == schema.name) {
                 Some(val) => val,
-                None => return Err(ApplyError::InvalidTransaction(format!(
+                None => return Err(invalid(Code::InvalidValue, format!(
                     "Provided struct missing required property from schema: {}",
                     schema.name,
                 )))
             };
 
             if value.data_type != schema.data_type {
-                return Err(ApplyError::InvalidTransaction(format!(
+                return Err(invalid(Code::InvalidValue, format!(
                     "Struct property \"{}\" must have data type: {:?}",
                     schema.name,
                     schema.data_type,
                 )))
             }
+            // Fields are stored as given, so cannot be converted
+            if !value.get_unit().is_empty() {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Struct property \"{}\" may not have a unit",
+                    schema.name,
+                )))
+            }
 
             if schema.data_type == property::PropertySchema_DataType::STRUCT {
                 match self._validate_struct_values(
@@ -1592,88 +8047,839 @@ impl SupplyChainTransactionHandler {
                     Ok(_) => (),
                     Err(e) => return Err(e),
                 }
+            } else if schema.data_type == property::PropertySchema_DataType::ARRAY {
+                self._validate_array_values(value.get_array_values(), schema)?;
+            } else {
+                check_scalar_value(&schema.name, value, schema)?;
             }
         }
 
         Ok(())
     }
-}
 
-impl TransactionHandler for SupplyChainTransactionHandler {
-    fn family_name(&self) -> String {
-        return self.family_name.clone();
-    }
+    /// Checks an ARRAY value's length, and that each element has the
+    /// element schema's type, validating elements which are structs or
+    /// arrays themselves
+    fn _validate_array_values(
+        &self,
+        array_values: &[property::PropertyValue],
+        schema: &property::PropertySchema,
+    ) -> Result<(), ApplyError> {
+        let max_length = array_max_length(schema);
+        if array_values.len() > max_length {
+            return Err(invalid(Code::InvalidValue, format!(
+                "Array {} has {} elements, more than the maximum of {}",
+                schema.get_name(),
+                array_values.len(),
+                max_length
+            )));
+        }
 
-    fn family_versions(&self) -> Vec<String> {
-        return self.family_versions.clone();
-    }
+        let element = schema.get_element_schema();
+        for value in array_values {
+            if value.get_data_type() != element.get_data_type() {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Elements of array {} must have data type: {:?}",
+                    schema.get_name(),
+                    element.get_data_type()
+                )));
+            }
+            // Elements are stored as given, so cannot be converted
+            if !value.get_unit().is_empty() {
+                return Err(invalid(Code::InvalidValue, format!(
+                    "Elements of array {} may not have a unit",
+                    schema.get_name()
+                )));
+            }
+            match element.get_data_type() {
+                property::PropertySchema_DataType::STRUCT => {
+                    self._validate_struct_values(&value.struct_values, &element.struct_properties)?
+                }
+                property::PropertySchema_DataType::ARRAY => {
+                    self._validate_array_values(value.get_array_values(), element)?
+                }
+                _ => check_scalar_value(schema.get_name(), value, element)?,
+            }
+        }
 
-    fn namespaces(&self) -> Vec<String> {
-        return self.namespaces.clone();
+        Ok(())
     }
 
-    fn apply(
+    /// Applies a transaction. This is what `apply` does, but against any
+    /// StateContext, so that recorded transactions can be replayed.
+    pub fn process(
         &self,
         request: &TpProcessRequest,
-        context: &mut TransactionContext,
+        context: &mut dyn StateContext,
     ) -> Result<(), ApplyError> {
+        let signer = request.get_header().get_signer_public_key();
+        let signature = request.get_signature();
+
         let payload = SupplyChainPayload::new(request.get_payload());
         let payload = match payload {
-            Err(e) => return Err(e),
+            Err(e) => return Err(with_context(e, None, signer, signature)),
             Ok(payload) => payload,
         };
         let payload = match payload {
             Some(x) => x,
             None => {
-                return Err(ApplyError::InvalidTransaction(String::from(
-                    "Request must contain a payload",
-                )))
+                return Err(with_context(
+                    invalid(Code::MalformedPayload, String::from(
+                        "Request must contain a payload",
+                    )),
+                    None,
+                    signer,
+                    signature,
+                ))
             }
         };
 
-        let signer = request.get_header().get_signer_public_key();
-        let state = SupplyChainState::new(context);
-
         info!(
             "payload: {:?} {} {} {}",
             payload.get_action(),
             payload.get_timestamp(),
-            request.get_header().get_inputs()[0],
-            request.get_header().get_outputs()[0]
+            request.get_header().get_inputs().first().map(String::as_str).unwrap_or_default(),
+            request.get_header().get_outputs().first().map(String::as_str).unwrap_or_default()
         );
 
-        match payload.get_action() {
-            Action::CreateAgent(agent_payload) => {
-                self._create_agent(agent_payload, state, signer, payload.get_timestamp())?
-            }
-            Action::CreateRecord(record_payload) => {
-                self._create_record(record_payload, state, signer, payload.get_timestamp())?
+        // Each check and action reads through its own SupplyChainState, so
+        // the whole transaction is staged to read each address from the
+        // validator once, and send each write once
+        let mut staged = StagedContext::new(context);
+        let result = self
+            ._check_enabled(payload.get_action(), &mut staged)
+            .and_then(|_| self._check_timestamp(payload.get_timestamp(), &mut staged))
+            .and_then(|_| self._check_active(&mut staged, signer))
+            .and_then(|_| self._check_permissions(payload.get_action(), &mut staged, signer))
+            .and_then(|_| {
+                let timestamp = payload.get_timestamp();
+                self._apply_action(payload.get_action(), timestamp, &mut staged, signer)
+            })
+            .and_then(|_| self._add_activity_receipt(payload.get_action(), &mut staged, signer))
+            .and_then(|_| {
+                staged
+                    .commit()
+                    .map_err(|err| ApplyError::InternalError(format!("{}", err)))
+            });
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let err = with_context(err, Some(payload.get_action()), signer, signature);
+                info!("{:?}", err);
+                Err(err)
             }
-            Action::FinalizeRecord(finalize_payload) => {
-                self._finalize_record(finalize_payload, state, signer)?
-            }
-            Action::CreateRecordType(record_type_payload) => {
-                self._create_record_type(record_type_payload, state, signer)?
+        }
+    }
+}
+
+impl TransactionHandler for SupplyChainTransactionHandler {
+    fn family_name(&self) -> String {
+        return self.family_name.clone();
+    }
+
+    fn family_versions(&self) -> Vec<String> {
+        return self.family_versions.clone();
+    }
+
+    fn namespaces(&self) -> Vec<String> {
+        return self.namespaces.clone();
+    }
+
+    fn apply(
+        &self,
+        request: &TpProcessRequest,
+        context: &mut TransactionContext,
+    ) -> Result<(), ApplyError> {
+        let log = match self.replay_log {
+            Some(ref log) => log,
+            None => return self.process(request, context),
+        };
+
+        let mut recording = RecordingContext::new(context);
+        let result = self.process(request, &mut recording);
+
+        // The log is a diagnostic aid, so failing to write it must not
+        // change whether the transaction is applied
+        let written = recording
+            .into_entry(request, &result)
+            .and_then(|entry| match log.lock() {
+                Ok(mut log) => log.append(&entry),
+                Err(_) => Err(String::from("Replay log lock is poisoned")),
+            });
+        if let Err(err) = written {
+            warn!("{}", err);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sawtooth_sdk::messages::transaction::TransactionHeader;
+    use sawtooth_sdk::processor::handler::ContextError;
+
+    #[derive(Default)]
+    struct MemoryContext {
+        state: HashMap<String, Vec<u8>>,
+        read_addresses: Vec<String>,
+        sets: usize,
+        written: Vec<String>,
+        receipts: Vec<Vec<u8>>,
+    }
+
+    impl StateContext for MemoryContext {
+        fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+            self.read_addresses.extend(addresses.first().cloned());
+            Ok(addresses
+                .first()
+                .and_then(|address| self.state.get(address).cloned()))
+        }
+
+        fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+            self.sets += 1;
+            self.written.extend(entries.keys().cloned());
+            self.state.extend(entries);
+            Ok(())
+        }
+
+        fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+            for address in addresses {
+                self.state.remove(&address);
             }
-            Action::UpdateProperties(update_properties_payload) => self._update_properties(
-                update_properties_payload,
-                state,
-                signer,
-                payload.get_timestamp(),
-            )?,
-            Action::CreateProposal(proposal_payload) => {
-                self._create_proposal(proposal_payload, state, signer, payload.get_timestamp())?
+            Ok(())
+        }
+
+        fn add_event(
+            &mut self,
+            _event_type: String,
+            _attributes: Vec<(String, String)>,
+            _data: &[u8],
+        ) -> Result<(), ContextError> {
+            Ok(())
+        }
+
+        fn add_receipt_data(&mut self, data: &[u8]) -> Result<(), ContextError> {
+            self.receipts.push(data.to_vec());
+            Ok(())
+        }
+    }
+
+    // Stores a container at an address, standing in for entries whose
+    // keys hash to the same address
+    fn seed<M: Message>(context: &mut MemoryContext, address: &str, container: &M) {
+        context
+            .state
+            .insert(address.to_string(), container.write_to_bytes().unwrap());
+    }
+
+    fn read<M: Message>(context: &MemoryContext, address: &str) -> M {
+        protobuf::parse_from_bytes(&context.state[address]).unwrap()
+    }
+
+    fn agent(public_key: &str, name: &str) -> agent::Agent {
+        let mut agent = agent::Agent::new();
+        agent.set_public_key(public_key.to_string());
+        agent.set_name(name.to_string());
+        agent
+    }
+
+    fn property(record_id: &str, name: &str, current_page: u32) -> property::Property {
+        let mut property = property::Property::new();
+        property.set_record_id(record_id.to_string());
+        property.set_name(name.to_string());
+        property.set_current_page(current_page);
+        property
+    }
+
+    #[test]
+    fn set_agent_replaces_only_its_own_entry() {
+        let address = make_agent_address("agent-b");
+        let mut container = agent::AgentContainer::new();
+        container.entries.push(agent("agent-c", "Colliding"));
+        container.entries.push(agent("agent-b", "Old name"));
+        let mut context = MemoryContext::default();
+        seed(&mut context, &address, &container);
+
+        {
+            let mut state = SupplyChainState::new(&mut context);
+            state.set_agent("agent-b", agent("agent-b", "New name")).unwrap();
+        }
+
+        let stored: agent::AgentContainer = read(&context, &address);
+        let entries: Vec<(&str, &str)> = stored
+            .get_entries()
+            .iter()
+            .map(|agent| (agent.get_public_key(), agent.get_name()))
+            .collect();
+        assert_eq!(entries, vec![("agent-b", "New name"), ("agent-c", "Colliding")]);
+    }
+
+    #[test]
+    fn set_record_type_replaces_only_its_own_entry() {
+        let address = make_record_type_address("fish");
+        let mut container = record::RecordTypeContainer::new();
+        for (name, version) in &[("fish", 1), ("tuna", 4)] {
+            let mut record_type = record::RecordType::new();
+            record_type.set_name(name.to_string());
+            record_type.set_version(*version);
+            container.entries.push(record_type);
+        }
+        let mut context = MemoryContext::default();
+        seed(&mut context, &address, &container);
+
+        {
+            let mut state = SupplyChainState::new(&mut context);
+            let mut record_type = state.get_record_type("fish").unwrap().unwrap();
+            record_type.set_version(2);
+            state.set_record_type("fish", record_type).unwrap();
+        }
+
+        let stored: record::RecordTypeContainer = read(&context, &address);
+        let entries: Vec<(&str, u32)> = stored
+            .get_entries()
+            .iter()
+            .map(|record_type| (record_type.get_name(), record_type.get_version()))
+            .collect();
+        assert_eq!(entries, vec![("fish", 2), ("tuna", 4)]);
+    }
+
+    #[test]
+    fn properties_of_different_records_share_a_container() {
+        let address = make_property_address("fish-1", "temperature", 0);
+        let page_address = make_property_address("fish-1", "temperature", 1);
+        let mut container = property::PropertyContainer::new();
+        container.entries.push(property("fish-2", "temperature", 5));
+        let mut pages = property::PropertyPageContainer::new();
+        let mut page = property::PropertyPage::new();
+        page.set_record_id(String::from("fish-2"));
+        page.set_name(String::from("temperature"));
+        pages.entries.push(page);
+        let mut context = MemoryContext::default();
+        seed(&mut context, &address, &container);
+        seed(&mut context, &page_address, &pages);
+
+        {
+            let mut state = SupplyChainState::new(&mut context);
+            assert!(state.get_property("fish-1", "temperature").unwrap().is_none());
+            state
+                .set_property("fish-1", "temperature", property("fish-1", "temperature", 1))
+                .unwrap();
+            state
+                .set_property("fish-1", "temperature", property("fish-1", "temperature", 2))
+                .unwrap();
+            let fish_1 = state.get_property("fish-1", "temperature").unwrap().unwrap();
+            assert_eq!(fish_1.get_current_page(), 2);
+
+            let mut page = property::PropertyPage::new();
+            page.set_record_id(String::from("fish-1"));
+            page.set_name(String::from("temperature"));
+            state.set_property_page("fish-1", "temperature", 1, page).unwrap();
+            state.delete_property_page("fish-1", "temperature", 1).unwrap();
+            assert!(state.get_property_page("fish-1", "temperature", 1).unwrap().is_none());
+        }
+
+        let stored: property::PropertyContainer = read(&context, &address);
+        let entries: Vec<(&str, u32)> = stored
+            .get_entries()
+            .iter()
+            .map(|property| (property.get_record_id(), property.get_current_page()))
+            .collect();
+        assert_eq!(entries, vec![("fish-1", 2), ("fish-2", 5)]);
+        let stored: property::PropertyPageContainer = read(&context, &page_address);
+        let record_ids: Vec<&str> = stored
+            .get_entries()
+            .iter()
+            .map(|page| page.get_record_id())
+            .collect();
+        assert_eq!(record_ids, vec!["fish-2"]);
+    }
+
+    #[test]
+    fn emptied_containers_are_deleted() {
+        let mut context = MemoryContext::default();
+        let address = make_property_address("fish-1", "temperature", 1);
+        {
+            let mut state = SupplyChainState::new(&mut context);
+            let mut page = property::PropertyPage::new();
+            page.set_record_id(String::from("fish-1"));
+            page.set_name(String::from("temperature"));
+            state.set_property_page("fish-1", "temperature", 1, page).unwrap();
+            state.delete_property_page("fish-1", "temperature", 1).unwrap();
+            assert!(state.get_property_page("fish-1", "temperature", 1).unwrap().is_none());
+        }
+        assert!(!context.state.contains_key(&address));
+    }
+
+    fn make_request(signer: &str, payload: &payload::SCPayload) -> TpProcessRequest {
+        let mut header = TransactionHeader::new();
+        header.set_signer_public_key(signer.to_string());
+        header.set_inputs(RepeatedField::from_vec(vec![String::from("in")]));
+        header.set_outputs(RepeatedField::from_vec(vec![String::from("out")]));
+        let mut request = TpProcessRequest::new();
+        request.set_header(header);
+        request.set_payload(payload.write_to_bytes().unwrap());
+        request
+    }
+
+    fn create_agent(name: &str) -> payload::SCPayload {
+        let mut action = payload::CreateAgentAction::new();
+        action.set_name(name.to_string());
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::CREATE_AGENT);
+        payload.set_create_agent(action);
+        payload.set_timestamp(1000);
+        payload
+    }
+
+    #[test]
+    fn transactions_read_and_write_each_address_once() {
+        let request = make_request("alice", &create_agent("Alice"));
+        let mut context = MemoryContext::default();
+        SupplyChainTransactionHandler::new()
+            .process(&request, &mut context)
+            .unwrap();
+
+        // The agent and its first activity page
+        assert_eq!(context.sets, 1);
+        assert_eq!(context.state.len(), 2);
+        let mut read_addresses = context.read_addresses.clone();
+        read_addresses.sort();
+        read_addresses.dedup();
+        assert_eq!(read_addresses.len(), context.read_addresses.len());
+        let stored: agent::AgentContainer = read(&context, &make_agent_address("alice"));
+        assert_eq!(stored.get_entries()[0].get_name(), "Alice");
+    }
+
+    // Creates Alice's "fish-1" Record with a NUMBER "temperature"
+    fn create_fish(handler: &SupplyChainTransactionHandler, context: &mut MemoryContext) {
+        let mut schema = property::PropertySchema::new();
+        schema.set_name(String::from("temperature"));
+        schema.set_data_type(property::PropertySchema_DataType::NUMBER);
+        let mut create_type = payload::CreateRecordTypeAction::new();
+        create_type.set_name(String::from("fish"));
+        create_type.set_properties(RepeatedField::from_vec(vec![schema]));
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::CREATE_RECORD_TYPE);
+        payload.set_create_record_type(create_type);
+        payload.set_timestamp(1000);
+
+        let mut create_record = payload::CreateRecordAction::new();
+        create_record.set_record_id(String::from("fish-1"));
+        create_record.set_record_type(String::from("fish"));
+        let mut record_payload = payload::SCPayload::new();
+        record_payload.set_action(payload::SCPayload_Action::CREATE_RECORD);
+        record_payload.set_create_record(create_record);
+        record_payload.set_timestamp(1000);
+
+        for payload in &[create_agent("Alice"), payload, record_payload] {
+            handler.process(&make_request("alice", payload), context).unwrap();
+        }
+    }
+
+    fn update_temperature(timestamp: u64) -> payload::SCPayload {
+        let mut value = property::PropertyValue::new();
+        value.set_name(String::from("temperature"));
+        value.set_data_type(property::PropertySchema_DataType::NUMBER);
+        value.set_number_value(timestamp as i64);
+        let mut update = payload::UpdatePropertiesAction::new();
+        update.set_record_id(String::from("fish-1"));
+        update.set_properties(RepeatedField::from_vec(vec![value]));
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::UPDATE_PROPERTIES);
+        payload.set_update_properties(update);
+        payload.set_timestamp(timestamp);
+        payload
+    }
+
+    #[test]
+    fn ordinary_updates_write_only_the_page() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+        create_fish(&handler, &mut context);
+
+        let property_address = make_property_address("fish-1", "temperature", 0);
+        let page_address = make_property_address("fish-1", "temperature", 1);
+        for &(timestamp, expect_property_written) in &[(1001, true), (1002, false)] {
+            context.written.clear();
+            handler
+                .process(&make_request("alice", &update_temperature(timestamp)), &mut context)
+                .unwrap();
+            assert!(context.written.contains(&page_address));
+            // Only the first of the reporter's values on the page is counted
+            assert_eq!(context.written.contains(&property_address), expect_property_written);
+        }
+
+        let stored: property::PropertyContainer = read(&context, &property_address);
+        assert_eq!(stored.get_entries()[0].get_reporters()[0].get_page_count(), 1);
+    }
+
+    #[test]
+    fn lone_reporters_update_without_loading_the_property() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+        create_fish(&handler, &mut context);
+        let property_address = make_property_address("fish-1", "temperature", 0);
+        let update = |context: &mut MemoryContext, signer: &str, timestamp: u64| {
+            context.read_addresses.clear();
+            handler
+                .process(&make_request(signer, &update_temperature(timestamp)), context)
+                .unwrap();
+            context.read_addresses.contains(&property_address)
+        };
+
+        // The first value on the page is counted, later ones are not
+        assert!(update(&mut context, "alice", 1001));
+        assert!(!update(&mut context, "alice", 1002));
+
+        let mut create = payload::CreateProposalAction::new();
+        create.set_record_id(String::from("fish-1"));
+        create.set_receiving_agent(String::from("bob"));
+        create.set_role(proposal::Proposal_Role::REPORTER);
+        create.set_properties(RepeatedField::from_vec(vec![String::from("temperature")]));
+        let mut propose = payload::SCPayload::new();
+        propose.set_action(payload::SCPayload_Action::CREATE_PROPOSAL);
+        propose.set_create_proposal(create);
+        propose.set_timestamp(1003);
+        let mut answer = payload::AnswerProposalAction::new();
+        answer.set_record_id(String::from("fish-1"));
+        answer.set_receiving_agent(String::from("bob"));
+        answer.set_role(proposal::Proposal_Role::REPORTER);
+        answer.set_response(payload::AnswerProposalAction_Response::ACCEPT);
+        let mut accept = payload::SCPayload::new();
+        accept.set_action(payload::SCPayload_Action::ANSWER_PROPOSAL);
+        accept.set_answer_proposal(answer);
+        accept.set_timestamp(1004);
+        handler.process(&make_request("bob", &create_agent("Bob")), &mut context).unwrap();
+        handler.process(&make_request("alice", &propose), &mut context).unwrap();
+        handler.process(&make_request("bob", &accept), &mut context).unwrap();
+
+        // With a second reporter the Record no longer has a cursor
+        let records: record::RecordContainer = read(&context, &make_record_address("fish-1"));
+        assert!(records.get_entries()[0].get_reporting_cursors().is_empty());
+        assert!(update(&mut context, "alice", 1005));
+        assert!(update(&mut context, "bob", 1006));
+    }
+
+    #[test]
+    fn cursors_follow_pages_filled_within_a_payload() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+        create_fish(&handler, &mut context);
+        handler
+            .process(&make_request("alice", &update_temperature(1001)), &mut context)
+            .unwrap();
+
+        // Reporting moves on to a last page one value short of full, whose
+        // next page is a new one
+        let last_page = PROPERTY_PAGE_MAX_LENGTH as u32;
+        {
+            let mut state = SupplyChainState::new(&mut context);
+            let mut page = state
+                .get_property_page("fish-1", "temperature", 1)
+                .unwrap()
+                .unwrap();
+            let first = page.get_reported_values()[0].clone();
+            for timestamp in 1002..1000 + PROPERTY_PAGE_MAX_LENGTH as u64 {
+                let mut value = first.clone();
+                value.set_timestamp(timestamp);
+                page.mut_reported_values().push(value);
             }
-            Action::AnswerProposal(answer_proposal_payload) => self._answer_proposal(
-                answer_proposal_payload,
-                state,
-                signer,
-                payload.get_timestamp(),
-            )?,
-            Action::RevokeReporter(revoke_reporter_payload) => {
-                self._revoke_reporter(revoke_reporter_payload, state, signer)?
+            state
+                .set_property_page("fish-1", "temperature", last_page, page)
+                .unwrap();
+            let mut prop = state.get_property("fish-1", "temperature").unwrap().unwrap();
+            prop.set_current_page(last_page);
+            state.set_property("fish-1", "temperature", prop).unwrap();
+        }
+
+        // The first of two values fills the page, so the second starts the next
+        let mut payload = update_temperature(2000);
+        let mut values = payload.get_update_properties().get_properties().to_vec();
+        values.push(values[0].clone());
+        payload
+            .mut_update_properties()
+            .set_properties(RepeatedField::from_vec(values));
+        handler
+            .process(&make_request("alice", &payload), &mut context)
+            .unwrap();
+
+        let mut state = SupplyChainState::new(&mut context);
+        let prop = state.get_property("fish-1", "temperature").unwrap().unwrap();
+        assert_ne!(prop.get_current_page(), last_page);
+        let pages: Vec<usize> = [last_page, prop.get_current_page()]
+            .iter()
+            .map(|&number| {
+                state
+                    .get_property_page("fish-1", "temperature", number)
+                    .unwrap()
+                    .unwrap()
+                    .get_reported_values()
+                    .len()
+            })
+            .collect();
+        assert_eq!(pages, vec![PROPERTY_PAGE_MAX_LENGTH, 1]);
+    }
+
+    #[test]
+    fn archiving_compacts_the_record_and_can_delete_pages() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+        create_fish(&handler, &mut context);
+        handler.process(&make_request("alice", &update_temperature(1001)), &mut context).unwrap();
+
+        let mut finalize = payload::FinalizeRecordAction::new();
+        finalize.set_record_id(String::from("fish-1"));
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::FINALIZE_RECORD);
+        payload.set_finalize_record(finalize);
+        payload.set_timestamp(1002);
+        handler.process(&make_request("alice", &payload), &mut context).unwrap();
+        let final_record = SupplyChainState::new(&mut context)
+            .get_record("fish-1")
+            .unwrap()
+            .unwrap();
+        assert!(!final_record.get_reporting_cursors().is_empty());
+
+        let mut archive = payload::ArchiveRecordAction::new();
+        archive.set_record_id(String::from("fish-1"));
+        archive.set_delete_pages(true);
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::ARCHIVE_RECORD);
+        payload.set_archive_record(archive);
+        payload.set_timestamp(1003);
+        handler.process(&make_request("alice", &payload), &mut context).unwrap();
+
+        let archived = SupplyChainState::new(&mut context)
+            .get_record("fish-1")
+            .unwrap()
+            .unwrap();
+        let mut sha = Sha512::new();
+        sha.input(&final_record.write_to_bytes().unwrap());
+        assert_eq!(archived.get_archived_digest(), sha.result_str());
+        assert!(archived.get_reporting_cursors().is_empty());
+        assert_eq!(archived.get_pruned_properties()[0].get_name(), "temperature");
+        assert!(!context.state.contains_key(&make_property_address("fish-1", "temperature", 1)));
+    }
+
+    #[test]
+    fn cancel_all_checks_named_receiving_agents() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+        create_fish(&handler, &mut context);
+        handler.process(&make_request("bob", &create_agent("Bob")), &mut context).unwrap();
+
+        let mut create = payload::CreateProposalAction::new();
+        create.set_record_id(String::from("fish-1"));
+        create.set_receiving_agent(String::from("bob"));
+        create.set_role(proposal::Proposal_Role::REPORTER);
+        create.set_properties(RepeatedField::from_vec(vec![String::from("temperature")]));
+        let mut propose = payload::SCPayload::new();
+        propose.set_action(payload::SCPayload_Action::CREATE_PROPOSAL);
+        propose.set_create_proposal(create);
+        propose.set_timestamp(1001);
+        handler.process(&make_request("alice", &propose), &mut context).unwrap();
+        // As if the Proposal was opened before the open Proposal index existed
+        SupplyChainState::new(&mut context)
+            .delete_open_proposal_index("fish-1")
+            .unwrap();
+
+        let cancel = |receiving_agents: &[&str]| {
+            let mut cancellation = payload::CancelAllProposalsAction::new();
+            cancellation.set_record_id(String::from("fish-1"));
+            cancellation.set_receiving_agents(
+                receiving_agents.iter().map(|agent| agent.to_string()).collect(),
+            );
+            let mut payload = payload::SCPayload::new();
+            payload.set_action(payload::SCPayload_Action::CANCEL_ALL_PROPOSALS);
+            payload.set_cancel_all_proposals(cancellation);
+            payload.set_timestamp(1002);
+            make_request("alice", &payload)
+        };
+        match handler.process(&cancel(&[]), &mut context) {
+            Err(ApplyError::InvalidTransaction(msg)) => assert!(msg.contains("PROPOSAL_NOT_FOUND")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        handler.process(&cancel(&["bob"]), &mut context).unwrap();
+
+        let proposals = SupplyChainState::new(&mut context)
+            .get_proposal_container("fish-1", "bob")
+            .unwrap();
+        assert!(proposals.unwrap().get_entries().iter().all(|proposal| {
+            proposal.get_status() != proposal::Proposal_Status::OPEN
+        }));
+    }
+
+    // Reports a temperature for "fish-1" and "fish-2", which does not exist
+    fn bulk_update(policy: payload::BulkUpdatePropertiesAction_Policy) -> payload::SCPayload {
+        let mut bulk = payload::BulkUpdatePropertiesAction::new();
+        bulk.set_record_ids(RepeatedField::from_vec(vec![
+            String::from("fish-1"),
+            String::from("fish-2"),
+        ]));
+        bulk.set_properties(update_temperature(1001).take_update_properties().take_properties());
+        bulk.set_policy(policy);
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::BULK_UPDATE_PROPERTIES);
+        payload.set_bulk_update_properties(bulk);
+        payload.set_timestamp(1001);
+        payload
+    }
+
+    #[test]
+    fn all_or_nothing_bulk_updates_fail_together() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+        create_fish(&handler, &mut context);
+        let before = context.state.clone();
+
+        let policy = payload::BulkUpdatePropertiesAction_Policy::ALL_OR_NOTHING;
+        let result = handler.process(&make_request("alice", &bulk_update(policy)), &mut context);
+        match result {
+            Err(ApplyError::InvalidTransaction(msg)) => {
+                assert!(msg.contains("Record fish-2 of bulk update"), "{}", msg)
             }
+            _ => panic!("Bulk update was not rejected"),
         }
-        Ok(())
+        assert_eq!(context.state, before);
+    }
+
+    #[test]
+    fn best_effort_bulk_updates_list_failed_records() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+        create_fish(&handler, &mut context);
+        context.receipts.clear();
+
+        let policy = payload::BulkUpdatePropertiesAction_Policy::BEST_EFFORT;
+        handler
+            .process(&make_request("alice", &bulk_update(policy)), &mut context)
+            .unwrap();
+
+        let page: property::PropertyPageContainer =
+            read(&context, &make_property_address("fish-1", "temperature", 1));
+        assert_eq!(page.get_entries()[0].get_reported_values().len(), 1);
+        assert!(!context
+            .state
+            .contains_key(&make_property_address("fish-2", "temperature", 1)));
+
+        let bulk_receipt: receipt::BulkUpdateReceipt =
+            protobuf::parse_from_bytes(&context.receipts[0]).unwrap();
+        let entries = bulk_receipt.get_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get_record_id(), "fish-1");
+        assert!(entries[0].get_applied());
+        assert!(entries[0].get_error().is_empty());
+        assert_eq!(entries[1].get_record_id(), "fish-2");
+        assert!(!entries[1].get_applied());
+        assert!(entries[1].get_error().contains("RECORD_NOT_FOUND"));
+    }
+
+    #[test]
+    fn rules_compare_values_with_linked_records() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut context = MemoryContext::default();
+
+        let mut origin = property::PropertySchema::new();
+        origin.set_name(String::from("origin_country"));
+        origin.set_data_type(property::PropertySchema_DataType::STRING);
+        let mut parent = property::PropertySchema::new();
+        parent.set_name(String::from("parent"));
+        parent.set_data_type(property::PropertySchema_DataType::RECORD_REFERENCE);
+        let mut create_type = payload::CreateRecordTypeAction::new();
+        create_type.set_name(String::from("lot"));
+        create_type.set_properties(RepeatedField::from_vec(vec![origin, parent]));
+        create_type.set_rules(RepeatedField::from_vec(vec![String::from(
+            "action == \"transfer\" || parent == \"\" || property != \"origin_country\" \
+             || value == parent.origin_country",
+        )]));
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::CREATE_RECORD_TYPE);
+        payload.set_create_record_type(create_type);
+        payload.set_timestamp(1000);
+        for payload in &[create_agent("Alice"), payload] {
+            handler.process(&make_request("alice", payload), &mut context).unwrap();
+        }
+
+        let values = |country: &str, parent_id: &str| {
+            let mut origin = property::PropertyValue::new();
+            origin.set_name(String::from("origin_country"));
+            origin.set_data_type(property::PropertySchema_DataType::STRING);
+            origin.set_string_value(country.to_string());
+            let mut values = vec![origin];
+            if !parent_id.is_empty() {
+                let mut parent = property::PropertyValue::new();
+                parent.set_name(String::from("parent"));
+                parent.set_data_type(property::PropertySchema_DataType::RECORD_REFERENCE);
+                parent.set_record_reference_value(parent_id.to_string());
+                values.push(parent);
+            }
+            RepeatedField::from_vec(values)
+        };
+        let create = |record_id: &str, country: &str, parent_id: &str| {
+            let mut action = payload::CreateRecordAction::new();
+            action.set_record_id(record_id.to_string());
+            action.set_record_type(String::from("lot"));
+            action.set_properties(values(country, parent_id));
+            let mut payload = payload::SCPayload::new();
+            payload.set_action(payload::SCPayload_Action::CREATE_RECORD);
+            payload.set_create_record(action);
+            payload.set_timestamp(1001);
+            make_request("alice", &payload)
+        };
+        let update = |record_id: &str, country: &str| {
+            let mut action = payload::UpdatePropertiesAction::new();
+            action.set_record_id(record_id.to_string());
+            action.set_properties(values(country, ""));
+            let mut payload = payload::SCPayload::new();
+            payload.set_action(payload::SCPayload_Action::UPDATE_PROPERTIES);
+            payload.set_update_properties(action);
+            payload.set_timestamp(1002);
+            make_request("alice", &payload)
+        };
+
+        // A lot without a parent has nothing to match
+        handler.process(&create("lot-1", "PE", ""), &mut context).unwrap();
+        handler.process(&create("lot-2", "PE", "lot-1"), &mut context).unwrap();
+        let rule_failed = |result: Result<(), ApplyError>| match result {
+            Err(ApplyError::InvalidTransaction(msg)) => msg.contains("RULE_FAILED"),
+            _ => false,
+        };
+        assert!(rule_failed(handler.process(&create("lot-3", "CL", "lot-1"), &mut context)));
+
+        assert!(rule_failed(handler.process(&update("lot-2", "CL"), &mut context)));
+        handler.process(&update("lot-2", "PE"), &mut context).unwrap();
+        handler.process(&update("lot-1", "CL"), &mut context).unwrap();
+    }
+
+    #[test]
+    fn shared_setting_keys_match() {
+        let source = include_str!("../../shared/setting_addresses.js");
+        // The value of a constant such as TIMESTAMP_UNIT_SETTING
+        let constant = |name: &str| {
+            let start = source.find(&format!("const {} = '", name)).unwrap() + name.len() + 10;
+            source[start..].split('\'').next().unwrap().to_string()
+        };
+        let start = source.find("const SETTING_KEYS = [").unwrap();
+        let end = start + source[start..].find("\n]").unwrap();
+        let mut shared: Vec<String> = source[start..end]
+            .lines()
+            .skip(1)
+            .map(|line| line.trim().trim_end_matches(','))
+            .filter(|line| !line.is_empty() && !line.starts_with("//"))
+            .map(|entry| {
+                if entry.starts_with('\'') {
+                    entry.trim_matches('\'').to_string()
+                } else {
+                    constant(entry)
+                }
+            })
+            .collect();
+        shared.sort();
+
+        let mut keys: Vec<String> = FIXED_SETTINGS.iter().map(|key| key.to_string()).collect();
+        keys.sort();
+        assert_eq!(shared, keys);
     }
 }
@@ -0,0 +1,140 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Format checks for IDENTIFIER values, such as serial numbers and trace
+//! codes.
+//!
+//! Every identifier must be non-empty, no longer than `MAX_LENGTH`, and
+//! made of printable ASCII without spaces, so that it can be printed on a
+//! label and typed back in. A schema's format may require more:
+//!
+//! - `UUID_V4`: a random UUID in its hyphenated form, in either case
+//! - `GS1`: a numeric GS1 key, such as a GTIN, GLN, or SSCC, with a
+//!   correct check digit
+
+use messages::property::PropertySchema_IdentifierFormat as Format;
+
+/// The longest identifier accepted in any format
+pub const MAX_LENGTH: usize = 128;
+
+/// The lengths of the numeric GS1 keys ending in a check digit: GTIN-8,
+/// GTIN-12, GTIN-13 and GLN, GTIN-14, GSIN, and SSCC
+const GS1_LENGTHS: [usize; 6] = [8, 12, 13, 14, 17, 18];
+
+/// Checks an identifier against a format, describing the problem if it
+/// does not match
+pub fn check(format: Format, identifier: &str) -> Result<(), String> {
+    if identifier.is_empty() {
+        return Err(String::from("Identifier is empty"));
+    }
+    if identifier.len() > MAX_LENGTH {
+        return Err(format!(
+            "Identifier is longer than {} characters",
+            MAX_LENGTH
+        ));
+    }
+    if !identifier.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(String::from(
+            "Identifier may only contain printable ASCII without spaces",
+        ));
+    }
+
+    match format {
+        Format::FREE_FORM => Ok(()),
+        Format::UUID_V4 => check_uuid_v4(identifier),
+        Format::GS1 => check_gs1(identifier),
+    }
+}
+
+fn check_uuid_v4(identifier: &str) -> Result<(), String> {
+    let groups: Vec<&str> = identifier.split('-').collect();
+    let lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+    if lengths != [8, 4, 4, 4, 12]
+        || !groups.iter().all(|group| group.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        return Err(format!("Identifier is not a UUID: {}", identifier));
+    }
+    // The version is the first digit of the third group, and the variant
+    // the first digit of the fourth
+    if !groups[2].starts_with('4') {
+        return Err(format!("Identifier is not a version 4 UUID: {}", identifier));
+    }
+    match groups[3].as_bytes()[0].to_ascii_lowercase() {
+        b'8' | b'9' | b'a' | b'b' => Ok(()),
+        _ => Err(format!("Identifier has an invalid UUID variant: {}", identifier)),
+    }
+}
+
+fn check_gs1(identifier: &str) -> Result<(), String> {
+    if !identifier.bytes().all(|b| b.is_ascii_digit())
+        || !GS1_LENGTHS.contains(&identifier.len())
+    {
+        return Err(format!("Identifier is not a GS1 key: {}", identifier));
+    }
+
+    // Digits are weighted 3 and 1 alternately, starting with 3 from the
+    // right of the check digit, and the check digit makes the sum a
+    // multiple of ten
+    let digits: Vec<u32> = identifier.bytes().map(|b| u32::from(b - b'0')).collect();
+    let (check_digit, payload) = digits.split_last().expect("GS1 keys are not empty");
+    let sum: u32 = payload
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 0 { digit * 3 } else { *digit })
+        .sum();
+    if (10 - sum % 10) % 10 != *check_digit {
+        return Err(format!("Identifier has an incorrect check digit: {}", identifier));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_form_identifiers_must_be_printable() {
+        assert!(check(Format::FREE_FORM, "SN-0042/B").is_ok());
+        assert!(check(Format::FREE_FORM, "").is_err());
+        assert!(check(Format::FREE_FORM, "SN 0042").is_err());
+        assert!(check(Format::FREE_FORM, "SN\u{e9}").is_err());
+        assert!(check(Format::FREE_FORM, &"7".repeat(MAX_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn checks_uuid_versions_and_variants() {
+        assert!(check(Format::UUID_V4, "1b4e28ba-2fa1-41d2-883f-0016d3cca427").is_ok());
+        assert!(check(Format::UUID_V4, "1B4E28BA-2FA1-41D2-B83F-0016D3CCA427").is_ok());
+        // Version 1
+        assert!(check(Format::UUID_V4, "1b4e28ba-2fa1-11d2-883f-0016d3cca427").is_err());
+        // Wrong variant
+        assert!(check(Format::UUID_V4, "1b4e28ba-2fa1-41d2-c83f-0016d3cca427").is_err());
+        assert!(check(Format::UUID_V4, "1b4e28ba2fa141d2883f0016d3cca427").is_err());
+        assert!(check(Format::UUID_V4, "1b4e28ba-2fa1-41d2-883f-0016d3cca42g").is_err());
+    }
+
+    #[test]
+    fn checks_gs1_check_digits() {
+        // GTIN-13, GTIN-12, GTIN-8, and SSCC
+        assert!(check(Format::GS1, "4006381333931").is_ok());
+        assert!(check(Format::GS1, "036000291452").is_ok());
+        assert!(check(Format::GS1, "96385074").is_ok());
+        assert!(check(Format::GS1, "106141411234567897").is_ok());
+
+        assert!(check(Format::GS1, "4006381333932").is_err());
+        assert!(check(Format::GS1, "400638133393").is_err());
+        assert!(check(Format::GS1, "40063813339A1").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks for LOCATION values and the fences their schemas may have.
+//!
+//! Coordinates are in millionths of a degree. A fence is a polygon given
+//! by its vertices in order, and its edges are straight lines in latitude
+//! and longitude, so a fence cannot cross the antimeridian. Locations on
+//! an edge are inside the fence.
+
+use messages::property::Location;
+
+/// The largest latitude either way, in millionths of a degree
+const MAX_LATITUDE: i64 = 90_000_000;
+
+/// The largest longitude either way, in millionths of a degree
+const MAX_LONGITUDE: i64 = 180_000_000;
+
+/// The most vertices a fence may have
+pub const MAX_FENCE_VERTICES: usize = 256;
+
+/// Checks that a location's coordinates are on the globe
+pub fn check_coordinates(location: &Location) -> Result<(), String> {
+    if location.get_latitude().abs() > MAX_LATITUDE {
+        return Err(format!(
+            "Latitude is outside of -90 to 90 degrees: {}",
+            location.get_latitude()
+        ));
+    }
+    if location.get_longitude().abs() > MAX_LONGITUDE {
+        return Err(format!(
+            "Longitude is outside of -180 to 180 degrees: {}",
+            location.get_longitude()
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that a fence has enough vertices to enclose an area, and no
+/// more than the maximum, each on the globe
+pub fn check_fence(fence: &[Location]) -> Result<(), String> {
+    if fence.len() < 3 {
+        return Err(String::from("Fence must have at least three vertices"));
+    }
+    if fence.len() > MAX_FENCE_VERTICES {
+        return Err(format!(
+            "Fence has {} vertices, more than the maximum of {}",
+            fence.len(),
+            MAX_FENCE_VERTICES
+        ));
+    }
+    for vertex in fence {
+        check_coordinates(vertex)?;
+    }
+    Ok(())
+}
+
+/// Whether a location is inside a fence, or on one of its edges
+pub fn is_inside(location: &Location, fence: &[Location]) -> bool {
+    let point = coordinates(location);
+    let mut inside = false;
+    for (i, vertex) in fence.iter().enumerate() {
+        let a = coordinates(vertex);
+        let b = coordinates(&fence[(i + 1) % fence.len()]);
+        // Positive when the point is left of the edge from a to b
+        let cross = (b.0 - a.0) * (point.1 - a.1) - (point.0 - a.0) * (b.1 - a.1);
+        let within_box = a.0.min(b.0) <= point.0
+            && point.0 <= a.0.max(b.0)
+            && a.1.min(b.1) <= point.1
+            && point.1 <= a.1.max(b.1);
+        if cross == 0 && within_box {
+            return true;
+        }
+        // Counts the edges crossed by a ray running east from the point
+        if (a.1 > point.1) != (b.1 > point.1) && (cross > 0) == (b.1 > a.1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// A location as (longitude, latitude), wide enough to multiply
+fn coordinates(location: &Location) -> (i128, i128) {
+    (
+        i128::from(location.get_longitude()),
+        i128::from(location.get_latitude()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(latitude: i64, longitude: i64) -> Location {
+        let mut location = Location::new();
+        location.set_latitude(latitude);
+        location.set_longitude(longitude);
+        location
+    }
+
+    fn fence(vertices: &[(i64, i64)]) -> Vec<Location> {
+        vertices
+            .iter()
+            .map(|&(latitude, longitude)| location(latitude, longitude))
+            .collect()
+    }
+
+    #[test]
+    fn checks_coordinates() {
+        assert!(check_coordinates(&location(90_000_000, -180_000_000)).is_ok());
+        assert!(check_coordinates(&location(90_000_001, 0)).is_err());
+        assert!(check_coordinates(&location(0, -180_000_001)).is_err());
+    }
+
+    #[test]
+    fn checks_fences() {
+        assert!(check_fence(&fence(&[(0, 0), (1, 1)])).is_err());
+        assert!(check_fence(&fence(&[(0, 0), (1, 1), (91_000_000, 0)])).is_err());
+        assert!(check_fence(&vec![location(0, 0); MAX_FENCE_VERTICES + 1]).is_err());
+        assert!(check_fence(&fence(&[(0, 0), (1, 1), (0, 1)])).is_ok());
+    }
+
+    #[test]
+    fn finds_locations_inside_fences() {
+        // An L shape, so that the notch is outside
+        let corridor = fence(&[
+            (0, 0),
+            (0, 4_000_000),
+            (2_000_000, 4_000_000),
+            (2_000_000, 2_000_000),
+            (4_000_000, 2_000_000),
+            (4_000_000, 0),
+        ]);
+        assert!(is_inside(&location(1_000_000, 1_000_000), &corridor));
+        assert!(is_inside(&location(3_000_000, 1_000_000), &corridor));
+        assert!(is_inside(&location(1_000_000, 3_000_000), &corridor));
+        assert!(!is_inside(&location(3_000_000, 3_000_000), &corridor));
+        assert!(!is_inside(&location(-1, 1_000_000), &corridor));
+        // Edges and vertices
+        assert!(is_inside(&location(0, 2_000_000), &corridor));
+        assert!(is_inside(&location(2_000_000, 3_000_000), &corridor));
+        assert!(is_inside(&location(4_000_000, 2_000_000), &corridor));
+    }
+}
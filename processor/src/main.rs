@@ -15,6 +15,7 @@
 #[macro_use]
 extern crate clap;
 extern crate crypto;
+extern crate ctrlc;
 extern crate log4rs;
 #[macro_use]
 extern crate log;
@@ -24,17 +25,39 @@ extern crate sawtooth_sdk;
 
 mod handler;
 mod addressing;
+mod errors;
+mod estimate;
+mod identifiers;
+mod locations;
 mod messages;
+mod patterns;
+mod plugins;
+mod replay;
+mod rules;
+mod shadow;
+mod staging;
+mod units;
+mod values;
+mod workers;
 
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 use log::LogLevelFilter;
 use log4rs::append::console::ConsoleAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 
-use sawtooth_sdk::processor::TransactionProcessor;
+use sawtooth_sdk::messages::processor::TpProcessRequest;
+use sawtooth_sdk::processor::handler::ApplyError;
 
 use handler::SupplyChainTransactionHandler;
+use replay::ReplayLog;
+use shadow::ShadowConfig;
+use workers::WorkerConfig;
 
 fn main() {
     let matches = clap_app!(intkey =>
@@ -43,7 +66,35 @@ fn main() {
         (@arg connect: -C --connect +takes_value
          "connection endpoint for validator")
         (@arg verbose: -v --verbose +multiple
-         "increase output verbosity"))
+         "increase output verbosity")
+        (@arg replay_log: --("replay-log") +takes_value
+         "append every transaction applied to this file, for replaying")
+        (@arg workers: --workers +takes_value
+         "number of threads applying transactions (default 1)")
+        (@arg queue_size: --("queue-size") +takes_value
+         "number of transactions waiting for a worker (default 16)")
+        (@arg metrics_interval: --("metrics-interval") +takes_value
+         "seconds between logging per-action metrics, or 0 for never \
+          (default 60)")
+        (@subcommand replay =>
+            (about: "re-apply the transactions in a replay log and report \
+                     any whose outcome differs from the recorded one")
+            (@arg LOG: +required "the replay log to read"))
+        (@subcommand shadow =>
+            (about: "follow the replay log of a running processor, applying \
+                     each transaction appended to it with this build, and log \
+                     any whose outcome differs from the recorded one")
+            (@arg LOG: +required "the replay log to follow")
+            (@arg from_start: --("from-start")
+             "also apply the transactions already in the log"))
+        (@subcommand estimate =>
+            (about: "apply a payload to a snapshot of state, without a \
+                     validator, and report the state it reads and writes")
+            (@arg PAYLOAD: +required "a file holding a serialized SCPayload")
+            (@arg signer: --signer +takes_value +required
+             "public key of the transaction's signer")
+            (@arg state: --state +takes_value +multiple
+             "a validator REST API /state response to read state from")))
         .get_matches();
 
     let endpoint = matches
@@ -77,11 +128,176 @@ fn main() {
         Err(_) => process::exit(1),
     }
 
-    let handler = SupplyChainTransactionHandler::new();
-    let mut processor = TransactionProcessor::new(endpoint);
+    #[allow(unused_mut)]
+    let mut handler = SupplyChainTransactionHandler::new();
+    #[cfg(feature = "example-plugins")]
+    plugins::register_examples(&mut handler);
+
+    if let Some(replay_matches) = matches.subcommand_matches("replay") {
+        let log = replay_matches.value_of("LOG").unwrap_or_default();
+        process::exit(run_replay(&handler, log));
+    }
+
+    if let Some(shadow_matches) = matches.subcommand_matches("shadow") {
+        let config = ShadowConfig {
+            from_start: shadow_matches.is_present("from_start"),
+            metrics_interval: worker_config(&matches).metrics_interval,
+        };
+        let log = shadow_matches.value_of("LOG").unwrap_or_default();
+        process::exit(run_shadow(&handler, log, &config));
+    }
+
+    if let Some(estimate_matches) = matches.subcommand_matches("estimate") {
+        process::exit(run_estimate(&handler, estimate_matches));
+    }
+
+    if let Some(path) = matches.value_of("replay_log") {
+        match ReplayLog::open(path) {
+            Ok(log) => handler.set_replay_log(log),
+            Err(err) => {
+                error!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    let config = worker_config(&matches);
 
     info!("Console logging level: {}", console_log_level);
 
-    processor.add_handler(&handler);
-    processor.start();
+    workers::run(endpoint, Arc::new(handler), &config);
+}
+
+/// Reads the worker pool's settings from the command line, exiting if
+/// one is not a positive number (or zero, for the metrics interval)
+fn worker_config(matches: &clap::ArgMatches) -> WorkerConfig {
+    let parse = |name: &str, default: u64, min: u64| -> u64 {
+        match matches.value_of(name) {
+            None => default,
+            Some(value) => match value.parse::<u64>() {
+                Ok(parsed) if parsed >= min => parsed,
+                _ => {
+                    error!("Invalid --{}: {}", name.replace('_', "-"), value);
+                    process::exit(2);
+                }
+            },
+        }
+    };
+
+    let interval = parse("metrics_interval", workers::DEFAULT_METRICS_INTERVAL, 0);
+    WorkerConfig {
+        workers: parse("workers", workers::DEFAULT_WORKERS as u64, 1) as usize,
+        queue_size: parse("queue_size", workers::DEFAULT_QUEUE_SIZE as u64, 1) as usize,
+        metrics_interval: if interval == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(interval))
+        },
+    }
+}
+
+/// Replays every transaction in a log, printing those whose outcome has
+/// changed. Returns the exit code.
+fn run_replay(handler: &SupplyChainTransactionHandler, log: &str) -> i32 {
+    let mut replayed = 0;
+    let mut diverged = 0;
+
+    let result = replay::read_log(log, |entry| {
+        replayed += 1;
+        let differences = replay::replay(handler, &entry)?;
+        if !differences.is_empty() {
+            diverged += 1;
+            let signature = protobuf::parse_from_bytes::<TpProcessRequest>(entry.get_request())
+                .map(|request| request.get_signature().to_string())
+                .unwrap_or_default();
+            println!("Transaction {} ({}):", replayed, signature);
+            for difference in differences {
+                println!("    {}", difference);
+            }
+        }
+        Ok(())
+    });
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        return 2;
+    }
+
+    println!("{} transactions replayed, {} diverged", replayed, diverged);
+    if diverged > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Shadows the transactions appended to a log until interrupted, then
+/// prints how many diverged. Returns the exit code.
+fn run_shadow(handler: &SupplyChainTransactionHandler, log: &str, config: &ShadowConfig) -> i32 {
+    let metrics = match shadow::run(handler, log, config) {
+        Ok(metrics) => metrics,
+        Err(err) => {
+            error!("{}", err);
+            return 2;
+        }
+    };
+
+    let stats = metrics.snapshot();
+    let shadowed: u64 = stats.values().map(|stats| stats.applied).sum();
+    let diverged: u64 = stats.values().map(|stats| stats.diverged).sum();
+    println!("{} transactions shadowed, {} diverged", shadowed, diverged);
+    if diverged > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Estimates the cost of a payload, printing it along with whether the
+/// transaction would be valid. Returns the exit code.
+fn run_estimate(handler: &SupplyChainTransactionHandler, matches: &clap::ArgMatches) -> i32 {
+    let path = matches.value_of("PAYLOAD").unwrap_or_default();
+    let payload = match File::open(path).and_then(|mut file| {
+        let mut payload = Vec::new();
+        file.read_to_end(&mut payload).map(|_| payload)
+    }) {
+        Ok(payload) => payload,
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", path, err);
+            return 2;
+        }
+    };
+
+    let mut state = HashMap::new();
+    for snapshot in matches.values_of("state").into_iter().flatten() {
+        match estimate::load_snapshot(snapshot) {
+            Ok(entries) => state.extend(entries),
+            Err(err) => {
+                eprintln!("{}", err);
+                return 2;
+            }
+        }
+    }
+
+    let mut request = TpProcessRequest::new();
+    request.set_header(estimate::make_header(matches.value_of("signer").unwrap_or_default()));
+    request.set_payload(payload);
+    request.set_signature(String::from("estimate"));
+
+    let (estimate, result) = estimate::estimate(handler, &request, state);
+    println!("{}", estimate);
+    match result {
+        Ok(()) => {
+            println!("outcome: valid");
+            0
+        }
+        Err(ApplyError::InvalidTransaction(message)) => {
+            println!("outcome: invalid, counted up to the rejection: {}", message);
+            1
+        }
+        Err(ApplyError::InternalError(message)) => {
+            println!("outcome: internal error, counted up to the error: {}", message);
+            1
+        }
+    }
 }
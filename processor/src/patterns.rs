@@ -0,0 +1,334 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! String patterns carried by property schemas.
+//!
+//! Patterns are checked by every validator, so they are a subset of
+//! regular expressions which always matches in time linear in the length
+//! of the value:
+//!
+//! - a pattern matches the whole value, and a leading `^` or trailing `$`
+//!   is accepted but changes nothing
+//! - atoms are literal characters, `.`, the escapes `\d`, `\w`, and `\s`,
+//!   an escaped punctuation character such as `\.`, and classes such as
+//!   `[A-Z0-9_-]` or `[^ ]`
+//! - an atom may be followed by `*`, `+`, `?`, `{n}`, `{n,}`, or `{n,m}`
+//! - there are no groups, alternation, or backreferences
+//!
+//! A pattern is compiled to a list of steps, one for each occurrence of
+//! an atom it allows, and matched by tracking every step that could be
+//! reached so far, so there is no backtracking.
+
+/// The longest pattern a schema may have
+pub const MAX_PATTERN_LENGTH: usize = 256;
+
+/// The most steps a compiled pattern may have, counting each repetition
+/// of an atom separately
+const MAX_STEPS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Atom {
+    Any,
+    Char(char),
+    Digit,
+    Word,
+    Space,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            Atom::Any => true,
+            Atom::Char(expected) => c == expected,
+            Atom::Digit => c.is_ascii_digit(),
+            Atom::Word => c.is_ascii_alphanumeric() || c == '_',
+            Atom::Space => c.is_whitespace(),
+            Atom::Class {
+                negated,
+                ref ranges,
+            } => ranges.iter().any(|&(low, high)| low <= c && c <= high) != negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Required(Atom),
+    Optional(Atom),
+    Repeated(Atom),
+}
+
+/// A compiled pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    steps: Vec<Step>,
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern, String> {
+        if pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(format!(
+                "Pattern is longer than {} characters",
+                MAX_PATTERN_LENGTH
+            ));
+        }
+        let mut body = pattern;
+        if body.starts_with('^') {
+            body = &body[1..];
+        }
+        if body.ends_with('$') && !body.ends_with("\\$") {
+            body = &body[..body.len() - 1];
+        }
+
+        let chars: Vec<char> = body.chars().collect();
+        let mut steps = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let (atom, next) = parse_atom(&chars, i)?;
+            let (min, max, next) = parse_quantifier(&chars, next)?;
+            for _ in 0..min {
+                steps.push(Step::Required(atom.clone()));
+            }
+            match max {
+                None => steps.push(Step::Repeated(atom)),
+                Some(max) => {
+                    for _ in min..max {
+                        steps.push(Step::Optional(atom.clone()));
+                    }
+                }
+            }
+            if steps.len() > MAX_STEPS {
+                return Err(String::from("Pattern repeats too many times"));
+            }
+            i = next;
+        }
+        Ok(Pattern { steps })
+    }
+
+    /// Whether the pattern matches the whole of a value
+    pub fn matches(&self, value: &str) -> bool {
+        let mut reached = vec![false; self.steps.len() + 1];
+        reached[0] = true;
+        self.close(&mut reached);
+
+        for c in value.chars() {
+            let mut next = vec![false; self.steps.len() + 1];
+            for (i, step) in self.steps.iter().enumerate() {
+                if !reached[i] {
+                    continue;
+                }
+                match *step {
+                    Step::Required(ref atom) | Step::Optional(ref atom) => {
+                        if atom.matches(c) {
+                            next[i + 1] = true;
+                        }
+                    }
+                    Step::Repeated(ref atom) => {
+                        if atom.matches(c) {
+                            next[i] = true;
+                        }
+                    }
+                }
+            }
+            self.close(&mut next);
+            if !next.iter().any(|&r| r) {
+                return false;
+            }
+            reached = next;
+        }
+        reached[self.steps.len()]
+    }
+
+    /// Marks the steps which can be reached by skipping optional and
+    /// repeated ones
+    fn close(&self, reached: &mut [bool]) {
+        for (i, step) in self.steps.iter().enumerate() {
+            if !reached[i] {
+                continue;
+            }
+            match *step {
+                Step::Optional(_) | Step::Repeated(_) => reached[i + 1] = true,
+                Step::Required(_) => (),
+            }
+        }
+    }
+}
+
+fn parse_atom(chars: &[char], i: usize) -> Result<(Atom, usize), String> {
+    match chars[i] {
+        '.' => Ok((Atom::Any, i + 1)),
+        '\\' => parse_escape(chars, i),
+        '[' => parse_class(chars, i + 1),
+        '*' | '+' | '?' | '{' => Err(format!(
+            "Quantifier {} does not follow anything at {}",
+            chars[i], i
+        )),
+        '(' | ')' | '|' => Err(format!(
+            "Groups and alternation are not supported: {}",
+            chars[i]
+        )),
+        c => Ok((Atom::Char(c), i + 1)),
+    }
+}
+
+fn parse_escape(chars: &[char], i: usize) -> Result<(Atom, usize), String> {
+    match chars.get(i + 1) {
+        Some('d') => Ok((Atom::Digit, i + 2)),
+        Some('w') => Ok((Atom::Word, i + 2)),
+        Some('s') => Ok((Atom::Space, i + 2)),
+        Some(&c) if c.is_ascii_punctuation() => Ok((Atom::Char(c), i + 2)),
+        Some(c) => Err(format!("Unsupported escape: \\{}", c)),
+        None => Err(String::from("Pattern ends with an escape")),
+    }
+}
+
+fn parse_class(chars: &[char], start: usize) -> Result<(Atom, usize), String> {
+    let mut i = start;
+    let negated = chars.get(i) == Some(&'^');
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    loop {
+        let low = match chars.get(i) {
+            None => return Err(String::from("Character class is not closed")),
+            Some(']') if i > start + negated as usize => return Ok((
+                Atom::Class { negated, ranges },
+                i + 1,
+            )),
+            Some('\\') => match chars.get(i + 1) {
+                Some(&c) if c.is_ascii_punctuation() => {
+                    i += 1;
+                    c
+                }
+                _ => return Err(String::from("Unsupported escape in character class")),
+            },
+            Some(&c) => c,
+        };
+        i += 1;
+        let is_range = chars.get(i) == Some(&'-')
+            && chars.get(i + 1).is_some()
+            && chars.get(i + 1) != Some(&']');
+        if is_range {
+            let high = chars[i + 1];
+            if high < low {
+                return Err(format!("Character range is backwards: {}-{}", low, high));
+            }
+            ranges.push((low, high));
+            i += 2;
+        } else {
+            ranges.push((low, low));
+        }
+    }
+}
+
+fn parse_quantifier(
+    chars: &[char],
+    i: usize,
+) -> Result<(usize, Option<usize>, usize), String> {
+    match chars.get(i) {
+        Some('*') => Ok((0, None, i + 1)),
+        Some('+') => Ok((1, None, i + 1)),
+        Some('?') => Ok((0, Some(1), i + 1)),
+        Some('{') => {
+            let close = match chars[i..].iter().position(|&c| c == '}') {
+                Some(offset) => i + offset,
+                None => return Err(String::from("Repetition is not closed")),
+            };
+            let inner: String = chars[i + 1..close].iter().collect();
+            let parse = |s: &str| {
+                s.parse::<usize>()
+                    .map_err(|_| format!("Invalid repetition: {{{}}}", inner))
+            };
+            let (min, max) = match inner.find(',') {
+                None => {
+                    let n = parse(&inner)?;
+                    (n, Some(n))
+                }
+                Some(comma) if comma == inner.len() - 1 => (parse(&inner[..comma])?, None),
+                Some(comma) => (
+                    parse(&inner[..comma])?,
+                    Some(parse(&inner[comma + 1..])?),
+                ),
+            };
+            if let Some(max) = max {
+                if max < min {
+                    return Err(format!("Invalid repetition: {{{}}}", inner));
+                }
+            }
+            if min > MAX_STEPS || max.unwrap_or(0) > MAX_STEPS {
+                return Err(String::from("Pattern repeats too many times"));
+            }
+            Ok((min, max, close + 1))
+        }
+        _ => Ok((1, Some(1), i)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, value: &str) -> bool {
+        Pattern::parse(pattern).unwrap().matches(value)
+    }
+
+    #[test]
+    fn matches_whole_values() {
+        assert!(matches("abc", "abc"));
+        assert!(!matches("abc", "abcd"));
+        assert!(!matches("abc", "xabc"));
+        assert!(matches("^abc$", "abc"));
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn matches_quantified_atoms() {
+        assert!(matches("[A-Z]{3}-\\d+", "MCX-42"));
+        assert!(!matches("[A-Z]{3}-\\d+", "MC-42"));
+        assert!(!matches("[A-Z]{3}-\\d+", "MCX-"));
+        assert!(matches("a*b?c{1,2}", "aaacc"));
+        assert!(matches("a*b?c{1,2}", "bc"));
+        assert!(!matches("a*b?c{1,2}", "accc"));
+        assert!(matches("x{2,}", "xxxx"));
+        assert!(!matches("x{2,}", "x"));
+        assert!(matches(".+", "anything"));
+        assert!(!matches(".+", ""));
+    }
+
+    #[test]
+    fn matches_classes_and_escapes() {
+        assert!(matches("[^ ]+", "no-spaces"));
+        assert!(!matches("[^ ]+", "two words"));
+        assert!(matches("\\w+\\.\\w+", "file_1.txt"));
+        assert!(!matches("\\w+\\.\\w+", "file_1txt"));
+        assert!(matches("[a-c-]+", "a-b-c"));
+        assert!(matches("\\d\\s\\$", "4 $"));
+    }
+
+    #[test]
+    fn rejects_unsupported_patterns() {
+        assert!(Pattern::parse("(ab)+").is_err());
+        assert!(Pattern::parse("a|b").is_err());
+        assert!(Pattern::parse("*a").is_err());
+        assert!(Pattern::parse("[abc").is_err());
+        assert!(Pattern::parse("[z-a]").is_err());
+        assert!(Pattern::parse("a{3,1}").is_err());
+        assert!(Pattern::parse("a{2000}").is_err());
+        assert!(Pattern::parse("\\q").is_err());
+        assert!(Pattern::parse(&"a".repeat(MAX_PATTERN_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation plugins let a deployment compile custom business rules into
+//! the transaction processor without patching the core handler. Plugins
+//! are registered with `SupplyChainTransactionHandler::add_plugin` and are
+//! invoked, in registration order, at each of the hook points below. Any
+//! plugin returning an error rejects the transaction.
+//!
+//! Plugins run inside `apply`, so they must be deterministic: they may only
+//! look at the arguments they are given, never at clocks, randomness, or
+//! local configuration that could differ between validators. They are
+//! shared by the processor's worker threads, so must be `Send + Sync`.
+
+use sawtooth_sdk::processor::handler::ApplyError;
+
+use messages::*;
+
+pub trait ValidationPlugin: Send + Sync {
+    /// A short name identifying the plugin in error messages
+    fn name(&self) -> &str;
+
+    /// Called by CreateRecord after the record passes schema validation,
+    /// before anything is written to state.
+    fn pre_create_record(
+        &self,
+        _record: &record::Record,
+        _values: &[property::PropertyValue],
+        _signer: &str,
+    ) -> Result<(), ApplyError> {
+        Ok(())
+    }
+
+    /// Called by UpdateProperties for each value after the reporter and
+    /// type checks, before the value is appended to its page.
+    fn pre_update_property(
+        &self,
+        _record: &record::Record,
+        _property: &property::Property,
+        _value: &property::PropertyValue,
+        _signer: &str,
+    ) -> Result<(), ApplyError> {
+        Ok(())
+    }
+
+    /// Called by AnswerProposal when an ownership or custodianship
+    /// proposal is accepted, before the record changes hands.
+    fn pre_transfer(
+        &self,
+        _record: &record::Record,
+        _proposal: &proposal::Proposal,
+        _signer: &str,
+    ) -> Result<(), ApplyError> {
+        Ok(())
+    }
+}
+
+/// Rejects records whose id does not start with a fixed prefix, e.g. a
+/// company's serial number scheme.
+#[cfg(any(test, feature = "example-plugins"))]
+pub struct RecordIdPrefixPlugin {
+    prefix: String,
+}
+
+#[cfg(any(test, feature = "example-plugins"))]
+impl RecordIdPrefixPlugin {
+    pub fn new(prefix: &str) -> RecordIdPrefixPlugin {
+        RecordIdPrefixPlugin {
+            prefix: prefix.to_string(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "example-plugins"))]
+impl ValidationPlugin for RecordIdPrefixPlugin {
+    fn name(&self) -> &str {
+        "record-id-prefix"
+    }
+
+    fn pre_create_record(
+        &self,
+        record: &record::Record,
+        _values: &[property::PropertyValue],
+        _signer: &str,
+    ) -> Result<(), ApplyError> {
+        if !record.get_record_id().starts_with(&self.prefix) {
+            return Err(ApplyError::InvalidTransaction(format!(
+                "Record id must start with {}: {}",
+                self.prefix,
+                record.get_record_id()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Rejects NUMBER updates to a named property that fall outside a range,
+/// e.g. temperature readings a sensor cannot physically produce.
+#[cfg(any(test, feature = "example-plugins"))]
+pub struct NumberRangePlugin {
+    property_name: String,
+    min: i64,
+    max: i64,
+}
+
+#[cfg(any(test, feature = "example-plugins"))]
+impl NumberRangePlugin {
+    pub fn new(property_name: &str, min: i64, max: i64) -> NumberRangePlugin {
+        NumberRangePlugin {
+            property_name: property_name.to_string(),
+            min: min,
+            max: max,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "example-plugins"))]
+impl ValidationPlugin for NumberRangePlugin {
+    fn name(&self) -> &str {
+        "number-range"
+    }
+
+    fn pre_update_property(
+        &self,
+        _record: &record::Record,
+        property: &property::Property,
+        value: &property::PropertyValue,
+        _signer: &str,
+    ) -> Result<(), ApplyError> {
+        if property.get_name() != self.property_name
+            || value.get_data_type() != property::PropertySchema_DataType::NUMBER
+        {
+            return Ok(());
+        }
+        let number = value.get_number_value();
+        if number < self.min || number > self.max {
+            return Err(ApplyError::InvalidTransaction(format!(
+                "Value for {} must be between {} and {}: {}",
+                self.property_name, self.min, self.max, number
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Registers the example plugins. Deployments wanting their own rules
+/// should write a function like this one and call it from main.
+#[cfg(feature = "example-plugins")]
+pub fn register_examples(handler: &mut ::handler::SupplyChainTransactionHandler) {
+    handler.add_plugin(Box::new(RecordIdPrefixPlugin::new("fish-")));
+    handler.add_plugin(Box::new(NumberRangePlugin::new(
+        "temperature",
+        -100000,
+        100000,
+    )));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_record(record_id: &str) -> record::Record {
+        let mut record = record::Record::new();
+        record.set_record_id(record_id.to_string());
+        record
+    }
+
+    fn make_number_property(name: &str) -> property::Property {
+        let mut property = property::Property::new();
+        property.set_name(name.to_string());
+        property.set_data_type(property::PropertySchema_DataType::NUMBER);
+        property
+    }
+
+    fn make_number_value(name: &str, number: i64) -> property::PropertyValue {
+        let mut value = property::PropertyValue::new();
+        value.set_name(name.to_string());
+        value.set_data_type(property::PropertySchema_DataType::NUMBER);
+        value.set_number_value(number);
+        value
+    }
+
+    #[test]
+    fn record_id_prefix_accepts_matching_ids() {
+        let plugin = RecordIdPrefixPlugin::new("fish-");
+        assert!(plugin
+            .pre_create_record(&make_record("fish-456"), &[], "signer")
+            .is_ok());
+    }
+
+    #[test]
+    fn record_id_prefix_rejects_other_ids() {
+        let plugin = RecordIdPrefixPlugin::new("fish-");
+        assert!(plugin
+            .pre_create_record(&make_record("asset-1"), &[], "signer")
+            .is_err());
+    }
+
+    #[test]
+    fn number_range_checks_bounds() {
+        let plugin = NumberRangePlugin::new("temperature", -10, 10);
+        let record = make_record("fish-456");
+        let property = make_number_property("temperature");
+
+        assert!(plugin
+            .pre_update_property(&record, &property, &make_number_value("temperature", 10), "s")
+            .is_ok());
+        assert!(plugin
+            .pre_update_property(&record, &property, &make_number_value("temperature", 11), "s")
+            .is_err());
+        assert!(plugin
+            .pre_update_property(&record, &property, &make_number_value("temperature", -11), "s")
+            .is_err());
+    }
+
+    #[test]
+    fn number_range_ignores_other_properties() {
+        let plugin = NumberRangePlugin::new("temperature", -10, 10);
+        let record = make_record("fish-456");
+        let property = make_number_property("weight");
+
+        assert!(plugin
+            .pre_update_property(&record, &property, &make_number_value("weight", 500), "s")
+            .is_ok());
+    }
+
+    #[test]
+    fn default_hooks_accept_everything() {
+        let plugin = RecordIdPrefixPlugin::new("fish-");
+        let proposal = proposal::Proposal::new();
+        assert!(plugin
+            .pre_transfer(&make_record("asset-1"), &proposal, "signer")
+            .is_ok());
+    }
+}
@@ -0,0 +1,657 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic replay of recorded transactions.
+//!
+//! Started with `--replay-log <file>`, the processor appends an entry to
+//! the file for every transaction it applies: the request, the state it
+//! read, the state it wrote, the events and receipt data it added, and
+//! the outcome. The
+//! `replay` subcommand re-executes a log against the handler it was built
+//! with and reports every transaction whose outcome differs, so that a
+//! refactor can be checked against real traffic before it is deployed.
+//!
+//! Replaying serves reads from the log, so no validator is needed. If the
+//! handler being replayed reads an address the recorded one did not, the
+//! value is unknown; the read fails and is reported as a difference.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Write};
+
+use protobuf;
+use protobuf::{CodedInputStream, Message, RepeatedField};
+
+use sawtooth_sdk::messages::processor::TpProcessRequest;
+use sawtooth_sdk::processor::handler::{ApplyError, ContextError, TransactionContext};
+
+use messages::replay::{ReplayEntry, ReplayEntry_Event, ReplayEntry_Event_Attribute,
+                       ReplayEntry_StateEntry, ReplayEntry_Status};
+
+use handler::SupplyChainTransactionHandler;
+
+/// The parts of a validator context the handler uses, so that it can be
+/// run against a recording or replaying context as well as a live one
+pub trait StateContext {
+    fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError>;
+
+    fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError>;
+
+    fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError>;
+
+    fn add_event(
+        &mut self,
+        event_type: String,
+        attributes: Vec<(String, String)>,
+        data: &[u8],
+    ) -> Result<(), ContextError>;
+
+    fn add_receipt_data(&mut self, data: &[u8]) -> Result<(), ContextError>;
+}
+
+impl StateContext for TransactionContext {
+    fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+        TransactionContext::get_state(self, addresses)
+    }
+
+    fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+        TransactionContext::set_state(self, entries)
+    }
+
+    fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+        TransactionContext::delete_state(self, addresses).map(|_| ())
+    }
+
+    fn add_event(
+        &mut self,
+        event_type: String,
+        attributes: Vec<(String, String)>,
+        data: &[u8],
+    ) -> Result<(), ContextError> {
+        TransactionContext::add_event(self, event_type, attributes, data)
+    }
+
+    fn add_receipt_data(&mut self, data: &[u8]) -> Result<(), ContextError> {
+        TransactionContext::add_receipt_data(self, data)
+    }
+}
+
+/// What a transaction asked the validator to change
+#[derive(Default)]
+struct Effects {
+    writes: BTreeMap<String, Vec<u8>>,
+    events: Vec<ReplayEntry_Event>,
+    receipts: Vec<Vec<u8>>,
+}
+
+impl Effects {
+    fn set_state(&mut self, entries: &HashMap<String, Vec<u8>>) {
+        for (address, data) in entries {
+            self.writes.insert(address.clone(), data.clone());
+        }
+    }
+
+    /// Records a deleted address as a write of no data, which reads the
+    /// same as an address nothing was ever stored at
+    fn delete_state(&mut self, addresses: &[String]) {
+        for address in addresses {
+            self.writes.insert(address.clone(), Vec::new());
+        }
+    }
+
+    fn add_event(&mut self, event_type: &str, attributes: &[(String, String)], data: &[u8]) {
+        let mut event = ReplayEntry_Event::new();
+        event.set_event_type(event_type.to_string());
+        event.set_attributes(RepeatedField::from_vec(
+            attributes
+                .iter()
+                .map(|&(ref key, ref value)| {
+                    let mut attribute = ReplayEntry_Event_Attribute::new();
+                    attribute.set_key(key.clone());
+                    attribute.set_value(value.clone());
+                    attribute
+                })
+                .collect(),
+        ));
+        event.set_data(data.to_vec());
+        self.events.push(event);
+    }
+
+    fn into_entry(self, result: &Result<(), ApplyError>) -> ReplayEntry {
+        let mut entry = ReplayEntry::new();
+        entry.set_writes(RepeatedField::from_vec(
+            self.writes
+                .into_iter()
+                .map(|(address, data)| make_state_entry(address, data))
+                .collect(),
+        ));
+        entry.set_events(RepeatedField::from_vec(self.events));
+        entry.set_receipts(RepeatedField::from_vec(self.receipts));
+        match *result {
+            Ok(()) => entry.set_status(ReplayEntry_Status::OK),
+            Err(ApplyError::InvalidTransaction(ref message)) => {
+                entry.set_status(ReplayEntry_Status::INVALID_TRANSACTION);
+                entry.set_message(message.clone());
+            }
+            Err(ApplyError::InternalError(ref message)) => {
+                entry.set_status(ReplayEntry_Status::INTERNAL_ERROR);
+                entry.set_message(message.clone());
+            }
+        }
+        entry
+    }
+}
+
+fn make_state_entry(address: String, data: Vec<u8>) -> ReplayEntry_StateEntry {
+    let mut entry = ReplayEntry_StateEntry::new();
+    entry.set_address(address);
+    entry.set_data(data);
+    entry
+}
+
+/// Passes everything through to another context, recording what it was
+/// asked for and what it returned
+pub struct RecordingContext<'a> {
+    context: &'a mut dyn StateContext,
+    reads: Vec<ReplayEntry_StateEntry>,
+    read_addresses: BTreeSet<String>,
+    effects: Effects,
+}
+
+impl<'a> RecordingContext<'a> {
+    pub fn new(context: &'a mut dyn StateContext) -> RecordingContext<'a> {
+        RecordingContext {
+            context: context,
+            reads: Vec::new(),
+            read_addresses: BTreeSet::new(),
+            effects: Effects::default(),
+        }
+    }
+
+    /// Builds the log entry for the transaction, once it has been applied
+    pub fn into_entry(
+        self,
+        request: &TpProcessRequest,
+        result: &Result<(), ApplyError>,
+    ) -> Result<ReplayEntry, String> {
+        let mut entry = self.effects.into_entry(result);
+        entry.set_request(request
+            .write_to_bytes()
+            .map_err(|err| format!("Cannot serialize request: {}", err))?);
+        entry.set_reads(RepeatedField::from_vec(self.reads));
+        Ok(entry)
+    }
+}
+
+impl<'a> StateContext for RecordingContext<'a> {
+    fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+        let address = addresses.first().cloned();
+        let data = self.context.get_state(addresses)?;
+
+        // Only reads of state as it was before the transaction are needed
+        // to replay it; later reads see the transaction's own writes
+        if let Some(address) = address {
+            if !self.effects.writes.contains_key(&address)
+                && self.read_addresses.insert(address.clone())
+            {
+                self.reads.push(make_state_entry(
+                    address,
+                    data.clone().unwrap_or_default(),
+                ));
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+        self.effects.set_state(&entries);
+        self.context.set_state(entries)
+    }
+
+    fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+        self.effects.delete_state(&addresses);
+        self.context.delete_state(addresses)
+    }
+
+    fn add_event(
+        &mut self,
+        event_type: String,
+        attributes: Vec<(String, String)>,
+        data: &[u8],
+    ) -> Result<(), ContextError> {
+        self.effects.add_event(&event_type, &attributes, data);
+        self.context.add_event(event_type, attributes, data)
+    }
+
+    fn add_receipt_data(&mut self, data: &[u8]) -> Result<(), ContextError> {
+        self.effects.receipts.push(data.to_vec());
+        self.context.add_receipt_data(data)
+    }
+}
+
+/// Serves reads from a log entry, and collects what the transaction
+/// writes instead of sending it anywhere
+struct ReplayContext {
+    recorded: HashMap<String, Vec<u8>>,
+    unrecorded: Vec<String>,
+    effects: Effects,
+}
+
+impl ReplayContext {
+    fn new(entry: &ReplayEntry) -> ReplayContext {
+        ReplayContext {
+            recorded: entry
+                .get_reads()
+                .iter()
+                .map(|read| (read.get_address().to_string(), read.get_data().to_vec()))
+                .collect(),
+            unrecorded: Vec::new(),
+            effects: Effects::default(),
+        }
+    }
+}
+
+impl StateContext for ReplayContext {
+    fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+        let address = match addresses.first() {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+
+        let data = match self.effects.writes.get(address) {
+            Some(data) => data,
+            None => match self.recorded.get(address) {
+                Some(data) => data,
+                None => {
+                    self.unrecorded.push(address.clone());
+                    return Err(ContextError::ResponseAttributeError(format!(
+                        "Address was not read when the transaction was recorded: {}",
+                        address
+                    )));
+                }
+            },
+        };
+
+        if data.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(data.clone()))
+        }
+    }
+
+    fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+        self.effects.set_state(&entries);
+        Ok(())
+    }
+
+    fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+        self.effects.delete_state(&addresses);
+        Ok(())
+    }
+
+    fn add_event(
+        &mut self,
+        event_type: String,
+        attributes: Vec<(String, String)>,
+        data: &[u8],
+    ) -> Result<(), ContextError> {
+        self.effects.add_event(&event_type, &attributes, data);
+        Ok(())
+    }
+
+    fn add_receipt_data(&mut self, data: &[u8]) -> Result<(), ContextError> {
+        self.effects.receipts.push(data.to_vec());
+        Ok(())
+    }
+}
+
+/// An append-only file of ReplayEntry messages, each prefixed with its
+/// length
+pub struct ReplayLog {
+    file: File,
+}
+
+impl ReplayLog {
+    pub fn open(path: &str) -> Result<ReplayLog, String> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map(|file| ReplayLog { file: file })
+            .map_err(|err| format!("Cannot open replay log {}: {}", path, err))
+    }
+
+    pub fn append(&mut self, entry: &ReplayEntry) -> Result<(), String> {
+        // Written in one call, so an entry is never split by another
+        let bytes = entry
+            .write_length_delimited_to_bytes()
+            .map_err(|err| format!("Cannot serialize replay entry: {}", err))?;
+        self.file
+            .write_all(&bytes)
+            .and_then(|_| self.file.flush())
+            .map_err(|err| format!("Cannot write replay log: {}", err))
+    }
+}
+
+/// Calls `f` with each entry of the log at `path`, in order
+pub fn read_log<F>(path: &str, mut f: F) -> Result<(), String>
+where
+    F: FnMut(ReplayEntry) -> Result<(), String>,
+{
+    let file = File::open(path).map_err(|err| format!("Cannot open {}: {}", path, err))?;
+    let mut reader = BufReader::new(file);
+    let mut input = CodedInputStream::from_buffered_reader(&mut reader);
+
+    while !input
+        .eof()
+        .map_err(|err| format!("Cannot read {}: {}", path, err))?
+    {
+        let entry = input
+            .read_message::<ReplayEntry>()
+            .map_err(|err| format!("Corrupt entry in {}: {}", path, err))?;
+        f(entry)?;
+    }
+    Ok(())
+}
+
+/// Re-applies a recorded transaction, returning a description of each way
+/// the outcome differs from the recorded one
+pub fn replay(
+    handler: &SupplyChainTransactionHandler,
+    entry: &ReplayEntry,
+) -> Result<Vec<String>, String> {
+    let request: TpProcessRequest = protobuf::parse_from_bytes(entry.get_request())
+        .map_err(|err| format!("Cannot parse recorded request: {}", err))?;
+
+    let mut context = ReplayContext::new(entry);
+    let result = handler.process(&request, &mut context);
+    let unrecorded = context.unrecorded.clone();
+    let outcome = context.effects.into_entry(&result);
+
+    let mut differences: Vec<String> = unrecorded
+        .into_iter()
+        .map(|address| format!("read unrecorded address {}", address))
+        .collect();
+    differences.extend(compare(entry, &outcome));
+    Ok(differences)
+}
+
+fn describe_outcome(entry: &ReplayEntry) -> String {
+    match entry.get_status() {
+        ReplayEntry_Status::OK => String::from("OK"),
+        ReplayEntry_Status::INVALID_TRANSACTION => {
+            format!("InvalidTransaction: {}", entry.get_message())
+        }
+        ReplayEntry_Status::INTERNAL_ERROR => format!("InternalError: {}", entry.get_message()),
+    }
+}
+
+fn compare(recorded: &ReplayEntry, replayed: &ReplayEntry) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    if recorded.get_status() != replayed.get_status()
+        || recorded.get_message() != replayed.get_message()
+    {
+        differences.push(format!(
+            "outcome was {}, now {}",
+            describe_outcome(recorded),
+            describe_outcome(replayed)
+        ));
+    }
+
+    // The validator discards everything a failed transaction did, so only
+    // the effects of applied transactions need to match
+    if recorded.get_status() != ReplayEntry_Status::OK
+        || replayed.get_status() != ReplayEntry_Status::OK
+    {
+        return differences;
+    }
+
+    let writes = |entry: &ReplayEntry| -> BTreeMap<String, Vec<u8>> {
+        entry
+            .get_writes()
+            .iter()
+            .map(|write| (write.get_address().to_string(), write.get_data().to_vec()))
+            .collect()
+    };
+    let before = writes(recorded);
+    let after = writes(replayed);
+
+    for (address, data) in &before {
+        match after.get(address) {
+            None => differences.push(format!("no longer writes {}", address)),
+            Some(replayed) if replayed != data => {
+                differences.push(format!("writes different data to {}", address))
+            }
+            Some(_) => (),
+        }
+    }
+    for address in after.keys().filter(|address| !before.contains_key(*address)) {
+        differences.push(format!("now also writes {}", address));
+    }
+
+    if recorded.get_events() != replayed.get_events() {
+        let types = |entry: &ReplayEntry| -> Vec<String> {
+            entry
+                .get_events()
+                .iter()
+                .map(|event| event.get_event_type().to_string())
+                .collect()
+        };
+        differences.push(format!(
+            "events were {:?}, now {:?}",
+            types(recorded),
+            types(replayed)
+        ));
+    }
+
+    if recorded.get_receipts() != replayed.get_receipts() {
+        differences.push(String::from("adds different receipt data"));
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use messages::payload;
+    use sawtooth_sdk::messages::transaction::TransactionHeader;
+    use std::env;
+    use std::fs;
+
+    #[derive(Default)]
+    struct MemoryContext {
+        state: HashMap<String, Vec<u8>>,
+    }
+
+    impl StateContext for MemoryContext {
+        fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+            Ok(addresses
+                .first()
+                .and_then(|address| self.state.get(address).cloned()))
+        }
+
+        fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+            self.state.extend(entries);
+            Ok(())
+        }
+
+        fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+            for address in addresses {
+                self.state.remove(&address);
+            }
+            Ok(())
+        }
+
+        fn add_event(
+            &mut self,
+            _event_type: String,
+            _attributes: Vec<(String, String)>,
+            _data: &[u8],
+        ) -> Result<(), ContextError> {
+            Ok(())
+        }
+
+        fn add_receipt_data(&mut self, _data: &[u8]) -> Result<(), ContextError> {
+            Ok(())
+        }
+    }
+
+    fn set(context: &mut dyn StateContext, address: &str, data: &[u8]) {
+        let mut entries = HashMap::new();
+        entries.insert(address.to_string(), data.to_vec());
+        context.set_state(entries).unwrap();
+    }
+
+    fn make_request(signer: &str, name: &str) -> TpProcessRequest {
+        let mut action = payload::CreateAgentAction::new();
+        action.set_name(name.to_string());
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::CREATE_AGENT);
+        payload.set_create_agent(action);
+        payload.set_timestamp(1000);
+
+        let mut header = TransactionHeader::new();
+        header.set_signer_public_key(signer.to_string());
+        header.set_inputs(RepeatedField::from_vec(vec![String::from("in")]));
+        header.set_outputs(RepeatedField::from_vec(vec![String::from("out")]));
+
+        let mut request = TpProcessRequest::new();
+        request.set_header(header);
+        request.set_payload(payload.write_to_bytes().unwrap());
+        request.set_signature(String::from("signature"));
+        request
+    }
+
+    fn record(
+        handler: &SupplyChainTransactionHandler,
+        context: &mut MemoryContext,
+        request: &TpProcessRequest,
+    ) -> ReplayEntry {
+        let mut recording = RecordingContext::new(context);
+        let result = handler.process(request, &mut recording);
+        recording.into_entry(request, &result).unwrap()
+    }
+
+    #[test]
+    fn records_state_as_it_was_before_the_transaction() {
+        let mut memory = MemoryContext::default();
+        set(&mut memory, "a", b"before");
+
+        let mut recording = RecordingContext::new(&mut memory);
+        recording.get_state(vec![String::from("a")]).unwrap();
+        set(&mut recording, "a", b"first");
+        set(&mut recording, "a", b"second");
+        recording.get_state(vec![String::from("a")]).unwrap();
+        recording.get_state(vec![String::from("b")]).unwrap();
+        set(&mut recording, "c", b"new");
+        recording.get_state(vec![String::from("c")]).unwrap();
+
+        let entry = recording
+            .into_entry(&TpProcessRequest::new(), &Ok(()))
+            .unwrap();
+        let reads: Vec<(&str, &[u8])> = entry
+            .get_reads()
+            .iter()
+            .map(|read| (read.get_address(), read.get_data()))
+            .collect();
+        assert_eq!(reads, vec![("a", &b"before"[..]), ("b", &b""[..])]);
+
+        let writes: Vec<(&str, &[u8])> = entry
+            .get_writes()
+            .iter()
+            .map(|write| (write.get_address(), write.get_data()))
+            .collect();
+        assert_eq!(writes, vec![("a", &b"second"[..]), ("c", &b"new"[..])]);
+    }
+
+    #[test]
+    fn replays_recorded_transactions_identically() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut memory = MemoryContext::default();
+
+        let created = record(&handler, &mut memory, &make_request("alice", "Alice"));
+        assert_eq!(created.get_status(), ReplayEntry_Status::OK);
+        assert_eq!(replay(&handler, &created), Ok(vec![]));
+
+        let duplicate = record(&handler, &mut memory, &make_request("alice", "Alice"));
+        assert_eq!(
+            duplicate.get_status(),
+            ReplayEntry_Status::INVALID_TRANSACTION
+        );
+        assert_eq!(replay(&handler, &duplicate), Ok(vec![]));
+    }
+
+    #[test]
+    fn reports_differences() {
+        let handler = SupplyChainTransactionHandler::new();
+        let mut memory = MemoryContext::default();
+        let entry = record(&handler, &mut memory, &make_request("alice", "Alice"));
+
+        let mut changed_write = entry.clone();
+        changed_write.mut_writes()[0].set_data(b"tampered".to_vec());
+        let differences = replay(&handler, &changed_write).unwrap();
+        assert_eq!(differences.len(), 1);
+        assert!(differences[0].starts_with("writes different data to "));
+
+        let mut changed_outcome = entry.clone();
+        changed_outcome.set_status(ReplayEntry_Status::INVALID_TRANSACTION);
+        changed_outcome.set_message(String::from("Agent already exists: Alice"));
+        assert_eq!(
+            replay(&handler, &changed_outcome),
+            Ok(vec![String::from(
+                "outcome was InvalidTransaction: Agent already exists: Alice, now OK"
+            )])
+        );
+
+        let mut missing_read = entry.clone();
+        missing_read.clear_reads();
+        let differences = replay(&handler, &missing_read).unwrap();
+        assert!(differences[0].starts_with("read unrecorded address "));
+        assert!(differences[1].starts_with("outcome was OK, now "));
+
+        let mut changed_receipt = entry.clone();
+        changed_receipt.mut_receipts()[0] = b"tampered".to_vec();
+        assert_eq!(
+            replay(&handler, &changed_receipt),
+            Ok(vec![String::from("adds different receipt data")])
+        );
+    }
+
+    #[test]
+    fn appends_and_reads_logs() {
+        let path = env::temp_dir().join(format!("supply-chain-replay-{}.log", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let handler = SupplyChainTransactionHandler::new();
+        let mut memory = MemoryContext::default();
+        let first = record(&handler, &mut memory, &make_request("alice", "Alice"));
+        let second = record(&handler, &mut memory, &make_request("bob", "Bob"));
+
+        ReplayLog::open(path).unwrap().append(&first).unwrap();
+        ReplayLog::open(path).unwrap().append(&second).unwrap();
+
+        let mut entries = Vec::new();
+        read_log(path, |entry| {
+            entries.push(entry);
+            Ok(())
+        }).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(entries, vec![first, second]);
+    }
+}
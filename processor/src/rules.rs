@@ -0,0 +1,485 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small expression language for rules carried by record types.
+//!
+//! Rules are evaluated by every validator, so the language is restricted to
+//! what can be computed deterministically:
+//!
+//! - values are 64-bit integers, strings, and booleans; there are no floats
+//! - arithmetic is checked, and overflow is an error rather than a wrap
+//! - the only inputs are the variables bound by the handler
+//! - there are no loops or function calls, and every evaluated node costs
+//!   one unit of gas, so evaluation always terminates within the gas limit
+//!
+//! Grammar, lowest precedence first:
+//!
+//! ```text
+//! or      := and ( "||" and )*
+//! and     := cmp ( "&&" cmp )*
+//! cmp     := sum ( ( "==" | "!=" | "<" | "<=" | ">" | ">=" ) sum )?
+//! sum     := unary ( ( "+" | "-" ) unary )*
+//! unary   := ( "!" | "-" ) unary | primary
+//! primary := integer | "string" | true | false | identifier | "(" or ")"
+//! ```
+//!
+//! A rule passes when it evaluates to `true`. Any error, including a type
+//! mismatch, an unbound variable, or running out of gas, fails the rule.
+
+use std::collections::{BTreeSet, HashMap};
+
+/// The longest rule source accepted
+pub const MAX_RULE_LENGTH: usize = 512;
+
+/// The gas available to a single rule evaluation
+pub const RULE_GAS_LIMIT: u32 = 1000;
+
+/// Bounds recursion in the parser so deeply nested input cannot overflow
+/// the stack
+const MAX_NESTING_DEPTH: u32 = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+}
+
+pub type Bindings = HashMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Var(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+const OPERATORS: [&str; 12] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "!", "=",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            let number = digits
+                .parse::<i64>()
+                .map_err(|_| format!("Integer literal out of range: {}", digits))?;
+            tokens.push(Token::Int(number));
+        } else if c == '"' {
+            let mut literal = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some(&'"') => break,
+                    Some(&'\\') => match chars.get(i + 1) {
+                        Some(&escaped) if escaped == '"' || escaped == '\\' => {
+                            literal.push(escaped);
+                            i += 2;
+                        }
+                        _ => return Err(String::from("Invalid escape in string literal")),
+                    },
+                    Some(&other) => {
+                        literal.push(other);
+                        i += 1;
+                    }
+                    None => return Err(String::from("Unterminated string literal")),
+                }
+            }
+            tokens.push(Token::Str(literal));
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            let op = match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(&"=") | None => return Err(format!("Unexpected character: {}", c)),
+                Some(op) => *op,
+            };
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: u32,
+}
+
+impl Parser {
+    fn peek_op(&self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(&Token::Op(op)) if ops.contains(&op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(String::from("Rule is nested too deeply"));
+        }
+        let mut left = self.parse_and()?;
+        while let Some(op) = self.peek_op(&["||"]) {
+            self.position += 1;
+            let right = self.parse_and()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        self.depth -= 1;
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_cmp()?;
+        while let Some(op) = self.peek_op(&["&&"]) {
+            self.position += 1;
+            let right = self.parse_cmp()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_cmp(&mut self) -> Result<Expr, String> {
+        let left = self.parse_sum()?;
+        match self.peek_op(&["==", "!=", "<", "<=", ">", ">="]) {
+            Some(op) => {
+                self.position += 1;
+                let right = self.parse_sum()?;
+                Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+            }
+            None => Ok(left),
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.peek_op(&["+", "-"]) {
+            self.position += 1;
+            let right = self.parse_unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek_op(&["!", "-"]) {
+            Some(op) => {
+                self.position += 1;
+                self.depth += 1;
+                if self.depth > MAX_NESTING_DEPTH {
+                    return Err(String::from("Rule is nested too deeply"));
+                }
+                let operand = self.parse_unary()?;
+                self.depth -= 1;
+                if op == "!" {
+                    Ok(Expr::Not(Box::new(operand)))
+                } else {
+                    Ok(Expr::Neg(Box::new(operand)))
+                }
+            }
+            None => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let token = match self.tokens.get(self.position) {
+            Some(token) => token.clone(),
+            None => return Err(String::from("Unexpected end of rule")),
+        };
+        self.position += 1;
+        match token {
+            Token::Int(number) => Ok(Expr::Literal(Value::Int(number))),
+            Token::Str(literal) => Ok(Expr::Literal(Value::Str(literal))),
+            Token::Ident(ref name) if name == "true" => Ok(Expr::Literal(Value::Bool(true))),
+            Token::Ident(ref name) if name == "false" => Ok(Expr::Literal(Value::Bool(false))),
+            Token::Ident(name) => Ok(Expr::Var(name)),
+            Token::LParen => {
+                let inner = self.parse_or()?;
+                match self.tokens.get(self.position) {
+                    Some(&Token::RParen) => {
+                        self.position += 1;
+                        Ok(inner)
+                    }
+                    _ => Err(String::from("Expected closing parenthesis")),
+                }
+            }
+            other => Err(format!("Unexpected token: {:?}", other)),
+        }
+    }
+}
+
+/// Parses a rule, checking only its syntax
+pub fn parse(source: &str) -> Result<Expr, String> {
+    if source.len() > MAX_RULE_LENGTH {
+        return Err(format!(
+            "Rule is longer than {} characters",
+            MAX_RULE_LENGTH
+        ));
+    }
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        depth: 0,
+    };
+    let expr = parser.parse_or()?;
+    if parser.position != parser.tokens.len() {
+        return Err(String::from("Unexpected trailing input"));
+    }
+    Ok(expr)
+}
+
+fn collect_variables(expr: &Expr, variables: &mut BTreeSet<String>) {
+    match *expr {
+        Expr::Literal(_) => (),
+        Expr::Var(ref name) => {
+            variables.insert(name.clone());
+        }
+        Expr::Not(ref operand) | Expr::Neg(ref operand) => collect_variables(operand, variables),
+        Expr::Binary(_, ref left, ref right) => {
+            collect_variables(left, variables);
+            collect_variables(right, variables);
+        }
+    }
+}
+
+/// Lists the variables a rule refers to, so that the handler only looks
+/// up the bindings which need state to be read
+pub fn variables(source: &str) -> Result<BTreeSet<String>, String> {
+    let mut variables = BTreeSet::new();
+    collect_variables(&parse(source)?, &mut variables);
+    Ok(variables)
+}
+
+fn eval(expr: &Expr, bindings: &Bindings, gas: &mut u32) -> Result<Value, String> {
+    if *gas == 0 {
+        return Err(String::from("Rule ran out of gas"));
+    }
+    *gas -= 1;
+
+    match *expr {
+        Expr::Literal(ref value) => Ok(value.clone()),
+        Expr::Var(ref name) => match bindings.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(format!("Unbound variable: {}", name)),
+        },
+        Expr::Not(ref operand) => match eval(operand, bindings, gas)? {
+            Value::Bool(b) => Ok(Value::Bool(!b)),
+            other => Err(format!("Cannot negate {:?}", other)),
+        },
+        Expr::Neg(ref operand) => match eval(operand, bindings, gas)? {
+            Value::Int(n) => n
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| String::from("Integer overflow")),
+            other => Err(format!("Cannot negate {:?}", other)),
+        },
+        Expr::Binary(op, ref left, ref right) if op == "&&" || op == "||" => {
+            let left = match eval(left, bindings, gas)? {
+                Value::Bool(b) => b,
+                other => return Err(format!("Expected boolean, found {:?}", other)),
+            };
+            // Short-circuit so rules can guard variables that are only
+            // bound for some actions
+            if (op == "&&" && !left) || (op == "||" && left) {
+                return Ok(Value::Bool(left));
+            }
+            match eval(right, bindings, gas)? {
+                Value::Bool(b) => Ok(Value::Bool(b)),
+                other => Err(format!("Expected boolean, found {:?}", other)),
+            }
+        }
+        Expr::Binary(op, ref left, ref right) => {
+            let left = eval(left, bindings, gas)?;
+            let right = eval(right, bindings, gas)?;
+            match (op, left, right) {
+                ("==", l, r) => Ok(Value::Bool(l == r)),
+                ("!=", l, r) => Ok(Value::Bool(l != r)),
+                ("+", Value::Int(l), Value::Int(r)) => l
+                    .checked_add(r)
+                    .map(Value::Int)
+                    .ok_or_else(|| String::from("Integer overflow")),
+                ("-", Value::Int(l), Value::Int(r)) => l
+                    .checked_sub(r)
+                    .map(Value::Int)
+                    .ok_or_else(|| String::from("Integer overflow")),
+                (op, Value::Int(l), Value::Int(r)) => Ok(Value::Bool(compare(op, &l, &r))),
+                (op, Value::Str(l), Value::Str(r)) if op != "+" && op != "-" => {
+                    Ok(Value::Bool(compare(op, &l, &r)))
+                }
+                (op, l, r) => Err(format!("Cannot apply {} to {:?} and {:?}", op, l, r)),
+            }
+        }
+    }
+}
+
+fn compare<T: PartialOrd>(op: &str, left: &T, right: &T) -> bool {
+    match op {
+        "<" => left < right,
+        "<=" => left <= right,
+        ">" => left > right,
+        _ => left >= right,
+    }
+}
+
+/// Evaluates a rule against the given bindings, returning whether it
+/// passed or why it could not be evaluated
+pub fn evaluate(source: &str, bindings: &Bindings) -> Result<bool, String> {
+    let expr = parse(source)?;
+    let mut gas = RULE_GAS_LIMIT;
+    match eval(&expr, bindings, &mut gas)? {
+        Value::Bool(b) => Ok(b),
+        other => Err(format!("Rule must evaluate to a boolean, found {:?}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(pairs: &[(&str, Value)]) -> Bindings {
+        pairs
+            .iter()
+            .map(|&(ref name, ref value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn evaluates_comparisons_and_logic() {
+        let env = bindings(&[
+            ("action", Value::Str("update".into())),
+            ("value", Value::Int(-3)),
+        ]);
+        assert_eq!(evaluate("value < 0 && value >= -10", &env), Ok(true));
+        assert_eq!(evaluate("action == \"update\" && value > 0", &env), Ok(false));
+        assert_eq!(evaluate("!(value == -3) || 1 + 2 == 3", &env), Ok(true));
+        assert_eq!(evaluate("\"abc\" < \"abd\"", &env), Ok(true));
+    }
+
+    #[test]
+    fn short_circuits_unbound_variables() {
+        let env = bindings(&[("action", Value::Str("transfer".into()))]);
+        assert_eq!(
+            evaluate("action != \"update\" || value < 100", &env),
+            Ok(true)
+        );
+        assert!(evaluate("action == \"transfer\" && value < 100", &env).is_err());
+    }
+
+    #[test]
+    fn rejects_type_errors() {
+        let env = bindings(&[("value", Value::Int(1))]);
+        assert!(evaluate("value", &env).is_err());
+        assert!(evaluate("value && true", &env).is_err());
+        assert!(evaluate("value < \"1\"", &env).is_err());
+        assert!(evaluate("\"a\" + \"b\" == \"ab\"", &env).is_err());
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        let env = bindings(&[]);
+        assert!(evaluate("9223372036854775807 + 1 > 0", &env).is_err());
+        assert!(evaluate("99999999999999999999 > 0", &env).is_err());
+    }
+
+    #[test]
+    fn lists_variables() {
+        let names = variables("action != \"update\" || value == parent.origin && !final").unwrap();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        assert_eq!(names, vec!["action", "final", "parent.origin", "value"]);
+        assert!(variables("value <").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        assert!(parse("").is_err());
+        assert!(parse("value <").is_err());
+        assert!(parse("(value < 1").is_err());
+        assert!(parse("value = 1").is_err());
+        assert!(parse("value < 1 1").is_err());
+        assert!(parse("\"unterminated").is_err());
+        assert!(parse(&"x".repeat(MAX_RULE_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn bounds_nesting_depth() {
+        let nested = format!("{}true{}", "(".repeat(64), ")".repeat(64));
+        assert!(parse(&nested).is_err());
+        let negated = format!("{}true", "!".repeat(64));
+        assert!(parse(&negated).is_err());
+    }
+
+    #[test]
+    fn bounds_gas() {
+        let env = bindings(&[("x", Value::Int(1))]);
+        let expr = parse("x + x + x + x > 0").unwrap();
+
+        let mut gas = 3;
+        assert_eq!(
+            eval(&expr, &env, &mut gas),
+            Err(String::from("Rule ran out of gas"))
+        );
+
+        let mut gas = RULE_GAS_LIMIT;
+        assert_eq!(eval(&expr, &env, &mut gas), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn evaluation_is_repeatable() {
+        let env = bindings(&[("value", Value::Int(7)), ("owner", Value::Str("a".into()))]);
+        let rule = "value - 7 == 0 && owner != \"b\"";
+        let first = evaluate(rule, &env);
+        for _ in 0..10 {
+            assert_eq!(evaluate(rule, &env), first);
+        }
+    }
+}
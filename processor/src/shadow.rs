@@ -0,0 +1,311 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shadow-mode execution of a new handler build.
+//!
+//! The `shadow` subcommand follows the replay log of a processor which is
+//! serving a validator, started with `--replay-log`, and re-applies each
+//! transaction as it is appended with the handler this build was compiled
+//! with. Reads are served from the log, as when replaying, so the shadow
+//! never talks to the validator and cannot affect consensus. Each
+//! transaction whose outcome, writes, events or receipts differ from
+//! those of the serving processor is logged as a warning, and the number
+//! applied and diverged for each action is logged every
+//! `--metrics-interval` seconds.
+//!
+//! A new build can so be run against live traffic for as long as needed
+//! before it replaces the serving one.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ctrlc;
+use protobuf;
+
+use sawtooth_sdk::messages::processor::TpProcessRequest;
+
+use messages::replay::ReplayEntry;
+
+use handler::SupplyChainTransactionHandler;
+use replay;
+use workers;
+
+/// How long to wait for the log to grow before reading it again
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct ShadowConfig {
+    /// Whether to apply the transactions already in the log, rather than
+    /// only those appended after the shadow starts
+    pub from_start: bool,
+    /// How often to log metrics, or never if None
+    pub metrics_interval: Option<Duration>,
+}
+
+/// Reads entries from a replay log while it is being appended to
+pub struct LogFollower {
+    file: File,
+    /// Bytes read from the file which do not yet make a whole entry
+    pending: Vec<u8>,
+}
+
+impl LogFollower {
+    pub fn open(path: &str, from_start: bool) -> Result<LogFollower, String> {
+        let mut file = File::open(path).map_err(|err| format!("Cannot open {}: {}", path, err))?;
+        if !from_start {
+            file.seek(SeekFrom::End(0))
+                .map_err(|err| format!("Cannot seek to the end of {}: {}", path, err))?;
+        }
+        Ok(LogFollower {
+            file: file,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Returns the next entry, or None if it has not been completely
+    /// written yet
+    pub fn next_entry(&mut self) -> Result<Option<ReplayEntry>, String> {
+        self.file
+            .read_to_end(&mut self.pending)
+            .map_err(|err| format!("Cannot read replay log: {}", err))?;
+
+        let (length, prefix) = match read_length(&self.pending) {
+            Some(delimiter) => delimiter,
+            None => return Ok(None),
+        };
+        if self.pending.len() < prefix + length {
+            return Ok(None);
+        }
+
+        let entry = protobuf::parse_from_bytes(&self.pending[prefix..prefix + length])
+            .map_err(|err| format!("Corrupt entry in replay log: {}", err))?;
+        self.pending.drain(..prefix + length);
+        Ok(Some(entry))
+    }
+}
+
+/// Decodes the varint length at the start of `bytes`, returning it and
+/// the number of bytes it takes, or None if it is incomplete
+fn read_length(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut length = 0u64;
+    for (i, byte) in bytes.iter().take(10).enumerate() {
+        length |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((length as usize, i + 1));
+        }
+    }
+    None
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShadowStats {
+    pub applied: u64,
+    /// Transactions whose outcome differed from the serving processor's
+    pub diverged: u64,
+}
+
+/// Counts of the transactions shadowed, by action name
+#[derive(Default)]
+pub struct ShadowMetrics {
+    stats: BTreeMap<String, ShadowStats>,
+}
+
+impl ShadowMetrics {
+    pub fn record(&mut self, action: &str, diverged: bool) {
+        let entry = self
+            .stats
+            .entry(action.to_string())
+            .or_insert_with(Default::default);
+        entry.applied += 1;
+        if diverged {
+            entry.diverged += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, ShadowStats> {
+        self.stats.clone()
+    }
+
+    fn log(&self) {
+        for (action, stats) in &self.stats {
+            info!(
+                "{}: {} shadowed, {} diverged",
+                action, stats.applied, stats.diverged
+            );
+        }
+    }
+}
+
+/// Applies one logged transaction with the shadow handler, logging how
+/// it diverged, if it did
+pub fn shadow(
+    handler: &SupplyChainTransactionHandler,
+    entry: &ReplayEntry,
+    metrics: &mut ShadowMetrics,
+) {
+    let (action, signature) =
+        match protobuf::parse_from_bytes::<TpProcessRequest>(entry.get_request()) {
+            Ok(request) => (
+                workers::action_name(&request),
+                request.get_signature().to_string(),
+            ),
+            Err(_) => (String::from("UNKNOWN"), String::new()),
+        };
+
+    let differences = match replay::replay(handler, entry) {
+        Ok(differences) => differences,
+        Err(err) => vec![err],
+    };
+    if !differences.is_empty() {
+        warn!(
+            "{} transaction {} diverged: {}",
+            action,
+            signature,
+            differences.join("; ")
+        );
+    }
+    metrics.record(&action, !differences.is_empty());
+}
+
+/// Follows the replay log at `path`, shadowing each transaction appended
+/// to it, until interrupted
+pub fn run(
+    handler: &SupplyChainTransactionHandler,
+    path: &str,
+    config: &ShadowConfig,
+) -> Result<ShadowMetrics, String> {
+    let stopping = Arc::new(AtomicBool::new(false));
+    let interrupted = Arc::clone(&stopping);
+    ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))
+        .map_err(|err| format!("Error setting Ctrl-C handler: {}", err))?;
+
+    let mut follower = LogFollower::open(path, config.from_start)?;
+    let mut metrics = ShadowMetrics::default();
+    let mut last_report = Instant::now();
+    info!("Shadowing transactions in {}", path);
+
+    while !stopping.load(Ordering::SeqCst) {
+        match follower.next_entry()? {
+            Some(entry) => shadow(handler, &entry, &mut metrics),
+            None => thread::sleep(POLL_INTERVAL),
+        }
+
+        if let Some(interval) = config.metrics_interval {
+            if last_report.elapsed() >= interval {
+                metrics.log();
+                last_report = Instant::now();
+            }
+        }
+    }
+
+    metrics.log();
+    Ok(metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use messages::payload;
+    use messages::replay::ReplayEntry_Status;
+    use protobuf::{Message, RepeatedField};
+    use replay::ReplayLog;
+    use sawtooth_sdk::messages::transaction::TransactionHeader;
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    fn log_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!(
+            "supply-chain-shadow-{}-{}.log",
+            name,
+            ::std::process::id()
+        ));
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn make_entry(message: &str) -> ReplayEntry {
+        let mut entry = ReplayEntry::new();
+        entry.set_status(ReplayEntry_Status::INVALID_TRANSACTION);
+        entry.set_message(message.to_string());
+        entry
+    }
+
+    #[test]
+    fn follows_entries_as_they_are_written() {
+        let path = log_path("follow");
+        ReplayLog::open(&path).unwrap().append(&make_entry("before")).unwrap();
+
+        let mut follower = LogFollower::open(&path, false).unwrap();
+        assert_eq!(follower.next_entry().unwrap(), None);
+
+        // An entry is only returned once all of it has been written
+        let bytes = make_entry("after").write_length_delimited_to_bytes().unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&bytes[..3]).unwrap();
+        assert_eq!(follower.next_entry().unwrap(), None);
+        file.write_all(&bytes[3..]).unwrap();
+        assert_eq!(follower.next_entry().unwrap(), Some(make_entry("after")));
+        assert_eq!(follower.next_entry().unwrap(), None);
+
+        let mut from_start = LogFollower::open(&path, true).unwrap();
+        assert_eq!(from_start.next_entry().unwrap(), Some(make_entry("before")));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn counts_diverged_transactions() {
+        let mut action = payload::CreateAgentAction::new();
+        action.set_name(String::from("Alice"));
+        let mut payload = payload::SCPayload::new();
+        payload.set_action(payload::SCPayload_Action::CREATE_AGENT);
+        payload.set_create_agent(action);
+        payload.set_timestamp(1000);
+
+        let mut header = TransactionHeader::new();
+        header.set_signer_public_key(String::from("alice"));
+        header.set_inputs(RepeatedField::from_vec(vec![String::from("in")]));
+        header.set_outputs(RepeatedField::from_vec(vec![String::from("out")]));
+        let mut request = TpProcessRequest::new();
+        request.set_header(header);
+        request.set_payload(payload.write_to_bytes().unwrap());
+
+        // Recorded as applied without writing anything, which the handler
+        // does not agree with
+        let mut entry = ReplayEntry::new();
+        entry.set_request(request.write_to_bytes().unwrap());
+
+        let handler = SupplyChainTransactionHandler::new();
+        let mut metrics = ShadowMetrics::default();
+        shadow(&handler, &entry, &mut metrics);
+        let mut corrupt = make_entry("corrupt");
+        corrupt.set_request(vec![0xff]);
+        shadow(&handler, &corrupt, &mut metrics);
+
+        let stats = metrics.snapshot();
+        assert_eq!(
+            stats["CREATE_AGENT"],
+            ShadowStats {
+                applied: 1,
+                diverged: 1,
+            }
+        );
+        assert_eq!(stats["UNKNOWN"].diverged, 1);
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Staging of state changes until a sequence of actions has been checked.
+//!
+//! The actions of a composite payload are applied one after another, each
+//! seeing the state left by those before it. Their writes, events and
+//! receipt data are held back until the last of them has succeeded, and
+//! then sent to the validator together, so a composite that fails part
+//! way through never sends anything. Deletions are staged as writes of
+//! nothing, and sent as deletions.
+//!
+//! Every transaction is staged the same way, so that an address written
+//! several times is sent once, and an address read by several checks and
+//! actions is only read from the validator the first time.
+
+use std::collections::HashMap;
+
+use sawtooth_sdk::processor::handler::ContextError;
+
+use replay::StateContext;
+
+/// An event's type, attributes and data
+type Event = (String, Vec<(String, String)>, Vec<u8>);
+
+/// Holds writes, events and receipt data in memory, serving reads of
+/// staged addresses from them, until they are committed to the
+/// underlying context
+pub struct StagedContext<'a> {
+    context: &'a mut dyn StateContext,
+    // None for an address which is to be deleted
+    writes: HashMap<String, Option<Vec<u8>>>,
+    // What the underlying context returned for each address read, with
+    // None for an address nothing is stored at
+    reads: HashMap<String, Option<Vec<u8>>>,
+    events: Vec<Event>,
+    receipts: Vec<Vec<u8>>,
+}
+
+impl<'a> StagedContext<'a> {
+    pub fn new(context: &'a mut dyn StateContext) -> StagedContext<'a> {
+        StagedContext {
+            context: context,
+            writes: HashMap::new(),
+            reads: HashMap::new(),
+            events: Vec::new(),
+            receipts: Vec::new(),
+        }
+    }
+
+    /// Sends the staged writes, as a single set, and deletions, as a
+    /// single delete, and then the staged events and receipt data in the
+    /// order they were added
+    pub fn commit(self) -> Result<(), ContextError> {
+        let mut sets = HashMap::new();
+        let mut deletes = Vec::new();
+        for (address, data) in self.writes {
+            match data {
+                Some(data) => {
+                    sets.insert(address, data);
+                }
+                None => deletes.push(address),
+            }
+        }
+        if !sets.is_empty() {
+            self.context.set_state(sets)?;
+        }
+        if !deletes.is_empty() {
+            deletes.sort();
+            self.context.delete_state(deletes)?;
+        }
+        for (event_type, attributes, data) in self.events {
+            self.context.add_event(event_type, attributes, &data)?;
+        }
+        for data in self.receipts {
+            self.context.add_receipt_data(&data)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> StateContext for StagedContext<'a> {
+    fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+        let address = match addresses.first() {
+            Some(address) => address.clone(),
+            None => return self.context.get_state(addresses),
+        };
+        if let Some(data) = self.writes.get(&address).or_else(|| self.reads.get(&address)) {
+            return Ok(data.clone());
+        }
+        let data = self.context.get_state(addresses)?;
+        self.reads.insert(address, data.clone());
+        Ok(data)
+    }
+
+    fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+        self.writes
+            .extend(entries.into_iter().map(|(address, data)| (address, Some(data))));
+        Ok(())
+    }
+
+    fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+        self.writes
+            .extend(addresses.into_iter().map(|address| (address, None)));
+        Ok(())
+    }
+
+    fn add_event(
+        &mut self,
+        event_type: String,
+        attributes: Vec<(String, String)>,
+        data: &[u8],
+    ) -> Result<(), ContextError> {
+        self.events.push((event_type, attributes, data.to_vec()));
+        Ok(())
+    }
+
+    fn add_receipt_data(&mut self, data: &[u8]) -> Result<(), ContextError> {
+        self.receipts.push(data.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryContext {
+        state: HashMap<String, Vec<u8>>,
+        get_calls: usize,
+        set_calls: usize,
+        deleted: Vec<String>,
+        events: Vec<String>,
+        receipts: Vec<Vec<u8>>,
+    }
+
+    impl StateContext for MemoryContext {
+        fn get_state(&mut self, addresses: Vec<String>) -> Result<Option<Vec<u8>>, ContextError> {
+            self.get_calls += 1;
+            Ok(addresses
+                .first()
+                .and_then(|address| self.state.get(address).cloned()))
+        }
+
+        fn set_state(&mut self, entries: HashMap<String, Vec<u8>>) -> Result<(), ContextError> {
+            self.set_calls += 1;
+            self.state.extend(entries);
+            Ok(())
+        }
+
+        fn delete_state(&mut self, addresses: Vec<String>) -> Result<(), ContextError> {
+            for address in addresses {
+                self.state.remove(&address);
+                self.deleted.push(address);
+            }
+            Ok(())
+        }
+
+        fn add_event(
+            &mut self,
+            event_type: String,
+            _attributes: Vec<(String, String)>,
+            _data: &[u8],
+        ) -> Result<(), ContextError> {
+            self.events.push(event_type);
+            Ok(())
+        }
+
+        fn add_receipt_data(&mut self, data: &[u8]) -> Result<(), ContextError> {
+            self.receipts.push(data.to_vec());
+            Ok(())
+        }
+    }
+
+    fn set(context: &mut dyn StateContext, address: &str, data: &[u8]) {
+        let mut entries = HashMap::new();
+        entries.insert(address.to_string(), data.to_vec());
+        context.set_state(entries).unwrap();
+    }
+
+    fn get(context: &mut dyn StateContext, address: &str) -> Option<Vec<u8>> {
+        context.get_state(vec![address.to_string()]).unwrap()
+    }
+
+    #[test]
+    fn reads_see_staged_writes() {
+        let mut memory = MemoryContext::default();
+        set(&mut memory, "a", b"before");
+        memory.set_calls = 0;
+
+        let mut staged = StagedContext::new(&mut memory);
+        assert_eq!(get(&mut staged, "a"), Some(b"before".to_vec()));
+        set(&mut staged, "a", b"after");
+        set(&mut staged, "b", b"new");
+        assert_eq!(get(&mut staged, "a"), Some(b"after".to_vec()));
+        assert_eq!(get(&mut staged, "b"), Some(b"new".to_vec()));
+        assert_eq!(get(&mut staged, "c"), None);
+    }
+
+    #[test]
+    fn nothing_is_sent_until_commit() {
+        let mut memory = MemoryContext::default();
+        {
+            let mut staged = StagedContext::new(&mut memory);
+            set(&mut staged, "a", b"1");
+            staged.add_event(String::from("created"), vec![], b"").unwrap();
+            staged.add_receipt_data(b"touched").unwrap();
+        }
+        assert!(memory.state.is_empty());
+        assert!(memory.events.is_empty());
+        assert!(memory.receipts.is_empty());
+
+        {
+            let mut staged = StagedContext::new(&mut memory);
+            set(&mut staged, "a", b"1");
+            set(&mut staged, "a", b"2");
+            set(&mut staged, "b", b"3");
+            staged.add_event(String::from("first"), vec![], b"").unwrap();
+            staged.add_event(String::from("second"), vec![], b"").unwrap();
+            staged.add_receipt_data(b"touched").unwrap();
+            staged.commit().unwrap();
+        }
+        assert_eq!(memory.set_calls, 1);
+        assert_eq!(memory.state.get("a"), Some(&b"2".to_vec()));
+        assert_eq!(memory.state.get("b"), Some(&b"3".to_vec()));
+        assert_eq!(memory.events, vec!["first", "second"]);
+        assert_eq!(memory.receipts, vec![b"touched".to_vec()]);
+    }
+
+    #[test]
+    fn deletions_are_staged_like_writes() {
+        let mut memory = MemoryContext::default();
+        set(&mut memory, "a", b"1");
+        set(&mut memory, "b", b"2");
+        memory.set_calls = 0;
+
+        {
+            let mut staged = StagedContext::new(&mut memory);
+            staged.delete_state(vec![String::from("a")]).unwrap();
+            assert_eq!(get(&mut staged, "a"), None);
+            // A write after a deletion stores the address again
+            staged.delete_state(vec![String::from("b")]).unwrap();
+            set(&mut staged, "b", b"3");
+            assert_eq!(get(&mut staged, "b"), Some(b"3".to_vec()));
+        }
+        assert_eq!(memory.state.get("a"), Some(&b"1".to_vec()));
+
+        {
+            let mut staged = StagedContext::new(&mut memory);
+            staged.delete_state(vec![String::from("a")]).unwrap();
+            set(&mut staged, "b", b"3");
+            staged.commit().unwrap();
+        }
+        assert_eq!(memory.set_calls, 1);
+        assert_eq!(memory.deleted, vec!["a"]);
+        assert_eq!(memory.state.get("a"), None);
+        assert_eq!(memory.state.get("b"), Some(&b"3".to_vec()));
+    }
+
+    #[test]
+    fn addresses_are_read_once() {
+        let mut memory = MemoryContext::default();
+        set(&mut memory, "a", b"1");
+
+        {
+            let mut staged = StagedContext::new(&mut memory);
+            assert_eq!(get(&mut staged, "a"), Some(b"1".to_vec()));
+            assert_eq!(get(&mut staged, "b"), None);
+            assert_eq!(get(&mut staged, "a"), Some(b"1".to_vec()));
+            assert_eq!(get(&mut staged, "b"), None);
+        }
+        assert_eq!(memory.get_calls, 2);
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversions between well-known units, so that NUMBER values reported
+//! in one unit can be stored in the unit of their property's schema.
+//!
+//! Only units of the same quantity convert into each other:
+//!
+//! - mass: `kg`, `g`, `lb`, and `oz`
+//! - temperature: `C`, `F`, and `K`
+//!
+//! Conversions are exact until the result is rounded, half away from
+//! zero, to the schema's exponent.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quantity {
+    Mass,
+    Temperature,
+}
+
+/// A unit, defined by how its values convert to the base unit of its
+/// quantity: base = value * scale + offset, each a fraction
+struct Unit {
+    name: &'static str,
+    quantity: Quantity,
+    scale: (i128, i128),
+    offset: (i128, i128),
+}
+
+const UNITS: [Unit; 7] = [
+    Unit { name: "kg", quantity: Quantity::Mass, scale: (1, 1), offset: (0, 1) },
+    Unit { name: "g", quantity: Quantity::Mass, scale: (1, 1000), offset: (0, 1) },
+    Unit {
+        name: "lb",
+        quantity: Quantity::Mass,
+        scale: (45_359_237, 100_000_000),
+        offset: (0, 1),
+    },
+    Unit {
+        name: "oz",
+        quantity: Quantity::Mass,
+        scale: (45_359_237, 1_600_000_000),
+        offset: (0, 1),
+    },
+    Unit { name: "C", quantity: Quantity::Temperature, scale: (1, 1), offset: (0, 1) },
+    Unit { name: "F", quantity: Quantity::Temperature, scale: (5, 9), offset: (-160, 9) },
+    Unit { name: "K", quantity: Quantity::Temperature, scale: (1, 1), offset: (-27_315, 100) },
+];
+
+fn find(name: &str) -> Result<&'static Unit, String> {
+    UNITS
+        .iter()
+        .find(|unit| unit.name == name)
+        .ok_or_else(|| format!("Unknown unit: {}", name))
+}
+
+/// An exact fraction, kept in lowest terms with a positive denominator
+#[derive(Debug, Clone, Copy)]
+struct Fraction(i128, i128);
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Fraction {
+    fn new(num: i128, den: i128) -> Fraction {
+        let divisor = gcd(num, den).max(1) * den.signum();
+        Fraction(num / divisor, den / divisor)
+    }
+
+    fn add(self, other: Fraction) -> Option<Fraction> {
+        let num = self.0.checked_mul(other.1)?.checked_add(other.0.checked_mul(self.1)?)?;
+        Some(Fraction::new(num, self.1.checked_mul(other.1)?))
+    }
+
+    fn sub(self, other: Fraction) -> Option<Fraction> {
+        self.add(Fraction(-other.0, other.1))
+    }
+
+    fn mul(self, other: Fraction) -> Option<Fraction> {
+        Some(Fraction::new(
+            self.0.checked_mul(other.0)?,
+            self.1.checked_mul(other.1)?,
+        ))
+    }
+
+    fn div(self, other: Fraction) -> Option<Fraction> {
+        self.mul(Fraction::new(other.1, other.0))
+    }
+
+    fn round(self) -> i128 {
+        let half = self.1 / 2;
+        if self.0 >= 0 {
+            (self.0 + half) / self.1
+        } else {
+            (self.0 - half) / self.1
+        }
+    }
+}
+
+/// Converts a NUMBER value, stored as an integer scaled by 10^`exponent`,
+/// from one unit to another, keeping the same exponent
+pub fn convert(value: i64, exponent: i32, from: &str, to: &str) -> Result<i64, String> {
+    if from == to {
+        return Ok(value);
+    }
+    let from_unit = find(from)?;
+    let to_unit = find(to)?;
+    if from_unit.quantity != to_unit.quantity {
+        return Err(format!("Unit {} cannot be converted to {}", from, to));
+    }
+
+    // Offsets are in whole units, so are scaled to the exponent first
+    let per_step = 10i128.checked_pow(exponent.unsigned_abs()).map(|power| {
+        if exponent >= 0 {
+            Fraction(power, 1)
+        } else {
+            Fraction(1, power)
+        }
+    });
+    let offset = per_step.and_then(|per_step| {
+        Fraction::new(from_unit.offset.0, from_unit.offset.1)
+            .sub(Fraction::new(to_unit.offset.0, to_unit.offset.1))?
+            .div(per_step)
+    });
+    let converted = offset
+        .and_then(|offset| {
+            Fraction(i128::from(value), 1)
+                .mul(Fraction::new(from_unit.scale.0, from_unit.scale.1))?
+                .add(offset)
+        })
+        .and_then(|base| base.div(Fraction::new(to_unit.scale.0, to_unit.scale.1)))
+        .map(Fraction::round);
+
+    match converted {
+        Some(converted) if converted >= i128::from(i64::MIN)
+            && converted <= i128::from(i64::MAX) =>
+        {
+            Ok(converted as i64)
+        }
+        _ => Err(format!("Value {} {} is too large to convert to {}", value, from, to)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_mass() {
+        // 1 lb is 0.45359237 kg, and 16 oz
+        assert_eq!(convert(100_000_000, -8, "lb", "kg"), Ok(45_359_237));
+        assert_eq!(convert(1000, -3, "lb", "kg"), Ok(454));
+        assert_eq!(convert(1, 0, "lb", "oz"), Ok(16));
+        assert_eq!(convert(2500, 0, "g", "kg"), Ok(3));
+        assert_eq!(convert(-2500, 0, "g", "kg"), Ok(-3));
+        assert_eq!(convert(5, 3, "kg", "g"), Ok(5000));
+    }
+
+    #[test]
+    fn converts_temperature() {
+        assert_eq!(convert(212, 0, "F", "C"), Ok(100));
+        assert_eq!(convert(-40, 0, "C", "F"), Ok(-40));
+        assert_eq!(convert(410, -1, "F", "C"), Ok(50));
+        assert_eq!(convert(0, -2, "C", "K"), Ok(27_315));
+        assert_eq!(convert(0, 0, "K", "F"), Ok(-460));
+    }
+
+    #[test]
+    fn rejects_unknown_and_mismatched_units() {
+        assert_eq!(convert(7, 0, "furlong", "furlong"), Ok(7));
+        assert!(convert(7, 0, "furlong", "kg").is_err());
+        assert!(convert(7, 0, "kg", "C").is_err());
+        assert!(convert(i64::MAX, 0, "kg", "g").is_err());
+    }
+}
@@ -0,0 +1,628 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed access to property values.
+//!
+//! Values are stored in type-specific protobuf fields, and reading one
+//! correctly also needs details kept on its Property or PropertySchema:
+//! the number exponent, the enum options a reported index refers to, the
+//! schemas of struct fields, and the unit. The functions here combine the
+//! two into a `Value`, so that the rules for interpreting a value live in
+//! one place.
+//!
+//! Numbers keep their exact integer alongside the exponent. Validation
+//! must be deterministic, so the handler only ever looks at the integer;
+//! `Number::to_f64` and the `Display` impls are for presenting values.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use protobuf::RepeatedField;
+
+use messages::*;
+use messages::property::PropertySchema_DataType as DataType;
+
+/// Location coordinates are stored in millionths of a degree
+const LOCATION_EXPONENT: i32 = -6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bytes(Vec<u8>),
+    Boolean(bool),
+    Number(Number),
+    String(String),
+    Enum(String),
+    Struct(BTreeMap<String, Value>),
+    Location { latitude: f64, longitude: f64 },
+    Timestamp(u64),
+    RecordReference(String),
+    Array(Vec<Value>),
+    Identifier(String),
+}
+
+/// A NUMBER value: `value * 10^exponent`, measured in `unit`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Number {
+    pub value: i64,
+    pub exponent: i32,
+    pub unit: String,
+}
+
+impl Number {
+    /// The number with its exponent applied. This may lose precision, so
+    /// it must not be used when validating transactions.
+    #[allow(dead_code)]
+    pub fn to_f64(&self) -> f64 {
+        self.value as f64 * 10f64.powi(self.exponent)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_decimal(self.value, self.exponent))?;
+        if !self.unit.is_empty() {
+            write!(f, " {}", self.unit)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Bytes(ref bytes) => {
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            Value::Boolean(boolean) => write!(f, "{}", boolean),
+            Value::Number(ref number) => write!(f, "{}", number),
+            Value::String(ref string)
+            | Value::Enum(ref string)
+            | Value::RecordReference(ref string)
+            | Value::Identifier(ref string) => write!(f, "{}", string),
+            Value::Struct(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Array(ref elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Location {
+                latitude,
+                longitude,
+            } => write!(f, "{}, {}", latitude, longitude),
+            Value::Timestamp(timestamp) => write!(f, "{}", timestamp),
+        }
+    }
+}
+
+/// The parts of a Property or PropertySchema needed to read its values
+pub trait ValueSchema {
+    fn data_type(&self) -> DataType;
+    fn number_exponent(&self) -> i32;
+    fn enum_options(&self) -> &[String];
+    fn struct_properties(&self) -> &[property::PropertySchema];
+    fn element_schema(&self) -> Option<&property::PropertySchema>;
+    fn unit(&self) -> &str;
+}
+
+impl ValueSchema for property::Property {
+    fn data_type(&self) -> DataType {
+        self.get_data_type()
+    }
+    fn number_exponent(&self) -> i32 {
+        self.get_number_exponent()
+    }
+    fn enum_options(&self) -> &[String] {
+        self.get_enum_options()
+    }
+    fn struct_properties(&self) -> &[property::PropertySchema] {
+        self.get_struct_properties()
+    }
+    // Properties do not keep their element schema; arrays are read with
+    // the PropertySchema
+    fn element_schema(&self) -> Option<&property::PropertySchema> {
+        None
+    }
+    fn unit(&self) -> &str {
+        self.get_unit()
+    }
+}
+
+impl ValueSchema for property::PropertySchema {
+    fn data_type(&self) -> DataType {
+        self.get_data_type()
+    }
+    fn number_exponent(&self) -> i32 {
+        self.get_number_exponent()
+    }
+    fn enum_options(&self) -> &[String] {
+        self.get_enum_options()
+    }
+    fn struct_properties(&self) -> &[property::PropertySchema] {
+        self.get_struct_properties()
+    }
+    fn element_schema(&self) -> Option<&property::PropertySchema> {
+        if self.has_element_schema() {
+            Some(self.get_element_schema())
+        } else {
+            None
+        }
+    }
+    fn unit(&self) -> &str {
+        self.get_unit()
+    }
+}
+
+/// Reads a value sent in a payload, or a field of a struct value.
+/// Enum values are taken as given; checking them against the schema's
+/// options is left to validation.
+pub fn from_property_value<S: ValueSchema>(
+    schema: &S,
+    value: &property::PropertyValue,
+) -> Result<Value, String> {
+    match schema.data_type() {
+        DataType::ENUM => Ok(Value::Enum(value.get_enum_value().to_string())),
+        DataType::STRUCT => Ok(Value::Struct(read_struct(
+            schema.struct_properties(),
+            value.get_struct_values(),
+        )?)),
+        DataType::TIMESTAMP => Ok(Value::Timestamp(value.get_timestamp_value())),
+        DataType::RECORD_REFERENCE => Ok(Value::RecordReference(
+            value.get_record_reference_value().to_string(),
+        )),
+        DataType::ARRAY => Ok(Value::Array(read_array(schema, value.get_array_values())?)),
+        DataType::IDENTIFIER => Ok(Value::Identifier(value.get_identifier_value().to_string())),
+        data_type => Ok(read_scalar(
+            schema,
+            data_type,
+            value.get_bytes_value(),
+            value.get_boolean_value(),
+            value.get_number_value(),
+            value.get_string_value(),
+            value.get_location_value(),
+        )),
+    }
+}
+
+/// Reads a value from a PropertyPage, resolving its enum index to the
+/// option's name
+pub fn from_reported_value<S: ValueSchema>(
+    schema: &S,
+    value: &property::PropertyPage_ReportedValue,
+) -> Result<Value, String> {
+    match schema.data_type() {
+        DataType::ENUM => {
+            let index = value.get_enum_value() as usize;
+            match schema.enum_options().get(index) {
+                Some(option) => Ok(Value::Enum(option.to_string())),
+                None => Err(format!("Enum index out of range: {}", index)),
+            }
+        }
+        DataType::STRUCT => Ok(Value::Struct(read_struct(
+            schema.struct_properties(),
+            value.get_struct_values(),
+        )?)),
+        DataType::TIMESTAMP => Ok(Value::Timestamp(value.get_timestamp_value())),
+        DataType::RECORD_REFERENCE => Ok(Value::RecordReference(
+            value.get_record_reference_value().to_string(),
+        )),
+        DataType::ARRAY => Ok(Value::Array(read_array(schema, value.get_array_values())?)),
+        DataType::IDENTIFIER => Ok(Value::Identifier(value.get_identifier_value().to_string())),
+        data_type => Ok(read_scalar(
+            schema,
+            data_type,
+            value.get_bytes_value(),
+            value.get_boolean_value(),
+            value.get_number_value(),
+            value.get_string_value(),
+            value.get_location_value(),
+        )),
+    }
+}
+
+/// Turns a value from a PropertyPage back into one that could be sent in
+/// a payload, naming its enum option rather than its index
+pub fn to_property_value(
+    schema: &property::PropertySchema,
+    value: &property::PropertyPage_ReportedValue,
+) -> Result<property::PropertyValue, String> {
+    let mut property_value = property::PropertyValue::new();
+    property_value.set_name(schema.get_name().to_string());
+    property_value.set_data_type(schema.get_data_type());
+    match schema.get_data_type() {
+        DataType::BYTES => property_value.set_bytes_value(value.get_bytes_value().to_vec()),
+        DataType::BOOLEAN => property_value.set_boolean_value(value.get_boolean_value()),
+        DataType::NUMBER => property_value.set_number_value(value.get_number_value()),
+        DataType::STRING => property_value.set_string_value(value.get_string_value().to_string()),
+        DataType::ENUM => {
+            let index = value.get_enum_value() as usize;
+            match schema.get_enum_options().get(index) {
+                Some(option) => property_value.set_enum_value(option.to_string()),
+                None => return Err(format!("Enum index out of range: {}", index)),
+            }
+        }
+        DataType::STRUCT => property_value
+            .set_struct_values(RepeatedField::from_vec(value.get_struct_values().to_vec())),
+        DataType::LOCATION => property_value.set_location_value(value.get_location_value().clone()),
+        DataType::TIMESTAMP => property_value.set_timestamp_value(value.get_timestamp_value()),
+        DataType::RECORD_REFERENCE => property_value
+            .set_record_reference_value(value.get_record_reference_value().to_string()),
+        DataType::ARRAY => property_value
+            .set_array_values(RepeatedField::from_vec(value.get_array_values().to_vec())),
+        DataType::IDENTIFIER => {
+            property_value.set_identifier_value(value.get_identifier_value().to_string())
+        }
+        DataType::TYPE_UNSET => (),
+    }
+    Ok(property_value)
+}
+
+fn read_scalar<S: ValueSchema>(
+    schema: &S,
+    data_type: DataType,
+    bytes: &[u8],
+    boolean: bool,
+    number: i64,
+    string: &str,
+    location: &property::Location,
+) -> Value {
+    match data_type {
+        DataType::BOOLEAN => Value::Boolean(boolean),
+        DataType::NUMBER => Value::Number(Number {
+            value: number,
+            exponent: schema.number_exponent(),
+            unit: schema.unit().to_string(),
+        }),
+        DataType::STRING => Value::String(string.to_string()),
+        DataType::LOCATION => Value::Location {
+            latitude: location.get_latitude() as f64 * 10f64.powi(LOCATION_EXPONENT),
+            longitude: location.get_longitude() as f64 * 10f64.powi(LOCATION_EXPONENT),
+        },
+        // Unknown types are read as bytes, as the REST API does
+        _ => Value::Bytes(bytes.to_vec()),
+    }
+}
+
+fn read_array<S: ValueSchema>(
+    schema: &S,
+    values: &[property::PropertyValue],
+) -> Result<Vec<Value>, String> {
+    let element = match schema.element_schema() {
+        Some(element) => element,
+        None => return Err(String::from("Array has no element schema")),
+    };
+    values
+        .iter()
+        .map(|value| from_property_value(element, value))
+        .collect()
+}
+
+fn read_struct(
+    schemas: &[property::PropertySchema],
+    values: &[property::PropertyValue],
+) -> Result<BTreeMap<String, Value>, String> {
+    let mut fields = BTreeMap::new();
+    for value in values {
+        let schema = match schemas.iter().find(|s| s.get_name() == value.get_name()) {
+            Some(schema) => schema,
+            None => return Err(format!("Struct has unknown field: {}", value.get_name())),
+        };
+        fields.insert(
+            value.get_name().to_string(),
+            from_property_value(schema, value)?,
+        );
+    }
+    Ok(fields)
+}
+
+/// Merges a partial struct value into a complete one. `fields` names the
+/// fields `update` replaces, with a field of a nested struct named by its
+/// path joined with periods; every other field keeps its value from
+/// `base`. The merged struct still has to be validated against its schema.
+pub fn merge_struct(
+    base: &[property::PropertyValue],
+    update: &[property::PropertyValue],
+    fields: &[String],
+) -> Result<Vec<property::PropertyValue>, String> {
+    // Each masked field, with the paths masked within it, where `None`
+    // means the whole field is replaced
+    let mut masked: BTreeMap<&str, Vec<Option<&str>>> = BTreeMap::new();
+    for field in fields {
+        let mut parts = field.splitn(2, '.');
+        let name = parts.next().unwrap_or("");
+        if name.is_empty() {
+            return Err(format!("Update mask has an empty field name: {}", field));
+        }
+        masked.entry(name).or_default().push(parts.next());
+    }
+
+    if let Some(value) = update.iter().find(|v| !masked.contains_key(v.get_name())) {
+        return Err(format!(
+            "Struct field is not in the update mask: {}",
+            value.get_name()
+        ));
+    }
+
+    let mut merged = base.to_vec();
+    for (name, paths) in masked {
+        let value = match update.iter().find(|v| v.get_name() == name) {
+            Some(value) => value,
+            None => return Err(format!("Struct update is missing masked field: {}", name)),
+        };
+        let position = merged.iter().position(|v| v.get_name() == name);
+
+        let new_value = if paths.contains(&None) {
+            value.clone()
+        } else {
+            let mut nested = match position {
+                Some(index) => merged[index].clone(),
+                None => return Err(format!("Struct has no field to update: {}", name)),
+            };
+            let nested_fields: Vec<String> = paths
+                .iter()
+                .filter_map(|path| path.map(String::from))
+                .collect();
+            let nested_values = merge_struct(
+                nested.get_struct_values(),
+                value.get_struct_values(),
+                &nested_fields,
+            )?;
+            nested.set_struct_values(RepeatedField::from_vec(nested_values));
+            nested
+        };
+
+        match position {
+            Some(index) => merged[index] = new_value,
+            None => merged.push(new_value),
+        }
+    }
+    Ok(merged)
+}
+
+/// Writes `value * 10^exponent` exactly, without going through a float
+fn format_decimal(value: i64, exponent: i32) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let mut digits = value.unsigned_abs().to_string();
+    if exponent >= 0 {
+        if value != 0 {
+            digits.extend((0..exponent).map(|_| '0'));
+        }
+        return format!("{}{}", sign, digits);
+    }
+
+    let places = (-exponent) as usize;
+    if digits.len() <= places {
+        let padding: String = (0..places + 1 - digits.len()).map(|_| '0').collect();
+        digits = padding + &digits;
+    }
+    let point = digits.len() - places;
+    format!("{}{}.{}", sign, &digits[..point], &digits[point..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protobuf::RepeatedField;
+
+    fn make_schema(name: &str, data_type: DataType) -> property::PropertySchema {
+        let mut schema = property::PropertySchema::new();
+        schema.set_name(name.to_string());
+        schema.set_data_type(data_type);
+        schema
+    }
+
+    fn make_number(name: &str, number: i64) -> property::PropertyValue {
+        let mut value = property::PropertyValue::new();
+        value.set_name(name.to_string());
+        value.set_data_type(DataType::NUMBER);
+        value.set_number_value(number);
+        value
+    }
+
+    #[test]
+    fn formats_numbers_exactly() {
+        assert_eq!(format_decimal(1234, -2), "12.34");
+        assert_eq!(format_decimal(-5, -3), "-0.005");
+        assert_eq!(format_decimal(7, 3), "7000");
+        assert_eq!(format_decimal(0, 2), "0");
+        assert_eq!(format_decimal(42, 0), "42");
+        assert_eq!(format_decimal(i64::MIN, -1), "-922337203685477580.8");
+    }
+
+    #[test]
+    fn applies_exponent_and_unit() {
+        let mut schema = make_schema("temperature", DataType::NUMBER);
+        schema.set_number_exponent(-1);
+        schema.set_unit("C".to_string());
+
+        let value = from_property_value(&schema, &make_number("temperature", -45)).unwrap();
+        match value {
+            Value::Number(ref number) => {
+                assert_eq!(number.value, -45);
+                assert!((number.to_f64() + 4.5).abs() < 1e-9);
+            }
+            ref other => panic!("expected a number: {:?}", other),
+        }
+        assert_eq!(value.to_string(), "-4.5 C");
+    }
+
+    #[test]
+    fn resolves_reported_enum_indices() {
+        let mut schema = make_schema("grade", DataType::ENUM);
+        schema.set_enum_options(RepeatedField::from_vec(vec![
+            "low".to_string(),
+            "high".to_string(),
+        ]));
+
+        let mut reported = property::PropertyPage_ReportedValue::new();
+        reported.set_enum_value(1);
+        assert_eq!(
+            from_reported_value(&schema, &reported),
+            Ok(Value::Enum("high".to_string()))
+        );
+
+        reported.set_enum_value(2);
+        assert!(from_reported_value(&schema, &reported).is_err());
+    }
+
+    #[test]
+    fn converts_reported_values_back() {
+        let mut schema = make_schema("grade", DataType::ENUM);
+        schema.set_enum_options(RepeatedField::from_vec(vec![
+            "low".to_string(),
+            "high".to_string(),
+        ]));
+
+        let mut reported = property::PropertyPage_ReportedValue::new();
+        reported.set_enum_value(1);
+        let value = to_property_value(&schema, &reported).unwrap();
+        assert_eq!(value.get_name(), "grade");
+        assert_eq!(value.get_data_type(), DataType::ENUM);
+        assert_eq!(value.get_enum_value(), "high");
+
+        reported.set_enum_value(2);
+        assert!(to_property_value(&schema, &reported).is_err());
+
+        let schema = make_schema("weight", DataType::NUMBER);
+        reported.set_number_value(-7);
+        let value = to_property_value(&schema, &reported).unwrap();
+        assert_eq!(value, make_number("weight", -7));
+    }
+
+    #[test]
+    fn reads_struct_fields_with_their_schemas() {
+        let mut weight = make_schema("weight", DataType::NUMBER);
+        weight.set_number_exponent(3);
+        let mut schema = make_schema("shipment", DataType::STRUCT);
+        schema.set_struct_properties(RepeatedField::from_vec(vec![weight]));
+
+        let mut reported = property::PropertyPage_ReportedValue::new();
+        reported.set_struct_values(RepeatedField::from_vec(vec![make_number("weight", 2)]));
+        let value = from_reported_value(&schema, &reported).unwrap();
+        assert_eq!(value.to_string(), "{weight: 2000}");
+
+        reported.set_struct_values(RepeatedField::from_vec(vec![make_number("height", 2)]));
+        assert!(from_reported_value(&schema, &reported).is_err());
+    }
+
+    #[test]
+    fn merges_masked_struct_fields() {
+        let mut dimensions = property::PropertyValue::new();
+        dimensions.set_name("dimensions".to_string());
+        dimensions.set_data_type(DataType::STRUCT);
+        dimensions.set_struct_values(RepeatedField::from_vec(vec![
+            make_number("height", 10),
+            make_number("width", 20),
+        ]));
+        let base = vec![make_number("weight", 5), dimensions.clone()];
+
+        let mut new_dimensions = dimensions.clone();
+        new_dimensions.set_struct_values(RepeatedField::from_vec(vec![make_number("width", 25)]));
+        let update = vec![make_number("weight", 6), new_dimensions];
+        let fields = vec!["weight".to_string(), "dimensions.width".to_string()];
+
+        let merged = merge_struct(&base, &update, &fields).unwrap();
+        assert_eq!(merged[0], make_number("weight", 6));
+        assert_eq!(
+            merged[1].get_struct_values(),
+            &[make_number("height", 10), make_number("width", 25)][..]
+        );
+
+        // Fields sent outside the mask are not silently dropped
+        let fields = vec!["weight".to_string()];
+        assert!(merge_struct(&base, &update, &fields).is_err());
+
+        let fields = vec!["weight".to_string(), "length".to_string()];
+        assert!(merge_struct(&base, &update[..1], &fields).is_err());
+    }
+
+    #[test]
+    fn reads_locations_in_degrees() {
+        let schema = make_schema("position", DataType::LOCATION);
+        let mut location = property::Location::new();
+        location.set_latitude(43_000_000);
+        location.set_longitude(-79_500_000);
+        let mut reported = property::PropertyPage_ReportedValue::new();
+        reported.set_location_value(location);
+
+        match from_reported_value(&schema, &reported) {
+            Ok(Value::Location {
+                latitude,
+                longitude,
+            }) => {
+                assert!((latitude - 43.0).abs() < 1e-9);
+                assert!((longitude + 79.5).abs() < 1e-9);
+            }
+            other => panic!("expected a location: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trips_timestamps() {
+        let schema = make_schema("caught", DataType::TIMESTAMP);
+        let mut reported = property::PropertyPage_ReportedValue::new();
+        reported.set_timestamp_value(1_514_764_800);
+
+        let value = from_reported_value(&schema, &reported).unwrap();
+        assert_eq!(value, Value::Timestamp(1_514_764_800));
+        assert_eq!(value.to_string(), "1514764800");
+
+        let value = to_property_value(&schema, &reported).unwrap();
+        assert_eq!(value.get_data_type(), DataType::TIMESTAMP);
+        assert_eq!(value.get_timestamp_value(), 1_514_764_800);
+    }
+
+    #[test]
+    fn reads_arrays_of_structs() {
+        let mut element = make_schema("", DataType::STRUCT);
+        element.set_struct_properties(RepeatedField::from_vec(vec![make_schema(
+            "grade",
+            DataType::NUMBER,
+        )]));
+        let mut schema = make_schema("certifications", DataType::ARRAY);
+        schema.set_element_schema(element);
+
+        let make_struct = |grade| {
+            let mut value = property::PropertyValue::new();
+            value.set_data_type(DataType::STRUCT);
+            value.set_struct_values(RepeatedField::from_vec(vec![make_number("grade", grade)]));
+            value
+        };
+        let mut reported = property::PropertyPage_ReportedValue::new();
+        reported.set_array_values(RepeatedField::from_vec(vec![make_struct(1), make_struct(2)]));
+
+        let value = from_reported_value(&schema, &reported).unwrap();
+        assert_eq!(value.to_string(), "[{grade: 1}, {grade: 2}]");
+
+        let mut property = property::Property::new();
+        property.set_data_type(DataType::ARRAY);
+        assert!(from_reported_value(&property, &reported).is_err());
+    }
+}
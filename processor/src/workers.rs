@@ -0,0 +1,486 @@
+// Copyright 2018 Cargill Incorporated
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Applies transactions on a pool of worker threads.
+//!
+//! The SDK's `TransactionProcessor` applies one transaction at a time, on
+//! the thread reading from the validator, and its queues cannot be sized.
+//! This runs the same connection loop, but hands each request to one of
+//! `--workers` threads through a queue holding at most `--queue-size`
+//! requests. The processor registers with the validator for as many
+//! requests as it can hold, so the queue only fills if the validator
+//! ignores that; a request which does not fit is answered with an
+//! internal error, which the validator retries.
+//!
+//! For each action, the number of transactions being applied, the most
+//! ever applied at once, and their outcomes are logged every
+//! `--metrics-interval` seconds.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ctrlc;
+use protobuf;
+use protobuf::Message as M;
+use protobuf::RepeatedField;
+
+use sawtooth_sdk::messages::network::PingResponse;
+use sawtooth_sdk::messages::processor::{TpProcessRequest, TpProcessResponse,
+                                        TpProcessResponse_Status, TpRegisterRequest,
+                                        TpUnregisterRequest};
+use sawtooth_sdk::messages::validator::{Message, Message_MessageType};
+use sawtooth_sdk::messaging::stream::{MessageConnection, MessageSender, ReceiveError,
+                                      SendError};
+use sawtooth_sdk::messaging::zmq_stream::{ZmqMessageConnection, ZmqMessageSender};
+use sawtooth_sdk::processor::handler::{ApplyError, TransactionContext, TransactionHandler};
+
+use handler;
+use handler::SupplyChainTransactionHandler;
+
+pub const DEFAULT_WORKERS: usize = 1;
+pub const DEFAULT_QUEUE_SIZE: usize = 16;
+pub const DEFAULT_METRICS_INTERVAL: u64 = 60;
+
+pub struct WorkerConfig {
+    pub workers: usize,
+    pub queue_size: usize,
+    /// How often to log metrics, or never if None
+    pub metrics_interval: Option<Duration>,
+}
+
+/// How a transaction given to the pool ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Applied,
+    Invalid,
+    Failed,
+    /// Answered without being applied, because the queue was full
+    QueueFull,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActionStats {
+    pub in_flight: usize,
+    /// The most transactions with the action ever applied at once
+    pub peak: usize,
+    pub applied: u64,
+    pub invalid: u64,
+    pub failed: u64,
+    pub queue_full: u64,
+    /// The time spent applying transactions with the action, which may be
+    /// more than has passed when they are applied concurrently
+    pub busy: Duration,
+}
+
+/// Counts of the transactions applied by the pool, by action name
+#[derive(Default)]
+pub struct ActionMetrics {
+    stats: Mutex<BTreeMap<String, ActionStats>>,
+}
+
+impl ActionMetrics {
+    pub fn started(&self, action: &str) {
+        let mut stats = self.stats.lock().expect("Metrics lock poisoned");
+        let entry = stats.entry(action.to_string()).or_insert_with(Default::default);
+        entry.in_flight += 1;
+        if entry.in_flight > entry.peak {
+            entry.peak = entry.in_flight;
+        }
+    }
+
+    /// Records the outcome of a transaction. Unless it was turned away
+    /// because the queue was full, it must have been started first.
+    pub fn finished(&self, action: &str, outcome: Outcome, elapsed: Duration) {
+        let mut stats = self.stats.lock().expect("Metrics lock poisoned");
+        let entry = stats.entry(action.to_string()).or_insert_with(Default::default);
+        if outcome == Outcome::QueueFull {
+            entry.queue_full += 1;
+            return;
+        }
+
+        entry.in_flight = entry.in_flight.saturating_sub(1);
+        entry.busy += elapsed;
+        match outcome {
+            Outcome::Applied => entry.applied += 1,
+            Outcome::Invalid => entry.invalid += 1,
+            Outcome::Failed => entry.failed += 1,
+            Outcome::QueueFull => unreachable!(),
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, ActionStats> {
+        self.stats.lock().expect("Metrics lock poisoned").clone()
+    }
+
+    fn log(&self) {
+        for (action, stats) in self.snapshot() {
+            info!(
+                "{}: {} in flight, peak {}, {} applied, {} invalid, {} failed, \
+                 {} turned away, {}ms busy",
+                action,
+                stats.in_flight,
+                stats.peak,
+                stats.applied,
+                stats.invalid,
+                stats.failed,
+                stats.queue_full,
+                stats.busy.as_secs() * 1000 + u64::from(stats.busy.subsec_nanos()) / 1_000_000
+            );
+        }
+    }
+}
+
+/// The name metrics are kept under for a request, which is its payload's
+/// action, or UNKNOWN if the payload cannot be parsed. Only the action is
+/// read, so that labelling a request does not hold up the receive thread
+/// with a full decode.
+pub fn action_name(request: &TpProcessRequest) -> String {
+    handler::payload_action(request.get_payload())
+        .map(|action| format!("{:?}", action))
+        .unwrap_or_else(|| "UNKNOWN".to_string())
+}
+
+struct Job {
+    request: TpProcessRequest,
+    action: String,
+    correlation_id: String,
+    sender: ZmqMessageSender,
+}
+
+fn reply<T: M>(
+    sender: &ZmqMessageSender,
+    message_type: Message_MessageType,
+    correlation_id: &str,
+    response: &T,
+) -> Result<(), SendError> {
+    let serialized = match response.write_to_bytes() {
+        Ok(serialized) => serialized,
+        Err(err) => {
+            error!("Serialization failed: {}", err);
+            return Ok(());
+        }
+    };
+    sender.reply(message_type, correlation_id, &serialized)
+}
+
+fn process_response(status: TpProcessResponse_Status, message: &str) -> TpProcessResponse {
+    let mut response = TpProcessResponse::new();
+    response.set_status(status);
+    response.set_message(message.to_string());
+    response
+}
+
+fn apply(handler: &SupplyChainTransactionHandler, metrics: &ActionMetrics, job: Job) {
+    metrics.started(&job.action);
+    let start = Instant::now();
+
+    let mut context = TransactionContext::new(job.request.get_context_id(), job.sender.clone());
+    let (outcome, response) = match handler.apply(&job.request, &mut context) {
+        Ok(()) => (
+            Outcome::Applied,
+            process_response(TpProcessResponse_Status::OK, ""),
+        ),
+        Err(ApplyError::InvalidTransaction(msg)) => (
+            Outcome::Invalid,
+            process_response(TpProcessResponse_Status::INVALID_TRANSACTION, &msg),
+        ),
+        Err(ApplyError::InternalError(msg)) => (
+            Outcome::Failed,
+            process_response(TpProcessResponse_Status::INTERNAL_ERROR, &msg),
+        ),
+    };
+    metrics.finished(&job.action, outcome, start.elapsed());
+
+    if let Err(err) = reply(
+        &job.sender,
+        Message_MessageType::TP_PROCESS_RESPONSE,
+        &job.correlation_id,
+        &response,
+    ) {
+        error!("Cannot send TpProcessResponse: {:?}", err);
+    }
+}
+
+fn spawn_workers(
+    count: usize,
+    handler: &Arc<SupplyChainTransactionHandler>,
+    metrics: &Arc<ActionMetrics>,
+    jobs: Receiver<Job>,
+) -> Vec<thread::JoinHandle<()>> {
+    let jobs = Arc::new(Mutex::new(jobs));
+    (0..count)
+        .map(|_| {
+            let handler = Arc::clone(handler);
+            let metrics = Arc::clone(metrics);
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || loop {
+                // The lock is only held while waiting, so each job is taken
+                // by whichever worker is free first
+                let job = match jobs.lock().expect("Job queue lock poisoned").recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                apply(&handler, &metrics, job);
+            })
+        })
+        .collect()
+}
+
+fn register(
+    handler: &SupplyChainTransactionHandler,
+    sender: &ZmqMessageSender,
+    max_occupancy: u32,
+    stopping: &AtomicBool,
+) -> bool {
+    for version in handler.family_versions() {
+        let mut request = TpRegisterRequest::new();
+        request.set_family(handler.family_name());
+        request.set_version(version.clone());
+        request.set_namespaces(RepeatedField::from_vec(handler.namespaces()));
+        request.set_max_occupancy(max_occupancy);
+        info!(
+            "Registering {} {} for up to {} transactions",
+            handler.family_name(),
+            version,
+            max_occupancy
+        );
+
+        let serialized = match request.write_to_bytes() {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                error!("Serialization failed: {}", err);
+                return false;
+            }
+        };
+        let mut future = match sender.send(
+            Message_MessageType::TP_REGISTER_REQUEST,
+            &format!("register-{}", version),
+            &serialized,
+        ) {
+            Ok(future) => future,
+            Err(err) => {
+                error!("Registration failed: {:?}", err);
+                return false;
+            }
+        };
+        while future.get_timeout(Duration::from_secs(10)).is_err() {
+            if stopping.load(Ordering::SeqCst) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn unregister(sender: &ZmqMessageSender) {
+    info!("Unregistering");
+    let serialized = match TpUnregisterRequest::new().write_to_bytes() {
+        Ok(serialized) => serialized,
+        Err(err) => {
+            error!("Serialization failed: {}", err);
+            return;
+        }
+    };
+    match sender.send(
+        Message_MessageType::TP_UNREGISTER_REQUEST,
+        "unregister",
+        &serialized,
+    ) {
+        Ok(mut future) => {
+            if future.get_timeout(Duration::from_secs(1)).is_err() {
+                info!("No reply to TpUnregisterRequest");
+            }
+        }
+        Err(err) => error!("Unregistration failed: {:?}", err),
+    }
+}
+
+/// Connects to the validator at `endpoint` and applies the transactions
+/// it sends until interrupted, reconnecting if the connection is lost
+pub fn run(endpoint: &str, handler: Arc<SupplyChainTransactionHandler>, config: &WorkerConfig) {
+    let stopping = Arc::new(AtomicBool::new(false));
+    let interrupted = Arc::clone(&stopping);
+    ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))
+        .expect("Error setting Ctrl-C handler");
+
+    let metrics = Arc::new(ActionMetrics::default());
+    let (queue, jobs) = sync_channel(config.queue_size);
+    let workers = spawn_workers(config.workers, &handler, &metrics, jobs);
+    let max_occupancy = (config.workers + config.queue_size) as u32;
+    let mut last_report = Instant::now();
+
+    'connect: while !stopping.load(Ordering::SeqCst) {
+        info!("Connecting to {} with {} workers", endpoint, config.workers);
+        let (sender, receiver) = ZmqMessageConnection::new(endpoint).create();
+        if !register(&handler, &sender, max_occupancy, &stopping) {
+            continue;
+        }
+
+        loop {
+            if stopping.load(Ordering::SeqCst) {
+                unregister(&sender);
+                break 'connect;
+            }
+            if let Some(interval) = config.metrics_interval {
+                if last_report.elapsed() >= interval {
+                    metrics.log();
+                    last_report = Instant::now();
+                }
+            }
+
+            let message: Message = match receiver.recv_timeout(Duration::from_secs(1)) {
+                Ok(Ok(message)) => message,
+                Ok(Err(ReceiveError::DisconnectedError)) => {
+                    info!("Disconnected, reconnecting");
+                    continue 'connect;
+                }
+                Ok(Err(err)) => {
+                    error!("Cannot receive message: {:?}", err);
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    info!("Disconnected, reconnecting");
+                    continue 'connect;
+                }
+            };
+
+            let sent = match message.get_message_type() {
+                Message_MessageType::TP_PROCESS_REQUEST => {
+                    let request: TpProcessRequest =
+                        match protobuf::parse_from_bytes(message.get_content()) {
+                            Ok(request) => request,
+                            Err(err) => {
+                                error!("Cannot parse TpProcessRequest: {}", err);
+                                continue;
+                            }
+                        };
+                    let job = Job {
+                        action: action_name(&request),
+                        request,
+                        correlation_id: message.get_correlation_id().to_string(),
+                        sender: sender.clone(),
+                    };
+                    match queue.try_send(job) {
+                        Ok(()) => Ok(()),
+                        Err(TrySendError::Full(job)) => {
+                            metrics.finished(&job.action, Outcome::QueueFull, Duration::default());
+                            reply(
+                                &sender,
+                                Message_MessageType::TP_PROCESS_RESPONSE,
+                                &job.correlation_id,
+                                &process_response(
+                                    TpProcessResponse_Status::INTERNAL_ERROR,
+                                    "Transaction processor queue is full",
+                                ),
+                            )
+                        }
+                        Err(TrySendError::Disconnected(_)) => {
+                            error!("All workers have stopped");
+                            break 'connect;
+                        }
+                    }
+                }
+                Message_MessageType::PING_REQUEST => reply(
+                    &sender,
+                    Message_MessageType::PING_RESPONSE,
+                    message.get_correlation_id(),
+                    &PingResponse::new(),
+                ),
+                message_type => {
+                    info!("Ignoring {:?} message", message_type);
+                    Ok(())
+                }
+            };
+
+            match sent {
+                Ok(()) => (),
+                Err(SendError::DisconnectedError) => {
+                    error!("Disconnected while replying");
+                    continue 'connect;
+                }
+                Err(err) => error!("Cannot send reply: {:?}", err),
+            }
+        }
+    }
+
+    // Let the workers finish what they have taken before exiting
+    drop(queue);
+    for worker in workers {
+        if worker.join().is_err() {
+            error!("A worker panicked");
+        }
+    }
+    if config.metrics_interval.is_some() {
+        metrics.log();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_peak_concurrency_by_action() {
+        let metrics = ActionMetrics::default();
+        metrics.started("CREATE_RECORD");
+        metrics.started("CREATE_RECORD");
+        metrics.started("UPDATE_PROPERTIES");
+        metrics.finished("CREATE_RECORD", Outcome::Applied, Duration::from_millis(5));
+        metrics.started("CREATE_RECORD");
+        metrics.finished("CREATE_RECORD", Outcome::Invalid, Duration::from_millis(2));
+
+        let stats = metrics.snapshot();
+        let create = &stats["CREATE_RECORD"];
+        assert_eq!(create.in_flight, 1);
+        assert_eq!(create.peak, 2);
+        assert_eq!(create.applied, 1);
+        assert_eq!(create.invalid, 1);
+        assert_eq!(create.busy, Duration::from_millis(7));
+        assert_eq!(stats["UPDATE_PROPERTIES"].in_flight, 1);
+    }
+
+    #[test]
+    fn counts_turned_away_transactions_separately() {
+        let metrics = ActionMetrics::default();
+        metrics.started("MESSAGE");
+        metrics.finished("MESSAGE", Outcome::QueueFull, Duration::default());
+
+        let stats = metrics.snapshot();
+        assert_eq!(stats["MESSAGE"].in_flight, 1);
+        assert_eq!(stats["MESSAGE"].queue_full, 1);
+        assert_eq!(stats["MESSAGE"].applied, 0);
+    }
+
+    #[test]
+    fn names_requests_by_action() {
+        use messages::payload::{CreateAgentAction, SCPayload, SCPayload_Action};
+
+        let mut action = CreateAgentAction::new();
+        action.set_name(String::from("Alice"));
+        let mut payload = SCPayload::new();
+        payload.set_action(SCPayload_Action::CREATE_AGENT);
+        payload.set_create_agent(action);
+        let mut request = TpProcessRequest::new();
+        request.set_payload(payload.write_to_bytes().unwrap());
+        assert_eq!(action_name(&request), "CREATE_AGENT");
+
+        request.set_payload(vec![0xff, 0xff]);
+        assert_eq!(action_name(&request), "UNKNOWN");
+    }
+}
@@ -18,6 +18,7 @@
 
 const _ = require('lodash')
 const db = require('../db/agents')
+const { BadRequest, NotFound } = require('./errors')
 
 const FILTER_KEYS = ['name', 'publicKey']
 
@@ -25,7 +26,30 @@ const list = params => db.list(_.pick(params, FILTER_KEYS))
 
 const fetch = ({ publicKey, authedKey }) => db.fetch(publicKey, publicKey === authedKey)
 
+const fetchSubscriptions = ({ publicKey }) => db.fetchSubscriptions(publicKey)
+
+// A page of the actions an Agent has signed, oldest first. Defaults to
+// the page the Agent's most recent actions are on.
+const fetchActivity = ({ publicKey, page }) => {
+  return Promise.resolve()
+    .then(() => {
+      if (page === undefined) return null
+      const pageNum = Number(page)
+      if (!Number.isInteger(pageNum) || pageNum < 0) {
+        throw new BadRequest('Page must be a non-negative integer')
+      }
+      return pageNum
+    })
+    .then(pageNum => db.fetchActivityPage(publicKey, pageNum))
+    .then(activity => {
+      if (!activity) throw new NotFound(`No agent with key: ${publicKey}`)
+      return activity
+    })
+}
+
 module.exports = {
   list,
-  fetch
+  fetch,
+  fetchSubscriptions,
+  fetchActivity
 }
@@ -0,0 +1,50 @@
+/**
+ * Copyright 2017 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const db = require('../db/batches')
+const blockchain = require('../blockchain/')
+const { BadRequest, NotFound } = require('./errors')
+
+const DEFAULT_LIMIT = 50
+
+const list = ({ authedKey, limit }) => {
+  const parsed = limit === undefined ? DEFAULT_LIMIT : Number(limit)
+  if (!Number.isInteger(parsed) || parsed < 1) {
+    return Promise.reject(new BadRequest('Limit must be a positive integer'))
+  }
+
+  return db.list(authedKey, parsed)
+    .then(blockchain.refreshBatches)
+}
+
+const fetch = ({ batchId, authedKey }) => {
+  return db.fetch(batchId)
+    .then(batch => {
+      // Only the submitter may see a batch, others just get a 404
+      if (!batch || batch.submitter !== authedKey) {
+        throw new NotFound(`No batch with the id "${batchId}" exists`)
+      }
+      return blockchain.refreshBatches([batch])
+    })
+    .then(batches => batches[0])
+}
+
+module.exports = {
+  list,
+  fetch
+}
@@ -0,0 +1,43 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const blockchain = require('../blockchain/')
+const { BadRequest, NotFound } = require('./errors')
+
+const BLOCK_ID_PATTERN = /^[0-9a-f]{128}$/
+
+// Which Records and Agents a block's Supply Chain transactions touched,
+// for block explorers
+const fetchActivity = ({ blockId }) => {
+  if (!BLOCK_ID_PATTERN.test(blockId)) {
+    return Promise.reject(
+      new BadRequest('Block id must be 128 lowercase hex characters'))
+  }
+
+  return blockchain.fetchBlockActivity(blockId)
+    .then(activity => {
+      if (!activity) {
+        throw new NotFound(`No block with the id "${blockId}" exists`)
+      }
+      return activity
+    })
+}
+
+module.exports = {
+  fetchActivity
+}
@@ -37,6 +37,20 @@ class NotFound extends Error {
   }
 }
 
+class TooManyRequests extends Error {
+  constructor (message) {
+    super(message)
+    this.status = 429
+  }
+}
+
+class ServiceUnavailable extends Error {
+  constructor (message) {
+    super(message)
+    this.status = 503
+  }
+}
+
 class InternalServerError extends Error {
   constructor (message) {
     super(message)
@@ -48,5 +62,7 @@ module.exports = {
   BadRequest,
   Unauthorized,
   NotFound,
+  TooManyRequests,
+  ServiceUnavailable,
   InternalServerError
 }
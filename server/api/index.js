@@ -22,11 +22,23 @@ const bodyParser = require('body-parser')
 
 const auth = require('./auth')
 const users = require('./users')
-const { BadRequest, Unauthorized } = require('./errors')
+const webhooks = require('./webhooks')
+const verify = require('./verify')
+const validate = require('./validate')
+const {
+  BadRequest,
+  Unauthorized,
+  TooManyRequests,
+  ServiceUnavailable
+} = require('./errors')
 const agents = require('./agents')
+const batches = require('./batches')
+const blocks = require('./blocks')
+const messages = require('./messages')
 const records = require('./records')
 const recordTypes = require('./record_types')
 const blockchain = require('../blockchain/')
+const db = require('../db')
 const batcher = require('../blockchain/batcher')
 const config = require('../system/config')
 
@@ -110,6 +122,44 @@ const waitParser = (req, res, next) => {
   next()
 }
 
+// Middleware for the min_block query parameter, which holds a read
+// briefly until the database has synced the block a client's own
+// write was committed in (as returned when submitting with wait)
+const minBlockHandler = (req, res, next) => {
+  if (req.method !== 'GET' || req.query.min_block === undefined) {
+    return next()
+  }
+
+  const minBlock = Number(req.query.min_block)
+  if (!Number.isInteger(minBlock) || minBlock < 0) {
+    return next(new BadRequest('The min_block parameter must be a block number'))
+  }
+
+  db.awaitBlock(minBlock, config.MIN_BLOCK_WAIT)
+    .then(synced => {
+      if (synced) return next()
+      next(new ServiceUnavailable(
+        `Block ${minBlock} has not been synced yet, try again shortly`))
+    })
+    .catch(next)
+}
+
+// Limits each client address to a number of requests a minute, for public
+// routes that are meant to be hit by anyone (e.g. by scanning a QR code)
+const rateLimit = limit => {
+  const counts = new Map()
+  setInterval(() => counts.clear(), 60 * 1000).unref()
+
+  return (req, res, next) => {
+    const count = (counts.get(req.ip) || 0) + 1
+    counts.set(req.ip, count)
+    if (count > limit) {
+      return next(new TooManyRequests('Too many requests, try again shortly'))
+    }
+    next()
+  }
+}
+
 // Check the Authorization header if present.
 // Saves the encoded public key to the request object.
 const authHandler = (req, res, next) => {
@@ -150,18 +200,28 @@ router.use(bodyParser.raw({ type: 'application/octet-stream' }))
 router.use(logRequest)
 router.use(initInternalParams)
 router.use(waitParser)
+router.use(minBlockHandler)
 router.use(authHandler)
 
 router.get('/agents', handle(agents.list))
 router.get('/agents/:publicKey', handle(agents.fetch))
+router.get('/agents/:publicKey/subscriptions',
+           handle(agents.fetchSubscriptions))
+router.get('/agents/:publicKey/activity', handle(agents.fetchActivity))
+router.route('/agents/:publicKey/webhook')
+  .get(restrict, handle(webhooks.fetch))
+  .put(restrict, handleBody(webhooks.update))
+  .delete(restrict, handle(webhooks.remove))
 
 router.post('/authorization', handleBody(auth.authorize))
 
 router.get('/info', handle(() => {
-  return Promise.resolve()
-    .then(() => ({
+  return blockchain.fetchTimestampUnit()
+    .then(timestampUnit => ({
       pubkey: batcher.getPublicKey(),
       mapsApiKey: config.MAPS_API_KEY,
+      timestampUnit,
+      languages: messages.listLanguages(),
       endpoints: endpointInfo
     }))
 }))
@@ -177,15 +237,42 @@ router.post('/info/mapsApiKey', handleBody(body => {
     })
 }))
 
+router.get('/messages', handle(messages.fetch))
+
 router.get('/records', handle(records.listRecords))
 router.get('/records/:recordId', handle(records.fetchRecord))
+router.get('/records/:recordId/timeline', handle(records.fetchTimeline))
+router.get('/records/:recordId/holders', handle(records.fetchHolders))
+router.get('/records/:recordId/messages', handle(records.listMessages))
+router.get('/records/:recordId/feedback', handle(records.listFeedback))
 router.get('/records/:recordId/property/:propertyName', handle(records.fetchProperty))
+router.get('/records/:recordId/property/:propertyName/excursions',
+           handle(records.listExcursions))
 router.get('/records/:recordId/:propertyName', handle(records.fetchProperty))
 
+router.get('/location-reports', handle(records.listLocationReports))
+
+router.get('/sync', handle(records.fetchChanges))
+
+router.get('/verify/:recordId',
+           rateLimit(Number(config.VERIFY_RATE_LIMIT)),
+           handle(verify.fetchProvenance))
+
 router.get('/record-types', handle(recordTypes.list))
 router.get('/record-types/:typeName', handle(recordTypes.fetch))
+router.get('/record-types/:typeName/stats', handle(recordTypes.fetchStats))
+
+router.get('/record-templates/:templateName',
+           handle(recordTypes.fetchTemplate))
 
 router.post('/transactions', handleBody(blockchain.submit))
+router.post('/validate', handleBody(validate.validate))
+
+router.get('/batches', restrict, handle(batches.list))
+router.get('/batches/:batchId', restrict, handle(batches.fetch))
+
+router.get('/blocks/:blockId/supply-chain-activity',
+           handle(blocks.fetchActivity))
 
 router.route('/users')
   .post(handleBody(users.create))
@@ -0,0 +1,64 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const fs = require('fs')
+const path = require('path')
+const config = require('../system/config')
+const { NotFound } = require('./errors')
+
+const DEFAULT_LANG = 'en'
+const CATALOG_DIRS = [ path.join(__dirname, '../catalog'), config.CATALOG_DIR ]
+
+// Reads every <lang>.json catalog, with those in CATALOG_DIR replacing
+// any shipped for the same language
+const loadCatalogs = () => {
+  return CATALOG_DIRS
+    .filter(dir => dir && fs.existsSync(dir))
+    .reduce((catalogs, dir) => {
+      fs.readdirSync(dir)
+        .filter(file => path.extname(file) === '.json')
+        .forEach(file => {
+          const lang = path.basename(file, '.json')
+          catalogs[lang] = JSON.parse(fs.readFileSync(path.join(dir, file)))
+        })
+      return catalogs
+    }, {})
+}
+
+const catalogs = loadCatalogs()
+
+// Messages for the error codes of invalid transactions and the names of
+// record events. Keys missing from a language fall back to English, so a
+// front-end always has something to show.
+const fetch = ({ lang = DEFAULT_LANG }) => {
+  return Promise.resolve()
+    .then(() => {
+      if (!catalogs[lang]) {
+        throw new NotFound(`No messages for language: ${lang}`)
+      }
+      return _.merge({ lang }, catalogs[DEFAULT_LANG], catalogs[lang])
+    })
+}
+
+const listLanguages = () => Object.keys(catalogs).sort()
+
+module.exports = {
+  fetch,
+  listLanguages
+}
@@ -17,10 +17,33 @@
 'use strict'
 
 const _ = require('lodash')
-const { NotFound } = require('./errors')
+const { createHash } = require('crypto')
+const blockchain = require('../blockchain/')
+const { BadRequest, NotFound } = require('./errors')
 const db = require('../db/record_types')
 
 const FILTER_KEYS = ['name']
+const NAMESPACE = '3400de'
+const STATS_PREFIX = 'a3'
+const TEMPLATE_PREFIX = 'a6'
+const DATE_PATTERN = /^\d{4}-\d{2}-\d{2}$/
+const MS_PER_DAY = 24 * 60 * 60 * 1000
+
+const hash = (str, length) => {
+  return createHash('sha512').update(str).digest('hex').slice(0, length)
+}
+
+// Converts a UTC date, like "2018-06-30", to days from the Unix epoch
+const toDay = (date, name) => {
+  if (date === undefined) return null
+  const time = DATE_PATTERN.test(date) ? Date.parse(date) : NaN
+  if (isNaN(time)) {
+    throw new BadRequest(`The ${name} date must be formatted YYYY-MM-DD`)
+  }
+  return time / MS_PER_DAY
+}
+
+const toDate = day => new Date(day * MS_PER_DAY).toISOString().slice(0, 10)
 
 const fetch = ({ typeName }) => {
   return db.fetch(typeName)
@@ -34,7 +57,50 @@ const fetch = ({ typeName }) => {
 
 const list = params => db.list(_.pick(params, FILTER_KEYS))
 
+// Daily totals of the Records of a type created and finalized, read from
+// state, optionally limited to the days from and to UTC dates
+const fetchStats = ({ typeName, from, to }) => {
+  return Promise.resolve()
+    .then(() => [toDay(from, 'from'), toDay(to, 'to')])
+    .then(([ firstDay, lastDay ]) => {
+      return fetch({ typeName })
+        .then(() => {
+          const address = NAMESPACE + STATS_PREFIX + hash(typeName, 54)
+          return blockchain.fetchRecordTypeStats(address)
+        })
+        .then(stats => _.chain(stats)
+          .filter({ recordType: typeName })
+          .filter(({ day }) => firstDay === null || day >= firstDay)
+          .filter(({ day }) => lastDay === null || day <= lastDay)
+          .sortBy('day')
+          .map(({ day, created, finalized, finalizedSeconds }) => ({
+            date: toDate(day),
+            created,
+            finalized,
+            averageSecondsToFinalize: finalized > 0
+              ? finalizedSeconds / finalized
+              : null
+          }))
+          .value())
+    })
+}
+
+// A RecordTemplate, read from state
+const fetchTemplate = ({ templateName }) => {
+  const address = NAMESPACE + TEMPLATE_PREFIX + hash(templateName, 62)
+  return blockchain.fetchRecordTemplates(address)
+    .then(templates => {
+      const template = _.find(templates, { name: templateName })
+      if (!template) {
+        throw new NotFound(`No record template with name: ${templateName}`)
+      }
+      return template
+    })
+}
+
 module.exports = {
   fetch,
+  fetchStats,
+  fetchTemplate,
   list
 }
@@ -18,23 +18,223 @@
 
 const _ = require('lodash')
 const db = require('../db/records')
+const redaction = require('./redaction')
+const { BadRequest, NotFound } = require('./errors')
 
 const FILTER_KEYS = ['recordId', 'recordType']
+const MAX_SYNC_RECORDS = 100
+const FEEDBACK_KINDS = ['SCAN', 'FEEDBACK']
 
-const fetchProperty = ({recordId, propertyName}) => {
+// The attributes, besides recordId, identifying a document in each table
+// that changes are read from
+const SYNC_KEYS = {
+  records: [],
+  properties: ['name'],
+  propertyPages: ['name', 'pageNum'],
+  recordTimelinePages: ['pageNum'],
+  proposals: ['timestamp', 'receivingAgent', 'role'],
+  messageAnchors: ['digest'],
+  excursionLogs: ['name']
+}
+
+const hiddenProperty = (recordId, propertyName) => {
+  return new NotFound(
+    `No property "${propertyName}" on the record "${recordId}" exists`)
+}
+
+const fetchProperty = ({recordId, propertyName, authedKey}) => {
   return db.fetchProperty(recordId, propertyName)
+    .then(property => redaction.redactProperty(property, authedKey))
+    .then(property => {
+      if (!property) throw hiddenProperty(recordId, propertyName)
+      return property
+    })
 }
 
 const fetchRecord = ({recordId, authedKey}) => {
   return db.fetchRecord(recordId, authedKey)
+    .then(record => redaction.redactRecord(record, authedKey))
+}
+
+const parseInteger = (name, value) => {
+  const parsed = Number(value)
+  if (!Number.isInteger(parsed) || parsed < 0) {
+    throw new BadRequest(`${name} must be a non-negative integer`)
+  }
+  return parsed
+}
+
+const fetchTimeline = ({recordId, page}) => {
+  return Promise.resolve()
+    .then(() => page === undefined ? null : parseInteger('Page', page))
+    .then(pageNum => db.fetchTimelinePage(recordId, pageNum))
+}
+
+const parseRecordIds = ids => {
+  const recordIds = _.uniq((ids || '').split(',').filter(id => id))
+  if (recordIds.length === 0) {
+    throw new BadRequest('Ids must be one or more record ids, ' +
+                         'separated by commas')
+  }
+  if (recordIds.length > MAX_SYNC_RECORDS) {
+    throw new BadRequest(
+      `Changes can be fetched for at most ${MAX_SYNC_RECORDS} records`)
+  }
+  return recordIds
+}
+
+// Lists the changes to a set of Records since a block, for clients which
+// keep a local copy of them. Each change is the new state of a document,
+// grouped by Record, and identified by its table and key, which a client
+// replaces its copy with. Without a block, or if the block passed (by
+// number, with its id) has since been forked out, every document is
+// returned and reset is true, so the client should start its copy over.
+const fetchChanges = ({ids, since, blockId, authedKey}) => {
+  return Promise.resolve()
+    .then(() => {
+      const recordIds = parseRecordIds(ids)
+      const sinceNum = since === undefined ? -1 : parseInteger('Since', since)
+
+      return db.fetchChanges(recordIds, sinceNum)
+        .then(result => {
+          if (sinceNum > result.block.blockNum) {
+            throw new BadRequest(
+              `Since is after the current block: ${result.block.blockNum}`)
+          }
+          const isForked = sinceNum >= 0 && blockId !== undefined &&
+            (!result.sinceBlock || result.sinceBlock.blockId !== blockId)
+          if (isForked) {
+            return db.fetchChanges(recordIds, -1)
+              .then(fresh => _.assign(fresh, { reset: true }))
+          }
+          return _.assign(result, { reset: sinceNum < 0 })
+        })
+    })
+    .then(result => {
+      return redaction.redactChanges(result.changes, authedKey)
+        .then(changes => _.assign(result, { changes }))
+    })
+    .then(({ block, reset, changes }) => ({
+      blockNum: block.blockNum,
+      blockId: block.blockId,
+      reset,
+      changes: _.chain(changes)
+        .groupBy(change => change.state.recordId)
+        .mapValues(recordChanges => recordChanges.map(({ table, state }) => ({
+          table,
+          key: SYNC_KEYS[table].map(attr => state[attr]),
+          state
+        })))
+        .value()
+    }))
+}
+
+// Finds who owned and had custody of a Record at a Unix UTC timestamp,
+// or null for a role if the Record did not exist yet
+const fetchHolders = ({recordId, at}) => {
+  const now = Math.floor(Date.now() / 1000)
+  return Promise.resolve()
+    .then(() => at === undefined ? now : parseInteger('At', at))
+    .then(timestamp => {
+      return db.fetchTimelineEntries(recordId)
+        .then(entries => {
+          const holders = _.chain(entries)
+            .sortBy('timestamp')
+            .filter(entry => entry.timestamp <= timestamp)
+            .groupBy('role')
+            .mapValues(roleEntries => _.last(roleEntries).agentId)
+            .value()
+
+          return {
+            recordId,
+            at: timestamp,
+            owner: holders.OWNER || null,
+            custodian: holders.CUSTODIAN || null
+          }
+        })
+    })
+}
+
+// Parses an area given as "latitude,longitude" points in degrees,
+// separated by semicolons
+const parsePolygon = polygon => {
+  const points = (polygon || '').split(';')
+    .map(point => point.split(',').map(Number))
+  const isValid = points.every(point => {
+    return point.length === 2 &&
+      point.every(Number.isFinite) &&
+      Math.abs(point[0]) <= 90 &&
+      Math.abs(point[1]) <= 180
+  })
+  if (points.length < 3 || !isValid) {
+    throw new BadRequest(
+      'Polygon must be three or more "latitude,longitude" points ' +
+      'separated by semicolons')
+  }
+  return points
+}
+
+// Lists the Records which reported a LOCATION value inside an area,
+// optionally only between two Unix UTC timestamps
+const listLocationReports = ({polygon, start, end, authedKey}) => {
+  return Promise.resolve()
+    .then(() => {
+      const points = parsePolygon(polygon)
+      const from = start === undefined ? 0 : parseInteger('Start', start)
+      const to = end === undefined
+        ? Number.MAX_SAFE_INTEGER
+        : parseInteger('End', end)
+      return db.listLocationReports(points, from, to)
+        .then(results => {
+          return redaction.redactLocationReports(results, points, authedKey)
+        })
+    })
+}
+
+const listExcursions = ({recordId, propertyName, authedKey}) => {
+  return redaction.isPropertyHidden(recordId, propertyName, authedKey)
+    .then(isHidden => {
+      if (isHidden) throw hiddenProperty(recordId, propertyName)
+      return db.listExcursions(recordId, propertyName)
+    })
+}
+
+// Lists the message anchors for a Record, oldest first, optionally only
+// those matching a digest
+const listMessages = ({recordId, digest}) => {
+  return db.listMessages(recordId, digest === undefined ? null : digest)
+}
+
+// Lists the feedback anchored to a Record, newest first, optionally only
+// scans or only feedback
+const listFeedback = ({recordId, kind}) => {
+  return Promise.resolve()
+    .then(() => {
+      if (kind === undefined) return null
+      if (!FEEDBACK_KINDS.includes(kind)) {
+        throw new BadRequest('Kind must be either SCAN or FEEDBACK')
+      }
+      return kind
+    })
+    .then(feedbackKind => db.listFeedback(recordId, feedbackKind))
 }
 
 const listRecords = params => {
   return db.listRecords(params.authedKey, _.pick(params, FILTER_KEYS))
+    .then(records => records.map(record => {
+      return redaction.redactRecord(record, params.authedKey)
+    }))
 }
 
 module.exports = {
+  fetchChanges,
   fetchProperty,
   fetchRecord,
+  fetchTimeline,
+  fetchHolders,
+  listExcursions,
+  listFeedback,
+  listLocationReports,
+  listMessages,
   listRecords
 }
@@ -0,0 +1,216 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const fs = require('fs')
+const db = require('../db/records')
+const config = require('../system/config')
+
+// Locations are stored in millionths of a degree, and masked to the
+// nearest tenth of one, which is around ten kilometers
+const REGION_SIZE = 1e5
+
+const NO_RULE = { hidden: [], regionOnly: [] }
+
+// The tables synced documents are read from which belong to a Property
+const PROPERTY_TABLES = ['properties', 'propertyPages', 'excursionLogs']
+
+// Reads the file named by the REDACTION_RULES setting, which maps the
+// names of RecordTypes to what their Records show anyone but their owner:
+//   { "fish": { "hidden": ["price"], "regionOnly": ["location"] } }
+// Hidden Properties are left out entirely, and the LOCATION values of
+// regionOnly Properties are masked to a region.
+const loadRules = () => {
+  if (!config.REDACTION_RULES) return {}
+  const rules = JSON.parse(fs.readFileSync(config.REDACTION_RULES))
+  return _.mapValues(rules, rule => _.assign({}, NO_RULE, rule))
+}
+
+const rules = loadRules()
+
+const isEnabled = () => !_.isEmpty(rules)
+
+// The rule a viewer of a Record is held to, owners seeing everything
+const getRule = ({ recordType, owner }, viewer) => {
+  if (viewer && owner === viewer) return NO_RULE
+  return rules[recordType] || NO_RULE
+}
+
+const isHidden = (rule, name) => rule.hidden.includes(name)
+
+const toRegion = coordinate => Math.round(coordinate / REGION_SIZE) * REGION_SIZE
+
+const maskLocation = location => {
+  if (!location || !_.isNumber(location.latitude)) return location
+  return {
+    latitude: toRegion(location.latitude),
+    longitude: toRegion(location.longitude)
+  }
+}
+
+const maskValue = (rule, name) => value => {
+  return rule.regionOnly.includes(name) ? maskLocation(value) : value
+}
+
+const maskUpdates = (rule, name, updates) => {
+  return updates.map(update => {
+    return _.assign({}, update, { value: maskValue(rule, name)(update.value) })
+  })
+}
+
+// Resolves to the rules a viewer is held to for each of the passed
+// Records, keyed by their ids
+const fetchRules = (recordIds, viewer) => {
+  if (!isEnabled()) return Promise.resolve({})
+  return db.listRecordSummaries(recordIds)
+    .then(summaries => _.chain(summaries)
+      .keyBy('recordId')
+      .mapValues(summary => getRule(summary, viewer))
+      .value())
+}
+
+// Applies the rules to a Record as fetched or listed
+const redactRecord = (record, viewer) => {
+  const rule = getRule(record, viewer)
+  if (rule === NO_RULE) return record
+
+  return _.assign({}, record, {
+    properties: record.properties
+      .filter(property => !isHidden(rule, property.name))
+      .map(property => _.assign({}, property, {
+        value: maskValue(rule, property.name)(property.value)
+      })),
+    updates: _.assign({}, record.updates, {
+      properties: _.chain(record.updates.properties)
+        .omit(rule.hidden)
+        .mapValues((updates, name) => maskUpdates(rule, name, updates))
+        .value()
+    })
+  })
+}
+
+// Applies the rules to a single Property, resolving to null if it is
+// hidden from the viewer
+const redactProperty = (property, viewer) => {
+  return fetchRules([property.recordId], viewer)
+    .then(ruleMap => {
+      const rule = ruleMap[property.recordId] || NO_RULE
+      if (isHidden(rule, property.name)) return null
+      if (!rule.regionOnly.includes(property.name)) return property
+
+      return _.assign({}, property, {
+        value: property.value && maskUpdates(rule, property.name,
+                                             [property.value])[0],
+        updates: maskUpdates(rule, property.name, property.updates)
+      })
+    })
+}
+
+// Whether a Property of a Record is hidden from the viewer
+const isPropertyHidden = (recordId, name, viewer) => {
+  return fetchRules([recordId], viewer)
+    .then(ruleMap => isHidden(ruleMap[recordId] || NO_RULE, name))
+}
+
+// Whether a point, in millionths of a degree, is inside an area of
+// [latitude, longitude] points in degrees
+const isInside = (points, { latitude, longitude }) => {
+  const lat = latitude / 1e6
+  const lng = longitude / 1e6
+  return points.reduce((inside, [latA, lngA], i) => {
+    const [latB, lngB] = points[(i + 1) % points.length]
+    const crosses = (latA > lat) !== (latB > lat) &&
+      lng < (lngB - lngA) * (lat - latA) / (latB - latA) + lngA
+    return crosses ? !inside : inside
+  }, false)
+}
+
+// Applies the rules to location reports found within an area. Masked
+// reports are only kept if their region is also within the area, so that
+// searching ever smaller areas cannot reveal more than the region.
+const redactLocationReports = (results, points, viewer) => {
+  return fetchRules(results.map(result => result.recordId), viewer)
+    .then(ruleMap => results
+      .map(result => {
+        const rule = ruleMap[result.recordId] || NO_RULE
+        const reports = result.reports
+          .filter(report => !isHidden(rule, report.propertyName))
+          .map(report => _.assign({}, report, {
+            location: maskValue(rule, report.propertyName)(report.location)
+          }))
+          .filter(report => !rule.regionOnly.includes(report.propertyName) ||
+                  isInside(points, report.location))
+        return _.assign({}, result, { reports })
+      })
+      .filter(result => result.reports.length > 0))
+}
+
+// Applies the rules to the documents returned by a sync, leaving out
+// those belonging to hidden Properties
+const redactChanges = (changes, viewer) => {
+  const recordIds = _.uniq(changes.map(change => change.state.recordId))
+  return fetchRules(recordIds, viewer)
+    .then(ruleMap => changes
+      .filter(({ table, state }) => {
+        const rule = ruleMap[state.recordId] || NO_RULE
+        return !PROPERTY_TABLES.includes(table) || !isHidden(rule, state.name)
+      })
+      .map(change => {
+        const { table, state } = change
+        const rule = ruleMap[state.recordId] || NO_RULE
+        if (table !== 'propertyPages' || !rule.regionOnly.includes(state.name)) {
+          return change
+        }
+        return {
+          table,
+          state: _.assign({}, state, {
+            reportedValues: state.reportedValues.map(reported => {
+              return _.assign({}, reported, {
+                locationValue: maskLocation(reported.locationValue)
+              })
+            })
+          })
+        }
+      }))
+}
+
+// Applies the rules to a public provenance report, which has no viewer
+const redactProvenance = provenance => {
+  const rule = getRule(provenance, null)
+  if (rule === NO_RULE) return provenance
+
+  return _.assign({}, provenance, {
+    properties: provenance.properties
+      .filter(property => !isHidden(rule, property.name))
+      .map(property => {
+        if (!property.value) return property
+        return _.assign({}, property, {
+          value: maskUpdates(rule, property.name, [property.value])[0]
+        })
+      })
+  })
+}
+
+module.exports = {
+  isPropertyHidden,
+  redactChanges,
+  redactLocationReports,
+  redactProperty,
+  redactProvenance,
+  redactRecord
+}
@@ -0,0 +1,511 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const db = require('../db/records')
+const { BadRequest } = require('./errors')
+
+// In the order of PropertySchema.DataType, so numeric types can be read
+const DATA_TYPES = [
+  'TYPE_UNSET',
+  'BYTES',
+  'BOOLEAN',
+  'NUMBER',
+  'STRING',
+  'ENUM',
+  'STRUCT',
+  'LOCATION',
+  'TIMESTAMP',
+  'RECORD_REFERENCE',
+  'ARRAY',
+  'IDENTIFIER'
+]
+
+const getDataType = ({ dataType }) => {
+  return _.isNumber(dataType) ? DATA_TYPES[dataType] : dataType || 'TYPE_UNSET'
+}
+
+// Errors carry the processor's code for a problem as well as its message
+const makeError = (code, message, property = null) => {
+  return { property, code, message }
+}
+
+const isAuthorized = (reporter, timestamp) => {
+  return reporter.authorized &&
+    (!reporter.expiresAt || timestamp < reporter.expiresAt)
+}
+
+// Mirrors the handler's check of struct values against their schemas
+const checkStruct = (values = [], schemas = []) => {
+  if (values.length !== schemas.length) {
+    return 'Provided struct does not match schema length: ' +
+      `${values.length} != ${schemas.length}`
+  }
+
+  for (const schema of schemas) {
+    const value = _.find(values, { name: schema.name })
+    if (!value) {
+      return 'Provided struct missing required property from schema: ' +
+        schema.name
+    }
+    if (getDataType(value) !== schema.dataType) {
+      return `Struct property "${schema.name}" must have data type: ` +
+        schema.dataType
+    }
+    if (value.unit) {
+      return `Struct property "${schema.name}" may not have a unit`
+    }
+    if (schema.dataType === 'STRUCT') {
+      const error = checkStruct(value.structValues, schema.structProperties)
+      if (error) return error
+    } else if (schema.dataType === 'ARRAY') {
+      const error = checkArray(value.arrayValues, schema)
+      if (error) return error
+    }
+  }
+
+  return null
+}
+
+// The most elements an array may have when its schema does not say
+const MAX_ARRAY_LENGTH = 256
+
+// Mirrors the handler's check of array elements against their schema
+const checkArray = (values = [], schema) => {
+  const maxLength = schema.maxLength || MAX_ARRAY_LENGTH
+  if (values.length > maxLength) {
+    return `Array ${schema.name} has ${values.length} elements, more than ` +
+      `the maximum of ${maxLength}`
+  }
+
+  const element = schema.elementSchema || {}
+  for (const value of values) {
+    if (getDataType(value) !== element.dataType) {
+      return `Elements of array ${schema.name} must have data type: ` +
+        element.dataType
+    }
+    if (value.unit) {
+      return `Elements of array ${schema.name} may not have a unit`
+    }
+    const error = element.dataType === 'STRUCT'
+      ? checkStruct(value.structValues, element.structProperties)
+      : element.dataType === 'ARRAY' ? checkArray(value.arrayValues, element)
+      : null
+    if (error) return error
+  }
+
+  return null
+}
+
+// Timestamps from here on are in milliseconds rather than seconds
+const TIMESTAMP_UNIT_BOUNDARY = 10000000000
+
+// How far past the transaction a TIMESTAMP value may be, about a century
+const MAX_TIMESTAMP_VALUE_AHEAD = 100 * 365 * 86400
+
+// The largest value a sint64 can hold, as near as a double can get
+const MAX_SINT64 = 9223372036854775807
+
+const toSeconds = timestamp => {
+  return timestamp >= TIMESTAMP_UNIT_BOUNDARY
+    ? Math.floor(timestamp / 1000)
+    : timestamp
+}
+
+// The longest identifier accepted in any format
+const MAX_IDENTIFIER_LENGTH = 128
+
+const UUID_V4_PATTERN =
+  /^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$/i
+
+const GS1_LENGTHS = [8, 12, 13, 14, 17, 18]
+
+// Mirrors the handler's check of an identifier against its format
+const checkIdentifier = (identifier = '', format) => {
+  if (!identifier) return 'Identifier is empty'
+  if (identifier.length > MAX_IDENTIFIER_LENGTH) {
+    return `Identifier is longer than ${MAX_IDENTIFIER_LENGTH} characters`
+  }
+  if (!/^[!-~]+$/.test(identifier)) {
+    return 'Identifier may only contain printable ASCII without spaces'
+  }
+  if (format === 'UUID_V4' && !UUID_V4_PATTERN.test(identifier)) {
+    return `Identifier is not a version 4 UUID: ${identifier}`
+  }
+  if (format === 'GS1') {
+    if (!/^[0-9]+$/.test(identifier) ||
+        !GS1_LENGTHS.includes(identifier.length)) {
+      return `Identifier is not a GS1 key: ${identifier}`
+    }
+    const digits = identifier.split('').map(Number)
+    const checkDigit = digits.pop()
+    const sum = _.sum(digits.reverse().map((digit, i) => {
+      return digit * (i % 2 === 0 ? 3 : 1)
+    }))
+    if ((10 - sum % 10) % 10 !== checkDigit) {
+      return `Identifier has an incorrect check digit: ${identifier}`
+    }
+  }
+  return null
+}
+
+// Patterns always match whole values, and are a subset of JavaScript's
+// regular expressions
+const toRegExp = pattern => {
+  const body = pattern.replace(/^\^/, '').replace(/([^\\])\$$/, '$1')
+  return new RegExp(`^(?:${body})$`)
+}
+
+// Mirrors the handler's check of a string against its schema
+const checkString = (value = '', { maxLength, stringPattern }) => {
+  if (maxLength && Array.from(value).length > maxLength) {
+    return `is longer than ${maxLength} characters`
+  }
+  if (stringPattern && !toRegExp(stringPattern).test(value)) {
+    return `does not match the pattern ${stringPattern}: ${value}`
+  }
+  return null
+}
+
+// Mirrors the handler's conversion of a value into a reported value
+// Whether a location, as [longitude, latitude] in millionths of a degree,
+// is inside a fence or on one of its edges, mirroring
+// processor/src/locations.rs
+const isInsideFence = ([x, y], fence) => {
+  let inside = false
+  const vertices = fence.map(({ latitude, longitude }) => {
+    return [Number(longitude || 0), Number(latitude || 0)]
+  })
+  for (let i = 0; i < vertices.length; i++) {
+    const [ax, ay] = vertices[i]
+    const [bx, by] = vertices[(i + 1) % vertices.length]
+    const cross = (bx - ax) * (y - ay) - (x - ax) * (by - ay)
+    const withinBox = Math.min(ax, bx) <= x && x <= Math.max(ax, bx) &&
+      Math.min(ay, by) <= y && y <= Math.max(ay, by)
+    if (cross === 0 && withinBox) return true
+    if ((ay > y) !== (by > y) && (cross > 0) === (by > ay)) inside = !inside
+  }
+  return inside
+}
+
+const checkLocation = (name, location = {}, { fence }) => {
+  const latitude = Number(location.latitude || 0)
+  const longitude = Number(location.longitude || 0)
+  if (Math.abs(latitude) > 90e6) {
+    return `Latitude is outside of -90 to 90 degrees: ${latitude}: ${name}`
+  }
+  if (Math.abs(longitude) > 180e6) {
+    return `Longitude is outside of -180 to 180 degrees: ${longitude}: ` +
+      name
+  }
+  const isFenced = fence && fence.length > 0
+  if (isFenced && !isInsideFence([longitude, latitude], fence)) {
+    return `Location of ${name} is outside its fence: ${latitude}, ` +
+      longitude
+  }
+  return null
+}
+
+// Well-known units, as [quantity, scale, offset] to a base unit of the
+// quantity, mirroring processor/src/units.rs
+const UNITS = {
+  kg: ['mass', 1, 0],
+  g: ['mass', 1 / 1000, 0],
+  lb: ['mass', 0.45359237, 0],
+  oz: ['mass', 0.45359237 / 16, 0],
+  C: ['temperature', 1, 0],
+  F: ['temperature', 5 / 9, -160 / 9],
+  K: ['temperature', 1, -273.15]
+}
+
+// Converts a NUMBER value to its Property's unit, approximately, or
+// returns a problem with the value's unit as a string
+const toPropertyUnit = (value, property) => {
+  const number = Number(value.numberValue || 0)
+  const unit = value.unit || ''
+  if (!unit || unit === property.unit) return number
+  if (getDataType(value) !== 'NUMBER') {
+    return `Only NUMBER values may have a unit: ${value.name}`
+  }
+  if (!property.unit) {
+    return `Property ${value.name} has no unit, so its values may not have one`
+  }
+  const from = UNITS[unit]
+  const to = UNITS[property.unit]
+  if (!from || !to || from[0] !== to[0]) {
+    return `Unit ${unit} cannot be converted to ${property.unit}: ${value.name}`
+  }
+  const step = Math.pow(10, property.numberExponent || 0)
+  return Math.round(
+    (number * from[1] + (from[2] - to[2]) / step) / to[1])
+}
+
+const checkValue = (value, property, timestamp) => {
+  const dataType = getDataType(value)
+  if (dataType === 'TYPE_UNSET') {
+    return { code: 'MALFORMED_PAYLOAD', message: 'DataType is not set' }
+  }
+  const number = toPropertyUnit(value, property)
+  if (typeof number === 'string') {
+    return { code: 'INVALID_VALUE', message: number }
+  }
+  if (dataType === 'ENUM' && !property.enumOptions.includes(value.enumValue)) {
+    return {
+      code: 'INVALID_VALUE',
+      message: `Provided enum name is not a valid option: ${value.enumValue}`
+    }
+  }
+  if (dataType === 'STRUCT') {
+    const message = checkStruct(value.structValues, property.structProperties)
+    return message ? { code: 'INVALID_VALUE', message } : null
+  }
+  if (dataType === 'TIMESTAMP') {
+    const timestampValue = Number(value.timestampValue || 0)
+    if (!timestampValue) {
+      return {
+        code: 'INVALID_VALUE',
+        message: `Timestamp value must be set: ${value.name}`
+      }
+    }
+    if (toSeconds(timestampValue) > timestamp + MAX_TIMESTAMP_VALUE_AHEAD) {
+      return {
+        code: 'INVALID_VALUE',
+        message: 'Timestamp value is too far in the future: ' +
+          `${value.name}: ${timestampValue}`
+      }
+    }
+  }
+  if (dataType === 'NUMBER') {
+    const exponent = property.numberExponent || 0
+    if (exponent > 0 && Math.abs(number) * Math.pow(10, exponent) > MAX_SINT64) {
+      return {
+        code: 'INVALID_VALUE',
+        message: `Value of ${value.name} overflows with exponent ${exponent}: ` +
+          number
+      }
+    }
+    const { bounds } = property
+    if (bounds && (number < bounds.min || number > bounds.max)) {
+      return {
+        code: 'INVALID_VALUE',
+        message: `Value of ${value.name} is outside of ${bounds.min} to ` +
+          `${bounds.max}: ${number}`
+      }
+    }
+  }
+  if (dataType === 'ARRAY') {
+    const message = checkArray(value.arrayValues, property)
+    return message ? { code: 'INVALID_VALUE', message } : null
+  }
+  if (dataType === 'STRING') {
+    const problem = checkString(value.stringValue, property)
+    return problem
+      ? { code: 'INVALID_VALUE', message: `Value of ${value.name} ${problem}` }
+      : null
+  }
+  if (dataType === 'LOCATION') {
+    const message = checkLocation(value.name, value.locationValue, property)
+    return message ? { code: 'INVALID_VALUE', message } : null
+  }
+  if (dataType === 'IDENTIFIER') {
+    const message = checkIdentifier(
+      value.identifierValue, property.identifierFormat)
+    return message
+      ? { code: 'INVALID_VALUE', message: `${message}: ${value.name}` }
+      : null
+  }
+  if (dataType === 'RECORD_REFERENCE' && !value.recordReferenceValue) {
+    return {
+      code: 'INVALID_VALUE',
+      message: `Record reference must be set: ${value.name}`
+    }
+  }
+  return null
+}
+
+const validateCreateRecord = (action, signer, state, timestamp) => {
+  const errors = []
+  if (!state.agentExists) {
+    errors.push(makeError('AGENT_NOT_FOUND', `Agent is not register: ${signer}`))
+  }
+  if (state.record) {
+    errors.push(makeError('RECORD_EXISTS',
+                          `Record already exists: ${action.recordId}`))
+  }
+  if (!state.recordType) {
+    errors.push(makeError('RECORD_TYPE_NOT_FOUND',
+                          `Record Type does not exist ${action.recordType}`))
+    return errors
+  }
+
+  const schemas = _.keyBy(state.recordType.properties, 'name')
+  const values = _.keyBy(action.properties, 'name')
+
+  _.filter(schemas, 'required')
+    .filter(schema => !values[schema.name])
+    .forEach(schema => {
+      errors.push(makeError('INVALID_VALUE',
+                            `Required property ${schema.name} not provided`,
+                            schema.name))
+    })
+
+  _.forEach(values, (value, name) => {
+    const schema = schemas[name]
+    let problem = null
+    if (!schema) {
+      problem = {
+        code: 'PROPERTY_NOT_FOUND',
+        message: `Provided property ${name} is not in schemata`
+      }
+    } else if (getDataType(value) !== schema.dataType) {
+      problem = {
+        code: 'INVALID_VALUE',
+        message: `Value provided for ${name} is the wrong type`
+      }
+    } else if (schema.delayed) {
+      problem = {
+        code: 'INVALID_VALUE',
+        message: "Property is 'delayed', and cannot be set at record " +
+          `creation: ${name}`
+      }
+    } else {
+      problem = checkValue(value, schema, timestamp)
+    }
+    if (problem) errors.push(makeError(problem.code, problem.message, name))
+  })
+
+  return errors
+}
+
+// Whether a RecordType's schema lets an Agent report a Property at all
+const isAllowedReporter = (schema, signer) => {
+  const allowed = (schema && schema.allowedReporters) || []
+  return allowed.length === 0 || allowed.includes(signer)
+}
+
+const validateUpdateProperties = (action, signer, state, timestamp) => {
+  if (!state.record) {
+    return [makeError('RECORD_NOT_FOUND',
+                      `Record does not exist: ${action.recordId}`)]
+  }
+  if (state.record.final) {
+    return [makeError('RECORD_FINAL', `Record is final: ${action.recordId}`)]
+  }
+
+  const properties = _.keyBy(state.properties, 'name')
+  const schemas = state.recordType
+    ? _.keyBy(state.recordType.properties, 'name')
+    : {}
+  const masks = _.keyBy(action.structMasks, 'property')
+
+  return action.properties
+    .map(value => {
+      const name = value.name
+      const property = properties[name]
+      const dataType = getDataType(value)
+      let problem = null
+
+      if (!property) {
+        problem = {
+          code: 'PROPERTY_NOT_FOUND',
+          message: `Record does not have provided poperty: ${name}`
+        }
+      } else if (!_.some(property.reporters, reporter => {
+        return reporter.publicKey === signer && isAuthorized(reporter, timestamp)
+      })) {
+        problem = {
+          code: 'NOT_AUTHORIZED',
+          message: `Reporter is not authorized: ${signer}`
+        }
+      } else if (property.fixed) {
+        problem = {
+          code: 'PROPERTY_IMMUTABLE',
+          message: `Property is fixed and cannot be updated: ${name}`
+        }
+      } else if (dataType !== property.dataType) {
+        problem = {
+          code: 'INVALID_VALUE',
+          message: `Update has wrong type: ${dataType} != ${property.dataType}`
+        }
+      } else if (!isAllowedReporter(schemas[name], signer)) {
+        problem = {
+          code: 'NOT_AUTHORIZED',
+          message: `Record type ${state.record.recordType} does not allow ` +
+            `${signer} to report ${name}`
+        }
+      } else if (masks[name] && property.dataType !== 'STRUCT') {
+        problem = {
+          code: 'INVALID_VALUE',
+          message: `Only STRUCT properties can be partially updated: ${name}`
+        }
+      } else if (property.immutable && (property.currentPage !== 1 ||
+                 property.wrapped || !property.firstPageEmpty)) {
+        problem = {
+          code: 'PROPERTY_IMMUTABLE',
+          message: `Property is immutable and already has a value: ${name}`
+        }
+      } else if (!masks[name]) {
+        // A partial struct is only complete once merged with the latest
+        // value, which is left to the processor. Options and struct
+        // schemas are only copied onto older Properties.
+        problem = checkValue(value, schemas[name] || property, timestamp)
+      }
+
+      return problem ? makeError(problem.code, problem.message, name) : null
+    })
+    .filter(error => error)
+}
+
+// Checks a CreateRecord or UpdateProperties payload against the current
+// RecordType and Record, reporting every problem the transaction
+// processor would reject it for, with the processor's codes and messages.
+// The processor stops at the first, and also checks Record rules and any
+// compiled-in plugins, so a valid result is not a guarantee.
+const validate = (body, { authedKey }) => {
+  const signer = body.signer || authedKey
+  const timestamp = Math.floor(Date.now() / 1000)
+
+  return Promise.resolve()
+    .then(() => {
+      if (!signer) {
+        throw new BadRequest('A signer public key is required')
+      }
+
+      const action = body.createRecord || body.updateProperties
+      if (!action || (body.createRecord && body.updateProperties)) {
+        throw new BadRequest(
+          'Payload must have one of createRecord or updateProperties')
+      }
+      if (typeof action.recordId !== 'string' ||
+          !Array.isArray(action.properties || [])) {
+        throw new BadRequest('Payload must have a recordId and properties')
+      }
+      action.properties = action.properties || []
+
+      return db.fetchValidationState(signer, action.recordId,
+                                     String(action.recordType || ''))
+        .then(state => body.createRecord
+          ? validateCreateRecord(action, signer, state, timestamp)
+          : validateUpdateProperties(action, signer, state, timestamp))
+    })
+    .then(errors => ({ valid: errors.length === 0, errors }))
+}
+
+module.exports = {
+  validate
+}
@@ -0,0 +1,118 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const { createHash } = require('crypto')
+const db = require('../db/records')
+const config = require('../system/config')
+const redaction = require('./redaction')
+const { NotFound } = require('./errors')
+
+const NAMESPACE = '3400de'
+
+const hash = (str, length) => {
+  return createHash('sha512').update(str).digest('hex').slice(0, length)
+}
+
+const toPage = num => _.padStart(num.toString(16), 4, '0')
+
+// The Properties whose current values are shown to the public, such as
+// a product's origin or certifications
+const getPublicProperties = () => {
+  return String(config.PUBLIC_PROPERTIES || '')
+    .split(',')
+    .map(name => name.trim())
+    .filter(name => name)
+}
+
+// The state addresses of the entries a provenance report was read from.
+// Anyone can fetch these from a validator at the reported block and
+// check the report against them.
+const getProofAddresses = provenance => {
+  const recordId = provenance.recordId
+  return {
+    record: NAMESPACE + 'ec' + hash(recordId, 62),
+    timeline: _.range(1, provenance.timelinePage + 1).map(page => {
+      return NAMESPACE + 'ed' + hash(recordId, 58) + toPage(page)
+    }),
+    properties: _.fromPairs(provenance.properties.map(property => [
+      property.name,
+      NAMESPACE + 'ea' + hash(recordId, 36) + hash(property.name, 22) +
+        toPage(property.currentPage)
+    ]))
+  }
+}
+
+const formatOwner = owner => ({
+  name: owner.name,
+  publicKey: owner.publicKey,
+  timestamp: owner.timestamp
+})
+
+// A consumer-safe summary of a Record: where it came from, who has owned
+// it, and its public Properties, but not its custodians, reporters,
+// proposals, or other Properties. The lots merged into a Record, or the
+// Record it was merged into, are named so their provenance can be
+// fetched in turn. A recalled Record gives the reason it was recalled, and
+// each auditor's latest attestation is listed with its result.
+const fetchProvenance = ({recordId}) => {
+  return db.fetchProvenance(recordId, getPublicProperties())
+    .then(provenance => {
+      if (!provenance) {
+        throw new NotFound(`No record with the id "${recordId}" exists`)
+      }
+      return redaction.redactProvenance(provenance)
+    })
+    .then(provenance => {
+      return {
+        recordId: provenance.recordId,
+        recordType: provenance.recordType,
+        final: provenance.final,
+        mergedFrom: provenance.parents,
+        mergedInto: provenance.consumedBy || null,
+        archivedAt: provenance.archivedAt || null,
+        recall: provenance.recall && {
+          reason: provenance.recall.reason,
+          recalledAt: provenance.recall.recalledAt
+        },
+        audits: provenance.audits.map(audit => ({
+          auditor: audit.auditor,
+          reviewedThrough: audit.reviewedThrough,
+          attestedAt: audit.timestamp,
+          digest: audit.digest,
+          passed: audit.passed
+        })),
+        origin: provenance.owners.length > 0
+          ? formatOwner(provenance.owners[0])
+          : null,
+        ownership: provenance.owners.map(formatOwner),
+        properties: _.fromPairs(provenance.properties.map(property => [
+          property.name,
+          _.assign({ dataType: property.dataType }, property.value,
+                   property.unit ? { unit: property.unit } : {})
+        ])),
+        proof: _.assign({}, provenance.block, {
+          addresses: getProofAddresses(provenance)
+        })
+      }
+    })
+}
+
+module.exports = {
+  fetchProvenance
+}
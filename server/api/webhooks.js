@@ -0,0 +1,146 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const _ = require('lodash')
+const { URL } = require('url')
+const db = require('../db/webhooks')
+const { BadRequest, NotFound, Unauthorized } = require('./errors')
+
+const MIN_SECRET_LENGTH = 16
+const SCOPES = ['RECORD', 'RECORD_TYPE', 'HELD_RECORDS']
+const ROLES = ['OWNER', 'CUSTODIAN', 'REPORTER']
+
+const checkOwner = ({ publicKey, authedKey }) => {
+  if (publicKey !== authedKey) {
+    throw new Unauthorized('You may only manage your own webhook!')
+  }
+}
+
+const parseUrl = url => {
+  try {
+    const parsed = new URL(url)
+    if (parsed.protocol === 'http:' || parsed.protocol === 'https:') {
+      return parsed.href
+    }
+  } catch (err) {}
+  throw new BadRequest('Webhook url must be an http or https URL')
+}
+
+const isStringArray = value => {
+  return Array.isArray(value) && value.every(item => typeof item === 'string')
+}
+
+const parseThreshold = ({ property, above, below }) => {
+  if (typeof property !== 'string' || !property) {
+    throw new BadRequest('Each threshold must name a property')
+  }
+  const bounds = _.pickBy({ above, below }, bound => bound !== undefined)
+  if (_.isEmpty(bounds) || !_.every(bounds, _.isFinite)) {
+    throw new BadRequest(
+      `Threshold for "${property}" needs a numeric above or below bound`)
+  }
+  return _.assign({ property }, bounds)
+}
+
+// Filters narrow which events a subscription sends to the webhook. They
+// are kept here rather than on-chain, and applied by the notifier.
+const parseFilter = ({ scope, target = '', recordTypes, roles, thresholds }) => {
+  if (!SCOPES.includes(scope)) {
+    throw new BadRequest(`Filter scope must be one of: ${SCOPES.join(', ')}`)
+  }
+  if (typeof target !== 'string') {
+    throw new BadRequest('Filter target must be a string')
+  }
+
+  const filter = { scope, target }
+  if (recordTypes !== undefined) {
+    if (!isStringArray(recordTypes)) {
+      throw new BadRequest('Filter recordTypes must be a list of names')
+    }
+    filter.recordTypes = recordTypes
+  }
+  if (roles !== undefined) {
+    if (!isStringArray(roles) || !roles.every(role => ROLES.includes(role))) {
+      throw new BadRequest(`Filter roles must be from: ${ROLES.join(', ')}`)
+    }
+    filter.roles = roles
+  }
+  if (thresholds !== undefined) {
+    if (!Array.isArray(thresholds)) {
+      throw new BadRequest('Filter thresholds must be a list')
+    }
+    filter.thresholds = thresholds.map(parseThreshold)
+  }
+  return filter
+}
+
+const parseFilters = (filters = []) => {
+  if (!Array.isArray(filters)) {
+    throw new BadRequest('Webhook filters must be a list')
+  }
+  const parsed = filters.map(parseFilter)
+  if (_.uniqBy(parsed, f => `${f.scope}:${f.target}`).length < parsed.length) {
+    throw new BadRequest('Only one filter is allowed per subscription')
+  }
+  return parsed
+}
+
+// Sets where the notifier POSTs events for an Agent's subscriptions, and
+// the secret it signs them with, replacing any filters
+const update = ({ url, secret, filters }, params) => {
+  return Promise.resolve()
+    .then(() => checkOwner(params))
+    .then(() => {
+      if (typeof secret !== 'string' || secret.length < MIN_SECRET_LENGTH) {
+        throw new BadRequest(
+          `Webhook secret must be at least ${MIN_SECRET_LENGTH} characters`)
+      }
+      const webhook = {
+        publicKey: params.publicKey,
+        url: parseUrl(url),
+        filters: parseFilters(filters)
+      }
+      return db.upsert(_.assign({ secret }, webhook))
+        .then(() => webhook)
+    })
+}
+
+const fetch = params => {
+  return Promise.resolve()
+    .then(() => checkOwner(params))
+    .then(() => db.fetch(params.publicKey))
+    .then(webhook => {
+      if (!webhook) {
+        throw new NotFound(`No webhook is set for "${params.publicKey}"`)
+      }
+      return _.pick(webhook, 'publicKey', 'url', 'filters')
+    })
+}
+
+const remove = params => {
+  return Promise.resolve()
+    .then(() => checkOwner(params))
+    .then(() => db.remove(params.publicKey))
+    .then(() => ({ publicKey: params.publicKey, url: null, filters: [] }))
+}
+
+module.exports = {
+  update,
+  fetch,
+  remove
+}
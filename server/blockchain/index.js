@@ -20,17 +20,37 @@ const _ = require('lodash')
 const { Stream } = require('sawtooth-sdk/messaging/stream')
 const {
   Message,
+  BlockHeader,
   ClientBatchSubmitRequest,
   ClientBatchSubmitResponse,
   ClientBatchStatus,
   ClientBatchStatusRequest,
-  ClientBatchStatusResponse
+  ClientBatchStatusResponse,
+  ClientBlockGetByBatchIdRequest,
+  ClientBlockGetByIdRequest,
+  ClientBlockGetResponse,
+  ClientReceiptGetRequest,
+  ClientReceiptGetResponse,
+  ClientStateGetRequest,
+  ClientStateGetResponse,
+  ClientStateListRequest,
+  ClientStateListResponse,
+  Setting,
+  TransactionHeader
 } = require('sawtooth-sdk/protobuf')
 
 const batcher = require('./batcher')
+const protos = require('./protos')
+const db = require('../db')
+const batches = require('../db/batches')
 const config = require('../system/config')
+const {
+  TIMESTAMP_UNIT_SETTING,
+  TIMESTAMP_UNIT_SETTING_ADDRESS
+} = require('../../shared/setting_addresses')
 
 const VALIDATOR_URL = config.VALIDATOR_URL
+const FAMILY_NAME = 'supply_chain'
 const stream = new Stream(VALIDATOR_URL)
 
 const connect = () => {
@@ -42,59 +62,322 @@ const connect = () => {
     })
 }
 
+// The transaction processor appends "(code: C, action: X, record: Y,
+// signer: Z)" to invalid transaction messages, split it back out into
+// fields. The code keys the message catalog served at /messages.
+const CONTEXT_PATTERN = /^(.*) \(([a-z_]+: [^,()]*(?:, [a-z_]+: [^,()]*)*)\)$/
+
+const parseInvalidTransaction = ({ transactionId, message, extendedData }) => {
+  const reason = { transactionId, message, code: null, context: {} }
+  const match = CONTEXT_PATTERN.exec(message)
+  if (match) {
+    reason.message = match[1]
+    match[2].split(', ').forEach(pair => {
+      const [ key, value ] = pair.split(': ')
+      reason.context[key] = value
+    })
+    reason.code = reason.context.code || null
+  }
+  if (extendedData && extendedData.length > 0) {
+    reason.extendedData = Buffer.from(extendedData).toString('base64')
+  }
+  return reason
+}
+
+const formatBatchStatus = statusBody => ({
+  status: _.findKey(ClientBatchStatus.Status,
+                    val => val === statusBody.status),
+  invalidTransactions: statusBody.invalidTransactions
+    .map(parseInvalidTransaction)
+})
+
+// Record the latest known status of a submitted batch, logging rather
+// than failing the submission if the database write does not succeed
+const saveBatchStatus = (batchId, status) => {
+  return batches.update(batchId, _.assign({ updated: Date.now() }, status))
+    .catch(err => console.warn(`Unable to save batch ${batchId}:`, err))
+}
+
+const fetchBatchStatuses = (batchIds, wait = null) => {
+  return stream.send(
+    Message.MessageType.CLIENT_BATCH_STATUS_REQUEST,
+    ClientBatchStatusRequest.encode({
+      batchIds,
+      wait: wait !== null,
+      timeout: wait || 0
+    }).finish()
+  )
+  .then(statusResponse => {
+    return ClientBatchStatusResponse
+      .decode(statusResponse)
+      .batchStatuses
+  })
+}
+
+// Fetch the block a committed batch was included in, identifying the
+// point in the chain a client's reads must reach to see its own writes
+const fetchCommitBlock = batchId => {
+  return stream.send(
+    Message.MessageType.CLIENT_BLOCK_GET_BY_BATCH_ID_REQUEST,
+    ClientBlockGetByBatchIdRequest.encode({ batchId }).finish()
+  )
+  .then(response => ClientBlockGetResponse.decode(response))
+  .then(({ status, block }) => {
+    if (status !== ClientBlockGetResponse.Status.OK) {
+      const name = _.findKey(ClientBlockGetResponse.Status,
+                             val => val === status)
+      throw new Error(`Unable to fetch block for batch ${batchId}: ${name}`)
+    }
+    const header = BlockHeader.decode(block.header)
+    return {
+      blockNum: parseInt(header.blockNum.toString()),
+      blockId: block.headerSignature,
+      stateRootHash: header.stateRootHash
+    }
+  })
+}
+
+// Query the validator for any batches that are not yet final, and update
+// the stored copies with the results
+const refreshBatches = stored => {
+  const pending = stored
+    .filter(batch => batch.status === 'PENDING' || batch.status === 'UNKNOWN')
+    .map(batch => batch.batchId)
+  if (pending.length === 0) return Promise.resolve(stored)
+
+  return fetchBatchStatuses(pending)
+    .then(statuses => Promise.all(statuses.map(statusBody => {
+      const status = formatBatchStatus(statusBody)
+      return saveBatchStatus(statusBody.batchId, status)
+        .then(() => [statusBody.batchId, status])
+    })))
+    .then(_.fromPairs)
+    .then(updates => stored.map(batch => {
+      return _.assign({}, batch, updates[batch.batchId])
+    }))
+}
+
+// Reads an on-chain setting at the chain head, resolving null if unset
+const fetchSetting = (key, address) => {
+  return stream.send(
+    Message.MessageType.CLIENT_STATE_GET_REQUEST,
+    ClientStateGetRequest.encode({ address }).finish()
+  )
+  .then(response => ClientStateGetResponse.decode(response))
+  .then(({ status, value }) => {
+    if (status === ClientStateGetResponse.Status.NO_RESOURCE) return null
+    if (status !== ClientStateGetResponse.Status.OK) {
+      const name = _.findKey(ClientStateGetResponse.Status,
+                             val => val === status)
+      throw new Error(`Unable to fetch setting ${key}: ${name}`)
+    }
+    const entry = _.find(Setting.decode(value).entries, { key })
+    return entry ? entry.value : null
+  })
+}
+
+// The unit payload timestamps must be in, "seconds" unless the network
+// has set it to "milliseconds"
+const fetchTimestampUnit = () => {
+  return fetchSetting(TIMESTAMP_UNIT_SETTING, TIMESTAMP_UNIT_SETTING_ADDRESS)
+    .then(unit => unit || 'seconds')
+}
+
+// Lists the Records and Agents each Supply Chain transaction in a block
+// touched, from the ActivityReceipts the transaction processor adds,
+// resolving null if the validator has no block with that id
+const fetchBlockActivity = blockId => {
+  return stream.send(
+    Message.MessageType.CLIENT_BLOCK_GET_BY_ID_REQUEST,
+    ClientBlockGetByIdRequest.encode({ blockId }).finish()
+  )
+  .then(response => ClientBlockGetResponse.decode(response))
+  .then(({ status, block }) => {
+    if (status === ClientBlockGetResponse.Status.NO_RESOURCE) return null
+    if (status !== ClientBlockGetResponse.Status.OK) {
+      const name = _.findKey(ClientBlockGetResponse.Status,
+                             val => val === status)
+      throw new Error(`Unable to fetch block ${blockId}: ${name}`)
+    }
+
+    const header = BlockHeader.decode(block.header)
+    const transactionIds = _.chain(block.batches)
+      .flatMap(batch => batch.transactions)
+      .filter(txn => {
+        return TransactionHeader.decode(txn.header).familyName === FAMILY_NAME
+      })
+      .map(txn => txn.headerSignature)
+      .value()
+
+    const blockInfo = {
+      blockNum: parseInt(header.blockNum.toString()),
+      blockId: block.headerSignature
+    }
+    if (transactionIds.length === 0) {
+      return _.assign(blockInfo, { recordIds: [], agents: [], transactions: [] })
+    }
+
+    return fetchReceipts(transactionIds)
+      .then(receipts => {
+        const transactions = receipts.map(({ transactionId, data }) => {
+          // A transaction's ActivityReceipt is added last, after the
+          // BulkUpdateReceipt of each bulk update it made
+          const { recordIds, agents } = _.isEmpty(data)
+            ? { recordIds: [], agents: [] }
+            : protos.ActivityReceipt.decode(_.last(data))
+          const bulkUpdates = _.initial(data)
+            .map(bytes => protos.BulkUpdateReceipt.decode(bytes).entries)
+          return { transactionId, recordIds, agents, bulkUpdates }
+        })
+
+        return _.assign(blockInfo, {
+          recordIds: _.chain(transactions)
+            .flatMap('recordIds').uniq().sortBy().value(),
+          agents: _.chain(transactions)
+            .flatMap('agents').uniq().sortBy().value(),
+          transactions
+        })
+      })
+  })
+}
+
+// Lists the entries at every address starting with a prefix at the chain
+// head, following the validator's paging
+const listState = (address, start = '') => {
+  return stream.send(
+    Message.MessageType.CLIENT_STATE_LIST_REQUEST,
+    ClientStateListRequest.encode({ address, paging: { start } }).finish()
+  )
+  .then(response => ClientStateListResponse.decode(response))
+  .then(({ status, entries, paging }) => {
+    if (status === ClientStateListResponse.Status.NO_RESOURCE) return []
+    if (status !== ClientStateListResponse.Status.OK) {
+      const name = _.findKey(ClientStateListResponse.Status,
+                             val => val === status)
+      throw new Error(`Unable to list state under ${address}: ${name}`)
+    }
+    if (!paging || !paging.next) return entries
+    return listState(address, paging.next)
+      .then(rest => entries.concat(rest))
+  })
+}
+
+// Reads the daily RecordTypeStats stored under an address prefix, which
+// are kept in state by the transaction processor rather than synced
+const fetchRecordTypeStats = address => {
+  return listState(address)
+    .then(entries => _.flatMap(entries, ({ value }) => {
+      return protos.RecordTypeStatsContainer.decode(value).entries
+        .map(stats => protos.RecordTypeStats.toObject(stats, {
+          longs: Number,
+          defaults: true
+        }))
+    }))
+}
+
+// Reads the RecordTemplates stored at an address, which are also kept
+// in state rather than synced
+const fetchRecordTemplates = address => {
+  return listState(address)
+    .then(entries => _.flatMap(entries, ({ value }) => {
+      return protos.RecordTemplateContainer.decode(value).entries
+        .map(template => protos.RecordTemplate.toObject(template, {
+          enums: String,
+          longs: Number,
+          defaults: true
+        }))
+    }))
+}
+
+const fetchReceipts = transactionIds => {
+  return stream.send(
+    Message.MessageType.CLIENT_RECEIPT_GET_REQUEST,
+    ClientReceiptGetRequest.encode({ transactionIds }).finish()
+  )
+  .then(response => ClientReceiptGetResponse.decode(response))
+  .then(({ status, receipts }) => {
+    if (status !== ClientReceiptGetResponse.Status.OK) {
+      const name = _.findKey(ClientReceiptGetResponse.Status,
+                             val => val === status)
+      throw new Error(`Unable to fetch transaction receipts: ${name}`)
+    }
+    return receipts
+  })
+}
+
 const submit = (txnBytes, { wait }) => {
   const batch = batcher.batch(txnBytes)
+  const batchId = batch.headerSignature
+  const submitter = TransactionHeader
+    .decode(batch.transactions[0].header)
+    .signerPublicKey
 
-  return stream.send(
+  return batches.insert({
+    batchId,
+    submitter,
+    transactionIds: batch.transactions.map(txn => txn.headerSignature),
+    submitted: Date.now(),
+    updated: Date.now(),
+    status: 'PENDING',
+    invalidTransactions: []
+  })
+  .catch(err => console.warn(`Unable to save batch ${batchId}:`, err))
+  .then(() => stream.send(
     Message.MessageType.CLIENT_BATCH_SUBMIT_REQUEST,
     ClientBatchSubmitRequest.encode({
       batches: [batch]
     }).finish()
-  )
+  ))
   .then(response => ClientBatchSubmitResponse.decode(response))
   .then((decoded) => {
     const submitStatus = _.findKey(ClientBatchSubmitResponse.Status,
                              val => val === decoded.status)
     if (submitStatus !== 'OK') {
-      throw new Error(`Batch submission failed with status '${submitStatus}'`)
+      return saveBatchStatus(batchId, { status: 'REJECTED', submitStatus })
+        .then(() => {
+          throw new Error(
+            `Batch submission failed with status '${submitStatus}'`)
+        })
     }
 
     if (wait === null) {
       return { batch: batch.headerSignature }
     }
 
-    return stream.send(
-      Message.MessageType.CLIENT_BATCH_STATUS_REQUEST,
-      ClientBatchStatusRequest.encode({
-        batchIds: [batch.headerSignature],
-        wait: true,
-        timeout: wait
-      }).finish()
-    )
-    .then(statusResponse => {
-      const statusBody = ClientBatchStatusResponse
-        .decode(statusResponse)
-        .batchStatuses[0]
-
+    return fetchBatchStatuses([batchId], wait)
+    .then(statuses => {
+      const statusBody = statuses[0]
+      const status = formatBatchStatus(statusBody)
+      return saveBatchStatus(batchId, status).then(() => [statusBody, status])
+    })
+    .then(([ statusBody, { status } ]) => {
       if (statusBody.status !== ClientBatchStatus.Status.COMMITTED) {
         const id = statusBody.batchId
-        const status = _.findKey(ClientBatchStatus.Status,
-                                 val => val === statusBody.status)
         const message = statusBody.invalidTransactions.length > 0
           ? statusBody.invalidTransactions[0].message
           : ''
         throw new Error(`Batch ${id} is ${status}, with message: ${message}`)
       }
 
-      // Wait to return until new block is in database
-      return new Promise(resolve => setTimeout(() => {
-        resolve({ batch: batch.headerSignature })
-      }, 1000))
+      // Wait to return until new block is in database, so the client
+      // can read its own write, and hand back the block as a token for
+      // the min_block parameter in case the database is lagging
+      return fetchCommitBlock(batchId)
+        .then(block => saveBatchStatus(batchId, block).then(() => block))
+        .then(block => {
+          return db.awaitBlock(block.blockNum, config.MIN_BLOCK_WAIT)
+            .then(() => _.assign({ batch: batch.headerSignature }, block))
+        })
     })
   })
 }
 
 module.exports = {
   connect,
-  submit
+  fetchTimestampUnit,
+  fetchBlockActivity,
+  fetchRecordTypeStats,
+  fetchRecordTemplates,
+  submit,
+  refreshBatches
 }
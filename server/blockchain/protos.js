@@ -36,7 +36,9 @@ const compile = () => {
   return Promise.all([
     loadProtos('agent.proto', [
       'Agent',
-      'AgentContainer'
+      'AgentContainer',
+      'AgentActivityPage',
+      'AgentActivityPageContainer'
     ]),
     loadProtos('property.proto', [
       'Property',
@@ -45,17 +47,27 @@ const compile = () => {
       'PropertyPageContainer',
       'PropertySchema',
       'PropertyValue',
-      'Location'
+      'Location',
+      'ExcursionLog',
+      'ExcursionLogContainer'
     ]),
     loadProtos('proposal.proto', [
       'Proposal',
-      'ProposalContainer'
+      'ProposalContainer',
+      'ProposalHistoryPage',
+      'ProposalHistoryPageContainer',
+      'OpenProposalIndex',
+      'OpenProposalIndexContainer'
     ]),
     loadProtos('record.proto', [
       'Record',
       'RecordContainer',
       'RecordType',
-      'RecordTypeContainer'
+      'RecordTypeContainer',
+      'RecordTemplate',
+      'RecordTemplateContainer',
+      'RecordTimelinePage',
+      'RecordTimelinePageContainer'
     ]),
     loadProtos('payload.proto', [
       'SCPayload',
@@ -66,7 +78,64 @@ const compile = () => {
       'UpdatePropertiesAction',
       'CreateProposalAction',
       'AnswerProposalAction',
-      'RevokeReporterAction'
+      'RevokeReporterAction',
+      'ExecuteTransferAction',
+      'MessageAction',
+      'SubscribeAction',
+      'UnsubscribeAction',
+      'CompositeAction',
+      'ReceiveConfirmationAction',
+      'AnchorStateAction',
+      'CloneRecordAction',
+      'AnchorFeedbackAction',
+      'MigrateStateAction',
+      'DeleteRecordTypeAction',
+      'AcknowledgeValueAction',
+      'UpdateRecordTypeAction',
+      'DirectTransferAction',
+      'TransferAuthorization',
+      'BulkUpdatePropertiesAction',
+      'DelegateReporterAction',
+      'MergeRecordsAction',
+      'ArchiveRecordAction',
+      'ReopenRecordAction',
+      'RecallRecordAction',
+      'SetAgentRoleAction',
+      'SetRecordMetadataAction',
+      'AttestAuditAction',
+      'CancelAllProposalsAction',
+      'AmendProposalAction',
+      'ReleaseEscrowAction',
+      'DeactivateAgentAction',
+      'CreateRecordTemplateAction',
+      'CreateRecordFromTemplateAction'
+    ]),
+    loadProtos('anchor.proto', [
+      'StateAnchor',
+      'StateAnchorContainer'
+    ]),
+    loadProtos('feedback.proto', [
+      'FeedbackLog',
+      'FeedbackLogContainer',
+      'FeedbackPage',
+      'FeedbackPageContainer'
+    ]),
+    loadProtos('message.proto', [
+      'MessageAnchor',
+      'MessageAnchorContainer'
+    ]),
+    loadProtos('receipt.proto', [
+      'ActivityReceipt',
+      'BulkUpdateReceipt'
+    ]),
+    loadProtos('stats.proto', [
+      'RecordTypeStats',
+      'RecordTypeStatsContainer'
+    ]),
+    loadProtos('subscription.proto', [
+      'Subscription',
+      'SubscriptionList',
+      'SubscriptionListContainer'
     ])
   ])
 }
@@ -74,6 +74,8 @@ const listQuery = filterQuery => block => {
     .map(agent => r.expr({
       'name': getName(agent),
       'key': getPublicKey(agent),
+      'role': agent('role').default('OPERATOR'),
+      'deactivatedAt': agent('deactivatedAt').default(0),
       'owns': getTable('records', block)
         .filter(isRecordOwner(agent))
         .map(getRecordId)
@@ -92,8 +94,13 @@ const listQuery = filterQuery => block => {
 const fetchQuery = (publicKey, auth) => block => {
   return getTable('agents', block)
     .filter(hasPublicKey(publicKey))
-    .pluck('name', 'publicKey')
+    .pluck('name', 'publicKey', 'role', 'deactivatedAt', 'deactivatedBy')
     .nth(0)
+    .merge(agent => ({
+      role: agent('role').default('OPERATOR'),
+      deactivatedAt: agent('deactivatedAt').default(0),
+      deactivatedBy: agent('deactivatedBy').default('')
+    }))
     .do(
       agent => {
         return r.branch(
@@ -111,12 +118,55 @@ const fetchUser = publicKey => {
     .nth(0)
 }
 
+const fetchSubscriptionsQuery = publicKey => block => {
+  return getTable('subscriptions', block)
+    .filter(r.row('agentId').eq(publicKey))
+    .coerceTo('array')
+    .do(lists => r.branch(
+      lists.isEmpty(),
+      [],
+      lists.nth(0)('subscriptions')))
+}
+
+// Resolves null if there is no such Agent
+const fetchActivityPageQuery = (publicKey, pageNum) => block => {
+  const getActivityPage = agent => {
+    const currentPage = agent('activityPage').default(0)
+    return r.expr(pageNum).default(currentPage).do(page => {
+      return getTable('agentActivityPages', block)
+        .filter(r.row('agentId').eq(publicKey))
+        .filter({ pageNum: page })
+        .coerceTo('array')
+        .do(pages => r.expr({
+          'agentId': publicKey,
+          'pageNum': page,
+          'currentPage': currentPage,
+          'entries': r.branch(pages.isEmpty(), [], pages(0)('entries'))
+        }))
+    })
+  }
+
+  return getTable('agents', block)
+    .filter(hasPublicKey(publicKey))
+    .nth(0)
+    .default(null)
+    .do(agent => r.branch(agent.eq(null), null, getActivityPage(agent)))
+}
+
 const list = filterQuery => db.queryWithCurrentBlock(listQuery(filterQuery))
 
 const fetch = (publicKey, auth) =>
       db.queryWithCurrentBlock(fetchQuery(publicKey, auth))
 
+const fetchSubscriptions = publicKey =>
+      db.queryWithCurrentBlock(fetchSubscriptionsQuery(publicKey))
+
+const fetchActivityPage = (publicKey, pageNum) =>
+      db.queryWithCurrentBlock(fetchActivityPageQuery(publicKey, pageNum))
+
 module.exports = {
   list,
-  fetch
+  fetch,
+  fetchSubscriptions,
+  fetchActivityPage
 }
@@ -0,0 +1,51 @@
+/**
+ * Copyright 2017 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const r = require('rethinkdb')
+const db = require('./')
+
+// Batches submitted through the server, keyed by batch header signature.
+// Statuses mirror the validator's ClientBatchStatus names, plus REJECTED
+// for batches the validator refused to accept at submission.
+const insert = batch => db.insertTable('batches', batch)
+
+const update = (batchId, changes) => {
+  return db.modifyTable('batches', batches => {
+    return batches.get(batchId).update(changes)
+  })
+}
+
+const fetch = batchId => {
+  return db.queryTable('batches', batches => batches.get(batchId), false)
+}
+
+const list = (submitter, limit) => {
+  return db.queryTable('batches', batches => {
+    return batches
+      .getAll(submitter, { index: 'submitter' })
+      .orderBy(r.desc('submitted'))
+      .limit(limit)
+  })
+}
+
+module.exports = {
+  insert,
+  update,
+  fetch,
+  list
+}
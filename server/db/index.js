@@ -26,6 +26,7 @@ const PORT = config.DB_PORT
 const NAME = config.DB_NAME
 const RETRY_WAIT = config.RETRY_WAIT
 const AWAIT_TABLE = 'blocks'
+const BLOCK_POLL_INTERVAL = 100
 
 // Connection to db for query methods, run connect before querying
 let connection = null
@@ -83,6 +84,30 @@ const queryWithCurrentBlock = query => {
   )
 }
 
+const fetchCurrentBlockNum = () => {
+  return runQuery(
+    r.table('blocks')
+      .orderBy(r.desc('blockNum'))
+      .nth(0)('blockNum')
+      .default(-1)
+  )
+}
+
+// Resolves true once ledger sync has written the block with the passed
+// number, or false if it has not done so before the timeout (in ms)
+const awaitBlock = (blockNum, timeout) => {
+  const deadline = Date.now() + timeout
+  const poll = () => {
+    return fetchCurrentBlockNum()
+      .then(current => {
+        if (current >= blockNum) return true
+        if (Date.now() >= deadline) return false
+        return promisedTimeout(poll, BLOCK_POLL_INTERVAL)
+      })
+  }
+  return poll()
+}
+
 // Runs a specified query against a database table
 const queryTable = (table, query, removeCursor = true) => {
   return query(r.table(table))
@@ -152,6 +177,7 @@ module.exports = {
   connect,
   runQuery,
   queryWithCurrentBlock,
+  awaitBlock,
   queryTable,
   modifyTable,
   insertTable,
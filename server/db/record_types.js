@@ -38,7 +38,8 @@ const arrayToObject = namedResources => {
 const publishRecordType = type => {
   return r.expr({
     name: type('name'),
-    properties: arrayToObject(type('properties'))
+    properties: arrayToObject(type('properties')),
+    retentionRules: type('retentionRules').default([])
   })
 }
 
@@ -39,10 +39,20 @@ const getAssociatedAgentId = role => record => record(role).nth(-1)('agentId')
 const getOwnerId = getAssociatedAgentId('owners')
 const getCustodianId = getAssociatedAgentId('custodians')
 
-const getAssociatedAgents =
-      role => record => record(role).orderBy(r.desc('timestamp'))
-const getOwners = getAssociatedAgents('owners')
-const getCustodians = getAssociatedAgents('custodians')
+// Holders are read from the Record's timeline pages, falling back to the
+// Record itself for Records synced before timelines existed. Custodians
+// include their receipt confirmation, once they have made one, and
+// holders the terms of the Proposal they took the role by.
+const getAssociatedAgents = (role, timelineRole) => block => record => {
+  return getTimelineEntries(getRecordId(record))(block)
+    .filter(entry => entry('role').eq(timelineRole))
+    .pluck('agentId', 'timestamp', 'receipt', 'terms')
+    .coerceTo('array')
+    .do(entries => r.branch(entries.isEmpty(), record(role), entries))
+    .orderBy(r.desc('timestamp'))
+}
+const getOwners = getAssociatedAgents('owners', 'OWNER')
+const getCustodians = getAssociatedAgents('custodians', 'CUSTODIAN')
 
 const hasAttribute = getAttr => attr => obj => r.eq(attr, getAttr(obj))
 const hasName = hasAttribute(getName)
@@ -61,11 +71,33 @@ const getTable = (tableName, block) => {
   return r.table(tableName).filter(hasBlock(block))
 }
 
+const getTimelinePages = recordId => block => {
+  return getTable('recordTimelinePages', block)
+    .filter(hasRecordId(recordId))
+}
+
+const getTimelineEntries = recordId => block => {
+  return getTimelinePages(recordId)(block)
+    .orderBy('pageNum')
+    .concatMap(page => page('entries'))
+}
+
+// Proposals past their expiration stay open in state until an answer or
+// a new Proposal closes them, but can no longer be accepted
+const isUnlapsed = proposal => {
+  return proposal('expiration').default(0).do(expiration => {
+    return r.or(expiration.eq(0), expiration.gt(r.now().toEpochTime()))
+  })
+}
+
 const getProposals = recordId => receivingAgent => block => {
   return getTable('proposals', block)
     .filter(hasRecordId(recordId))
     .filter(hasStatus('OPEN'))
-    .pluck('receivingAgent', 'issuingAgent', 'role', 'properties')
+    .filter(isUnlapsed)
+    .pluck('receivingAgent', 'issuingAgent', 'role', 'properties', 'terms',
+           'previousProposal', 'toHolder', 'approvers', 'approvals',
+           'amendedAt', 'transferTerms')
     .coerceTo('array')
 }
 
@@ -113,8 +145,12 @@ const getValue = dataType => value => {
     r.eq(dataType, 'STRING'), value('stringValue'),
     r.eq(dataType, 'BYTES'), value('bytesValue'),
     r.eq(dataType, 'LOCATION'), value('locationValue'),
+    r.eq(dataType, 'TIMESTAMP'), value('timestampValue'),
+    r.eq(dataType, 'RECORD_REFERENCE'), value('recordReferenceValue'),
     r.eq(dataType, 'ENUM'), value('enumValue'),
     r.eq(dataType, 'STRUCT'), value('structValue'),
+    r.eq(dataType, 'ARRAY'), value('arrayValue'),
+    r.eq(dataType, 'IDENTIFIER'), value('identifierValue'),
     value('bytesValue') // if dataType is unknown, use bytesValue
   )
 }
@@ -123,18 +159,32 @@ const getUpdate = dataType => reporterKeys => block => value => {
   return r.expr({
     'value': getValue(dataType)(value),
     'timestamp': value('timestamp'),
-    'reporter': getReporter(reporterKeys.map(getPublicKey).nth(value('reporterIndex')))(block)
+    'reporter': getReporter(
+      reporterKeys
+        .filter(reporter => reporter('index').eq(value('reporterIndex')))
+        .nth(0)('publicKey')
+    )(block)
   })
 }
 
+// The properties of a record, in the order its type lists them. Records
+// made with an earlier version of their type keep the properties of that
+// version, so any the current version no longer has come last.
 const getTypeProperties = record => block => {
   return getTable('recordTypes', block)
     .filter(hasName(getRecordType(record)))
     .map(getProperties)
     .map(getName)
     .nth(0)
-    .map(findProperty(getRecordId(record))(block))
-    .coerceTo('array')
+    .default([])
+    .do(names => {
+      return getTable('properties', block)
+        .filter(hasRecordId(getRecordId(record)))
+        .orderBy(property => {
+          return names.offsetsOf(getName(property)).nth(0).default(names.count())
+        })
+        .coerceTo('array')
+    })
 }
 
 const getPropertyValues = recordId => block => property => {
@@ -144,8 +194,10 @@ const getPropertyValues = recordId => block => property => {
         'name': getName(property),
         'dataType': dataType,
         fixed: property('fixed'),
+        immutable: property('immutable').default(false),
         numberExponent: property('numberExponent'),
         unit: property('unit'),
+        threshold: property('threshold').default(null),
         'reporterKeys': reporterKeys,
         'values': findReportedValues(recordId)(getName(property))(dataType)(reporterKeys)(block)
       })
@@ -173,9 +225,17 @@ const makePropertiesEntry = propertyValues => {
     .default({})
 }
 
+// Reporters with an expiry are listed until it passes, after which the
+// transaction processor no longer accepts their updates
+const isUnexpired = reporter => {
+  return reporter('expiresAt').default(0).do(expiresAt => {
+    return r.or(expiresAt.eq(0), expiresAt.gt(r.now().toEpochTime()))
+  })
+}
+
 const getAuthorizedReporterKeys = propertyValue => {
   return propertyValue('reporterKeys')
-    .filter(getAuthorization)
+    .filter(reporter => r.and(getAuthorization(reporter), isUnexpired(reporter)))
     .map(getPublicKey)
     .coerceTo('array')
 }
@@ -203,9 +263,23 @@ const _loadRecord = (block, authedKey) => (record) => {
     .map(getPropertyValues(recordId)(block)).do(propertyValues => {
       return r.expr({
         'recordId': getRecordId(record),
+        'recordType': getRecordType(record),
         'owner': getOwnerId(record),
         'custodian': getCustodianId(record),
         'final': getFinal(record),
+        'finalizedAt': record('finalizedAt').default(0),
+        'unacknowledged': record('unacknowledged').default([]),
+        'parents': record('parents').default([]),
+        'consumedBy': record('consumedBy').default(''),
+        'archivedAt': record('archivedAt').default(0),
+        'prunedProperties': record('prunedProperties').default([]),
+        'archivedDigest': record('archivedDigest').default(''),
+        'reopenRequest': record('reopenRequest').default(null),
+        'reopenings': record('reopenings').default([]),
+        'recall': record('recall').default(null),
+        'metadata': record('metadata').default([]),
+        'audits': record('audits').default([]),
+        'escrow': record('escrow').default(null),
         'properties': propertyValues
           .map(propertyValue => r.expr({
             'name': getName(propertyValue),
@@ -226,14 +300,22 @@ const _loadRecord = (block, authedKey) => (record) => {
             propertyValue('fixed'),
             { fixed: propertyValue('fixed') },
             {}
+          )).merge(r.branch(
+            propertyValue('immutable'),
+            { immutable: propertyValue('immutable') },
+            {}
           )).merge(r.branch(
             propertyValue('unit').ne(''),
             { unit: propertyValue('unit') },
             {}
+          )).merge(r.branch(
+            propertyValue('threshold').ne(null),
+            { threshold: propertyValue('threshold') },
+            {}
           ))),
         'updates': r.expr({
-          'owners': getOwners(record),
-          'custodians': getCustodians(record),
+          'owners': getOwners(block)(record),
+          'custodians': getCustodians(block)(record),
           'properties': makePropertiesEntry(propertyValues)
         }),
         'proposals': getProposals(recordId)(authedKey)(block)
@@ -252,6 +334,194 @@ const listRecordsQuery = (authedKey, filterQuery) => block => {
     .coerceTo('array')
 }
 
+const fetchTimelinePageQuery = (recordId, pageNum) => block => {
+  return findRecord(recordId)(block).do(record => {
+    return r.expr(pageNum).default(record('timelinePage')).do(page => {
+      return getTimelinePages(recordId)(block)
+        .filter({ pageNum: page })
+        .coerceTo('array')
+        .do(pages => r.expr({
+          'recordId': recordId,
+          'pageNum': page,
+          'currentPage': record('timelinePage'),
+          'entries': r.branch(pages.isEmpty(), [], pages(0)('entries'))
+        }))
+    })
+  })
+}
+
+const fetchTimelineEntriesQuery = recordId => block => {
+  return getTimelineEntries(recordId)(block).coerceTo('array')
+}
+
+const listMessagesQuery = (recordId, digest) => block => {
+  return getTable('messageAnchors', block)
+    .filter(hasRecordId(recordId))
+    .filter(anchor => r.expr(digest).eq(null).or(anchor('digest').eq(digest)))
+    .orderBy('timestamp')
+    .pluck('digest', 'sender', 'recipient', 'recordId',
+           'proposalTimestamp', 'timestamp')
+    .coerceTo('array')
+}
+
+const listFeedbackQuery = (recordId, kind) => block => {
+  return getTable('feedbackPages', block)
+    .filter(hasRecordId(recordId))
+    .concatMap(page => page('entries'))
+    .filter(entry => r.expr(kind).eq(null).or(entry('kind').eq(kind)))
+    .orderBy(r.desc('timestamp'))
+    .coerceTo('array')
+}
+
+const listExcursionsQuery = (recordId, name) => block => {
+  return getTable('excursionLogs', block)
+    .filter(hasRecordId(recordId))
+    .filter(hasName(name))
+    .coerceTo('array')
+    .do(logs => r.branch(logs.isEmpty(), [], logs.nth(0)('excursions')))
+}
+
+// The public provenance of a Record: who has owned it and the current
+// values of the named Properties, along with the block they were read at.
+// Resolves to null if there is no such Record.
+const fetchProvenanceQuery = (recordId, propertyNames) => block => {
+  return getTable('records', block)
+    .filter(hasRecordId(recordId))
+    .coerceTo('array')
+    .do(records => r.branch(records.isEmpty(), null, records(0).do(record => {
+      return r.expr({
+        recordId: recordId,
+        recordType: getRecordType(record),
+        final: getFinal(record),
+        parents: record('parents').default([]),
+        consumedBy: record('consumedBy').default(''),
+        archivedAt: record('archivedAt').default(0),
+        recall: record('recall').default(null),
+        audits: record('audits').default([]),
+        timelinePage: record('timelinePage').default(0),
+        owners: getOwners(block)(record)
+          .orderBy('timestamp')
+          .map(owner => getReporter(owner('agentId'))(block)
+            .merge({ timestamp: owner('timestamp') })),
+        properties: r.expr(propertyNames)
+          .concatMap(name => {
+            return getTable('properties', block)
+              .filter(hasRecordId(recordId))
+              .filter(hasName(name))
+              .coerceTo('array')
+          })
+          .map(property => {
+            return getPropertyValues(recordId)(block)(property)
+              .do(propertyValues => ({
+                name: getName(property),
+                dataType: getDataType(property),
+                unit: property('unit'),
+                currentPage: property('currentPage'),
+                value: getCurrentValue(propertyValues).do(value => {
+                  return r.branch(value, value.pluck('value', 'timestamp'), null)
+                })
+              }))
+          }),
+        block: r.table('blocks').get(block)
+          .pluck('blockNum', 'blockId', 'stateRootHash')
+      })
+    })))
+}
+
+// Locations are stored in millionths of a degree
+const isValidLocation = location => {
+  return r.and(
+    location('latitude').ge(-90e6),
+    location('latitude').le(90e6),
+    location('longitude').ge(-180e6),
+    location('longitude').le(180e6)
+  )
+}
+
+const toPoint = location => {
+  return r.point(location('longitude').div(1e6), location('latitude').div(1e6))
+}
+
+// Finds pages through their geo index, then checks each of their values
+// against the area and time window
+const listLocationReportsQuery = (polygon, start, end) => block => {
+  return r.table('propertyPages')
+    .getIntersecting(polygon, { index: 'locations' })
+    .filter(hasBlock(block))
+    .concatMap(page => {
+      return page('reportedValues')
+        .filter(reported => r.and(
+          reported('timestamp').ge(start),
+          reported('timestamp').le(end),
+          isValidLocation(reported('locationValue')),
+          polygon.intersects(toPoint(reported('locationValue')))
+        ))
+        .map(reported => ({
+          propertyName: page('name'),
+          recordId: page('recordId'),
+          timestamp: reported('timestamp'),
+          location: reported('locationValue')
+        }))
+    })
+    .group('recordId')
+    .ungroup()
+    .map(group => ({
+      recordId: group('group'),
+      reports: group('reduction')
+        .orderBy('timestamp')
+        .without('recordId')
+    }))
+    .orderBy('recordId')
+}
+
+// The tables holding state about a Record, each document having the
+// recordId it belongs to
+const SYNC_TABLES = [
+  'records',
+  'properties',
+  'propertyPages',
+  'recordTimelinePages',
+  'proposals',
+  'messageAnchors',
+  'excursionLogs'
+]
+
+// The current state of the passed Records which was written after a
+// block, along with the block it was read at and the one it was read
+// since, if that has not been forked out
+const fetchChangesQuery = (recordIds, since) => block => {
+  const changes = SYNC_TABLES.map(table => {
+    return getTable(table, block)
+      .filter(doc => r.expr(recordIds).contains(getRecordId(doc)))
+      .filter(doc => doc('startBlockNum').gt(since))
+      .map(doc => ({
+        table,
+        state: doc.without('id', 'startBlockNum', 'endBlockNum', 'locations')
+      }))
+  })
+
+  return r.expr({
+    block: r.table('blocks').get(block).pluck('blockNum', 'blockId'),
+    sinceBlock: r.table('blocks').get(since).do(found => {
+      return r.branch(found, found.pluck('blockNum', 'blockId'), null)
+    }),
+    changes: changes[0].union(...changes.slice(1)).coerceTo('array')
+  })
+}
+
+// The type and current owner of each of the passed Records, which is what
+// the API's redaction rules depend on
+const listRecordSummariesQuery = recordIds => block => {
+  return getTable('records', block)
+    .filter(record => r.expr(recordIds).contains(getRecordId(record)))
+    .map(record => ({
+      recordId: getRecordId(record),
+      recordType: getRecordType(record),
+      owner: getOwnerId(record)
+    }))
+    .coerceTo('array')
+}
+
 /* Exported functions */
 
 const fetchProperty = (recordId, propertyName) => {
@@ -262,12 +532,98 @@ const fetchRecord = (recordId, authedKey) => {
   return db.queryWithCurrentBlock(fetchRecordQuery(recordId, authedKey))
 }
 
+const fetchProvenance = (recordId, propertyNames) => {
+  return db.queryWithCurrentBlock(
+    fetchProvenanceQuery(recordId, propertyNames))
+}
+
+const fetchChanges = (recordIds, since) => {
+  return db.queryWithCurrentBlock(fetchChangesQuery(recordIds, since))
+}
+
+const fetchTimelinePage = (recordId, pageNum) => {
+  return db.queryWithCurrentBlock(fetchTimelinePageQuery(recordId, pageNum))
+}
+
+const fetchTimelineEntries = recordId => {
+  return db.queryWithCurrentBlock(fetchTimelineEntriesQuery(recordId))
+}
+
+const listMessages = (recordId, digest) => {
+  return db.queryWithCurrentBlock(listMessagesQuery(recordId, digest))
+}
+
+const listFeedback = (recordId, kind) => {
+  return db.queryWithCurrentBlock(listFeedbackQuery(recordId, kind))
+}
+
+const listExcursions = (recordId, name) => {
+  return db.queryWithCurrentBlock(listExcursionsQuery(recordId, name))
+}
+
+// Takes the area as [latitude, longitude] pairs in degrees
+const listLocationReports = (points, start, end) => {
+  const polygon = r.polygon(...points.map(([lat, lng]) => [lng, lat]))
+  return db.queryWithCurrentBlock(
+    listLocationReportsQuery(polygon, start, end))
+}
+
 const listRecords = (authedKey, filterQuery) => {
   return db.queryWithCurrentBlock(listRecordsQuery(authedKey, filterQuery))
 }
 
+const listRecordSummaries = recordIds => {
+  return db.queryWithCurrentBlock(listRecordSummariesQuery(recordIds))
+}
+
+// The state the handler checks CreateRecord and UpdateProperties payloads
+// against: whether the signer is an Agent, the Record and its Properties,
+// and the RecordType a new Record would have, or an existing Record has
+const fetchValidationStateQuery = (signer, recordId, recordType) => block => {
+  const typeName = recordType ||
+    findRecord(recordId)(block)('recordType').default('')
+
+  return r.expr({
+    agentExists: getTable('agents', block)
+      .filter(hasPublicKey(signer))
+      .count()
+      .gt(0),
+    record: findRecord(recordId)(block).default(null),
+    recordType: getTable('recordTypes', block)
+      .filter(hasName(typeName))
+      .nth(0)
+      .default(null),
+    properties: getTable('properties', block)
+      .filter(hasRecordId(recordId))
+      .map(property => property.merge({
+        firstPageEmpty: getTable('propertyPages', block)
+          .filter(hasRecordId(recordId))
+          .filter(hasName(getName(property)))
+          .filter({ pageNum: 1 })
+          .concatMap(getReportedValues)
+          .isEmpty()
+      }))
+      .coerceTo('array')
+  })
+}
+
+const fetchValidationState = (signer, recordId, recordType) => {
+  return db.queryWithCurrentBlock(
+    fetchValidationStateQuery(signer, recordId, recordType))
+}
+
 module.exports = {
+  fetchChanges,
   fetchProperty,
+  fetchProvenance,
   fetchRecord,
-  listRecords
+  fetchTimelinePage,
+  fetchTimelineEntries,
+  fetchValidationState,
+  listExcursions,
+  listFeedback,
+  listLocationReports,
+  listMessages,
+  listRecords,
+  listRecordSummaries
 }
@@ -0,0 +1,45 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const db = require('./')
+
+// Webhook endpoints for the notifier, keyed by Agent public key. Secrets
+// are only ever read by the notifier, never returned by the API.
+const upsert = webhook => {
+  return db.modifyTable('webhooks', webhooks => {
+    return webhooks.insert(webhook, { conflict: 'replace' })
+  })
+}
+
+const fetch = publicKey => {
+  return db.queryTable('webhooks', webhooks => {
+    return webhooks.get(publicKey).default(null)
+  }, false)
+}
+
+const remove = publicKey => {
+  return db.modifyTable('webhooks', webhooks => {
+    return webhooks.get(publicKey).delete()
+  })
+}
+
+module.exports = {
+  upsert,
+  fetch,
+  remove
+}
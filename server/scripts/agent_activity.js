@@ -0,0 +1,73 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Lists the most recent actions signed by an Agent (the public key passed
+// as the first argument), newest first. The second argument is how many
+// to list, 20 by default.
+//
+//   SERVER=https://example.com npm run activity -- <public key> [count]
+
+const request = require('request-promise-native')
+
+const SERVER = process.env.SERVER || 'http://localhost:3000'
+const MAX_PAGE = 0xffff
+
+const publicKey = process.argv[2]
+const count = Number(process.argv[3] || 20)
+if (!publicKey || !Number.isInteger(count) || count < 1) {
+  console.error('Usage: npm run activity -- <public key> [count]')
+  process.exit(2)
+}
+
+const fetchPage = page => {
+  const query = page === null ? '' : `?page=${page}`
+  return request({
+    url: `${SERVER}/agents/${publicKey}/activity${query}`,
+    json: true
+  })
+}
+
+// Pages form a ring, so reading backwards from the current page stops
+// once it comes back around, or reaches a page that was never written
+const collect = (page, first, entries) => {
+  if (entries.length >= count || page.entries.length === 0) {
+    return entries.slice(0, count)
+  }
+
+  const collected = entries.concat(page.entries.slice().reverse())
+  const previous = page.pageNum === 1 ? MAX_PAGE : page.pageNum - 1
+  if (previous === first) return collected.slice(0, count)
+
+  return fetchPage(previous).then(prev => collect(prev, first, collected))
+}
+
+const formatEntry = ({ timestamp, action, recordId }) => {
+  const time = new Date(timestamp * 1000).toISOString()
+  return [time, action, recordId].filter(part => part).join('  ')
+}
+
+fetchPage(null)
+  .then(page => collect(page, page.pageNum, []))
+  .then(entries => {
+    if (entries.length === 0) console.log('No activity')
+    entries.forEach(entry => console.log(formatEntry(entry)))
+  })
+  .catch(err => {
+    console.error(err.error ? err.error.error || err.message : err.message)
+    process.exit(1)
+  })
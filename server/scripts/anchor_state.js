@@ -0,0 +1,141 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Runs until stopped, periodically publishing the state root of the chain
+// head to an external service and recording the service's receipt
+// on-chain with an ANCHOR_STATE transaction. The key in PRIVATE_KEY must
+// be listed in the supply_chain.anchor.signers setting.
+//
+//   PRIVATE_KEY=<hex> ANCHOR_SERVICE=opentimestamps npm run anchor
+
+const request = require('request-promise-native')
+const protos = require('../blockchain/protos')
+const {
+  awaitServerPubkey,
+  getTxnCreator,
+  submitTxns,
+  encodeTimestampedPayload
+} = require('../system/submit_utils')
+const { makeSettingAddress } = require('../../shared/setting_addresses')
+
+const REST_API = process.env.REST_API || 'http://localhost:8008'
+const PRIVATE_KEY = process.env.PRIVATE_KEY
+const SERVICE = process.env.ANCHOR_SERVICE || 'opentimestamps'
+const ANCHOR_URL = process.env.ANCHOR_URL
+const INTERVAL = Number(process.env.ANCHOR_INTERVAL || 10 * 60 * 1000)
+
+// Read by the processor to check the signer may record anchors
+const SIGNERS_ADDRESS = makeSettingAddress('supply_chain.anchor.signers')
+
+const OPENTIMESTAMPS_URL = 'https://a.pool.opentimestamps.org'
+
+// Each publisher sends a block's state root to a service, resolving the
+// service's receipt as a Buffer
+const PUBLISHERS = {
+  // Commits the root to Bitcoin through an OpenTimestamps calendar. The
+  // receipt is a pending timestamp, which the OpenTimestamps client can
+  // upgrade to a full proof once the calendar's transaction confirms.
+  opentimestamps: ({ stateRoot }) => request({
+    method: 'POST',
+    url: `${ANCHOR_URL || OPENTIMESTAMPS_URL}/digest`,
+    headers: {
+      'Accept': 'application/vnd.opentimestamps.v1',
+      'Content-Type': 'application/octet-stream'
+    },
+    body: Buffer.from(stateRoot, 'hex'),
+    encoding: null
+  }),
+
+  // Posts the block as JSON to any other service, such as a bridge to
+  // another chain, keeping the response body as the receipt
+  http: ({ blockNum, blockId, stateRoot }) => request({
+    method: 'POST',
+    url: ANCHOR_URL,
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ blockNum, blockId, stateRoot }),
+    encoding: null
+  })
+}
+
+const publish = PUBLISHERS[SERVICE]
+if (!PRIVATE_KEY || !publish || (SERVICE === 'http' && !ANCHOR_URL)) {
+  console.error('Usage: PRIVATE_KEY=<hex> ANCHOR_SERVICE=<service> ' +
+                '[ANCHOR_URL=<url>] npm run anchor')
+  console.error(`Services: ${Object.keys(PUBLISHERS).join(', ')} ` +
+                '(http requires ANCHOR_URL)')
+  process.exit(2)
+}
+
+let createTxn = null
+
+// The block the last anchor was committed in, so that a chain with no
+// activity but the anchors themselves is not anchored again
+let lastCommitted = -1
+
+const fetchHead = () => {
+  return request({
+    url: `${REST_API}/blocks`,
+    qs: { limit: 1 },
+    json: true
+  })
+    .then(({ data }) => ({
+      blockNum: Number(data[0].header.block_num),
+      blockId: data[0].header_signature,
+      stateRoot: data[0].header.state_root_hash
+    }))
+}
+
+const anchorHead = () => {
+  return fetchHead()
+    .then(block => {
+      if (block.blockNum <= lastCommitted) return
+
+      return publish(block)
+        .then(receipt => createTxn(encodeTimestampedPayload({
+          action: protos.SCPayload.Action.ANCHOR_STATE,
+          anchorState: protos.AnchorStateAction.create({
+            service: SERVICE,
+            blockNum: block.blockNum,
+            blockId: block.blockId,
+            stateRoot: block.stateRoot,
+            receipt
+          })
+        }), [SIGNERS_ADDRESS]))
+        .then(txn => submitTxns([txn]))
+        .then(response => {
+          lastCommitted = JSON.parse(response).blockNum
+          console.log(`Anchored block ${block.blockNum} with ${SERVICE}`)
+        })
+    })
+    .catch(err => {
+      const message = err.error ? err.error.toString() : err.message
+      console.warn(`Unable to anchor state: ${message}`)
+    })
+    .then(() => setTimeout(anchorHead, INTERVAL))
+}
+
+protos.compile()
+  .then(awaitServerPubkey)
+  .then(batcherPublicKey => {
+    createTxn = getTxnCreator(PRIVATE_KEY, batcherPublicKey)
+  })
+  .then(anchorHead)
+  .catch(err => {
+    console.error(err.message)
+    process.exit(1)
+  })
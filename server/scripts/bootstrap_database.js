@@ -51,6 +51,58 @@ r.connect({host: HOST, port: PORT})
       .then(() => {
         return r.db(NAME).table('agents').indexCreate('publicKey').run(conn)
       })
+      .then(() => {
+        console.log('Creating "agentActivityPages" table...')
+        return r.db(NAME).tableCreate('agentActivityPages').run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('agentActivityPages').indexCreate(
+          'attributes', [r.row('agentId'), r.row('pageNum')]
+        ).run(conn)
+      })
+      .then(() => {
+        console.log('Creating "subscriptions" table...')
+        return r.db(NAME).tableCreate('subscriptions').run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('subscriptions').indexCreate('agentId')
+          .run(conn)
+      })
+      .then(() => {
+        console.log('Creating "messageAnchors" table...')
+        return r.db(NAME).tableCreate('messageAnchors').run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('messageAnchors').indexCreate(
+          'attributes', [r.row('recordId'), r.row('digest')]
+        ).run(conn)
+      })
+      .then(() => {
+        console.log('Creating "feedbackLogs" table...')
+        return r.db(NAME).tableCreate('feedbackLogs').run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('feedbackLogs').indexCreate('recordId')
+          .run(conn)
+      })
+      .then(() => {
+        console.log('Creating "feedbackPages" table...')
+        return r.db(NAME).tableCreate('feedbackPages').run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('feedbackPages').indexCreate(
+          'attributes', [r.row('recordId'), r.row('pageNum')]
+        ).run(conn)
+      })
+      .then(() => {
+        console.log('Creating "excursionLogs" table...')
+        return r.db(NAME).tableCreate('excursionLogs').run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('excursionLogs').indexCreate(
+          'attributes', [r.row('name'), r.row('recordId')]
+        ).run(conn)
+      })
       .then(() => {
         console.log('Creating "records" table...')
         return r.db(NAME).tableCreate('records').run(conn)
@@ -58,6 +110,15 @@ r.connect({host: HOST, port: PORT})
       .then(() => {
         r.db(NAME).table('records').indexCreate('recordId').run(conn)
       })
+      .then(() => {
+        console.log('Creating "recordTimelinePages" table...')
+        return r.db(NAME).tableCreate('recordTimelinePages').run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('recordTimelinePages').indexCreate(
+          'attributes', [r.row('recordId'), r.row('pageNum')]
+        ).run(conn)
+      })
       .then(() => {
         console.log('Creating "recordTypes" table...')
         return r.db(NAME).tableCreate('recordTypes').run(conn)
@@ -86,6 +147,11 @@ r.connect({host: HOST, port: PORT})
           r.row('pageNum')
         ]).run(conn)
       })
+      .then(() => {
+        return r.db(NAME).table('propertyPages').indexCreate(
+          'locations', r.row('locations'), { geo: true, multi: true }
+        ).run(conn)
+      })
       .then(() => {
         console.log('Creating "proposals" table...')
         return r.db(NAME).tableCreate('proposals').run(conn)
@@ -98,6 +164,32 @@ r.connect({host: HOST, port: PORT})
           r.row('role')
         ]).run(conn)
       })
+      .then(() => {
+        console.log('Creating "batches" table...')
+        return r.db(NAME).tableCreate('batches', {
+          primaryKey: 'batchId'
+        }).run(conn)
+      })
+      .then(() => {
+        return r.db(NAME).table('batches').indexCreate('submitter').run(conn)
+      })
+      .then(() => {
+        console.log('Creating "syncProgress" table...')
+        return r.db(NAME).tableCreate('syncProgress').run(conn)
+      })
+      .then(() => {
+        // Webhook endpoints hold secrets, so are kept off-chain
+        console.log('Creating "webhooks" table...')
+        return r.db(NAME).tableCreate('webhooks', {
+          primaryKey: 'publicKey'
+        }).run(conn)
+      })
+      .then(() => {
+        console.log('Creating "notifierProgress" table...')
+        return r.db(NAME).tableCreate('notifierProgress', {
+          primaryKey: 'blockNum'
+        }).run(conn)
+      })
       .then(() => {
         console.log('Creating "blocks" table...')
         return r.db(NAME).tableCreate('blocks', {
@@ -0,0 +1,246 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Moves all Supply Chain state to the addresses of a new addressing
+// scheme (see ../system/address_schemes.js), with MIGRATE_STATE
+// transactions. Every entry is read from the validator's REST API, and its
+// address re-derived under the new scheme; entries which now share an
+// address are stored in one container. The new containers are written
+// first, in dependency order (RecordTypes and Agents before the Records
+// which refer to them, and Records before their Properties), then the old
+// addresses emptied. The key in PRIVATE_KEY must be listed in the
+// supply_chain.migration.signers setting.
+//
+// With --dry-run, nothing is sent, and the addresses which would be
+// written (+), rewritten (~) and emptied (-) are printed instead.
+//
+//   PRIVATE_KEY=<hex> npm run migrate -- [--dry-run] [--tenant <name>]
+//     [--role-aware-proposals]
+
+const _ = require('lodash')
+const request = require('request-promise-native')
+const protos = require('../blockchain/protos')
+const { NAMESPACE, makeScheme } = require('../system/address_schemes')
+const {
+  awaitServerPubkey,
+  getTxnCreator,
+  submitTxns,
+  encodeTimestampedPayload
+} = require('../system/submit_utils')
+const { makeSettingAddress } = require('../../shared/setting_addresses')
+
+const REST_API_URL = process.env.REST_API_URL || 'http://localhost:8008'
+const PRIVATE_KEY = process.env.PRIVATE_KEY
+
+// The most writes the transaction processor accepts in one migration
+const MAX_WRITES = 64
+
+// Read by the processor to check the signer may migrate state
+const SIGNERS_ADDRESS = makeSettingAddress('supply_chain.migration.signers')
+
+const USAGE = 'Usage: PRIVATE_KEY=<hex> npm run migrate -- [--dry-run] ' +
+  '[--tenant <name>] [--role-aware-proposals]'
+
+const parseArgs = args => {
+  const options = { dryRun: false, tenant: null, roleAwareProposals: false }
+  for (let i = 0; i < args.length; i++) {
+    if (args[i] === '--dry-run') options.dryRun = true
+    else if (args[i] === '--role-aware-proposals') {
+      options.roleAwareProposals = true
+    } else if (args[i] === '--tenant' && args[i + 1]) options.tenant = args[++i]
+    else return null
+  }
+  return options
+}
+
+const options = parseArgs(process.argv.slice(2))
+if (!options || (!options.dryRun && !PRIVATE_KEY)) {
+  console.error(USAGE)
+  process.exit(2)
+}
+
+const current = makeScheme()
+const target = makeScheme(options)
+
+// The kinds of state, by the type characters of their addresses, in the
+// order they are migrated. Each names its protobuf, which may depend on
+// the page number ending its address, and re-derives an entry's address
+// under a scheme.
+const KINDS = [
+  ['ee', 'RecordType', (s, type) => s.recordType(type.name)],
+  // Versions stored before they were numbered are version 1
+  ['a5', 'RecordType',
+    (s, type) => s.recordTypeVersion(type.name, Math.max(type.version, 1))],
+  ['ae', 'Agent', (s, agent) => s.agent(agent.publicKey)],
+  ['ec', 'Record', (s, record) => s.record(record.recordId)],
+  ['ea', page => page === 0 ? 'Property' : 'PropertyPage',
+    (s, property, page) => s.property(property.recordId, property.name, page)],
+  ['ed', 'RecordTimelinePage',
+    (s, timeline, page) => s.recordTimeline(timeline.recordId, page)],
+  ['ac', 'ExcursionLog', (s, log) => s.excursionLog(log.recordId, log.name)],
+  ['ad', 'MessageAnchor',
+    (s, anchor) => s.message(anchor.recordId, anchor.digest)],
+  ['aa', 'Proposal', (s, proposal) => {
+    return s.proposal(proposal.recordId, proposal.receivingAgent, proposal.role)
+  }],
+  ['ab', 'ProposalHistoryPage', (s, history, page) => {
+    return s.proposalHistory(history.recordId, history.receivingAgent, page)
+  }],
+  ['a2', 'OpenProposalIndex', (s, index) => s.proposalIndex(index.recordId)],
+  ['a4', page => page === 0 ? 'FeedbackLog' : 'FeedbackPage',
+    (s, feedback, page) => s.feedback(feedback.recordId, page)],
+  ['a0', 'AgentActivityPage',
+    (s, activity, page) => s.agentActivity(activity.agentId, page)],
+  ['af', 'SubscriptionList', (s, list) => s.subscription(list.agentId)],
+  ['a3', 'RecordTypeStats',
+    (s, stats) => s.recordTypeStats(stats.recordType, stats.day)],
+  ['a6', 'RecordTemplate', (s, template) => s.recordTemplate(template.name)],
+  ['a1', 'StateAnchor',
+    (s, anchor) => s.stateAnchor(anchor.service, anchor.blockNum)]
+].map(([type, proto, derive], order) => ({
+  type,
+  protoName: _.isFunction(proto) ? proto : () => proto,
+  derive,
+  order
+}))
+
+const getKind = address => {
+  const kind = _.find(KINDS, { type: address.slice(6, 8) })
+  if (!kind) throw new Error(`Unknown state at ${address}`)
+  return kind
+}
+
+const getPage = address => parseInt(address.slice(-4), 16)
+
+const getContainer = (kind, page) => {
+  return protos[`${kind.protoName(page)}Container`]
+}
+
+const toObject = (proto, message) => proto.toObject(message, {
+  enums: String,
+  longs: Number,
+  defaults: true
+})
+
+const fetchEntries = (url = `${REST_API_URL}/state?address=${NAMESPACE}`) => {
+  return request({ url, json: true })
+    .then(({ data, paging }) => {
+      if (!paging || !paging.next) return data
+      return fetchEntries(paging.next).then(rest => data.concat(rest))
+    })
+}
+
+// Groups the entries of every container by their address under the
+// target scheme, checking each is where the current scheme says it is
+const regroup = entries => {
+  const targets = {}
+  entries.forEach(({ address, data }) => {
+    const kind = getKind(address)
+    const page = getPage(address)
+    const protoName = kind.protoName(page)
+    const decoded = getContainer(kind, page).decode(data).entries
+
+    decoded.forEach(message => {
+      const entry = toObject(protos[protoName], message)
+      if (kind.derive(current, entry, page) !== address) {
+        throw new Error(`${protoName} at ${address} is not at its address`)
+      }
+      const targetAddress = kind.derive(target, entry, page)
+      targets[targetAddress] = targets[targetAddress] ||
+        { kind, protoName, messages: [] }
+      targets[targetAddress].messages.push(message)
+    })
+  })
+  return targets
+}
+
+// The writes which move state to the target addresses, in the order to
+// send them: containers in dependency order, then the emptied addresses
+const planWrites = entries => {
+  // Encoded again, so that only containers whose entries change are
+  // rewritten, however the processor happened to encode them
+  const previous = _.fromPairs(entries.map(({ address, data }) => {
+    const container = getContainer(getKind(address), getPage(address))
+    return [address, Buffer.from(container.encode(container.decode(data)).finish())]
+  }))
+  const targets = regroup(entries)
+
+  const writes = _.map(targets, ({ kind, protoName, messages }, address) => {
+    const data = Buffer.from(protos[`${protoName}Container`]
+      .encode({ entries: messages })
+      .finish())
+    const existing = previous[address]
+    const change = !existing ? '+' : existing.equals(data) ? null : '~'
+    return { address, data, change, kind }
+  })
+  const empties = entries
+    .filter(({ address }) => !targets[address])
+    .map(({ address }) => ({
+      address,
+      data: Buffer.alloc(0),
+      change: '-',
+      kind: getKind(address)
+    }))
+
+  const byOrder = writes => _.sortBy(writes, ['kind.order', 'address'])
+  return byOrder(writes.filter(write => write.change !== null))
+    .concat(byOrder(empties))
+}
+
+const printPlan = writes => {
+  writes.forEach(({ address, change }) => console.log(`${change} ${address}`))
+  const counts = _.countBy(writes, 'change')
+  console.log(`${counts['+'] || 0} written, ${counts['~'] || 0} rewritten, ` +
+              `${counts['-'] || 0} emptied`)
+}
+
+const submitPlan = writes => {
+  return awaitServerPubkey()
+    .then(batcherPublicKey => getTxnCreator(PRIVATE_KEY, batcherPublicKey))
+    .then(createTxn => {
+      // Sent one at a time, so that no address is emptied before every
+      // container has been written to its new address
+      return _.chunk(writes, MAX_WRITES).reduce((sent, chunk, i, chunks) => {
+        return sent
+          .then(() => createTxn(encodeTimestampedPayload({
+            action: protos.SCPayload.Action.MIGRATE_STATE,
+            migrateState: protos.MigrateStateAction.create({
+              writes: chunk.map(({ address, data }) => ({ address, data }))
+            })
+          }), [SIGNERS_ADDRESS]))
+          .then(txn => submitTxns([txn]))
+          .then(() => console.log(`Sent migration ${i + 1} of ${chunks.length}`))
+      }, Promise.resolve())
+    })
+}
+
+protos.compile()
+  .then(() => fetchEntries())
+  .then(entries => entries
+    .map(({ address, data }) => ({ address, data: Buffer.from(data, 'base64') }))
+    .filter(({ data }) => data.length > 0))
+  .then(planWrites)
+  .then(writes => {
+    if (options.dryRun) return printPlan(writes)
+    if (writes.length === 0) return console.log('Nothing to migrate')
+    return submitPlan(writes)
+  })
+  .catch(err => {
+    console.error(err.error ? err.error.toString() : err.message)
+    process.exit(1)
+  })
@@ -0,0 +1,363 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Generates a demo for a set of RecordTypes, to stand up a proof of
+// concept without copying one of the sample clients. The schema file
+// lists RecordTypes as the sample clients' core_types.json do, though data
+// types may also be given by name (e.g. "NUMBER"). Written to the output
+// directory are:
+//   - core_types.json, to create the types with seed_core_types.js
+//   - sample_data.json, records of each type for seed_sample_data.js,
+//     held by the fish client's demo Agents so their logins work
+//   - sample_updates.json, reports for run_sample_updates.js
+//   - queries.sh, curl commands for the API queries a demo shows off
+//   - dashboard.json, what a dashboard should show of each type
+// The commands to seed the demo are printed once it is written.
+//
+//   npm run scaffold -- <schema.json> [--out <dir>] [--records <count>]
+
+const _ = require('lodash')
+const fs = require('fs')
+const path = require('path')
+
+const DEMO_AGENTS = path.resolve(__dirname,
+  '../../fish_client/sample_data/sample_data.json')
+
+const USAGE = 'Usage: npm run scaffold -- <schema.json> [--out <dir>] ' +
+  '[--records <count>]'
+
+// The values of PropertySchema.DataType
+const DATA_TYPES = {
+  BYTES: 1,
+  BOOLEAN: 2,
+  NUMBER: 3,
+  STRING: 4,
+  ENUM: 5,
+  STRUCT: 6,
+  LOCATION: 7,
+  TIMESTAMP: 8,
+  RECORD_REFERENCE: 9,
+  ARRAY: 10,
+  IDENTIFIER: 11
+}
+const TYPE_NAMES = _.invert(DATA_TYPES)
+
+// Where sample records are placed, in millionths of a degree, with each
+// one a little further along
+const ORIGIN = { latitude: 44977753, longitude: -93265011 }
+const SPACING = 250000
+
+// Sample TIMESTAMP values are a day apart from the start of 2018
+const SAMPLE_EPOCH = 1514764800
+
+// A GS1 key's check digit, weighting digits 3 and 1 from the right
+const gs1CheckDigit = digits => {
+  const sum = _.sum(digits.split('').reverse().map((digit, i) => {
+    return Number(digit) * (i % 2 === 0 ? 3 : 1)
+  }))
+  return (10 - sum % 10) % 10
+}
+
+// A sample identifier in the schema's format, which may be given by
+// name or number
+const makeIdentifier = (schema, i) => {
+  const format = schema.identifierFormat
+  const serial = _.padStart(String(i + 1), 12, '0')
+  if (format === 'UUID_V4' || format === 1) {
+    return `00000000-0000-4000-8000-${serial}`
+  }
+  if (format === 'GS1' || format === 2) {
+    return serial + gs1CheckDigit(serial)
+  }
+  return `${schema.name}-${i + 1}`
+}
+
+const parseArgs = args => {
+  const options = { schema: null, out: null, records: 3 }
+  for (let i = 0; i < args.length; i++) {
+    if (args[i] === '--out' && args[i + 1]) options.out = args[++i]
+    else if (args[i] === '--records' && args[i + 1]) {
+      options.records = Number(args[++i])
+    } else if (!options.schema) options.schema = args[i]
+    else return null
+  }
+  const isValid = options.schema &&
+    Number.isInteger(options.records) && options.records > 0
+  return isValid ? options : null
+}
+
+const toDataType = (dataType, name) => {
+  const value = _.isString(dataType) ? DATA_TYPES[dataType] : dataType
+  if (!TYPE_NAMES[value]) {
+    throw new Error(`Property "${name}" has an unknown data type: ${dataType}`)
+  }
+  return value
+}
+
+const normalizeSchema = schema => {
+  return _.assign({}, schema, {
+    dataType: toDataType(schema.dataType, schema.name),
+    structProperties: schema.structProperties &&
+      schema.structProperties.map(normalizeSchema),
+    elementSchema: schema.elementSchema && normalizeSchema(schema.elementSchema)
+  })
+}
+
+const loadTypes = file => {
+  const types = JSON.parse(fs.readFileSync(file))
+  if (!Array.isArray(types) || types.length === 0) {
+    throw new Error(`${file} must list one or more record types`)
+  }
+  return types.map(type => {
+    if (!type.name || !Array.isArray(type.properties)) {
+      throw new Error(`Each record type in ${file} needs a name and properties`)
+    }
+    return _.assign({}, type, {
+      properties: type.properties.map(schema => {
+        return _.omitBy(normalizeSchema(schema), _.isUndefined)
+      })
+    })
+  })
+}
+
+const toLocation = i => ({
+  latitude: ORIGIN.latitude + i * SPACING,
+  longitude: ORIGIN.longitude + i * SPACING
+})
+
+// A plausible value for the i-th sample record, as a PropertyValue
+const makeValue = (schema, i) => {
+  const value = { name: schema.name, dataType: schema.dataType }
+  switch (TYPE_NAMES[schema.dataType]) {
+    case 'BYTES':
+      value.bytesValue = Buffer.from(`${schema.name}-${i + 1}`)
+        .toString('base64')
+      break
+    case 'BOOLEAN':
+      value.booleanValue = i % 2 === 0
+      break
+    case 'NUMBER':
+      value.numberValue = (i + 1) * 10 *
+        Math.pow(10, Math.max(-(schema.numberExponent || 0), 0))
+      break
+    case 'STRING':
+      value.stringValue = `${_.startCase(schema.name)} ${i + 1}`
+      break
+    case 'ENUM':
+      value.enumValue = schema.enumOptions[i % schema.enumOptions.length]
+      break
+    case 'STRUCT':
+      value.structValues = schema.structProperties.map(field => {
+        return makeValue(field, i)
+      })
+      break
+    case 'LOCATION':
+      value.locationValue = toLocation(i)
+      break
+    case 'TIMESTAMP':
+      value.timestampValue = SAMPLE_EPOCH + i * 86400
+      break
+    case 'ARRAY':
+      value.arrayValues = [makeValue(schema.elementSchema, i)]
+      break
+    case 'IDENTIFIER':
+      value.identifierValue = makeIdentifier(schema, i)
+      break
+  }
+  return value
+}
+
+// Properties set when a Record is created; delayed ones are only
+// reported later, and there are no sample Records to reference
+const isInitial = schema => {
+  return !schema.delayed && !isOfType('RECORD_REFERENCE')(schema)
+}
+
+const isOfType = (...dataTypes) => schema => {
+  return dataTypes.includes(TYPE_NAMES[schema.dataType])
+}
+
+// Properties reported over time, which the update script can vary
+const isReported = schema => {
+  return !schema.fixed && !schema.immutable && !schema.requiresAcknowledgment &&
+    isOfType('NUMBER', 'LOCATION')(schema)
+}
+
+const makeSampleData = (types, agents, count) => {
+  const records = _.flatMap(types, type => _.range(count).map(i => {
+    const reported = type.properties.filter(isReported).map(s => s.name)
+    return _.assign({
+      recordId: `${type.name}-${i + 1}`,
+      recordType: type.name,
+      properties: type.properties
+        .filter(isInitial)
+        .map(schema => makeValue(schema, i)),
+      ownerIndex: i % agents.length
+    }, reported.length === 0 || agents.length < 2 ? {} : {
+      reporterIndex: (i + 1) % agents.length,
+      reportableProperties: reported
+    })
+  }))
+  return { records, agents }
+}
+
+const makeUpdate = (schema, i) => {
+  if (isOfType('LOCATION')(schema)) {
+    return {
+      name: schema.name,
+      dataType: schema.dataType,
+      value: { latitude: 50000, longitude: 50000 },
+      isRelative: true,
+      startValue: toLocation(i)
+    }
+  }
+  const scale = Math.pow(10, Math.max(-(schema.numberExponent || 0), 0))
+  return {
+    name: schema.name,
+    dataType: schema.dataType,
+    value: 2 * scale,
+    isRelative: true,
+    startValue: makeValue(schema, i).numberValue
+  }
+}
+
+const makeSampleUpdates = ({ records, agents }, types) => {
+  return records
+    .filter(record => record.reporterIndex !== undefined)
+    .map(record => {
+      const type = _.find(types, { name: record.recordType })
+      const i = Number(_.last(record.recordId.split('-'))) - 1
+      return {
+        recordId: record.recordId,
+        privateKey: agents[record.reporterIndex].privateKey,
+        updates: type.properties
+          .filter(isReported)
+          .map(schema => makeUpdate(schema, i))
+      }
+    })
+}
+
+const makeQueries = types => {
+  const lines = [
+    '#!/bin/sh',
+    '# API queries for the demo. Set SERVER to the Supply Chain server.',
+    'SERVER=${SERVER:-http://localhost:3000}',
+    ''
+  ]
+  types.forEach(type => {
+    const sample = `${type.name}-1`
+    lines.push(
+      `# The ${type.name} type, and every record of it`,
+      `curl "$SERVER/record-types/${type.name}"`,
+      `curl "$SERVER/records?recordType=${type.name}"`,
+      '',
+      `# One ${type.name}, who has held it, and its history`,
+      `curl "$SERVER/records/${sample}"`,
+      `curl "$SERVER/records/${sample}/timeline"`,
+      `curl "$SERVER/verify/${sample}"`)
+    type.properties.filter(isReported).forEach(schema => {
+      lines.push(`curl "$SERVER/records/${sample}/property/${schema.name}"`)
+    })
+    lines.push('')
+  })
+
+  const hasLocations = types.some(type => {
+    return type.properties.some(isOfType('LOCATION'))
+  })
+  if (hasLocations) {
+    const corner = (lat, lng) => `${ORIGIN.latitude / 1e6 + lat},` +
+      `${ORIGIN.longitude / 1e6 + lng}`
+    const area = [
+      corner(-1, -1), corner(-1, 5), corner(5, 5), corner(5, -1)
+    ].join(';')
+    lines.push('# Records reported within the area the samples are placed in',
+               `curl "$SERVER/location-reports?polygon=${area}"`, '')
+  }
+  return lines.join('\n')
+}
+
+// What a dashboard shows of each type: a table of records with a few of
+// their properties, a map of where they have been, and charts of the
+// numbers reported about them
+const makeDashboard = types => ({
+  recordTypes: types.map(type => {
+    const tabular = type.properties
+      .filter(isOfType('STRING', 'ENUM', 'NUMBER', 'BOOLEAN'))
+    return {
+      name: type.name,
+      title: _.startCase(type.name),
+      columns: _.sortBy(tabular, schema => !schema.required)
+        .slice(0, 4)
+        .map(schema => schema.name),
+      map: type.properties
+        .filter(isOfType('LOCATION'))
+        .map(schema => schema.name),
+      charts: type.properties
+        .filter(schema => isReported(schema) && isOfType('NUMBER')(schema))
+        .map(schema => _.pickBy({
+          property: schema.name,
+          unit: schema.unit,
+          numberExponent: schema.numberExponent
+        }, value => value !== undefined))
+    }
+  })
+})
+
+const writeJson = (dir, name, data) => {
+  const file = path.join(dir, name)
+  fs.writeFileSync(file, JSON.stringify(data, null, 2) + '\n')
+  return file
+}
+
+const options = parseArgs(process.argv.slice(2))
+if (!options) {
+  console.error(USAGE)
+  process.exit(2)
+}
+
+try {
+  const types = loadTypes(options.schema)
+  const { agents } = JSON.parse(fs.readFileSync(DEMO_AGENTS))
+  const out = path.resolve(options.out || `${types[0].name}_demo`)
+  if (!fs.existsSync(out)) fs.mkdirSync(out)
+
+  const sampleData = makeSampleData(types, agents, options.records)
+  // The seed scripts load their DATA relative to themselves
+  const data = file => path.relative(__dirname, file)
+  const files = {
+    types: writeJson(out, 'core_types.json', types),
+    records: writeJson(out, 'sample_data.json', sampleData),
+    updates: writeJson(out, 'sample_updates.json',
+                       makeSampleUpdates(sampleData, types))
+  }
+  fs.writeFileSync(path.join(out, 'queries.sh'), makeQueries(types) + '\n',
+                   { mode: 0o755 })
+  writeJson(out, 'dashboard.json', makeDashboard(types))
+
+  console.log(`Demo written to ${out}. To seed it, from the server directory:`)
+  console.log(`  DATA="${data(files.types)}" node ./scripts/seed_core_types.js`)
+  console.log(
+    `  DATA="${data(files.records)}" node ./scripts/seed_sample_data.js`)
+  console.log(
+    `  DATA="${data(files.updates)}" node ./scripts/run_sample_updates.js`)
+  console.log(`Demo logins are those of the fish client: ` +
+              agents.map(agent => agent.username).join(', '))
+} catch (err) {
+  console.error(err.message)
+  process.exit(1)
+}
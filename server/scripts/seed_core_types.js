@@ -20,6 +20,7 @@ const protos = require('../blockchain/protos')
 const {
   awaitServerPubkey,
   getTxnCreator,
+  makeNamespaceInputs,
   submitTxns,
   encodeTimestampedPayload
 } = require('../system/submit_utils')
@@ -29,11 +30,14 @@ if (DATA.indexOf('.json') === -1) {
   throw new Error('Use the "DATA" environment variable to specify a JSON file')
 }
 
+// Types in a namespace must be created by one of its members
+const PRIVATE_KEY = process.env.PRIVATE_KEY || null
+
 const types = require(`./${DATA}`)
 
 protos.compile()
   .then(awaitServerPubkey)
-  .then(batcherPublicKey => getTxnCreator(null, batcherPublicKey))
+  .then(batcherPublicKey => getTxnCreator(PRIVATE_KEY, batcherPublicKey))
   .then(createTxn => {
     const agentPayload = encodeTimestampedPayload({
       action: protos.SCPayload.Action.CREATE_AGENT,
@@ -42,8 +46,8 @@ protos.compile()
       })
     })
 
-    const typePayloads = types.map(type => {
-      return encodeTimestampedPayload({
+    const typeTxns = types.map(type => {
+      const payload = encodeTimestampedPayload({
         action: protos.SCPayload.Action.CREATE_RECORD_TYPE,
         createRecordType: protos.CreateRecordTypeAction.create({
           name: type.name,
@@ -52,10 +56,10 @@ protos.compile()
           })
         })
       })
+      return createTxn(payload, makeNamespaceInputs(type.name))
     })
 
-    const txns = [ createTxn(agentPayload) ]
-      .concat(typePayloads.map(payload => createTxn(payload)))
+    const txns = [ createTxn(agentPayload) ].concat(typeTxns)
     return submitTxns(txns)
   })
   .then(res => console.log('Types submitted:\n', JSON.parse(res)))
@@ -0,0 +1,109 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Keeps a local copy of some Records in a JSON file (the first argument),
+// bringing it up to date with the server's /sync endpoint. Any further
+// arguments are record ids to add to the copy. Only the changes since the
+// last sync are fetched, so it can be run whenever a connection is
+// available, by an app which reads the file while offline.
+//
+//   SERVER=https://example.com npm run sync -- <cache file> [record id...]
+
+const _ = require('lodash')
+const fs = require('fs')
+const request = require('request-promise-native')
+
+const SERVER = process.env.SERVER || 'http://localhost:3000'
+
+const cacheFile = process.argv[2]
+const newIds = process.argv.slice(3)
+if (!cacheFile) {
+  console.error('Usage: npm run sync -- <cache file> [record id...]')
+  process.exit(2)
+}
+
+const loadCache = () => {
+  if (!fs.existsSync(cacheFile)) {
+    return { blockNum: null, blockId: null, records: {} }
+  }
+  return JSON.parse(fs.readFileSync(cacheFile, 'utf8'))
+}
+
+// Replaces the cached documents which changed. Documents are kept by
+// table, then by their key joined with slashes.
+const applyChanges = (cache, recordIds, delta) => {
+  const records = delta.reset ? {} : cache.records
+  recordIds.forEach(recordId => {
+    records[recordId] = records[recordId] || {}
+    const changes = delta.changes[recordId] || []
+    changes.forEach(({ table, key, state }) => {
+      _.set(records[recordId], [table, key.join('/')], state)
+    })
+  })
+
+  return { blockNum: delta.blockNum, blockId: delta.blockId, records }
+}
+
+const fetchChanges = (cache, recordIds) => {
+  // Records added to the copy need every change, so are synced from the
+  // start, separately from those already in it
+  const query = cache.blockNum === null
+    ? {}
+    : { since: cache.blockNum, blockId: cache.blockId }
+  return request({
+    url: `${SERVER}/sync`,
+    qs: _.assign({ ids: recordIds.join(',') }, query),
+    json: true
+  })
+}
+
+const cache = loadCache()
+const knownIds = Object.keys(cache.records)
+const addedIds = _.difference(newIds, knownIds)
+
+Promise.resolve()
+  .then(() => {
+    if (knownIds.length === 0) return cache
+    return fetchChanges(cache, knownIds)
+      .then(delta => applyChanges(cache, knownIds, delta))
+  })
+  .then(synced => {
+    if (addedIds.length === 0) return synced
+    const fresh = { blockNum: null, blockId: null, records: {} }
+    return fetchChanges(fresh, addedIds)
+      .then(delta => {
+        // Keeping the earlier of the two blocks only means some of the
+        // added Records' changes are fetched again next time
+        const added = applyChanges(fresh, addedIds, delta)
+        const block = synced.blockNum === null ? added : synced
+        return {
+          blockNum: block.blockNum,
+          blockId: block.blockId,
+          records: _.assign(synced.records, added.records)
+        }
+      })
+  })
+  .then(synced => {
+    fs.writeFileSync(cacheFile, JSON.stringify(synced, null, 2))
+    console.log(`Synced ${Object.keys(synced.records).length} records ` +
+                `to block ${synced.blockNum}`)
+  })
+  .catch(err => {
+    console.error(err.error ? err.error.error || err.message : err.message)
+    process.exit(1)
+  })
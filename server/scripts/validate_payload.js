@@ -0,0 +1,59 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Checks a candidate payload (a JSON file passed as the first argument)
+// against the server's /validate endpoint before it is submitted. The file
+// has a "createRecord" or "updateProperties" object, and a "signer" public
+// key. Exits non-zero if the payload would be rejected.
+//
+//   SERVER=https://example.com npm run validate -- <payload file>
+
+const fs = require('fs')
+const request = require('request-promise-native')
+
+const SERVER = process.env.SERVER || 'http://localhost:3000'
+
+const payloadFile = process.argv[2]
+if (!payloadFile) {
+  console.error('Usage: npm run validate -- <payload file>')
+  process.exit(2)
+}
+
+Promise.resolve()
+  .then(() => JSON.parse(fs.readFileSync(payloadFile, 'utf8')))
+  .then(payload => request({
+    method: 'POST',
+    url: `${SERVER}/validate`,
+    body: payload,
+    json: true
+  }))
+  .then(({ valid, errors }) => {
+    if (valid) {
+      console.log('Payload is valid')
+      return
+    }
+
+    errors.forEach(({ property, message }) => {
+      console.log(property ? `${property}: ${message}` : message)
+    })
+    process.exit(1)
+  })
+  .catch(err => {
+    console.error(err.error ? err.error.error || err.message : err.message)
+    process.exit(2)
+  })
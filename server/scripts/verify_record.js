@@ -0,0 +1,149 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Fetches the public provenance of a Record (the record id passed as the
+// first argument) and checks it against state read directly from a
+// validator's REST API, rather than trusting the server's database.
+// Exits non-zero if anything does not match.
+//
+//   SERVER=https://example.com REST_API_URL=http://localhost:8008 \
+//     npm run verify -- <record id>
+
+const _ = require('lodash')
+const request = require('request-promise-native')
+const protos = require('../blockchain/protos')
+
+const SERVER = process.env.SERVER || 'http://localhost:3000'
+const REST_API_URL = process.env.REST_API_URL || 'http://localhost:8008'
+
+const VALUE_FIELDS = {
+  BOOLEAN: 'booleanValue',
+  NUMBER: 'numberValue',
+  STRING: 'stringValue'
+}
+
+const recordId = process.argv[2]
+if (!recordId) {
+  console.error('Usage: npm run verify -- <record id>')
+  process.exit(2)
+}
+
+const failures = []
+const check = (description, passed) => {
+  console.log(`${passed ? 'ok      ' : 'MISMATCH'}  ${description}`)
+  if (!passed) failures.push(description)
+}
+
+const fetchState = (address, blockId, containerName) => {
+  return request({
+    url: `${REST_API_URL}/state/${address}?head=${blockId}`,
+    json: true
+  })
+    .then(({ data }) => {
+      const container = protos[containerName]
+      return container.toObject(
+        container.decode(Buffer.from(data, 'base64')),
+        { enums: String, longs: Number, defaults: true }
+      ).entries
+    })
+}
+
+const verifyBlock = proof => {
+  return request({ url: `${REST_API_URL}/blocks/${proof.blockId}`, json: true })
+    .then(({ data }) => {
+      check(`block ${proof.blockNum} has state root ${proof.stateRootHash}`,
+            data.header.state_root_hash === proof.stateRootHash)
+    })
+}
+
+const verifyRecord = (provenance, proof) => {
+  return fetchState(proof.addresses.record, proof.blockId, 'RecordContainer')
+    .then(entries => {
+      const record = _.find(entries, { recordId })
+      check('record exists', !!record)
+      if (!record) return
+
+      check(`record type is ${provenance.recordType}`,
+            record.recordType === provenance.recordType)
+      check(`record is ${provenance.final ? '' : 'not '}final`,
+            record.final === provenance.final)
+    })
+}
+
+const verifyOwnership = (provenance, proof) => {
+  return Promise.all(proof.addresses.timeline.map(address => {
+    return fetchState(address, proof.blockId, 'RecordTimelinePageContainer')
+  }))
+    .then(pages => {
+      const owners = _.chain(pages)
+        .flatten()
+        .filter({ recordId })
+        .flatMap('entries')
+        .filter({ role: 'OWNER' })
+        .map(entry => [entry.agentId, entry.timestamp])
+        .value()
+      const claimed = provenance.ownership
+        .map(owner => [owner.publicKey, owner.timestamp])
+
+      check(`ownership history has ${claimed.length} hops`,
+            _.isEqual(owners, claimed))
+    })
+}
+
+const verifyProperties = (provenance, proof) => {
+  return Promise.all(_.map(provenance.properties, (property, name) => {
+    const address = proof.addresses.properties[name]
+    return fetchState(address, proof.blockId, 'PropertyPageContainer')
+      .then(entries => {
+        const page = _.find(entries, { recordId, name })
+        const reported = page && _.find(page.reportedValues, {
+          timestamp: property.timestamp
+        })
+        check(`${name} was reported at ${property.timestamp}`, !!reported)
+
+        const field = VALUE_FIELDS[property.dataType]
+        if (reported && field) {
+          check(`${name} is ${property.value}`,
+                reported[field] === property.value)
+        }
+      })
+  }))
+}
+
+protos.compile()
+  .then(() => request({ url: `${SERVER}/verify/${recordId}`, json: true }))
+  .then(provenance => {
+    const proof = provenance.proof
+    console.log(`Verifying ${recordId} at block ${proof.blockNum}`)
+
+    return verifyBlock(proof)
+      .then(() => verifyRecord(provenance, proof))
+      .then(() => verifyOwnership(provenance, proof))
+      .then(() => verifyProperties(provenance, proof))
+  })
+  .then(() => {
+    if (failures.length > 0) {
+      console.log(`${failures.length} check(s) failed`)
+      process.exit(1)
+    }
+    console.log('All checks passed')
+  })
+  .catch(err => {
+    console.error(`Unable to verify ${recordId}: ${err.message}`)
+    process.exit(2)
+  })
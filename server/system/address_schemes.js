@@ -0,0 +1,90 @@
+/**
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+const { createHash } = require('crypto')
+
+const NAMESPACE = '3400de'
+const TENANT_PREFIX_LENGTH = 6
+
+const hash = (str, length) => {
+  return createHash('sha512').update(str).digest('hex').slice(0, length)
+}
+
+const toHex = (num, length) => num.toString(16).padStart(length, '0')
+
+// Makes the address functions of an addressing scheme. With no options
+// they are the transaction processor's current addresses. Otherwise:
+//
+//   tenant: a tenant name, whose hash follows the type of every address,
+//     so a tenant's state can be listed by prefix. It takes the place of
+//     the start of the first hashed part of the address.
+//   roleAwareProposals: if true, Proposals are stored by role as well as
+//     by Record and receiving Agent, so that open ownership, custodianship
+//     and reporter Proposals no longer share a container
+const makeScheme = ({ tenant = null, roleAwareProposals = false } = {}) => {
+  const tenantPrefix = tenant === null ? '' : hash(tenant, TENANT_PREFIX_LENGTH)
+  const address = (type, first, length, ...rest) => {
+    return NAMESPACE + type + tenantPrefix +
+      hash(first, length - tenantPrefix.length) + rest.join('')
+  }
+
+  return {
+    agent: publicKey => address('ae', publicKey, 62),
+    agentActivity: (agentId, page) => {
+      return address('a0', agentId, 58, toHex(page, 4))
+    },
+    subscription: agentId => address('af', agentId, 62),
+    message: (recordId, digest) => {
+      return address('ad', recordId, 36, hash(digest, 26))
+    },
+    feedback: (recordId, page) => address('a4', recordId, 58, toHex(page, 4)),
+    stateAnchor: (service, blockNum) => {
+      return address('a1', service, 46, toHex(blockNum, 16))
+    },
+    record: recordId => address('ec', recordId, 62),
+    recordTimeline: (recordId, page) => {
+      return address('ed', recordId, 58, toHex(page, 4))
+    },
+    recordType: name => address('ee', name, 62),
+    recordTypeVersion: (name, version) => {
+      return address('a5', name, 54, toHex(version, 8))
+    },
+    recordTypeStats: (name, day) => address('a3', name, 54, toHex(day, 8)),
+    recordTemplate: name => address('a6', name, 62),
+    property: (recordId, name, page) => {
+      return address('ea', recordId, 36, hash(name, 22), toHex(page, 4))
+    },
+    excursionLog: (recordId, name) => {
+      return address('ac', recordId, 36, hash(name, 26))
+    },
+    proposal: (recordId, agentId, role) => {
+      return roleAwareProposals
+        ? address('aa', recordId, 36, hash(agentId, 22), hash(role, 4))
+        : address('aa', recordId, 36, hash(agentId, 26))
+    },
+    proposalHistory: (recordId, agentId, page) => {
+      return address('ab', recordId, 36, hash(agentId, 22), toHex(page, 4))
+    },
+    proposalIndex: recordId => address('a2', recordId, 62)
+  }
+}
+
+module.exports = {
+  NAMESPACE,
+  makeScheme
+}
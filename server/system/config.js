@@ -40,6 +40,9 @@ const initConfigValue = (key, defaultValue = null) => {
 initConfigValue('PORT', 3000)
 initConfigValue('RETRY_WAIT', 5000)
 initConfigValue('DEFAULT_SUBMIT_WAIT', 5000000)
+initConfigValue('MIN_BLOCK_WAIT', 5000)
+initConfigValue('VERIFY_RATE_LIMIT', 30)
+initConfigValue('PUBLIC_PROPERTIES', 'origin,certifications')
 initConfigValue('VALIDATOR_URL', 'tcp://localhost:4004')
 initConfigValue('DB_HOST', 'localhost')
 initConfigValue('DB_PORT', 28015)
@@ -48,6 +51,8 @@ initConfigValue('SIGNING_ALGORITHM', 'secp256k1')
 
 // Setup config variables with no defaults
 initConfigValue('MAPS_API_KEY')
+initConfigValue('CATALOG_DIR')
+initConfigValue('REDACTION_RULES')
 
 // Setup sensitive variable, warning user if using defaults
 initConfigValue('JWT_SECRET')
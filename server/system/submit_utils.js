@@ -26,6 +26,10 @@ const {
   TransactionList
 } = require('sawtooth-sdk/protobuf')
 const protos = require('../blockchain/protos')
+const {
+  makeNamespaceInputs,
+  SETTING_ADDRESSES
+} = require('../../shared/setting_addresses')
 
 const FAMILY_NAME = 'supply_chain'
 const FAMILY_VERSION = '1.1'
@@ -34,6 +38,9 @@ const NAMESPACE = '3400de'
 const SERVER = process.env.SERVER || 'http://localhost:3000'
 const RETRY_WAIT = process.env.RETRY_WAIT || 5000
 
+// The network's unit for payload timestamps, as reported by the server
+let timestampUnit = 'seconds'
+
 const awaitServerInfo = () => {
   return request(`${SERVER}/info`)
     .catch(() => {
@@ -45,16 +52,22 @@ const awaitServerInfo = () => {
 }
 
 const awaitServerPubkey = () => {
-  return awaitServerInfo().then(info => JSON.parse(info).pubkey)
+  return awaitServerInfo()
+    .then(info => JSON.parse(info))
+    .then(info => {
+      timestampUnit = info.timestampUnit || timestampUnit
+      return info.pubkey
+    })
 }
 
-const encodeHeader = (signerPublicKey, batcherPublicKey, payload) => {
+const encodeHeader = (signerPublicKey, batcherPublicKey, payload,
+                      extraInputs = []) => {
   return TransactionHeader.encode({
     signerPublicKey,
     batcherPublicKey,
     familyName: FAMILY_NAME,
     familyVersion: FAMILY_VERSION,
-    inputs: [NAMESPACE],
+    inputs: _.uniq([NAMESPACE].concat(SETTING_ADDRESSES, extraInputs)),
     outputs: [NAMESPACE],
     nonce: (Math.random() * 10 ** 18).toString(36),
     payloadSha512: createHash('sha512').update(payload).digest('hex')
@@ -72,8 +85,9 @@ const getTxnCreator = (privateKeyHex = null, batcherPublicKeyHex = null) => {
     ? signerPublicKey
     : batcherPublicKeyHex
 
-  return payload => {
-    const header = encodeHeader(signerPublicKey, batcherPublicKey, payload)
+  return (payload, extraInputs = []) => {
+    const header = encodeHeader(
+      signerPublicKey, batcherPublicKey, payload, extraInputs)
     const headerSignature = context.sign(header, privateKey)
     return Transaction.create({ header, headerSignature, payload })
   }
@@ -91,13 +105,16 @@ const submitTxns = transactions => {
 
 const encodeTimestampedPayload = message => {
   return protos.SCPayload.encode(_.assign({
-    timestamp: Math.floor(Date.now() / 1000)
+    timestamp: timestampUnit === 'milliseconds'
+      ? Date.now()
+      : Math.floor(Date.now() / 1000)
   }, message)).finish()
 }
 
 module.exports = {
   awaitServerPubkey,
   getTxnCreator,
+  makeNamespaceInputs,
   submitTxns,
   encodeTimestampedPayload
 }
@@ -0,0 +1,80 @@
+/**
+ * Copyright 2018 Cargill Incorporated
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ----------------------------------------------------------------------------
+ */
+'use strict'
+
+// Shared by the server and both clients, so it requires nothing outside
+// of Node's core modules
+const { createHash } = require('crypto')
+
+// Settings addresses hash up to four dot-separated parts of the key
+const makeSettingAddress = key => {
+  const parts = key.split('.')
+  const hashed = [0, 1, 2, 3].map(i => {
+    const part = i < 3 ? parts[i] || '' : parts.slice(3).join('.')
+    return createHash('sha256').update(part).digest('hex').slice(0, 16)
+  })
+  return '000000' + hashed.join('')
+}
+
+const TIMESTAMP_UNIT_SETTING = 'supply_chain.timestamp.unit'
+
+// The settings the processor reads by a fixed key, which every
+// transaction lists among its inputs. These must be the same as
+// FIXED_SETTINGS in processor/src/handler.rs, which a test there checks.
+const SETTING_KEYS = [
+  // Read when validating new RecordTypes
+  'supply_chain.record_type.max_properties',
+  // Read when authorizing reporters
+  'supply_chain.property.max_reporters',
+  // Read when creating Proposals
+  'supply_chain.proposal.max_open',
+  // Read for every transaction
+  'supply_chain.actions.disabled',
+  // Read when recording state anchors
+  'supply_chain.anchor.signers',
+  // Read when anchoring consumer feedback
+  'supply_chain.feedback.kiosks',
+  // Read when migrating state
+  'supply_chain.migration.signers',
+  // Read for every transaction to know whether Agents' roles are enforced
+  'supply_chain.agent.admins',
+  TIMESTAMP_UNIT_SETTING
+]
+
+const SETTING_ADDRESSES = SETTING_KEYS.map(makeSettingAddress)
+
+// Prefix of the supply_chain.record_type.namespace.<namespace> settings,
+// read when creating or updating a RecordType with a namespaced name
+const RECORD_TYPE_NAMESPACE_SETTING = 'supply_chain.record_type.namespace.'
+
+// The setting address to include when creating or updating a RecordType,
+// if its name is in a namespace, like "acme.fish_lot"
+const makeNamespaceInputs = typeName => {
+  const period = typeName.indexOf('.')
+  if (period === -1) return []
+  const namespace = typeName.slice(0, period)
+  return [makeSettingAddress(RECORD_TYPE_NAMESPACE_SETTING + namespace)]
+}
+
+module.exports = {
+  makeSettingAddress,
+  makeNamespaceInputs,
+  SETTING_KEYS,
+  SETTING_ADDRESSES,
+  TIMESTAMP_UNIT_SETTING,
+  TIMESTAMP_UNIT_SETTING_ADDRESS: makeSettingAddress(TIMESTAMP_UNIT_SETTING)
+}
@@ -14,6 +14,8 @@
 # -----------------------------------------------------------------------------
 
 import hashlib
+import os
+import re
 
 
 def _hash(string):
@@ -30,10 +32,22 @@ FAMILY_NAME = 'supply_chain'
 NAMESPACE = _hash(FAMILY_NAME)[:6]
 
 AGENT = 'ae'
+AGENT_ACTIVITY = 'a0'
+EXCURSION_LOG = 'ac'
+FEEDBACK = 'a4'
+MESSAGE = 'ad'
 PROPERTY = 'ea'
 PROPOSAL = 'aa'
+PROPOSAL_HISTORY = 'ab'
+PROPOSAL_INDEX = 'a2'
 RECORD = 'ec'
+RECORD_TEMPLATE = 'a6'
+RECORD_TIMELINE = 'ed'
 RECORD_TYPE = 'ee'
+RECORD_TYPE_STATS = 'a3'
+RECORD_TYPE_VERSION = 'a5'
+STATE_ANCHOR = 'a1'
+SUBSCRIPTION = 'af'
 
 
 def make_agent_address(identifier):
@@ -44,6 +58,55 @@ def make_agent_address(identifier):
     )
 
 
+def make_agent_activity_address(agent_id, page):
+    return (
+        make_agent_activity_address_range(agent_id)
+        + _num_to_page_number(page)
+    )
+
+
+def make_agent_activity_address_range(agent_id):
+    return (
+        NAMESPACE
+        + AGENT_ACTIVITY
+        + _hash(agent_id)[:58]
+    )
+
+
+def make_subscription_address(agent_id):
+    return (
+        NAMESPACE
+        + SUBSCRIPTION
+        + _hash(agent_id)[:62]
+    )
+
+
+def make_message_address(record_id, digest):
+    return (
+        NAMESPACE
+        + MESSAGE
+        + _hash(record_id)[:36]
+        + _hash(digest)[:26]
+    )
+
+
+def make_feedback_address_range(record_id):
+    return (
+        NAMESPACE
+        + FEEDBACK
+        + _hash(record_id)[:58]
+    )
+
+
+def make_state_anchor_address(service, block_num):
+    return (
+        NAMESPACE
+        + STATE_ANCHOR
+        + _hash(service)[:46]
+        + '{:016x}'.format(block_num)
+    )
+
+
 def make_record_address(record_id):
     return (
         NAMESPACE
@@ -52,6 +115,25 @@ def make_record_address(record_id):
     )
 
 
+RECORD_ADDRESS_RANGE = NAMESPACE + RECORD
+
+
+def make_record_timeline_address_range(record_id):
+    return (
+        NAMESPACE
+        + RECORD_TIMELINE
+        + _hash(record_id)[:58]
+    )
+
+
+def make_record_template_address(name):
+    return (
+        NAMESPACE
+        + RECORD_TEMPLATE
+        + _hash(name)[:62]
+    )
+
+
 def make_record_type_address(type_name):
     return (
         NAMESPACE
@@ -63,6 +145,86 @@ def make_record_type_address(type_name):
 RECORD_TYPE_ADDRESS_RANGE = NAMESPACE + RECORD_TYPE
 
 
+def make_record_type_version_address(type_name, version):
+    return (
+        NAMESPACE
+        + RECORD_TYPE_VERSION
+        + _hash(type_name)[:54]
+        + '{:08x}'.format(version)
+    )
+
+
+RECORD_TYPE_VERSION_ADDRESS_RANGE = NAMESPACE + RECORD_TYPE_VERSION
+
+
+def make_record_type_stats_address_range(type_name):
+    return (
+        NAMESPACE
+        + RECORD_TYPE_STATS
+        + _hash(type_name)[:54]
+    )
+
+
+RECORD_TYPE_STATS_ADDRESS_RANGE = NAMESPACE + RECORD_TYPE_STATS
+
+
+# Settings live in the sawtooth_settings namespace, which addresses
+# a key by hashing up to four of its dot-separated parts.
+
+SETTINGS_NAMESPACE = '000000'
+
+MAX_PROPERTIES_SETTING = 'supply_chain.record_type.max_properties'
+MAX_REPORTERS_SETTING = 'supply_chain.property.max_reporters'
+DISABLED_ACTIONS_SETTING = 'supply_chain.actions.disabled'
+TIMESTAMP_UNIT_SETTING = 'supply_chain.timestamp.unit'
+ANCHOR_SIGNERS_SETTING = 'supply_chain.anchor.signers'
+FEEDBACK_KIOSKS_SETTING = 'supply_chain.feedback.kiosks'
+MIGRATION_SIGNERS_SETTING = 'supply_chain.migration.signers'
+AGENT_ADMINS_SETTING = 'supply_chain.agent.admins'
+MAX_OPEN_PROPOSALS_SETTING = 'supply_chain.proposal.max_open'
+# Followed by the namespace
+RECORD_TYPE_NAMESPACE_SETTING = 'supply_chain.record_type.namespace.'
+
+
+def make_setting_address(key):
+    parts = key.split('.', 3)
+    parts += [''] * (4 - len(parts))
+    return SETTINGS_NAMESPACE + ''.join(
+        hashlib.sha256(part.encode('utf-8')).hexdigest()[:16]
+        for part in parts
+    )
+
+
+# The module the server and clients take the settings they list among
+# every transaction's inputs from
+SHARED_SETTINGS_PATH = os.path.join(
+    os.path.dirname(os.path.abspath(__file__)),
+    '..', '..', 'shared', 'setting_addresses.js')
+
+
+def make_shared_setting_addresses():
+    """The addresses of the settings in SETTING_KEYS of
+    shared/setting_addresses.js, read from the module itself so that
+    transactions list exactly the inputs the server and clients do"""
+    with open(SHARED_SETTINGS_PATH) as shared:
+        source = shared.read()
+    start = source.index('const SETTING_KEYS = [')
+    end = source.index('\n]', start)
+
+    keys = []
+    for line in source[start:end].splitlines()[1:]:
+        entry = line.strip().rstrip(',')
+        if not entry or entry.startswith('//'):
+            continue
+        if entry.startswith("'"):
+            keys.append(entry.strip("'"))
+        else:
+            keys.append(re.search(
+                "const {} = '([^']*)'".format(entry), source).group(1))
+
+    return [make_setting_address(key) for key in keys]
+
+
 def make_property_address(record_id, property_name, page=0):
     return (
         make_property_address_range(record_id)
@@ -83,6 +245,21 @@ def make_property_address_range(record_id):
     )
 
 
+def make_excursion_log_address(record_id, property_name):
+    return (
+        make_excursion_log_address_range(record_id)
+        + _hash(property_name)[:26]
+    )
+
+
+def make_excursion_log_address_range(record_id):
+    return (
+        NAMESPACE
+        + EXCURSION_LOG
+        + _hash(record_id)[:36]
+    )
+
+
 def make_proposal_address(record_id, agent_id):
     return (
         NAMESPACE
@@ -90,3 +267,29 @@ def make_proposal_address(record_id, agent_id):
         + _hash(record_id)[:36]
         + _hash(agent_id)[:26]
     )
+
+
+def make_proposal_address_range(record_id):
+    return NAMESPACE + PROPOSAL + _hash(record_id)[:36]
+
+
+def make_proposal_history_address_range(record_id, agent_id=None):
+    # Without an agent, the range covers the history of every Agent
+    # proposed to for the Record
+    if agent_id is None:
+        return NAMESPACE + PROPOSAL_HISTORY + _hash(record_id)[:36]
+
+    return (
+        NAMESPACE
+        + PROPOSAL_HISTORY
+        + _hash(record_id)[:36]
+        + _hash(agent_id)[:22]
+    )
+
+
+def make_proposal_index_address(record_id):
+    return (
+        NAMESPACE
+        + PROPOSAL_INDEX
+        + _hash(record_id)[:62]
+    )
@@ -19,21 +19,67 @@ import time
 
 from sawtooth_processor_test.message_factory import MessageFactory
 
+from sawtooth_sdk.protobuf.transaction_pb2 import TransactionHeader
+
+from sawtooth_signing import create_context
+from sawtooth_signing import CryptoFactory
+
 from sawtooth_sc_test.protobuf.payload_pb2 import SCPayload
 from sawtooth_sc_test.protobuf.payload_pb2 import CreateAgentAction
 from sawtooth_sc_test.protobuf.payload_pb2 import CreateProposalAction
 from sawtooth_sc_test.protobuf.payload_pb2 import AnswerProposalAction
 from sawtooth_sc_test.protobuf.payload_pb2 import CreateRecordAction
+from sawtooth_sc_test.protobuf.payload_pb2 import ReporterAuthorization
 from sawtooth_sc_test.protobuf.payload_pb2 import \
     CreateRecordTypeAction
 from sawtooth_sc_test.protobuf.payload_pb2 import FinalizeRecordAction
 from sawtooth_sc_test.protobuf.payload_pb2 import \
     UpdatePropertiesAction
+from sawtooth_sc_test.protobuf.payload_pb2 import StructUpdateMask
 from sawtooth_sc_test.protobuf.payload_pb2 import RevokeReporterAction
+from sawtooth_sc_test.protobuf.payload_pb2 import ExecuteTransferAction
+from sawtooth_sc_test.protobuf.payload_pb2 import MessageAction
+from sawtooth_sc_test.protobuf.payload_pb2 import SubscribeAction
+from sawtooth_sc_test.protobuf.payload_pb2 import UnsubscribeAction
+from sawtooth_sc_test.protobuf.payload_pb2 import CompositeAction
+from sawtooth_sc_test.protobuf.payload_pb2 import \
+    ReceiveConfirmationAction
+from sawtooth_sc_test.protobuf.payload_pb2 import AnchorStateAction
+from sawtooth_sc_test.protobuf.payload_pb2 import CloneRecordAction
+from sawtooth_sc_test.protobuf.payload_pb2 import AnchorFeedbackAction
+from sawtooth_sc_test.protobuf.payload_pb2 import MigrateStateAction
+from sawtooth_sc_test.protobuf.payload_pb2 import DeleteRecordTypeAction
+from sawtooth_sc_test.protobuf.payload_pb2 import AcknowledgeValueAction
+from sawtooth_sc_test.protobuf.payload_pb2 import UpdateRecordTypeAction
+from sawtooth_sc_test.protobuf.payload_pb2 import DirectTransferAction
+from sawtooth_sc_test.protobuf.payload_pb2 import BulkUpdatePropertiesAction
+from sawtooth_sc_test.protobuf.payload_pb2 import DelegateReporterAction
+from sawtooth_sc_test.protobuf.payload_pb2 import MergeRecordsAction
+from sawtooth_sc_test.protobuf.payload_pb2 import ArchiveRecordAction
+from sawtooth_sc_test.protobuf.payload_pb2 import ReopenRecordAction
+from sawtooth_sc_test.protobuf.payload_pb2 import RecallRecordAction
+from sawtooth_sc_test.protobuf.payload_pb2 import SetAgentRoleAction
+from sawtooth_sc_test.protobuf.payload_pb2 import DeactivateAgentAction
+from sawtooth_sc_test.protobuf.payload_pb2 import CreateRecordTemplateAction
+from sawtooth_sc_test.protobuf.payload_pb2 import \
+    CreateRecordFromTemplateAction
+from sawtooth_sc_test.protobuf.payload_pb2 import SetRecordMetadataAction
+from sawtooth_sc_test.protobuf.payload_pb2 import AttestAuditAction
+from sawtooth_sc_test.protobuf.payload_pb2 import CancelAllProposalsAction
+from sawtooth_sc_test.protobuf.payload_pb2 import AmendProposalAction
+from sawtooth_sc_test.protobuf.payload_pb2 import ReleaseEscrowAction
+from sawtooth_sc_test.protobuf.payload_pb2 import TransferAuthorization
 
+from sawtooth_sc_test.protobuf.property_pb2 import Location
 from sawtooth_sc_test.protobuf.property_pb2 import PropertySchema
 from sawtooth_sc_test.protobuf.property_pb2 import PropertyValue
 
+from sawtooth_sc_test.protobuf.record_pb2 import ConditionCheck
+from sawtooth_sc_test.protobuf.record_pb2 import FinalizationPolicy
+from sawtooth_sc_test.protobuf.record_pb2 import Record
+from sawtooth_sc_test.protobuf.record_pb2 import RetentionRule
+from sawtooth_sc_test.protobuf.record_pb2 import TransferTerms
+
 import sawtooth_sc_test.addressing as addressing
 
 
@@ -47,8 +93,42 @@ class Enum(object):
         self.value = name
 
 
+class Timestamp(object):
+    """A wrapper class to tell a Unix timestamp apart from a number"""
+    def __init__(self, timestamp):
+        self.value = timestamp
+
+
+class RecordReference(object):
+    """A wrapper class to tell a referenced record_id apart from a string"""
+    def __init__(self, record_id):
+        self.value = record_id
+
+
+class Identifier(object):
+    """A wrapper class to tell a serial number or trace code apart from a
+    string"""
+    def __init__(self, identifier):
+        self.value = identifier
+
+
+class Measurement(object):
+    """A wrapper class for a number reported in a unit other than its
+    property's"""
+    def __init__(self, number, unit):
+        self.value = number
+        self.unit = unit
+
+
 class SupplyChainMessageFactory:
     def __init__(self, signer=None):
+        # Kept to sign transfer authorizations as well as transactions
+        if signer is None:
+            context = create_context('secp256k1')
+            signer = CryptoFactory(context).new_signer(
+                context.new_random_private_key())
+        self._signer = signer
+
         self._factory = MessageFactory(
             family_name=addressing.FAMILY_NAME,
             family_version='1.1',
@@ -69,31 +149,86 @@ class SupplyChainMessageFactory:
             [self.signer_address],
         )
 
-    def create_record_type(self, name, *properties):
-        def make_schema(name, data_type, attrs):
-            if 'struct_properties' in attrs:
-                attrs['struct_properties'] =\
-                    [make_schema(n, dt, a)
-                     for n, dt, a in attrs['struct_properties']]
-            return PropertySchema(name=name, data_type=data_type, **attrs)
-
+    def create_record_type(self, name, *properties, rules=None,
+                           retention_rules=None, finalization_policy=None):
         payload = _make_sc_payload(
             action=SCPayload.CREATE_RECORD_TYPE,
             create_record_type=CreateRecordTypeAction(
                 name=name,
-                properties=[make_schema(n, dt, a) for (n, dt, a) in properties]
+                properties=[
+                    _make_property_schema(n, dt, a)
+                    for (n, dt, a) in properties],
+                rules=rules or [],
+                retention_rules=[
+                    RetentionRule(property_name=n, prunable_after=after)
+                    for n, after in (retention_rules or {}).items()],
+                finalization_policy=_make_finalization_policy(
+                    finalization_policy),
             )
         )
 
         record_type_address = addressing.make_record_type_address(name)
+        setting_address = addressing.make_setting_address(
+            addressing.MAX_PROPERTIES_SETTING)
+
+        inputs = [record_type_address, self.signer_address, setting_address]
+
+        # A namespaced type is checked against its namespace's members
+        if '.' in name:
+            inputs.append(addressing.make_setting_address(
+                addressing.RECORD_TYPE_NAMESPACE_SETTING
+                + name.split('.')[0]))
 
         return self._create_transaction(
             payload,
-            inputs=[record_type_address, self.signer_address],
+            inputs=inputs,
             outputs=[record_type_address],
         )
 
-    def create_record(self, record_id, record_type, properties_dict):
+    def set_agent_role(self, agent_id, role):
+        payload = _make_sc_payload(
+            action=SCPayload.SET_AGENT_ROLE,
+            set_agent_role=SetAgentRoleAction(
+                agent_id=agent_id,
+                role=role))
+
+        agent_address = addressing.make_agent_address(agent_id)
+
+        return self._create_transaction(
+            payload,
+            [
+                agent_address,
+                addressing.make_setting_address(
+                    addressing.AGENT_ADMINS_SETTING),
+            ],
+            [agent_address]
+        )
+
+    def deactivate_agent(self, agent_id):
+        payload = _make_sc_payload(
+            action=SCPayload.DEACTIVATE_AGENT,
+            deactivate_agent=DeactivateAgentAction(
+                agent_id=agent_id))
+
+        agent_address = addressing.make_agent_address(agent_id)
+
+        return self._create_transaction(
+            payload,
+            [
+                agent_address,
+                addressing.make_setting_address(
+                    addressing.AGENT_ADMINS_SETTING),
+            ],
+            [agent_address]
+        )
+
+    def create_record(self, record_id, record_type, properties_dict,
+                      reporters=None):
+        """Creates a Record, authorizing each public key in the
+        `reporters` dict to report on the Properties it maps to"""
+        if reporters is None:
+            reporters = {}
+
         payload = _make_sc_payload(
             action=SCPayload.CREATE_RECORD,
             create_record=CreateRecordAction(
@@ -102,6 +237,12 @@ class SupplyChainMessageFactory:
                 properties=[
                     _make_property_value(name, value)
                     for name, value in properties_dict.items()
+                ],
+                reporters=[
+                    ReporterAuthorization(
+                        public_key=public_key,
+                        properties=properties)
+                    for public_key, properties in reporters.items()
                 ]
             )
         )
@@ -110,11 +251,212 @@ class SupplyChainMessageFactory:
         record_type_address = addressing.make_record_type_address(record_type)
         property_address_range = \
             addressing.make_property_address_range(record_id)
+        timeline_address_range = \
+            addressing.make_record_timeline_address_range(record_id)
+        excursion_log_address_range = \
+            addressing.make_excursion_log_address_range(record_id)
+        stats_address_range = \
+            addressing.make_record_type_stats_address_range(record_type)
 
         inputs = [
             record_address,
             record_type_address,
             property_address_range,
+            timeline_address_range,
+            excursion_log_address_range,
+            stats_address_range,
+            self.signer_address,
+        ] + _make_reference_addresses(properties_dict)
+
+        if reporters:
+            inputs.append(addressing.make_setting_address(
+                addressing.MAX_REPORTERS_SETTING))
+            inputs.extend(
+                addressing.make_agent_address(public_key)
+                for public_key in reporters)
+
+        # The first Record of a type marks the type as used
+        return self._create_transaction(
+            payload,
+            inputs=inputs,
+            outputs=[
+                record_address,
+                record_type_address,
+                property_address_range,
+                timeline_address_range,
+                excursion_log_address_range,
+                stats_address_range,
+            ]
+        )
+
+    def create_record_template(self, name, record_type, properties_dict):
+        payload = _make_sc_payload(
+            action=SCPayload.CREATE_RECORD_TEMPLATE,
+            create_record_template=CreateRecordTemplateAction(
+                name=name,
+                record_type=record_type,
+                properties=[
+                    _make_property_value(name, value)
+                    for name, value in properties_dict.items()
+                ]))
+
+        template_address = addressing.make_record_template_address(name)
+
+        return self._create_transaction(
+            payload,
+            inputs=[
+                template_address,
+                addressing.make_record_type_address(record_type),
+                self.signer_address,
+            ],
+            outputs=[template_address]
+        )
+
+    def create_record_from_template(self, record_id, template,
+                                    properties_dict=None, reporters=None):
+        """Creates a Record as create_record does, with the template's
+        values for any Properties not in `properties_dict`"""
+        if properties_dict is None:
+            properties_dict = {}
+        if reporters is None:
+            reporters = {}
+
+        payload = _make_sc_payload(
+            action=SCPayload.CREATE_RECORD_FROM_TEMPLATE,
+            create_record_from_template=CreateRecordFromTemplateAction(
+                record_id=record_id,
+                template=template,
+                properties=[
+                    _make_property_value(name, value)
+                    for name, value in properties_dict.items()
+                ],
+                reporters=[
+                    ReporterAuthorization(
+                        public_key=public_key,
+                        properties=properties)
+                    for public_key, properties in reporters.items()
+                ]
+            )
+        )
+
+        # The Record's type is only known from the template, so the
+        # ranges of every type and its stats are given
+        addresses = [
+            addressing.make_record_address(record_id),
+            addressing.make_property_address_range(record_id),
+            addressing.make_record_timeline_address_range(record_id),
+            addressing.make_excursion_log_address_range(record_id),
+            addressing.RECORD_TYPE_ADDRESS_RANGE,
+            addressing.RECORD_TYPE_STATS_ADDRESS_RANGE,
+        ]
+
+        # The template's values may reference any Record
+        inputs = addresses + [
+            addressing.make_record_template_address(template),
+            addressing.RECORD_ADDRESS_RANGE,
+            self.signer_address,
+        ]
+
+        if reporters:
+            inputs.append(addressing.make_setting_address(
+                addressing.MAX_REPORTERS_SETTING))
+            inputs.extend(
+                addressing.make_agent_address(public_key)
+                for public_key in reporters)
+
+        return self._create_transaction(
+            payload,
+            inputs=inputs,
+            outputs=addresses
+        )
+
+    def merge_records(self, record_id, record_type, parent_ids,
+                      properties_dict=None):
+        if properties_dict is None:
+            properties_dict = {}
+
+        payload = _make_sc_payload(
+            action=SCPayload.MERGE_RECORDS,
+            merge_records=MergeRecordsAction(
+                record_id=record_id,
+                record_type=record_type,
+                parent_ids=parent_ids,
+                properties=[
+                    _make_property_value(name, value)
+                    for name, value in properties_dict.items()
+                ]))
+
+        # The new Record is created as by create_record, and the ones
+        # merged into it finalized as by finalize_record
+        merged_addresses = [
+            addressing.make_record_address(record_id),
+            addressing.make_property_address_range(record_id),
+            addressing.make_record_timeline_address_range(record_id),
+            addressing.make_excursion_log_address_range(record_id),
+        ]
+        parent_addresses = [
+            addressing.make_record_address(parent_id)
+            for parent_id in parent_ids
+        ]
+        parent_reads = [
+            address_range
+            for parent_id in parent_ids
+            for address_range in (
+                addressing.make_property_address_range(parent_id),
+                addressing.make_excursion_log_address_range(parent_id))
+        ]
+
+        return self._create_transaction(
+            payload,
+            inputs=merged_addresses + parent_addresses + parent_reads + [
+                addressing.RECORD_TYPE_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_STATS_ADDRESS_RANGE,
+                self.signer_address,
+            ],
+            outputs=merged_addresses + parent_addresses + [
+                addressing.make_record_type_address(record_type),
+                addressing.RECORD_TYPE_STATS_ADDRESS_RANGE,
+            ],
+        )
+
+    def clone_record(self, record_id, source_record_id, properties_dict=None):
+        if properties_dict is None:
+            properties_dict = {}
+
+        payload = _make_sc_payload(
+            action=SCPayload.CLONE_RECORD,
+            clone_record=CloneRecordAction(
+                record_id=record_id,
+                source_record_id=source_record_id,
+                properties=[
+                    _make_property_value(name, value)
+                    for name, value in properties_dict.items()
+                ]
+            )
+        )
+
+        record_address = addressing.make_record_address(record_id)
+        source_address = addressing.make_record_address(source_record_id)
+        property_address_range = \
+            addressing.make_property_address_range(record_id)
+        source_property_address_range = \
+            addressing.make_property_address_range(source_record_id)
+        timeline_address_range = \
+            addressing.make_record_timeline_address_range(record_id)
+        excursion_log_address_range = \
+            addressing.make_excursion_log_address_range(record_id)
+
+        inputs = [
+            record_address,
+            source_address,
+            addressing.RECORD_TYPE_ADDRESS_RANGE,
+            addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+            property_address_range,
+            source_property_address_range,
+            timeline_address_range,
+            excursion_log_address_range,
+            addressing.RECORD_TYPE_STATS_ADDRESS_RANGE,
             self.signer_address,
         ]
 
@@ -124,6 +466,9 @@ class SupplyChainMessageFactory:
             outputs=[
                 record_address,
                 property_address_range,
+                timeline_address_range,
+                excursion_log_address_range,
+                addressing.RECORD_TYPE_STATS_ADDRESS_RANGE,
             ]
         )
 
@@ -133,6 +478,91 @@ class SupplyChainMessageFactory:
             finalize_record=FinalizeRecordAction(
                 record_id=record_id))
 
+        record_address = addressing.make_record_address(record_id)
+        stats_address_range = addressing.RECORD_TYPE_STATS_ADDRESS_RANGE
+
+        # The Record's type is read for its finalization policy, which
+        # may read the Record's Properties and excursion logs
+        return self._create_transaction(
+            payload,
+            [
+                record_address,
+                stats_address_range,
+                addressing.RECORD_TYPE_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+                addressing.make_property_address_range(record_id),
+                addressing.make_excursion_log_address_range(record_id),
+            ],
+            [record_address, stats_address_range]
+        )
+
+    def archive_record(self, record_id, prune_values=False,
+                       delete_pages=False):
+        payload = _make_sc_payload(
+            action=SCPayload.ARCHIVE_RECORD,
+            archive_record=ArchiveRecordAction(
+                record_id=record_id,
+                prune_values=prune_values,
+                delete_pages=delete_pages))
+
+        record_address = addressing.make_record_address(record_id)
+        property_address_range = \
+            addressing.make_property_address_range(record_id)
+
+        # The Record's type is read for its retention rules and Properties,
+        # and the pages of values being deleted are removed
+        return self._create_transaction(
+            payload,
+            [
+                record_address,
+                property_address_range,
+                addressing.RECORD_TYPE_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+            ],
+            [record_address, property_address_range]
+        )
+
+    def reopen_record(self, record_id):
+        payload = _make_sc_payload(
+            action=SCPayload.REOPEN_RECORD,
+            reopen_record=ReopenRecordAction(
+                record_id=record_id))
+
+        record_address = addressing.make_record_address(record_id)
+
+        return self._create_transaction(
+            payload,
+            [record_address],
+            [record_address]
+        )
+
+    def set_record_metadata(self, record_id, metadata_dict):
+        payload = _make_sc_payload(
+            action=SCPayload.SET_RECORD_METADATA,
+            set_record_metadata=SetRecordMetadataAction(
+                record_id=record_id,
+                entries=[
+                    Record.MetadataEntry(key=key, value=value)
+                    for key, value in metadata_dict.items()
+                ]))
+
+        record_address = addressing.make_record_address(record_id)
+
+        return self._create_transaction(
+            payload,
+            [record_address],
+            [record_address]
+        )
+
+    def attest_audit(self, record_id, reviewed_through, digest, passed):
+        payload = _make_sc_payload(
+            action=SCPayload.ATTEST_AUDIT,
+            attest_audit=AttestAuditAction(
+                record_id=record_id,
+                reviewed_through=reviewed_through,
+                digest=digest,
+                passed=passed))
+
         record_address = addressing.make_record_address(record_id)
 
         return self._create_transaction(
@@ -141,7 +571,29 @@ class SupplyChainMessageFactory:
             [record_address]
         )
 
-    def update_properties(self, record_id, properties_dict):
+    def recall_record(self, record_id, reason, include_descendants=False):
+        payload = _make_sc_payload(
+            action=SCPayload.RECALL_RECORD,
+            recall_record=RecallRecordAction(
+                record_id=record_id,
+                reason=reason,
+                include_descendants=include_descendants))
+
+        # The Records merged into are only known once the Record is read,
+        # so a recall of them may touch any Record
+        if include_descendants:
+            record_addresses = [addressing.RECORD_ADDRESS_RANGE]
+        else:
+            record_addresses = [addressing.make_record_address(record_id)]
+
+        return self._create_transaction(
+            payload, record_addresses, record_addresses)
+
+    def update_properties(self, record_id, properties_dict,
+                          struct_masks=None):
+        if struct_masks is None:
+            struct_masks = {}
+
         payload = _make_sc_payload(
             action=SCPayload.UPDATE_PROPERTIES,
             update_properties=UpdatePropertiesAction(
@@ -149,6 +601,10 @@ class SupplyChainMessageFactory:
                 properties=[
                     _make_property_value(name, value)
                     for name, value in properties_dict.items()
+                ],
+                struct_masks=[
+                    StructUpdateMask(property=name, fields=fields)
+                    for name, fields in struct_masks.items()
                 ]
             )
         )
@@ -156,80 +612,282 @@ class SupplyChainMessageFactory:
         record_address = addressing.make_record_address(record_id)
         property_address_range = \
             addressing.make_property_address_range(record_id)
+        excursion_log_address_range = \
+            addressing.make_excursion_log_address_range(record_id)
 
         inputs = [
             record_address,
             property_address_range,
-        ]
+            excursion_log_address_range,
+            addressing.RECORD_TYPE_ADDRESS_RANGE,
+            addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+        ] + _make_escrow_addresses(record_id) + \
+            _make_reference_addresses(properties_dict)
+
+        # The Record lists Properties awaiting the owner's acknowledgment,
+        # and reporting its escrow's release Property transfers it
+        return self._create_transaction(
+            payload,
+            inputs=inputs,
+            outputs=[
+                record_address,
+                property_address_range,
+                excursion_log_address_range,
+            ] + _make_escrow_addresses(record_id)
+        )
+
+    def bulk_update_properties(self, record_ids, properties_dict,
+                               struct_masks=None, policy=None):
+        if struct_masks is None:
+            struct_masks = {}
+
+        if policy is None:
+            policy = BulkUpdatePropertiesAction.ALL_OR_NOTHING
+
+        payload = _make_sc_payload(
+            action=SCPayload.BULK_UPDATE_PROPERTIES,
+            bulk_update_properties=BulkUpdatePropertiesAction(
+                record_ids=record_ids,
+                properties=[
+                    _make_property_value(name, value)
+                    for name, value in properties_dict.items()
+                ],
+                struct_masks=[
+                    StructUpdateMask(property=name, fields=fields)
+                    for name, fields in struct_masks.items()
+                ],
+                policy=policy
+            )
+        )
+
+        outputs = []
+        for record_id in record_ids:
+            outputs.extend([
+                addressing.make_record_address(record_id),
+                addressing.make_property_address_range(record_id),
+                addressing.make_excursion_log_address_range(record_id),
+            ] + _make_escrow_addresses(record_id))
+
+        inputs = outputs + [
+            addressing.RECORD_TYPE_ADDRESS_RANGE,
+            addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+        ] + _make_reference_addresses(properties_dict)
 
         return self._create_transaction(
             payload,
             inputs=inputs,
-            outputs=[property_address_range]
+            outputs=outputs
         )
 
     def create_proposal(self, record_id, receiving_agent,
-                        role, properties=None):
+                        role, properties=None, effective_at=0,
+                        expires_at=0, expiration=0, approvers=None,
+                        transfer_terms=None, escrow=False,
+                        release_property=''):
         if properties is None:
             properties = []
 
+        if approvers is None:
+            approvers = []
+
+        if transfer_terms is not None:
+            transfer_terms = TransferTerms(**transfer_terms)
+
         payload = _make_sc_payload(
             action=SCPayload.CREATE_PROPOSAL,
             create_proposal=CreateProposalAction(
                 record_id=record_id,
                 receiving_agent=receiving_agent,
                 role=role,
-                properties=properties))
+                properties=properties,
+                effective_at=effective_at,
+                expires_at=expires_at,
+                expiration=expiration,
+                approvers=approvers,
+                transfer_terms=transfer_terms,
+                escrow=escrow,
+                release_property=release_property))
 
-        proposal_address = addressing.make_proposal_address(
-            record_id,
-            receiving_agent)
+        # Open Proposals for the role which have expired, to this or any
+        # other Agent, are moved to their histories
+        proposal_address_range = \
+            addressing.make_proposal_address_range(record_id)
+
+        history_address_range = \
+            addressing.make_proposal_history_address_range(record_id)
 
         receiving_address = addressing.make_agent_address(receiving_agent)
 
+        approver_addresses = [
+            addressing.make_agent_address(approver)
+            for approver in approvers
+        ]
+
         record_address = addressing.make_record_address(record_id)
 
+        index_address = addressing.make_proposal_index_address(record_id)
+
+        setting_address = addressing.make_setting_address(
+            addressing.MAX_OPEN_PROPOSALS_SETTING)
+
         return self._create_transaction(
             payload,
             inputs=[
-                proposal_address,
+                proposal_address_range,
+                history_address_range,
+                index_address,
                 record_address,
                 receiving_address,
                 self.signer_address,
+                setting_address,
+            ] + approver_addresses,
+            outputs=[
+                proposal_address_range,
+                history_address_range,
+                index_address,
             ],
-            outputs=[proposal_address],
         )
 
-    def answer_proposal(self, record_id, receiving_agent, role, response):
+    def answer_proposal(self, record_id, receiving_agent, role, response,
+                        terms=''):
         payload = _make_sc_payload(
             action=SCPayload.ANSWER_PROPOSAL,
             answer_proposal=AnswerProposalAction(
                 record_id=record_id,
                 receiving_agent=receiving_agent,
                 role=role,
-                response=response))
+                response=response,
+                terms=terms))
 
-        proposal_address = addressing.make_proposal_address(
-            record_id,
-            receiving_agent)
+        # A counter-proposal is stored with the issuing agent's proposals
+        proposal_address_range = \
+            addressing.make_proposal_address_range(record_id)
 
         record_address = addressing.make_record_address(record_id)
 
         property_address_range = addressing.make_property_address_range(
             record_id)
 
+        index_address = addressing.make_proposal_index_address(record_id)
+
+        history_address_range = \
+            addressing.make_proposal_history_address_range(record_id)
+
+        timeline_address_range = \
+            addressing.make_record_timeline_address_range(record_id)
+
+        setting_address = addressing.make_setting_address(
+            addressing.MAX_REPORTERS_SETTING)
+
         return self._create_transaction(
             payload,
             inputs=[
-                proposal_address,
+                proposal_address_range,
+                index_address,
+                history_address_range,
                 record_address,
                 property_address_range,
+                timeline_address_range,
                 addressing.RECORD_TYPE_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+                setting_address,
             ],
             outputs=[
-                proposal_address,
+                proposal_address_range,
+                index_address,
+                history_address_range,
                 record_address,
                 property_address_range,
+                timeline_address_range,
+            ],
+        )
+
+    def amend_proposal(self, record_id, receiving_agent, role,
+                       properties=None, terms='', transfer_terms=None):
+        if transfer_terms is not None:
+            transfer_terms = TransferTerms(**transfer_terms)
+
+        payload = _make_sc_payload(
+            action=SCPayload.AMEND_PROPOSAL,
+            amend_proposal=AmendProposalAction(
+                record_id=record_id,
+                receiving_agent=receiving_agent,
+                role=role,
+                properties=properties or [],
+                terms=terms,
+                transfer_terms=transfer_terms))
+
+        proposal_address = addressing.make_proposal_address(
+            record_id, receiving_agent)
+
+        record_address = addressing.make_record_address(record_id)
+
+        return self._create_transaction(
+            payload,
+            inputs=[proposal_address, record_address],
+            outputs=[proposal_address],
+        )
+
+    def release_escrow(self, record_id, revert=False):
+        payload = _make_sc_payload(
+            action=SCPayload.RELEASE_ESCROW,
+            release_escrow=ReleaseEscrowAction(
+                record_id=record_id,
+                revert=revert))
+
+        # Completing the transfer authorizes the new owner to report each
+        # Property, as accepting a Proposal does
+        record_address = addressing.make_record_address(record_id)
+
+        property_address_range = addressing.make_property_address_range(
+            record_id)
+
+        addresses = [
+            record_address,
+            property_address_range,
+        ] + _make_escrow_addresses(record_id)
+
+        return self._create_transaction(
+            payload,
+            inputs=addresses + [
+                addressing.RECORD_TYPE_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+            ],
+            outputs=addresses,
+        )
+
+    def cancel_all_proposals(self, record_id, receiving_agents=()):
+        payload = _make_sc_payload(
+            action=SCPayload.CANCEL_ALL_PROPOSALS,
+            cancel_all_proposals=CancelAllProposalsAction(
+                record_id=record_id,
+                receiving_agents=receiving_agents))
+
+        # The signer's open Proposals are found through the index and the
+        # receiving Agents named, and each is moved to its receiving
+        # Agent's history
+        proposal_address_range = \
+            addressing.make_proposal_address_range(record_id)
+
+        history_address_range = \
+            addressing.make_proposal_history_address_range(record_id)
+
+        index_address = addressing.make_proposal_index_address(record_id)
+
+        record_address = addressing.make_record_address(record_id)
+
+        return self._create_transaction(
+            payload,
+            inputs=[
+                proposal_address_range,
+                history_address_range,
+                index_address,
+                record_address,
+            ],
+            outputs=[
+                proposal_address_range,
+                history_address_range,
+                index_address,
             ],
         )
 
@@ -265,6 +923,378 @@ class SupplyChainMessageFactory:
             ],
         )
 
+    def delegate_reporter(self, record_id, reporter_id, properties):
+        payload = _make_sc_payload(
+            action=SCPayload.DELEGATE_REPORTER,
+            delegate_reporter=DelegateReporterAction(
+                record_id=record_id,
+                reporter_id=reporter_id,
+                properties=properties))
+
+        record_address = addressing.make_record_address(record_id)
+
+        reporter_address = addressing.make_agent_address(reporter_id)
+
+        property_addresses = [
+            addressing.make_property_address(
+                record_id, property_name)
+            for property_name in properties
+        ]
+
+        setting_address = addressing.make_setting_address(
+            addressing.MAX_REPORTERS_SETTING)
+
+        return self._create_transaction(
+            payload,
+            inputs=[
+                record_address,
+                reporter_address,
+                setting_address,
+                *property_addresses,
+            ],
+            outputs=property_addresses,
+        )
+
+    def execute_transfer(self, record_id, receiving_agent, role):
+        payload = _make_sc_payload(
+            action=SCPayload.EXECUTE_TRANSFER,
+            execute_transfer=ExecuteTransferAction(
+                record_id=record_id,
+                receiving_agent=receiving_agent,
+                role=role))
+
+        proposal_address = addressing.make_proposal_address(
+            record_id,
+            receiving_agent)
+
+        index_address = addressing.make_proposal_index_address(record_id)
+
+        history_address_range = \
+            addressing.make_proposal_history_address_range(
+                record_id, receiving_agent)
+
+        record_address = addressing.make_record_address(record_id)
+
+        property_address_range = addressing.make_property_address_range(
+            record_id)
+
+        timeline_address_range = \
+            addressing.make_record_timeline_address_range(record_id)
+
+        return self._create_transaction(
+            payload,
+            inputs=[
+                proposal_address,
+                index_address,
+                history_address_range,
+                record_address,
+                property_address_range,
+                timeline_address_range,
+                addressing.RECORD_TYPE_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+            ],
+            outputs=[
+                proposal_address,
+                index_address,
+                history_address_range,
+                record_address,
+                property_address_range,
+                timeline_address_range,
+            ],
+        )
+
+    def authorize_transfer(self, record_id, role, issuing_agent,
+                           expires_at):
+        """Signs this factory's agent's consent to take the role of a
+        Record over from the issuing agent, returning the authorization
+        and its signature for the issuing agent's direct_transfer"""
+        authorization = TransferAuthorization(
+            record_id=record_id,
+            role=role,
+            issuing_agent=issuing_agent,
+            receiving_agent=self.public_key,
+            timestamp=round(time.time()),
+            expires_at=expires_at,
+        ).SerializeToString()
+
+        return authorization, self._signer.sign(authorization)
+
+    def direct_transfer(self, authorization, signature):
+        payload = _make_sc_payload(
+            action=SCPayload.DIRECT_TRANSFER,
+            direct_transfer=DirectTransferAction(
+                authorization=authorization,
+                signature=signature))
+
+        transfer = TransferAuthorization.FromString(authorization)
+        record_id = transfer.record_id
+
+        record_address = addressing.make_record_address(record_id)
+
+        property_address_range = addressing.make_property_address_range(
+            record_id)
+
+        timeline_address_range = \
+            addressing.make_record_timeline_address_range(record_id)
+
+        return self._create_transaction(
+            payload,
+            inputs=[
+                record_address,
+                property_address_range,
+                timeline_address_range,
+                addressing.make_agent_address(transfer.receiving_agent),
+                addressing.RECORD_TYPE_ADDRESS_RANGE,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+            ],
+            outputs=[
+                record_address,
+                property_address_range,
+                timeline_address_range,
+            ],
+        )
+
+    def anchor_message(self, record_id, recipient, digest,
+                       proposal_timestamp=0):
+        payload = _make_sc_payload(
+            action=SCPayload.MESSAGE,
+            message=MessageAction(
+                record_id=record_id,
+                recipient=recipient,
+                digest=digest,
+                proposal_timestamp=proposal_timestamp))
+
+        message_address = addressing.make_message_address(record_id, digest)
+
+        return self._create_transaction(
+            payload,
+            inputs=[
+                message_address,
+                addressing.make_record_address(record_id),
+                addressing.make_agent_address(recipient),
+                addressing.make_proposal_address(record_id, recipient),
+                addressing.make_proposal_address(record_id, self.public_key),
+                self.signer_address,
+            ],
+            outputs=[message_address],
+        )
+
+    def subscribe(self, scope, target, events):
+        payload = _make_sc_payload(
+            action=SCPayload.SUBSCRIBE,
+            subscribe=SubscribeAction(
+                scope=scope,
+                target=target,
+                events=events))
+
+        subscription_address = \
+            addressing.make_subscription_address(self.public_key)
+
+        return self._create_transaction(
+            payload,
+            inputs=[
+                subscription_address,
+                self.signer_address,
+                addressing.make_record_address(target),
+                addressing.make_record_type_address(target),
+            ],
+            outputs=[subscription_address],
+        )
+
+    def unsubscribe(self, scope, target):
+        payload = _make_sc_payload(
+            action=SCPayload.UNSUBSCRIBE,
+            unsubscribe=UnsubscribeAction(
+                scope=scope,
+                target=target))
+
+        subscription_address = \
+            addressing.make_subscription_address(self.public_key)
+
+        return self._create_transaction(
+            payload,
+            inputs=[subscription_address],
+            outputs=[subscription_address],
+        )
+
+    def receive_confirmation(self, record_id, checks, notes=''):
+        """Confirms receipt of a Record as its custodian, where checks is a
+        list of (item, passed) or (item, passed, note) tuples"""
+        payload = _make_sc_payload(
+            action=SCPayload.RECEIVE_CONFIRMATION,
+            receive_confirmation=ReceiveConfirmationAction(
+                record_id=record_id,
+                checks=[_make_condition_check(*check) for check in checks],
+                notes=notes))
+
+        record_address = addressing.make_record_address(record_id)
+
+        timeline_address_range = \
+            addressing.make_record_timeline_address_range(record_id)
+
+        return self._create_transaction(
+            payload,
+            inputs=[record_address, timeline_address_range],
+            outputs=[record_address, timeline_address_range],
+        )
+
+    def anchor_state(self, service, block_num, block_id, state_root,
+                     receipt):
+        payload = _make_sc_payload(
+            action=SCPayload.ANCHOR_STATE,
+            anchor_state=AnchorStateAction(
+                service=service,
+                block_num=block_num,
+                block_id=block_id,
+                state_root=state_root,
+                receipt=receipt))
+
+        # Sent by server/scripts/anchor_state.js, so listing its inputs
+        return self._create_transaction(
+            payload,
+            inputs=_make_script_inputs([
+                addressing.make_setting_address(
+                    addressing.ANCHOR_SIGNERS_SETTING),
+            ]),
+            outputs=[addressing.NAMESPACE],
+        )
+
+    def anchor_feedback(self, record_id, kind, digest):
+        payload = _make_sc_payload(
+            action=SCPayload.ANCHOR_FEEDBACK,
+            anchor_feedback=AnchorFeedbackAction(
+                record_id=record_id,
+                kind=kind,
+                digest=digest))
+
+        # Sent by kiosks running the fish or asset client, so listing
+        # the inputs the clients do
+        return self._create_transaction(
+            payload,
+            inputs=_make_client_inputs(),
+            outputs=[addressing.NAMESPACE],
+        )
+
+    def migrate_state(self, writes):
+        """Stores each (address, data) pair as it is, emptying the
+        address if data is empty"""
+        payload = _make_sc_payload(
+            action=SCPayload.MIGRATE_STATE,
+            migrate_state=MigrateStateAction(
+                writes=[
+                    MigrateStateAction.Write(address=address, data=data)
+                    for address, data in writes
+                ]))
+
+        # Sent by server/scripts/migrate_state.js, so listing its inputs
+        return self._create_transaction(
+            payload,
+            inputs=_make_script_inputs([
+                addressing.make_setting_address(
+                    addressing.MIGRATION_SIGNERS_SETTING),
+            ]),
+            outputs=[addressing.NAMESPACE],
+        )
+
+    def delete_record_type(self, name):
+        payload = _make_sc_payload(
+            action=SCPayload.DELETE_RECORD_TYPE,
+            delete_record_type=DeleteRecordTypeAction(name=name))
+
+        record_type_address = addressing.make_record_type_address(name)
+
+        return self._create_transaction(
+            payload,
+            inputs=[record_type_address, self.signer_address],
+            outputs=[record_type_address],
+        )
+
+    def update_record_type(self, name, *properties, rules=None,
+                           retention_rules=None, finalization_policy=None):
+        """Replaces the definition of a record type with a new version,
+        keeping the current one for the Records created with it"""
+        payload = _make_sc_payload(
+            action=SCPayload.UPDATE_RECORD_TYPE,
+            update_record_type=UpdateRecordTypeAction(
+                name=name,
+                properties=[
+                    _make_property_schema(n, dt, a)
+                    for (n, dt, a) in properties],
+                rules=rules or [],
+                retention_rules=[
+                    RetentionRule(property_name=n, prunable_after=after)
+                    for n, after in (retention_rules or {}).items()],
+                finalization_policy=_make_finalization_policy(
+                    finalization_policy),
+            )
+        )
+
+        record_type_address = addressing.make_record_type_address(name)
+        setting_address = addressing.make_setting_address(
+            addressing.MAX_PROPERTIES_SETTING)
+
+        inputs = [
+            record_type_address,
+            addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+            self.signer_address,
+            setting_address,
+        ]
+
+        if '.' in name:
+            inputs.append(addressing.make_setting_address(
+                addressing.RECORD_TYPE_NAMESPACE_SETTING
+                + name.split('.')[0]))
+
+        return self._create_transaction(
+            payload,
+            inputs=inputs,
+            outputs=[
+                record_type_address,
+                addressing.RECORD_TYPE_VERSION_ADDRESS_RANGE,
+            ],
+        )
+
+    def acknowledge_value(self, record_id, property_name):
+        payload = _make_sc_payload(
+            action=SCPayload.ACKNOWLEDGE_VALUE,
+            acknowledge_value=AcknowledgeValueAction(
+                record_id=record_id,
+                property_name=property_name))
+
+        record_address = addressing.make_record_address(record_id)
+
+        return self._create_transaction(
+            payload,
+            inputs=[record_address],
+            outputs=[record_address],
+        )
+
+    def composite(self, *transactions):
+        """Combines transactions made by the other methods into one,
+        which applies all of their actions or none of them"""
+        actions = []
+        inputs = []
+        outputs = []
+        for transaction in transactions:
+            action = SCPayload()
+            action.ParseFromString(transaction.payload)
+            actions.append(action)
+
+            header = TransactionHeader()
+            header.ParseFromString(transaction.header)
+            inputs.extend(a for a in header.inputs if a not in inputs)
+            outputs.extend(a for a in header.outputs if a not in outputs)
+
+        payload = _make_sc_payload(
+            action=SCPayload.COMPOSITE,
+            composite=CompositeAction(actions=actions))
+
+        return self._create_transaction(
+            payload,
+            inputs=inputs,
+            outputs=outputs,
+        )
+
     def make_empty_payload(self, public_key):
         address = addressing.make_agent_address(public_key)
 
@@ -275,6 +1305,26 @@ class SupplyChainMessageFactory:
         )
 
     def _create_transaction(self, payload, inputs, outputs):
+        # Every action is checked against the disabled actions, timestamp
+        # unit and agent admins settings, and is added to the signer's
+        # activity pages
+        activity_address_range = \
+            addressing.make_agent_activity_address_range(self.public_key)
+        inputs = _add_addresses(inputs, [
+            addressing.make_setting_address(
+                addressing.DISABLED_ACTIONS_SETTING),
+            addressing.make_setting_address(
+                addressing.TIMESTAMP_UNIT_SETTING),
+            addressing.make_setting_address(
+                addressing.AGENT_ADMINS_SETTING),
+            self.signer_address,
+            activity_address_range,
+        ])
+        outputs = _add_addresses(outputs, [
+            self.signer_address,
+            activity_address_range,
+        ])
+
         return self._factory.create_transaction(
             payload, inputs, outputs, [])
 
@@ -282,6 +1332,66 @@ class SupplyChainMessageFactory:
         return self._factory.create_batch([transaction])
 
 
+def _add_addresses(addresses, extra):
+    return addresses + [a for a in extra if a not in addresses]
+
+
+def _make_client_inputs():
+    """The inputs createTxn in the fish and asset clients'
+    services/transactions.js gives every transaction: the whole
+    namespace and the shared settings"""
+    return [addressing.NAMESPACE] + addressing.make_shared_setting_addresses()
+
+
+def _make_script_inputs(extra):
+    """The inputs server/system/submit_utils.js gives the transactions
+    of the server's scripts: the whole namespace, the shared settings,
+    and any the script adds"""
+    return _add_addresses(
+        [addressing.NAMESPACE] + addressing.make_shared_setting_addresses(),
+        extra)
+
+
+def _make_reference_addresses(properties_dict):
+    """The addresses of the Records referenced by the values, including
+    those in arrays and structs, which are read to check they exist"""
+    addresses = []
+    for value in properties_dict.values():
+        if type(value) == RecordReference:
+            addresses.append(addressing.make_record_address(value.value))
+        elif type(value) == list:
+            addresses.extend(_make_reference_addresses(dict(enumerate(value))))
+        elif type(value) == dict:
+            addresses.extend(_make_reference_addresses(value))
+    return addresses
+
+
+def _make_escrow_addresses(record_id):
+    """The addresses completing an escrowed transfer of the Record
+    touches, besides those of the Record and its Properties"""
+    return [
+        addressing.make_proposal_address_range(record_id),
+        addressing.make_proposal_history_address_range(record_id),
+        addressing.make_proposal_index_address(record_id),
+        addressing.make_record_timeline_address_range(record_id),
+    ]
+
+
+def _make_finalization_policy(policy):
+    """Makes a FinalizationPolicy from a dict with any of the keys
+    `reported_properties`, a list of names, `bounds`, mapping names to
+    (min, max) pairs, and `no_open_excursions`"""
+    if policy is None:
+        return None
+
+    return FinalizationPolicy(
+        reported_properties=policy.get('reported_properties', []),
+        bounds=[
+            FinalizationPolicy.Bound(property_name=name, min=low, max=high)
+            for name, (low, high) in policy.get('bounds', {}).items()],
+        no_open_excursions=policy.get('no_open_excursions', False))
+
+
 def _make_sc_payload(**kwargs):
     return SCPayload(
         timestamp=round(time.time()),
@@ -289,6 +1399,34 @@ def _make_sc_payload(**kwargs):
     ).SerializeToString()
 
 
+def _make_condition_check(item, passed, note=''):
+    return ConditionCheck(item=item, passed=passed, note=note)
+
+
+def _make_property_schema(name, data_type, attrs):
+    if 'struct_properties' in attrs:
+        attrs['struct_properties'] = \
+            [_make_property_schema(n, dt, a)
+             for n, dt, a in attrs['struct_properties']]
+    if 'element_schema' in attrs:
+        element_type, element_attrs = attrs['element_schema']
+        attrs['element_schema'] = \
+            _make_property_schema('', element_type, element_attrs)
+    if 'threshold' in attrs:
+        attrs['threshold'] = \
+            PropertySchema.Threshold(**attrs['threshold'])
+    if 'bounds' in attrs:
+        attrs['bounds'] = PropertySchema.Bounds(**attrs['bounds'])
+    if 'fence' in attrs:
+        attrs['fence'] = [
+            Location(latitude=latitude, longitude=longitude)
+            for latitude, longitude in attrs['fence']]
+    if 'default_value' in attrs:
+        attrs['default_value'] = \
+            _make_property_value(name, attrs['default_value'])
+    return PropertySchema(name=name, data_type=data_type, **attrs)
+
+
 def _make_property_value(name, value):
     if type(value) == dict:
         values = [_make_property_value(k, v) for k, v in value.items()]
@@ -297,6 +1435,19 @@ def _make_property_value(name, value):
             data_type=PropertySchema.STRUCT,
             struct_values=values)
 
+    if type(value) == list:
+        return PropertyValue(
+            name=name,
+            data_type=PropertySchema.ARRAY,
+            array_values=[_make_property_value('', v) for v in value])
+
+    if type(value) == Location:
+        property_value = PropertyValue(
+            name=name,
+            data_type=PropertySchema.LOCATION)
+        property_value.location_value.CopyFrom(value)
+        return property_value
+
     property_value = PropertyValue(name=name)
 
     type_slots = {
@@ -305,6 +1456,10 @@ def _make_property_value(name, value):
         str: 'string_value',
         bytes: 'bytes_value',
         Enum: 'enum_value',
+        Timestamp: 'timestamp_value',
+        RecordReference: 'record_reference_value',
+        Identifier: 'identifier_value',
+        Measurement: 'number_value',
     }
 
     type_tags = {
@@ -313,6 +1468,10 @@ def _make_property_value(name, value):
         str: PropertySchema.STRING,
         bytes: PropertySchema.BYTES,
         Enum: PropertySchema.ENUM,
+        Timestamp: PropertySchema.TIMESTAMP,
+        RecordReference: PropertySchema.RECORD_REFERENCE,
+        Identifier: PropertySchema.IDENTIFIER,
+        Measurement: PropertySchema.NUMBER,
     }
 
     try:
@@ -325,5 +1484,7 @@ def _make_property_value(name, value):
     unwrapped_value = getattr(value, 'value', value)
     setattr(property_value, slot, unwrapped_value)
     property_value.data_type = type_tag
+    if isinstance(value, Measurement):
+        property_value.unit = value.unit
 
     return property_value